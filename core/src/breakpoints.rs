@@ -28,14 +28,547 @@
 
     Implement enum for breakpoint definitions.
 
+    Any breakpoint may be wrapped in a BreakPointCondition, which carries an
+    optional condition expression and a hit count. Condition expressions are
+    evaluated over registers and memory when the breakpoint is reached, e.g.:
+
+        AX==0x4C00 && [DS:SI]==0x24
+
+    Operands are registers, numbers (decimal, or hex with a 0x prefix or h
+    suffix), byte memory references [seg:offset] or [flat], and word memory
+    references w[seg:offset]. Operators follow C precedence:
+        ! ~ - (unary), + -, < <= > >=, == !=, &, ^, |, &&, ||
+
+    Watchpoints cover a range of flat addresses and trigger on reads, writes,
+    or writes that change the value in memory.
 */
 
+use anyhow::{anyhow, Error};
+
 #[allow(dead_code)]
 pub enum BreakPointType {
-    Execute(u16, u16),   // Breakpoint on CS:IP
-    ExecuteOffset(u16),  // Breakpoint on *::IP
-    ExecuteFlat(u32),    // Breakpoint on CS<<4+IP
-    MemAccess(u16, u16), // Breakpoint on memory access, seg::offset
-    MemAccessFlat(u32),  // Breakpoint on memory access, seg<<4+offset
-    Interrupt(u8),       // Breakpoint on interrupt #
+    Execute(u16, u16),          // Breakpoint on CS:IP
+    ExecuteOffset(u16),         // Breakpoint on *::IP
+    ExecuteFlat(u32),           // Breakpoint on CS<<4+IP
+    MemAccess(u16, u16),        // Breakpoint on memory access, seg::offset
+    MemAccessFlat(u32),         // Breakpoint on memory access, seg<<4+offset
+    Interrupt(u8),              // Breakpoint on interrupt #
+    IoRead(u16),                // Breakpoint on read from IO port
+    IoWrite(u16, u8, u8),       // Breakpoint on write to IO port, value, value mask (mask of 0 matches any value)
+    Watch(u32, u32, WatchType), // Watchpoint on flat address range, start, length
+    // Breakpoint that only triggers when its condition is met
+    Conditional(Box<BreakPointType>, BreakPointCondition),
+}
+
+impl BreakPointType {
+    /// Return the underlying breakpoint type, looking through any condition.
+    pub fn base(&self) -> &BreakPointType {
+        match self {
+            BreakPointType::Conditional(inner, _) => inner.base(),
+            _ => self,
+        }
+    }
+
+    /// Return true if this is an IO breakpoint matching the specified port access.
+    pub fn matches_io(&self, port: u16, value: u8, write: bool) -> bool {
+        match *self.base() {
+            BreakPointType::IoRead(bp_port) => !write && bp_port == port,
+            BreakPointType::IoWrite(bp_port, bp_value, mask) => {
+                write && bp_port == port && (value & mask) == (bp_value & mask)
+            }
+            _ => false,
+        }
+    }
+
+    /// Return true if this is a watchpoint matching the specified memory access.
+    pub fn matches_watch(&self, address: u32, write: bool, changed: bool) -> bool {
+        match *self.base() {
+            BreakPointType::Watch(start, len, kind) => {
+                let in_range = address >= start && address < start.saturating_add(len);
+                in_range
+                    && match kind {
+                        WatchType::Read => !write,
+                        WatchType::Write => write,
+                        WatchType::Change => write && changed,
+                    }
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WatchType {
+    Read,
+    Write,
+    Change,
+}
+
+impl std::fmt::Display for WatchType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchType::Read => write!(f, "Read"),
+            WatchType::Write => write!(f, "Write"),
+            WatchType::Change => write!(f, "Change"),
+        }
+    }
+}
+
+/// Registers that may be referenced in a condition expression.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExprRegister {
+    AL,
+    CL,
+    DL,
+    BL,
+    AH,
+    CH,
+    DH,
+    BH,
+    AX,
+    CX,
+    DX,
+    BX,
+    SP,
+    BP,
+    SI,
+    DI,
+    ES,
+    CS,
+    SS,
+    DS,
+    IP,
+    Flags,
+}
+
+impl ExprRegister {
+    fn from_name(name: &str) -> Option<ExprRegister> {
+        let reg = match name.to_ascii_lowercase().as_str() {
+            "al" => ExprRegister::AL,
+            "cl" => ExprRegister::CL,
+            "dl" => ExprRegister::DL,
+            "bl" => ExprRegister::BL,
+            "ah" => ExprRegister::AH,
+            "ch" => ExprRegister::CH,
+            "dh" => ExprRegister::DH,
+            "bh" => ExprRegister::BH,
+            "ax" => ExprRegister::AX,
+            "cx" => ExprRegister::CX,
+            "dx" => ExprRegister::DX,
+            "bx" => ExprRegister::BX,
+            "sp" => ExprRegister::SP,
+            "bp" => ExprRegister::BP,
+            "si" => ExprRegister::SI,
+            "di" => ExprRegister::DI,
+            "es" => ExprRegister::ES,
+            "cs" => ExprRegister::CS,
+            "ss" => ExprRegister::SS,
+            "ds" => ExprRegister::DS,
+            "ip" => ExprRegister::IP,
+            "flags" => ExprRegister::Flags,
+            _ => return None,
+        };
+        Some(reg)
+    }
+}
+
+/// Provides the machine state a condition expression is evaluated against.
+pub trait BreakPointEnv {
+    fn expr_register(&self, reg: ExprRegister) -> u16;
+    fn expr_peek_u8(&self, address: u32) -> u8;
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExprUnaryOp {
+    Not,
+    Complement,
+    Negate,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ExprBinaryOp {
+    Add,
+    Sub,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    And,
+    Xor,
+    Or,
+    LogicalAnd,
+    LogicalOr,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BreakPointExpr {
+    Const(u32),
+    Register(ExprRegister),
+    // Memory reference: optional segment, offset (or flat address), word size
+    Memory(Option<Box<BreakPointExpr>>, Box<BreakPointExpr>, bool),
+    Unary(ExprUnaryOp, Box<BreakPointExpr>),
+    Binary(ExprBinaryOp, Box<BreakPointExpr>, Box<BreakPointExpr>),
+}
+
+impl BreakPointExpr {
+    pub fn parse(expr: &str) -> Result<BreakPointExpr, Error> {
+        let tokens = tokenize(expr)?;
+        let mut parser = ExprParser { tokens, pos: 0 };
+        let parsed = parser.parse_binary(0)?;
+        if parser.pos != parser.tokens.len() {
+            return Err(anyhow!("Unexpected token in expression: {:?}", parser.tokens[parser.pos]));
+        }
+        Ok(parsed)
+    }
+
+    pub fn eval(&self, env: &impl BreakPointEnv) -> u32 {
+        match self {
+            BreakPointExpr::Const(value) => *value,
+            BreakPointExpr::Register(reg) => env.expr_register(*reg) as u32,
+            BreakPointExpr::Memory(segment, offset, word) => {
+                let address = match segment {
+                    Some(segment) => ((segment.eval(env) & 0xFFFF) << 4) + (offset.eval(env) & 0xFFFF),
+                    None => offset.eval(env),
+                };
+                let lo = env.expr_peek_u8(address & 0xFFFFF) as u32;
+                if *word {
+                    lo | (env.expr_peek_u8(address.wrapping_add(1) & 0xFFFFF) as u32) << 8
+                }
+                else {
+                    lo
+                }
+            }
+            BreakPointExpr::Unary(op, operand) => {
+                let value = operand.eval(env);
+                match op {
+                    ExprUnaryOp::Not => (value == 0) as u32,
+                    ExprUnaryOp::Complement => !value,
+                    ExprUnaryOp::Negate => value.wrapping_neg(),
+                }
+            }
+            BreakPointExpr::Binary(op, lhs, rhs) => {
+                let l = lhs.eval(env);
+                // Short-circuit logical operators.
+                match op {
+                    ExprBinaryOp::LogicalAnd if l == 0 => return 0,
+                    ExprBinaryOp::LogicalOr if l != 0 => return 1,
+                    _ => {}
+                }
+                let r = rhs.eval(env);
+                match op {
+                    ExprBinaryOp::Add => l.wrapping_add(r),
+                    ExprBinaryOp::Sub => l.wrapping_sub(r),
+                    ExprBinaryOp::Lt => (l < r) as u32,
+                    ExprBinaryOp::Le => (l <= r) as u32,
+                    ExprBinaryOp::Gt => (l > r) as u32,
+                    ExprBinaryOp::Ge => (l >= r) as u32,
+                    ExprBinaryOp::Eq => (l == r) as u32,
+                    ExprBinaryOp::Ne => (l != r) as u32,
+                    ExprBinaryOp::And => l & r,
+                    ExprBinaryOp::Xor => l ^ r,
+                    ExprBinaryOp::Or => l | r,
+                    ExprBinaryOp::LogicalAnd | ExprBinaryOp::LogicalOr => (r != 0) as u32,
+                }
+            }
+        }
+    }
+}
+
+/// A condition attached to a breakpoint. The breakpoint triggers when the expression (if any)
+/// is true and the expression has been true at least `hit_count` times.
+pub struct BreakPointCondition {
+    pub expr: Option<BreakPointExpr>,
+    pub hit_count: u32,
+    hits: u32,
+}
+
+impl BreakPointCondition {
+    pub fn new(expr: Option<BreakPointExpr>, hit_count: u32) -> Self {
+        Self {
+            expr,
+            hit_count,
+            hits: 0,
+        }
+    }
+
+    pub fn hits(&self) -> u32 {
+        self.hits
+    }
+
+    /// Evaluate the condition when its breakpoint is reached. Updates the hit count.
+    pub fn test(&mut self, env: &impl BreakPointEnv) -> bool {
+        if let Some(expr) = &self.expr {
+            if expr.eval(env) == 0 {
+                return false;
+            }
+        }
+        self.hits = self.hits.saturating_add(1);
+        self.hits >= self.hit_count
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum ExprToken {
+    Number(u32),
+    Register(ExprRegister),
+    Word,
+    Op(&'static str),
+}
+
+const EXPR_OPERATORS: [&str; 19] = [
+    "&&", "||", "==", "!=", "<=", ">=", "<", ">", "&", "|", "^", "+", "-", "!", "~", "(", ")", "[", "]",
+];
+
+fn tokenize(expr: &str) -> Result<Vec<ExprToken>, Error> {
+    let mut tokens = Vec::new();
+    let mut rest = expr.trim_start();
+
+    while !rest.is_empty() {
+        if let Some(op) = EXPR_OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(ExprToken::Op(*op));
+            rest = &rest[op.len()..];
+        }
+        else if rest.starts_with(':') {
+            tokens.push(ExprToken::Op(":"));
+            rest = &rest[1..];
+        }
+        else {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            if len == 0 {
+                return Err(anyhow!("Invalid character in expression: {}", rest));
+            }
+            let word = &rest[..len];
+            tokens.push(parse_word(word)?);
+            rest = &rest[len..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+fn parse_word(word: &str) -> Result<ExprToken, Error> {
+    if word.starts_with(|c: char| c.is_ascii_digit()) {
+        let lower = word.to_ascii_lowercase();
+        let value = if let Some(hex) = lower.strip_prefix("0x") {
+            u32::from_str_radix(hex, 16)
+        }
+        else if let Some(hex) = lower.strip_suffix('h') {
+            u32::from_str_radix(hex, 16)
+        }
+        else {
+            lower.parse::<u32>()
+        };
+        value
+            .map(ExprToken::Number)
+            .map_err(|_| anyhow!("Invalid number in expression: {}", word))
+    }
+    else if word.eq_ignore_ascii_case("w") {
+        Ok(ExprToken::Word)
+    }
+    else {
+        ExprRegister::from_name(word)
+            .map(ExprToken::Register)
+            .ok_or_else(|| anyhow!("Unknown register in expression: {}", word))
+    }
+}
+
+struct ExprParser {
+    tokens: Vec<ExprToken>,
+    pos: usize,
+}
+
+// Binary operators by precedence level, lowest first.
+const EXPR_PRECEDENCE: [&[(&str, ExprBinaryOp)]; 7] = [
+    &[("||", ExprBinaryOp::LogicalOr)],
+    &[("&&", ExprBinaryOp::LogicalAnd)],
+    &[("|", ExprBinaryOp::Or)],
+    &[("^", ExprBinaryOp::Xor)],
+    &[("&", ExprBinaryOp::And)],
+    &[("==", ExprBinaryOp::Eq), ("!=", ExprBinaryOp::Ne)],
+    &[
+        ("<", ExprBinaryOp::Lt),
+        ("<=", ExprBinaryOp::Le),
+        (">", ExprBinaryOp::Gt),
+        (">=", ExprBinaryOp::Ge),
+    ],
+];
+
+impl ExprParser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(ExprToken::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), Error> {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            Ok(())
+        }
+        else {
+            Err(anyhow!("Expected '{}' in expression", op))
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<BreakPointExpr, Error> {
+        if level == EXPR_PRECEDENCE.len() {
+            return self.parse_additive();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(op) = self
+            .peek_op()
+            .and_then(|tok| EXPR_PRECEDENCE[level].iter().find(|(s, _)| *s == tok))
+        {
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = BreakPointExpr::Binary(op.1, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_additive(&mut self) -> Result<BreakPointExpr, Error> {
+        let mut lhs = self.parse_unary()?;
+        loop {
+            let op = match self.peek_op() {
+                Some("+") => ExprBinaryOp::Add,
+                Some("-") => ExprBinaryOp::Sub,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.parse_unary()?;
+            lhs = BreakPointExpr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<BreakPointExpr, Error> {
+        let op = match self.peek_op() {
+            Some("!") => Some(ExprUnaryOp::Not),
+            Some("~") => Some(ExprUnaryOp::Complement),
+            Some("-") => Some(ExprUnaryOp::Negate),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.pos += 1;
+            return Ok(BreakPointExpr::Unary(op, Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<BreakPointExpr, Error> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of expression"))?;
+        self.pos += 1;
+
+        match token {
+            ExprToken::Number(value) => Ok(BreakPointExpr::Const(value)),
+            ExprToken::Register(reg) => Ok(BreakPointExpr::Register(reg)),
+            ExprToken::Word => {
+                self.expect("[")?;
+                self.parse_memory(true)
+            }
+            ExprToken::Op("[") => self.parse_memory(false),
+            ExprToken::Op("(") => {
+                let expr = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            ExprToken::Op(op) => Err(anyhow!("Unexpected '{}' in expression", op)),
+        }
+    }
+
+    fn parse_memory(&mut self, word: bool) -> Result<BreakPointExpr, Error> {
+        let first = self.parse_binary(0)?;
+        let expr = if self.peek_op() == Some(":") {
+            self.pos += 1;
+            let offset = self.parse_binary(0)?;
+            BreakPointExpr::Memory(Some(Box::new(first)), Box::new(offset), word)
+        }
+        else {
+            BreakPointExpr::Memory(None, Box::new(first), word)
+        };
+        self.expect("]")?;
+        Ok(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestEnv {
+        mem: Vec<u8>,
+    }
+
+    impl BreakPointEnv for TestEnv {
+        fn expr_register(&self, reg: ExprRegister) -> u16 {
+            match reg {
+                ExprRegister::AX => 0x4C00,
+                ExprRegister::AH => 0x4C,
+                ExprRegister::DS => 0x1000,
+                ExprRegister::SI => 0x0010,
+                _ => 0,
+            }
+        }
+
+        fn expr_peek_u8(&self, address: u32) -> u8 {
+            self.mem[address as usize]
+        }
+    }
+
+    #[test]
+    fn test_breakpoint_expr() {
+        let mut env = TestEnv { mem: vec![0; 0x100000] };
+        env.mem[0x10010] = 0x24;
+        env.mem[0x10011] = 0x12;
+
+        let eval = |expr: &str| BreakPointExpr::parse(expr).unwrap().eval(&env);
+
+        assert_eq!(eval("AX==0x4C00 && [DS:SI]==0x24"), 1);
+        assert_eq!(eval("ax == 4C01h || [ds:si+1] == 24h"), 0);
+        assert_eq!(eval("w[DS:SI]"), 0x1224);
+        assert_eq!(eval("[10011h]"), 0x12);
+        assert_eq!(eval("1 + 2 == 3 && !(ah != 76)"), 1);
+        assert_eq!(eval("ax & 0xFF00 | 1"), 0x4C01);
+
+        assert!(BreakPointExpr::parse("AX ==").is_err());
+        assert!(BreakPointExpr::parse("QX == 1").is_err());
+        assert!(BreakPointExpr::parse("[DS:SI").is_err());
+    }
+
+    #[test]
+    fn test_breakpoint_hit_count() {
+        let env = TestEnv { mem: vec![0; 16] };
+        let mut cond = BreakPointCondition::new(Some(BreakPointExpr::parse("AX==0x4C00").unwrap()), 3);
+        assert!(!cond.test(&env));
+        assert!(!cond.test(&env));
+        assert!(cond.test(&env));
+        assert_eq!(cond.hits(), 3);
+
+        let mut never = BreakPointCondition::new(Some(BreakPointExpr::parse("AX==0").unwrap()), 0);
+        assert!(!never.test(&env));
+        assert_eq!(never.hits(), 0);
+    }
+
+    #[test]
+    fn test_watchpoint_match() {
+        let watch = BreakPointType::Watch(0x1000, 0x10, WatchType::Change);
+        assert!(watch.matches_watch(0x1000, true, true));
+        assert!(watch.matches_watch(0x100F, true, true));
+        assert!(!watch.matches_watch(0x1010, true, true));
+        assert!(!watch.matches_watch(0x1000, true, false));
+        assert!(!watch.matches_watch(0x1000, false, false));
+
+        let read = BreakPointType::Watch(0x2000, 1, WatchType::Read);
+        assert!(read.matches_watch(0x2000, false, false));
+        assert!(!read.matches_watch(0x2000, true, true));
+    }
 }
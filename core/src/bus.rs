@@ -53,22 +53,28 @@ use crate::{
         VideoType,
     },
     devices::{
+        adlib::AdLibCard,
         cga::{self, CGACard},
         dma::*,
-        fdc::FloppyController,
+        fdc::{FloppyController, FDC_MAX_DRIVES},
         hdc::*,
+        kbc::Kbc,
         keyboard::{KeyboardType, *},
         mda::{self, MDACard},
         mouse::*,
+        pcjr_port_b::PcJrPortB,
         pic::*,
         pit::Pit,
         ppi::*,
         serial::*,
+        sn76489::Sn76489,
+        sound_blaster::{SoundBlaster, SoundBlasterType, SB_DEFAULT_IO_BASE, SB_DEFAULT_IRQ, SB_VALID_IRQS},
     },
     machine::{KeybufferEntry, MachineCheckpoint, MachinePatch},
-    machine_config::{normalize_conventional_memory, MachineConfiguration, MachineDescriptor},
-    machine_types::{HardDiskControllerType, SerialControllerType, SerialMouseType},
+    machine_config::{normalize_conventional_memory, KbControllerType, MachineConfiguration, MachineDescriptor},
+    machine_types::{HardDiskControllerType, ParallelDeviceType, SerialControllerType, SerialMouseType, SoundType},
     memerror::MemError,
+    savestate::{SaveState, StateReader, StateValue, StateWriter},
     syntax_token::SyntaxToken,
     tracelogger::TraceLogger,
     updatable::*,
@@ -79,7 +85,15 @@ use crate::devices::ega::{self, EGACard};
 #[cfg(feature = "vga")]
 use crate::devices::vga::{self, VGACard};
 use crate::{
-    devices::{lpt_card::ParallelController, tga, tga::TGACard},
+    devices::{
+        covox::CovoxSpeechThing,
+        disney_sound_source::DisneySoundSource,
+        lpt_card::ParallelController,
+        lpt_port::ParallelDevice,
+        printer::Printer,
+        tga,
+        tga::TGACard,
+    },
     syntax_token::SyntaxFormatType,
 };
 
@@ -99,6 +113,11 @@ pub const MEM_BPE_BIT: u8 = 0b0010_0000; // Bit to signify that this address is
 pub const MEM_BPA_BIT: u8 = 0b0001_0000; // Bit to signify that this address is associated with a breakpoint on access
 pub const MEM_CP_BIT: u8 = 0b0000_1000; // Bit to signify that this address is a ROM checkpoint
 pub const MEM_MMIO_BIT: u8 = 0b0000_0100; // Bit to signify that this address is MMIO mapped
+pub const MEM_BPR_BIT: u8 = 0b0000_0010; // Bit to signify that this address is associated with a read watchpoint
+pub const MEM_BPW_BIT: u8 = 0b0000_0001; // Bit to signify that this address is associated with a write or change watchpoint
+
+pub const IO_BPR_BIT: u8 = 0b0000_0001; // Bit to signify that this IO port is associated with a breakpoint on read
+pub const IO_BPW_BIT: u8 = 0b0000_0010; // Bit to signify that this IO port is associated with a breakpoint on write
 
 pub const KB_UPDATE_RATE: f64 = 5000.0; // Keyboard device update rate in microseconds
 
@@ -174,6 +193,29 @@ pub enum DeviceRunTimeUnit {
     Microseconds(f64),
 }
 
+impl StateValue for DeviceRunTimeUnit {
+    fn write_state(&self, w: &mut StateWriter) {
+        match self {
+            DeviceRunTimeUnit::SystemTicks(ticks) => {
+                w.write_u8(0);
+                w.put(ticks);
+            }
+            DeviceRunTimeUnit::Microseconds(us) => {
+                w.write_u8(1);
+                w.put(us);
+            }
+        }
+    }
+
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        match r.read_u8()? {
+            0 => Ok(DeviceRunTimeUnit::SystemTicks(r.get()?)),
+            1 => Ok(DeviceRunTimeUnit::Microseconds(r.get()?)),
+            tag => Err(anyhow::anyhow!("Invalid DeviceRunTimeUnit value: {}", tag)),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum DeviceId {
     None,
@@ -249,6 +291,8 @@ impl MemRangeDescriptor {
 
 pub enum IoDeviceType {
     Ppi,
+    PcJrPortB,
+    KbController,
     Pit,
     DmaPrimary,
     DmaSecondary,
@@ -259,6 +303,9 @@ pub enum IoDeviceType {
     FloppyController,
     HardDiskController,
     Mouse,
+    SoundChip,
+    AdLib,
+    SoundBlaster,
     Video(VideoCardId),
 }
 
@@ -334,6 +381,14 @@ pub enum MmioDeviceType {
 // on the machine type.
 // But this allows us to 'disassociate' devices from the bus on io writes to allow
 // us to call them with bus as an argument.
+/// An access to an IO port with a breakpoint set.
+#[derive(Copy, Clone, Debug)]
+pub struct IoBreakPointHit {
+    pub port:  u16,
+    pub data:  u8,
+    pub write: bool,
+}
+
 pub struct BusInterface {
     cpu_factor: ClockFactor,
     timing_table: Box<[TimingTableEntry; TIMING_TABLE_LEN]>,
@@ -352,7 +407,11 @@ pub struct BusInterface {
 
     io_map: FxHashMap<u16, IoDeviceType>,
     io_stats: FxHashMap<u16, (bool, IoDeviceStats)>,
+    io_bp_flags: FxHashMap<u16, u8>,
+    io_bp_hit: Option<IoBreakPointHit>,
     ppi: Option<Ppi>,
+    pcjr_port_b: Option<PcJrPortB>,
+    kbc: Option<Kbc>,
     pit: Option<Pit>,
     dma_counter: u16,
     dma1: Option<DMAController>,
@@ -364,6 +423,9 @@ pub struct BusInterface {
     fdc: Option<FloppyController>,
     hdc: Option<HardDiskController>,
     mouse: Option<Mouse>,
+    sound_chip: Option<Sn76489>,
+    adlib: Option<AdLibCard>,
+    sound_blaster: Option<SoundBlaster>,
 
     videocards:    FxHashMap<VideoCardId, VideoCardDispatch>,
     videocard_ids: Vec<VideoCardId>,
@@ -493,7 +555,11 @@ impl Default for BusInterface {
 
             io_map: FxHashMap::default(),
             io_stats: FxHashMap::default(),
+            io_bp_flags: FxHashMap::default(),
+            io_bp_hit: None,
             ppi: None,
+            pcjr_port_b: None,
+            kbc: None,
             pit: None,
             dma_counter: 0,
             dma1: None,
@@ -505,6 +571,9 @@ impl Default for BusInterface {
             fdc: None,
             hdc: None,
             mouse: None,
+            sound_chip: None,
+            adlib: None,
+            sound_blaster: None,
             videocards: FxHashMap::default(),
             videocard_ids: Vec::new(),
 
@@ -1567,7 +1636,7 @@ impl BusInterface {
                 machine_desc.machine_type,
                 conventional_memory,
                 false,
-                video_types,
+                video_types.clone(),
                 num_floppies,
             ));
             // Add PPI ports to io_map
@@ -1576,6 +1645,25 @@ impl BusInterface {
                 .extend(port_list.into_iter().map(|p| (p, IoDeviceType::Ppi)));
         }
 
+        // Create the PCjr's port B register, which takes the place of the PPI's port B.
+        if machine_desc.have_pcjr_port_b {
+            let pcjr_port_b = PcJrPortB::new();
+            let port_list = pcjr_port_b.port_list();
+            self.io_map
+                .extend(port_list.into_iter().map(|p| (p, IoDeviceType::PcJrPortB)));
+            self.pcjr_port_b = Some(pcjr_port_b);
+        }
+
+        // Create the 8042 keyboard controller on AT-class machines. Its ports are mapped after the PPI's,
+        // so the controller owns port 0x60 if both are present.
+        if let KbControllerType::At = machine_desc.kb_controller {
+            let kbc = Kbc::new(&video_types);
+            let port_list = kbc.port_list();
+            self.io_map
+                .extend(port_list.into_iter().map(|p| (p, IoDeviceType::KbController)));
+            self.kbc = Some(kbc);
+        }
+
         // Create the PIT. One PIT will always exist, but it may be an 8253 or 8254.
         // Pick the device type from MachineDesc.
         // Provide the timer with its base crystal and divisor.
@@ -1628,6 +1716,9 @@ impl BusInterface {
                 kb_config.typematic_delay,
                 kb_config.typematic_rate,
             );
+            if self.kbc.is_some() {
+                keyboard.attach_at_controller();
+            }
 
             self.keyboard = Some(keyboard);
         }
@@ -1636,7 +1727,10 @@ impl BusInterface {
         if let Some(fdc_config) = &machine_config.fdc {
             let floppy_ct = fdc_config.drive.len();
 
-            let fdc = FloppyController::new(floppy_ct);
+            let mut fdc = FloppyController::new(floppy_ct);
+            for (i, drive) in fdc_config.drive.iter().take(FDC_MAX_DRIVES).enumerate() {
+                fdc.set_drive_type(i, drive.fd_type);
+            }
             // Add FDC ports to io_map
             let port_list = fdc.port_list();
             self.io_map
@@ -1662,7 +1756,7 @@ impl BusInterface {
         // Create an onboard parallel port if specified
         if let Some(port_base) = machine_desc.onboard_parallel {
             log::debug!("Creating on-board parallel port...");
-            let parallel = ParallelController::new(Some(port_base));
+            let parallel = ParallelController::new(Some(port_base), None);
             // Add Parallel Port ports to io_map
             let port_list = parallel.port_list();
             self.io_map
@@ -1670,6 +1764,104 @@ impl BusInterface {
             self.parallel = Some(parallel);
         }
 
+        // Create a parallel card if specified, and plug in its device. A machine with an on-board
+        // parallel port gets the device plugged into that port instead.
+        if let Some(parallel_config) = machine_config.parallel.get(0) {
+            if self.parallel.is_none() {
+                log::debug!("Creating parallel card...");
+                if let Some(irq) = parallel_config.irq.filter(|irq| *irq > 7) {
+                    return Err(anyhow::anyhow!(
+                        "Invalid parallel port IRQ: {}. Valid IRQs are 0-7",
+                        irq
+                    ));
+                }
+                let parallel = ParallelController::new(parallel_config.io_base, parallel_config.irq);
+                // Add Parallel Port ports to io_map
+                let port_list = parallel.port_list();
+                self.io_map
+                    .extend(port_list.into_iter().map(|p| (p, IoDeviceType::Parallel)));
+                self.parallel = Some(parallel);
+            }
+            if let Some(device_type) = parallel_config.device {
+                let device = match device_type {
+                    ParallelDeviceType::Covox => ParallelDevice::Covox(CovoxSpeechThing::new()),
+                    ParallelDeviceType::DisneySoundSource => {
+                        ParallelDevice::DisneySoundSource(DisneySoundSource::new())
+                    }
+                    ParallelDeviceType::Printer => {
+                        ParallelDevice::Printer(Printer::new(parallel_config.printer_emulation))
+                    }
+                };
+                log::debug!("Attaching {} to parallel port...", device.name());
+                if let Some(parallel) = &mut self.parallel {
+                    parallel.attach(device);
+                }
+            }
+        }
+
+        // Create an onboard sound chip if specified
+        if let Some(chip_type) = machine_desc.onboard_sound {
+            log::debug!("Creating on-board sound chip: {:?}", chip_type);
+            let sound_chip = Sn76489::new(chip_type);
+            // Add sound chip ports to io_map
+            let port_list = sound_chip.port_list();
+            self.io_map
+                .extend(port_list.into_iter().map(|p| (p, IoDeviceType::SoundChip)));
+            self.sound_chip = Some(sound_chip);
+        }
+
+        // Create sound cards
+        for sound_config in machine_config.sound.iter() {
+            match sound_config.sound_type {
+                SoundType::AdLib => {
+                    if self.adlib.is_some() {
+                        log::warn!("Only one AdLib card is supported.");
+                        continue;
+                    }
+                    let adlib = AdLibCard::new();
+                    // Add AdLib ports to io_map
+                    let port_list = adlib.port_list();
+                    self.io_map
+                        .extend(port_list.into_iter().map(|p| (p, IoDeviceType::AdLib)));
+                    self.adlib = Some(adlib);
+                }
+                SoundType::SoundBlaster | SoundType::SoundBlaster2 => {
+                    if self.sound_blaster.is_some() {
+                        log::warn!("Only one Sound Blaster card is supported.");
+                        continue;
+                    }
+                    let sb_type = match sound_config.sound_type {
+                        SoundType::SoundBlaster2 => SoundBlasterType::SoundBlaster2,
+                        _ => SoundBlasterType::SoundBlaster1,
+                    };
+                    // The FM chip is also decoded at the AdLib ports, unless an AdLib card has them.
+                    let fm_alias = self.adlib.is_none();
+                    if !fm_alias {
+                        log::warn!("AdLib card present. Sound Blaster FM will only be available at its base address.");
+                    }
+                    let sb_irq = sound_config.irq.unwrap_or(SB_DEFAULT_IRQ);
+                    if !SB_VALID_IRQS.contains(&sb_irq) {
+                        return Err(anyhow::anyhow!(
+                            "Invalid Sound Blaster IRQ: {}. Valid IRQs are {:?}",
+                            sb_irq,
+                            SB_VALID_IRQS
+                        ));
+                    }
+                    let sound_blaster = SoundBlaster::new(
+                        sb_type,
+                        sound_config.io_base.unwrap_or(SB_DEFAULT_IO_BASE),
+                        sb_irq,
+                        fm_alias,
+                    );
+                    // Add Sound Blaster ports to io_map
+                    let port_list = sound_blaster.port_list();
+                    self.io_map
+                        .extend(port_list.into_iter().map(|p| (p, IoDeviceType::SoundBlaster)));
+                    self.sound_blaster = Some(sound_blaster);
+                }
+            }
+        }
+
         // Create a Serial card if specified
         if let Some(serial_config) = machine_config.serial.get(0) {
             match serial_config.sc_type {
@@ -1718,6 +1910,30 @@ impl BusInterface {
 
                     video_dispatch = VideoCardDispatch::Mda(mda)
                 }
+                VideoType::HGC => {
+                    let hgc = MDACard::new_hgc(TraceLogger::None, clock_mode, true, video_frame_debug);
+                    let port_list = hgc.port_list();
+                    self.io_map
+                        .extend(port_list.into_iter().map(|p| (p, IoDeviceType::Video(video_id))));
+
+                    // The second HGC page at B8000 overlaps a color adapter's memory. If one is installed
+                    // alongside, only map the first page; the HGC's 'half' configuration behaves the same way.
+                    let color_card = machine_config
+                        .video
+                        .iter()
+                        .any(|v| !matches!(v.video_type, VideoType::MDA | VideoType::HGC));
+                    let aperture = if color_card {
+                        log::debug!("Color adapter present: mapping only the first HGC page");
+                        mda::MDA_MEM_APERTURE
+                    }
+                    else {
+                        mda::HGC_MEM_APERTURE
+                    };
+                    let mem_descriptor = MemRangeDescriptor::new(mda::MDA_MEM_ADDRESS, aperture, false);
+                    self.register_map(MmioDeviceType::Video(video_id), mem_descriptor);
+
+                    video_dispatch = VideoCardDispatch::Mda(hgc)
+                }
                 VideoType::CGA => {
                     let cga = CGACard::new(TraceLogger::None, clock_mode, video_frame_debug);
                     let port_list = cga.port_list();
@@ -1758,7 +1974,7 @@ impl BusInterface {
                 }
                 #[cfg(feature = "vga")]
                 VideoType::VGA => {
-                    let vga = VGACard::new(TraceLogger::None);
+                    let vga = VGACard::new(TraceLogger::None, clock_mode, video_frame_debug);
                     let port_list = vga.port_list();
                     self.io_map
                         .extend(port_list.into_iter().map(|p| (p, IoDeviceType::Video(video_id))));
@@ -1805,6 +2021,19 @@ impl BusInterface {
         }
     }
 
+    /// Run the sound chip if present. On the PCjr, the chip is only heard when selected by the
+    /// audio multiplexer.
+    pub(crate) fn run_sound_chip(&mut self, us: f64) {
+        if let Some(sound_chip) = &mut self.sound_chip {
+            let selected = self
+                .pcjr_port_b
+                .as_ref()
+                .is_none_or(|port_b| port_b.sound_chip_selected());
+            sound_chip.set_enabled(selected);
+            sound_chip.run(us);
+        }
+    }
+
     // Schedule extra ticks for the PIT.
     pub fn adjust_pit(&mut self, ticks: u32) {
         log::debug!("Scheduling {} extra system ticks for PIT", ticks);
@@ -1831,17 +2060,19 @@ impl BusInterface {
                     false => keyboard.key_up(kb_event.keycode),
                 }
 
-                // Read a byte from the keyboard
-                if let Some(kb_byte) = keyboard.recv_scancode() {
-                    // Do we have a PPI? if so, send the scancode to the PPI
-                    if let Some(ppi) = &mut self.ppi {
-                        ppi.send_keyboard(kb_byte);
-
-                        if ppi.kb_enabled() {
-                            if let Some(pic) = &mut self.pic1 {
-                                // TODO: Should we let the PPI do this directly?
-                                //log::warn!("sending kb interrupt for byte: {:02X}", kb_byte);
-                                pic.pulse_interrupt(1);
+                // Read a byte from the keyboard, unless the keyboard controller reads it when it runs.
+                if self.kbc.is_none() {
+                    if let Some(kb_byte) = keyboard.recv_scancode() {
+                        // Do we have a PPI? if so, send the scancode to the PPI
+                        if let Some(ppi) = &mut self.ppi {
+                            ppi.send_keyboard(kb_byte);
+
+                            if ppi.kb_enabled() {
+                                if let Some(pic) = &mut self.pic1 {
+                                    // TODO: Should we let the PPI do this directly?
+                                    //log::warn!("sending kb interrupt for byte: {:02X}", kb_byte);
+                                    pic.pulse_interrupt(1);
+                                }
                             }
                         }
                     }
@@ -1854,17 +2085,19 @@ impl BusInterface {
                 keyboard.run(KB_UPDATE_RATE);
                 self.kb_us_accum -= KB_UPDATE_RATE;
 
-                // Read a byte from the keyboard
-                if let Some(kb_byte) = keyboard.recv_scancode() {
-                    // Do we have a PPI? if so, send the scancode to the PPI
-                    if let Some(ppi) = &mut self.ppi {
-                        ppi.send_keyboard(kb_byte);
-
-                        if ppi.kb_enabled() {
-                            if let Some(pic) = &mut self.pic1 {
-                                // TODO: Should we let the PPI do this directly?
-                                //log::warn!("sending kb interrupt for byte: {:02X}", kb_byte);
-                                pic.pulse_interrupt(1);
+                // Read a byte from the keyboard, unless the keyboard controller reads it when it runs.
+                if self.kbc.is_none() {
+                    if let Some(kb_byte) = keyboard.recv_scancode() {
+                        // Do we have a PPI? if so, send the scancode to the PPI
+                        if let Some(ppi) = &mut self.ppi {
+                            ppi.send_keyboard(kb_byte);
+
+                            if ppi.kb_enabled() {
+                                if let Some(pic) = &mut self.pic1 {
+                                    // TODO: Should we let the PPI do this directly?
+                                    //log::warn!("sending kb interrupt for byte: {:02X}", kb_byte);
+                                    pic.pulse_interrupt(1);
+                                }
                             }
                         }
                     }
@@ -1885,6 +2118,28 @@ impl BusInterface {
             ppi.run(pic, us);
         }
 
+        // Run the keyboard controller if present. It exchanges bytes with the keyboard and takes PIC
+        // to generate keyboard interrupts.
+        if let Some(kbc) = &mut self.kbc {
+            kbc.run(self.keyboard.as_mut(), pic);
+        }
+
+        self.run_sound_chip(us);
+
+        // Run the AdLib card if present.
+        if let Some(adlib) = &mut self.adlib {
+            adlib.run(us);
+        }
+
+        // Run the parallel port, for any device plugged into it.
+        if let Some(parallel) = &mut self.parallel {
+            if parallel.run(us) {
+                if let Some(pic) = &mut self.pic1 {
+                    pic.pulse_interrupt(parallel.irq() as u8);
+                }
+            }
+        }
+
         // Run the PIT. The PIT communicates with lots of things, so we send it the entire bus.
         // The PIT may have a separate clock crystal, such as in the IBM AT. In this case, there may not
         // be an integer number of PIT ticks per system ticks. Therefore, the PIT can take either
@@ -1943,6 +2198,12 @@ impl BusInterface {
             self.hdc = Some(hdc);
         }
 
+        // Run the Sound Blaster, passing it DMA controller while DMA is still unattached.
+        if let Some(mut sound_blaster) = self.sound_blaster.take() {
+            sound_blaster.run(&mut dma1, self, us);
+            self.sound_blaster = Some(sound_blaster);
+        }
+
         // Run the DMA controller.
         dma1.run(self);
 
@@ -2138,6 +2399,11 @@ impl BusInterface {
             dma1.reset();
         }
 
+        // Reset keyboard controller
+        if let Some(kbc) = self.kbc.as_mut() {
+            kbc.reset();
+        }
+
         // Reset video cards
         let vids: Vec<_> = self.videocards.keys().cloned().collect();
         for vid in vids {
@@ -2172,6 +2438,16 @@ impl BusInterface {
                         byte = Some(ppi.read_u8(port, nul_delta));
                     }
                 }
+                IoDeviceType::PcJrPortB => {
+                    if let Some(pcjr_port_b) = &mut self.pcjr_port_b {
+                        byte = Some(pcjr_port_b.read_u8(port, nul_delta));
+                    }
+                }
+                IoDeviceType::KbController => {
+                    if let Some(kbc) = &mut self.kbc {
+                        byte = Some(kbc.read_u8(port, nul_delta));
+                    }
+                }
                 IoDeviceType::Pit => {
                     // There will always be a PIT, so safe to unwrap
                     byte = Some(
@@ -2223,6 +2499,21 @@ impl BusInterface {
                         byte = Some(parallel.read_u8(port, nul_delta));
                     }
                 }
+                IoDeviceType::SoundChip => {
+                    if let Some(sound_chip) = &mut self.sound_chip {
+                        byte = Some(sound_chip.read_u8(port, nul_delta));
+                    }
+                }
+                IoDeviceType::AdLib => {
+                    if let Some(adlib) = &mut self.adlib {
+                        byte = Some(adlib.read_u8(port, nul_delta));
+                    }
+                }
+                IoDeviceType::SoundBlaster => {
+                    if let Some(sound_blaster) = &mut self.sound_blaster {
+                        byte = Some(sound_blaster.read_u8(port, nul_delta));
+                    }
+                }
                 IoDeviceType::Video(vid) => {
                     if let Some(video_dispatch) = self.videocards.get_mut(&vid) {
                         byte = match video_dispatch {
@@ -2255,7 +2546,11 @@ impl BusInterface {
             })
             .or_insert((byte.is_some(), IoDeviceStats::one_read()));
 
-        byte.unwrap_or(NO_IO_BYTE)
+        let byte = byte.unwrap_or(NO_IO_BYTE);
+        if !self.io_bp_flags.is_empty() {
+            self.check_io_breakpoint(port, byte, false);
+        }
+        byte
     }
 
     /// Write an 8-bit value to an IO port.
@@ -2281,6 +2576,20 @@ impl BusInterface {
                         self.ppi = Some(ppi);
                     }
                 }
+                IoDeviceType::PcJrPortB => {
+                    if let Some(pcjr_port_b) = &mut self.pcjr_port_b {
+                        // Port B write does not need bus.
+                        pcjr_port_b.write_u8(port, data, None, nul_delta);
+                        resolved = true;
+                    }
+                }
+                IoDeviceType::KbController => {
+                    if let Some(kbc) = &mut self.kbc {
+                        // Keyboard controller write does not need bus.
+                        kbc.write_u8(port, data, None, nul_delta);
+                        resolved = true;
+                    }
+                }
                 IoDeviceType::Pit => {
                     if let Some(mut pit) = self.pit.take() {
                         //log::debug!("writing PIT with {} cycles", cycles);
@@ -2344,6 +2653,25 @@ impl BusInterface {
                         resolved = true;
                     }
                 }
+                IoDeviceType::SoundChip => {
+                    if let Some(sound_chip) = &mut self.sound_chip {
+                        // Sound chip write does not need bus.
+                        sound_chip.write_u8(port, data, None, nul_delta);
+                        resolved = true;
+                    }
+                }
+                IoDeviceType::AdLib => {
+                    if let Some(adlib) = &mut self.adlib {
+                        adlib.write_u8(port, data, None, nul_delta);
+                        resolved = true;
+                    }
+                }
+                IoDeviceType::SoundBlaster => {
+                    if let Some(sound_blaster) = &mut self.sound_blaster {
+                        sound_blaster.write_u8(port, data, None, nul_delta);
+                        resolved = true;
+                    }
+                }
                 IoDeviceType::Video(vid) => {
                     if let Some(video_dispatch) = self.videocards.get_mut(&vid) {
                         match video_dispatch {
@@ -2384,6 +2712,40 @@ impl BusInterface {
                 e.1.writes_dirty = true;
             })
             .or_insert((resolved, IoDeviceStats::one_read()));
+
+        if !self.io_bp_flags.is_empty() {
+            self.check_io_breakpoint(port, data, true);
+        }
+    }
+
+    /// Record an access to an IO port with a breakpoint flag set. The CPU collects the hit with
+    /// take_io_breakpoint_hit() and decides whether the breakpoint triggers.
+    fn check_io_breakpoint(&mut self, port: u16, data: u8, write: bool) {
+        let flag = if write { IO_BPW_BIT } else { IO_BPR_BIT };
+        if self.io_bp_flags.get(&port).is_some_and(|f| f & flag != 0) {
+            self.io_bp_hit = Some(IoBreakPointHit { port, data, write });
+        }
+    }
+
+    /// Set the specified breakpoint flags for an IO port.
+    pub fn set_io_flags(&mut self, port: u16, flags: u8) {
+        *self.io_bp_flags.entry(port).or_insert(0) |= flags;
+    }
+
+    /// Clear the specified breakpoint flags for an IO port.
+    pub fn clear_io_flags(&mut self, port: u16, flags: u8) {
+        if let Some(f) = self.io_bp_flags.get_mut(&port) {
+            *f &= !flags;
+            if *f == 0 {
+                self.io_bp_flags.remove(&port);
+            }
+        }
+    }
+
+    /// Return and clear the last IO breakpoint hit, if any.
+    #[inline]
+    pub fn take_io_breakpoint_hit(&mut self) -> Option<IoBreakPointHit> {
+        self.io_bp_hit.take()
     }
 
     /// Return a boolean indicating whether a timer interrupt is imminent.
@@ -2410,6 +2772,14 @@ impl BusInterface {
         &mut self.ppi
     }
 
+    pub fn pcjr_port_b_mut(&mut self) -> &mut Option<PcJrPortB> {
+        &mut self.pcjr_port_b
+    }
+
+    pub fn kbc_mut(&mut self) -> &mut Option<Kbc> {
+        &mut self.kbc
+    }
+
     pub fn dma_mut(&mut self) -> &mut Option<DMAController> {
         &mut self.dma1
     }
@@ -2430,6 +2800,22 @@ impl BusInterface {
         &mut self.mouse
     }
 
+    pub fn sound_chip_mut(&mut self) -> &mut Option<Sn76489> {
+        &mut self.sound_chip
+    }
+
+    pub fn adlib_mut(&mut self) -> &mut Option<AdLibCard> {
+        &mut self.adlib
+    }
+
+    pub fn sound_blaster_mut(&mut self) -> &mut Option<SoundBlaster> {
+        &mut self.sound_blaster
+    }
+
+    pub fn parallel_mut(&mut self) -> &mut Option<ParallelController> {
+        &mut self.parallel
+    }
+
     pub fn primary_video(&self) -> Option<Box<&dyn VideoCard>> {
         if self.videocard_ids.len() > 0 {
             self.video(&self.videocard_ids[0])
@@ -2590,3 +2976,89 @@ impl BusInterface {
         token_vec.iter().map(|(_, tokens)| tokens.clone()).collect()
     }
 }
+
+/// Memory contents and the state of every installed device. Memory flags are not saved, as they
+/// hold breakpoints and ROM markers that belong to the running session rather than the machine.
+impl SaveState for BusInterface {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.write_bytes(&self.memory);
+        w.put(&self.intr_imminent);
+        w.put(&self.dma_counter);
+        w.put(&self.pit_ticks_advance);
+        w.put(&self.timer_trigger1_armed);
+        w.put(&self.timer_trigger2_armed);
+        w.put(&self.cga_tick_accum);
+        w.put(&self.tga_tick_accum);
+        w.put(&self.kb_us_accum);
+        w.put(&self.refresh_active);
+
+        w.device(b"KBD ", &self.keyboard);
+        w.device(b"PPI ", &self.ppi);
+        w.device(b"PJB ", &self.pcjr_port_b);
+        w.device(b"KBC ", &self.kbc);
+        w.device(b"PIT ", &self.pit);
+        w.device(b"DMA1", &self.dma1);
+        w.device(b"DMA2", &self.dma2);
+        w.device(b"PIC1", &self.pic1);
+        w.device(b"PIC2", &self.pic2);
+        w.device(b"SER ", &self.serial);
+        w.device(b"FDC ", &self.fdc);
+        w.device(b"HDC ", &self.hdc);
+        w.device(b"SND ", &self.sound_chip);
+        w.device(b"ADLB", &self.adlib);
+        w.device(b"SB  ", &self.sound_blaster);
+        w.device(b"LPT ", &self.parallel);
+
+        w.put(&self.videocard_ids.len());
+        for vid in self.videocard_ids.iter() {
+            if let Some(card) = self.videocards.get(vid) {
+                card.save_state(w);
+            }
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        r.read_bytes_into(&mut self.memory)?;
+        self.intr_imminent = r.get()?;
+        self.dma_counter = r.get()?;
+        self.pit_ticks_advance = r.get()?;
+        self.timer_trigger1_armed = r.get()?;
+        self.timer_trigger2_armed = r.get()?;
+        self.cga_tick_accum = r.get()?;
+        self.tga_tick_accum = r.get()?;
+        self.kb_us_accum = r.get()?;
+        self.refresh_active = r.get()?;
+
+        r.device(b"KBD ", &mut self.keyboard)?;
+        r.device(b"PPI ", &mut self.ppi)?;
+        r.device(b"PJB ", &mut self.pcjr_port_b)?;
+        r.device(b"KBC ", &mut self.kbc)?;
+        r.device(b"PIT ", &mut self.pit)?;
+        r.device(b"DMA1", &mut self.dma1)?;
+        r.device(b"DMA2", &mut self.dma2)?;
+        r.device(b"PIC1", &mut self.pic1)?;
+        r.device(b"PIC2", &mut self.pic2)?;
+        r.device(b"SER ", &mut self.serial)?;
+        r.device(b"FDC ", &mut self.fdc)?;
+        r.device(b"HDC ", &mut self.hdc)?;
+        r.device(b"SND ", &mut self.sound_chip)?;
+        r.device(b"ADLB", &mut self.adlib)?;
+        r.device(b"SB  ", &mut self.sound_blaster)?;
+        r.device(b"LPT ", &mut self.parallel)?;
+
+        let card_ct: usize = r.get()?;
+        if card_ct != self.videocard_ids.len() {
+            anyhow::bail!(
+                "State has {} video cards, machine has {}",
+                card_ct,
+                self.videocard_ids.len()
+            );
+        }
+        for vid in self.videocard_ids.iter() {
+            if let Some(card) = self.videocards.get_mut(vid) {
+                card.load_state(r)?;
+            }
+        }
+        Ok(())
+    }
+}
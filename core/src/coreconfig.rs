@@ -38,6 +38,7 @@ use crate::{
     cpu_common::TraceMode,
     cpu_validator::ValidatorType,
     device_traits::videocard::{ClockingMode, VideoType},
+    devices::pc_speaker::SpeakerFilter,
     machine_types::MachineType,
 };
 use std::path::PathBuf;
@@ -59,6 +60,7 @@ pub trait CoreConfig {
     fn get_machine_type(&self) -> MachineType;
 
     fn get_audio_enabled(&self) -> bool;
+    fn get_speaker_filter(&self) -> SpeakerFilter;
     fn get_machine_noroms(&self) -> bool;
     fn get_machine_turbo(&self) -> bool;
     //fn get_keyboard_type(&self) -> Option<KeyboardType>;
@@ -937,11 +937,24 @@ impl Cpu {
         self.trace_comment("BUS_BEGIN");
 
         // Check this address for a memory access breakpoint
-        if self.bus.get_flags(address as usize) & MEM_BPA_BIT != 0 {
+        if self.bus.get_flags(address as usize) & MEM_BPA_BIT != 0
+            && self.eval_breakpoints(|bp| matches!(bp, BreakPointType::MemAccessFlat(a) if *a == address))
+        {
             // Breakpoint hit
             self.state = CpuState::BreakpointHit;
         }
 
+        // Check this address for a watchpoint
+        match new_bus_status {
+            BusStatus::MemRead if self.bus.get_flags(address as usize) & MEM_BPR_BIT != 0 => {
+                self.check_watchpoint(address, 0, false);
+            }
+            BusStatus::MemWrite if self.bus.get_flags(address as usize) & MEM_BPW_BIT != 0 => {
+                self.check_watchpoint(address, data as u8, true);
+            }
+            _ => {}
+        }
+
         if new_bus_status != BusStatus::CodeFetch {
             // The EU has requested a Read/Write cycle, if we haven't scheduled a prefetch, block
             // prefetching until the bus transfer is complete.
@@ -599,6 +599,7 @@ impl Cpu {
                     .io_read_u8((self.address_latch & 0xFFFF) as u16, self.instr_elapsed);
                 self.data_bus = byte as u16;
                 self.instr_elapsed = 0;
+                self.check_io_breakpoint();
 
                 validate_read_u8!(
                     self,
@@ -616,6 +617,7 @@ impl Cpu {
                     self.instr_elapsed,
                 );
                 self.instr_elapsed = 0;
+                self.check_io_breakpoint();
 
                 validate_write_u8!(self, self.address_latch, (self.data_bus & 0x00FF) as u8, BusType::Io);
             }
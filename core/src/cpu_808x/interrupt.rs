@@ -212,7 +212,9 @@ impl Cpu {
     /// INT1 or INT2.
     pub fn intr_routine(&mut self, vector: u8, itype: InterruptType, skip_first: bool) {
         // Check for interrupt breakpoint.
-        if self.int_flags[vector as usize] & INTERRUPT_BREAKPOINT != 0 {
+        if self.int_flags[vector as usize] & INTERRUPT_BREAKPOINT != 0
+            && self.eval_breakpoints(|bp| matches!(bp, BreakPointType::Interrupt(v) if *v == vector))
+        {
             self.set_breakpoint_flag();
         }
 
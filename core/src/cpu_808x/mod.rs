@@ -60,6 +60,7 @@ mod modrm;
 mod muldiv;
 mod queue;
 mod stack;
+mod state;
 mod step;
 mod string;
 
@@ -73,8 +74,8 @@ use crate::cpu_common::{CpuOption, CpuType, TraceMode};
 use crate::cpu_validator::ValidatorType;
 
 use crate::{
-    breakpoints::BreakPointType,
-    bus::{BusInterface, MEM_BPA_BIT, MEM_BPE_BIT, MEM_RET_BIT},
+    breakpoints::{BreakPointEnv, BreakPointType, ExprRegister, WatchType},
+    bus::{BusInterface, IO_BPR_BIT, IO_BPW_BIT, MEM_BPA_BIT, MEM_BPE_BIT, MEM_BPR_BIT, MEM_BPW_BIT, MEM_RET_BIT},
     bytequeue::*,
 };
 //use crate::interrupt::log_post_interrupt;
@@ -686,6 +687,32 @@ pub struct I8288 {
     _den:  bool,
 }
 
+/// Details of the memory access that triggered a watchpoint.
+#[derive(Clone)]
+pub struct WatchpointHit {
+    pub address: u32,
+    pub kind: WatchType,
+    pub old_value: u8,
+    pub new_value: u8,
+    pub cs: u16,
+    pub ip: u16,
+    pub instruction: Instruction,
+}
+
+impl fmt::Display for WatchpointHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            WatchType::Read => write!(f, "Read of [{:05X}] ({:02X})", self.address, self.old_value)?,
+            _ => write!(
+                f,
+                "Write to [{:05X}] ({:02X} -> {:02X})",
+                self.address, self.old_value, self.new_value
+            )?,
+        }
+        write!(f, " by {} at {:04X}:{:04X}", self.instruction, self.cs, self.ip)
+    }
+}
+
 #[derive(Default)]
 pub struct Cpu {
     cpu_type: CpuType,
@@ -794,6 +821,7 @@ pub struct Cpu {
 
     // Breakpoints
     breakpoints: Vec<BreakPointType>,
+    watchpoint_hit: Option<WatchpointHit>,
 
     step_over_target: Option<CpuAddress>,
 
@@ -1965,26 +1993,13 @@ impl Cpu {
     ///
     /// Clears bus breakpoint flags from previous breakpoint list before applying new.
     pub fn set_breakpoints(&mut self, bp_list: Vec<BreakPointType>) {
-        // Clear bus flags for current breakpoints
-        self.breakpoints.iter().for_each(|bp| match bp {
-            BreakPointType::ExecuteFlat(addr) => {
-                log::debug!("Clearing breakpoint on execute at address: {:05X}", *addr);
-                self.bus.clear_flags(*addr as usize, MEM_BPE_BIT);
-            }
-            BreakPointType::MemAccessFlat(addr) => {
-                self.bus.clear_flags(*addr as usize, MEM_BPA_BIT);
-            }
-            BreakPointType::Interrupt(vector) => {
-                self.int_flags[*vector as usize] = 0;
-            }
-            _ => {}
-        });
+        self.clear_breakpoint_flags();
 
         // Replace current breakpoint list
         self.breakpoints = bp_list;
 
         // Set bus flags for new breakpoints
-        self.breakpoints.iter().for_each(|bp| match bp {
+        self.breakpoints.iter().for_each(|bp| match bp.base() {
             BreakPointType::ExecuteFlat(addr) => {
                 log::debug!("Setting breakpoint on execute at address: {:05X}", *addr);
                 self.bus.set_flags(*addr as usize, MEM_BPE_BIT);
@@ -1996,10 +2011,129 @@ impl Cpu {
             BreakPointType::Interrupt(vector) => {
                 self.int_flags[*vector as usize] = INTERRUPT_BREAKPOINT;
             }
+            BreakPointType::IoRead(port) => {
+                log::debug!("Setting breakpoint on IO read at port: {:04X}", *port);
+                self.bus.set_io_flags(*port, IO_BPR_BIT);
+            }
+            BreakPointType::IoWrite(port, _, _) => {
+                log::debug!("Setting breakpoint on IO write at port: {:04X}", *port);
+                self.bus.set_io_flags(*port, IO_BPW_BIT);
+            }
+            BreakPointType::Watch(start, len, kind) => {
+                log::debug!("Setting {:?} watchpoint at address: {:05X} len: {}", kind, *start, *len);
+                let flag = match kind {
+                    WatchType::Read => MEM_BPR_BIT,
+                    WatchType::Write | WatchType::Change => MEM_BPW_BIT,
+                };
+                for addr in *start..start.saturating_add(*len) {
+                    self.bus.set_flags(addr as usize, flag);
+                }
+            }
+            _ => {}
+        });
+    }
+
+    /// Remove the breakpoints matching the specified predicate and add the provided list,
+    /// leaving any other breakpoints (and their state) in place.
+    pub fn update_breakpoints(&mut self, remove: impl Fn(&BreakPointType) -> bool, add: Vec<BreakPointType>) {
+        self.clear_breakpoint_flags();
+        let mut bp_list: Vec<BreakPointType> =
+            std::mem::take(&mut self.breakpoints).into_iter().filter(|bp| !remove(bp)).collect();
+        bp_list.extend(add);
+        self.set_breakpoints(bp_list);
+    }
+
+    /// Clear bus flags for current breakpoints
+    fn clear_breakpoint_flags(&mut self) {
+        self.breakpoints.iter().for_each(|bp| match bp.base() {
+            BreakPointType::ExecuteFlat(addr) => {
+                log::debug!("Clearing breakpoint on execute at address: {:05X}", *addr);
+                self.bus.clear_flags(*addr as usize, MEM_BPE_BIT);
+            }
+            BreakPointType::MemAccessFlat(addr) => {
+                self.bus.clear_flags(*addr as usize, MEM_BPA_BIT);
+            }
+            BreakPointType::Interrupt(vector) => {
+                self.int_flags[*vector as usize] = 0;
+            }
+            BreakPointType::IoRead(port) => {
+                self.bus.clear_io_flags(*port, IO_BPR_BIT);
+            }
+            BreakPointType::IoWrite(port, _, _) => {
+                self.bus.clear_io_flags(*port, IO_BPW_BIT);
+            }
+            BreakPointType::Watch(start, len, _) => {
+                for addr in *start..start.saturating_add(*len) {
+                    self.bus.clear_flags(addr as usize, MEM_BPR_BIT | MEM_BPW_BIT);
+                }
+            }
             _ => {}
         });
     }
 
+    /// Evaluate the breakpoints that match the specified predicate, once their bus or interrupt
+    /// flag has been hit. Returns true if any matching breakpoint triggers. Conditions are evaluated
+    /// for every matching breakpoint so that hit counts stay accurate.
+    pub fn eval_breakpoints(&mut self, pred: impl Fn(&BreakPointType) -> bool) -> bool {
+        let mut breakpoints = std::mem::take(&mut self.breakpoints);
+        let mut triggered = false;
+        for bp in breakpoints.iter_mut() {
+            if !pred(bp.base()) {
+                continue;
+            }
+            match bp {
+                BreakPointType::Conditional(_, condition) => triggered |= condition.test(self),
+                _ => triggered = true,
+            }
+        }
+        self.breakpoints = breakpoints;
+        triggered
+    }
+
+    /// Check an IO breakpoint hit recorded by the bus, if any, and set the breakpoint flag if
+    /// a matching breakpoint triggers.
+    #[inline]
+    pub fn check_io_breakpoint(&mut self) {
+        if let Some(hit) = self.bus.take_io_breakpoint_hit() {
+            if self.eval_breakpoints(|bp| bp.matches_io(hit.port, hit.data, hit.write)) {
+                log::debug!("IO breakpoint hit on port {:04X}", hit.port);
+                self.set_breakpoint_flag();
+            }
+        }
+    }
+
+    /// Check a memory access to an address with a watchpoint flag set, and set the breakpoint flag
+    /// if a matching watchpoint triggers. For writes, `data` is the value about to be written.
+    pub fn check_watchpoint(&mut self, address: u32, data: u8, write: bool) {
+        let old_value = self.bus.peek_u8(address as usize).unwrap_or(0xFF);
+        let changed = write && old_value != data;
+
+        if self.eval_breakpoints(|bp| bp.matches_watch(address, write, changed)) {
+            let kind = match (write, changed) {
+                (false, _) => WatchType::Read,
+                (true, true) => WatchType::Change,
+                (true, false) => WatchType::Write,
+            };
+            let hit = WatchpointHit {
+                address,
+                kind,
+                old_value,
+                new_value: if write { data } else { old_value },
+                cs: self.cs,
+                ip: self.instruction_ip,
+                instruction: self.i.clone(),
+            };
+            log::debug!("Watchpoint hit: {}", hit);
+            self.watchpoint_hit = Some(hit);
+            self.set_breakpoint_flag();
+        }
+    }
+
+    /// Return and clear the details of the last watchpoint hit, if any.
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.watchpoint_hit.take()
+    }
+
     pub fn get_breakpoint_flag(&self) -> bool {
         if let CpuState::BreakpointHit = self.state {
             true
@@ -2250,3 +2384,36 @@ impl Cpu {
         &self.validator
     }
 }
+
+impl BreakPointEnv for Cpu {
+    fn expr_register(&self, reg: ExprRegister) -> u16 {
+        match reg {
+            ExprRegister::AL => self.a.l() as u16,
+            ExprRegister::CL => self.c.l() as u16,
+            ExprRegister::DL => self.d.l() as u16,
+            ExprRegister::BL => self.b.l() as u16,
+            ExprRegister::AH => self.a.h() as u16,
+            ExprRegister::CH => self.c.h() as u16,
+            ExprRegister::DH => self.d.h() as u16,
+            ExprRegister::BH => self.b.h() as u16,
+            ExprRegister::AX => self.a.x(),
+            ExprRegister::CX => self.c.x(),
+            ExprRegister::DX => self.d.x(),
+            ExprRegister::BX => self.b.x(),
+            ExprRegister::SP => self.sp,
+            ExprRegister::BP => self.bp,
+            ExprRegister::SI => self.si,
+            ExprRegister::DI => self.di,
+            ExprRegister::ES => self.es,
+            ExprRegister::CS => self.cs,
+            ExprRegister::SS => self.ss,
+            ExprRegister::DS => self.ds,
+            ExprRegister::IP => self.ip(),
+            ExprRegister::Flags => self.flags,
+        }
+    }
+
+    fn expr_peek_u8(&self, address: u32) -> u8 {
+        self.bus.peek_u8(address as usize).unwrap_or(0xFF)
+    }
+}
@@ -30,7 +30,11 @@
 
 */
 
-use crate::cpu_808x::*;
+use crate::{
+    cpu_808x::*,
+    savestate::{SaveState, StateReader, StateWriter},
+};
+use anyhow::bail;
 
 pub struct InstructionQueue {
     size: usize,
@@ -169,3 +173,27 @@ impl InstructionQueue {
         }
     }
 }
+
+impl SaveState for InstructionQueue {
+    fn save_state(&self, w: &mut StateWriter) {
+        // Store the queue contents in order rather than the raw ring buffer.
+        w.put(&self.size);
+        let contents: Vec<u8> = (0..self.len).map(|i| self.q[(self.back + i) % self.size]).collect();
+        w.put(&contents);
+        w.put(&self.preload);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        let size: usize = r.get()?;
+        let contents: Vec<u8> = r.get()?;
+        if size != self.size || contents.len() > size {
+            bail!("Queue size mismatch: expected {}, got {}", self.size, size);
+        }
+        self.flush();
+        for byte in contents {
+            self.push8(byte);
+        }
+        self.preload = r.get()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,520 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    ---------------------------------------------------------------------------
+
+    cpu_808x::state.rs
+
+    Save state support for the CPU.
+
+    Save states are taken on instruction boundaries, so the decoded instruction
+    itself is not stored. The one exception is a REP-prefixed string
+    instruction that is still repeating - in that case the instruction is
+    decoded again from memory when the state is loaded.
+
+    Breakpoints, instruction history, tracing and validator state belong to the
+    debugging session rather than the machine and are not saved.
+*/
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::{
+    cpu_808x::*,
+    savestate::{impl_state_enum, SaveState, StateReader, StateValue, StateWriter},
+};
+
+impl_state_enum!(CpuState { Normal, BreakpointHit });
+impl_state_enum!(QueueOp {
+    Idle,
+    First,
+    Flush,
+    Subsequent
+});
+impl_state_enum!(TCycle {
+    Tinit,
+    Ti,
+    T1,
+    T2,
+    T3,
+    Tw,
+    T4
+});
+impl_state_enum!(BusStatus {
+    InterruptAck,
+    IoRead,
+    IoWrite,
+    Halt,
+    CodeFetch,
+    MemRead,
+    MemWrite,
+    Passive
+});
+impl_state_enum!(Segment { None, ES, CS, SS, DS });
+impl_state_enum!(TransferSize { Byte, Word });
+impl_state_enum!(OperandSize {
+    NoOperand,
+    NoSize,
+    Operand8,
+    Operand16
+});
+impl_state_enum!(RepType {
+    NoRep,
+    Rep,
+    Repne,
+    Repe,
+    MulDiv
+});
+
+impl StateValue for BiuStateNew {
+    fn write_state(&self, w: &mut StateWriter) {
+        let (tag, n) = match *self {
+            BiuStateNew::Idle => (0, 0),
+            BiuStateNew::ToIdle(n) => (1, n),
+            BiuStateNew::Prefetch => (2, 0),
+            BiuStateNew::ToPrefetch(n) => (3, n),
+            BiuStateNew::Eu => (4, 0),
+            BiuStateNew::ToEu(n) => (5, n),
+        };
+        w.write_u8(tag);
+        w.write_u8(n);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        let tag = r.read_u8()?;
+        let n = r.read_u8()?;
+        Ok(match tag {
+            0 => BiuStateNew::Idle,
+            1 => BiuStateNew::ToIdle(n),
+            2 => BiuStateNew::Prefetch,
+            3 => BiuStateNew::ToPrefetch(n),
+            4 => BiuStateNew::Eu,
+            5 => BiuStateNew::ToEu(n),
+            _ => bail!("Invalid BiuStateNew value: {}", tag),
+        })
+    }
+}
+
+impl StateValue for FetchState {
+    fn write_state(&self, w: &mut StateWriter) {
+        let (tag, n) = match *self {
+            FetchState::Idle => (0, 0),
+            FetchState::Suspended => (1, 0),
+            FetchState::InProgress => (2, 0),
+            FetchState::Scheduled(n) => (3, n),
+            FetchState::ScheduleNext => (4, 0),
+            FetchState::Delayed(n) => (5, n),
+            FetchState::DelayDone => (6, 0),
+            FetchState::Aborting(n) => (7, n),
+            FetchState::BlockedByEU => (8, 0),
+        };
+        w.write_u8(tag);
+        w.write_u8(n);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        let tag = r.read_u8()?;
+        let n = r.read_u8()?;
+        Ok(match tag {
+            0 => FetchState::Idle,
+            1 => FetchState::Suspended,
+            2 => FetchState::InProgress,
+            3 => FetchState::Scheduled(n),
+            4 => FetchState::ScheduleNext,
+            5 => FetchState::Delayed(n),
+            6 => FetchState::DelayDone,
+            7 => FetchState::Aborting(n),
+            8 => FetchState::BlockedByEU,
+            _ => bail!("Invalid FetchState value: {}", tag),
+        })
+    }
+}
+
+impl StateValue for DmaState {
+    fn write_state(&self, w: &mut StateWriter) {
+        let (tag, n) = match *self {
+            DmaState::Idle => (0, 0),
+            DmaState::Dreq => (1, 0),
+            DmaState::Hrq => (2, 0),
+            DmaState::HoldA => (3, 0),
+            DmaState::Operating(n) => (4, n),
+            DmaState::End => (5, 0),
+        };
+        w.write_u8(tag);
+        w.write_u8(n);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        let tag = r.read_u8()?;
+        let n = r.read_u8()?;
+        Ok(match tag {
+            0 => DmaState::Idle,
+            1 => DmaState::Dreq,
+            2 => DmaState::Hrq,
+            3 => DmaState::HoldA,
+            4 => DmaState::Operating(n),
+            5 => DmaState::End,
+            _ => bail!("Invalid DmaState value: {}", tag),
+        })
+    }
+}
+
+impl SaveState for I8288 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.mrdc);
+        w.put(&self.amwc);
+        w.put(&self.mwtc);
+        w.put(&self.iorc);
+        w.put(&self.aiowc);
+        w.put(&self.iowc);
+        w.put(&self.inta);
+        w.put(&self.ale);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        self.mrdc = r.get()?;
+        self.amwc = r.get()?;
+        self.mwtc = r.get()?;
+        self.iorc = r.get()?;
+        self.aiowc = r.get()?;
+        self.iowc = r.get()?;
+        self.inta = r.get()?;
+        self.ale = r.get()?;
+        Ok(())
+    }
+}
+
+impl Cpu {
+    /// Save the state of the CPU, excluding the bus. The bus is saved separately by the Machine.
+    pub fn save_cpu_state(&self, w: &mut StateWriter) {
+        w.put(&format!("{:?}", self.cpu_type));
+        w.put(&self.state);
+
+        // Registers
+        w.put(&self.a.x());
+        w.put(&self.b.x());
+        w.put(&self.c.x());
+        w.put(&self.d.x());
+        w.put(&self.sp);
+        w.put(&self.bp);
+        w.put(&self.si);
+        w.put(&self.di);
+        w.put(&self.cs);
+        w.put(&self.ds);
+        w.put(&self.ss);
+        w.put(&self.es);
+        w.put(&self.flags);
+        w.put(&self.pc);
+
+        w.put(&self.address_bus);
+        w.put(&self.address_latch);
+        w.put(&self.data_bus);
+        w.put(&self.last_ea);
+        self.i8288.save_state(w);
+        w.put(&self.mc_pc);
+        w.put(&self.nx);
+        w.put(&self.rni);
+        w.put(&self.ea_opr);
+
+        w.put(&self.intr);
+        w.put(&self.intr_pending);
+        w.put(&self.in_int);
+        w.put(&self.int_count);
+        w.put(&self.iret_count);
+        w.put(&self.interrupt_inhibit);
+
+        // BIU
+        w.put(&self.biu_state_new);
+        w.put(&self.ready);
+        self.queue.save_state(w);
+        w.put(&self.fetch_size);
+        w.put(&self.fetch_state);
+        w.put(&self.next_fetch_state);
+        w.put(&self.fetch_suspended);
+        w.put(&self.bus_pending_eu);
+        w.put(&self.queue_op);
+        w.put(&self.last_queue_op);
+        w.put(&self.queue_byte);
+        w.put(&self.last_queue_byte);
+        w.put(&self.last_queue_len);
+        w.put(&self.t_cycle);
+        w.put(&self.bus_status);
+        w.put(&self.bus_status_latch);
+        w.put(&self.bus_segment);
+        w.put(&self.transfer_size);
+        w.put(&self.operand_size);
+        w.put(&self.transfer_n);
+        w.put(&self.final_transfer);
+        w.put(&self.bus_wait_states);
+        w.put(&self.wait_states);
+        w.put(&self.lock);
+
+        // Halt
+        w.put(&self.halted);
+        w.put(&self.reported_halt);
+        w.put(&self.halt_not_hold);
+        w.put(&self.wake_timer);
+        w.put(&self.halt_resume_delay);
+        w.put(&self.is_running);
+        w.put(&self.is_error);
+
+        // Rep prefix
+        w.put(&self.in_rep);
+        w.put(&self.rep_init);
+        w.put(&self.rep_type);
+
+        // Cycle state
+        w.put(&self.cycle_num);
+        w.put(&self.t_stamp);
+        w.put(&self.t_step);
+        w.put(&self.t_step_h);
+        w.put(&self.instr_cycle);
+        w.put(&self.device_cycles);
+        w.put(&self.int_elapsed);
+        w.put(&self.instr_elapsed);
+        w.put(&self.instruction_count);
+        w.put(&self.instruction_ip);
+        w.put(&self.instruction_address);
+
+        // Interrupt scheduling
+        w.put(&self.interrupt_scheduling);
+        w.put(&self.interrupt_cycle_period);
+        w.put(&self.interrupt_cycle_num);
+        w.put(&self.interrupt_retrigger);
+        w.put(&self.clk0);
+
+        // DMA
+        w.put(&self.dma_state);
+        w.put(&self.dram_refresh_simulation);
+        w.put(&self.dram_refresh_cycle_period);
+        w.put(&self.dram_refresh_cycle_num);
+        w.put(&self.dram_refresh_adjust);
+        w.put(&self.dram_refresh_tc);
+        w.put(&self.dram_refresh_retrigger);
+        w.put(&self.dma_aen);
+        w.put(&self.dma_holda);
+        w.put(&self.dma_req);
+        w.put(&self.dma_ack);
+        w.put(&self.dma_wait_states);
+
+        // Traps and NMI
+        w.put(&self.trap_enable_delay);
+        w.put(&self.trap_disable_delay);
+        w.put(&self.trap_suppressed);
+        w.put(&self.nmi);
+        w.put(&self.nmi_triggered);
+    }
+
+    /// Load the state of the CPU, excluding the bus. The bus must be loaded first, as an in-progress
+    /// REP instruction is decoded from memory.
+    pub fn load_cpu_state(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        let cpu_type: String = r.get()?;
+        if cpu_type != format!("{:?}", self.cpu_type) {
+            bail!("CPU type mismatch: state has {}, machine has {:?}", cpu_type, self.cpu_type);
+        }
+        self.state = r.get()?;
+
+        self.a.set_x(r.get()?);
+        self.b.set_x(r.get()?);
+        self.c.set_x(r.get()?);
+        self.d.set_x(r.get()?);
+        self.sp = r.get()?;
+        self.bp = r.get()?;
+        self.si = r.get()?;
+        self.di = r.get()?;
+        self.cs = r.get()?;
+        self.ds = r.get()?;
+        self.ss = r.get()?;
+        self.es = r.get()?;
+        self.flags = r.get()?;
+        self.pc = r.get()?;
+
+        self.address_bus = r.get()?;
+        self.address_latch = r.get()?;
+        self.data_bus = r.get()?;
+        self.last_ea = r.get()?;
+        self.i8288.load_state(r)?;
+        self.mc_pc = r.get()?;
+        self.nx = r.get()?;
+        self.rni = r.get()?;
+        self.ea_opr = r.get()?;
+
+        self.intr = r.get()?;
+        self.intr_pending = r.get()?;
+        self.in_int = r.get()?;
+        self.int_count = r.get()?;
+        self.iret_count = r.get()?;
+        self.interrupt_inhibit = r.get()?;
+
+        self.biu_state_new = r.get()?;
+        self.ready = r.get()?;
+        self.queue.load_state(r)?;
+        self.fetch_size = r.get()?;
+        self.fetch_state = r.get()?;
+        self.next_fetch_state = r.get()?;
+        self.fetch_suspended = r.get()?;
+        self.bus_pending_eu = r.get()?;
+        self.queue_op = r.get()?;
+        self.last_queue_op = r.get()?;
+        self.queue_byte = r.get()?;
+        self.last_queue_byte = r.get()?;
+        self.last_queue_len = r.get()?;
+        self.t_cycle = r.get()?;
+        self.bus_status = r.get()?;
+        self.bus_status_latch = r.get()?;
+        self.bus_segment = r.get()?;
+        self.transfer_size = r.get()?;
+        self.operand_size = r.get()?;
+        self.transfer_n = r.get()?;
+        self.final_transfer = r.get()?;
+        self.bus_wait_states = r.get()?;
+        self.wait_states = r.get()?;
+        self.lock = r.get()?;
+
+        self.halted = r.get()?;
+        self.reported_halt = r.get()?;
+        self.halt_not_hold = r.get()?;
+        self.wake_timer = r.get()?;
+        self.halt_resume_delay = r.get()?;
+        self.is_running = r.get()?;
+        self.is_error = r.get()?;
+
+        self.in_rep = r.get()?;
+        self.rep_init = r.get()?;
+        self.rep_type = r.get()?;
+
+        self.cycle_num = r.get()?;
+        self.t_stamp = r.get()?;
+        self.t_step = r.get()?;
+        self.t_step_h = r.get()?;
+        self.instr_cycle = r.get()?;
+        self.device_cycles = r.get()?;
+        self.int_elapsed = r.get()?;
+        self.instr_elapsed = r.get()?;
+        self.instruction_count = r.get()?;
+        self.instruction_ip = r.get()?;
+        self.instruction_address = r.get()?;
+
+        self.interrupt_scheduling = r.get()?;
+        self.interrupt_cycle_period = r.get()?;
+        self.interrupt_cycle_num = r.get()?;
+        self.interrupt_retrigger = r.get()?;
+        self.clk0 = r.get()?;
+
+        self.dma_state = r.get()?;
+        self.dram_refresh_simulation = r.get()?;
+        self.dram_refresh_cycle_period = r.get()?;
+        self.dram_refresh_cycle_num = r.get()?;
+        self.dram_refresh_adjust = r.get()?;
+        self.dram_refresh_tc = r.get()?;
+        self.dram_refresh_retrigger = r.get()?;
+        self.dma_aen = r.get()?;
+        self.dma_holda = r.get()?;
+        self.dma_req = r.get()?;
+        self.dma_ack = r.get()?;
+        self.dma_wait_states = r.get()?;
+
+        self.trap_enable_delay = r.get()?;
+        self.trap_disable_delay = r.get()?;
+        self.trap_suppressed = r.get()?;
+        self.nmi = r.get()?;
+        self.nmi_triggered = r.get()?;
+
+        // Debugging state from the previous session no longer applies.
+        self.instruction_history.clear();
+        self.call_stack.clear();
+        self.service_events.clear();
+        self.step_over_target = None;
+
+        // An in-progress REP instruction continues executing without being fetched again, so we
+        // must recover it from memory.
+        if self.in_rep {
+            self.bus.seek(self.instruction_address as usize);
+            self.i = Cpu::decode(&mut self.bus).map_err(|_| {
+                anyhow!(
+                    "Failed to decode REP instruction at {:05X}",
+                    self.instruction_address
+                )
+            })?;
+            self.i.address = self.instruction_address;
+            self.rep_mnemonic = self.i.mnemonic;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cpu_state_roundtrip() {
+        let mut cpu = Cpu::new(
+            CpuType::Intel8088,
+            TraceMode::None,
+            TraceLogger::None,
+            #[cfg(feature = "cpu_validator")]
+            ValidatorType::None,
+            #[cfg(feature = "cpu_validator")]
+            TraceLogger::None,
+            #[cfg(feature = "cpu_validator")]
+            crate::cpu_validator::ValidatorMode::Instruction,
+            #[cfg(feature = "cpu_validator")]
+            1_000_000,
+        );
+
+        cpu.randomize_seed(1234);
+        cpu.reset();
+        cpu.randomize_regs();
+
+        let regs = [
+            Register16::AX,
+            Register16::BX,
+            Register16::CX,
+            Register16::DX,
+            Register16::SP,
+            Register16::BP,
+            Register16::SI,
+            Register16::DI,
+            Register16::CS,
+            Register16::DS,
+            Register16::ES,
+            Register16::SS,
+            Register16::PC,
+        ];
+        let saved_regs: Vec<u16> = regs.iter().map(|r| cpu.get_register16(*r)).collect();
+        let saved_flags = cpu.get_flags();
+
+        let mut w = StateWriter::new();
+        cpu.save_cpu_state(&mut w);
+        let data = w.into_inner();
+
+        cpu.randomize_regs();
+
+        let mut r = StateReader::new(&data);
+        cpu.load_cpu_state(&mut r).expect("Failed to load CPU state");
+        assert_eq!(r.remaining(), 0);
+
+        let loaded_regs: Vec<u16> = regs.iter().map(|r| cpu.get_register16(*r)).collect();
+        assert_eq!(saved_regs, loaded_regs);
+        assert_eq!(saved_flags, cpu.get_flags());
+    }
+}
@@ -156,7 +156,10 @@ impl Cpu {
             }
 
             // Check instruction address for breakpoint on execute flag
-            if !skip_breakpoint && self.bus.get_flags(instruction_address as usize) & MEM_BPE_BIT != 0 {
+            if !skip_breakpoint
+                && self.bus.get_flags(instruction_address as usize) & MEM_BPE_BIT != 0
+                && self.eval_breakpoints(|bp| matches!(bp, BreakPointType::ExecuteFlat(a) if *a == instruction_address))
+            {
                 // Breakpoint hit.
                 log::debug!("Breakpoint hit at {:05X}", instruction_address);
                 self.set_breakpoint_flag();
@@ -418,10 +421,7 @@ impl Cpu {
                 // the address of the next instruction. (Step Over skips ISRs)
                 step_result = StepResult::Call(CpuAddress::Segmented(self.cs, self.ip()));
 
-                if self.int_flags[irq as usize] != 0 {
-                    // This interrupt has a breakpoint
-                    self.set_breakpoint_flag();
-                }
+                // Interrupt breakpoints are evaluated once, by the INTR routine.
                 self.hw_interrupt(irq);
                 self.biu_fetch_next();
             }
@@ -71,6 +71,7 @@ use crate::devices::vga::VGACard;
 use crate::devices::{cga::CGACard, mda::MDACard, tga::TGACard};
 
 use crate::devices::pic::Pic;
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateWriter};
 use serde::Deserialize;
 use serde_derive::Serialize;
 
@@ -79,6 +80,7 @@ use serde_derive::Serialize;
 #[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq, Hash)]
 pub enum VideoType {
     MDA,
+    HGC,
     CGA,
     TGA,
     #[cfg(feature = "ega")]
@@ -101,6 +103,7 @@ impl FromStr for VideoType {
     {
         match s {
             "MDA" => Ok(VideoType::MDA),
+            "HGC" => Ok(VideoType::HGC),
             "CGA" => Ok(VideoType::CGA),
             "TGA" => Ok(VideoType::TGA),
             #[cfg(feature = "ega")]
@@ -324,10 +327,35 @@ pub struct DisplayExtents {
     pub field_w: u32,                    // The total width of the video field
     pub field_h: u32,                    // The total height of the video field
     pub row_stride: usize,               // Number of bytes in frame buffer to skip to reach next row
+    pub bytes_per_pixel: usize,          // 1 for indexed frame buffers, 4 for RGBA frame buffers
     pub double_scan: bool,               // Whether the display should be double-scanned when RGBA converted
     pub mode_byte: u8,                   // Mode byte. Used by CGA modes only.
 }
 
+impl DisplayExtents {
+    /// Copy the video field out of a display buffer as packed rows of `field_w` pixels.
+    /// Rows missing from a short buffer are filled with zeros.
+    pub fn pack_field(&self, buf: &[u8]) -> Vec<u8> {
+        let w = self.field_w as usize * self.bytes_per_pixel;
+        let field_len = w * self.field_h as usize;
+        let mut field = Vec::with_capacity(field_len);
+        for y in 0..self.field_h as usize {
+            let start = y * self.row_stride;
+            match buf.get(start..start + w) {
+                Some(row) => field.extend_from_slice(row),
+                None => break,
+            }
+        }
+        field.resize(field_len, 0);
+        field
+    }
+
+    /// Return the MD5 digest of the video field in a display buffer as a lowercase hex string.
+    pub fn hash_field(&self, buf: &[u8]) -> String {
+        format!("{:x}", md5::compute(self.pack_field(buf)))
+    }
+}
+
 pub trait VideoCard {
     /// Apply the specified VideoOption to the adapter.
     fn set_video_option(&mut self, opt: VideoOption);
@@ -365,6 +393,10 @@ pub trait VideoCard {
     /// Return the 16 color CGA color index for the active overscan color.
     fn get_overscan_color(&self) -> u8;
 
+    /// Return the RGBA palette for adapters whose buffers contain indices into a programmable palette.
+    /// Adapters with a fixed palette should return None.
+    fn get_palette(&self) -> Option<&[[u8; 4]]>;
+
     /// Return the u8 slice representing the selected buffer type. (Direct rendering only)
     fn get_buf(&self, buf_select: BufferSelect) -> &[u8];
 
@@ -379,6 +411,11 @@ pub trait VideoCard {
     /// Get the position of the CRT beam (Direct rendering only)
     fn get_beam_pos(&self) -> Option<(u32, u32)>;
 
+    /// Set the state of the light pen. `pos` is the position the pen is held over, in the same coordinate
+    /// space as get_beam_pos(), or None if the pen is away from the screen. `switch` is the state of the
+    /// pen's tip switch. Adapters without light pen support should ignore this.
+    fn set_light_pen(&mut self, pos: Option<(u32, u32)>, switch: bool);
+
     /// Get the current scanline being rendered.
     fn get_scanline(&self) -> u32;
 
@@ -453,3 +490,83 @@ pub trait VideoCard {
     /// text mode, an empty vector should be returned.
     fn get_text_mode_strings(&self) -> Vec<String>;
 }
+
+impl_state_enum!(ClockingMode {
+    Default,
+    Cycle,
+    Character,
+    Scanline,
+    Dynamic
+});
+impl_state_enum!(DisplayMode {
+    Disabled,
+    Mode0TextBw40,
+    Mode1TextCo40,
+    Mode2TextBw80,
+    Mode3TextCo80,
+    ModeTextAndGraphicsHack,
+    Mode4LowResGraphics,
+    Mode5LowResAltPalette,
+    Mode6HiResGraphics,
+    Mode7LowResComposite,
+    Mode8LowResTweaked,
+    Mode9PCJrLowResGraphics,
+    ModeAPCjrHiResGraphics,
+    ModeBEGAInternal,
+    ModeCEGAInternal,
+    ModeDEGALowResGraphics,
+    ModeEEGAMedResGraphics,
+    ModeFMonoHiresGraphics,
+    Mode10EGAHiResGraphics,
+    Mode11VGAHiResMono,
+    Mode12VGAHiResGraphics,
+    Mode13VGALowRes256,
+});
+
+/// Aperture definitions and pixel formats are fixed per card, so only the field dimensions are saved.
+impl SaveState for DisplayExtents {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.field_w);
+        w.put(&self.field_h);
+        w.put(&self.row_stride);
+        w.put(&self.double_scan);
+        w.put(&self.mode_byte);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.field_w = r.get()?;
+        self.field_h = r.get()?;
+        self.row_stride = r.get()?;
+        self.double_scan = r.get()?;
+        self.mode_byte = r.get()?;
+        Ok(())
+    }
+}
+
+impl SaveState for VideoCardDispatch {
+    fn save_state(&self, w: &mut StateWriter) {
+        match self {
+            VideoCardDispatch::Mda(mda) => w.section(b"MDA ", |w| mda.save_state(w)),
+            VideoCardDispatch::Cga(cga) => w.section(b"CGA ", |w| cga.save_state(w)),
+            VideoCardDispatch::Tga(tga) => w.section(b"TGA ", |w| tga.save_state(w)),
+            #[cfg(feature = "ega")]
+            VideoCardDispatch::Ega(ega) => w.section(b"EGA ", |w| ega.save_state(w)),
+            #[cfg(feature = "vga")]
+            VideoCardDispatch::Vga(vga) => w.section(b"VGA ", |w| vga.save_state(w)),
+            VideoCardDispatch::None => w.section(b"NONE", |_| {}),
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        match self {
+            VideoCardDispatch::Mda(mda) => r.section(b"MDA ", |r| mda.load_state(r)),
+            VideoCardDispatch::Cga(cga) => r.section(b"CGA ", |r| cga.load_state(r)),
+            VideoCardDispatch::Tga(tga) => r.section(b"TGA ", |r| tga.load_state(r)),
+            #[cfg(feature = "ega")]
+            VideoCardDispatch::Ega(ega) => r.section(b"EGA ", |r| ega.load_state(r)),
+            #[cfg(feature = "vga")]
+            VideoCardDispatch::Vga(vga) => r.section(b"VGA ", |r| vga.load_state(r)),
+            VideoCardDispatch::None => r.section(b"NONE", |_| Ok(())),
+        }
+    }
+}
@@ -32,6 +32,8 @@
 
 use std::fmt::Display;
 
+use crate::savestate::{StateReader, StateValue, StateWriter};
+
 #[derive(Copy, Clone, Debug)]
 pub struct DiskChs {
     c: u8,
@@ -143,3 +145,18 @@ impl DiskChs {
         self
     }
 }
+
+impl StateValue for DiskChs {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.write_u8(self.c);
+        w.write_u8(self.h);
+        w.write_u8(self.s);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            c: r.read_u8()?,
+            h: r.read_u8()?,
+            s: r.read_u8()?,
+        })
+    }
+}
@@ -0,0 +1,104 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::adlib.rs
+
+    Implement the AdLib Music Synthesizer Card, an 8-bit ISA card carrying
+    a single OPL2 chip.
+
+    The card decodes two ports. Writing 0x388 selects an OPL2 register and
+    writing 0x389 writes to it. Reading 0x388 returns the OPL2 status
+    register, which software polls to detect the card via its timers.
+
+*/
+
+use crate::{
+    bus::{BusInterface, DeviceRunTimeUnit, IoDevice, NO_IO_BYTE},
+    devices::opl2::Opl2,
+    savestate::{SaveState, StateReader, StateWriter},
+};
+
+pub const ADLIB_ADDRESS_PORT: u16 = 0x388;
+pub const ADLIB_DATA_PORT: u16 = 0x389;
+
+pub struct AdLibCard {
+    opl: Opl2,
+}
+
+impl IoDevice for AdLibCard {
+    fn read_u8(&mut self, port: u16, _delta: DeviceRunTimeUnit) -> u8 {
+        match port {
+            ADLIB_ADDRESS_PORT => self.opl.read_status(),
+            _ => NO_IO_BYTE,
+        }
+    }
+
+    fn write_u8(&mut self, port: u16, data: u8, _bus: Option<&mut BusInterface>, _delta: DeviceRunTimeUnit) {
+        match port {
+            ADLIB_ADDRESS_PORT => self.opl.write_address(data),
+            ADLIB_DATA_PORT => self.opl.write_data(data),
+            _ => {}
+        }
+    }
+
+    fn port_list(&self) -> Vec<u16> {
+        vec![ADLIB_ADDRESS_PORT, ADLIB_DATA_PORT]
+    }
+}
+
+impl Default for AdLibCard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AdLibCard {
+    pub fn new() -> Self {
+        Self { opl: Opl2::new() }
+    }
+
+    pub fn opl(&self) -> &Opl2 {
+        &self.opl
+    }
+
+    pub fn opl_mut(&mut self) -> &mut Opl2 {
+        &mut self.opl
+    }
+
+    pub fn run(&mut self, us: f64) {
+        self.opl.run(us);
+    }
+}
+
+impl SaveState for AdLibCard {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.opl.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.opl.load_state(r)
+    }
+}
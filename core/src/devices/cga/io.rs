@@ -137,3 +137,55 @@ impl IoDevice for CGACard {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{device_traits::videocard::VideoCard, tracelogger::TraceLogger};
+
+    #[test]
+    fn test_lightpen() {
+        let mut cga = CGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+        let delta = DeviceRunTimeUnit::SystemTicks(0);
+        let lp_bits = STATUS_LIGHTPEN_TRIGGER_SET | STATUS_LIGHTPEN_SWITCH_STATUS;
+        for (i, reg) in [56, 40, 45, 10, 31, 6, 25, 28, 2, 7].iter().enumerate() {
+            cga.write_u8(CRTC_REGISTER_SELECT2, i as u8, None, delta);
+            cga.write_u8(CRTC_REGISTER2, *reg, None, delta);
+        }
+        cga.write_u8(CGA_MODE_CONTROL_REGISTER, 0x08, None, delta);
+        // Let the monitor sync to the new timings.
+        for _ in 0..1000 {
+            cga.run(DeviceRunTimeUnit::SystemTicks(300), &mut None);
+        }
+        let status = cga.read_u8(CGA_STATUS_REGISTER, delta);
+        assert_eq!(status & lp_bits, 0b0000_0100);
+
+        // Hold the pen over the middle of row 5, column 10 of 40 column text with the switch pressed.
+        cga.set_light_pen(
+            Some((CGA_APERTURE_CROPPED_X + 10 * 16 + 8, CGA_APERTURE_CROPPED_Y + 5 * 8 + 3)),
+            true,
+        );
+        cga.write_u8(CGA_LIGHTPEN_LATCH_RESET, 0, None, delta);
+        for _ in 0..1000 {
+            cga.run(DeviceRunTimeUnit::SystemTicks(300), &mut None);
+        }
+        let status = cga.read_u8(CGA_STATUS_REGISTER, delta);
+        assert_eq!(status & lp_bits, 0b0000_0010);
+
+        let mut lp_addr = 0;
+        for reg in [16, 17] {
+            cga.write_u8(CRTC_REGISTER_SELECT2, reg, None, delta);
+            lp_addr = (lp_addr << 8) | cga.read_u8(CRTC_REGISTER2, delta) as u16;
+        }
+        assert_eq!(lp_addr, 5 * 40 + 10);
+
+        // The latch holds its address until reset, and only triggers again once reset.
+        cga.set_light_pen(None, false);
+        cga.write_u8(CGA_LIGHTPEN_LATCH_RESET, 0, None, delta);
+        for _ in 0..1000 {
+            cga.run(DeviceRunTimeUnit::SystemTicks(300), &mut None);
+        }
+        let status = cga.read_u8(CGA_STATUS_REGISTER, delta);
+        assert_eq!(status & lp_bits, 0b0000_0100);
+    }
+}
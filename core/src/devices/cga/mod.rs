@@ -44,6 +44,7 @@ use std::{collections::HashMap, convert::TryInto, path::Path};
 mod io;
 mod draw;
 mod mmio;
+mod state;
 mod tablegen;
 mod videocard;
 
@@ -556,8 +557,10 @@ pub struct CGACard {
     trace_logger:  TraceLogger,
     debug_counter: u64,
 
-    lightpen_latch: bool,
-    lightpen_addr:  usize,
+    lightpen_latch:  bool,
+    lightpen_addr:   usize,
+    lightpen_pos:    Option<(u32, u32)>,
+    lightpen_switch: bool,
 }
 
 #[derive(Debug)]
@@ -595,6 +598,7 @@ impl CgaDefault for DisplayExtents {
             field_w: CGA_XRES_MAX,
             field_h: CGA_YRES_MAX,
             row_stride: CGA_XRES_MAX as usize,
+            bytes_per_pixel: 1,
             double_scan: true,
             mode_byte: 0,
         }
@@ -752,8 +756,10 @@ impl Default for CGACard {
             trace_logger:  TraceLogger::None,
             debug_counter: 0,
 
-            lightpen_latch: false,
-            lightpen_addr:  0,
+            lightpen_latch:  false,
+            lightpen_addr:   0,
+            lightpen_pos:    None,
+            lightpen_switch: false,
         }
     }
 }
@@ -874,7 +880,7 @@ impl CGACard {
         if self.ticks_advanced % CGA_LCHAR_CLOCK as u32 > 0 {
             // We have advanced the CGA card out of phase with the character clock. Count
             // how many pixel clocks we need to tick by to be back in phase.
-            ((!self.cycles + 1) & 0x0F) as u32
+            (self.cycles.wrapping_neg() & 0x0F) as u32
         }
         else {
             0
@@ -883,7 +889,7 @@ impl CGACard {
 
     #[inline]
     fn calc_phase_offset(&mut self) -> u32 {
-        ((!self.cycles + 1) & 0x0F) as u32
+        (self.cycles.wrapping_neg() & 0x0F) as u32
     }
 
     fn set_lp_latch(&mut self) {
@@ -901,6 +907,17 @@ impl CGACard {
         self.lightpen_latch = false;
     }
 
+    /// Trigger the light pen latch if the light pen is positioned within the span of `width` pixels
+    /// about to be drawn at the current beam position. The pen only sees the beam in the display area.
+    #[inline]
+    fn check_lp_trigger(&mut self, width: u32) {
+        if let Some((pen_x, pen_y)) = self.lightpen_pos {
+            if self.in_display_area && self.beam_y == pen_y && pen_x >= self.beam_x && pen_x < self.beam_x + width {
+                self.set_lp_latch();
+            }
+        }
+    }
+
     fn get_cursor_span(&self) -> (u8, u8) {
         (self.crtc_cursor_start_line, self.crtc_cursor_end_line)
     }
@@ -1332,7 +1349,9 @@ impl CGACard {
         }
 
         // This bit is logically reversed, i.e., 0 is switch on
-        //byte |= STATUS_LIGHTPEN_SWITCH_STATUS;
+        if !self.lightpen_switch {
+            byte |= STATUS_LIGHTPEN_SWITCH_STATUS;
+        }
 
         trace_regs!(self);
         trace!(
@@ -1718,6 +1737,8 @@ impl CGACard {
             }*/
         }
 
+        self.check_lp_trigger(8 * self.clock_divisor as u32);
+
         // Update position to next pixel and character column.
         self.beam_x += 8 * self.clock_divisor as u32;
         self.rba += 8 * self.clock_divisor as usize;
@@ -1804,6 +1825,8 @@ impl CGACard {
             }
         }
 
+        self.check_lp_trigger(16);
+
         // Update position to next pixel and character column.
         self.beam_x += 16;
         self.rba += 16;
@@ -1931,6 +1954,8 @@ impl CGACard {
             }
         }
 
+        self.check_lp_trigger(self.clock_divisor as u32);
+
         // Update position to next pixel and character column.
         self.beam_x += self.clock_divisor as u32;
         self.rba += self.clock_divisor as usize;
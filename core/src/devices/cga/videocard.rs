@@ -136,6 +136,12 @@ impl VideoCard for CGACard {
         Some((self.beam_x, self.beam_y))
     }
 
+    /// Set the position and switch state of the light pen.
+    fn set_light_pen(&mut self, pos: Option<(u32, u32)>, switch: bool) {
+        self.lightpen_pos = pos;
+        self.lightpen_switch = switch;
+    }
+
     /// Tick the CGA the specified number of video clock cycles.
     fn debug_tick(&mut self, ticks: u32) {
         match self.clock_mode {
@@ -186,6 +192,10 @@ impl VideoCard for CGACard {
         }
     }
 
+    fn get_palette(&self) -> Option<&[[u8; 4]]> {
+        None
+    }
+
     /// Get the current scanline being rendered.
     fn get_scanline(&self) -> u32 {
         self.scanline
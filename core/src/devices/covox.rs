@@ -0,0 +1,106 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::covox.rs
+
+    Implement the Covox Speech Thing, an 8-bit resistor ladder DAC that
+    plugs into a parallel port. Whatever is written to the port's data
+    register is output as an unsigned 8-bit sample.
+
+    The device has no clock of its own, so software drives the playback
+    rate by writing the data register. The DAC is sampled at a fixed rate
+    for the mixer.
+
+*/
+
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+pub const COVOX_OUTPUT_RATE: u32 = 44100;
+
+const DAC_SILENCE: u8 = 0x80;
+
+pub struct CovoxSpeechThing {
+    dac: u8,
+    output_us: f64,
+    samples: Vec<f32>,
+}
+
+impl Default for CovoxSpeechThing {
+    fn default() -> Self {
+        Self {
+            dac: DAC_SILENCE,
+            output_us: 0.0,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl CovoxSpeechThing {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        COVOX_OUTPUT_RATE
+    }
+
+    /// Return the samples produced since the last call to clear_samples(), at COVOX_OUTPUT_RATE.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn data_write(&mut self, data: u8) {
+        self.dac = data;
+    }
+
+    pub fn run(&mut self, us: f64) {
+        let output_period = 1_000_000.0 / COVOX_OUTPUT_RATE as f64;
+        self.output_us += us;
+        while self.output_us >= output_period {
+            self.output_us -= output_period;
+            self.samples.push((self.dac as f32 - 128.0) / 128.0);
+        }
+    }
+}
+
+/// Samples not yet collected by the machine are discarded.
+impl SaveState for CovoxSpeechThing {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.dac);
+        w.put(&self.output_us);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.dac = r.get()?;
+        self.output_us = r.get()?;
+        self.samples.clear();
+        Ok(())
+    }
+}
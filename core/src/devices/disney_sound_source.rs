@@ -0,0 +1,197 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::disney_sound_source.rs
+
+    Implement the Disney Sound Source, an 8-bit DAC that plugs into a
+    parallel port and plays samples from a 16-byte FIFO at a fixed rate of
+    about 7kHz.
+
+    Software writes a sample to the data register, then pulses the SELECT
+    IN line (bit 3 of the control register) to latch it into the FIFO. The
+    ACK line (bit 6 of the status register) is raised while the FIFO is
+    full, which software polls both to pace its writes and to detect the
+    device.
+
+*/
+
+use std::collections::VecDeque;
+
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+pub const DSS_SAMPLE_RATE: u32 = 7000;
+pub const DSS_FIFO_LEN: usize = 16;
+
+const CONTROL_SELECT_IN: u8 = 0x08;
+const DAC_SILENCE: u8 = 0x80;
+
+pub struct DisneySoundSource {
+    data: u8,
+    select_in: bool,
+    fifo: VecDeque<u8>,
+    dac: u8,
+    sample_us: f64,
+    samples: Vec<f32>,
+}
+
+impl Default for DisneySoundSource {
+    fn default() -> Self {
+        Self {
+            data: 0,
+            select_in: false,
+            fifo: VecDeque::with_capacity(DSS_FIFO_LEN),
+            dac: DAC_SILENCE,
+            sample_us: 0.0,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl DisneySoundSource {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        DSS_SAMPLE_RATE
+    }
+
+    /// Return the samples produced since the last call to clear_samples(), at DSS_SAMPLE_RATE.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn fifo_full(&self) -> bool {
+        self.fifo.len() >= DSS_FIFO_LEN
+    }
+
+    pub fn data_write(&mut self, data: u8) {
+        self.data = data;
+    }
+
+    /// Latch the data register into the FIFO when SELECT IN is asserted. Writes to a full FIFO are
+    /// lost.
+    pub fn control_write(&mut self, control: u8) {
+        let select_in = control & CONTROL_SELECT_IN != 0;
+        if select_in && !self.select_in && !self.fifo_full() {
+            self.fifo.push_back(self.data);
+        }
+        self.select_in = select_in;
+    }
+
+    /// Return the status register with the ACK bit driven by the FIFO state.
+    pub fn status(&self, status: u8) -> u8 {
+        if self.fifo_full() {
+            status | 0x40
+        }
+        else {
+            status & !0x40
+        }
+    }
+
+    pub fn run(&mut self, us: f64) {
+        let sample_period = 1_000_000.0 / DSS_SAMPLE_RATE as f64;
+        self.sample_us += us;
+        while self.sample_us >= sample_period {
+            self.sample_us -= sample_period;
+            // The DAC holds its last value when the FIFO runs dry.
+            if let Some(sample) = self.fifo.pop_front() {
+                self.dac = sample;
+            }
+            self.samples.push((self.dac as f32 - 128.0) / 128.0);
+        }
+    }
+}
+
+/// Samples not yet collected by the machine are discarded.
+impl SaveState for DisneySoundSource {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.data);
+        w.put(&self.select_in);
+        w.put(&self.fifo);
+        w.put(&self.dac);
+        w.put(&self.sample_us);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.data = r.get()?;
+        self.select_in = r.get()?;
+        self.fifo = r.get()?;
+        self.dac = r.get()?;
+        self.sample_us = r.get()?;
+        self.samples.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latch(dss: &mut DisneySoundSource, data: u8) {
+        dss.data_write(data);
+        dss.control_write(0x0C);
+        dss.control_write(0x04);
+    }
+
+    #[test]
+    fn test_fifo_handshake() {
+        let mut dss = DisneySoundSource::new();
+        assert_eq!(dss.status(0x00) & 0x40, 0);
+
+        for i in 0..DSS_FIFO_LEN {
+            latch(&mut dss, i as u8);
+        }
+        assert_eq!(dss.status(0x00) & 0x40, 0x40);
+
+        // Writes to a full FIFO are lost.
+        latch(&mut dss, 0xFF);
+        assert_eq!(dss.fifo.len(), DSS_FIFO_LEN);
+
+        // Playing one sample makes room for another.
+        dss.run(1_000_000.0 / DSS_SAMPLE_RATE as f64);
+        assert_eq!(dss.samples().len(), 1);
+        assert_eq!(dss.dac, 0);
+        assert_eq!(dss.status(0x00) & 0x40, 0);
+    }
+
+    #[test]
+    fn test_select_edge() {
+        let mut dss = DisneySoundSource::new();
+
+        // Only asserting SELECT IN latches a sample, holding it does not.
+        dss.data_write(0x55);
+        dss.control_write(0x0C);
+        dss.control_write(0x0C);
+        dss.control_write(0x04);
+        assert_eq!(dss.fifo.len(), 1);
+        assert_eq!(dss.fifo.back(), Some(&0x55));
+    }
+}
@@ -30,7 +30,10 @@
 
 */
 
-use crate::bus::{BusInterface, DeviceRunTimeUnit, IoDevice};
+use crate::{
+    bus::{BusInterface, DeviceRunTimeUnit, IoDevice},
+    savestate::{impl_state_enum, SaveState, StateReader, StateWriter},
+};
 
 pub const DMA_CHANNEL_0_ADDR_PORT: u16 = 0x00; // R/W
 pub const DMA_CHANNEL_0_WC_PORT: u16 = 0x01; // R/W
@@ -792,3 +795,99 @@ impl DMAController {
         }
     }
 }
+
+impl_state_enum!(TimingMode {
+    NormalTiming,
+    CompressedTiming
+});
+impl_state_enum!(PriorityMode { Fixed, Rotating });
+impl_state_enum!(ServiceMode {
+    Demand,
+    Single,
+    Block,
+    Cascade
+});
+impl_state_enum!(AddressMode {
+    Increment,
+    Decrement
+});
+impl_state_enum!(TransferType {
+    Verify,
+    Write,
+    Read,
+    Illegal
+});
+
+impl SaveState for DMAChannel {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.current_address_reg);
+        w.put(&self.current_word_count_reg);
+        w.put(&self.base_address_reg);
+        w.put(&self.base_word_count_reg);
+        w.put(&self.mode_reg);
+        w.put(&self.auto_init);
+        w.put(&self.service_mode);
+        w.put(&self.address_mode);
+        w.put(&self.transfer_type);
+        w.put(&self.terminal_count);
+        w.put(&self.terminal_count_reached);
+        w.put(&self.request);
+        w.put(&self.masked);
+        w.put(&self.page);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.current_address_reg = r.get()?;
+        self.current_word_count_reg = r.get()?;
+        self.base_address_reg = r.get()?;
+        self.base_word_count_reg = r.get()?;
+        self.mode_reg = r.get()?;
+        self.auto_init = r.get()?;
+        self.service_mode = r.get()?;
+        self.address_mode = r.get()?;
+        self.transfer_type = r.get()?;
+        self.terminal_count = r.get()?;
+        self.terminal_count_reached = r.get()?;
+        self.request = r.get()?;
+        self.masked = r.get()?;
+        self.page = r.get()?;
+        Ok(())
+    }
+}
+
+impl SaveState for DMAController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.enabled);
+        w.put(&self.mem_to_mem_enabled);
+        w.put(&self.channel_0_hold_enabled);
+        w.put(&self.timing_mode);
+        w.put(&self.priority_mode);
+        w.put(&self.flipflop);
+        for channel in &self.channels {
+            channel.save_state(w);
+        }
+        w.put(&self.command_register);
+        w.put(&self.request_reg);
+        w.put(&self.status_reg);
+        w.put(&self.temp_reg);
+        w.put(&self.dreq);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.enabled = r.get()?;
+        self.mem_to_mem_enabled = r.get()?;
+        self.channel_0_hold_enabled = r.get()?;
+        self.timing_mode = r.get()?;
+        self.priority_mode = r.get()?;
+        self.flipflop = r.get()?;
+        for channel in self.channels.iter_mut() {
+            channel.load_state(r)?;
+        }
+        self.command_register = r.get()?;
+        self.request_reg = r.get()?;
+        self.status_reg = r.get()?;
+        self.temp_reg = r.get()?;
+        self.dreq = r.get()?;
+        Ok(())
+    }
+}
@@ -31,6 +31,7 @@
 */
 
 use super::*;
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateValue, StateWriter};
 
 #[derive(Copy, Clone, Debug)]
 pub enum AttributeRegister {
@@ -102,6 +103,7 @@ pub struct APaletteRegister {
 }
 
 #[bitfield]
+#[derive(Copy, Clone)]
 pub struct AModeControl {
     #[bits = 1]
     pub mode: AttributeMode,
@@ -128,6 +130,7 @@ pub struct AOverscanColor {
 }
 
 #[bitfield]
+#[derive(Copy, Clone)]
 pub struct AColorPlaneEnable {
     pub enable_plane: B4,
     pub video_status_mux: B2,
@@ -516,3 +519,84 @@ impl AttributeController {
         attribute_vec
     }
 }
+
+impl_state_enum!(AttributeRegister {
+    Palette0,
+    Palette1,
+    Palette2,
+    Palette3,
+    Palette4,
+    Palette5,
+    Palette6,
+    Palette7,
+    Palette8,
+    Palette9,
+    PaletteA,
+    PaletteB,
+    PaletteC,
+    PaletteD,
+    PaletteE,
+    PaletteF,
+    ModeControl,
+    OverscanColor,
+    ColorPlaneEnable,
+    HorizontalPelPanning,
+});
+
+impl_state_enum!(AttributeRegisterFlipFlop { Address, Data });
+
+impl StateValue for AttributePaletteEntry {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.put(&self.six);
+        w.put(&self.four);
+        w.put(&self.four_to_six);
+        w.put(&self.mono);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            six: r.get()?,
+            four: r.get()?,
+            four_to_six: r.get()?,
+            mono: r.get()?,
+        })
+    }
+}
+
+impl SaveState for AttributeController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.register_flipflop);
+        w.put(&self.register_select_byte);
+        w.put(&self.register_selected);
+        w.put(&self.palette_registers);
+        w.put(&self.palette_index);
+        w.put(&self.mode_control.into_bytes()[0]);
+        w.put(&self.overscan_color);
+        w.put(&self.overscan_color64);
+        w.put(&self.color_plane_enable.into_bytes()[0]);
+        w.put(&self.color_plane_enable64);
+        w.put(&self.pel_panning);
+        w.put(&self.blink_state);
+        w.put(&self.last_den);
+        w.put(&self.shift_reg);
+        w.put(&self.shift_buf);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.register_flipflop = r.get()?;
+        self.register_select_byte = r.get()?;
+        self.register_selected = r.get()?;
+        self.palette_registers = r.get()?;
+        self.palette_index = r.get()?;
+        self.mode_control = AModeControl::from_bytes([r.get()?]);
+        self.overscan_color = r.get()?;
+        self.overscan_color64 = r.get()?;
+        self.color_plane_enable = AColorPlaneEnable::from_bytes([r.get()?]);
+        self.color_plane_enable64 = r.get()?;
+        self.pel_panning = r.get()?;
+        self.blink_state = r.get()?;
+        self.last_den = r.get()?;
+        self.shift_reg = r.get()?;
+        self.shift_buf = r.get()?;
+        Ok(())
+    }
+}
@@ -31,6 +31,7 @@
 */
 
 use super::*;
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateValue, StateWriter};
 
 pub const EGA_VBLANK_MASK: u16 = 0x001F;
 pub const EGA_VSYNC_MASK: u16 = 0x001F;
@@ -1152,3 +1153,195 @@ impl EgaCrtc {
         internal_vec
     }
 }
+
+impl_state_enum!(CRTCRegister {
+    HorizontalTotal,
+    HorizontalDisplayEnd,
+    StartHorizontalBlank,
+    EndHorizontalBlank,
+    StartHorizontalRetrace,
+    EndHorizontalRetrace,
+    VerticalTotal,
+    Overflow,
+    PresetRowScan,
+    MaximumScanLine,
+    CursorStartLine,
+    CursorEndLine,
+    StartAddressH,
+    StartAddressL,
+    CursorAddressH,
+    CursorAddressL,
+    VerticalRetraceStart,
+    VerticalRetraceEnd,
+    VerticalDisplayEnd,
+    Offset,
+    UnderlineLocation,
+    StartVerticalBlank,
+    EndVerticalBlank,
+    ModeControl,
+    LineCompare,
+});
+
+impl StateValue for CrtcStatus {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.put(&self.begin_hsync);
+        w.put(&self.begin_vsync);
+        w.put(&self.hsync);
+        w.put(&self.vsync);
+        w.put(&self.hblank);
+        w.put(&self.vblank);
+        w.put(&self.hborder);
+        w.put(&self.vborder);
+        w.put(&self.den);
+        w.put(&self.den_skew);
+        w.put(&self.cursor);
+        w.put(&self.cref);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            begin_hsync: r.get()?,
+            begin_vsync: r.get()?,
+            hsync:       r.get()?,
+            vsync:       r.get()?,
+            hblank:      r.get()?,
+            vblank:      r.get()?,
+            hborder:     r.get()?,
+            vborder:     r.get()?,
+            den:         r.get()?,
+            den_skew:    r.get()?,
+            cursor:      r.get()?,
+            cref:        r.get()?,
+        })
+    }
+}
+
+impl SaveState for EgaCrtc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.register_select_byte);
+        w.put(&self.register_selected);
+        w.put(&self.crtc_horizontal_total);
+        w.put(&self.crtc_horizontal_display_end);
+        w.put(&self.crtc_start_horizontal_blank);
+        w.put(&self.crtc_end_horizontal_blank.into_bytes()[0]);
+        w.put(&self.crtc_end_horizontal_blank_norm);
+        w.put(&self.crtc_start_horizontal_retrace);
+        w.put(&self.crtc_end_horizontal_retrace.into_bytes()[0]);
+        w.put(&self.crtc_end_horizontal_retrace_norm);
+        w.put(&self.crtc_retrace_width);
+        w.put(&self.crtc_vertical_total);
+        w.put(&self.crtc_overflow);
+        w.put(&self.crtc_preset_row_scan);
+        w.put(&self.crtc_maximum_scanline);
+        w.put(&self.crtc_cursor_start);
+        w.put(&self.crtc_cursor_enabled);
+        w.put(&self.crtc_cursor_end.into_bytes()[0]);
+        w.put(&self.crtc_cursor_skew);
+        w.put(&self.crtc_start_address_ho);
+        w.put(&self.crtc_start_address_lo);
+        w.put(&self.crtc_start_address);
+        w.put(&self.start_address_latch);
+        w.put(&self.crtc_cursor_address_lo);
+        w.put(&self.crtc_cursor_address_ho);
+        w.put(&self.crtc_cursor_address);
+        w.put(&self.crtc_vertical_retrace_start);
+        w.put(&self.crtc_vertical_retrace_end.into_bytes()[0]);
+        w.put(&self.crtc_vertical_retrace_end_norm);
+        w.put(&self.crtc_vertical_display_end);
+        w.put(&self.crtc_offset);
+        w.put(&self.crtc_underline_location);
+        w.put(&self.crtc_start_vertical_blank);
+        w.put(&self.crtc_end_vertical_blank);
+        w.put(&self.crtc_mode_control.into_bytes()[0]);
+        w.put(&self.crtc_line_compare);
+        w.put(&self.hcc);
+        w.put(&self.vlc);
+        w.put(&self.vcc);
+        w.put(&self.slc);
+        w.put(&self.hsc);
+        w.put(&self.vsc);
+        w.put(&self.vtac_c5);
+        w.put(&self.in_vta);
+        w.put(&self.in_hrd);
+        w.put(&self.hrdc);
+        w.put(&self.effective_vta);
+        w.put(&self.vma);
+        w.put(&self.vma_sl);
+        w.put(&self.vma_t);
+        w.put(&self.vmws);
+        w.put(&self.den_skew_front);
+        w.put(&self.den_skew_back);
+        w.put(&self.dsc);
+        w.put(&self.status);
+        w.put(&self.blink_state);
+        w.put(&self.monitor_hsync);
+        w.put(&self.monitor_vsync);
+        w.put(&self.in_last_vblank_line);
+        w.put(&self.cursor_data);
+        w.put(&self.frame);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.register_select_byte = r.get()?;
+        self.register_selected = r.get()?;
+        self.crtc_horizontal_total = r.get()?;
+        self.crtc_horizontal_display_end = r.get()?;
+        self.crtc_start_horizontal_blank = r.get()?;
+        self.crtc_end_horizontal_blank = CEndHorizontalBlank::from_bytes([r.get()?]);
+        self.crtc_end_horizontal_blank_norm = r.get()?;
+        self.crtc_start_horizontal_retrace = r.get()?;
+        self.crtc_end_horizontal_retrace = CEndHorizontalRetrace::from_bytes([r.get()?]);
+        self.crtc_end_horizontal_retrace_norm = r.get()?;
+        self.crtc_retrace_width = r.get()?;
+        self.crtc_vertical_total = r.get()?;
+        self.crtc_overflow = r.get()?;
+        self.crtc_preset_row_scan = r.get()?;
+        self.crtc_maximum_scanline = r.get()?;
+        self.crtc_cursor_start = r.get()?;
+        self.crtc_cursor_enabled = r.get()?;
+        self.crtc_cursor_end = CCursorEnd::from_bytes([r.get()?]);
+        self.crtc_cursor_skew = r.get()?;
+        self.crtc_start_address_ho = r.get()?;
+        self.crtc_start_address_lo = r.get()?;
+        self.crtc_start_address = r.get()?;
+        self.start_address_latch = r.get()?;
+        self.crtc_cursor_address_lo = r.get()?;
+        self.crtc_cursor_address_ho = r.get()?;
+        self.crtc_cursor_address = r.get()?;
+        self.crtc_vertical_retrace_start = r.get()?;
+        self.crtc_vertical_retrace_end = CVerticalRetraceEnd::from_bytes([r.get()?]);
+        self.crtc_vertical_retrace_end_norm = r.get()?;
+        self.crtc_vertical_display_end = r.get()?;
+        self.crtc_offset = r.get()?;
+        self.crtc_underline_location = r.get()?;
+        self.crtc_start_vertical_blank = r.get()?;
+        self.crtc_end_vertical_blank = r.get()?;
+        self.crtc_mode_control = CModeControl::from_bytes([r.get()?]);
+        self.crtc_line_compare = r.get()?;
+        self.hcc = r.get()?;
+        self.vlc = r.get()?;
+        self.vcc = r.get()?;
+        self.slc = r.get()?;
+        self.hsc = r.get()?;
+        self.vsc = r.get()?;
+        self.vtac_c5 = r.get()?;
+        self.in_vta = r.get()?;
+        self.in_hrd = r.get()?;
+        self.hrdc = r.get()?;
+        self.effective_vta = r.get()?;
+        self.vma = r.get()?;
+        self.vma_sl = r.get()?;
+        self.vma_t = r.get()?;
+        self.vmws = r.get()?;
+        self.den_skew_front = r.get()?;
+        self.den_skew_back = r.get()?;
+        self.dsc = r.get()?;
+        self.status = r.get()?;
+        self.blink_state = r.get()?;
+        self.monitor_hsync = r.get()?;
+        self.monitor_vsync = r.get()?;
+        self.in_last_vblank_line = r.get()?;
+        self.cursor_data = r.get()?;
+        self.frame = r.get()?;
+        Ok(())
+    }
+}
@@ -32,6 +32,7 @@
 */
 
 use super::*;
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateWriter};
 
 #[derive(Copy, Clone, Debug)]
 pub enum GraphicsRegister {
@@ -48,6 +49,7 @@ pub enum GraphicsRegister {
 
 #[allow(dead_code)]
 #[bitfield]
+#[derive(Copy, Clone)]
 pub struct GDataRotateRegister {
     pub count: B3,
     #[bits = 2]
@@ -609,3 +611,58 @@ impl GraphicsController {
         graphics_vec
     }
 }
+
+impl_state_enum!(GraphicsRegister {
+    SetReset,
+    EnableSetReset,
+    ColorCompare,
+    DataRotate,
+    ReadMapSelect,
+    Mode,
+    Miscellaneous,
+    ColorDontCare,
+    BitMask,
+});
+
+impl_state_enum!(LogicFunction { Unmodified, And, Or, Xor });
+
+impl SaveState for GraphicsController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.graphics_register_select_byte);
+        w.put(&self.graphics_register_selected);
+        w.put(&self.graphics_set_reset);
+        w.put(&self.graphics_enable_set_reset);
+        w.put(&self.graphics_color_compare);
+        w.put(&self.graphics_data_rotate.into_bytes()[0]);
+        w.put(&self.graphics_data_rotate_function);
+        w.put(&self.graphics_read_map_select);
+        w.put(&self.graphics_mode.into_bytes()[0]);
+        w.put(&self.graphics_micellaneous.into_bytes()[0]);
+        w.put(&self.graphics_color_dont_care);
+        w.put(&self.graphics_bitmask);
+        w.put(&self.latches);
+        w.put(&self.pixel_buf);
+        w.put(&self.pipeline_buf);
+        w.put(&self.serialize_buf);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.graphics_register_select_byte = r.get()?;
+        self.graphics_register_selected = r.get()?;
+        self.graphics_set_reset = r.get()?;
+        self.graphics_enable_set_reset = r.get()?;
+        self.graphics_color_compare = r.get()?;
+        self.graphics_data_rotate = GDataRotateRegister::from_bytes([r.get()?]);
+        self.graphics_data_rotate_function = r.get()?;
+        self.graphics_read_map_select = r.get()?;
+        self.graphics_mode = GModeRegister::from_bytes([r.get()?]);
+        self.graphics_micellaneous = GMiscellaneousRegister::from_bytes([r.get()?]);
+        self.graphics_color_dont_care = r.get()?;
+        self.graphics_bitmask = r.get()?;
+        self.latches = r.get()?;
+        self.pixel_buf = r.get()?;
+        self.pipeline_buf = r.get()?;
+        self.serialize_buf = r.get()?;
+        Ok(())
+    }
+}
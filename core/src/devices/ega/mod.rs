@@ -56,6 +56,7 @@ mod io;
 mod mmio;
 mod planes;
 mod sequencer;
+mod state;
 mod tablegen;
 mod videocard;
 mod vram;
@@ -802,6 +803,7 @@ impl EGACard {
             field_w: EGA16_MAX_RASTER_X,
             field_h: EGA16_MAX_RASTER_Y,
             row_stride: EGA16_MAX_RASTER_X as usize,
+            bytes_per_pixel: 1,
             double_scan: false,
             mode_byte: 0,
         }
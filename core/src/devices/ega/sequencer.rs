@@ -35,6 +35,7 @@ use crate::{
     devices::ega::{tablegen::BIT_EXTEND_TABLE64, vram::Vram, EGA_CHARACTER_HEIGHT},
 };
 use modular_bitfield::{bitfield, prelude::*, BitfieldSpecifier};
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateWriter};
 
 #[derive(Copy, Clone, Debug)]
 pub enum SequencerRegister {
@@ -382,3 +383,49 @@ impl Sequencer {
         sequencer_vec
     }
 }
+
+impl_state_enum!(SequencerRegister {
+    Reset,
+    ClockingMode,
+    MapMask,
+    CharacterMapSelect,
+    MemoryMode,
+    Invalid,
+});
+
+impl SaveState for Sequencer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.address_byte);
+        w.put(&self.register_selected);
+        w.put(&self.reset);
+        w.put(&self.clocking_mode.into_bytes()[0]);
+        w.put(&self.map_mask);
+        w.put(&self.character_map_select.into_bytes()[0]);
+        w.put(&self.memory_mode.into_bytes()[0]);
+        w.put(&self.clock_change_pending);
+        w.put(&self.clock_divisor);
+        w.put(&self.char_clock);
+        w.put(&self.font_select_enabled);
+        w.put(&self.font_offset_a);
+        w.put(&self.font_offset_b);
+        self.vram.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.address_byte = r.get()?;
+        self.register_selected = r.get()?;
+        self.reset = r.get()?;
+        self.clocking_mode = SClockingModeRegister::from_bytes([r.get()?]);
+        self.map_mask = r.get()?;
+        self.character_map_select = SCharacterMapSelect::from_bytes([r.get()?]);
+        self.memory_mode = SMemoryModeRegister::from_bytes([r.get()?]);
+        self.clock_change_pending = r.get()?;
+        self.clock_divisor = r.get()?;
+        self.char_clock = r.get()?;
+        self.font_select_enabled = r.get()?;
+        self.font_offset_a = r.get()?;
+        self.font_offset_b = r.get()?;
+        self.vram.load_state(r)?;
+        Ok(())
+    }
+}
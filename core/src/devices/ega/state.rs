@@ -0,0 +1,143 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::ega::state.rs
+
+    Save state support for the IBM EGA card.
+
+    The frame buffers are not saved - they are redrawn as the restored card
+    continues to run. The DIP switches are configuration and are left as they are.
+*/
+
+use super::*;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+impl SaveState for EGACard {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.ticks_accum);
+        w.put(&self.clock_mode);
+        w.put(&self.cycles);
+        w.put(&self.io_adjust);
+        w.put(&self.mode_byte);
+        w.put(&self.display_mode);
+        w.put(&self.mode_enable);
+        w.put(&self.mode_graphics);
+        w.put(&self.mode_bw);
+        w.put(&self.mode_line_gfx);
+        w.put(&self.mode_hires_gfx);
+        w.put(&self.mode_hires_txt);
+        w.put(&self.mode_blinking);
+        w.put(&self.scanline);
+        w.put(&self.frame);
+        w.put(&self.scanline_cycles);
+        w.put(&self.frame_cycles);
+        w.put(&self.cursor_frames);
+        w.put(&self.raster_x);
+        w.put(&self.raster_y);
+        w.put(&self.cur_char);
+        w.put(&self.next_char);
+        w.put(&self.cur_attr);
+        w.put(&self.next_attr);
+        w.put(&self.cur_fg);
+        w.put(&self.cur_bg);
+        w.put(&self.cur_blink);
+        w.put(&self.blink_state);
+        w.put(&self.cursor_status);
+        w.put(&self.cursor_slowblink);
+        w.put(&self.cursor_blink_rate);
+        w.put(&self.cursor_attr);
+        self.crtc.save_state(w);
+        w.put(&self.vma);
+        self.sequencer.save_state(w);
+        self.gc.save_state(w);
+        self.ac.save_state(w);
+        w.put(&self.pel_pan_latch);
+        w.put(&self.current_font);
+        w.put(&self.misc_output_register.into_bytes()[0]);
+        self.extents.save_state(w);
+        w.put(&self.rba);
+        w.put(&self.hblank_color);
+        w.put(&self.vblank_color);
+        w.put(&self.disable_color);
+        w.put(&self.hsync_ct);
+        w.put(&self.vsync_ct);
+        w.put(&self.intr);
+        w.put(&self.last_intr);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.ticks_accum = r.get()?;
+        self.clock_mode = r.get()?;
+        self.cycles = r.get()?;
+        self.io_adjust = r.get()?;
+        self.mode_byte = r.get()?;
+        self.display_mode = r.get()?;
+        self.mode_enable = r.get()?;
+        self.mode_graphics = r.get()?;
+        self.mode_bw = r.get()?;
+        self.mode_line_gfx = r.get()?;
+        self.mode_hires_gfx = r.get()?;
+        self.mode_hires_txt = r.get()?;
+        self.mode_blinking = r.get()?;
+        self.scanline = r.get()?;
+        self.frame = r.get()?;
+        self.scanline_cycles = r.get()?;
+        self.frame_cycles = r.get()?;
+        self.cursor_frames = r.get()?;
+        self.raster_x = r.get()?;
+        self.raster_y = r.get()?;
+        self.cur_char = r.get()?;
+        self.next_char = r.get()?;
+        self.cur_attr = r.get()?;
+        self.next_attr = r.get()?;
+        self.cur_fg = r.get()?;
+        self.cur_bg = r.get()?;
+        self.cur_blink = r.get()?;
+        self.blink_state = r.get()?;
+        self.cursor_status = r.get()?;
+        self.cursor_slowblink = r.get()?;
+        self.cursor_blink_rate = r.get()?;
+        self.cursor_attr = r.get()?;
+        self.crtc.load_state(r)?;
+        self.vma = r.get()?;
+        self.sequencer.load_state(r)?;
+        self.gc.load_state(r)?;
+        self.ac.load_state(r)?;
+        self.pel_pan_latch = r.get()?;
+        self.current_font = r.get()?;
+        self.misc_output_register = EMiscellaneousOutputRegister::from_bytes([r.get()?]);
+        self.extents.load_state(r)?;
+        self.rba = r.get()?;
+        self.hblank_color = r.get()?;
+        self.vblank_color = r.get()?;
+        self.disable_color = r.get()?;
+        self.hsync_ct = r.get()?;
+        self.vsync_ct = r.get()?;
+        self.intr = r.get()?;
+        self.last_intr = r.get()?;
+        Ok(())
+    }
+}
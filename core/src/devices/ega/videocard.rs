@@ -105,6 +105,8 @@ impl VideoCard for EGACard {
         Some((self.raster_x, self.raster_y))
     }
 
+    fn set_light_pen(&mut self, _pos: Option<(u32, u32)>, _switch: bool) {}
+
     /// Unimplemented
     fn debug_tick(&mut self, _ticks: u32) {}
 
@@ -143,6 +145,10 @@ impl VideoCard for EGACard {
         0
     }
 
+    fn get_palette(&self) -> Option<&[[u8; 4]]> {
+        None
+    }
+
     /// Return the current refresh rate.
     /// TODO: Handle VGA 70Hz modes.
     fn get_refresh_rate(&self) -> u32 {
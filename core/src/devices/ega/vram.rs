@@ -37,6 +37,7 @@
 */
 
 use crate::devices::ega::EGA_GFX_PLANE_SIZE;
+use crate::savestate::{SaveState, StateReader, StateWriter};
 
 pub struct Vram {
     // Display Planes
@@ -134,3 +135,22 @@ impl Vram {
         self.deplane(offset);
     }
 }
+
+impl SaveState for Vram {
+    fn save_state(&self, w: &mut StateWriter) {
+        for plane in self.planes.iter() {
+            w.write_bytes(plane);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        for plane in self.planes.iter_mut() {
+            r.read_bytes_into(plane)?;
+        }
+        // The linear buffer is derived from the planes, so rebuild it.
+        for offset in 0..EGA_GFX_PLANE_SIZE {
+            self.deplane(offset);
+        }
+        Ok(())
+    }
+}
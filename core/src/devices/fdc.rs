@@ -31,13 +31,18 @@
 
 #![allow(dead_code)]
 
-use std::{collections::VecDeque, default::Default};
+use std::{collections::VecDeque, default::Default, path::Path};
 
 use crate::{
     bus::{BusInterface, DeviceRunTimeUnit, IoDevice},
-    device_types::{chs::DiskChs, fdc::DISK_FORMATS},
+    device_types::chs::DiskChs,
     devices::{dma, floppy_drive::FloppyDiskDrive},
+    floppy_image::{FloppyImage, FloppyImageFormat, FloppySector, SectorId, SectorMatch, MAX_SECTOR_SIZE_CODE},
+    host_dir::{fat::FatParams, HostDirVolume},
+    machine_types::FloppyDriveType,
+    savestate::{impl_state_enum, SaveState, StateReader, StateValue, StateWriter},
 };
+use anyhow::{bail, Error};
 
 pub const FDC_IRQ: u8 = 0x06;
 pub const FDC_DMA: usize = 2;
@@ -86,6 +91,9 @@ pub const DOR_MOTOR_FDD_C: u8 = 0b0100_0000;
 pub const DOR_MOTOR_FDD_D: u8 = 0b1000_0000;
 
 pub const COMMAND_MASK: u8 = 0b0001_1111;
+pub const COMMAND_MT_BIT: u8 = 0b1000_0000;
+pub const COMMAND_MFM_BIT: u8 = 0b0100_0000;
+pub const COMMAND_SK_BIT: u8 = 0b0010_0000;
 pub const COMMAND_READ_TRACK: u8 = 0x02;
 pub const COMMAND_WRITE_SECTOR: u8 = 0x05;
 pub const COMMAND_READ_SECTOR: u8 = 0x06;
@@ -112,6 +120,13 @@ pub const ST0_RESET: u8 = 0b1100_0000;
 pub const ST1_NO_ID: u8 = 0b0000_0001;
 pub const ST1_WRITE_PROTECT: u8 = 0b0000_0010;
 pub const ST1_NODATA: u8 = 0b0000_0100;
+pub const ST1_DATA_ERROR: u8 = 0b0010_0000;
+pub const ST1_END_OF_CYLINDER: u8 = 0b1000_0000;
+
+pub const ST2_NO_DATA_MARK: u8 = 0b0000_0001;
+pub const ST2_WRONG_CYLINDER: u8 = 0b0001_0000;
+pub const ST2_DATA_ERROR: u8 = 0b0010_0000;
+pub const ST2_CONTROL_MARK: u8 = 0b0100_0000;
 
 pub const ST3_ESIG: u8 = 0b1000_0000;
 pub const ST3_WRITE_PROTECT: u8 = 0b0100_0000;
@@ -177,6 +192,14 @@ pub enum DriveError {
     BadWrite,
     WriteProtect,
     DMAError,
+    NoSectorId,
+    WrongCylinder,
+    NoAddressMark,
+    NoDataMark,
+    IdCrcError,
+    DataCrcError,
+    DeletedData,
+    EndOfCylinder,
 }
 
 pub struct OperationSpecifier {
@@ -218,6 +241,7 @@ pub struct FloppyController {
     dio: IoMode,
     reading_command: bool,
     command: Command,
+    command_flags: u8,
     command_fn: Option<CommandDispatchFn>,
     last_command: Command,
     receiving_command: bool,
@@ -294,6 +318,7 @@ impl Default for FloppyController {
             dio: IoMode::FromCpu,
             reading_command: false,
             command: Command::NoCommand,
+            command_flags: 0,
             command_fn: None,
             last_command: Command::NoCommand,
             command_byte_n: 0,
@@ -376,74 +401,91 @@ impl FloppyController {
         self.drive_ct
     }
 
+    /// Set the type of the specified drive. The drive type determines how far the drive can seek,
+    /// which allows formatting tracks beyond the end of the inserted image.
+    pub fn set_drive_type(&mut self, drive_select: usize, drive_type: FloppyDriveType) {
+        self.drives[drive_select].set_drive_type(drive_type);
+    }
+
     /// Load a disk into the specified drive
-    pub fn load_image_from(
-        &mut self,
-        drive_select: usize,
-        src_vec: Vec<u8>,
-        write_protect: bool,
-    ) -> Result<(), &'static str> {
+    pub fn load_image_from(&mut self, drive_select: usize, src_vec: Vec<u8>, write_protect: bool) -> Result<(), Error> {
         if drive_select >= FDC_MAX_DRIVES {
-            return Err("Invalid drive selection");
+            bail!("Invalid drive selection");
         }
 
-        let image_len: usize = src_vec.len();
+        let image = FloppyImage::load(src_vec)?;
+        log::debug!("Loading floppy image into drive: {}", drive_select);
+        self.drives[drive_select].load_image(image);
+        self.drives[drive_select].write_protected = write_protect;
 
-        // Disk images must contain whole sectors
-        if image_len % SECTOR_SIZE > 0 {
-            return Err("Invalid image length");
-        }
+        Ok(())
+    }
 
-        // Look up disk parameters based on image size
-        if let Some(fmt) = DISK_FORMATS.get(&image_len) {
-            self.drives[drive_select].max_cylinders = fmt.chs.c();
-            self.drives[drive_select].max_heads = fmt.chs.h();
-            self.drives[drive_select].max_sectors = fmt.chs.s();
-        }
-        else {
-            // No image format found.
-            if image_len < 163_840 {
-                // If image is smaller than single sided disk, assume single sided disk, 8 sectors per track
-                // This is useful for loading things like boot sector images without having to copy them to
-                // a full disk image
-                self.drives[drive_select].max_cylinders = 40;
-                self.drives[drive_select].max_heads = 1;
-                self.drives[drive_select].max_sectors = 8;
-            }
-            else {
-                return Err("Invalid image length");
-            }
+    /// Build a disk from the contents of a host directory and load it into the specified drive.
+    /// The disk is formatted to the capacity of the drive. Unless `read_only` is set, files the
+    /// guest writes are written back to the directory.
+    pub fn load_host_dir(&mut self, drive_select: usize, path: &Path, read_only: bool) -> Result<(), Error> {
+        if drive_select >= FDC_MAX_DRIVES {
+            bail!("Invalid drive selection");
         }
 
-        self.drives[drive_select].have_disk = true;
-        self.drives[drive_select].disk_image = src_vec;
-        log::debug!(
-            "Loaded floppy image, drive: {} size: {} c: {} h: {} s: {}",
-            drive_select,
-            self.drives[drive_select].disk_image.len(),
-            self.drives[drive_select].max_cylinders,
-            self.drives[drive_select].max_heads,
-            self.drives[drive_select].max_sectors
-        );
+        let geom = self.drives[drive_select].drive_geom;
+        let total_sectors = geom.c() as u32 * geom.h() as u32 * geom.s() as u32;
+        let params = match FatParams::floppy(total_sectors) {
+            Some(params) => params,
+            None => bail!("No floppy format for drive geometry {}", geom),
+        };
 
-        self.drives[drive_select].write_protected = write_protect;
+        let (host_dir, volume) = HostDirVolume::build(path, &params, read_only)?;
+        let image = FloppyImage::from_raw(&volume)?;
+        log::debug!("Loading host directory {} into drive: {}", path.display(), drive_select);
+        self.drives[drive_select].load_host_dir(image, host_dir);
+        self.drives[drive_select].write_protected = read_only;
 
         Ok(())
     }
 
-    pub fn get_image_data(&self, drive_select: usize) -> Option<&[u8]> {
-        if self.drives[drive_select].disk_image.len() > 0 {
-            // We have at least some kind of disk image, return it
-            Some(&self.drives[drive_select].disk_image)
+    /// Return the format of the image in the specified drive, if a disk is present.
+    pub fn get_image_format(&self, drive_select: usize) -> Option<FloppyImageFormat> {
+        self.drives[drive_select].disk_image.as_ref().map(|image| image.format)
+    }
+
+    /// Return the contents of the disk in the specified drive, serialized in the format the image
+    /// was loaded from.
+    pub fn get_image_data(&self, drive_select: usize) -> Option<Vec<u8>> {
+        let format = self.get_image_format(drive_select)?;
+        match self.get_image_data_as(drive_select, format) {
+            Ok(data) => Some(data),
+            Err(err) => {
+                log::error!("Failed to serialize floppy image: {}", err);
+                None
+            }
         }
-        else {
-            None
+    }
+
+    /// Return the contents of the disk in the specified drive, serialized in the specified format.
+    pub fn get_image_data_as(&self, drive_select: usize, format: FloppyImageFormat) -> Result<Vec<u8>, Error> {
+        match &self.drives[drive_select].disk_image {
+            Some(image) => image.save(format),
+            None => bail!("No disk in drive {}", drive_select),
         }
     }
 
+    /// Returns whether the disk in the specified drive has been written to since it was loaded
+    /// or last saved.
+    pub fn is_image_dirty(&self, drive_select: usize) -> bool {
+        self.drives[drive_select].dirty
+    }
+
+    /// Clear the modified flag of the disk in the specified drive, once it has been saved.
+    pub fn clear_image_dirty(&mut self, drive_select: usize) {
+        self.drives[drive_select].dirty = false;
+    }
+
     /// Unload (eject) the disk in the specified drive
     pub fn unload_image(&mut self, drive_select: usize) {
         let drive = &mut self.drives[drive_select];
+        drive.sync_host_dir();
 
         drive.chs.set_c(0);
         drive.chs.set_h(0);
@@ -452,7 +494,10 @@ impl FloppyController {
         drive.max_heads = 1;
         drive.max_sectors = 8;
         drive.have_disk = false;
-        drive.disk_image.clear();
+        drive.disk_image = None;
+        drive.sector_index = 0;
+        drive.dirty = false;
+        drive.host_dir = None;
     }
 
     pub fn handle_status_register_read(&mut self) -> u8 {
@@ -600,6 +645,10 @@ impl FloppyController {
         st1_byte |= match self.last_error {
             DriveError::BadRead | DriveError::BadWrite | DriveError::BadSeek => ST1_NODATA,
             DriveError::WriteProtect => ST1_WRITE_PROTECT | ST1_NO_ID,
+            DriveError::NoSectorId | DriveError::WrongCylinder => ST1_NODATA,
+            DriveError::NoAddressMark | DriveError::NoDataMark => ST1_NO_ID,
+            DriveError::IdCrcError | DriveError::DataCrcError => ST1_DATA_ERROR,
+            DriveError::EndOfCylinder => ST1_END_OF_CYLINDER,
             _ => 0,
         };
 
@@ -614,9 +663,14 @@ impl FloppyController {
 
     /// Generate the value of the ST2 Status Register in response to a command
     pub fn make_st2_byte(&self, _drive_select: usize) -> u8 {
-        // The ST2 status register contains mostly error codes, so for now we can just always return success
-        // by returning 0 until we handle possible errors.
-        0
+        // The ST2 status register contains mostly error codes relating to the data field of a sector
+        match self.last_error {
+            DriveError::WrongCylinder => ST2_WRONG_CYLINDER,
+            DriveError::NoDataMark => ST2_NO_DATA_MARK,
+            DriveError::DataCrcError => ST2_DATA_ERROR,
+            DriveError::DeletedData => ST2_CONTROL_MARK,
+            _ => 0,
+        }
     }
 
     /// Generate the value of the ST3 Status Register in response to a command
@@ -693,10 +747,12 @@ impl FloppyController {
             return false;
         }
 
+        // The drive may seek beyond the tracks present on the disk, up to its own limits.
         // Sectors are 1-indexed
-        if c < self.drives[drive_select].max_cylinders
-            && h < self.drives[drive_select].max_heads
-            && s <= self.drives[drive_select].max_sectors
+        let drive = &self.drives[drive_select];
+        if c < drive.max_cylinders.max(drive.drive_geom.c())
+            && h < drive.max_heads.max(drive.drive_geom.h())
+            && s <= drive.max_sectors.max(drive.drive_geom.s())
         {
             return true;
         }
@@ -717,6 +773,8 @@ impl FloppyController {
         //log::trace!("Data Register Write");
         if !self.receiving_command {
             let command = data & COMMAND_MASK;
+            // The upper bits of the command byte hold the MT, MFM and SK flags for read and write commands
+            self.command_flags = data & !COMMAND_MASK;
             match command {
                 COMMAND_READ_TRACK => {
                     log::trace!("Received Read Track command: {:02}", command);
@@ -1005,8 +1063,8 @@ impl FloppyController {
         let head_select = (drive_head_select >> 2) & 0x01;
 
         if head != head_select {
-            // Head and head_select should always match. Seems redundant
-            log::warn!("command_read_sector: non-matching head specifiers");
+            // The head in a sector ID usually matches the physical head, but needn't
+            log::trace!("command_read_sector: non-matching head specifiers");
         }
 
         // Set drive_select for status register reads
@@ -1023,22 +1081,9 @@ impl FloppyController {
             return Continuation::CommandComplete;
         }
 
-        // Is this read out of bounds?
-        if !self.is_id_valid(drive_select, cylinder, head, sector) {
-            self.last_error = DriveError::BadRead;
-            self.send_interrupt = true;
-            log::warn!(
-                "command_read_sector: invalid chs: drive:{}, c:{} h:{} s:{}",
-                drive_select,
-                cylinder,
-                head,
-                sector
-            );
-            return Continuation::CommandComplete;
-        }
-
-        // Seek to values given in command
-        self.drives[drive_select].chs.seek(cylinder, head, sector);
+        // Select the physical head. The drive stays on the cylinder it was last seeked to - the
+        // cylinder in the command is only compared against the sector IDs on the track.
+        self.drives[drive_select].chs.set_h(head_select);
 
         // Start read operation
         self.operation = Operation::ReadSector(cylinder, head, sector, sector_size, track_len, gap3_len, data_len);
@@ -1050,25 +1095,12 @@ impl FloppyController {
         self.in_dma = true;
 
         // The IBM PC BIOS only seems to ever set a track_len of 8. How do we support 9 sector (365k) floppies?
-        // Answer: DOS seems to know to request sector #9 and the BIOS doesn't complain
-
-        // Maximum size of DMA transfer
-
-        //let max_sectors;
-        //if track_len > 0 {
-        //    max_sectors = track_len - sector + 1;
-        //}
-        //else {
-        //    max_sectors = 1;
-        //}
-        //self.dma_bytes_left = max_sectors as usize * SECTOR_SIZE;
+        // Answer: DOS seems to know to request sector #9 and the BIOS doesn't complain. The controller only
+        // compares the sector number against track_len after reading a sector, so reading sector 9 works
+        // as long as DMA terminal count ends the transfer.
 
         log::trace!("command_read_sector: drive: {} cyl:{} head:{} sector:{} sector_size:{} track_len:{} gap3_len:{} data_len:{}",
             drive_select, cylinder, head, sector, sector_size, track_len, gap3_len, data_len);
-        //log::trace!("command_read_sector: may operate on maximum of {} sectors", max_sectors);
-
-        let base_address = self.get_image_address(self.drive_select, cylinder, head, sector);
-        log::trace!("command_read_sector: base address of image read: {:06X}", base_address);
 
         // Flag to set up transfer size later
         self.operation_init = false;
@@ -1092,11 +1124,13 @@ impl FloppyController {
         let head_select = (drive_head_select >> 2) & 0x01;
 
         if head != head_select {
-            log::warn!("command_write_sector: non-matching head specifiers");
+            log::trace!("command_write_sector: non-matching head specifiers");
         }
 
-        // Seek to values given in command
-        self.drives[drive_select].chs.seek(cylinder, head, sector);
+        self.drive_select = drive_select;
+
+        // Select the physical head. As with reads, the cylinder is only matched against sector IDs.
+        self.drives[drive_select].chs.set_h(head_select);
 
         // Start write operation
         self.operation = Operation::WriteSector(cylinder, head, sector, sector_size, track_len, gap3_len, data_len);
@@ -1117,13 +1151,6 @@ impl FloppyController {
             gap3_len,
             data_len
         );
-        //log::trace!("command_read_sector: may operate on maximum of {} sectors", max_sectors);
-
-        let base_address = self.get_image_address(self.drive_select, cylinder, head, sector);
-        log::trace!(
-            "command_write_sector: base address of image write: {:06X}",
-            base_address
-        );
 
         // Flag to set up transfer size later
         self.operation_init = false;
@@ -1132,7 +1159,7 @@ impl FloppyController {
         Continuation::ContinueAsOperation
     }
 
-    /// Perform the Write Sector Command
+    /// Perform the Format Track Command
     pub fn command_format_track(&mut self) -> Continuation {
         let drive_head_select = self.data_register_in.pop_front().unwrap();
         let sector_size = self.data_register_in.pop_front().unwrap();
@@ -1140,8 +1167,12 @@ impl FloppyController {
         let gap3_len = self.data_register_in.pop_front().unwrap();
         let fill_byte = self.data_register_in.pop_front().unwrap();
 
-        let _drive_select = (drive_head_select & 0x03) as usize;
-        let _head_select = (drive_head_select >> 2) & 0x01;
+        let drive_select = (drive_head_select & 0x03) as usize;
+        let head_select = (drive_head_select >> 2) & 0x01;
+
+        // Format the track under the selected head at the drive's current cylinder
+        self.drive_select = drive_select;
+        self.drives[drive_select].chs.set_h(head_select);
 
         // Start format operation
         self.operation_init = false;
@@ -1166,71 +1197,189 @@ impl FloppyController {
     }
 
     /// Perform the Read Sector ID Command
+    ///
+    /// Returns the ID of the next sector to pass under the head on the current track.
     pub fn command_read_sector_id(&mut self) -> Continuation {
         let drive_head_select = self.data_register_in.pop_front().unwrap();
 
         let drive_select = (drive_head_select & 0x03) as usize;
-        let _head_select = (drive_head_select >> 2) & 0x01;
+        let head_select = (drive_head_select >> 2) & 0x01;
 
-        self.send_results_phase(
-            InterruptCode::NormalTermination,
-            drive_select,
-            self.drives[drive_select].chs,
-            0x02,
-        );
+        self.drive_select = drive_select;
+
+        // As with Read Sector, let the command time out if there is no disk.
+        if !self.drives[drive_select].have_disk {
+            return Continuation::CommandComplete;
+        }
+
+        let drive = &mut self.drives[drive_select];
+        drive.chs.set_h(head_select);
+
+        let mut result = InterruptCode::AbnormalTermination;
+        let mut id = SectorId::new(drive.chs.c(), head_select, drive.chs.s(), 0);
+
+        match drive.track(head_select).map(|t| t.sectors.len()).unwrap_or(0) {
+            0 => {
+                // An unformatted track has no ID address marks to find
+                self.last_error = DriveError::NoAddressMark;
+            }
+            sector_ct => {
+                let index = drive.sector_index % sector_ct;
+                let sector = drive.sector(head_select, index).unwrap();
+                id = sector.id;
+                if sector.id_crc_error {
+                    self.last_error = DriveError::IdCrcError;
+                }
+                else {
+                    result = InterruptCode::NormalTermination;
+                }
+                drive.sector_index = index + 1;
+            }
+        }
+
+        log::trace!("command_read_sector_id: drive: {} id: {}", drive_select, id);
+
+        self.send_results_phase(result, drive_select, DiskChs::new(id.c, id.h, id.r), id.n);
 
         self.send_interrupt = true;
         Continuation::CommandComplete
     }
 
-    /// Return a byte offset given a CHS (Cylinder, Head, Sector) address
-    pub fn get_image_address(&self, drive_select: usize, cylinder: u8, head: u8, sector: u8) -> usize {
-        if sector == 0 {
-            log::warn!("Invalid sector == 0");
-            return 0;
+    /// Return the number of bytes transferred for a sector with the specified size code. A size
+    /// code of 0 means the data_len parameter of the command specifies the transfer length.
+    pub fn sector_transfer_len(sector_size: u8, data_len: u8) -> usize {
+        if sector_size == 0 {
+            (data_len as usize).min(128)
+        }
+        else {
+            128 << sector_size.min(MAX_SECTOR_SIZE_CODE)
         }
-        let hpc = self.drives[drive_select].max_heads as usize;
-        let spt = self.drives[drive_select].max_sectors as usize;
-        let lba: usize = (cylinder as usize * hpc + (head as usize)) * spt + (sector as usize - 1);
-        lba * SECTOR_SIZE
     }
 
-    pub fn get_chs_sector_offset(
-        &self,
-        drive_select: usize,
-        sector_offset: u32,
-        cylinder: u8,
-        head: u8,
-        sector: u8,
-    ) -> (u8, u8, u8) {
-        let mut c = cylinder;
-        let mut h = head;
-        let mut s = sector;
+    /// Return the ID that follows the specified sector ID in a multi-sector transfer, and whether
+    /// the transfer has reached the end of the cylinder. The controller increments the sector
+    /// number until it reaches track_len. In multi-track mode it then continues with sector 1 on
+    /// head 1.
+    fn next_sector_id(&mut self, id: SectorId, track_len: u8) -> (SectorId, bool) {
+        if id.r != track_len {
+            return (SectorId::new(id.c, id.h, id.r.wrapping_add(1), id.n), false);
+        }
 
-        for _ in 0..sector_offset {
-            (c, h, s) = self.get_next_sector(drive_select, c, h, s);
+        let drive = &mut self.drives[self.drive_select];
+        if self.command_flags & COMMAND_MT_BIT == 0 {
+            (SectorId::new(id.c.wrapping_add(1), id.h, 1, id.n), true)
         }
+        else if drive.chs.h() == 0 {
+            drive.chs.set_h(1);
+            (SectorId::new(id.c, id.h ^ 1, 1, id.n), false)
+        }
+        else {
+            (SectorId::new(id.c.wrapping_add(1), id.h ^ 1, 1, id.n), true)
+        }
+    }
 
-        (c, h, s)
+    /// Search the current track for the sector a read or write operation should transfer. If it
+    /// can't be found, the operation is terminated.
+    fn locate_sector(&mut self, id: SectorId) -> Option<usize> {
+        let drive = &self.drives[self.drive_select];
+        let head = drive.chs.h();
+
+        match drive.find_sector(head, &id) {
+            SectorMatch::Found(index) => return Some(index),
+            SectorMatch::WrongCylinder(c) => {
+                log::debug!("Sector {} not found: track has sector with cylinder {}", id, c);
+                self.last_error = DriveError::WrongCylinder;
+            }
+            SectorMatch::NotFound => {
+                log::debug!("Sector {} not found on track {}:{}", id, drive.chs.c(), head);
+                self.last_error = match drive.track(head).map(|t| t.sectors.len()).unwrap_or(0) {
+                    0 => DriveError::NoAddressMark,
+                    _ => DriveError::NoSectorId,
+                };
+            }
+        }
+        self.end_sector_operation(InterruptCode::AbnormalTermination, id);
+        None
     }
 
-    pub fn get_next_sector(&self, drive_select: usize, cylinder: u8, head: u8, sector: u8) -> (u8, u8, u8) {
-        if sector < self.drives[drive_select].max_sectors {
-            // Not at last sector, just return next sector
-            (cylinder, head, sector + 1)
+    /// Finish a sector read or write. The result phase reports the sector ID the controller
+    /// stopped at.
+    fn end_sector_operation(&mut self, result: InterruptCode, id: SectorId) {
+        self.dma_byte_count = 0;
+        self.dma_bytes_left = 0;
+
+        self.send_results_phase(result, self.drive_select, DiskChs::new(id.c, id.h, id.r), id.n);
+
+        // The drive remains on its current cylinder; only the sector changes
+        self.drives[self.drive_select].chs.set_s(id.r);
+
+        log::trace!(
+            "Sector operation completed: drive chs: {} result id: {}",
+            &self.drives[self.drive_select].chs,
+            id
+        );
+        self.operation = Operation::NoOperation;
+        self.send_interrupt = true;
+    }
+
+    /// Called when all bytes of a sector have been transferred. Advances the operation to the
+    /// next sector, or terminates it if the sector had an error or the end of the cylinder was
+    /// reached.
+    fn complete_sector(&mut self, id: SectorId, index: usize, track_len: u8, data_crc_error: bool, deleted: bool) {
+        self.dma_byte_count = 0;
+        self.xfer_completed_sectors += 1;
+        self.drives[self.drive_select].sector_index = index + 1;
+
+        if data_crc_error {
+            // The sector data was transferred, but the operation ends with an error
+            self.last_error = DriveError::DataCrcError;
+            self.end_sector_operation(InterruptCode::AbnormalTermination, id);
+            return;
         }
-        else if head < self.drives[drive_select].max_heads - 1 {
-            // At last sector, but not at last head, go to next head, same cylinder, sector 1
-            (cylinder, head + 1, 1)
+
+        let (next_id, end_of_cylinder) = self.next_sector_id(id, track_len);
+        self.operation = match self.operation {
+            Operation::ReadSector(_, _, _, _, tl, gap, dl) => {
+                Operation::ReadSector(next_id.c, next_id.h, next_id.r, next_id.n, tl, gap, dl)
+            }
+            Operation::WriteSector(_, _, _, _, tl, gap, dl) => {
+                Operation::WriteSector(next_id.c, next_id.h, next_id.r, next_id.n, tl, gap, dl)
+            }
+            _ => Operation::NoOperation,
+        };
+
+        if deleted {
+            // A normal read stops after a sector with a deleted data address mark
+            self.last_error = DriveError::DeletedData;
+            self.end_sector_operation(InterruptCode::AbnormalTermination, next_id);
         }
-        else if cylinder < self.drives[drive_select].max_cylinders - 1 {
-            // At last sector and last head, go to next cylinder, head 0, sector 1
-            (cylinder + 1, 0, 1)
+        else if end_of_cylinder && self.dma_bytes_left > 0 {
+            // Terminal count should have ended the transfer by now
+            self.last_error = DriveError::EndOfCylinder;
+            self.end_sector_operation(InterruptCode::AbnormalTermination, next_id);
         }
-        else {
-            // Return end of drive? What does this do on real hardware
-            (self.drives[drive_select].max_cylinders, 0, 1)
+    }
+
+    /// Set up the DMA transfer for a sector read or write operation.
+    fn init_sector_transfer(&mut self, dma: &mut dma::DMAController, sector_len: usize) {
+        let xfer_size = dma.get_dma_transfer_size(FDC_DMA);
+
+        if xfer_size % sector_len != 0 {
+            log::warn!("DMA word count not multiple of sector size");
         }
+
+        let xfer_sectors = xfer_size / sector_len;
+        log::trace!("DMA programmed for transfer of {} sectors", xfer_sectors);
+
+        let address = dma.get_dma_transfer_address(FDC_DMA);
+        log::trace!("DMA transfer address: {:05X}", address);
+
+        self.xfer_size_sectors = xfer_sectors as u32;
+        self.xfer_completed_sectors = 0;
+        self.xfer_size_bytes = xfer_size;
+        self.dma_bytes_left = xfer_size;
+        self.dma_byte_count = 0;
+        self.operation_init = true;
     }
 
     fn send_results_phase(&mut self, result: InterruptCode, drive_select: usize, chs: DiskChs, sector_size: u8) {
@@ -1260,17 +1409,18 @@ impl FloppyController {
         self.send_data_register();
         // Clear error state
         self.last_error = DriveError::NoError;
+
+        // Apply any writes the command made to the disk's host directory, once it has completed.
+        self.drives[drive_select].sync_host_dir();
     }
 
     fn operation_read_sector(
         &mut self,
         dma: &mut dma::DMAController,
         bus: &mut BusInterface,
-        cylinder: u8,
-        head: u8,
-        sector: u8,
-        sector_size: u8,
-        _track_len: u8,
+        id: SectorId,
+        track_len: u8,
+        data_len: u8,
     ) {
         if !self.in_dma {
             log::error!("FDC in invalid state: ReadSector operation without DMA! Aborting.");
@@ -1278,115 +1428,73 @@ impl FloppyController {
             return;
         }
 
-        // Is read valid?
-
+        let sector_len = FloppyController::sector_transfer_len(id.n, data_len);
         if !self.operation_init {
-            let xfer_size = dma.get_dma_transfer_size(FDC_DMA);
-
-            if xfer_size % SECTOR_SIZE != 0 {
-                log::warn!("DMA word count not multiple of sector size");
-            }
-
-            let xfer_sectors = xfer_size / SECTOR_SIZE;
-            log::trace!("DMA programmed for transfer of {} sectors", xfer_sectors);
-
-            let dst_address = dma.get_dma_transfer_address(FDC_DMA);
-            log::trace!("DMA destination address: {:05X}", dst_address);
-
-            self.xfer_size_sectors = xfer_sectors as u32;
-            self.xfer_completed_sectors = 0;
-            self.xfer_size_bytes = xfer_sectors * SECTOR_SIZE;
-            self.dma_bytes_left = xfer_sectors * SECTOR_SIZE;
-            self.operation_init = true;
+            self.init_sector_transfer(dma, sector_len);
         }
 
-        if self.dma_bytes_left > 0 {
-            // Bytes left to transfer
-
-            // Calculate how many sectors we've done
-            if (self.dma_bytes_left < self.xfer_size_bytes) && (self.dma_bytes_left % SECTOR_SIZE == 0) {
-                // Completed one sector
-
-                self.xfer_completed_sectors += 1;
-                log::trace!(
-                    "operation_read_sector: Transferred {} sectors.",
-                    self.xfer_completed_sectors
-                );
-            }
-
-            // Check if DMA is ready
-            if dma.check_dma_ready(FDC_DMA) {
-                let base_address = self.get_image_address(self.drive_select, cylinder, head, sector);
-                let byte_address = base_address + self.dma_byte_count;
-
-                //log::trace!("Byte address for FDC read: {:04X}", byte_address);
-                if byte_address >= self.drives[self.drive_select].disk_image.len() {
-                    log::error!(
-                        "Read past end of disk image: {}/{}!",
-                        byte_address,
-                        self.drives[self.drive_select].disk_image.len()
-                    );
-                    self.dma_bytes_left = 0;
-                }
-                else {
-                    let byte = self.drives[self.drive_select].disk_image[byte_address];
-
-                    dma.do_dma_write_u8(bus, FDC_DMA, byte);
-                    self.dma_byte_count += 1;
-                    self.dma_bytes_left -= 1;
-
-                    // See if we are done
-                    let tc = dma.check_terminal_count(FDC_DMA);
-                    if tc {
-                        log::trace!(
-                            "DMA terminal count triggered end of Sector Read operation, {} bytes read.",
-                            self.dma_byte_count
-                        );
-                        self.dma_bytes_left = 0;
-                    }
-                }
-            }
-        }
-        else {
+        if self.dma_bytes_left == 0 {
             // No more bytes left to transfer. Finalize operation
-
             let tc = dma.check_terminal_count(FDC_DMA);
             if !tc {
                 log::warn!("FDC sector read complete without DMA terminal count.");
             }
 
-            self.dma_byte_count = 0;
-            self.dma_bytes_left = 0;
+            // The operation has already advanced to the next sector ID, which the result phase reports
+            self.end_sector_operation(InterruptCode::NormalTermination, id);
+            return;
+        }
 
-            let (new_c, new_h, new_s) = self.get_chs_sector_offset(
-                self.drive_select,
-                self.xfer_completed_sectors + 1,
-                cylinder,
-                head,
-                sector,
-            );
-            //let (new_c, new_h, new_s) = self.get_next_sector(self.drive_select, cylinder, head, sector);
+        let Some(index) = self.locate_sector(id)
+        else {
+            return;
+        };
 
-            let new_chs = DiskChs::new(new_c, new_h, new_s);
+        let head = self.drives[self.drive_select].chs.h();
+        let sector = self.drives[self.drive_select].sector(head, index).unwrap();
+        let (has_data, deleted, data_crc_error) = (sector.data.is_some(), sector.deleted, sector.data_crc_error);
+        let byte = sector
+            .data
+            .as_ref()
+            .and_then(|data| data.get(self.dma_byte_count))
+            .copied()
+            .unwrap_or(0);
+
+        if self.dma_byte_count == 0 {
+            // Starting a new sector. Check its data address mark.
+            if !has_data {
+                self.last_error = DriveError::NoDataMark;
+                self.end_sector_operation(InterruptCode::AbnormalTermination, id);
+                return;
+            }
+            if deleted && (self.command_flags & COMMAND_SK_BIT != 0) {
+                // Skip sectors with a deleted data address mark
+                log::trace!("operation_read_sector: skipping deleted sector {}", id);
+                self.complete_sector(id, index, track_len, false, false);
+                return;
+            }
+        }
 
-            // Terminate normally by sending results registers
-            self.send_results_phase(
-                InterruptCode::NormalTermination,
-                self.drive_select,
-                new_chs,
-                sector_size,
-            );
+        // Check if DMA is ready
+        if dma.check_dma_ready(FDC_DMA) {
+            dma.do_dma_write_u8(bus, FDC_DMA, byte);
+            self.dma_byte_count += 1;
+            self.dma_bytes_left -= 1;
 
-            // Seek to new CHS
-            self.drives[self.drive_select].chs.seek_to(&new_chs);
+            // See if we are done
+            let tc = dma.check_terminal_count(FDC_DMA);
+            if tc {
+                log::trace!(
+                    "DMA terminal count triggered end of Sector Read operation, {} sectors read.",
+                    self.xfer_completed_sectors + 1
+                );
+                self.dma_bytes_left = 0;
+            }
 
-            log::trace!(
-                "operation_read_sector completed: new chs: {}",
-                &self.drives[self.drive_select].chs
-            );
-            // Finalize operation
-            self.operation = Operation::NoOperation;
-            self.send_interrupt = true;
+            if self.dma_byte_count == sector_len {
+                log::trace!("operation_read_sector: read sector {}", id);
+                self.complete_sector(id, index, track_len, data_crc_error, deleted);
+            }
         }
     }
 
@@ -1394,9 +1502,9 @@ impl FloppyController {
         &mut self,
         dma: &mut dma::DMAController,
         bus: &mut BusInterface,
-        chs: DiskChs,
-        sector_size: u8,
-        _track_len: u8,
+        id: SectorId,
+        track_len: u8,
+        data_len: u8,
     ) {
         if !self.in_dma {
             log::error!("Error: WriteSector operation without DMA!");
@@ -1410,104 +1518,75 @@ impl FloppyController {
 
             // Terminate with WriteProtect error.
             self.last_error = DriveError::WriteProtect;
-            self.send_results_phase(InterruptCode::AbnormalPolling, self.drive_select, chs, sector_size);
+            self.send_results_phase(
+                InterruptCode::AbnormalPolling,
+                self.drive_select,
+                DiskChs::new(id.c, id.h, id.r),
+                id.n,
+            );
 
             self.send_interrupt = true;
             self.operation = Operation::NoOperation;
             return;
         }
 
+        let sector_len = FloppyController::sector_transfer_len(id.n, data_len);
         if !self.operation_init {
-            let xfer_size = dma.get_dma_transfer_size(FDC_DMA);
-
-            if xfer_size % SECTOR_SIZE != 0 {
-                log::warn!("DMA word count not multiple of sector size");
-            }
-
-            let xfer_sectors = xfer_size / SECTOR_SIZE;
-            log::trace!("DMA programmed for transfer of {} sectors", xfer_sectors);
-
-            self.dma_bytes_left = xfer_sectors * SECTOR_SIZE;
-            self.operation_init = true;
+            self.init_sector_transfer(dma, sector_len);
         }
 
-        if self.dma_bytes_left == SECTOR_SIZE {
-            let dst_address = dma.get_dma_transfer_address(FDC_DMA);
-            log::trace!("DMA source address: {:05X}", dst_address)
-        }
-
-        if self.dma_bytes_left > 0 {
-            // Bytes left to transfer
-
-            // Check if DMA is ready
-            if dma.check_dma_ready(FDC_DMA) {
-                let base_address = self.get_image_address(self.drive_select, chs.c(), chs.h(), chs.s());
-                let byte_address = base_address + self.dma_byte_count;
-
-                //log::trace!("Byte address for FDC write: {:04X}", byte_address);
-                if byte_address >= self.drives[self.drive_select].disk_image.len() {
-                    log::error!(
-                        "Write past end of disk image: {}/{}!",
-                        byte_address,
-                        self.drives[self.drive_select].disk_image.len()
-                    );
-                    self.dma_bytes_left = 0;
-                    // cleanup ?
-                }
-                else {
-                    let byte = dma.do_dma_read_u8(bus, FDC_DMA);
-                    self.drives[self.drive_select].disk_image[byte_address] = byte;
-                    self.dma_byte_count += 1;
-                    self.dma_bytes_left -= 1;
-
-                    // See if we are done
-                    let tc = dma.check_terminal_count(FDC_DMA);
-                    if tc {
-                        log::trace!(
-                            "DMA terminal count triggered end of Sector Write operation, {} byte(s) written.",
-                            self.dma_byte_count
-                        );
-                        self.dma_bytes_left = 0;
-                    }
-                }
-            }
-        }
-        else {
+        if self.dma_bytes_left == 0 {
             // No more bytes left to transfer. Finalize operation
-
             let tc = dma.check_terminal_count(FDC_DMA);
             if !tc {
                 log::warn!("FDC sector write complete without DMA terminal count.");
             }
 
-            self.dma_byte_count = 0;
-            self.dma_bytes_left = 0;
+            self.end_sector_operation(InterruptCode::NormalTermination, id);
+            return;
+        }
 
-            let (new_c, new_h, new_s) = self.get_chs_sector_offset(
-                self.drive_select,
-                self.xfer_completed_sectors + 1,
-                chs.c(),
-                chs.h(),
-                chs.s(),
-            );
+        let Some(index) = self.locate_sector(id)
+        else {
+            return;
+        };
 
-            //let (new_c, new_h, new_s) = self.get_next_sector(self.drive_select, chs.c(), chs.h(), chs.s());
-            let new_chs = DiskChs::new(new_c, new_h, new_s);
+        // Check if DMA is ready
+        if dma.check_dma_ready(FDC_DMA) {
+            let byte = dma.do_dma_read_u8(bus, FDC_DMA);
 
-            // Terminate normally by sending results registers
-            self.send_results_phase(
-                InterruptCode::NormalTermination,
-                self.drive_select,
-                new_chs,
-                sector_size,
-            );
+            let drive = &mut self.drives[self.drive_select];
+            let head = drive.chs.h();
+            drive.mark_dirty();
 
-            // Set new CHS
-            self.drives[self.drive_select].chs.seek_to(&new_chs);
+            let sector = drive.sector_mut(head, index).unwrap();
+            if self.dma_byte_count == 0 {
+                // Writing a sector replaces its data field with a normal, error-free one
+                sector.data = Some(vec![0; id.size()]);
+                sector.deleted = false;
+                sector.data_crc_error = false;
+            }
+            if let Some(data_byte) = sector.data.as_mut().and_then(|data| data.get_mut(self.dma_byte_count)) {
+                *data_byte = byte;
+            }
 
-            // Finalize operation
-            self.operation = Operation::NoOperation;
-            self.send_interrupt = true;
+            self.dma_byte_count += 1;
+            self.dma_bytes_left -= 1;
+
+            // See if we are done
+            let tc = dma.check_terminal_count(FDC_DMA);
+            if tc {
+                log::trace!(
+                    "DMA terminal count triggered end of Sector Write operation, {} sectors written.",
+                    self.xfer_completed_sectors + 1
+                );
+                self.dma_bytes_left = 0;
+            }
+
+            if self.dma_byte_count == sector_len {
+                log::trace!("operation_write_sector: wrote sector {}", id);
+                self.complete_sector(id, index, track_len, false, false);
+            }
         }
     }
 
@@ -1530,6 +1609,23 @@ impl FloppyController {
             return;
         }
 
+        // Fail operation if there is no disk to format. ST0 reports the drive as not ready.
+        if self.drives[self.drive_select].disk_image.is_none() {
+            log::warn!("FormatTrack operation with no disk in drive {}!", self.drive_select);
+
+            self.last_error = DriveError::NoMedia;
+            self.send_results_phase(
+                InterruptCode::AbnormalTermination,
+                self.drive_select,
+                Default::default(),
+                sector_size,
+            );
+
+            self.send_interrupt = true;
+            self.operation = Operation::NoOperation;
+            return;
+        }
+
         // Fail operation if disk is write protected
         if self.drives[self.drive_select].write_protected {
             log::warn!("FormatTrack operation on write protected disk!");
@@ -1563,6 +1659,15 @@ impl FloppyController {
             let xfer_sectors = xfer_size / SECTOR_SIZE;
             log::trace!("Format Track: DMA programmed for transfer of {} sectors", xfer_sectors);
 
+            // Erase the track. The ID fields read in over DMA lay out its new sectors.
+            let drive = &mut self.drives[self.drive_select];
+            let (cylinder, head) = (drive.chs.c(), drive.chs.h());
+            if let Some(image) = drive.disk_image.as_mut() {
+                image.format_track(cylinder, head);
+                drive.sector_index = 0;
+                drive.mark_dirty();
+            }
+
             self.dma_bytes_left = track_len as usize * FORMAT_BUFFER_SIZE;
             self.operation_init = true;
         }
@@ -1593,7 +1698,7 @@ impl FloppyController {
                     fill_byte
                 );
 
-                self.format_sector(f_cylinder, f_head, f_sector, fill_byte);
+                self.format_sector(f_cylinder, f_head, f_sector, f_sector_size, sector_size, fill_byte);
                 self.send_interrupt = true;
 
                 // Clear for next 4 bytes
@@ -1624,10 +1729,8 @@ impl FloppyController {
                 sector_size,
             );
 
-            // Set new CHS
-            //self.drives[self.drive_select].cylinder = new_c;
-            //self.drives[self.drive_select].head = new_h;
-            //self.drives[self.drive_select].sector = new_s;
+            // Formatting may have changed the number of sectors, heads or cylinders on the disk
+            self.drives[self.drive_select].update_geometry();
 
             // Finalize operation
             self.operation = Operation::NoOperation;
@@ -1635,7 +1738,20 @@ impl FloppyController {
         }
     }
 
-    pub fn format_sector(&mut self, _cylinder: u8, _head: u8, _sector: u8, _fill_byte: u8) {}
+    /// Append a sector to the track being formatted. The ID field is written as supplied, while
+    /// the size of the data field is set by the sector size parameter of the format command, and
+    /// filled with the specified byte.
+    pub fn format_sector(&mut self, cylinder: u8, head: u8, sector: u8, id_size: u8, sector_size: u8, fill_byte: u8) {
+        let id = SectorId::new(cylinder, head, sector, id_size);
+        let data_len = SectorId::new(cylinder, head, sector, sector_size).size();
+
+        let drive = &mut self.drives[self.drive_select];
+        let phys_head = drive.chs.h();
+        match drive.track_mut(phys_head) {
+            Some(track) => track.sectors.push(FloppySector::new(id, vec![fill_byte; data_len])),
+            None => log::warn!("format_sector: no track to format for sector {}", id),
+        }
+    }
 
     /// Run the Floppy Drive Controller. Process running Operations.
     pub fn run(&mut self, dma: &mut dma::DMAController, bus: &mut BusInterface, _us: f64) {
@@ -1659,16 +1775,21 @@ impl FloppyController {
             Operation::NoOperation => {
                 // Do nothing
             }
-            Operation::ReadSector(cylinder, head, sector, sector_size, track_len, _gap3_len, _data_len) => {
-                self.operation_read_sector(dma, bus, cylinder, head, sector, sector_size, track_len)
-            }
-            Operation::WriteSector(cylinder, head, sector, sector_size, track_len, _gap3_len, _data_len) => self
+            Operation::ReadSector(cylinder, head, sector, sector_size, track_len, _gap3_len, data_len) => self
+                .operation_read_sector(
+                    dma,
+                    bus,
+                    SectorId::new(cylinder, head, sector, sector_size),
+                    track_len,
+                    data_len,
+                ),
+            Operation::WriteSector(cylinder, head, sector, sector_size, track_len, _gap3_len, data_len) => self
                 .operation_write_sector(
                     dma,
                     bus,
-                    DiskChs::from((cylinder, head, sector)),
-                    sector_size,
+                    SectorId::new(cylinder, head, sector, sector_size),
                     track_len,
+                    data_len,
                 ),
             Operation::FormatTrack(sector_size, track_len, gap3_len, fill_byte) => {
                 self.operation_format_track(dma, bus, sector_size, track_len, gap3_len, fill_byte)
@@ -1679,3 +1800,177 @@ impl FloppyController {
         }
     }
 }
+
+impl_state_enum!(IoMode { ToCpu, FromCpu });
+impl_state_enum!(Command {
+    NoCommand,
+    ReadTrack,
+    WriteSector,
+    ReadSector,
+    WriteDeletedSector,
+    ReadDeletedSector,
+    FormatTrack,
+    FixDriveData,
+    CheckDriveStatus,
+    CalibrateDrive,
+    SenseIntStatus,
+    ReadSectorID,
+    SeekParkHead,
+    Invalid,
+});
+impl_state_enum!(DriveError {
+    NoError,
+    NoMedia,
+    BadSeek,
+    BadRead,
+    BadWrite,
+    WriteProtect,
+    DMAError,
+    NoSectorId,
+    WrongCylinder,
+    NoAddressMark,
+    NoDataMark,
+    IdCrcError,
+    DataCrcError,
+    DeletedData,
+    EndOfCylinder,
+});
+
+impl StateValue for Operation {
+    fn write_state(&self, w: &mut StateWriter) {
+        match *self {
+            Operation::NoOperation => w.write_u8(0),
+            Operation::ReadSector(c, h, s, ss, tl, gap, dl) => {
+                w.write_u8(1);
+                w.write_raw(&[c, h, s, ss, tl, gap, dl]);
+            }
+            Operation::WriteSector(c, h, s, ss, tl, gap, dl) => {
+                w.write_u8(2);
+                w.write_raw(&[c, h, s, ss, tl, gap, dl]);
+            }
+            Operation::FormatTrack(a, b, c, d) => {
+                w.write_u8(3);
+                w.write_raw(&[a, b, c, d]);
+            }
+        }
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, anyhow::Error> {
+        Ok(match r.read_u8()? {
+            0 => Operation::NoOperation,
+            1 => {
+                let b = r.read_raw(7)?;
+                Operation::ReadSector(b[0], b[1], b[2], b[3], b[4], b[5], b[6])
+            }
+            2 => {
+                let b = r.read_raw(7)?;
+                Operation::WriteSector(b[0], b[1], b[2], b[3], b[4], b[5], b[6])
+            }
+            3 => {
+                let b = r.read_raw(4)?;
+                Operation::FormatTrack(b[0], b[1], b[2], b[3])
+            }
+            tag => bail!("Invalid Operation value: {}", tag),
+        })
+    }
+}
+
+impl FloppyController {
+    /// Return the dispatch function for a command. Used to restore a partially received command
+    /// from a save state.
+    fn command_dispatch_fn(command: Command) -> Option<CommandDispatchFn> {
+        match command {
+            Command::WriteSector => Some(FloppyController::command_write_sector),
+            Command::ReadSector => Some(FloppyController::command_read_sector),
+            Command::FormatTrack => Some(FloppyController::command_format_track),
+            Command::FixDriveData => Some(FloppyController::command_fix_drive_data),
+            Command::CheckDriveStatus => Some(FloppyController::command_check_drive_status),
+            Command::CalibrateDrive => Some(FloppyController::command_calibrate_drive),
+            Command::ReadSectorID => Some(FloppyController::command_read_sector_id),
+            Command::SeekParkHead => Some(FloppyController::command_seek_head),
+            _ => None,
+        }
+    }
+}
+
+impl SaveState for FloppyController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.status_byte);
+        w.put(&self.reset_flag);
+        w.put(&self.reset_sense_count);
+        w.put(&self.mrq);
+        w.put(&self.data_register);
+        w.put(&self.dma);
+        w.put(&self.dor);
+        w.put(&self.busy);
+        w.put(&self.dio);
+        w.put(&self.reading_command);
+        w.put(&self.command);
+        w.put(&self.command_flags);
+        w.put(&self.last_command);
+        w.put(&self.receiving_command);
+        w.put(&self.command_byte_n);
+        w.put(&self.operation);
+        w.put(&self.operation_init);
+        w.put(&self.send_interrupt);
+        w.put(&self.pending_interrupt);
+        w.put(&self.end_interrupt);
+        w.put(&self.last_error);
+        w.put(&self.data_register_out);
+        w.put(&self.data_register_in);
+        w.put(&self.format_buffer);
+        w.put(&self.drive_ct);
+        w.put(&self.drive_select);
+        w.put(&self.in_dma);
+        w.put(&self.dma_byte_count);
+        w.put(&self.dma_bytes_left);
+        w.put(&self.xfer_size_sectors);
+        w.put(&self.xfer_size_bytes);
+        w.put(&self.xfer_completed_sectors);
+        for drive in &self.drives {
+            drive.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.status_byte = r.get()?;
+        self.reset_flag = r.get()?;
+        self.reset_sense_count = r.get()?;
+        self.mrq = r.get()?;
+        self.data_register = r.get()?;
+        self.dma = r.get()?;
+        self.dor = r.get()?;
+        self.busy = r.get()?;
+        self.dio = r.get()?;
+        self.reading_command = r.get()?;
+        self.command = r.get()?;
+        self.command_flags = r.get()?;
+        self.command_fn = FloppyController::command_dispatch_fn(self.command);
+        self.last_command = r.get()?;
+        self.receiving_command = r.get()?;
+        self.command_byte_n = r.get()?;
+        self.operation = r.get()?;
+        self.operation_init = r.get()?;
+        self.send_interrupt = r.get()?;
+        self.pending_interrupt = r.get()?;
+        self.end_interrupt = r.get()?;
+        self.last_error = r.get()?;
+        self.data_register_out = r.get()?;
+        self.data_register_in = r.get()?;
+        self.format_buffer = r.get()?;
+        let drive_ct: usize = r.get()?;
+        if drive_ct != self.drive_ct {
+            bail!("Floppy drive count mismatch: state has {}, machine has {}", drive_ct, self.drive_ct);
+        }
+        self.drive_select = r.get()?;
+        self.in_dma = r.get()?;
+        self.dma_byte_count = r.get()?;
+        self.dma_bytes_left = r.get()?;
+        self.xfer_size_sectors = r.get()?;
+        self.xfer_size_bytes = r.get()?;
+        self.xfer_completed_sectors = r.get()?;
+        for drive in self.drives.iter_mut() {
+            drive.load_state(r)?;
+        }
+        Ok(())
+    }
+}
@@ -30,17 +30,20 @@
 */
 
 use crate::{
-    device_types::{chs::DiskChs, fdc::DISK_FORMATS},
-    devices::fdc::SECTOR_SIZE,
+    device_types::chs::DiskChs,
+    floppy_image::{FloppyImage, FloppySector, FloppyTrack, SectorId, SectorMatch},
+    host_dir::{changed_sectors, HostDirVolume},
+    machine_types::FloppyDriveType,
+    savestate::{SaveState, StateReader, StateWriter},
 };
-use anyhow::{anyhow, Error};
+use anyhow::Error;
 
 pub struct FloppyDiskDrive {
     pub(crate) error_signal: bool,
 
     pub(crate) chs: DiskChs,
     media_geom: DiskChs,
-    drive_geom: DiskChs,
+    pub(crate) drive_geom: DiskChs,
 
     pub(crate) max_cylinders: u8,
     pub(crate) max_heads: u8,
@@ -50,7 +53,17 @@ pub struct FloppyDiskDrive {
     pub(crate) positioning: bool,
     pub(crate) have_disk: bool,
     pub(crate) write_protected: bool,
-    pub(crate) disk_image: Vec<u8>,
+    pub(crate) disk_image: Option<FloppyImage>,
+    /// Index of the next sector on the current track to pass under the head.
+    pub(crate) sector_index: usize,
+    /// Set when the disk is written to or formatted.
+    pub(crate) dirty: bool,
+    /// The host directory the disk was built from, if any. Not saved in save states.
+    pub(crate) host_dir: Option<HostDirVolume>,
+    /// A raw image of the disk as of the last host directory sync.
+    host_dir_snapshot: Vec<u8>,
+    /// Set when the disk is written to or formatted, and cleared by a host directory sync.
+    host_dir_dirty: bool,
 }
 
 impl Default for FloppyDiskDrive {
@@ -68,7 +81,12 @@ impl Default for FloppyDiskDrive {
             positioning: false,
             have_disk: false,
             write_protected: true,
-            disk_image: Vec::new(),
+            disk_image: None,
+            sector_index: 0,
+            dirty: false,
+            host_dir: None,
+            host_dir_snapshot: Vec::new(),
+            host_dir_dirty: false,
         }
     }
 }
@@ -81,7 +99,7 @@ impl FloppyDiskDrive {
     /// Called when FDC itself is reset.
     pub fn reset(&mut self) {
         // Preserve the disk image before defaulting the drive
-        let image = std::mem::replace(&mut self.disk_image, Vec::new());
+        let image = self.disk_image.take();
 
         *self = Self {
             ready: self.have_disk,
@@ -93,51 +111,172 @@ impl FloppyDiskDrive {
             motor_on: false,
             positioning: false,
             disk_image: image,
+            drive_geom: self.drive_geom,
+            dirty: self.dirty,
+            host_dir: self.host_dir.take(),
+            host_dir_snapshot: std::mem::take(&mut self.host_dir_snapshot),
+            host_dir_dirty: self.host_dir_dirty,
             ..Default::default()
         };
     }
 
     /// Load a disk into the specified drive
     pub fn load_image_from(&mut self, src_vec: Vec<u8>) -> Result<(), Error> {
-        let image_len: usize = src_vec.len();
-
-        // Disk images must contain whole sectors
-        if image_len % SECTOR_SIZE > 0 {
-            return Err(anyhow!("Invalid image length"));
-        }
+        let image = FloppyImage::load(src_vec)?;
+        self.load_image(image);
+        Ok(())
+    }
 
-        // Look up disk parameters based on image size
-        if let Some(fmt) = DISK_FORMATS.get(&image_len) {
-            self.max_cylinders = fmt.chs.c();
-            self.max_heads = fmt.chs.h();
-            self.max_sectors = fmt.chs.s();
-        }
-        else {
-            // No image format found.
-            if image_len < 163_840 {
-                // If image is smaller than single sided disk, assume single sided disk, 8 sectors per track
-                // This is useful for loading things like boot sector images without having to copy them to
-                // a full disk image
-                self.max_cylinders = 40;
-                self.max_heads = 1;
-                self.max_sectors = 8;
-            }
-            else {
-                return Err(anyhow!("Invalid image length"));
-            }
-        }
+    /// Set the geometry of the physical drive
+    pub fn set_drive_type(&mut self, drive_type: FloppyDriveType) {
+        self.drive_geom = match drive_type {
+            FloppyDriveType::Floppy360K => DiskChs::new(40, 2, 9),
+            FloppyDriveType::Floppy720K => DiskChs::new(80, 2, 9),
+            FloppyDriveType::Floppy12M => DiskChs::new(80, 2, 15),
+            FloppyDriveType::Floppy144M => DiskChs::new(80, 2, 18),
+        };
+    }
 
+    /// Insert a parsed disk image into the drive
+    pub fn load_image(&mut self, image: FloppyImage) {
         self.have_disk = true;
-        self.disk_image = src_vec;
+        self.disk_image = Some(image);
+        self.sector_index = 0;
+        self.dirty = false;
+        self.host_dir = None;
+        self.host_dir_snapshot.clear();
+        self.host_dir_dirty = false;
+        self.update_geometry();
 
         log::debug!(
-            "Loaded floppy image, size: {} c: {} h: {} s: {}",
-            self.disk_image.len(),
+            "Loaded {} floppy image, c: {} h: {} s: {}",
+            self.disk_image.as_ref().unwrap().format,
             self.max_cylinders,
             self.max_heads,
             self.max_sectors
         );
+    }
+
+    /// Insert a disk built from a host directory. Writes to the disk are applied to the directory
+    /// by sync_host_dir().
+    pub fn load_host_dir(&mut self, image: FloppyImage, host_dir: HostDirVolume) {
+        let snapshot = image.to_raw();
+        self.load_image(image);
+        self.host_dir = Some(host_dir);
+        self.host_dir_snapshot = snapshot;
+    }
+
+    /// Mark the disk as written to or formatted.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.host_dir_dirty = true;
+    }
+
+    /// Apply any changes made to the disk since the last sync to its host directory.
+    pub fn sync_host_dir(&mut self) {
+        if !self.host_dir_dirty {
+            return;
+        }
+        self.host_dir_dirty = false;
+        let (Some(host_dir), Some(image)) = (self.host_dir.as_mut(), self.disk_image.as_ref())
+        else {
+            return;
+        };
+
+        let raw = image.to_raw();
+        let dirty_sectors = changed_sectors(&self.host_dir_snapshot, &raw);
+        if let Err(err) = host_dir.sync(&raw, &dirty_sectors) {
+            log::error!("Failed to sync floppy to host directory {}: {}", host_dir.root().display(), err);
+        }
+        self.host_dir_snapshot = raw;
+    }
+
+    /// Update the media geometry from the disk image, after it is loaded or reformatted.
+    pub fn update_geometry(&mut self) {
+        if let Some(image) = &self.disk_image {
+            let geom = image.geometry();
+            self.max_cylinders = geom.c();
+            self.max_heads = geom.h();
+            self.max_sectors = geom.s();
+            self.media_geom = geom;
+        }
+    }
+
+    /// Return the track under the specified head at the drive's current physical cylinder.
+    pub fn track(&self, head: u8) -> Option<&FloppyTrack> {
+        self.disk_image.as_ref()?.track(self.chs.c(), head)
+    }
+
+    pub fn track_mut(&mut self, head: u8) -> Option<&mut FloppyTrack> {
+        let cylinder = self.chs.c();
+        self.disk_image.as_mut()?.track_mut(cylinder, head)
+    }
+
+    /// Search the current track for a sector ID, beginning with the next sector to pass under
+    /// the head.
+    pub fn find_sector(&self, head: u8, id: &SectorId) -> SectorMatch {
+        match self.track(head) {
+            Some(track) => track.find_sector(id, self.sector_index),
+            None => SectorMatch::NotFound,
+        }
+    }
+
+    pub fn sector(&self, head: u8, index: usize) -> Option<&FloppySector> {
+        self.track(head)?.sectors.get(index)
+    }
+
+    pub fn sector_mut(&mut self, head: u8, index: usize) -> Option<&mut FloppySector> {
+        self.track_mut(head)?.sectors.get_mut(index)
+    }
+}
+
+/// The disk image is saved along with the drive, so that a restored machine sees the same disk
+/// contents it was using, including any writes not yet saved back to the host. A mounted host
+/// directory is detached on load.
+impl SaveState for FloppyDiskDrive {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.error_signal);
+        w.put(&self.chs);
+        w.put(&self.media_geom);
+        w.put(&self.drive_geom);
+        w.put(&self.max_cylinders);
+        w.put(&self.max_heads);
+        w.put(&self.max_sectors);
+        w.put(&self.ready);
+        w.put(&self.motor_on);
+        w.put(&self.positioning);
+        w.put(&self.have_disk);
+        w.put(&self.write_protected);
+        w.put(&self.disk_image);
+        w.put(&self.sector_index);
+    }
 
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Error> {
+        self.error_signal = r.get()?;
+        self.chs = r.get()?;
+        self.media_geom = r.get()?;
+        self.drive_geom = r.get()?;
+        self.max_cylinders = r.get()?;
+        self.max_heads = r.get()?;
+        self.max_sectors = r.get()?;
+        self.ready = r.get()?;
+        self.motor_on = r.get()?;
+        self.positioning = r.get()?;
+        self.have_disk = r.get()?;
+        self.write_protected = r.get()?;
+        self.disk_image = r.get()?;
+        self.sector_index = r.get()?;
+
+        // The restored image has no relation to the host directory snapshot, so syncing it would
+        // overwrite or delete host files. Detach the directory; the disk becomes a plain image.
+        if let Some(host_dir) = self.host_dir.take() {
+            log::warn!(
+                "Detached host directory {} from floppy on state load",
+                host_dir.root().display()
+            );
+        }
+        self.host_dir_snapshot.clear();
+        self.host_dir_dirty = false;
         Ok(())
     }
 }
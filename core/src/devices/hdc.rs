@@ -39,9 +39,10 @@ use core::fmt::Display;
 use crate::{
     bus::{BusInterface, DeviceRunTimeUnit},
     devices::dma,
+    savestate::{impl_state_enum, SaveState, StateReader, StateWriter},
 };
 //use crate::fdc::Operation;
-use crate::{bus::IoDevice, device_types::hdc::HardDiskFormat, vhd::VirtualHardDisk};
+use crate::{bus::IoDevice, device_types::hdc::HardDiskFormat, hdd_image::DiskBackend};
 
 // Public consts
 pub const HDC_IRQ: u8 = 0x05;
@@ -97,6 +98,7 @@ pub enum ControllerError {
     NoError,
     InvalidDevice,
     UnsupportedVHD,
+    GeometryMismatch,
 }
 impl Error for ControllerError {}
 impl Display for ControllerError {
@@ -107,7 +109,10 @@ impl Display for ControllerError {
                 write!(f, "The specified Device ID was out of range [0..1]")
             }
             ControllerError::UnsupportedVHD => {
-                write!(f, "The VHD file did not match the list of supported drive types.")
+                write!(f, "The disk image did not match the list of supported drive types.")
+            }
+            ControllerError::GeometryMismatch => {
+                write!(f, "The disk image did not match its configured geometry.")
             }
         }
     }
@@ -205,7 +210,7 @@ pub struct HardDisk {
     max_heads: u8,
     max_sectors: u8,
     sector_buf: Vec<u8>,
-    vhd: Option<VirtualHardDisk>,
+    disk: Option<Box<dyn DiskBackend>>,
 }
 
 impl HardDisk {
@@ -218,7 +223,7 @@ impl HardDisk {
             max_heads: 0,
             max_sectors: 0,
             sector_buf: vec![0; SECTOR_SIZE],
-            vhd: None,
+            disk: None,
         }
     }
 
@@ -390,34 +395,46 @@ impl HardDiskController {
         self.supported_formats.clone()
     }
 
-    pub fn set_vhd(&mut self, device_id: usize, vhd: VirtualHardDisk) -> Result<(), ControllerError> {
+    /// Mount a disk image into the specified drive. The image may be a VHD, a raw sector image, or
+    /// any other DiskBackend.
+    ///
+    /// If `geometry` is specified, it is the geometry configured for the image: the image must have
+    /// that geometry and be large enough to hold it. Otherwise the image's geometry must match one
+    /// of the supported formats.
+    pub fn set_disk(
+        &mut self,
+        device_id: usize,
+        disk: Box<dyn DiskBackend>,
+        geometry: Option<(u16, u8, u8)>,
+    ) -> Result<(), ControllerError> {
         if device_id > 1 {
             return Err(ControllerError::InvalidDevice);
         }
 
-        // Check that the VHD geometry is in the list of supported formats
-        // (Currently there is only one supported format but that might change)
-        let mut supported = false;
-        for format in &self.supported_formats {
-            if vhd.max_cylinders as u16 == format.max_cylinders
-                && vhd.max_heads as u8 == format.max_heads
-                && vhd.max_sectors as u8 == format.max_sectors
-            {
-                supported = true;
-                break;
+        let (max_cylinders, max_heads, max_sectors) = disk.geometry();
+        match geometry {
+            Some((c, h, s)) => {
+                let size = c as u64 * h as u64 * s as u64 * SECTOR_SIZE as u64;
+                if (max_cylinders, max_heads, max_sectors) != (c, h, s) || disk.size() < size {
+                    return Err(ControllerError::GeometryMismatch);
+                }
+            }
+            None => {
+                let supported = self.supported_formats.iter().any(|format| {
+                    max_cylinders == format.max_cylinders
+                        && max_heads == format.max_heads
+                        && max_sectors == format.max_sectors
+                });
+                if !supported {
+                    return Err(ControllerError::UnsupportedVHD);
+                }
             }
         }
 
-        if supported {
-            self.drives[device_id].max_cylinders = vhd.max_cylinders as u16;
-            self.drives[device_id].max_heads = vhd.max_heads as u8;
-            self.drives[device_id].max_sectors = vhd.max_sectors as u8;
-            self.drives[device_id].vhd = Some(vhd);
-        }
-        else {
-            return Err(ControllerError::UnsupportedVHD);
-        }
-
+        self.drives[device_id].max_cylinders = max_cylinders;
+        self.drives[device_id].max_heads = max_heads;
+        self.drives[device_id].max_sectors = max_sectors;
+        self.drives[device_id].disk = Some(disk);
         Ok(())
     }
 
@@ -788,7 +805,7 @@ impl HardDiskController {
 
     /// Return a boolean representing whether a virtual drive is mounted for the specified drive number
     fn drive_present(&mut self, drive_n: usize) -> bool {
-        self.drives[drive_n].vhd.is_some()
+        self.drives[drive_n].disk.is_some()
     }
 
     /// Perform the Sense Status command
@@ -859,11 +876,11 @@ impl HardDiskController {
         );
 
         // Prime the Sector Buffer with an intitial sector read
-        match &mut self.drives[dcb.drive_select].vhd {
-            Some(vhd) => {
-                if let Err(e) = vhd.read_sector(&mut self.drives[dcb.drive_select].sector_buf, dcb.c, dcb.h, dcb.s) {
+        match &mut self.drives[dcb.drive_select].disk {
+            Some(disk) => {
+                if let Err(e) = disk.read_sector(&mut self.drives[dcb.drive_select].sector_buf, dcb.c, dcb.h, dcb.s) {
                     log::error!(
-                        "VHD read_sector() failed: c:{} h:{} s:{} Error: {}",
+                        "Disk read_sector() failed: c:{} h:{} s:{} Error: {}",
                         dcb.c,
                         dcb.h,
                         dcb.s,
@@ -1172,6 +1189,15 @@ impl HardDiskController {
         Continuation::CommandComplete
     }
 
+    /// Commit buffered writes on the selected drive's disk at the end of a write command.
+    fn flush_disk(&mut self) {
+        if let Some(disk) = &mut self.drives[self.drive_select].disk {
+            if let Err(err) = disk.flush() {
+                log::error!("Disk flush failed: {}", err);
+            }
+        }
+    }
+
     /// End a Command that utilized DMA service.
     fn end_dma_command(&mut self, _drive: u32, error: bool) {
         self.clear_dreq = true;
@@ -1298,9 +1324,9 @@ impl HardDiskController {
                     self.drives[self.drive_select].sector = new_s;
                     self.operation_status.buffer_idx = 0;
 
-                    match &mut self.drives[self.drive_select].vhd {
-                        Some(vhd) => {
-                            match vhd.read_sector(
+                    match &mut self.drives[self.drive_select].disk {
+                        Some(disk) => {
+                            match disk.read_sector(
                                 &mut self.drives[self.drive_select].sector_buf,
                                 self.drives[self.drive_select].cylinder,
                                 self.drives[self.drive_select].head,
@@ -1315,7 +1341,7 @@ impl HardDiskController {
                             };
                         }
                         None => {
-                            log::error!("Read operation without VHD mounted.");
+                            log::error!("Read operation without disk mounted.");
                         }
                     }
                 }
@@ -1365,9 +1391,9 @@ impl HardDiskController {
 
                 // Filled the sector buffer, write it to disk
                 if self.operation_status.buffer_idx == SECTOR_SIZE {
-                    match &mut self.drives[self.drive_select].vhd {
-                        Some(vhd) => {
-                            match vhd.write_sector(
+                    match &mut self.drives[self.drive_select].disk {
+                        Some(disk) => {
+                            match disk.write_sector(
                                 &self.drives[self.drive_select].sector_buf,
                                 self.drives[self.drive_select].cylinder,
                                 self.drives[self.drive_select].head,
@@ -1388,7 +1414,7 @@ impl HardDiskController {
                             };
                         }
                         None => {
-                            log::error!("Write operation without VHD mounted.");
+                            log::error!("Write operation without disk mounted.");
                         }
                     }
 
@@ -1418,6 +1444,7 @@ impl HardDiskController {
                         );
                     }
 
+                    self.flush_disk();
                     self.end_dma_command(0, false);
                 }
             }
@@ -1428,6 +1455,7 @@ impl HardDiskController {
                     log::warn!("Command Write complete without DMA terminal count.");
                 }
 
+                self.flush_disk();
                 self.end_dma_command(0, false);
             }
         }
@@ -1507,3 +1535,239 @@ impl HardDiskController {
         }
     }
 }
+
+impl_state_enum!(OperationError {
+    NoError,
+    NoReadySignal,
+    InvalidCommand,
+    IllegalAccess,
+});
+impl_state_enum!(State {
+    Reset,
+    WaitingForCommand,
+    ReceivingCommand,
+    ExecutingCommand,
+    HaveCommandResult,
+    HaveCommandStatus,
+    HaveSenseBytes,
+});
+impl_state_enum!(Command {
+    None,
+    TestDriveReady,
+    Recalibrate,
+    RequestSense,
+    FormatDrive,
+    ReadyVerify,
+    FormatTrack,
+    FormatBadTrack,
+    Read,
+    Write,
+    Seek,
+    Initialize,
+    ReadEccBurstLength,
+    ReadSectorBuffer,
+    WriteSectorBuffer,
+    RamDiagnostic,
+    DriveDiagnostic,
+    ControllerDiagnostic,
+    ReadLongTrack,
+    WriteLongTrack,
+});
+
+/// The contents of a mounted disk image are not part of the save state. The same images must be mounted
+/// when a state is loaded, and must not have been modified since the state was saved.
+impl SaveState for HardDisk {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.cylinder);
+        w.put(&self.head);
+        w.put(&self.sector);
+        w.put(&self.max_cylinders);
+        w.put(&self.max_heads);
+        w.put(&self.max_sectors);
+        w.put(&self.sector_buf);
+        w.put(&self.disk.is_some());
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.cylinder = r.get()?;
+        self.head = r.get()?;
+        self.sector = r.get()?;
+        let geometry: (u16, (u8, u8)) = (r.get()?, (r.get()?, r.get()?));
+        self.sector_buf = r.get()?;
+        let have_vhd: bool = r.get()?;
+        if have_vhd != self.disk.is_some() {
+            anyhow::bail!("Hard disk image is {} in save state but not in machine", match have_vhd {
+                true => "mounted",
+                false => "not mounted",
+            });
+        }
+        if have_vhd && geometry != (self.max_cylinders, (self.max_heads, self.max_sectors)) {
+            anyhow::bail!("Mounted hard disk image geometry does not match save state");
+        }
+        Ok(())
+    }
+}
+
+impl HardDiskController {
+    /// Return the dispatch function for a command. Used to restore a partially received command
+    /// from a save state.
+    fn command_dispatch_fn(command: Command) -> Option<CommandDispatchFn> {
+        match command {
+            Command::TestDriveReady => Some(HardDiskController::command_test_drive_ready),
+            Command::Recalibrate => Some(HardDiskController::command_recalibrate),
+            Command::RequestSense => Some(HardDiskController::command_sense_status),
+            Command::ReadyVerify => Some(HardDiskController::command_ready_verify),
+            Command::Read => Some(HardDiskController::command_read),
+            Command::Write => Some(HardDiskController::command_write),
+            Command::Seek => Some(HardDiskController::command_seek),
+            Command::Initialize => Some(HardDiskController::command_initialize_dc),
+            Command::ReadSectorBuffer => Some(HardDiskController::command_read_sector_buffer),
+            Command::WriteSectorBuffer => Some(HardDiskController::command_write_sector_buffer),
+            Command::RamDiagnostic => Some(HardDiskController::command_ram_diagnostic),
+            Command::DriveDiagnostic => Some(HardDiskController::command_drive_diagnostic),
+            Command::ControllerDiagnostic => Some(HardDiskController::command_controller_diagnostic),
+            _ => None,
+        }
+    }
+}
+
+impl SaveState for HardDiskController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.drive_ct);
+        w.put(&self.drive_select);
+        w.put(&self.state);
+        w.put(&self.last_error);
+        w.put(&self.last_error_drive);
+        w.put(&self.error_flag);
+        w.put(&self.receiving_dcb);
+        w.put(&self.command);
+        w.put(&self.last_command);
+        w.put(&self.command_byte_n);
+        w.put(&self.command_result_pending);
+        w.put(&self.data_register_in);
+        w.put(&self.data_register_out);
+
+        w.put(&self.operation_status.drive_select);
+        w.put(&self.operation_status.buffer_idx);
+        w.put(&self.operation_status.block_ct);
+        w.put(&self.operation_status.block_n);
+        w.put(&self.operation_status.dma_bytes_left);
+        w.put(&self.operation_status.dma_byte_count);
+
+        w.put(&self.dma_enabled);
+        w.put(&self.irq_enabled);
+        w.put(&self.send_interrupt);
+        w.put(&self.clear_interrupt);
+        w.put(&self.interrupt_active);
+        w.put(&self.send_dreq);
+        w.put(&self.clear_dreq);
+        w.put(&self.dreq_active);
+        w.put(&self.state_accumulator);
+
+        for drive in &self.drives {
+            drive.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        let drive_ct: usize = r.get()?;
+        if drive_ct != self.drive_ct {
+            anyhow::bail!("Hard drive count mismatch: state has {}, machine has {}", drive_ct, self.drive_ct);
+        }
+        self.drive_select = r.get()?;
+        self.state = r.get()?;
+        self.last_error = r.get()?;
+        self.last_error_drive = r.get()?;
+        self.error_flag = r.get()?;
+        self.receiving_dcb = r.get()?;
+        self.command = r.get()?;
+        self.command_fn = HardDiskController::command_dispatch_fn(self.command);
+        self.last_command = r.get()?;
+        self.command_byte_n = r.get()?;
+        self.command_result_pending = r.get()?;
+        self.data_register_in = r.get()?;
+        self.data_register_out = r.get()?;
+
+        self.operation_status.drive_select = r.get()?;
+        self.operation_status.buffer_idx = r.get()?;
+        self.operation_status.block_ct = r.get()?;
+        self.operation_status.block_n = r.get()?;
+        self.operation_status.dma_bytes_left = r.get()?;
+        self.operation_status.dma_byte_count = r.get()?;
+
+        self.dma_enabled = r.get()?;
+        self.irq_enabled = r.get()?;
+        self.send_interrupt = r.get()?;
+        self.clear_interrupt = r.get()?;
+        self.interrupt_active = r.get()?;
+        self.send_dreq = r.get()?;
+        self.clear_dreq = r.get()?;
+        self.dreq_active = r.get()?;
+        self.state_accumulator = r.get()?;
+
+        for drive in self.drives.iter_mut() {
+            drive.load_state(r)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A disk backend of arbitrary geometry and size that reads as zeros.
+    struct TestDisk {
+        geometry: (u16, u8, u8),
+        size: u64,
+    }
+
+    impl DiskBackend for TestDisk {
+        fn geometry(&self) -> (u16, u8, u8) {
+            self.geometry
+        }
+        fn size(&self) -> u64 {
+            self.size
+        }
+        fn read_sector(&mut self, buf: &mut [u8], _cylinder: u16, _head: u8, _sector: u8) -> Result<(), anyhow::Error> {
+            buf.fill(0);
+            Ok(())
+        }
+        fn write_sector(&mut self, _buf: &[u8], _cylinder: u16, _head: u8, _sector: u8) -> Result<(), anyhow::Error> {
+            Ok(())
+        }
+    }
+
+    fn test_disk(geometry: (u16, u8, u8), sectors: u64) -> Box<dyn DiskBackend> {
+        Box::new(TestDisk {
+            geometry,
+            size: sectors * SECTOR_SIZE as u64,
+        })
+    }
+
+    #[test]
+    fn test_set_disk_geometry() {
+        let mut hdc = HardDiskController::default();
+        let geometry = (612, 4, 17);
+        let sectors = 612 * 4 * 17;
+
+        // Without a configured geometry, only the supported formats are accepted.
+        assert!(hdc.set_disk(0, test_disk((615, 4, 17), 615 * 4 * 17), None).is_ok());
+        assert!(matches!(
+            hdc.set_disk(0, test_disk(geometry, sectors), None),
+            Err(ControllerError::UnsupportedVHD)
+        ));
+
+        // With a configured geometry, the image must have that geometry and be large enough for it.
+        assert!(hdc.set_disk(0, test_disk(geometry, sectors), Some(geometry)).is_ok());
+        assert_eq!(hdc.drives[0].max_cylinders, 612);
+        assert!(matches!(
+            hdc.set_disk(0, test_disk(geometry, sectors - 1), Some(geometry)),
+            Err(ControllerError::GeometryMismatch)
+        ));
+        assert!(matches!(
+            hdc.set_disk(0, test_disk((615, 4, 17), 615 * 4 * 17), Some(geometry)),
+            Err(ControllerError::GeometryMismatch)
+        ));
+    }
+}
@@ -0,0 +1,478 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::kbc.rs
+
+    Implement the 8042 keyboard controller of the IBM PC/AT.
+
+    The 8042 replaces the PPI as the interface to the keyboard on AT-class
+    machines. Unlike the one-way XT interface, the controller can send
+    commands to the keyboard, and it can translate the keyboard's scancode
+    set 2 into the set 1 codes expected by PC software. The controller's
+    output port also drives the A20 gate and the CPU reset line.
+*/
+
+use std::collections::VecDeque;
+
+use crate::{
+    bus::{BusInterface, DeviceRunTimeUnit, IoDevice},
+    device_traits::videocard::VideoType,
+    devices::{keyboard::*, pic},
+    savestate::{SaveState, StateReader, StateWriter},
+};
+
+pub const KBC_DATA_PORT: u16 = 0x60;
+pub const KBC_COMMAND_PORT: u16 = 0x64;
+
+// Status register bits
+pub const STATUS_OUTPUT_FULL: u8 = 0b0000_0001;
+pub const STATUS_INPUT_FULL: u8 = 0b0000_0010;
+pub const STATUS_SYSTEM_FLAG: u8 = 0b0000_0100;
+pub const STATUS_COMMAND: u8 = 0b0000_1000;
+pub const STATUS_NOT_INHIBITED: u8 = 0b0001_0000;
+pub const STATUS_TIMEOUT: u8 = 0b0100_0000;
+
+// Command byte bits
+pub const CMD_BYTE_KBD_IRQ: u8 = 0b0000_0001;
+pub const CMD_BYTE_SYSTEM_FLAG: u8 = 0b0000_0100;
+pub const CMD_BYTE_INHIBIT_OVERRIDE: u8 = 0b0000_1000;
+pub const CMD_BYTE_DISABLE_KBD: u8 = 0b0001_0000;
+pub const CMD_BYTE_TRANSLATE: u8 = 0b0100_0000;
+
+// Input port bits
+pub const INPUT_COLOR_DISPLAY: u8 = 0b0100_0000;
+pub const INPUT_NOT_INHIBITED: u8 = 0b1000_0000;
+
+// Output port bits
+pub const OUTPUT_SYSTEM_RESET: u8 = 0b0000_0001; // Active low
+pub const OUTPUT_A20_GATE: u8 = 0b0000_0010;
+pub const OUTPUT_OUTPUT_FULL: u8 = 0b0001_0000;
+pub const OUTPUT_INPUT_EMPTY: u8 = 0b0010_0000;
+pub const OUTPUT_KBD_CLOCK: u8 = 0b0100_0000;
+pub const OUTPUT_KBD_DATA: u8 = 0b1000_0000;
+
+// Controller commands
+pub const KBC_CMD_READ_RAM: u8 = 0x20; // 0x20-0x3F read controller RAM, 0x20 being the command byte
+pub const KBC_CMD_WRITE_RAM: u8 = 0x60; // 0x60-0x7F write controller RAM, 0x60 being the command byte
+pub const KBC_CMD_SELF_TEST: u8 = 0xAA;
+pub const KBC_CMD_INTERFACE_TEST: u8 = 0xAB;
+pub const KBC_CMD_DISABLE_KBD: u8 = 0xAD;
+pub const KBC_CMD_ENABLE_KBD: u8 = 0xAE;
+pub const KBC_CMD_READ_INPUT: u8 = 0xC0;
+pub const KBC_CMD_READ_OUTPUT: u8 = 0xD0;
+pub const KBC_CMD_WRITE_OUTPUT: u8 = 0xD1;
+pub const KBC_CMD_READ_TEST_INPUTS: u8 = 0xE0;
+pub const KBC_CMD_PULSE_OUTPUT: u8 = 0xF0; // 0xF0-0xFF pulse output port bits 0-3 low
+
+pub const KBC_SELF_TEST_OK: u8 = 0x55;
+pub const KBC_INTERFACE_TEST_OK: u8 = 0x00;
+
+pub const KBC_IRQ: u8 = 1;
+
+const KBC_RAM_SIZE: usize = 32;
+const KBC_DEFAULT_COMMAND_BYTE: u8 = CMD_BYTE_KBD_IRQ | CMD_BYTE_TRANSLATE;
+const KBC_DEFAULT_OUTPUT_PORT: u8 = OUTPUT_SYSTEM_RESET | OUTPUT_INPUT_EMPTY | OUTPUT_KBD_CLOCK | OUTPUT_KBD_DATA;
+
+pub struct Kbc {
+    ram: [u8; KBC_RAM_SIZE], // Internal RAM. Byte 0 holds the command byte.
+    input_port: u8,
+    output_port: u8,
+    output_buffer: u8,
+    output_full: bool,
+    last_write_command: bool,
+    timeout: bool,
+    pending_command: Option<u8>, // Controller command awaiting a data byte on port 0x60.
+    translate_break: bool,       // An 0xF0 break prefix was received while translating.
+    to_keyboard: VecDeque<u8>,
+    irq_pending: bool,
+    reset_request: bool,
+}
+
+impl Kbc {
+    pub fn new(video_types: &[VideoType]) -> Self {
+        // The input port reports the type of the primary display adapter, like the DIP switches of the PC/XT.
+        let mut input_port = INPUT_NOT_INHIBITED;
+        if video_types.first() != Some(&VideoType::MDA) {
+            input_port |= INPUT_COLOR_DISPLAY;
+        }
+
+        let mut kbc = Self {
+            ram: [0; KBC_RAM_SIZE],
+            input_port,
+            output_port: KBC_DEFAULT_OUTPUT_PORT,
+            output_buffer: 0,
+            output_full: false,
+            last_write_command: false,
+            timeout: false,
+            pending_command: None,
+            translate_break: false,
+            to_keyboard: VecDeque::new(),
+            irq_pending: false,
+            reset_request: false,
+        };
+        kbc.ram[0] = KBC_DEFAULT_COMMAND_BYTE;
+        kbc
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self {
+            input_port: self.input_port,
+            ..Self::new(&[])
+        };
+    }
+
+    pub fn command_byte(&self) -> u8 {
+        self.ram[0]
+    }
+
+    /// Return the state of the A20 gate output. This is a stub: the 8088 core has no address line 20
+    /// to gate, so the output is tracked for software that reads it back but does not affect memory.
+    pub fn a20_gate(&self) -> bool {
+        self.output_port & OUTPUT_A20_GATE != 0
+    }
+
+    /// Return and clear a pending request to reset the CPU.
+    pub fn take_reset_request(&mut self) -> bool {
+        std::mem::take(&mut self.reset_request)
+    }
+
+    fn status(&self) -> u8 {
+        let mut status = 0;
+        if self.output_full {
+            status |= STATUS_OUTPUT_FULL;
+        }
+        if self.ram[0] & CMD_BYTE_SYSTEM_FLAG != 0 {
+            status |= STATUS_SYSTEM_FLAG;
+        }
+        if self.last_write_command {
+            status |= STATUS_COMMAND;
+        }
+        if self.input_port & INPUT_NOT_INHIBITED != 0 || self.ram[0] & CMD_BYTE_INHIBIT_OVERRIDE != 0 {
+            status |= STATUS_NOT_INHIBITED;
+        }
+        if self.timeout {
+            status |= STATUS_TIMEOUT;
+        }
+        status
+    }
+
+    /// Place a byte in the output buffer, requesting an interrupt if enabled.
+    fn load_output(&mut self, byte: u8) {
+        self.output_buffer = byte;
+        self.output_full = true;
+        if self.ram[0] & CMD_BYTE_KBD_IRQ != 0 {
+            self.irq_pending = true;
+        }
+    }
+
+    fn write_output_port(&mut self, byte: u8) {
+        let a20_gate = byte & OUTPUT_A20_GATE != 0;
+        if a20_gate != self.a20_gate() {
+            log::debug!("KBC: A20 gate enabled: {}", a20_gate);
+        }
+        self.output_port = byte;
+        if byte & OUTPUT_SYSTEM_RESET == 0 {
+            log::debug!("KBC: CPU reset requested");
+            self.reset_request = true;
+            self.output_port |= OUTPUT_SYSTEM_RESET;
+        }
+    }
+
+    fn handle_command(&mut self, byte: u8) {
+        log::trace!("KBC: Command: {:02X}", byte);
+        match byte {
+            0x20..=0x3F => self.load_output(self.ram[(byte - KBC_CMD_READ_RAM) as usize]),
+            0x60..=0x7F | KBC_CMD_WRITE_OUTPUT => self.pending_command = Some(byte),
+            KBC_CMD_SELF_TEST => self.load_output(KBC_SELF_TEST_OK),
+            KBC_CMD_INTERFACE_TEST => self.load_output(KBC_INTERFACE_TEST_OK),
+            KBC_CMD_DISABLE_KBD => self.ram[0] |= CMD_BYTE_DISABLE_KBD,
+            KBC_CMD_ENABLE_KBD => self.ram[0] &= !CMD_BYTE_DISABLE_KBD,
+            KBC_CMD_READ_INPUT => self.load_output(self.input_port),
+            KBC_CMD_READ_OUTPUT => {
+                let mut port = self.output_port & !OUTPUT_OUTPUT_FULL;
+                if self.output_full {
+                    port |= OUTPUT_OUTPUT_FULL;
+                }
+                self.load_output(port);
+            }
+            KBC_CMD_READ_TEST_INPUTS => self.load_output(0),
+            0xF0..=0xFF => {
+                // Bits 0-3 of the command select output port bits to pulse low. Only the reset line is wired.
+                if byte & OUTPUT_SYSTEM_RESET == 0 {
+                    self.write_output_port(self.output_port & !OUTPUT_SYSTEM_RESET);
+                }
+            }
+            _ => log::warn!("KBC: Unhandled command: {:02X}", byte),
+        }
+    }
+
+    fn handle_data(&mut self, byte: u8) {
+        self.timeout = false;
+        match self.pending_command.take() {
+            Some(KBC_CMD_WRITE_OUTPUT) => self.write_output_port(byte),
+            Some(command) => self.ram[(command - KBC_CMD_WRITE_RAM) as usize] = byte,
+            None => {
+                // Data written without a pending command goes to the keyboard. Sending to the keyboard
+                // enables the keyboard interface.
+                self.ram[0] &= !CMD_BYTE_DISABLE_KBD;
+                self.to_keyboard.push_back(byte);
+            }
+        }
+    }
+
+    /// Translate a byte from the keyboard from scancode set 2 to set 1. Returns None for a break prefix,
+    /// which is folded into the following code.
+    fn translate(&mut self, byte: u8) -> Option<u8> {
+        if byte == 0xF0 {
+            self.translate_break = true;
+            return None;
+        }
+
+        let mut code = scancode_set2_to_set1(byte);
+        if std::mem::take(&mut self.translate_break) {
+            code |= 0x80;
+        }
+        Some(code)
+    }
+
+    /// Run the keyboard controller. Bytes sent by the host are delivered to the keyboard, and the next
+    /// byte from the keyboard is loaded once the output buffer has been read.
+    pub fn run(&mut self, keyboard: Option<&mut Keyboard>, pic: &mut pic::Pic) {
+        if let Some(keyboard) = keyboard {
+            while let Some(byte) = self.to_keyboard.pop_front() {
+                keyboard.send_command(byte);
+            }
+
+            while !self.output_full && self.ram[0] & CMD_BYTE_DISABLE_KBD == 0 {
+                match keyboard.recv_scancode() {
+                    Some(byte) if self.ram[0] & CMD_BYTE_TRANSLATE == 0 => self.load_output(byte),
+                    Some(byte) => {
+                        if let Some(code) = self.translate(byte) {
+                            self.load_output(code);
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+        else if !self.to_keyboard.is_empty() {
+            // Nobody is listening.
+            self.to_keyboard.clear();
+            self.timeout = true;
+        }
+
+        if std::mem::take(&mut self.irq_pending) {
+            pic.pulse_interrupt(KBC_IRQ);
+        }
+    }
+}
+
+impl IoDevice for Kbc {
+    fn read_u8(&mut self, port: u16, _delta: DeviceRunTimeUnit) -> u8 {
+        match port {
+            KBC_DATA_PORT => {
+                self.output_full = false;
+                self.output_buffer
+            }
+            KBC_COMMAND_PORT => self.status(),
+            _ => panic!("KBC: Bad port #"),
+        }
+    }
+
+    fn write_u8(&mut self, port: u16, byte: u8, _bus: Option<&mut BusInterface>, _delta: DeviceRunTimeUnit) {
+        match port {
+            KBC_DATA_PORT => {
+                self.last_write_command = false;
+                self.handle_data(byte);
+            }
+            KBC_COMMAND_PORT => {
+                self.last_write_command = true;
+                self.pending_command = None;
+                self.handle_command(byte);
+            }
+            _ => panic!("KBC: Bad port #"),
+        }
+    }
+
+    fn port_list(&self) -> Vec<u16> {
+        vec![KBC_DATA_PORT, KBC_COMMAND_PORT]
+    }
+}
+
+impl SaveState for Kbc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.ram);
+        w.put(&self.output_port);
+        w.put(&self.output_buffer);
+        w.put(&self.output_full);
+        w.put(&self.last_write_command);
+        w.put(&self.timeout);
+        w.put(&self.pending_command);
+        w.put(&self.translate_break);
+        w.put(&self.to_keyboard);
+        w.put(&self.irq_pending);
+        w.put(&self.reset_request);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.ram = r.get()?;
+        self.output_port = r.get()?;
+        self.output_buffer = r.get()?;
+        self.output_full = r.get()?;
+        self.last_write_command = r.get()?;
+        self.timeout = r.get()?;
+        self.pending_command = r.get()?;
+        self.translate_break = r.get()?;
+        self.to_keyboard = r.get()?;
+        self.irq_pending = r.get()?;
+        self.reset_request = r.get()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keys::MartyKey;
+
+    const NUL: DeviceRunTimeUnit = DeviceRunTimeUnit::Microseconds(0.0);
+
+    fn command(kbc: &mut Kbc, byte: u8) {
+        kbc.write_u8(KBC_COMMAND_PORT, byte, None, NUL);
+    }
+
+    fn data(kbc: &mut Kbc, byte: u8) {
+        kbc.write_u8(KBC_DATA_PORT, byte, None, NUL);
+    }
+
+    /// Run the controller and read back every byte it delivers.
+    fn drain(kbc: &mut Kbc, keyboard: &mut Keyboard, pic: &mut pic::Pic) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            kbc.run(Some(keyboard), pic);
+            if kbc.read_u8(KBC_COMMAND_PORT, NUL) & STATUS_OUTPUT_FULL == 0 {
+                return bytes;
+            }
+            bytes.push(kbc.read_u8(KBC_DATA_PORT, NUL));
+        }
+    }
+
+    #[test]
+    fn test_controller_commands() {
+        let mut kbc = Kbc::new(&[VideoType::CGA]);
+
+        command(&mut kbc, KBC_CMD_SELF_TEST);
+        assert_ne!(kbc.read_u8(KBC_COMMAND_PORT, NUL) & STATUS_OUTPUT_FULL, 0);
+        assert_eq!(kbc.read_u8(KBC_DATA_PORT, NUL), KBC_SELF_TEST_OK);
+        assert_eq!(kbc.read_u8(KBC_COMMAND_PORT, NUL) & STATUS_OUTPUT_FULL, 0);
+
+        // Write the command byte and read it back. The system flag is reflected in the status register.
+        command(&mut kbc, KBC_CMD_WRITE_RAM);
+        data(&mut kbc, 0x45);
+        command(&mut kbc, KBC_CMD_READ_RAM);
+        assert_eq!(kbc.read_u8(KBC_DATA_PORT, NUL), 0x45);
+        assert_ne!(kbc.read_u8(KBC_COMMAND_PORT, NUL) & STATUS_SYSTEM_FLAG, 0);
+
+        command(&mut kbc, KBC_CMD_READ_INPUT);
+        assert_ne!(kbc.read_u8(KBC_DATA_PORT, NUL) & INPUT_COLOR_DISPLAY, 0);
+    }
+
+    #[test]
+    fn test_output_port() {
+        let mut kbc = Kbc::new(&[VideoType::MDA]);
+        assert!(!kbc.a20_gate());
+
+        command(&mut kbc, KBC_CMD_WRITE_OUTPUT);
+        data(&mut kbc, 0xDF);
+        assert!(kbc.a20_gate());
+        command(&mut kbc, KBC_CMD_READ_OUTPUT);
+        assert_ne!(kbc.read_u8(KBC_DATA_PORT, NUL) & OUTPUT_A20_GATE, 0);
+
+        command(&mut kbc, KBC_CMD_WRITE_OUTPUT);
+        data(&mut kbc, 0xDD);
+        assert!(!kbc.a20_gate());
+        assert!(!kbc.take_reset_request());
+
+        // Pulsing output bit 0 resets the CPU.
+        command(&mut kbc, 0xFE);
+        assert!(kbc.take_reset_request());
+        assert!(!kbc.take_reset_request());
+    }
+
+    #[test]
+    fn test_keyboard_protocol() {
+        let mut kbc = Kbc::new(&[VideoType::CGA]);
+        let mut keyboard = Keyboard::new(KeyboardType::ModelM, false);
+        keyboard.attach_at_controller();
+        let mut pic = pic::Pic::new();
+
+        data(&mut kbc, KB_CMD_RESET);
+        assert_eq!(drain(&mut kbc, &mut keyboard, &mut pic), [KB_RESP_ACK, KB_RESP_BAT_OK]);
+
+        data(&mut kbc, KB_CMD_SET_LEDS);
+        data(&mut kbc, KB_LED_NUM_LOCK | KB_LED_CAPS_LOCK);
+        assert_eq!(drain(&mut kbc, &mut keyboard, &mut pic), [KB_RESP_ACK, KB_RESP_ACK]);
+        assert_eq!(keyboard.get_leds(), KB_LED_NUM_LOCK | KB_LED_CAPS_LOCK);
+
+        // The keyboard reports set 2, which the controller translates to 0x41.
+        data(&mut kbc, KB_CMD_SCANCODE_SET);
+        data(&mut kbc, 0);
+        let response = drain(&mut kbc, &mut keyboard, &mut pic);
+        assert_eq!(response, [KB_RESP_ACK, KB_RESP_ACK, 0x41]);
+    }
+
+    #[test]
+    fn test_scancode_translation() {
+        let mut kbc = Kbc::new(&[VideoType::CGA]);
+        let mut keyboard = Keyboard::new(KeyboardType::ModelM, false);
+        keyboard.attach_at_controller();
+        let mut pic = pic::Pic::new();
+        let modifiers = KeyboardModifiers::default();
+
+        // Set 2 codes are translated to the set 1 codes of an XT keyboard.
+        keyboard.key_down(MartyKey::KeyA, &modifiers, None);
+        keyboard.key_up(MartyKey::KeyA);
+        assert_eq!(drain(&mut kbc, &mut keyboard, &mut pic), [0x1E, 0x9E]);
+
+        keyboard.key_down(MartyKey::ArrowUp, &modifiers, None);
+        keyboard.key_up(MartyKey::ArrowUp);
+        assert_eq!(drain(&mut kbc, &mut keyboard, &mut pic), [0xE0, 0x48, 0xE0, 0xC8]);
+
+        // With translation off, software sees the keyboard's set 2 codes.
+        command(&mut kbc, KBC_CMD_WRITE_RAM);
+        data(&mut kbc, CMD_BYTE_KBD_IRQ);
+        keyboard.key_down(MartyKey::KeyA, &modifiers, None);
+        keyboard.key_up(MartyKey::KeyA);
+        assert_eq!(drain(&mut kbc, &mut keyboard, &mut pic), [0x1C, 0xF0, 0x1C]);
+
+        // Nothing is delivered while the keyboard interface is disabled.
+        command(&mut kbc, KBC_CMD_DISABLE_KBD);
+        keyboard.key_down(MartyKey::KeyA, &modifiers, None);
+        assert!(drain(&mut kbc, &mut keyboard, &mut pic).is_empty());
+        command(&mut kbc, KBC_CMD_ENABLE_KBD);
+        assert_eq!(drain(&mut kbc, &mut keyboard, &mut pic), [0x1C]);
+    }
+}
@@ -28,6 +28,12 @@
 
     Implementation of various keyboards.
 
+    The Model F speaks the one-way XT protocol through the PPI. The Model M
+    is an AT-style keyboard: it accepts commands from the keyboard controller
+    and produces scancode set 2 by default. Internally, both keyboards
+    translate keys into set 1 scancodes, which the Model M converts to its
+    active scancode set as they are queued.
+
 */
 
 use anyhow::{bail, Result};
@@ -43,7 +49,11 @@ use strum::IntoEnumIterator;
 use serde_derive::Deserialize;
 use toml;
 
-use crate::{keys::MartyKey, machine::KeybufferEntry};
+use crate::{
+    keys::MartyKey,
+    machine::KeybufferEntry,
+    savestate::{SaveState, StateReader, StateWriter},
+};
 
 // Define the various types of keyboard we can emulate.
 #[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
@@ -106,6 +116,67 @@ pub enum TranslationMode {
     Scancode,
 }
 
+// Commands accepted by the AT keyboard.
+pub const KB_CMD_SET_LEDS: u8 = 0xED;
+pub const KB_CMD_ECHO: u8 = 0xEE;
+pub const KB_CMD_SCANCODE_SET: u8 = 0xF0;
+pub const KB_CMD_IDENTIFY: u8 = 0xF2;
+pub const KB_CMD_SET_TYPEMATIC: u8 = 0xF3;
+pub const KB_CMD_ENABLE: u8 = 0xF4;
+pub const KB_CMD_DEFAULT_DISABLE: u8 = 0xF5;
+pub const KB_CMD_SET_DEFAULT: u8 = 0xF6;
+pub const KB_CMD_RESET: u8 = 0xFF;
+
+// Responses sent by the AT keyboard.
+pub const KB_RESP_BAT_OK: u8 = 0xAA;
+pub const KB_RESP_ECHO: u8 = 0xEE;
+pub const KB_RESP_ACK: u8 = 0xFA;
+pub const KB_RESP_RESEND: u8 = 0xFE;
+
+pub const KB_LED_SCROLL_LOCK: u8 = 0b0000_0001;
+pub const KB_LED_NUM_LOCK: u8 = 0b0000_0010;
+pub const KB_LED_CAPS_LOCK: u8 = 0b0000_0100;
+
+/// Identification bytes returned by an MF2 keyboard.
+const KB_MF2_ID: [u8; 2] = [0xAB, 0x83];
+/// Size of the AT keyboard's internal buffer, in bytes.
+const KB_AT_BUFFER_SIZE: usize = 16;
+
+/// Translation performed by the 8042 keyboard controller from scancode set 2 to set 1, indexed by the
+/// set 2 code. Codes above 0x7F are passed through, except for the two handled in [scancode_set2_to_set1].
+pub const SCANCODE_SET2_TO_SET1: [u8; 128] = [
+    0xFF, 0x43, 0x41, 0x3F, 0x3D, 0x3B, 0x3C, 0x58, 0x64, 0x44, 0x42, 0x40, 0x3E, 0x0F, 0x29, 0x59, // 00
+    0x65, 0x38, 0x2A, 0x70, 0x1D, 0x10, 0x02, 0x5A, 0x66, 0x71, 0x2C, 0x1F, 0x1E, 0x11, 0x03, 0x5B, // 10
+    0x67, 0x2E, 0x2D, 0x20, 0x12, 0x05, 0x04, 0x5C, 0x68, 0x39, 0x2F, 0x21, 0x14, 0x13, 0x06, 0x5D, // 20
+    0x69, 0x31, 0x30, 0x23, 0x22, 0x15, 0x07, 0x5E, 0x6A, 0x72, 0x32, 0x24, 0x16, 0x08, 0x09, 0x5F, // 30
+    0x6B, 0x33, 0x25, 0x17, 0x18, 0x0B, 0x0A, 0x60, 0x6C, 0x34, 0x35, 0x26, 0x27, 0x19, 0x0C, 0x61, // 40
+    0x6D, 0x73, 0x28, 0x74, 0x1A, 0x0D, 0x62, 0x6E, 0x3A, 0x36, 0x1C, 0x1B, 0x75, 0x2B, 0x63, 0x76, // 50
+    0x55, 0x56, 0x77, 0x78, 0x79, 0x7A, 0x0E, 0x7B, 0x7C, 0x4F, 0x7D, 0x4B, 0x47, 0x7E, 0x7F, 0x6F, // 60
+    0x52, 0x53, 0x50, 0x4C, 0x4D, 0x48, 0x01, 0x45, 0x57, 0x4E, 0x51, 0x4A, 0x37, 0x49, 0x46, 0x54, // 70
+];
+
+/// Translate a single scancode set 2 make code to set 1.
+pub fn scancode_set2_to_set1(code: u8) -> u8 {
+    match code {
+        0x00..=0x7F => SCANCODE_SET2_TO_SET1[code as usize],
+        0x83 => 0x41, // F7
+        0x84 => 0x54, // Alt-SysRq
+        _ => code,
+    }
+}
+
+/// Translate a single scancode set 1 make code to set 2. This is the inverse of [scancode_set2_to_set1].
+pub fn scancode_set1_to_set2(code: u8) -> u8 {
+    match code {
+        0x41 => 0x83, // F7 has a set 2 code outside the table.
+        0x00..=0x7F => SCANCODE_SET2_TO_SET1
+            .iter()
+            .position(|&c| c == code)
+            .map_or(code, |pos| pos as u8),
+        _ => code,
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct KeyState {
     pressed: bool,
@@ -169,9 +240,13 @@ pub struct Keyboard {
     typematic_delay: f64, // Typematic repeat delay from initial keypress (ms)
     typematic_rate: f64,  // Typematic repeat rate (ms)
     kb_buffer_size: usize,
-    kb_buffer: Vec<u8>, // Keyboard buffer. Variable length depending on keyboard model.
+    kb_buffer: VecDeque<u8>, // Keyboard buffer. Variable length depending on keyboard model.
     kb_buffer_overflow: bool,
     keycode_mappings: Vec<KeycodeMapping>,
+    scanning: bool,              // AT keyboards can be told to stop scanning keys.
+    scancode_set: u8,            // Active scancode set of an AT keyboard.
+    leds: u8,                    // Lock LED state of an AT keyboard.
+    pending_command: Option<u8>, // AT keyboard command awaiting its data byte.
 }
 
 impl Default for Keyboard {
@@ -185,9 +260,13 @@ impl Default for Keyboard {
             typematic_delay: 500.0,
             typematic_rate: 100.0,
             kb_buffer_size: 1,
-            kb_buffer: Vec::new(),
+            kb_buffer: VecDeque::new(),
             kb_buffer_overflow: false,
             keycode_mappings: Vec::new(),
+            scanning: true,
+            scancode_set: 1,
+            leds: 0,
+            pending_command: None,
         }
     }
 }
@@ -208,6 +287,15 @@ impl Keyboard {
         kb
     }
 
+    /// Connect an AT keyboard to an 8042 keyboard controller. The keyboard then buffers its output
+    /// and powers on in scancode set 2. Without a controller it sends set 1 codes, as on an XT.
+    pub fn attach_at_controller(&mut self) {
+        if self.kb_type == KeyboardType::ModelM {
+            self.kb_buffer_size = KB_AT_BUFFER_SIZE;
+            self.scancode_set = 2;
+        }
+    }
+
     pub fn set_debug(&mut self, state: bool) {
         self.debug = state;
     }
@@ -239,10 +327,10 @@ impl Keyboard {
         let toml_mapping: KeyboardMappingFile = toml::from_str(&toml_mapping_str)?;
 
         match self.kb_type {
-            KeyboardType::ModelF => {
+            // The Model M's set 1 scancodes are a superset of the Model F's, so it shares its mappings.
+            KeyboardType::ModelF | KeyboardType::ModelM => {
                 self.keycode_mappings = toml_mapping.keyboard.modelf.keycode_mappings;
             }
-            _ => unimplemented!(),
         }
 
         Ok(())
@@ -338,114 +426,155 @@ impl Keyboard {
                 // It had two variants, an 83-key version without lock status lights
                 // and an 84-key version with an added 'sysreq' key.
 
-                let scancode = match key_code {
-                    // From Left to Right on IBM XT keyboard
-                    MartyKey::F1 => Some(0x3b),
-                    MartyKey::F2 => Some(0x3c),
-                    MartyKey::F3 => Some(0x3d),
-                    MartyKey::F4 => Some(0x3e),
-                    MartyKey::F5 => Some(0x3f),
-                    MartyKey::F6 => Some(0x40),
-                    MartyKey::F7 => Some(0x41),
-                    MartyKey::F8 => Some(0x42),
-                    MartyKey::F9 => Some(0x43),
-                    MartyKey::F10 => Some(0x44),
-                    MartyKey::Escape => Some(0x01),
-                    MartyKey::Tab => Some(0x0F),
-                    MartyKey::ControlLeft => Some(0x1D),
-                    MartyKey::ShiftLeft => Some(0x2A),
-                    MartyKey::AltLeft => Some(0x38),
+                let scancode = Keyboard::modelf_scancode(key_code);
+
+                if let Some(s) = scancode {
+                    //log::debug!("Converted key: {:?} to scancode: {:02X}", key_code, s);
+                    scancodes.push(s);
+                }
+            }
+            KeyboardType::ModelM => {
+                // The Model M is the 101/102-key enhanced keyboard. Keys added with the enhanced layout
+                // that duplicate an existing key send an 0xE0 prefix ahead of the original key's code.
+                let extended = match key_code {
                     MartyKey::ControlRight => Some(0x1D),
                     MartyKey::AltRight => Some(0x38),
-                    MartyKey::Digit1 => Some(0x02),
-                    MartyKey::Digit2 => Some(0x03),
-                    MartyKey::Digit3 => Some(0x04),
-                    MartyKey::Digit4 => Some(0x05),
-                    MartyKey::Digit5 => Some(0x06),
-                    MartyKey::Digit6 => Some(0x07),
-                    MartyKey::Digit7 => Some(0x08),
-                    MartyKey::Digit8 => Some(0x09),
-                    MartyKey::Digit9 => Some(0x0A),
-                    MartyKey::Digit0 => Some(0x0B),
-                    MartyKey::Minus => Some(0x0C),
-                    MartyKey::Equal => Some(0x0D),
-                    MartyKey::KeyA => Some(0x1E),
-                    MartyKey::KeyB => Some(0x30),
-                    MartyKey::KeyC => Some(0x2E),
-                    MartyKey::KeyD => Some(0x20),
-                    MartyKey::KeyE => Some(0x12),
-                    MartyKey::KeyF => Some(0x21),
-                    MartyKey::KeyG => Some(0x22),
-                    MartyKey::KeyH => Some(0x23),
-                    MartyKey::KeyI => Some(0x17),
-                    MartyKey::KeyJ => Some(0x24),
-                    MartyKey::KeyK => Some(0x25),
-                    MartyKey::KeyL => Some(0x26),
-                    MartyKey::KeyM => Some(0x32),
-                    MartyKey::KeyN => Some(0x31),
-                    MartyKey::KeyO => Some(0x18),
-                    MartyKey::KeyP => Some(0x19),
-                    MartyKey::KeyQ => Some(0x10),
-                    MartyKey::KeyR => Some(0x13),
-                    MartyKey::KeyS => Some(0x1F),
-                    MartyKey::KeyT => Some(0x14),
-                    MartyKey::KeyU => Some(0x16),
-                    MartyKey::KeyV => Some(0x2F),
-                    MartyKey::KeyW => Some(0x11),
-                    MartyKey::KeyX => Some(0x2D),
-                    MartyKey::KeyY => Some(0x15),
-                    MartyKey::KeyZ => Some(0x2C),
-                    MartyKey::Backslash => Some(0x2B),
-                    MartyKey::Space => Some(0x39),
-                    MartyKey::Backspace => Some(0x0E),
-                    MartyKey::BracketLeft => Some(0x1A),
-                    MartyKey::BracketRight => Some(0x1B),
-                    MartyKey::Semicolon => Some(0x27),
-                    MartyKey::Backquote => Some(0x29), // Grave
-                    MartyKey::Quote => Some(0x28),     // Apostrophe
-                    MartyKey::Comma => Some(0x33),
-                    MartyKey::Period => Some(0x34),
-                    MartyKey::Slash => Some(0x35),
-                    MartyKey::Enter => Some(0x1C), // Return
-                    MartyKey::ShiftRight => Some(0x36),
-                    MartyKey::CapsLock => Some(0x3A),    // 'Capital'?
-                    MartyKey::PrintScreen => Some(0x37), // 'Snapshot'ù
-                    MartyKey::Delete => Some(0x53),
-                    MartyKey::NumLock => Some(0x45),
-                    MartyKey::ScrollLock => Some(0x46),
-                    MartyKey::Numpad0 | MartyKey::Insert => Some(0x52),
-                    MartyKey::Numpad1 | MartyKey::End => Some(0x4F),
-                    MartyKey::Numpad2 | MartyKey::ArrowDown => Some(0x50),
-                    MartyKey::Numpad3 | MartyKey::PageDown => Some(0x51),
-                    MartyKey::Numpad4 | MartyKey::ArrowLeft => Some(0x4B),
-                    MartyKey::Numpad5 => Some(0x4C),
-                    MartyKey::Numpad6 | MartyKey::ArrowRight => Some(0x4D),
-                    MartyKey::Numpad7 | MartyKey::Home => Some(0x47),
-                    MartyKey::Numpad8 | MartyKey::ArrowUp => Some(0x48),
-                    MartyKey::Numpad9 | MartyKey::PageUp => Some(0x49),
-                    MartyKey::NumpadSubtract => Some(0x4A),
-                    MartyKey::NumpadAdd => Some(0x4E),
-                    MartyKey::NumpadDecimal => Some(0x53),
+                    MartyKey::NumpadDivide => Some(0x35),
                     MartyKey::NumpadEnter => Some(0x1C),
-                    MartyKey::NumpadDivide => None,      // Can't directly map to shift-7
-                    MartyKey::NumpadMultiply => None,    // Can't directly map to shift-8
-                    MartyKey::NumpadEqual => Some(0x0D), // Present on Mac
+                    MartyKey::PrintScreen => Some(0x37),
+                    MartyKey::Insert => Some(0x52),
+                    MartyKey::Delete => Some(0x53),
+                    MartyKey::Home => Some(0x47),
+                    MartyKey::End => Some(0x4F),
+                    MartyKey::PageUp => Some(0x49),
+                    MartyKey::PageDown => Some(0x51),
+                    MartyKey::ArrowUp => Some(0x48),
+                    MartyKey::ArrowDown => Some(0x50),
+                    MartyKey::ArrowLeft => Some(0x4B),
+                    MartyKey::ArrowRight => Some(0x4D),
                     _ => None,
                 };
 
-                if let Some(s) = scancode {
-                    //log::debug!("Converted key: {:?} to scancode: {:02X}", key_code, s);
-                    scancodes.push(s);
+                if let Some(s) = extended {
+                    scancodes.extend([0xE0, s]);
+                }
+                else {
+                    let scancode = match key_code {
+                        MartyKey::F11 => Some(0x57),
+                        MartyKey::F12 => Some(0x58),
+                        MartyKey::NumpadMultiply => Some(0x37),
+                        MartyKey::IntlBackslash => Some(0x56),
+                        _ => Keyboard::modelf_scancode(key_code),
+                    };
+
+                    if let Some(s) = scancode {
+                        scancodes.push(s);
+                    }
                 }
-            }
-            _ => {
-                unimplemented!();
             }
         }
 
         scancodes
     }
 
+    /// Return the scancode of a key on the Model F keyboard, if it has one.
+    fn modelf_scancode(key_code: MartyKey) -> Option<u8> {
+        match key_code {
+            // From Left to Right on IBM XT keyboard
+            MartyKey::F1 => Some(0x3b),
+            MartyKey::F2 => Some(0x3c),
+            MartyKey::F3 => Some(0x3d),
+            MartyKey::F4 => Some(0x3e),
+            MartyKey::F5 => Some(0x3f),
+            MartyKey::F6 => Some(0x40),
+            MartyKey::F7 => Some(0x41),
+            MartyKey::F8 => Some(0x42),
+            MartyKey::F9 => Some(0x43),
+            MartyKey::F10 => Some(0x44),
+            MartyKey::Escape => Some(0x01),
+            MartyKey::Tab => Some(0x0F),
+            MartyKey::ControlLeft => Some(0x1D),
+            MartyKey::ShiftLeft => Some(0x2A),
+            MartyKey::AltLeft => Some(0x38),
+            MartyKey::ControlRight => Some(0x1D),
+            MartyKey::AltRight => Some(0x38),
+            MartyKey::Digit1 => Some(0x02),
+            MartyKey::Digit2 => Some(0x03),
+            MartyKey::Digit3 => Some(0x04),
+            MartyKey::Digit4 => Some(0x05),
+            MartyKey::Digit5 => Some(0x06),
+            MartyKey::Digit6 => Some(0x07),
+            MartyKey::Digit7 => Some(0x08),
+            MartyKey::Digit8 => Some(0x09),
+            MartyKey::Digit9 => Some(0x0A),
+            MartyKey::Digit0 => Some(0x0B),
+            MartyKey::Minus => Some(0x0C),
+            MartyKey::Equal => Some(0x0D),
+            MartyKey::KeyA => Some(0x1E),
+            MartyKey::KeyB => Some(0x30),
+            MartyKey::KeyC => Some(0x2E),
+            MartyKey::KeyD => Some(0x20),
+            MartyKey::KeyE => Some(0x12),
+            MartyKey::KeyF => Some(0x21),
+            MartyKey::KeyG => Some(0x22),
+            MartyKey::KeyH => Some(0x23),
+            MartyKey::KeyI => Some(0x17),
+            MartyKey::KeyJ => Some(0x24),
+            MartyKey::KeyK => Some(0x25),
+            MartyKey::KeyL => Some(0x26),
+            MartyKey::KeyM => Some(0x32),
+            MartyKey::KeyN => Some(0x31),
+            MartyKey::KeyO => Some(0x18),
+            MartyKey::KeyP => Some(0x19),
+            MartyKey::KeyQ => Some(0x10),
+            MartyKey::KeyR => Some(0x13),
+            MartyKey::KeyS => Some(0x1F),
+            MartyKey::KeyT => Some(0x14),
+            MartyKey::KeyU => Some(0x16),
+            MartyKey::KeyV => Some(0x2F),
+            MartyKey::KeyW => Some(0x11),
+            MartyKey::KeyX => Some(0x2D),
+            MartyKey::KeyY => Some(0x15),
+            MartyKey::KeyZ => Some(0x2C),
+            MartyKey::Backslash => Some(0x2B),
+            MartyKey::Space => Some(0x39),
+            MartyKey::Backspace => Some(0x0E),
+            MartyKey::BracketLeft => Some(0x1A),
+            MartyKey::BracketRight => Some(0x1B),
+            MartyKey::Semicolon => Some(0x27),
+            MartyKey::Backquote => Some(0x29), // Grave
+            MartyKey::Quote => Some(0x28),     // Apostrophe
+            MartyKey::Comma => Some(0x33),
+            MartyKey::Period => Some(0x34),
+            MartyKey::Slash => Some(0x35),
+            MartyKey::Enter => Some(0x1C), // Return
+            MartyKey::ShiftRight => Some(0x36),
+            MartyKey::CapsLock => Some(0x3A),    // 'Capital'?
+            MartyKey::PrintScreen => Some(0x37), // 'Snapshot'ù
+            MartyKey::Delete => Some(0x53),
+            MartyKey::NumLock => Some(0x45),
+            MartyKey::ScrollLock => Some(0x46),
+            MartyKey::Numpad0 | MartyKey::Insert => Some(0x52),
+            MartyKey::Numpad1 | MartyKey::End => Some(0x4F),
+            MartyKey::Numpad2 | MartyKey::ArrowDown => Some(0x50),
+            MartyKey::Numpad3 | MartyKey::PageDown => Some(0x51),
+            MartyKey::Numpad4 | MartyKey::ArrowLeft => Some(0x4B),
+            MartyKey::Numpad5 => Some(0x4C),
+            MartyKey::Numpad6 | MartyKey::ArrowRight => Some(0x4D),
+            MartyKey::Numpad7 | MartyKey::Home => Some(0x47),
+            MartyKey::Numpad8 | MartyKey::ArrowUp => Some(0x48),
+            MartyKey::Numpad9 | MartyKey::PageUp => Some(0x49),
+            MartyKey::NumpadSubtract => Some(0x4A),
+            MartyKey::NumpadAdd => Some(0x4E),
+            MartyKey::NumpadDecimal => Some(0x53),
+            MartyKey::NumpadEnter => Some(0x1C),
+            MartyKey::NumpadDivide => None,      // Can't directly map to shift-7
+            MartyKey::NumpadMultiply => None,    // Can't directly map to shift-8
+            MartyKey::NumpadEqual => Some(0x0D), // Present on Mac
+            _ => None,
+        }
+    }
+
     /// Set the corresponding key to pressed.
     pub fn key_down(
         &mut self,
@@ -538,18 +667,30 @@ impl Keyboard {
 
     /// Send the corresponding scancodes to the keyboard buffer.
     pub fn send_scancodes(&mut self, keys: &[u8]) {
+        if !self.scanning {
+            return;
+        }
+
+        let keys = match self.scancode_set {
+            2 => Keyboard::set1_sequence_to_set2(keys),
+            _ => keys.to_vec(),
+        };
+
         if keys.len() > 0 {
             if self.kb_buffer_size > 1 {
                 // We have a keyboard buffer
-                if self.kb_buffer.len() + keys.len() >= self.kb_buffer_size {
+                if self.kb_buffer.len() + keys.len() > self.kb_buffer_size {
                     // KB overflow!
                     self.kb_buffer_overflow = true;
                 }
+                else {
+                    self.kb_buffer.extend(keys);
+                }
             }
             else if self.kb_buffer_size == 1 {
                 // No keyboard buffer (kb_buffer_size == 1). Just set one scancode.
                 self.kb_buffer.clear();
-                self.kb_buffer.push(keys[0]);
+                self.kb_buffer.push_back(keys[0]);
             }
             else {
                 panic!("invalid kb_buffer_size");
@@ -557,18 +698,137 @@ impl Keyboard {
         }
     }
 
+    /// Convert a sequence of set 1 scancodes to set 2. Set 2 signals a key release with an 0xF0 prefix
+    /// instead of the high bit.
+    fn set1_sequence_to_set2(keys: &[u8]) -> Vec<u8> {
+        let mut set2 = Vec::with_capacity(keys.len() * 2);
+        for &key in keys {
+            match key {
+                0xE0 | 0xE1 => set2.push(key),
+                _ if key & 0x80 != 0 => set2.extend([0xF0, scancode_set1_to_set2(key & 0x7F)]),
+                _ => set2.push(scancode_set1_to_set2(key)),
+            }
+        }
+        set2
+    }
+
     /// Read out a scancode from the keyboard or None if no key in buffer.
     pub fn recv_scancode(&mut self) -> Option<u8> {
         if self.kb_buffer_overflow {
             // Send the keyboard overflow scancode
             self.kb_buffer_overflow = false;
-            Some(0xFF)
+            match self.scancode_set {
+                2 => Some(0x00),
+                _ => Some(0xFF),
+            }
         }
         else {
-            self.kb_buffer.pop()
+            self.kb_buffer.pop_front()
+        }
+    }
+
+    /// Queue response bytes to an AT keyboard command. Responses are not subject to scancode set conversion.
+    fn send_response(&mut self, bytes: &[u8]) {
+        self.kb_buffer.extend(bytes);
+    }
+
+    /// Receive a command or data byte from the keyboard controller. Only AT keyboards accept commands;
+    /// responses are queued in the keyboard buffer ahead of any further scancodes.
+    pub fn send_command(&mut self, byte: u8) {
+        if self.kb_type != KeyboardType::ModelM {
+            log::warn!("Keyboard: {:?} ignored command: {:02X}", self.kb_type, byte);
+            return;
+        }
+
+        if let Some(command) = self.pending_command.take() {
+            self.handle_command_data(command, byte);
+            return;
+        }
+
+        log::debug!("Keyboard: Received command: {:02X}", byte);
+        match byte {
+            KB_CMD_SET_LEDS | KB_CMD_SET_TYPEMATIC | KB_CMD_SCANCODE_SET => {
+                self.pending_command = Some(byte);
+                self.send_response(&[KB_RESP_ACK]);
+            }
+            KB_CMD_ECHO => {
+                self.send_response(&[KB_RESP_ECHO]);
+            }
+            KB_CMD_IDENTIFY => {
+                self.send_response(&[KB_RESP_ACK]);
+                self.send_response(&KB_MF2_ID);
+            }
+            KB_CMD_ENABLE => {
+                self.kb_buffer.clear();
+                self.scanning = true;
+                self.send_response(&[KB_RESP_ACK]);
+            }
+            KB_CMD_DEFAULT_DISABLE => {
+                self.set_defaults();
+                self.scanning = false;
+                self.send_response(&[KB_RESP_ACK]);
+            }
+            KB_CMD_SET_DEFAULT => {
+                self.set_defaults();
+                self.send_response(&[KB_RESP_ACK]);
+            }
+            KB_CMD_RESET => {
+                self.set_defaults();
+                self.scanning = true;
+                self.send_response(&[KB_RESP_ACK, KB_RESP_BAT_OK]);
+            }
+            _ => {
+                log::warn!("Keyboard: Unhandled command: {:02X}", byte);
+                self.send_response(&[KB_RESP_RESEND]);
+            }
         }
     }
 
+    /// Handle the data byte following a command that takes a parameter.
+    fn handle_command_data(&mut self, command: u8, byte: u8) {
+        match command {
+            KB_CMD_SET_LEDS => {
+                self.leds = byte & (KB_LED_SCROLL_LOCK | KB_LED_NUM_LOCK | KB_LED_CAPS_LOCK);
+                self.send_response(&[KB_RESP_ACK]);
+            }
+            KB_CMD_SET_TYPEMATIC => {
+                // Bits 5-6 select the delay in units of 250ms. Bits 0-4 select the repeat period as
+                // (8 + A) * 2^B * 4.17ms, where A is bits 0-2 and B is bits 3-4.
+                let delay = ((byte >> 5) & 0x03) as f64 * 250.0 + 250.0;
+                let rate = (8 + (byte & 0x07)) as f64 * (1 << ((byte >> 3) & 0x03)) as f64 * 4.17;
+                self.set_typematic_params(None, Some(delay), Some(rate));
+                self.send_response(&[KB_RESP_ACK]);
+            }
+            KB_CMD_SCANCODE_SET => match byte {
+                0 => self.send_response(&[KB_RESP_ACK, self.scancode_set]),
+                1 | 2 => {
+                    self.scancode_set = byte;
+                    self.kb_buffer.clear();
+                    self.send_response(&[KB_RESP_ACK]);
+                }
+                _ => {
+                    log::warn!("Keyboard: Unsupported scancode set: {}", byte);
+                    self.send_response(&[KB_RESP_RESEND]);
+                }
+            },
+            _ => {}
+        }
+    }
+
+    /// Restore the power-on defaults of an AT keyboard.
+    fn set_defaults(&mut self) {
+        self.kb_buffer.clear();
+        self.kb_buffer_overflow = false;
+        self.pending_command = None;
+        self.scancode_set = 2;
+        self.leds = 0;
+    }
+
+    /// Return the state of the lock LEDs of an AT keyboard.
+    pub fn get_leds(&self) -> u8 {
+        self.leds
+    }
+
     pub fn translate_keydown(&self, key_code: MartyKey, modifiers: &KeyboardModifiers) -> TranslationType {
         let mut translation = TranslationType::Scancode(Vec::new());
         let mut got_translation = false;
@@ -664,8 +924,11 @@ impl Keyboard {
 
                 translation[0] = translation[0] | 0x80;
             }
-            _ => {
-                unimplemented!();
+            KeyboardType::ModelM => {
+                // Set 1 key up codes keep any 0xE0 prefix. Conversion to set 2 happens when the codes are queued.
+                for code in translation.iter_mut().filter(|code| **code != 0xE0) {
+                    *code |= 0x80;
+                }
             }
         }
     }
@@ -733,3 +996,34 @@ impl Keyboard {
         }
     }
 }
+
+/// Only the keyboard's internal buffer and AT command state are saved. Keys held on the host when a state is
+/// loaded are released, as they have no relation to the restored machine.
+impl SaveState for Keyboard {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.kb_buffer);
+        w.put(&self.kb_buffer_overflow);
+        w.put(&self.scanning);
+        w.put(&self.scancode_set);
+        w.put(&self.leds);
+        w.put(&self.pending_command);
+        w.put(&self.typematic_delay);
+        w.put(&self.typematic_rate);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.kb_buffer = r.get()?;
+        self.kb_buffer_overflow = r.get()?;
+        self.scanning = r.get()?;
+        self.scancode_set = r.get()?;
+        self.leds = r.get()?;
+        self.pending_command = r.get()?;
+        self.typematic_delay = r.get()?;
+        self.typematic_rate = r.get()?;
+        for key_state in self.kb_hash.values_mut() {
+            *key_state = KeyState::default();
+        }
+        self.keys_pressed.clear();
+        Ok(())
+    }
+}
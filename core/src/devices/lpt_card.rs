@@ -32,7 +32,12 @@
 
 use crate::{
     bus::{BusInterface, DeviceRunTimeUnit, IoDevice, NO_IO_BYTE},
-    devices::{lpt_port::ParallelPort, mda::MDACard},
+    devices::{
+        lpt_port::{ParallelDevice, ParallelPort},
+        mda::MDACard,
+    },
+    savestate::{SaveState, StateReader, StateWriter},
+    tracelogger::TraceLogger,
 };
 
 pub const LPT_DEFAULT_IO_BASE: u16 = 0x3BC;
@@ -53,12 +58,34 @@ impl Default for ParallelController {
 }
 
 impl ParallelController {
-    pub fn new(port_base: Option<u16>) -> Self {
+    pub fn new(port_base: Option<u16>, irq: Option<u16>) -> Self {
         ParallelController {
             lpt_port_base: port_base.unwrap_or(LPT_DEFAULT_IO_BASE),
-            ..Default::default()
+            lpt: ParallelPort::new(irq, TraceLogger::None),
         }
     }
+
+    pub fn port(&self) -> &ParallelPort {
+        &self.lpt
+    }
+
+    pub fn port_mut(&mut self) -> &mut ParallelPort {
+        &mut self.lpt
+    }
+
+    /// Plug a device into the card's parallel port.
+    pub fn attach(&mut self, device: ParallelDevice) {
+        self.lpt.attach(device);
+    }
+
+    pub fn irq(&self) -> u16 {
+        self.lpt.irq()
+    }
+
+    /// Run the card's parallel port. Returns true if the port raised its interrupt.
+    pub fn run(&mut self, us: f64) -> bool {
+        self.lpt.run(us)
+    }
 }
 
 impl IoDevice for ParallelController {
@@ -87,3 +114,13 @@ impl IoDevice for ParallelController {
         lpt_ports
     }
 }
+
+impl SaveState for ParallelController {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.lpt.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.lpt.load_state(r)
+    }
+}
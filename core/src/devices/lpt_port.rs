@@ -30,9 +30,16 @@
     implementation, and must be embedded into a card implementation that can
     decode the proper port address.
 
+    A device may be attached to the port. Devices see writes to the data and
+    control registers and may drive lines of the status register.
+
 */
 
-use crate::tracelogger::TraceLogger;
+use crate::{
+    devices::{covox::CovoxSpeechThing, disney_sound_source::DisneySoundSource, printer::Printer},
+    savestate::{SaveState, StateReader, StateWriter},
+    tracelogger::TraceLogger,
+};
 use modular_bitfield::{bitfield, prelude::*};
 
 pub const LPT_DEFAULT_IRQ: u16 = 7;
@@ -61,12 +68,113 @@ pub struct ParallelControl {
     pub unused2: B3,
 }
 
+/// A device plugged into a parallel port.
+pub enum ParallelDevice {
+    Covox(CovoxSpeechThing),
+    DisneySoundSource(DisneySoundSource),
+    Printer(Printer),
+}
+
+impl ParallelDevice {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParallelDevice::Covox(_) => "Covox Speech Thing",
+            ParallelDevice::DisneySoundSource(_) => "Disney Sound Source",
+            ParallelDevice::Printer(_) => "Printer",
+        }
+    }
+
+    /// Return the sample rate of the device's audio output, if it has any.
+    pub fn sample_rate(&self) -> Option<u32> {
+        match self {
+            ParallelDevice::Covox(covox) => Some(covox.sample_rate()),
+            ParallelDevice::DisneySoundSource(dss) => Some(dss.sample_rate()),
+            ParallelDevice::Printer(_) => None,
+        }
+    }
+
+    /// Return the samples produced since the last call to clear_samples().
+    pub fn samples(&self) -> &[f32] {
+        match self {
+            ParallelDevice::Covox(covox) => covox.samples(),
+            ParallelDevice::DisneySoundSource(dss) => dss.samples(),
+            ParallelDevice::Printer(_) => &[],
+        }
+    }
+
+    pub fn clear_samples(&mut self) {
+        match self {
+            ParallelDevice::Covox(covox) => covox.clear_samples(),
+            ParallelDevice::DisneySoundSource(dss) => dss.clear_samples(),
+            ParallelDevice::Printer(_) => {}
+        }
+    }
+
+    fn data_write(&mut self, data: u8) {
+        match self {
+            ParallelDevice::Covox(covox) => covox.data_write(data),
+            ParallelDevice::DisneySoundSource(dss) => dss.data_write(data),
+            ParallelDevice::Printer(printer) => printer.data_write(data),
+        }
+    }
+
+    fn control_write(&mut self, control: u8) {
+        match self {
+            ParallelDevice::Covox(_) => {}
+            ParallelDevice::DisneySoundSource(dss) => dss.control_write(control),
+            ParallelDevice::Printer(printer) => printer.control_write(control),
+        }
+    }
+
+    fn status(&self, status: u8) -> u8 {
+        match self {
+            ParallelDevice::Covox(_) => status,
+            ParallelDevice::DisneySoundSource(dss) => dss.status(status),
+            ParallelDevice::Printer(printer) => printer.status(status),
+        }
+    }
+
+    /// Run the device. Returns true if the device pulsed ACK.
+    fn run(&mut self, us: f64) -> bool {
+        match self {
+            ParallelDevice::Covox(covox) => {
+                covox.run(us);
+                false
+            }
+            ParallelDevice::DisneySoundSource(dss) => {
+                dss.run(us);
+                false
+            }
+            ParallelDevice::Printer(printer) => printer.run(us),
+        }
+    }
+}
+
+impl SaveState for ParallelDevice {
+    fn save_state(&self, w: &mut StateWriter) {
+        match self {
+            ParallelDevice::Covox(covox) => covox.save_state(w),
+            ParallelDevice::DisneySoundSource(dss) => dss.save_state(w),
+            ParallelDevice::Printer(printer) => printer.save_state(w),
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        match self {
+            ParallelDevice::Covox(covox) => covox.load_state(r),
+            ParallelDevice::DisneySoundSource(dss) => dss.load_state(r),
+            ParallelDevice::Printer(printer) => printer.load_state(r),
+        }
+    }
+}
+
 #[allow(dead_code)]
 pub struct ParallelPort {
     data: u8,
     status: ParallelStatus,
     control: ParallelControl,
     irq: u16,
+    device: Option<ParallelDevice>,
     trace_logger: TraceLogger,
 }
 
@@ -77,6 +185,7 @@ impl Default for ParallelPort {
             status: ParallelStatus::from_bytes([0]),
             control: ParallelControl::from_bytes([0]),
             irq: LPT_DEFAULT_IRQ,
+            device: None,
             trace_logger: TraceLogger::None,
         }
     }
@@ -91,6 +200,31 @@ impl ParallelPort {
         }
     }
 
+    /// Plug a device into the port, replacing any device already attached.
+    pub fn attach(&mut self, device: ParallelDevice) {
+        self.device = Some(device);
+    }
+
+    pub fn device(&self) -> Option<&ParallelDevice> {
+        self.device.as_ref()
+    }
+
+    pub fn device_mut(&mut self) -> Option<&mut ParallelDevice> {
+        self.device.as_mut()
+    }
+
+    pub fn irq(&self) -> u16 {
+        self.irq
+    }
+
+    /// Run the attached device. Returns true if the device pulsed ACK with interrupts enabled.
+    pub fn run(&mut self, us: f64) -> bool {
+        match &mut self.device {
+            Some(device) => device.run(us) && self.control.enable_irq() != 0,
+            None => false,
+        }
+    }
+
     pub fn port_write(&mut self, port: u16, data: u8) {
         match port & 0x03 {
             0 => {
@@ -123,6 +257,9 @@ impl ParallelPort {
 
     pub fn data_register_write(&mut self, data: u8) {
         self.data = data;
+        if let Some(device) = &mut self.device {
+            device.data_write(data);
+        }
         self.trace_logger
             .print(format!("LPT: Data register write: {:#02X}", data));
     }
@@ -135,6 +272,9 @@ impl ParallelPort {
 
     pub fn control_register_write(&mut self, data: u8) {
         self.control = ParallelControl::from_bytes([data]);
+        if let Some(device) = &mut self.device {
+            device.control_write(data);
+        }
         self.trace_logger
             .print(format!("LPT: Control register write: {:#02X}", data));
     }
@@ -146,7 +286,10 @@ impl ParallelPort {
     }
 
     pub fn status_register_read(&mut self) -> u8 {
-        let byte = self.status.into_bytes()[0];
+        let mut byte = self.status.into_bytes()[0];
+        if let Some(device) = &self.device {
+            byte = device.status(byte);
+        }
         self.trace_logger
             .print(format!("LPT: Status register read: {:#02X}", byte));
         byte
@@ -159,3 +302,25 @@ impl ParallelPort {
         byte
     }
 }
+
+/// The attached device is part of the machine configuration, so only its state is saved.
+impl SaveState for ParallelPort {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.data);
+        w.put(&self.status.into_bytes()[0]);
+        w.put(&self.control.into_bytes()[0]);
+        if let Some(device) = &self.device {
+            device.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.data = r.get()?;
+        self.status = ParallelStatus::from_bytes([r.get()?]);
+        self.control = ParallelControl::from_bytes([r.get()?]);
+        if let Some(device) = &mut self.device {
+            device.load_state(r)?;
+        }
+        Ok(())
+    }
+}
@@ -31,7 +31,11 @@
 
 */
 
-use crate::{device_traits::videocard::VideoCardStateEntry, tracelogger::TraceLogger};
+use crate::{
+    device_traits::videocard::VideoCardStateEntry,
+    savestate::{impl_state_enum, SaveState, StateReader, StateValue, StateWriter},
+    tracelogger::TraceLogger,
+};
 
 const CURSOR_LINE_MASK: u8 = 0b0000_1111;
 const CURSOR_ATTR_MASK: u8 = 0b0011_0000;
@@ -403,6 +407,14 @@ impl Crtc6845 {
         &self.status
     }
 
+    /// Latch the specified address into the light pen registers (R16 & R17), as on a rising edge
+    /// of the LPSTB input.
+    pub fn latch_lightpen(&mut self, addr: u16) {
+        self.lightpen_position = addr & 0x3FFF;
+        self.reg[16] = (self.lightpen_position >> 8) as u8;
+        self.reg[17] = (self.lightpen_position & 0xFF) as u8;
+    }
+
     fn update_start_address(&mut self) {
         self.start_address = (self.reg[12] as u16) << 8 | self.reg[13] as u16
     }
@@ -705,3 +717,110 @@ impl Crtc6845 {
         crtc_vec
     }
 }
+
+impl_state_enum!(CrtcRegister {
+    HorizontalTotal,
+    HorizontalDisplayed,
+    HorizontalSyncPosition,
+    SyncWidth,
+    VerticalTotal,
+    VerticalTotalAdjust,
+    VerticalDisplayed,
+    VerticalSync,
+    InterlaceMode,
+    MaximumScanlineAddress,
+    CursorStartLine,
+    CursorEndLine,
+    StartAddressH,
+    StartAddressL,
+    CursorAddressH,
+    CursorAddressL,
+    LightPenPositionH,
+    LightPenPositionL,
+});
+
+impl StateValue for CrtcStatus {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.put(&self.hblank);
+        w.put(&self.vblank);
+        w.put(&self.den);
+        w.put(&self.hborder);
+        w.put(&self.vborder);
+        w.put(&self.cursor);
+        w.put(&self.hsync);
+        w.put(&self.vsync);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            hblank:  r.get()?,
+            vblank:  r.get()?,
+            den:     r.get()?,
+            hborder: r.get()?,
+            vborder: r.get()?,
+            cursor:  r.get()?,
+            hsync:   r.get()?,
+            vsync:   r.get()?,
+        })
+    }
+}
+
+impl SaveState for Crtc6845 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.reg);
+        w.put(&self.reg_select);
+        w.put(&self.start_address);
+        w.put(&self.start_address_latch);
+        w.put(&self.lightpen_position);
+        w.put(&self.cursor_data);
+        w.put(&self.cursor_address);
+        w.put(&self.cursor_enabled);
+        w.put(&self.cursor_start_line);
+        w.put(&self.cursor_end_line);
+        w.put(&self.blink_state);
+        w.put(&self.cursor_blink_ct);
+        w.put(&self.cursor_blink_rate);
+        w.put(&self.hcc_c0);
+        w.put(&self.char_col);
+        w.put(&self.vlc_c9);
+        w.put(&self.vcc_c4);
+        w.put(&self.vsc_c3h);
+        w.put(&self.hsc_c3l);
+        w.put(&self.vtac_c5);
+        w.put(&self.in_vta);
+        w.put(&self.vma);
+        w.put(&self.vma_t);
+        w.put(&self.hsync_target);
+        w.put(&self.status);
+        w.put(&self.in_last_vblank_line);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.reg = r.get()?;
+        self.reg_select = r.get()?;
+        self.start_address = r.get()?;
+        self.start_address_latch = r.get()?;
+        self.lightpen_position = r.get()?;
+        self.cursor_data = r.get()?;
+        self.cursor_address = r.get()?;
+        self.cursor_enabled = r.get()?;
+        self.cursor_start_line = r.get()?;
+        self.cursor_end_line = r.get()?;
+        self.blink_state = r.get()?;
+        self.cursor_blink_ct = r.get()?;
+        self.cursor_blink_rate = r.get()?;
+        self.hcc_c0 = r.get()?;
+        self.char_col = r.get()?;
+        self.vlc_c9 = r.get()?;
+        self.vcc_c4 = r.get()?;
+        self.vsc_c3h = r.get()?;
+        self.hsc_c3l = r.get()?;
+        self.vtac_c5 = r.get()?;
+        self.in_vta = r.get()?;
+        self.vma = r.get()?;
+        self.vma_t = r.get()?;
+        self.hsync_target = r.get()?;
+        self.status = r.get()?;
+        self.in_last_vblank_line = r.get()?;
+        Ok(())
+    }
+}
@@ -57,7 +57,7 @@ impl MDACard {
     /// Since all pixels are the same we can draw 64 bits at a time.
     #[inline]
     pub fn draw_solid_hchar(&mut self, color: u8) {
-        for i in 0..MDA_CHAR_CLOCK as usize {
+        for i in 0..self.char_clock as usize {
             self.buf[self.back_buf][self.rba + i] = color;
         }
     }
@@ -152,6 +152,28 @@ impl MDACard {
         }
     }
 
+    /// Draw a character clock in HGC graphics mode. The word at the current CRTC address in the
+    /// bank selected by the row scan counter is drawn as 16 pixels, most significant bit first.
+    pub fn draw_gfx_mode_hchar(&mut self) {
+        if !self.mode.display_enable() {
+            self.draw_solid_hchar(0);
+            return;
+        }
+
+        let addr = self.page_base
+            + ((self.crtc.vlc() as usize & 0x03) << HGC_GFX_BANK_SHIFT)
+            + ((self.vma & HGC_GFX_WRAP) << 1);
+        let word = (self.mem[addr] as u16) << 8 | self.mem[addr + 1] as u16;
+
+        for hdot in 0..HGC_GFX_CHAR_CLOCK as usize {
+            self.buf[self.back_buf][self.rba + hdot] = match word & (0x8000 >> hdot) != 0 {
+                true => HGC_GFX_COLOR,
+                false => 0,
+            };
+        }
+        self.last_bit = word != 0;
+    }
+
     /*
     /// Draw an entire character row in high resolution text mode (8 pixels)
     pub fn draw_text_mode_hchar(&mut self) {
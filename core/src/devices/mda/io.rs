@@ -53,8 +53,11 @@ pub const CRTC_REGISTER_MASK: u16 = !0x007;
 
 pub const MDA_MODE_CONTROL_REGISTER: u16 = 0x3B8;
 pub const MDA_STATUS_REGISTER: u16 = 0x3BA;
-//pub const CGA_LIGHTPEN_LATCH_RESET: u16 = 0x3DB;
-//pub const CGA_LIGHTPEN_LATCH_SET: u16 = 0x3DC;
+// The HGC configuration switch shares its decode range with the LPT port.
+pub const HGC_CONFIG_REGISTER: u16 = 0x3BF;
+// Light pen latch ports, as decoded by the Hercules Graphics Card.
+pub const MDA_LIGHTPEN_LATCH_SET: u16 = 0x3B9;
+pub const MDA_LIGHTPEN_LATCH_RESET: u16 = 0x3BB;
 
 impl IoDevice for MDACard {
     fn read_u8(&mut self, port: u16, _delta: DeviceRunTimeUnit) -> u8 {
@@ -67,6 +70,10 @@ impl IoDevice for MDACard {
             // Read is from CRTC register.
             self.crtc.port_read(port)
         }
+        else if self.hgc && port == HGC_CONFIG_REGISTER {
+            // The configuration switch is write-only.
+            NO_IO_BYTE
+        }
         else if (port & LPT_PORT_MASK) == LPT_DEFAULT_IO_BASE {
             // Read is from LPT port.
             if let Some(lpt) = &mut self.lpt {
@@ -85,6 +92,14 @@ impl IoDevice for MDACard {
                     NO_IO_BYTE
                 }
                 MDA_STATUS_REGISTER => self.handle_status_register_read(),
+                MDA_LIGHTPEN_LATCH_SET if self.hgc => {
+                    self.set_lp_latch();
+                    NO_IO_BYTE
+                }
+                MDA_LIGHTPEN_LATCH_RESET if self.hgc => {
+                    self.clear_lp_latch();
+                    NO_IO_BYTE
+                }
                 _ => NO_IO_BYTE,
             }
         }
@@ -102,6 +117,9 @@ impl IoDevice for MDACard {
             // Write is to CRTC register.
             self.crtc.port_write(port, data);
         }
+        else if self.hgc && port == HGC_CONFIG_REGISTER {
+            self.handle_hgc_config_write(data);
+        }
         else if (port & LPT_PORT_MASK) == LPT_DEFAULT_IO_BASE {
             // Read is from LPT port.
             if let Some(lpt) = &mut self.lpt {
@@ -114,6 +132,8 @@ impl IoDevice for MDACard {
                 MDA_MODE_CONTROL_REGISTER => {
                     self.handle_mode_register(data);
                 }
+                MDA_LIGHTPEN_LATCH_SET if self.hgc => self.set_lp_latch(),
+                MDA_LIGHTPEN_LATCH_RESET if self.hgc => self.clear_lp_latch(),
                 _ => {}
             }
         }
@@ -133,6 +153,12 @@ impl IoDevice for MDACard {
             MDA_STATUS_REGISTER,
         ];
 
+        if self.hgc {
+            mda_ports.push(MDA_LIGHTPEN_LATCH_SET);
+            mda_ports.push(MDA_LIGHTPEN_LATCH_RESET);
+            mda_ports.push(HGC_CONFIG_REGISTER);
+        }
+
         if self.lpt.is_some() {
             log::debug!("Adding LPT ports to MDA port list");
             mda_ports.extend([self.lpt_port_base, self.lpt_port_base + 1, self.lpt_port_base + 2].iter());
@@ -140,3 +166,84 @@ impl IoDevice for MDACard {
         mda_ports
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bus::MemoryMappedDevice, tracelogger::TraceLogger};
+
+    #[test]
+    fn test_hgc_config_and_status() {
+        let mut hgc = MDACard::new_hgc(TraceLogger::None, ClockingMode::Dynamic, false, false);
+        let delta = DeviceRunTimeUnit::Microseconds(0.0);
+
+        // The second page is not decoded until enabled via the configuration switch.
+        hgc.mmio_write_u8(0xB8000, 0x55, 0);
+        assert_eq!(hgc.mmio_peek_u8(0xB8000), 0xFF);
+        hgc.write_u8(HGC_CONFIG_REGISTER, 0x03, None, delta);
+        hgc.mmio_write_u8(0xB8000, 0x55, 0);
+        assert_eq!(hgc.mmio_peek_u8(0xB8000), 0x55);
+        assert_eq!(hgc.mmio_peek_u8(0xB0000), 0x00);
+
+        // Bit 7 of the status register toggles with vertical retrace, which is how software
+        // tells a Hercules card from an MDA.
+        for (i, reg) in [97, 80, 82, 15, 25, 6, 25, 25, 2, 13].iter().enumerate() {
+            hgc.write_u8(CRTC_REGISTER_SELECT2, i as u8, None, delta);
+            hgc.write_u8(CRTC_REGISTER2, *reg, None, delta);
+        }
+        let mut seen = [false; 2];
+        for _ in 0..2000 {
+            hgc.run(DeviceRunTimeUnit::Microseconds(20.0), &mut None);
+            seen[(hgc.read_u8(MDA_STATUS_REGISTER, delta) >> 7) as usize] = true;
+        }
+        assert_eq!(seen, [true, true]);
+
+        let mut mda = MDACard::new(TraceLogger::None, ClockingMode::Dynamic, false, false);
+        assert_eq!(mda.mmio_peek_u8(0xB8000), mda.mmio_peek_u8(0xB0000));
+        assert!(!mda.port_list().contains(&HGC_CONFIG_REGISTER));
+        assert!(!mda.port_list().contains(&MDA_LIGHTPEN_LATCH_SET));
+        assert!(!mda.port_list().contains(&MDA_LIGHTPEN_LATCH_RESET));
+    }
+
+    #[test]
+    fn test_lightpen() {
+        let mut hgc = MDACard::new_hgc(TraceLogger::None, ClockingMode::Dynamic, false, false);
+        let delta = DeviceRunTimeUnit::Microseconds(0.0);
+        let lp_bits = STATUS_LIGHTPEN_TRIGGER_SET | STATUS_LIGHTPEN_SWITCH_STATUS;
+        for (i, reg) in [97, 80, 82, 15, 25, 6, 25, 25, 2, 13].iter().enumerate() {
+            hgc.write_u8(CRTC_REGISTER_SELECT2, i as u8, None, delta);
+            hgc.write_u8(CRTC_REGISTER2, *reg, None, delta);
+        }
+        hgc.write_u8(MDA_MODE_CONTROL_REGISTER, 0x08, None, delta);
+        let status = hgc.read_u8(MDA_STATUS_REGISTER, delta);
+        assert_eq!(status & lp_bits, 0b0000_0100);
+
+        // Hold the pen over the middle of row 5, column 10 with the switch pressed.
+        hgc.set_light_pen(
+            Some((MDA_APERTURE_CROPPED_X + 10 * 9 + 4, MDA_APERTURE_CROPPED_Y + 5 * 14 + 7)),
+            true,
+        );
+        hgc.write_u8(MDA_LIGHTPEN_LATCH_RESET, 0, None, delta);
+        for _ in 0..1000 {
+            hgc.run(DeviceRunTimeUnit::Microseconds(20.0), &mut None);
+        }
+        let status = hgc.read_u8(MDA_STATUS_REGISTER, delta);
+        assert_eq!(status & lp_bits, 0b0000_0010);
+
+        let mut lp_addr = 0;
+        for reg in [16, 17] {
+            hgc.write_u8(CRTC_REGISTER_SELECT2, reg, None, delta);
+            lp_addr = (lp_addr << 8) | hgc.read_u8(CRTC_REGISTER2, delta) as u16;
+        }
+        assert_eq!(lp_addr, 5 * 80 + 10);
+
+        // The latch holds its address until reset, and only triggers again once reset.
+        hgc.set_light_pen(None, false);
+        hgc.write_u8(MDA_LIGHTPEN_LATCH_RESET, 0, None, delta);
+        for _ in 0..1000 {
+            hgc.run(DeviceRunTimeUnit::Microseconds(20.0), &mut None);
+        }
+        let status = hgc.read_u8(MDA_STATUS_REGISTER, delta);
+        assert_eq!(status & lp_bits, 0b0000_0100);
+    }
+}
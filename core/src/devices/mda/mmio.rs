@@ -65,8 +65,7 @@ impl MemoryMappedDevice for MDACard {
             self.catch_up(DeviceRunTimeUnit::SystemTicks(cycles * 3));
         }*/
 
-        let a_offset = address & MDA_MEM_MASK;
-        if a_offset < MDA_MEM_SIZE {
+        if let Some(a_offset) = self.mem_offset(address) {
             // Do snow every other hchar
             if self.cycles & 0b1000 == 0 {
                 // Save bus parameters for snow emulation
@@ -84,39 +83,37 @@ impl MemoryMappedDevice for MDACard {
             (self.mem[a_offset], 0)
         }
         else {
-            // Read out of range, shouldn't happen...
+            // Read from a disabled HGC page.
             (0xFF, 0)
         }
     }
 
     fn mmio_peek_u8(&self, address: usize) -> u8 {
-        let a_offset = address & MDA_MEM_MASK;
-
-        self.mem[a_offset & 0x0FFF]
+        match self.mem_offset(address) {
+            Some(a_offset) => self.mem[a_offset],
+            None => 0xFF,
+        }
     }
 
     fn mmio_peek_u16(&self, address: usize) -> u16 {
-        let a_offset = address & MDA_MEM_MASK;
-
-        (self.mem[a_offset & 0x0FFF] as u16) << 8 | self.mem[(a_offset + 1) & 0x0FFF] as u16
+        (self.mmio_peek_u8(address) as u16) << 8 | self.mmio_peek_u8(address + 1) as u16
     }
 
     fn mmio_write_u8(&mut self, address: usize, byte: u8, _cycles: u32) -> u32 {
-        let a_offset = address & MDA_MEM_MASK;
-        if a_offset < MDA_MEM_SIZE {
+        if let Some(a_offset) = self.mem_offset(address) {
             // Save bus parameters for snow emulation
             self.last_bus_addr = a_offset;
             self.last_bus_value = byte;
             self.dirty_snow = true;
             self.snow_char = self.mem[a_offset];
 
-            self.mem[a_offset & 0x0FFF] = byte;
+            self.mem[a_offset] = byte;
 
             trace!(self, "WRITE_U8: {:04X}:{:02X}", a_offset, byte);
             0
         }
         else {
-            // Write out of range, shouldn't happen...
+            // Write to a disabled HGC page.
             0
         }
     }
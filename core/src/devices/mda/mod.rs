@@ -29,6 +29,12 @@
     Implementation of the IBM MDA card, built around the Motorola MC6845
     display controller.
 
+    The Hercules Graphics Card is implemented as a variant of the MDA. It adds
+    64K of memory in two 32K pages at B0000 and B8000, a 720x348 monochrome
+    graphics mode, a configuration switch register at 0x3BF that controls
+    access to graphics mode and the second page, and a vertical retrace bit
+    in the status register used to tell it apart from an MDA.
+
 */
 
 use super::mda::attr::*;
@@ -42,6 +48,7 @@ mod io;
 mod attr;
 mod draw;
 mod mmio;
+mod state;
 mod tablegen;
 mod videocard;
 
@@ -91,6 +98,11 @@ pub const MDA_MEM_APERTURE: usize = 0x8000;
 pub const MDA_MEM_SIZE: usize = 0x1000; // 4096 bytes
 pub const MDA_MEM_MASK: usize = 0x0FFF; // Applying this mask will implement memory mirror.
 
+pub const HGC_MEM_APERTURE: usize = 0x10000;
+pub const HGC_MEM_SIZE: usize = 0x10000; // 65536 bytes
+pub const HGC_MEM_MASK: usize = 0xFFFF;
+pub const HGC_PAGE_SIZE: usize = 0x8000;
+
 pub const MDA_REPEAT_COL_MASK: u8 = 0b1110_0000;
 pub const MDA_REPEAT_COL_VAL: u8 = 0b1100_0000;
 
@@ -148,7 +160,24 @@ const CURSOR_ATTR_MASK: u8 = 0b0110_0000;
 const CURSOR_ENABLE_MASK: u8 = 0b0010_0000;
 
 const STATUS_RETRACE: u8 = 0b0000_0001;
+const STATUS_LIGHTPEN_TRIGGER_SET: u8 = 0b0000_0010;
+const STATUS_LIGHTPEN_SWITCH_STATUS: u8 = 0b0000_0100;
 const STATUS_VIDEO: u8 = 0b0000_1000;
+// Low during vertical retrace. Bits 4-6 are 0 on a Hercules Graphics Card.
+const STATUS_HGC_NOT_VRETRACE: u8 = 0b1000_0000;
+
+const HGC_MODE_GRAPHICS: u8 = 0b0000_0010;
+const HGC_MODE_PAGE1: u8 = 0b1000_0000;
+const HGC_CONFIG_ALLOW_GRAPHICS: u8 = 0b0000_0001;
+const HGC_CONFIG_ENABLE_PAGE1: u8 = 0b0000_0010;
+
+// In graphics mode each character clock displays one word of memory as 16 pixels.
+const HGC_GFX_CHAR_CLOCK: u32 = 16;
+// The low two bits of the row scan counter select one of four 8K banks of the display page.
+const HGC_GFX_BANK_SHIFT: usize = 13;
+const HGC_GFX_WRAP: usize = 0x0FFF;
+// Graphics pixels are drawn in normal intensity.
+const HGC_GFX_COLOR: u8 = 2;
 
 // Include the standard 8x8 CGA font.
 // TODO: Support alternate font with thinner glyphs? It was normally not accessable except
@@ -326,6 +355,25 @@ const MDA_APERTURE_DESCS: [DisplayApertureDesc; 4] = [
 
 const MDA_DEFAULT_APERTURE: usize = 0;
 
+// HGC graphics mode uses a 16-dot character clock, which moves the left edge of the display.
+const HGC_GFX_APERTURE_CROPPED_W: u32 = 720;
+const HGC_GFX_APERTURE_CROPPED_H: u32 = 348;
+const HGC_GFX_APERTURE_CROPPED_X: u32 = 16;
+const HGC_GFX_APERTURE_CROPPED_Y: u32 = 6;
+
+const HGC_GFX_APERTURES: [DisplayAperture; 4] = [
+    DisplayAperture {
+        w: HGC_GFX_APERTURE_CROPPED_W,
+        h: HGC_GFX_APERTURE_CROPPED_H,
+        x: HGC_GFX_APERTURE_CROPPED_X,
+        y: HGC_GFX_APERTURE_CROPPED_Y,
+        debug: false,
+    },
+    MDA_APERTURES[1],
+    MDA_APERTURES[2],
+    MDA_APERTURES[3],
+];
+
 macro_rules! trace {
     ($self:ident, $($t:tt)*) => {{
         if $self.trace_logger.is_some() {
@@ -418,6 +466,10 @@ pub struct MDACard {
 
     crtc: Crtc6845,
 
+    hgc: bool,        // Whether this card is a Hercules Graphics Card
+    hgc_config: u8,   // HGC configuration switch register
+    page_base: usize, // Offset of the displayed page in video memory
+
     clock_divisor: u8, // Clock divisor is 1 in high resolution text mode, 2 in all other modes
     clock_mode:    ClockingMode,
     char_clock:    u32,
@@ -457,7 +509,7 @@ pub struct MDACard {
     ticks_accum: f64,
     clocks_accum: u32,
 
-    mem: Box<[u8; MDA_MEM_SIZE]>,
+    mem: Box<[u8]>,
 
     back_buf: usize,
     front_buf: usize,
@@ -471,8 +523,10 @@ pub struct MDACard {
     trace_logger:  TraceLogger,
     debug_counter: u64,
 
-    lightpen_latch: bool,
-    lightpen_addr:  usize,
+    lightpen_latch:  bool,
+    lightpen_addr:   usize,
+    lightpen_pos:    Option<(u32, u32)>,
+    lightpen_switch: bool,
 
     hblank_fn: Box<HBlankCallback>,
 
@@ -517,6 +571,7 @@ impl MdaDefault for DisplayExtents {
             field_w: MDA_XRES_MAX,
             field_h: MDA_YRES_MAX,
             row_stride: MDA_XRES_MAX as usize,
+            bytes_per_pixel: 1,
             double_scan: false,
             mode_byte: 0,
         }
@@ -574,6 +629,10 @@ impl Default for MDACard {
 
             crtc: Crtc6845::new(TraceLogger::None),
 
+            hgc: false,
+            hgc_config: 0,
+            page_base: 0,
+
             clock_divisor: DEFAULT_CLOCK_DIVISOR,
             clock_mode: ClockingMode::Character,
             char_clock: DEFAULT_CHAR_CLOCK,
@@ -610,7 +669,7 @@ impl Default for MDACard {
             clocks_accum: 0,
             pixel_clocks_owed: 0,
 
-            mem: vec![0; MDA_MEM_SIZE].into_boxed_slice().try_into().unwrap(),
+            mem: vec![0; MDA_MEM_SIZE].into_boxed_slice(),
 
             back_buf:  1,
             front_buf: 0,
@@ -632,8 +691,10 @@ impl Default for MDACard {
             trace_logger:  TraceLogger::None,
             debug_counter: 0,
 
-            lightpen_latch: false,
-            lightpen_addr:  0,
+            lightpen_latch:  false,
+            lightpen_addr:   0,
+            lightpen_pos:    None,
+            lightpen_switch: false,
 
             hblank_fn: Box::new(|| 10),
 
@@ -671,15 +732,28 @@ impl MDACard {
         mda
     }
 
+    /// Create a Hercules Graphics Card. The HGC powers on in text mode with graphics mode
+    /// and the second page disabled by the configuration switch register.
+    pub fn new_hgc(trace_logger: TraceLogger, clock_mode: ClockingMode, lpt: bool, video_frame_debug: bool) -> Self {
+        let mut hgc = Self::new(trace_logger, clock_mode, lpt, video_frame_debug);
+        hgc.hgc = true;
+        hgc.mem = vec![0; HGC_MEM_SIZE].into_boxed_slice();
+        hgc.update_hgc_mode();
+        hgc
+    }
+
     /// Reset CGA state (on reboot, for example)
     fn reset_private(&mut self) {
         let trace_logger = std::mem::replace(&mut self.trace_logger, TraceLogger::None);
         let hblank_fn = std::mem::replace(&mut self.hblank_fn, Box::new(|| 10));
         let lpt = std::mem::replace(&mut self.lpt, None);
+        let mem = vec![0; self.mem.len()].into_boxed_slice();
 
         // Save non-default values
         *self = Self {
             debug: self.debug,
+            hgc: self.hgc,
+            mem,
             clock_mode: self.clock_mode,
             enable_snow: self.enable_snow,
             frame_count: self.frame_count, // Keep frame count as to not confuse frontend
@@ -688,6 +762,9 @@ impl MDACard {
             hblank_fn,
             lpt,
             ..Self::default()
+        };
+        if self.hgc {
+            self.update_hgc_mode();
         }
     }
 
@@ -772,10 +849,10 @@ impl MDACard {
     /// until we are back in phase with the character clock.
     #[inline]
     fn calc_cycles_owed(&mut self) -> u32 {
-        if self.ticks_advanced % MDA_CHAR_CLOCK as u32 > 0 {
+        if self.ticks_advanced % self.char_clock > 0 {
             // We have advanced the CGA card out of phase with the character clock. Count
             // how many pixel clocks we need to tick by to be back in phase.
-            ((!self.cycles + 1) & 0x0F) as u32
+            (self.cycles.wrapping_neg() & 0x0F) as u32
         }
         else {
             0
@@ -784,7 +861,7 @@ impl MDACard {
 
     #[inline]
     fn calc_phase_offset(&mut self) -> u32 {
-        ((!self.cycles + 1) & 0x0F) as u32
+        (self.cycles.wrapping_neg() & 0x0F) as u32
     }
 
     fn set_lp_latch(&mut self) {
@@ -792,6 +869,7 @@ impl MDACard {
             // Low to high transition of light pen latch, set latch addr.
             log::debug!("Updating lightpen latch address");
             self.lightpen_addr = self.vma;
+            self.crtc.latch_lightpen(self.vma as u16);
         }
 
         self.lightpen_latch = true;
@@ -802,6 +880,17 @@ impl MDACard {
         self.lightpen_latch = false;
     }
 
+    /// Trigger the light pen latch if the light pen is positioned within the span of `width` pixels
+    /// about to be drawn at the current beam position. The pen only sees the beam in the display area.
+    #[inline]
+    fn check_lp_trigger(&mut self, width: u32) {
+        if let Some((pen_x, pen_y)) = self.lightpen_pos {
+            if self.crtc.den() && self.beam_y == pen_y && pen_x >= self.beam_x && pen_x < self.beam_x + width {
+                self.set_lp_latch();
+            }
+        }
+    }
+
     fn get_cursor_span(&self) -> (u8, u8) {
         self.crtc.cursor_extents()
     }
@@ -812,9 +901,62 @@ impl MDACard {
 
     /// Handle a write to the MDA mode register. Two of the bits are basically useless (0 & 1)
     /// leaving bit 3, which enables or disables video, and Bit 5, which controls blinking.
+    /// The HGC also uses bit 1 to select graphics mode and bit 7 to select the displayed page.
     fn handle_mode_register(&mut self, mode_byte: u8) {
         log::debug!("Write to MDA mode register: {:02X}", mode_byte);
         self.mode = MdaModeRegister::from_bytes([mode_byte]);
+        if self.hgc {
+            self.mode_byte = mode_byte;
+            self.update_hgc_mode();
+        }
+    }
+
+    /// Handle a write to the HGC configuration switch register. Bit 0 allows graphics mode to be
+    /// selected, and bit 1 maps the second page at B8000 and allows it to be displayed.
+    fn handle_hgc_config_write(&mut self, data: u8) {
+        log::debug!("Write to HGC configuration register: {:02X}", data);
+        self.hgc_config = data & (HGC_CONFIG_ALLOW_GRAPHICS | HGC_CONFIG_ENABLE_PAGE1);
+        self.update_hgc_mode();
+    }
+
+    /// Apply the HGC mode register bits permitted by the configuration switch register.
+    fn update_hgc_mode(&mut self) {
+        self.mode_graphics =
+            self.mode_byte & HGC_MODE_GRAPHICS != 0 && self.hgc_config & HGC_CONFIG_ALLOW_GRAPHICS != 0;
+        self.page_base = if self.mode_byte & HGC_MODE_PAGE1 != 0 && self.hgc_config & HGC_CONFIG_ENABLE_PAGE1 != 0 {
+            HGC_PAGE_SIZE
+        }
+        else {
+            0
+        };
+
+        if self.mode_graphics {
+            self.char_clock = HGC_GFX_CHAR_CLOCK;
+            self.display_mode = DisplayMode::ModeFMonoHiresGraphics;
+            self.extents.apertures = HGC_GFX_APERTURES.to_vec();
+        }
+        else {
+            self.char_clock = DEFAULT_CHAR_CLOCK;
+            self.display_mode = DisplayMode::Mode2TextBw80;
+            self.extents.apertures = MDA_APERTURES.to_vec();
+        }
+    }
+
+    /// Translate a bus address into an offset into video memory. Returns None if the address
+    /// falls in the HGC's second page while it is disabled.
+    fn mem_offset(&self, address: usize) -> Option<usize> {
+        if self.hgc {
+            let offset = address & HGC_MEM_MASK;
+            if offset >= HGC_PAGE_SIZE && self.hgc_config & HGC_CONFIG_ENABLE_PAGE1 == 0 {
+                None
+            }
+            else {
+                Some(offset)
+            }
+        }
+        else {
+            Some(address & MDA_MEM_MASK)
+        }
     }
 
     /// Handle a read from the MDA status register. This register has bits to indicate whether
@@ -827,7 +969,12 @@ impl MDACard {
         // to give the bit ample time to be detected toggling on and off.
 
         // Bit 3 is set when the horizontal retrace is active.
-        let mut byte = 0xF0;
+        // The HGC clears bits 4-6 and reports vertical retrace in bit 7, which is how software
+        // detects it.
+        let mut byte = if self.hgc { 0x00 } else { 0xF0 };
+        if self.hgc && !self.crtc.vblank() {
+            byte |= STATUS_HGC_NOT_VRETRACE;
+        }
 
         if self.crtc.hblank() {
             byte |= STATUS_RETRACE
@@ -837,6 +984,15 @@ impl MDACard {
             byte |= STATUS_VIDEO
         }
 
+        if self.lightpen_latch {
+            byte |= STATUS_LIGHTPEN_TRIGGER_SET;
+        }
+
+        // This bit is logically reversed, i.e., 0 is switch on
+        if !self.lightpen_switch {
+            byte |= STATUS_LIGHTPEN_SWITCH_STATUS;
+        }
+
         self.status_reads += 1;
 
         //trace_regs!(self);
@@ -893,7 +1049,7 @@ impl MDACard {
 
     /// Fetch the character and attribute for the specified CRTC address.
     fn fetch_char(&mut self, vma: u16) {
-        let addr = (vma as usize & MDA_TEXT_MODE_WRAP) << 1;
+        let addr = self.page_base + ((vma as usize & MDA_TEXT_MODE_WRAP) << 1);
         self.cur_char = self.mem[addr];
         self.cur_attr = self.mem[addr + 1];
 
@@ -985,14 +1141,19 @@ impl MDACard {
 
     /// Execute one high resolution character clock.
     pub fn tick_hchar(&mut self) {
-        self.cycles += MDA_CHAR_CLOCK as u64;
-        self.cur_screen_cycles += MDA_CHAR_CLOCK as u64;
+        self.cycles += self.char_clock as u64;
+        self.cur_screen_cycles += self.char_clock as u64;
         self.last_bit = false;
 
         // Only draw if render address is within display field
-        if self.rba < (MDA_MAX_CLOCK - MDA_CHAR_CLOCK as usize) {
+        if self.rba < (MDA_MAX_CLOCK - self.char_clock as usize) {
             if self.crtc.den() {
-                self.draw_text_mode_hchar_slow();
+                if self.mode_graphics {
+                    self.draw_gfx_mode_hchar();
+                }
+                else {
+                    self.draw_text_mode_hchar_slow();
+                }
             }
             else if self.crtc.hblank() {
                 // Draw hblank in debug color
@@ -1015,9 +1176,11 @@ impl MDACard {
             }
         }
 
+        self.check_lp_trigger(self.char_clock);
+
         // Update position to next pixel and character column.
-        self.beam_x += MDA_CHAR_CLOCK as u32;
-        self.rba += MDA_CHAR_CLOCK as usize;
+        self.beam_x += self.char_clock;
+        self.rba += self.char_clock as usize;
 
         // If we have reached the right edge of the 'monitor', return the raster position
         // to the left side of the screen.
@@ -1093,7 +1256,7 @@ impl MDACard {
         }
 
         // Done with the current character
-        if self.char_col == MDA_CHAR_CLOCK {
+        if self.char_col as u32 == self.char_clock {
             self.handle_crtc_tick();
         }
     }
@@ -1145,6 +1308,8 @@ impl MDACard {
             }
         }
 
+        self.check_lp_trigger(self.clock_divisor as u32);
+
         // Update position to next pixel and character column.
         self.beam_x += self.clock_divisor as u32;
         self.rba += self.clock_divisor as usize;
@@ -1193,8 +1358,8 @@ impl MDACard {
         }
 
         // Done with the current character
-        if self.char_col == MDA_CHAR_CLOCK {
-            if self.cycles % MDA_CHAR_CLOCK as u64 != 0 {
+        if self.char_col as u32 == self.char_clock {
+            if self.cycles % self.char_clock as u64 != 0 {
                 log::error!(
                     "tick(): calling tick_crtc_char but out of phase with cclock: cycles: {}",
                     self.cycles,
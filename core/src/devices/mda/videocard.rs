@@ -56,7 +56,12 @@ impl VideoCard for MDACard {
     }
 
     fn get_video_type(&self) -> VideoType {
-        VideoType::MDA
+        if self.hgc {
+            VideoType::HGC
+        }
+        else {
+            VideoType::MDA
+        }
     }
 
     fn get_render_mode(&self) -> RenderMode {
@@ -83,8 +88,8 @@ impl VideoCard for MDACard {
     fn get_display_size(&self) -> (u32, u32) {
         // MDA supports a single fixed 8x14 font. The size of the displayed window
         // is always HorizontalDisplayed * (VerticalDisplayed * (MaximumScanlineAddress + 1))
-        // (Excepting fancy CRTC tricks that delay vsync)
-        let width = self.crtc.reg[0] as u32 * MDA_CHAR_CLOCK as u32;
+        // (Excepting fancy CRTC tricks that delay vsync). HGC graphics mode uses a 16-dot character.
+        let width = self.crtc.reg[0] as u32 * self.char_clock;
         let height = self.crtc.reg[6] as u32 * (self.crtc.reg[9] as u32 + 1);
         (width, height)
     }
@@ -106,14 +111,20 @@ impl VideoCard for MDACard {
         Some((self.beam_x, self.beam_y))
     }
 
+    /// Set the position and switch state of the light pen.
+    fn set_light_pen(&mut self, pos: Option<(u32, u32)>, switch: bool) {
+        self.lightpen_pos = pos;
+        self.lightpen_switch = switch;
+    }
+
     /// Tick the MDA the specified number of video clock cycles.
     fn debug_tick(&mut self, ticks: u32) {
         match self.clock_mode {
             ClockingMode::Character | ClockingMode::Dynamic => {
-                let pixel_ticks = ticks % MDA_CHAR_CLOCK as u32;
-                let char_ticks = ticks / MDA_CHAR_CLOCK as u32;
+                let pixel_ticks = ticks % self.char_clock;
+                let char_ticks = ticks / self.char_clock;
 
-                assert_eq!(ticks, pixel_ticks + (char_ticks * 9));
+                assert_eq!(ticks, pixel_ticks + (char_ticks * self.char_clock));
 
                 for _ in 0..pixel_ticks {
                     self.tick();
@@ -143,6 +154,10 @@ impl VideoCard for MDACard {
         0
     }
 
+    fn get_palette(&self) -> Option<&[[u8; 4]]> {
+        None
+    }
+
     /// Get the current scanline being rendered.
     fn get_scanline(&self) -> u32 {
         self.scanline
@@ -293,6 +308,10 @@ impl VideoCard for MDACard {
         general_vec.push(("Adapter Type:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.get_video_type()))));
         general_vec.push(("Display Mode:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.get_display_mode()))));
         general_vec.push(("Video Enable:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.mode_enable))));
+        if self.hgc {
+            general_vec.push(("HGC Config:".to_string(), VideoCardStateEntry::String(format!("{:02X}", self.hgc_config))));
+            general_vec.push(("HGC Page:".to_string(), VideoCardStateEntry::String(format!("{}", self.page_base / HGC_PAGE_SIZE))));
+        }
         general_vec.push(("Clock Divisor:".to_string(), VideoCardStateEntry::String(format!("{}", self.clock_divisor))));
         general_vec.push(("Frame Count:".to_string(), VideoCardStateEntry::String(format!("{}", self.frame_count))));
         map.insert("General".to_string(), general_vec);
@@ -38,16 +38,29 @@ pub mod tga;
 #[cfg(feature = "vga")]
 pub mod vga;
 
+pub mod adlib;
+pub mod covox;
+pub mod disney_sound_source;
 pub mod dma;
 pub mod fdc;
 pub mod floppy_drive;
 pub mod hdc;
+pub mod kbc;
 pub mod keyboard;
 pub mod lpt_card;
 pub mod lpt_port;
 pub mod mc6845;
 pub mod mouse;
+pub mod opl2;
+pub mod pc_speaker;
+pub mod pcjr_port_b;
 pub mod pic;
 pub mod pit;
 pub mod ppi;
+pub mod printer;
 pub mod serial;
+pub mod sn76489;
+pub mod sound_blaster;
+
+#[cfg(test)]
+mod video_golden;
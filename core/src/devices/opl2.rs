@@ -0,0 +1,915 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::opl2.rs
+
+    Implement the Yamaha YM3812 (OPL2) FM synthesis chip, as used on the
+    AdLib and Sound Blaster cards.
+
+    The OPL2 has 18 operators arranged into 9 two-operator channels. Each
+    operator is a sine wave generator (with four selectable waveforms) whose
+    output is scaled by an ADSR envelope. In FM mode the first operator of a
+    channel modulates the phase of the second; in AM mode the outputs of the
+    two operators are added. The last three channels can instead be used as
+    five rhythm instruments.
+
+    Like the real chip, operator output is calculated in the log domain with
+    a quarter-wave log-sine table and an exponent table, and the envelope is
+    a 9-bit attenuation in 0.1875dB steps.
+
+    The chip runs at 3.579545MHz and produces one sample every 72 clocks, at
+    about 49.7KHz. The two timers count in 80us and 320us steps and are used
+    by software to detect the card. CSM speech synthesis mode is not
+    implemented.
+
+*/
+
+use std::f64::consts::PI;
+
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateWriter};
+
+pub const OPL2_CLOCK: f64 = 157.5 / 44.0;
+pub const OPL2_CLOCK_DIVISOR: f64 = 72.0;
+pub const OPL2_CHANNELS: usize = 9;
+pub const OPL2_OPERATORS: usize = OPL2_CHANNELS * 2;
+
+const TIMER1_US: f64 = 80.0;
+const TIMER2_US: f64 = 320.0;
+
+const STATUS_IRQ: u8 = 0x80;
+const STATUS_TIMER1: u8 = 0x40;
+const STATUS_TIMER2: u8 = 0x20;
+// The unused low bits of the status register read as 0b110 on the OPL2, which is how software
+// tells it apart from the OPL3.
+const STATUS_OPL2: u8 = 0x06;
+
+const MAX_ATTENUATION: u16 = 0x1FF;
+// A log-domain level at which an operator's output is silent.
+const LOG_SILENCE: u32 = 0x1000;
+
+/// Frequency multipliers, doubled. A multiplier of 0 is one half.
+const MULT_TABLE: [u32; 16] = [1, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 20, 24, 24, 30, 30];
+/// Key scale level attenuation by the top 4 bits of the F-number.
+const KSL_TABLE: [i32; 16] = [0, 32, 40, 45, 48, 51, 53, 55, 56, 58, 59, 60, 61, 62, 63, 64];
+/// Key scale level shift for 0, 3, 1.5 and 6 dB/octave.
+const KSL_SHIFT: [u32; 4] = [8, 1, 2, 0];
+/// Envelope increments for each rate, over an 8-step cycle. Rows 0-3 are the fractional parts
+/// of rates 0-12, rows 4-7 rate 13, rows 8-11 rate 14 and row 12 rate 15.
+const EG_INC: [[u16; 8]; 13] = [
+    [0, 1, 0, 1, 0, 1, 0, 1],
+    [0, 1, 0, 1, 1, 1, 0, 1],
+    [0, 1, 1, 1, 0, 1, 1, 1],
+    [0, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 1, 1, 1, 1, 1],
+    [1, 1, 1, 2, 1, 1, 1, 2],
+    [1, 2, 1, 2, 1, 2, 1, 2],
+    [1, 2, 2, 2, 1, 2, 2, 2],
+    [2, 2, 2, 2, 2, 2, 2, 2],
+    [2, 2, 2, 4, 2, 2, 2, 4],
+    [2, 4, 2, 4, 2, 4, 2, 4],
+    [2, 4, 4, 4, 2, 4, 4, 4],
+    [4, 4, 4, 4, 4, 4, 4, 4],
+];
+
+/// Number of samples between steps of the vibrato LFO (8 steps, ~6.1Hz).
+const VIBRATO_STEP: u32 = 1024;
+/// Number of samples between steps of the tremolo LFO (210 steps, ~3.7Hz).
+const TREMOLO_STEP: u32 = 64;
+const TREMOLO_STEPS: u8 = 210;
+
+// Rhythm mode operators.
+const OP_HIHAT: usize = 14;
+const OP_SNARE: usize = 15;
+const OP_TOM: usize = 16;
+const OP_CYMBAL: usize = 17;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+enum EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    #[default]
+    Off,
+}
+
+impl_state_enum!(EnvelopeState {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off
+});
+
+// Key-on sources for an operator. A rhythm instrument can be keyed independently of its channel.
+const KEY_NORMAL: u8 = 0x01;
+const KEY_RHYTHM: u8 = 0x02;
+
+#[derive(Copy, Clone, Default)]
+struct Operator {
+    tremolo: bool,
+    vibrato: bool,
+    sustain_hold: bool,
+    ksr: bool,
+    mult: u8,
+    ksl: u8,
+    tl: u8,
+    ar: u8,
+    dr: u8,
+    sl: u8,
+    rr: u8,
+    waveform: u8,
+
+    key: u8,
+    phase: u32,
+    env: u16,
+    env_state: EnvelopeState,
+    out: i32,
+    prev_out: i32,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key_on: bool,
+    feedback: u8,
+    additive: bool,
+}
+
+pub struct Opl2 {
+    address: u8,
+    waveform_enable: bool,
+    note_select: bool,
+    tremolo_deep: bool,
+    vibrato_deep: bool,
+    rhythm: u8,
+    ops: [Operator; OPL2_OPERATORS],
+    channels: [Channel; OPL2_CHANNELS],
+
+    timer1_reload: u8,
+    timer2_reload: u8,
+    timer1_counter: u16,
+    timer2_counter: u16,
+    timer1_running: bool,
+    timer2_running: bool,
+    timer1_masked: bool,
+    timer2_masked: bool,
+    timer1_us: f64,
+    timer2_us: f64,
+    status: u8,
+
+    eg_counter: u32,
+    sample_counter: u32,
+    vibrato_pos: u8,
+    tremolo_pos: u8,
+    noise: u32,
+
+    log_sin: [u16; 256],
+    exp: [u16; 256],
+
+    sample_accum: f64,
+    samples: Vec<f32>,
+}
+
+impl Default for Opl2 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Opl2 {
+    pub fn new() -> Self {
+        let mut log_sin = [0; 256];
+        let mut exp = [0; 256];
+        for i in 0..256 {
+            let sin = ((i as f64 + 0.5) * PI / 512.0).sin();
+            log_sin[i] = (-sin.log2() * 256.0).round() as u16;
+            exp[i] = (2.0f64.powf((255 - i) as f64 / 256.0) * 1024.0).round() as u16;
+        }
+
+        let mut ops = [Operator::default(); OPL2_OPERATORS];
+        for op in ops.iter_mut() {
+            op.env = MAX_ATTENUATION;
+        }
+
+        Self {
+            address: 0,
+            waveform_enable: false,
+            note_select: false,
+            tremolo_deep: false,
+            vibrato_deep: false,
+            rhythm: 0,
+            ops,
+            channels: [Channel::default(); OPL2_CHANNELS],
+            timer1_reload: 0,
+            timer2_reload: 0,
+            timer1_counter: 0,
+            timer2_counter: 0,
+            timer1_running: false,
+            timer2_running: false,
+            timer1_masked: false,
+            timer2_masked: false,
+            timer1_us: 0.0,
+            timer2_us: 0.0,
+            status: 0,
+            eg_counter: 0,
+            sample_counter: 0,
+            vibrato_pos: 0,
+            tremolo_pos: 0,
+            noise: 1,
+            log_sin,
+            exp,
+            sample_accum: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    /// The rate of the samples produced by the chip, in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        (OPL2_CLOCK * 1_000_000.0 / OPL2_CLOCK_DIVISOR).round() as u32
+    }
+
+    /// Return the samples produced since the last call to clear_samples().
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn read_status(&self) -> u8 {
+        let mut status = self.status | STATUS_OPL2;
+        if self.status & (STATUS_TIMER1 | STATUS_TIMER2) != 0 {
+            status |= STATUS_IRQ;
+        }
+        status
+    }
+
+    /// Return whether the chip is asserting its interrupt line.
+    pub fn irq(&self) -> bool {
+        self.status & (STATUS_TIMER1 | STATUS_TIMER2) != 0
+    }
+
+    pub fn write_address(&mut self, address: u8) {
+        self.address = address;
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        self.write_register(self.address, data);
+    }
+
+    pub fn write_register(&mut self, reg: u8, data: u8) {
+        match reg {
+            0x01 => self.waveform_enable = data & 0x20 != 0,
+            0x02 => self.timer1_reload = data,
+            0x03 => self.timer2_reload = data,
+            0x04 => {
+                if data & 0x80 != 0 {
+                    // Reset the timer flags. The other bits are ignored.
+                    self.status = 0;
+                    return;
+                }
+                self.timer1_masked = data & 0x40 != 0;
+                self.timer2_masked = data & 0x20 != 0;
+
+                let timer1_start = data & 0x01 != 0;
+                if timer1_start && !self.timer1_running {
+                    self.timer1_counter = self.timer1_reload as u16;
+                    self.timer1_us = 0.0;
+                }
+                self.timer1_running = timer1_start;
+
+                let timer2_start = data & 0x02 != 0;
+                if timer2_start && !self.timer2_running {
+                    self.timer2_counter = self.timer2_reload as u16;
+                    self.timer2_us = 0.0;
+                }
+                self.timer2_running = timer2_start;
+            }
+            0x08 => self.note_select = data & 0x40 != 0,
+            0x20..=0x35 => {
+                if let Some(op) = Self::operator_index(reg) {
+                    let op = &mut self.ops[op];
+                    op.tremolo = data & 0x80 != 0;
+                    op.vibrato = data & 0x40 != 0;
+                    op.sustain_hold = data & 0x20 != 0;
+                    op.ksr = data & 0x10 != 0;
+                    op.mult = data & 0x0F;
+                }
+            }
+            0x40..=0x55 => {
+                if let Some(op) = Self::operator_index(reg) {
+                    let op = &mut self.ops[op];
+                    op.ksl = data >> 6;
+                    op.tl = data & 0x3F;
+                }
+            }
+            0x60..=0x75 => {
+                if let Some(op) = Self::operator_index(reg) {
+                    let op = &mut self.ops[op];
+                    op.ar = data >> 4;
+                    op.dr = data & 0x0F;
+                }
+            }
+            0x80..=0x95 => {
+                if let Some(op) = Self::operator_index(reg) {
+                    let op = &mut self.ops[op];
+                    op.sl = data >> 4;
+                    op.rr = data & 0x0F;
+                }
+            }
+            0xA0..=0xA8 => {
+                let ch = &mut self.channels[(reg & 0x0F) as usize];
+                ch.fnum = (ch.fnum & 0x300) | data as u16;
+            }
+            0xB0..=0xB8 => {
+                let c = (reg & 0x0F) as usize;
+                let ch = &mut self.channels[c];
+                ch.fnum = (ch.fnum & 0x0FF) | ((data as u16 & 0x03) << 8);
+                ch.block = (data >> 2) & 0x07;
+                ch.key_on = data & 0x20 != 0;
+                let key_on = ch.key_on;
+                self.set_key(c * 2, KEY_NORMAL, key_on);
+                self.set_key(c * 2 + 1, KEY_NORMAL, key_on);
+            }
+            0xBD => {
+                self.tremolo_deep = data & 0x80 != 0;
+                self.vibrato_deep = data & 0x40 != 0;
+                // Bit 5 enables rhythm mode, bits 0-4 key the rhythm instruments.
+                self.rhythm = if data & 0x20 != 0 { data & 0x3F } else { 0 };
+                let rhythm = self.rhythm;
+                self.set_key(12, KEY_RHYTHM, rhythm & 0x10 != 0);
+                self.set_key(13, KEY_RHYTHM, rhythm & 0x10 != 0);
+                self.set_key(OP_SNARE, KEY_RHYTHM, rhythm & 0x08 != 0);
+                self.set_key(OP_TOM, KEY_RHYTHM, rhythm & 0x04 != 0);
+                self.set_key(OP_CYMBAL, KEY_RHYTHM, rhythm & 0x02 != 0);
+                self.set_key(OP_HIHAT, KEY_RHYTHM, rhythm & 0x01 != 0);
+            }
+            0xC0..=0xC8 => {
+                let ch = &mut self.channels[(reg & 0x0F) as usize];
+                ch.feedback = (data >> 1) & 0x07;
+                ch.additive = data & 0x01 != 0;
+            }
+            0xE0..=0xF5 => {
+                if let Some(op) = Self::operator_index(reg) {
+                    self.ops[op].waveform = data & 0x03;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Convert the operator offset in an operator register to an operator index. Operators are
+    /// numbered so that channel n owns operators 2n and 2n + 1.
+    fn operator_index(reg: u8) -> Option<usize> {
+        let offset = (reg & 0x1F) as usize;
+        let group = offset / 8;
+        let slot = offset % 8;
+        if group > 2 || slot > 5 {
+            return None;
+        }
+        Some((group * 3 + slot % 3) * 2 + slot / 3)
+    }
+
+    fn set_key(&mut self, op: usize, source: u8, on: bool) {
+        let ksv = self.key_scale(op / 2);
+        let op = &mut self.ops[op];
+        let was_on = op.key != 0;
+        if on {
+            op.key |= source;
+        }
+        else {
+            op.key &= !source;
+        }
+
+        if !was_on && op.key != 0 {
+            op.phase = 0;
+            op.env_state = EnvelopeState::Attack;
+            if Self::effective_rate(op, op.ar, ksv) >= 62 {
+                op.env = 0;
+                op.env_state = EnvelopeState::Decay;
+            }
+        }
+        else if was_on && op.key == 0 && op.env_state != EnvelopeState::Off {
+            op.env_state = EnvelopeState::Release;
+        }
+    }
+
+    /// Return the key scale value of a channel, used to scale envelope rates with pitch.
+    fn key_scale(&self, ch: usize) -> u8 {
+        let ch = &self.channels[ch];
+        let bit = (if self.note_select { ch.fnum >> 8 } else { ch.fnum >> 9 }) & 0x01;
+        (ch.block << 1) | bit as u8
+    }
+
+    fn effective_rate(op: &Operator, rate: u8, ksv: u8) -> u8 {
+        if rate == 0 {
+            return 0;
+        }
+        let ks = if op.ksr { ksv } else { ksv >> 2 };
+        (rate * 4 + ks).min(63)
+    }
+
+    /// Return the envelope increment for the specified effective rate at the current envelope
+    /// counter, or None if the envelope does not step on this sample.
+    fn envelope_increment(&self, rate: u8) -> Option<u16> {
+        if rate == 0 {
+            return None;
+        }
+        let (row, shift) = match rate >> 2 {
+            r @ 0..=12 => ((rate & 0x03) as usize, 12 - r as u32),
+            13 => (4 + (rate & 0x03) as usize, 0),
+            14 => (8 + (rate & 0x03) as usize, 0),
+            _ => (12, 0),
+        };
+        if self.eg_counter & ((1 << shift) - 1) != 0 {
+            return None;
+        }
+        Some(EG_INC[row][((self.eg_counter >> shift) & 0x07) as usize])
+    }
+
+    fn advance_envelope(&mut self, i: usize) {
+        let ksv = self.key_scale(i / 2);
+        let op = self.ops[i];
+        let mut env = op.env;
+        let mut state = op.env_state;
+
+        match state {
+            EnvelopeState::Attack => {
+                if let Some(inc) = self.envelope_increment(Self::effective_rate(&op, op.ar, ksv)) {
+                    let step = ((env as u32 + 1) * inc as u32 + 7) >> 3;
+                    env = env.saturating_sub(step as u16);
+                    if env == 0 {
+                        state = EnvelopeState::Decay;
+                    }
+                }
+            }
+            EnvelopeState::Decay => {
+                if let Some(inc) = self.envelope_increment(Self::effective_rate(&op, op.dr, ksv)) {
+                    env += inc;
+                }
+                let sustain_level = if op.sl == 0x0F { 0x1F0 } else { (op.sl as u16) << 4 };
+                if env >= sustain_level {
+                    state = EnvelopeState::Sustain;
+                }
+            }
+            EnvelopeState::Sustain => {
+                // Percussive envelopes continue to decay at the release rate.
+                if !op.sustain_hold {
+                    if let Some(inc) = self.envelope_increment(Self::effective_rate(&op, op.rr, ksv)) {
+                        env += inc;
+                    }
+                }
+            }
+            EnvelopeState::Release => {
+                if let Some(inc) = self.envelope_increment(Self::effective_rate(&op, op.rr, ksv)) {
+                    env += inc;
+                }
+            }
+            EnvelopeState::Off => {}
+        }
+
+        if env >= MAX_ATTENUATION {
+            env = MAX_ATTENUATION;
+            if state == EnvelopeState::Release || state == EnvelopeState::Sustain {
+                state = EnvelopeState::Off;
+            }
+        }
+        self.ops[i].env = env;
+        self.ops[i].env_state = state;
+    }
+
+    /// Return the total attenuation of an operator: envelope, total level, key scaling and tremolo.
+    fn attenuation(&self, i: usize) -> u16 {
+        let op = &self.ops[i];
+        let ch = &self.channels[i / 2];
+
+        let mut ksl = (KSL_TABLE[(ch.fnum >> 6) as usize] << 2) - ((8 - ch.block as i32) << 5);
+        if ksl < 0 {
+            ksl = 0;
+        }
+        let ksl = (ksl as u32 >> KSL_SHIFT[op.ksl as usize]) as u16;
+
+        let tremolo = if op.tremolo {
+            let pos = self.tremolo_pos as u16;
+            let level = if pos < 105 { pos } else { TREMOLO_STEPS as u16 - pos };
+            level >> if self.tremolo_deep { 2 } else { 4 }
+        }
+        else {
+            0
+        };
+
+        (op.env + ((op.tl as u16) << 2) + ksl + tremolo).min(MAX_ATTENUATION)
+    }
+
+    fn phase_increment(&self, i: usize) -> u32 {
+        let op = &self.ops[i];
+        let ch = &self.channels[i / 2];
+        let mut fnum = ch.fnum as i32;
+
+        if op.vibrato {
+            let pos = self.vibrato_pos;
+            let mut range = (fnum >> 7) & 0x07;
+            if pos & 0x03 == 0 {
+                range = 0;
+            }
+            else if pos & 0x01 != 0 {
+                range >>= 1;
+            }
+            if !self.vibrato_deep {
+                range >>= 1;
+            }
+            if pos & 0x04 != 0 {
+                range = -range;
+            }
+            fnum += range;
+        }
+
+        let base = ((fnum as u32) << ch.block) >> 1;
+        (base * MULT_TABLE[op.mult as usize]) >> 1
+    }
+
+    /// Return the log-domain level and sign of a sine wave at the specified 10-bit phase.
+    fn log_sine(&self, phase: u16) -> (u32, bool) {
+        let mut index = phase & 0xFF;
+        if phase & 0x100 != 0 {
+            index ^= 0xFF;
+        }
+        (self.log_sin[index as usize] as u32, phase & 0x200 != 0)
+    }
+
+    /// Calculate the output of an operator at a 10-bit phase, with the specified attenuation.
+    fn operator_output(&self, i: usize, phase: u16, attenuation: u16) -> i32 {
+        let phase = phase & 0x3FF;
+        let waveform = if self.waveform_enable { self.ops[i].waveform } else { 0 };
+
+        let (log, negative) = match waveform {
+            0 => self.log_sine(phase),
+            1 if phase & 0x200 != 0 => (LOG_SILENCE, false),
+            1 => self.log_sine(phase),
+            2 => (self.log_sine(phase & 0x1FF).0, false),
+            _ if phase & 0x100 != 0 => (LOG_SILENCE, false),
+            _ => (self.log_sin[(phase & 0xFF) as usize] as u32, false),
+        };
+
+        let level = log + ((attenuation as u32) << 3);
+        if level >= 0x1FFF {
+            return 0;
+        }
+        let out = ((self.exp[(level & 0xFF) as usize] as i32) << 1) >> (level >> 8);
+        if negative {
+            -out
+        }
+        else {
+            out
+        }
+    }
+
+    /// Calculate an operator with a phase offset and record its output.
+    fn run_operator(&mut self, i: usize, modulation: i32) -> i32 {
+        let phase = ((self.ops[i].phase >> 9) as i32 + modulation) as u16;
+        let out = self.operator_output(i, phase, self.attenuation(i));
+        self.ops[i].prev_out = self.ops[i].out;
+        self.ops[i].out = out;
+        out
+    }
+
+    /// Calculate an operator whose phase is replaced, for the rhythm instruments.
+    fn run_operator_phase(&mut self, i: usize, phase: u16) -> i32 {
+        let out = self.operator_output(i, phase, self.attenuation(i));
+        self.ops[i].prev_out = self.ops[i].out;
+        self.ops[i].out = out;
+        out
+    }
+
+    fn run_channel(&mut self, c: usize) -> i32 {
+        let (op1, op2) = (c * 2, c * 2 + 1);
+        let feedback = self.channels[c].feedback;
+        let modulation = if feedback != 0 {
+            (self.ops[op1].out + self.ops[op1].prev_out) >> (9 - feedback)
+        }
+        else {
+            0
+        };
+
+        let out1 = self.run_operator(op1, modulation);
+        if self.channels[c].additive {
+            out1 + self.run_operator(op2, 0)
+        }
+        else {
+            self.run_operator(op2, out1)
+        }
+    }
+
+    fn run_rhythm(&mut self) -> i32 {
+        // Bass drum: a two operator channel, except that in AM mode only the carrier is output.
+        let mut out = if self.channels[6].additive {
+            self.run_channel(6) - self.ops[12].out
+        }
+        else {
+            self.run_channel(6)
+        };
+
+        // The hi-hat, snare and cymbal derive their phase from the hi-hat and cymbal phase
+        // generators and the noise generator.
+        let noise = (self.noise & 0x01) as u16;
+        let hihat = ((self.ops[OP_HIHAT].phase >> 9) & 0x3FF) as u16;
+        let cymbal = ((self.ops[OP_CYMBAL].phase >> 9) & 0x3FF) as u16;
+        let bit = |phase: u16, n: u16| (phase >> n) & 0x01;
+
+        let xor = (bit(hihat, 2) ^ bit(hihat, 7)) | (bit(hihat, 3) ^ bit(cymbal, 5)) | (bit(cymbal, 3) ^ bit(cymbal, 5));
+
+        let hihat_phase = (xor << 9) | if xor ^ noise != 0 { 0xD0 } else { 0x34 };
+        out += self.run_operator_phase(OP_HIHAT, hihat_phase);
+
+        let snare_phase = (bit(hihat, 8) << 9) | ((bit(hihat, 8) ^ noise) << 8);
+        out += self.run_operator_phase(OP_SNARE, snare_phase);
+
+        out += self.run_operator(OP_TOM, 0);
+
+        let cymbal_phase = (xor << 9) | 0x80;
+        out += self.run_operator_phase(OP_CYMBAL, cymbal_phase);
+
+        // Rhythm instruments are output at twice the level of the melodic channels.
+        out * 2
+    }
+
+    fn generate_sample(&mut self) -> f32 {
+        let mut out = 0;
+        let melodic = if self.rhythm & 0x20 != 0 { 6 } else { OPL2_CHANNELS };
+        for c in 0..melodic {
+            out += self.run_channel(c);
+        }
+        if melodic < OPL2_CHANNELS {
+            out += self.run_rhythm();
+        }
+
+        // Advance the phase and envelope generators.
+        for i in 0..OPL2_OPERATORS {
+            let increment = self.phase_increment(i);
+            self.ops[i].phase = self.ops[i].phase.wrapping_add(increment);
+            self.advance_envelope(i);
+        }
+        self.eg_counter = self.eg_counter.wrapping_add(1);
+
+        // Advance the LFOs and the noise generator.
+        self.sample_counter = self.sample_counter.wrapping_add(1);
+        if self.sample_counter % VIBRATO_STEP == 0 {
+            self.vibrato_pos = (self.vibrato_pos + 1) & 0x07;
+        }
+        if self.sample_counter % TREMOLO_STEP == 0 {
+            self.tremolo_pos = (self.tremolo_pos + 1) % TREMOLO_STEPS;
+        }
+        let noise_bit = ((self.noise >> 14) ^ self.noise) & 0x01;
+        self.noise = (self.noise >> 1) | (noise_bit << 22);
+
+        out.clamp(i16::MIN as i32, i16::MAX as i32) as f32 / 32768.0
+    }
+
+    fn run_timers(&mut self, us: f64) {
+        if self.timer1_running {
+            self.timer1_us += us;
+            while self.timer1_us >= TIMER1_US {
+                self.timer1_us -= TIMER1_US;
+                self.timer1_counter += 1;
+                if self.timer1_counter > 0xFF {
+                    self.timer1_counter = self.timer1_reload as u16;
+                    if !self.timer1_masked {
+                        self.status |= STATUS_TIMER1;
+                    }
+                }
+            }
+        }
+        if self.timer2_running {
+            self.timer2_us += us;
+            while self.timer2_us >= TIMER2_US {
+                self.timer2_us -= TIMER2_US;
+                self.timer2_counter += 1;
+                if self.timer2_counter > 0xFF {
+                    self.timer2_counter = self.timer2_reload as u16;
+                    if !self.timer2_masked {
+                        self.status |= STATUS_TIMER2;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Run the chip for the specified number of microseconds, producing samples.
+    pub fn run(&mut self, us: f64) {
+        self.run_timers(us);
+
+        self.sample_accum += us * OPL2_CLOCK / OPL2_CLOCK_DIVISOR;
+        while self.sample_accum >= 1.0 {
+            self.sample_accum -= 1.0;
+            let sample = self.generate_sample();
+            self.samples.push(sample);
+        }
+    }
+}
+
+/// Samples not yet collected by the machine are discarded.
+impl SaveState for Opl2 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.address);
+        w.put(&self.waveform_enable);
+        w.put(&self.note_select);
+        w.put(&self.tremolo_deep);
+        w.put(&self.vibrato_deep);
+        w.put(&self.rhythm);
+        for op in self.ops.iter() {
+            w.put(&op.tremolo);
+            w.put(&op.vibrato);
+            w.put(&op.sustain_hold);
+            w.put(&op.ksr);
+            w.put(&op.mult);
+            w.put(&op.ksl);
+            w.put(&op.tl);
+            w.put(&op.ar);
+            w.put(&op.dr);
+            w.put(&op.sl);
+            w.put(&op.rr);
+            w.put(&op.waveform);
+            w.put(&op.key);
+            w.put(&op.phase);
+            w.put(&op.env);
+            w.put(&op.env_state);
+            w.put(&op.out);
+            w.put(&op.prev_out);
+        }
+        for ch in self.channels.iter() {
+            w.put(&ch.fnum);
+            w.put(&ch.block);
+            w.put(&ch.key_on);
+            w.put(&ch.feedback);
+            w.put(&ch.additive);
+        }
+        w.put(&self.timer1_reload);
+        w.put(&self.timer2_reload);
+        w.put(&self.timer1_counter);
+        w.put(&self.timer2_counter);
+        w.put(&self.timer1_running);
+        w.put(&self.timer2_running);
+        w.put(&self.timer1_masked);
+        w.put(&self.timer2_masked);
+        w.put(&self.timer1_us);
+        w.put(&self.timer2_us);
+        w.put(&self.status);
+        w.put(&self.eg_counter);
+        w.put(&self.sample_counter);
+        w.put(&self.vibrato_pos);
+        w.put(&self.tremolo_pos);
+        w.put(&self.noise);
+        w.put(&self.sample_accum);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.address = r.get()?;
+        self.waveform_enable = r.get()?;
+        self.note_select = r.get()?;
+        self.tremolo_deep = r.get()?;
+        self.vibrato_deep = r.get()?;
+        self.rhythm = r.get()?;
+        for op in self.ops.iter_mut() {
+            op.tremolo = r.get()?;
+            op.vibrato = r.get()?;
+            op.sustain_hold = r.get()?;
+            op.ksr = r.get()?;
+            op.mult = r.get()?;
+            op.ksl = r.get()?;
+            op.tl = r.get()?;
+            op.ar = r.get()?;
+            op.dr = r.get()?;
+            op.sl = r.get()?;
+            op.rr = r.get()?;
+            op.waveform = r.get()?;
+            op.key = r.get()?;
+            op.phase = r.get()?;
+            op.env = r.get()?;
+            op.env_state = r.get()?;
+            op.out = r.get()?;
+            op.prev_out = r.get()?;
+        }
+        for ch in self.channels.iter_mut() {
+            ch.fnum = r.get()?;
+            ch.block = r.get()?;
+            ch.key_on = r.get()?;
+            ch.feedback = r.get()?;
+            ch.additive = r.get()?;
+        }
+        self.timer1_reload = r.get()?;
+        self.timer2_reload = r.get()?;
+        self.timer1_counter = r.get()?;
+        self.timer2_counter = r.get()?;
+        self.timer1_running = r.get()?;
+        self.timer2_running = r.get()?;
+        self.timer1_masked = r.get()?;
+        self.timer2_masked = r.get()?;
+        self.timer1_us = r.get()?;
+        self.timer2_us = r.get()?;
+        self.status = r.get()?;
+        self.eg_counter = r.get()?;
+        self.sample_counter = r.get()?;
+        self.vibrato_pos = r.get()?;
+        self.tremolo_pos = r.get()?;
+        self.noise = r.get()?;
+        self.sample_accum = r.get()?;
+        self.samples.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timer_detection() {
+        // The detection sequence from the AdLib programming guide.
+        let mut opl = Opl2::new();
+        opl.write_register(0x04, 0x60);
+        opl.write_register(0x04, 0x80);
+        assert_eq!(opl.read_status() & 0xE0, 0x00);
+
+        opl.write_register(0x02, 0xFF);
+        opl.write_register(0x04, 0x21);
+        opl.run(79.0);
+        assert_eq!(opl.read_status() & 0xE0, 0x00);
+        opl.run(2.0);
+        assert_eq!(opl.read_status() & 0xE0, 0xC0);
+
+        opl.write_register(0x04, 0x60);
+        opl.write_register(0x04, 0x80);
+        assert_eq!(opl.read_status() & 0xE0, 0x00);
+    }
+
+    #[test]
+    fn test_operator_index() {
+        assert_eq!(Opl2::operator_index(0x20), Some(0));
+        assert_eq!(Opl2::operator_index(0x23), Some(1));
+        assert_eq!(Opl2::operator_index(0x28), Some(6));
+        assert_eq!(Opl2::operator_index(0x35), Some(17));
+        assert_eq!(Opl2::operator_index(0x26), None);
+    }
+
+    #[test]
+    fn test_tone() {
+        let mut opl = Opl2::new();
+        // Channel 0: silent modulator, full volume carrier with instant attack and full sustain.
+        opl.write_register(0x20, 0x21);
+        opl.write_register(0x40, 0x3F);
+        opl.write_register(0x23, 0x21);
+        opl.write_register(0x43, 0x00);
+        opl.write_register(0x63, 0xF0);
+        opl.write_register(0x83, 0x0F);
+        // F-number 0x241 in block 4 is ~440Hz.
+        opl.write_register(0xA0, 0x41);
+        opl.write_register(0xB0, 0x32);
+
+        opl.run(1_000_000.0);
+        let samples = opl.samples();
+        assert!((samples.len() as i64 - opl.sample_rate() as i64).abs() <= 1);
+
+        let crossings = samples
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+        let freq = crossings as f64 / 2.0;
+        let expected = 0x241 as f64 * opl.sample_rate() as f64 * 2.0f64.powi(4 - 20);
+        assert!((freq - expected).abs() < 2.0, "frequency {} expected {}", freq, expected);
+
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!(peak > 0.12, "peak {}", peak);
+
+        // Key off, and the carrier releases to silence.
+        opl.clear_samples();
+        opl.write_register(0xB0, 0x12);
+        opl.run(100_000.0);
+        assert_eq!(*opl.samples().last().unwrap(), 0.0);
+    }
+}
@@ -0,0 +1,308 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::pc_speaker.rs
+
+    Convert the PC speaker output (PIT channel 2, gated by the PPI) into
+    audio samples. The speaker line is sampled once per PIT tick, at about
+    1.19MHz, and must be filtered down to the output sample rate.
+
+    Two filters are available:
+
+    Average     - Average the ticks in each output sample. This is a box
+                  filter, which passes much of the energy above the Nyquist
+                  frequency and aliases badly on high frequency PWM effects.
+    BandLimited - Each transition of the speaker line is rendered as a
+                  band-limited step (BLEP) at its sub-sample position, using
+                  a windowed-sinc impulse that is integrated to form the
+                  output. This is a true low-pass filter with a cutoff just
+                  below the Nyquist frequency.
+
+*/
+
+use std::f64::consts::PI;
+
+use serde::Deserialize;
+
+/// Length of the band-limited impulse, in output samples. Determines the steepness of the filter
+/// and the latency of the output (half this length).
+const BLEP_TAPS: usize = 32;
+/// Number of sub-sample positions at which the impulse is precomputed.
+const BLEP_PHASES: usize = 64;
+/// Cutoff frequency of the filter, as a fraction of the output sample rate. Leaves room for the
+/// transition band of the window below the Nyquist frequency.
+const BLEP_CUTOFF: f64 = 0.40;
+
+#[derive(Copy, Clone, Debug, Default, Deserialize, PartialEq)]
+pub enum SpeakerFilter {
+    #[default]
+    Average,
+    BandLimited,
+}
+
+/// Box filter: each output sample is the average of the ticks it spans.
+struct AverageFilter {
+    ticks_per_sample: f64,
+    fractional_part: f64,
+    next_sample_size: usize,
+    sum: u32,
+    ticks: usize,
+}
+
+impl AverageFilter {
+    fn new(ticks_per_sample: f64) -> Self {
+        Self {
+            ticks_per_sample,
+            fractional_part: ticks_per_sample.fract(),
+            next_sample_size: ticks_per_sample.trunc() as usize,
+            sum: 0,
+            ticks: 0,
+        }
+    }
+
+    fn push_tick(&mut self, level: u8, out: &mut Vec<f32>) {
+        self.sum += level as u32;
+        self.ticks += 1;
+
+        if self.ticks >= self.next_sample_size {
+            out.push(self.sum as f32 / self.ticks as f32);
+            self.sum = 0;
+            self.ticks = 0;
+
+            // Calculate size of next audio sample in ticks by carrying over fractional part
+            let next_sample_f = self.ticks_per_sample + self.fractional_part;
+            self.next_sample_size = next_sample_f as usize;
+            self.fractional_part = next_sample_f.fract();
+        }
+    }
+}
+
+/// Band-limited step synthesis. Each transition adds a windowed-sinc impulse, scaled by the size
+/// of the step, into an accumulator spanning the next BLEP_TAPS output samples. Output samples
+/// are the running sum (integral) of the accumulator, so each impulse becomes a smooth step.
+struct BlepFilter {
+    /// Output samples per tick.
+    sample_step: f64,
+    /// Position of the current tick within the current output sample, from 0.0 to 1.0.
+    position: f64,
+    level: u8,
+    kernel: Vec<[f64; BLEP_TAPS]>,
+    accumulator: [f64; BLEP_TAPS],
+    integrator: f64,
+}
+
+impl BlepFilter {
+    fn new(ticks_per_sample: f64) -> Self {
+        Self {
+            sample_step: 1.0 / ticks_per_sample,
+            position: 0.0,
+            level: 0,
+            kernel: Self::make_kernel(),
+            accumulator: [0.0; BLEP_TAPS],
+            integrator: 0.0,
+        }
+    }
+
+    /// Build the table of impulses, one per sub-sample phase. Each is a Blackman-windowed sinc
+    /// centered between taps BLEP_TAPS/2 - 1 and BLEP_TAPS/2, normalized to sum to exactly 1 so
+    /// that the integrated step settles at the full step height.
+    fn make_kernel() -> Vec<[f64; BLEP_TAPS]> {
+        let half = BLEP_TAPS as f64 / 2.0;
+        (0..BLEP_PHASES)
+            .map(|phase| {
+                let offset = phase as f64 / BLEP_PHASES as f64;
+                let mut taps = [0.0; BLEP_TAPS];
+                for (i, tap) in taps.iter_mut().enumerate() {
+                    let x = i as f64 - (half - 1.0) - offset;
+                    let sinc = if x == 0.0 {
+                        2.0 * BLEP_CUTOFF
+                    }
+                    else {
+                        (2.0 * PI * BLEP_CUTOFF * x).sin() / (PI * x)
+                    };
+                    let w = (x + half) / BLEP_TAPS as f64;
+                    let window = 0.42 - 0.5 * (2.0 * PI * w).cos() + 0.08 * (4.0 * PI * w).cos();
+                    *tap = sinc * window;
+                }
+                let sum: f64 = taps.iter().sum();
+                taps.iter_mut().for_each(|tap| *tap /= sum);
+                taps
+            })
+            .collect()
+    }
+
+    fn push_tick(&mut self, level: u8, out: &mut Vec<f32>) {
+        if level != self.level {
+            let delta = level as f64 - self.level as f64;
+            let phase = ((self.position * BLEP_PHASES as f64) as usize).min(BLEP_PHASES - 1);
+            for (acc, tap) in self.accumulator.iter_mut().zip(self.kernel[phase].iter()) {
+                *acc += delta * tap;
+            }
+            self.level = level;
+        }
+
+        self.position += self.sample_step;
+        while self.position >= 1.0 {
+            self.position -= 1.0;
+            self.integrator += self.accumulator[0];
+            self.accumulator.copy_within(1.., 0);
+            self.accumulator[BLEP_TAPS - 1] = 0.0;
+            out.push(self.integrator as f32);
+        }
+    }
+}
+
+/// Produces speaker samples at the output rate from the per-tick speaker level, with the
+/// selected filter. Output samples range from 0.0 to 1.0.
+pub struct PcSpeaker {
+    filter: SpeakerFilter,
+    ticks_per_sample: f64,
+    average: AverageFilter,
+    band_limited: BlepFilter,
+}
+
+impl PcSpeaker {
+    pub fn new(tick_rate: f64, sample_rate: u32, filter: SpeakerFilter) -> Self {
+        let ticks_per_sample = tick_rate / sample_rate as f64;
+        Self {
+            filter,
+            ticks_per_sample,
+            average: AverageFilter::new(ticks_per_sample),
+            band_limited: BlepFilter::new(ticks_per_sample),
+        }
+    }
+
+    pub fn filter(&self) -> SpeakerFilter {
+        self.filter
+    }
+
+    /// Select the filter. The state of the newly selected filter is reset.
+    pub fn set_filter(&mut self, filter: SpeakerFilter) {
+        self.filter = filter;
+        self.average = AverageFilter::new(self.ticks_per_sample);
+        self.band_limited = BlepFilter::new(self.ticks_per_sample);
+    }
+
+    /// Process the speaker level for one tick, appending any output samples completed to `out`.
+    pub fn push_tick(&mut self, level: u8, out: &mut Vec<f32>) {
+        let level = (level != 0) as u8;
+        match self.filter {
+            SpeakerFilter::Average => self.average.push_tick(level, out),
+            SpeakerFilter::BandLimited => self.band_limited.push_tick(level, out),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK_RATE: f64 = 1_193_182.0;
+    const SAMPLE_RATE: u32 = 44100;
+
+    /// Render `ticks` ticks of a square wave from the PIT in mode 3 with the specified divisor.
+    fn render_square(filter: SpeakerFilter, divisor: usize, ticks: usize) -> Vec<f32> {
+        let mut speaker = PcSpeaker::new(TICK_RATE, SAMPLE_RATE, filter);
+        let mut out = Vec::new();
+        let high = (divisor + 1) / 2;
+        for tick in 0..ticks {
+            speaker.push_tick((tick % divisor < high) as u8, &mut out);
+        }
+        out
+    }
+
+    /// Measure the magnitude of a frequency in a signal, with a Hann window.
+    fn magnitude(samples: &[f32], freq: f64) -> f64 {
+        let n = samples.len() as f64;
+        let (mut re, mut im) = (0.0, 0.0);
+        for (i, sample) in samples.iter().enumerate() {
+            let window = 0.5 - 0.5 * (2.0 * PI * i as f64 / n).cos();
+            let angle = 2.0 * PI * freq * i as f64 / SAMPLE_RATE as f64;
+            re += *sample as f64 * window * angle.cos();
+            im += *sample as f64 * window * angle.sin();
+        }
+        (re * re + im * im).sqrt() / n
+    }
+
+    /// Return the fractional sample index at which a rising signal crosses 0.5.
+    fn crossing(samples: &[f32]) -> f64 {
+        let i = samples.iter().position(|s| *s >= 0.5).unwrap();
+        let (a, b) = (samples[i - 1] as f64, samples[i] as f64);
+        (i - 1) as f64 + (0.5 - a) / (b - a)
+    }
+
+    #[test]
+    fn test_blep_step() {
+        // A step settles at exactly the step height, with its midpoint at the position of the
+        // transition plus the filter latency.
+        let mut crossings = Vec::new();
+        for step_tick in [1000, 1013] {
+            let mut speaker = PcSpeaker::new(TICK_RATE, SAMPLE_RATE, SpeakerFilter::BandLimited);
+            let mut out = Vec::new();
+            for tick in 0..4000 {
+                speaker.push_tick((tick >= step_tick) as u8, &mut out);
+            }
+            assert!(out[..30].iter().all(|s| s.abs() < 1e-6));
+            assert!(out[out.len() - 10..].iter().all(|s| (s - 1.0).abs() < 1e-6));
+            crossings.push(crossing(&out));
+        }
+
+        // 13 ticks later is 0.48 samples later.
+        let expected = 13.0 * SAMPLE_RATE as f64 / TICK_RATE;
+        assert!(((crossings[1] - crossings[0]) - expected).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_blep_aliasing() {
+        // A 13.1kHz square wave has a third harmonic at 39.3kHz, which aliases to 4.76kHz at
+        // 44.1kHz. The band-limited filter must suppress it far more than the box filter does.
+        let divisor = 91;
+        let fundamental = TICK_RATE / divisor as f64;
+        let alias = SAMPLE_RATE as f64 - 3.0 * fundamental;
+
+        let ratio = |filter| {
+            let out = render_square(filter, divisor, TICK_RATE as usize / 2);
+            let samples = &out[64..];
+            magnitude(samples, alias) / magnitude(samples, fundamental)
+        };
+
+        let average = ratio(SpeakerFilter::Average);
+        let band_limited = ratio(SpeakerFilter::BandLimited);
+        assert!(average > 0.01, "average alias ratio: {}", average);
+        assert!(band_limited < 0.001, "band-limited alias ratio: {}", band_limited);
+    }
+
+    #[test]
+    fn test_pwm_level() {
+        // Both filters reproduce a steady level and a 50% duty cycle beyond the Nyquist frequency.
+        for filter in [SpeakerFilter::Average, SpeakerFilter::BandLimited] {
+            let out = render_square(filter, 2, 100_000);
+            let tail = &out[100..];
+            let mean = tail.iter().sum::<f32>() / tail.len() as f32;
+            assert!((mean - 0.5).abs() < 0.01);
+        }
+    }
+}
@@ -0,0 +1,206 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::pcjr_port_b.rs
+
+    Implement the PCjr's port B control register at port 0x61.
+
+    The PCjr does not use the PC's PPI wiring for its keyboard or DIP
+    switches, so the machine has no PPI device. Port B still gates PIT
+    channel 2 and drives the speaker data line in bits 0 and 1, and bits 5
+    and 6 select the source of the audio multiplexer. Only the selected
+    source is heard: the PIT speaker output or the SN76496 sound chip.
+
+*/
+
+use crate::{
+    bus::{BusInterface, DeviceRunTimeUnit, IoDevice},
+    savestate::{SaveState, StateReader, StateWriter},
+};
+
+pub const PCJR_PORT_B: u16 = 0x61;
+
+pub const PORTB_TIMER2_GATE: u8 = 0b0000_0001;
+pub const PORTB_SPEAKER_DATA: u8 = 0b0000_0010;
+pub const PORTB_AUDIO_MUX: u8 = 0b0110_0000;
+
+/// The sources selectable by the PCjr audio multiplexer.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AudioMux {
+    Timer,
+    Cassette,
+    IoChannel,
+    SoundChip,
+}
+
+impl AudioMux {
+    fn from_bits(byte: u8) -> AudioMux {
+        match (byte & PORTB_AUDIO_MUX) >> 5 {
+            0 => AudioMux::Timer,
+            1 => AudioMux::Cassette,
+            2 => AudioMux::IoChannel,
+            _ => AudioMux::SoundChip,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PcJrPortB {
+    pb_byte: u8,
+}
+
+impl IoDevice for PcJrPortB {
+    fn read_u8(&mut self, _port: u16, _delta: DeviceRunTimeUnit) -> u8 {
+        self.pb_byte
+    }
+
+    fn write_u8(&mut self, _port: u16, data: u8, _bus: Option<&mut BusInterface>, _delta: DeviceRunTimeUnit) {
+        if (data ^ self.pb_byte) & PORTB_AUDIO_MUX != 0 {
+            log::trace!("PCjr audio multiplexer source: {:?}", AudioMux::from_bits(data));
+        }
+        self.pb_byte = data;
+    }
+
+    fn port_list(&self) -> Vec<u16> {
+        vec![PCJR_PORT_B]
+    }
+}
+
+impl PcJrPortB {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn get_pit_channel2_gate(&self) -> bool {
+        self.pb_byte & PORTB_TIMER2_GATE != 0
+    }
+
+    /// Return the source selected by the audio multiplexer.
+    pub fn audio_mux(&self) -> AudioMux {
+        AudioMux::from_bits(self.pb_byte)
+    }
+
+    /// Return whether the PIT speaker output is heard. It must be enabled by the speaker data bit
+    /// and selected by the audio multiplexer.
+    pub fn speaker_selected(&self) -> bool {
+        self.pb_byte & PORTB_SPEAKER_DATA != 0 && self.audio_mux() == AudioMux::Timer
+    }
+
+    /// Return whether the sound chip is selected by the audio multiplexer.
+    pub fn sound_chip_selected(&self) -> bool {
+        self.audio_mux() == AudioMux::SoundChip
+    }
+}
+
+impl SaveState for PcJrPortB {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.pb_byte);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.pb_byte = r.get()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        devices::{
+            pit::{Pit, PitType, PIT_CHANNEL_2_DATA_PORT, PIT_COMMAND_REGISTER},
+            sn76489::{Sn76489, Sn76489Type},
+        },
+        machine_config::PIT_DIVISOR,
+    };
+    use ringbuf::RingBuffer;
+
+    const TICKS: usize = 10_000;
+
+    /// Write port B, then tick the PIT and return how many speaker samples were high.
+    fn speaker_high_count(bus: &mut BusInterface, pit: &mut Pit, pb_byte: u8) -> usize {
+        let delta = DeviceRunTimeUnit::Microseconds(0.0);
+        bus.pcjr_port_b_mut().as_mut().unwrap().write_u8(PCJR_PORT_B, pb_byte, None, delta);
+
+        let (mut producer, mut consumer) = RingBuffer::<u8>::new(TICKS).split();
+        for _ in 0..TICKS {
+            pit.tick(bus, Some(&mut producer));
+        }
+        let mut high = 0;
+        while let Some(sample) = consumer.pop() {
+            high += sample as usize;
+        }
+        high
+    }
+
+    /// Write port B, then run the sound chip through the bus and return the peak sample level. The
+    /// first sample is skipped, as it may average ticks from before the write.
+    fn sound_chip_peak(bus: &mut BusInterface, pb_byte: u8) -> f32 {
+        let delta = DeviceRunTimeUnit::Microseconds(0.0);
+        bus.pcjr_port_b_mut().as_mut().unwrap().write_u8(PCJR_PORT_B, pb_byte, None, delta);
+        bus.run_sound_chip(1000.0);
+
+        let sound_chip = bus.sound_chip_mut().as_mut().unwrap();
+        let peak = sound_chip.samples().iter().skip(1).fold(0.0f32, |peak, s| peak.max(s.abs()));
+        sound_chip.clear_samples();
+        peak
+    }
+
+    #[test]
+    fn test_audio_mux() {
+        let mut bus = BusInterface::default();
+        *bus.pcjr_port_b_mut() = Some(PcJrPortB::new());
+
+        // Program PIT channel 2 as a square wave, and the sound chip's tone 0 at full volume.
+        let delta = DeviceRunTimeUnit::SystemTicks(0);
+        let mut pit = Pit::new(PitType::Model8253, 0.0, PIT_DIVISOR, true);
+        pit.write_u8(PIT_COMMAND_REGISTER, 0b1011_0110, Some(&mut bus), delta);
+        pit.write_u8(PIT_CHANNEL_2_DATA_PORT, 0x00, Some(&mut bus), delta);
+        pit.write_u8(PIT_CHANNEL_2_DATA_PORT, 0x04, Some(&mut bus), delta);
+
+        let mut sound_chip = Sn76489::new(Sn76489Type::Sn76496);
+        for byte in [0x80, 0x10, 0x90] {
+            sound_chip.write_register(byte);
+        }
+        *bus.sound_chip_mut() = Some(sound_chip);
+
+        // Timer selected: only the speaker is heard.
+        let timer = PORTB_TIMER2_GATE | PORTB_SPEAKER_DATA;
+        assert_eq!(bus.pcjr_port_b_mut().as_ref().unwrap().audio_mux(), AudioMux::Timer);
+        assert!(speaker_high_count(&mut bus, &mut pit, timer) > 0);
+        assert_eq!(sound_chip_peak(&mut bus, timer), 0.0);
+
+        // Sound chip selected: only the sound chip is heard.
+        let sound_chip = timer | PORTB_AUDIO_MUX;
+        assert_eq!(speaker_high_count(&mut bus, &mut pit, sound_chip), 0);
+        assert!(sound_chip_peak(&mut bus, sound_chip) > 0.0);
+
+        // Cassette selected: neither is heard.
+        let cassette = timer | 0b0010_0000;
+        assert_eq!(speaker_high_count(&mut bus, &mut pit, cassette), 0);
+        assert_eq!(sound_chip_peak(&mut bus, cassette), 0.0);
+    }
+}
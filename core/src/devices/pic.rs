@@ -34,7 +34,10 @@
 
 //use std::io::Read;
 
-use crate::bus::{BusInterface, DeviceRunTimeUnit, IoDevice};
+use crate::{
+    bus::{BusInterface, DeviceRunTimeUnit, IoDevice},
+    savestate::{impl_state_enum, SaveState, StateReader, StateWriter},
+};
 
 //pub const PIC_INTERRUPT_OFFSET: u8 = 8;
 
@@ -622,3 +625,64 @@ impl Pic {
         }
     }
 }
+
+impl_state_enum!(InitializationState {
+    Normal,
+    ExpectingICW2,
+    ExpectingICW4
+});
+impl_state_enum!(TriggerMode { Edge, Level });
+impl_state_enum!(ReadSelect { ISR, IRR });
+
+impl SaveState for Pic {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.init_state);
+        w.put(&self.int_offset);
+        w.put(&self.imr);
+        w.put(&self.isr);
+        w.put(&self.irr);
+        w.put(&self.ir);
+        w.put(&self.read_select);
+        w.put(&self.irq);
+        w.put(&self.intr);
+        w.put(&self.buffered);
+        w.put(&self.nested);
+        w.put(&self.special_nested);
+        w.put(&self.polled);
+        w.put(&self.auto_eoi);
+        w.put(&self.rotate_on_aeoi);
+        w.put(&self.trigger_mode);
+        w.put(&self.expecting_icw2);
+        w.put(&self.expecting_icw4);
+        w.put(&self.error);
+        w.put(&self.spurious_irqs);
+        w.put(&self.intr_scheduled);
+        w.put(&self.intr_timer);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.init_state = r.get()?;
+        self.int_offset = r.get()?;
+        self.imr = r.get()?;
+        self.isr = r.get()?;
+        self.irr = r.get()?;
+        self.ir = r.get()?;
+        self.read_select = r.get()?;
+        self.irq = r.get()?;
+        self.intr = r.get()?;
+        self.buffered = r.get()?;
+        self.nested = r.get()?;
+        self.special_nested = r.get()?;
+        self.polled = r.get()?;
+        self.auto_eoi = r.get()?;
+        self.rotate_on_aeoi = r.get()?;
+        self.trigger_mode = r.get()?;
+        self.expecting_icw2 = r.get()?;
+        self.expecting_icw4 = r.get()?;
+        self.error = r.get()?;
+        self.spurious_irqs = r.get()?;
+        self.intr_scheduled = r.get()?;
+        self.intr_timer = r.get()?;
+        Ok(())
+    }
+}
@@ -38,6 +38,7 @@ use modular_bitfield::prelude::*;
 
 use crate::{
     bus::{BusInterface, DeviceRunTimeUnit, IoDevice},
+    savestate::{impl_state_enum, SaveState, StateReader, StateValue, StateWriter},
     syntax_token::*,
     updatable::*,
 };
@@ -1115,6 +1116,11 @@ impl ProgrammableIntervalTimer {
             speaker_data = ppi.get_pb1_state();
             self.channels[2].set_gate(ppi.get_pit_channel2_gate(), bus);
         }
+        else if let Some(port_b) = bus.pcjr_port_b_mut() {
+            // The PCjr speaker is only heard when selected by the audio multiplexer.
+            speaker_data = port_b.speaker_selected();
+            self.channels[2].set_gate(port_b.get_pit_channel2_gate(), bus);
+        }
 
         self.channels[0].tick(bus, None);
         self.channels[1].tick(bus, None);
@@ -1294,3 +1300,143 @@ impl ProgrammableIntervalTimer {
         state_vec
     }
 }
+
+impl_state_enum!(ChannelMode {
+    InterruptOnTerminalCount,
+    HardwareRetriggerableOneShot,
+    RateGenerator,
+    SquareWaveGenerator,
+    SoftwareTriggeredStrobe,
+    HardwareTriggeredStrobe,
+});
+impl_state_enum!(ReloadFlag { Normal, ReloadNextCycle });
+impl_state_enum!(PitType { Model8253, Model8254 });
+impl_state_enum!(RwMode { Lsb, Msb, LsbMsb });
+impl_state_enum!(LoadState { WaitingForLsb, WaitingForMsb });
+impl_state_enum!(LoadType {
+    InitialLoad,
+    SubsequentLoad
+});
+impl_state_enum!(ReadState { NoRead, ReadLsb });
+
+impl StateValue for ChannelState {
+    fn write_state(&self, w: &mut StateWriter) {
+        match self {
+            ChannelState::WaitingForReload => w.write_u8(0),
+            ChannelState::WaitingForGate => w.write_u8(1),
+            ChannelState::DeferLoadCycle => w.write_u8(2),
+            ChannelState::WaitingForLoadCycle => w.write_u8(3),
+            ChannelState::WaitingForLoadTrigger => w.write_u8(4),
+            ChannelState::Counting(flag) => {
+                w.write_u8(5);
+                w.put(flag);
+            }
+        }
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, anyhow::Error> {
+        Ok(match r.read_u8()? {
+            0 => ChannelState::WaitingForReload,
+            1 => ChannelState::WaitingForGate,
+            2 => ChannelState::DeferLoadCycle,
+            3 => ChannelState::WaitingForLoadCycle,
+            4 => ChannelState::WaitingForLoadTrigger,
+            5 => ChannelState::Counting(r.get()?),
+            tag => anyhow::bail!("Invalid ChannelState value: {}", tag),
+        })
+    }
+}
+
+impl SaveState for Channel {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&*self.mode);
+        w.put(&*self.rw_mode);
+        w.put(&self.channel_state);
+        w.put(&self.cycles_in_state);
+        w.put(&*self.count_register);
+        w.put(&self.load_state);
+        w.put(&self.load_type);
+        w.put(&self.load_mask);
+        w.put(&*self.reload_value);
+        w.put(&*self.counting_element);
+        w.put(&self.ce_undefined);
+        w.put(&self.armed);
+        w.put(&self.read_state);
+        w.put(&self.count_is_latched);
+        w.put(&*self.output);
+        w.put(&self.output_on_reload);
+        w.put(&self.reload_on_trigger);
+        w.put(&*self.output_latch);
+        w.put(&self.bcd_mode);
+        w.put(&*self.gate);
+        w.put(&self.incomplete_reload);
+        w.put(&self.ticked);
+        w.put(&self.defer_reload_flag);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.mode.set(r.get()?);
+        self.rw_mode.set(r.get()?);
+        self.channel_state = r.get()?;
+        self.cycles_in_state = r.get()?;
+        self.count_register.set(r.get()?);
+        self.load_state = r.get()?;
+        self.load_type = r.get()?;
+        self.load_mask = r.get()?;
+        self.reload_value.set(r.get()?);
+        self.counting_element.set(r.get()?);
+        self.ce_undefined = r.get()?;
+        self.armed = r.get()?;
+        self.read_state = r.get()?;
+        self.count_is_latched = r.get()?;
+        self.output.set(r.get()?);
+        self.output_on_reload = r.get()?;
+        self.reload_on_trigger = r.get()?;
+        self.output_latch.set(r.get()?);
+        self.bcd_mode = r.get()?;
+        self.gate.set(r.get()?);
+        self.incomplete_reload = r.get()?;
+        self.ticked = r.get()?;
+        self.defer_reload_flag = r.get()?;
+        self.dirty = true;
+        Ok(())
+    }
+}
+
+impl SaveState for ProgrammableIntervalTimer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.ptype);
+        w.put(&self.pit_cycles);
+        w.put(&self.sys_tick_accumulator);
+        w.put(&self.sys_ticks_advance);
+        w.put(&self.cycle_accumulator);
+        w.put(&self.timewarp);
+        w.put(&self.speaker_buf);
+        w.put(&self.defer_reload_flag);
+        w.put(&(self.channels.len() as u32));
+        for channel in &self.channels {
+            channel.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        let ptype: PitType = r.get()?;
+        if ptype != self.ptype {
+            anyhow::bail!("PIT type mismatch: state has {:?}, machine has {:?}", ptype, self.ptype);
+        }
+        self.pit_cycles = r.get()?;
+        self.sys_tick_accumulator = r.get()?;
+        self.sys_ticks_advance = r.get()?;
+        self.cycle_accumulator = r.get()?;
+        self.timewarp = r.get()?;
+        self.speaker_buf = r.get()?;
+        self.defer_reload_flag = r.get()?;
+        let channel_ct: u32 = r.get()?;
+        if channel_ct as usize != self.channels.len() {
+            anyhow::bail!("PIT channel count mismatch");
+        }
+        for channel in self.channels.iter_mut() {
+            channel.load_state(r)?;
+        }
+        Ok(())
+    }
+}
@@ -41,6 +41,7 @@ use crate::{
     device_traits::videocard::VideoType,
     devices::pic,
     machine_types::MachineType,
+    savestate::{impl_state_enum, SaveState, StateReader, StateWriter},
 };
 
 pub const PPI_PORT_A: u16 = 0x60;
@@ -644,3 +645,56 @@ impl Ppi {
         }
     }
 }
+
+impl_state_enum!(PortAMode {
+    SwitchBlock1,
+    KeyboardByte
+});
+impl_state_enum!(PortCMode {
+    Switch2OneToFour,
+    Switch2Five,
+    Switch1OneToFour,
+    Switch1FiveToEight
+});
+
+/// The DIP switches are part of the machine configuration and are not saved.
+impl SaveState for Ppi {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.port_a_mode);
+        w.put(&self.port_c_mode);
+        w.put(&self.kb_clock_low);
+        w.put(&self.kb_counting_low);
+        w.put(&self.kb_low_count);
+        w.put(&self.kb_do_reset);
+        w.put(&self.kb_count_until_reset_byte);
+        w.put(&self.kb_resets_counter);
+        w.put(&self.pb_byte);
+        w.put(&self.kb_byte);
+        w.put(&self.kb_byte_last);
+        w.put(&self.keyboard_clear_scheduled);
+        w.put(&self.ksr_cleared);
+        w.put(&self.kb_enabled);
+        w.put(&self.timer_in);
+        w.put(&self.speaker_in);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.port_a_mode = r.get()?;
+        self.port_c_mode = r.get()?;
+        self.kb_clock_low = r.get()?;
+        self.kb_counting_low = r.get()?;
+        self.kb_low_count = r.get()?;
+        self.kb_do_reset = r.get()?;
+        self.kb_count_until_reset_byte = r.get()?;
+        self.kb_resets_counter = r.get()?;
+        self.pb_byte = r.get()?;
+        self.kb_byte = r.get()?;
+        self.kb_byte_last = r.get()?;
+        self.keyboard_clear_scheduled = r.get()?;
+        self.ksr_cleared = r.get()?;
+        self.kb_enabled = r.get()?;
+        self.timer_in = r.get()?;
+        self.speaker_in = r.get()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,511 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::printer::escp.rs
+
+    Render the output of a 9-pin dot matrix printer to page images, by
+    interpreting the common subset of Epson ESC/P and IBM Graphics Printer
+    control codes: pitch and print modes, line spacing, margins, tabs, page
+    length and bit image graphics.
+
+    Pages are rendered at 240 dpi as 8-bit grayscale. Paper movement is
+    tracked in 1/216 inch units, the finest vertical step of the printer.
+    Text is drawn from the MDA character set, which is the IBM Graphics
+    Printer character set. In Epson mode, bytes with the high bit set print
+    as italic ASCII.
+
+*/
+
+use crate::machine_types::PrinterEmulation;
+
+const PRINTER_FONT: &[u8] = include_bytes!("../../../../assets/mda_8by14.bin");
+const PRINTER_FONT_SPAN: usize = 256;
+const GLYPH_ROWS: u32 = 14;
+const GLYPH_HEIGHT: u32 = 28;
+
+pub const PRINTER_DPI: u32 = 240;
+pub const PAGE_WIDTH: u32 = PRINTER_DPI * 17 / 2;
+
+const UNITS_PER_INCH: u32 = 216;
+const DEFAULT_PAGE_LENGTH: u32 = 11 * UNITS_PER_INCH;
+// The longest form ESC C accepts.
+const MAX_PAGE_LENGTH: u32 = 22 * UNITS_PER_INCH;
+const DEFAULT_LINE_SPACING: u32 = UNITS_PER_INCH / 6;
+const PIN_SPACING: u32 = UNITS_PER_INCH / 72;
+
+// The head can't reach the outer quarter inch of the paper, leaving 8 inches for 80 columns of pica.
+const LEFT_EDGE: f64 = PRINTER_DPI as f64 / 4.0;
+const RIGHT_EDGE: f64 = LEFT_EDGE + PRINTER_DPI as f64 * 8.0;
+
+const DOT_SIZE: u32 = 3;
+const INK: u8 = 0x00;
+const PAPER: u8 = 0xFF;
+
+const ESC: u8 = 0x1B;
+
+/// A finished page, as 8-bit grayscale pixels at PRINTER_DPI.
+pub struct PrintedPage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum ParseState {
+    Text,
+    Escape,
+    Params(u8, usize),
+    SkipToNul,
+    Graphics(f64, usize),
+}
+
+pub struct EscpRenderer {
+    emulation: PrinterEmulation,
+    state: ParseState,
+    params: Vec<u8>,
+
+    page: Vec<u8>,
+    page_height: u32,
+    pages: Vec<PrintedPage>,
+    page_length: u32,
+    x: f64,
+    y: u32,
+
+    line_spacing: u32,
+    ibm_line_spacing: Option<u32>,
+    left_margin: f64,
+    right_margin: f64,
+    elite: bool,
+    condensed: bool,
+    double_width: bool,
+    double_width_line: bool,
+    emphasized: bool,
+    double_strike: bool,
+    underline: bool,
+    italic: bool,
+    auto_line_feed: bool,
+}
+
+impl EscpRenderer {
+    pub fn new(emulation: PrinterEmulation) -> Self {
+        Self {
+            emulation,
+            state: ParseState::Text,
+            params: Vec::new(),
+            page: Vec::new(),
+            page_height: 0,
+            pages: Vec::new(),
+            page_length: DEFAULT_PAGE_LENGTH,
+            x: LEFT_EDGE,
+            y: 0,
+            line_spacing: DEFAULT_LINE_SPACING,
+            ibm_line_spacing: None,
+            left_margin: LEFT_EDGE,
+            right_margin: RIGHT_EDGE,
+            elite: false,
+            condensed: false,
+            double_width: false,
+            double_width_line: false,
+            emphasized: false,
+            double_strike: false,
+            underline: false,
+            italic: false,
+            auto_line_feed: false,
+        }
+    }
+
+    /// Restore the power-on settings. The paper and the page in progress are left where they are.
+    pub fn reset(&mut self) {
+        let mut renderer = EscpRenderer::new(self.emulation);
+        std::mem::swap(&mut self.page, &mut renderer.page);
+        std::mem::swap(&mut self.pages, &mut renderer.pages);
+        renderer.page_height = self.page_height;
+        renderer.y = self.y;
+        *self = renderer;
+    }
+
+    /// Return true if anything has been printed on the current page.
+    pub fn page_dirty(&self) -> bool {
+        !self.page.is_empty()
+    }
+
+    /// Return the pages finished since the last call.
+    pub fn take_pages(&mut self) -> Vec<PrintedPage> {
+        std::mem::take(&mut self.pages)
+    }
+
+    pub fn process(&mut self, byte: u8) {
+        match self.state {
+            ParseState::Text => self.text_byte(byte),
+            ParseState::Escape => {
+                self.params.clear();
+                self.state = ParseState::Text;
+                if byte == b'D' || byte == b'B' {
+                    // Tab stop lists are terminated by a NUL.
+                    self.state = ParseState::SkipToNul;
+                }
+                else {
+                    match self.param_count(byte) {
+                        0 => self.escape_command(byte, &[]),
+                        count => self.state = ParseState::Params(byte, count),
+                    }
+                }
+            }
+            ParseState::Params(command, mut count) => {
+                self.params.push(byte);
+                // ESC C 0 n sets the page length in inches rather than lines.
+                if command == b'C' && self.params.len() == 1 && byte == 0 {
+                    count = 2;
+                    self.state = ParseState::Params(command, count);
+                }
+                if self.params.len() == count {
+                    self.state = ParseState::Text;
+                    let params = std::mem::take(&mut self.params);
+                    self.escape_command(command, &params);
+                }
+            }
+            ParseState::SkipToNul => {
+                if byte == 0 {
+                    self.state = ParseState::Text;
+                }
+            }
+            ParseState::Graphics(step, remaining) => {
+                self.graphics_column(byte, step);
+                self.state = match remaining - 1 {
+                    0 => ParseState::Text,
+                    n => ParseState::Graphics(step, n),
+                };
+            }
+        }
+    }
+
+    fn text_byte(&mut self, byte: u8) {
+        match byte {
+            0x08 => self.x = (self.x - self.char_width()).max(self.left_margin),
+            0x09 => {
+                let tab = self.char_width() * 8.0;
+                let x = self.left_margin + (((self.x - self.left_margin) / tab).floor() + 1.0) * tab;
+                if x < self.right_margin {
+                    self.x = x;
+                }
+            }
+            0x0A | 0x0B => self.line_feed(),
+            0x0C => self.form_feed(),
+            0x0D => {
+                self.x = self.left_margin;
+                if self.auto_line_feed {
+                    self.line_feed();
+                }
+            }
+            0x0E => self.double_width_line = true,
+            0x0F => self.condensed = true,
+            0x12 => self.condensed = false,
+            0x14 => self.double_width_line = false,
+            ESC => self.state = ParseState::Escape,
+            0x00..=0x1F | 0x7F => {}
+            0x80..=0xFF if self.emulation == PrinterEmulation::EpsonEscP => {
+                // Epson printers mirror the control codes in the upper half, and print the rest
+                // as italic characters.
+                match byte & 0x7F {
+                    0x00..=0x1F => self.text_byte(byte & 0x7F),
+                    0x7F => {}
+                    glyph => self.print_char(glyph, true),
+                }
+            }
+            _ => self.print_char(byte, self.italic),
+        }
+    }
+
+    fn param_count(&self, command: u8) -> usize {
+        match command {
+            b'*' => 3,
+            b'K' | b'L' | b'Y' | b'Z' | b'$' | b'?' | b'\\' => 2,
+            b'!' | b'-' | b'3' | b'A' | b'C' | b'J' | b'j' | b'W' | b'l' | b'Q' | b'N' | b'U' | b'x' | b't'
+            | b'R' | b'S' | b'p' | b'k' | b's' | b'r' => 1,
+            b'5' if self.emulation == PrinterEmulation::IbmGraphics => 1,
+            _ => 0,
+        }
+    }
+
+    fn escape_command(&mut self, command: u8, params: &[u8]) {
+        let word = || params[0] as usize | (params[1] as usize) << 8;
+        match command {
+            b'@' => self.reset(),
+            b'0' => self.line_spacing = UNITS_PER_INCH / 8,
+            b'1' => self.line_spacing = UNITS_PER_INCH * 7 / 72,
+            b'2' => self.line_spacing = self.ibm_line_spacing.unwrap_or(DEFAULT_LINE_SPACING),
+            b'3' => self.line_spacing = params[0] as u32,
+            b'A' => {
+                // The IBM printer holds the new spacing until ESC 2.
+                let spacing = params[0] as u32 * PIN_SPACING;
+                match self.emulation {
+                    PrinterEmulation::EpsonEscP => self.line_spacing = spacing,
+                    PrinterEmulation::IbmGraphics => self.ibm_line_spacing = Some(spacing),
+                }
+            }
+            b'J' => self.advance(params[0] as u32),
+            b'j' => self.y = self.y.saturating_sub(params[0] as u32),
+            b'C' => {
+                let length = match params {
+                    [0, inches] => *inches as u32 * UNITS_PER_INCH,
+                    _ => params[0] as u32 * self.line_spacing,
+                };
+                if length > 0 {
+                    self.page_length = length.min(MAX_PAGE_LENGTH);
+                }
+            }
+            b'E' => self.emphasized = true,
+            b'F' => self.emphasized = false,
+            b'G' => self.double_strike = true,
+            b'H' => self.double_strike = false,
+            b'-' => self.underline = params[0] & 0x01 != 0,
+            b'W' => self.double_width = params[0] & 0x01 != 0,
+            b'M' => self.elite = true,
+            b'P' => self.elite = false,
+            0x0E => self.double_width_line = true,
+            0x0F => self.condensed = true,
+            b'4' if self.emulation == PrinterEmulation::EpsonEscP => self.italic = true,
+            b'5' => match self.emulation {
+                PrinterEmulation::EpsonEscP => self.italic = false,
+                PrinterEmulation::IbmGraphics => self.auto_line_feed = params[0] & 0x01 != 0,
+            },
+            b'!' => {
+                let mode = params[0];
+                self.elite = mode & 0x01 != 0;
+                self.condensed = mode & 0x04 != 0;
+                self.emphasized = mode & 0x08 != 0;
+                self.double_strike = mode & 0x10 != 0;
+                self.double_width = mode & 0x20 != 0;
+                self.italic = mode & 0x40 != 0;
+                self.underline = mode & 0x80 != 0;
+            }
+            b'K' => self.start_graphics(60, word()),
+            b'L' | b'Y' => self.start_graphics(120, word()),
+            b'Z' => self.start_graphics(240, word()),
+            b'*' => {
+                let dpi = match params[0] {
+                    0 => 60,
+                    1 | 2 => 120,
+                    3 => 240,
+                    4 => 80,
+                    5 => 72,
+                    6 => 90,
+                    _ => 60,
+                };
+                self.start_graphics(dpi, params[1] as usize | (params[2] as usize) << 8);
+            }
+            b'$' => self.x = self.left_margin + word() as f64 * (PRINTER_DPI / 60) as f64,
+            b'\\' if self.emulation == PrinterEmulation::EpsonEscP => {
+                let offset = word() as u16 as i16;
+                self.x = (self.x + offset as f64 * (PRINTER_DPI / 120) as f64).max(self.left_margin);
+            }
+            b'l' => self.left_margin = LEFT_EDGE + params[0] as f64 * self.char_width(),
+            b'Q' => self.right_margin = (LEFT_EDGE + params[0] as f64 * self.char_width()).min(RIGHT_EDGE),
+            _ => {}
+        }
+    }
+
+    fn char_width(&self) -> f64 {
+        // Pica is 10 characters per inch, elite 12. Condensed pica is about 17 and condensed elite 20.
+        let width = match (self.elite, self.condensed) {
+            (false, false) => 24.0,
+            (true, false) => 20.0,
+            (false, true) => 14.0,
+            (true, true) => 12.0,
+        };
+        if self.double_width || self.double_width_line {
+            width * 2.0
+        }
+        else {
+            width
+        }
+    }
+
+    fn to_pixels(units: u32) -> u32 {
+        units * PRINTER_DPI / UNITS_PER_INCH
+    }
+
+    fn line_feed(&mut self) {
+        self.double_width_line = false;
+        self.advance(self.line_spacing);
+    }
+
+    fn advance(&mut self, units: u32) {
+        self.y += units;
+        if self.y >= self.page_length {
+            self.eject();
+        }
+    }
+
+    fn form_feed(&mut self) {
+        self.x = self.left_margin;
+        self.eject();
+    }
+
+    /// Finish the current page and move to the top of the next one. Blank pages are not kept.
+    pub fn eject(&mut self) {
+        if !self.page.is_empty() {
+            self.pages.push(PrintedPage {
+                width: PAGE_WIDTH,
+                height: self.page_height,
+                pixels: std::mem::take(&mut self.page),
+            });
+        }
+        self.y = 0;
+    }
+
+    fn plot_rect(&mut self, x: u32, y: u32, w: u32, h: u32) {
+        if self.page.is_empty() {
+            self.page_height = Self::to_pixels(self.page_length);
+            self.page = vec![PAPER; (PAGE_WIDTH * self.page_height) as usize];
+        }
+        for py in y..(y + h).min(self.page_height) {
+            for px in x..(x + w).min(PAGE_WIDTH) {
+                self.page[(py * PAGE_WIDTH + px) as usize] = INK;
+            }
+        }
+    }
+
+    fn print_char(&mut self, glyph: u8, italic: bool) {
+        let width = self.char_width();
+        if self.x + width > self.right_margin + 0.5 {
+            self.x = self.left_margin;
+            self.line_feed();
+        }
+
+        let x = self.x as u32;
+        let top = Self::to_pixels(self.y);
+        let glyph_width = (width * 8.0 / 9.0) as u32;
+        let mut strikes = vec![(0, 0)];
+        if self.emphasized {
+            strikes.push((1, 0));
+        }
+        if self.double_strike {
+            strikes.push((0, 1));
+        }
+
+        for (ox, oy) in strikes {
+            for dy in 0..GLYPH_HEIGHT {
+                let row = PRINTER_FONT[(dy * GLYPH_ROWS / GLYPH_HEIGHT) as usize * PRINTER_FONT_SPAN + glyph as usize];
+                let slant = if italic { (GLYPH_HEIGHT - dy) / 6 } else { 0 };
+                for dx in 0..glyph_width {
+                    if row & (0x80 >> (dx * 8 / glyph_width)) != 0 {
+                        self.plot_rect(x + dx + slant + ox, top + dy + oy, 1, 1);
+                    }
+                }
+            }
+        }
+        if self.underline {
+            self.plot_rect(x, top + GLYPH_HEIGHT - 2, width as u32, 2);
+        }
+        self.x += width;
+    }
+
+    fn start_graphics(&mut self, dpi: u32, columns: usize) {
+        if columns > 0 {
+            self.state = ParseState::Graphics(PRINTER_DPI as f64 / dpi as f64, columns);
+        }
+    }
+
+    /// Print one column of bit image data. The top pin is the most significant bit. Columns past
+    /// the right margin are discarded.
+    fn graphics_column(&mut self, byte: u8, step: f64) {
+        if self.x < self.right_margin {
+            for pin in 0..8 {
+                if byte & (0x80 >> pin) != 0 {
+                    let top = Self::to_pixels(self.y + pin * PIN_SPACING);
+                    self.plot_rect(self.x as u32, top, DOT_SIZE, DOT_SIZE);
+                }
+            }
+        }
+        self.x += step;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn print(renderer: &mut EscpRenderer, bytes: &[u8]) {
+        for byte in bytes {
+            renderer.process(*byte);
+        }
+    }
+
+    #[test]
+    fn test_text_and_form_feed() {
+        let mut renderer = EscpRenderer::new(PrinterEmulation::EpsonEscP);
+        print(&mut renderer, b"\x0C");
+        assert!(renderer.take_pages().is_empty());
+
+        print(&mut renderer, b"HELLO\r\n");
+        assert!(renderer.page_dirty());
+        assert_eq!(renderer.x, LEFT_EDGE);
+        assert_eq!(renderer.y, DEFAULT_LINE_SPACING);
+
+        print(&mut renderer, b"\x0C");
+        let pages = renderer.take_pages();
+        assert_eq!(pages.len(), 1);
+        assert_eq!(pages[0].height, 11 * PRINTER_DPI);
+        assert_eq!(pages[0].pixels.len(), (PAGE_WIDTH * pages[0].height) as usize);
+        assert!(pages[0].pixels.iter().any(|p| *p == INK));
+    }
+
+    #[test]
+    fn test_escape_sequences() {
+        let mut renderer = EscpRenderer::new(PrinterEmulation::EpsonEscP);
+
+        // Page length of 2 inches, then 1/8 inch line spacing: 16 lines per page.
+        print(&mut renderer, b"\x1BC\x00\x02\x1B0");
+        print(&mut renderer, b"X");
+        for _ in 0..16 {
+            print(&mut renderer, b"\n");
+        }
+        assert_eq!(renderer.take_pages().len(), 1);
+        assert_eq!(renderer.y, 0);
+
+        // Page lengths are limited to 22 inches.
+        print(&mut renderer, b"\x1BC\x00\xFF");
+        assert_eq!(renderer.page_length, MAX_PAGE_LENGTH);
+        print(&mut renderer, b"\x1BC\x00\x02");
+
+        // 3 columns of 60 dpi graphics advance the head 3/60 inch.
+        print(&mut renderer, b"\r\x1BK\x03\x00\xFF\x81\xFF");
+        assert_eq!(renderer.x, LEFT_EDGE + 12.0);
+        assert!(renderer.state == ParseState::Text);
+
+        // Double width pica is 5 characters per inch.
+        print(&mut renderer, b"\r\x1BW\x01A");
+        assert_eq!(renderer.x, LEFT_EDGE + 48.0);
+
+        // The IBM printer waits for ESC 2 to apply ESC A.
+        let mut renderer = EscpRenderer::new(PrinterEmulation::IbmGraphics);
+        print(&mut renderer, b"\x1BA\x08");
+        assert_eq!(renderer.line_spacing, DEFAULT_LINE_SPACING);
+        print(&mut renderer, b"\x1B2");
+        assert_eq!(renderer.line_spacing, 24);
+    }
+}
@@ -0,0 +1,234 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::printer::mod.rs
+
+    Implement a printer attached to a parallel port.
+
+    Software writes a byte to the data register and pulses STROBE. The
+    printer raises BUSY while it takes the byte, then pulses ACK to request
+    the next one. The end of the ACK pulse raises the port's interrupt if
+    enabled.
+
+    Every byte received is kept for the frontend to capture to a file. If
+    an emulation is selected, the byte stream is also rendered to pages.
+    As a page only leaves the printer on a form feed, a page left partly
+    printed is ejected after a few seconds without data.
+
+*/
+
+pub mod escp;
+
+use crate::{
+    devices::printer::escp::{EscpRenderer, PrintedPage},
+    machine_types::PrinterEmulation,
+    savestate::{impl_state_enum, SaveState, StateReader, StateWriter},
+};
+
+pub const PRINTER_BUSY_US: f64 = 10.0;
+pub const PRINTER_ACK_US: f64 = 5.0;
+pub const PRINTER_EJECT_US: f64 = 5_000_000.0;
+
+const CONTROL_STROBE: u8 = 0x01;
+const CONTROL_INIT: u8 = 0x04;
+
+const STATUS_ERROR: u8 = 0x08;
+const STATUS_SELECT: u8 = 0x10;
+const STATUS_ACK: u8 = 0x40;
+const STATUS_BUSY: u8 = 0x80;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Handshake {
+    Ready,
+    Busy,
+    Ack,
+}
+
+impl_state_enum!(Handshake { Ready, Busy, Ack });
+
+pub struct Printer {
+    data: u8,
+    control: u8,
+    handshake: Handshake,
+    handshake_us: f64,
+    idle_us: f64,
+    output: Vec<u8>,
+    renderer: Option<EscpRenderer>,
+}
+
+impl Printer {
+    pub fn new(emulation: Option<PrinterEmulation>) -> Self {
+        Self {
+            data: 0,
+            control: 0,
+            handshake: Handshake::Ready,
+            handshake_us: 0.0,
+            idle_us: 0.0,
+            output: Vec::new(),
+            renderer: emulation.map(EscpRenderer::new),
+        }
+    }
+
+    /// Return the bytes received since the last call.
+    pub fn take_output(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.output)
+    }
+
+    /// Return the pages finished since the last call. Pages are only rendered if an emulation is
+    /// selected.
+    pub fn take_pages(&mut self) -> Vec<PrintedPage> {
+        match &mut self.renderer {
+            Some(renderer) => renderer.take_pages(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Eject the page in progress, as if the form feed button was pressed.
+    pub fn eject(&mut self) {
+        if let Some(renderer) = &mut self.renderer {
+            renderer.eject();
+        }
+    }
+
+    pub fn data_write(&mut self, data: u8) {
+        self.data = data;
+    }
+
+    /// Take the data byte on the leading edge of STROBE. The printer resets while INIT is low.
+    pub fn control_write(&mut self, control: u8) {
+        if control & CONTROL_STROBE != 0 && self.control & CONTROL_STROBE == 0 && self.handshake == Handshake::Ready {
+            self.receive(self.data);
+        }
+        if control & CONTROL_INIT == 0 && self.control & CONTROL_INIT != 0 {
+            if let Some(renderer) = &mut self.renderer {
+                renderer.reset();
+            }
+        }
+        self.control = control;
+    }
+
+    /// Return the status register with the lines driven by the printer. The printer is always
+    /// online with paper and no error. BUSY is inverted in the register, ACK is active low.
+    pub fn status(&self, status: u8) -> u8 {
+        let mut status = (status & 0x07) | STATUS_ERROR | STATUS_SELECT;
+        if self.handshake != Handshake::Busy {
+            status |= STATUS_BUSY;
+        }
+        if self.handshake != Handshake::Ack {
+            status |= STATUS_ACK;
+        }
+        status
+    }
+
+    fn receive(&mut self, byte: u8) {
+        self.output.push(byte);
+        if let Some(renderer) = &mut self.renderer {
+            renderer.process(byte);
+        }
+        self.handshake = Handshake::Busy;
+        self.handshake_us = 0.0;
+        self.idle_us = 0.0;
+    }
+
+    /// Run the printer. Returns true at the end of an ACK pulse.
+    pub fn run(&mut self, us: f64) -> bool {
+        let mut ack = false;
+        if self.handshake != Handshake::Ready {
+            self.handshake_us += us;
+        }
+        if self.handshake == Handshake::Busy && self.handshake_us >= PRINTER_BUSY_US {
+            self.handshake = Handshake::Ack;
+            self.handshake_us -= PRINTER_BUSY_US;
+        }
+        if self.handshake == Handshake::Ack && self.handshake_us >= PRINTER_ACK_US {
+            self.handshake = Handshake::Ready;
+            ack = true;
+        }
+
+        if let Some(renderer) = &mut self.renderer {
+            if renderer.page_dirty() {
+                self.idle_us += us;
+                if self.idle_us >= PRINTER_EJECT_US {
+                    log::debug!("Printer idle, ejecting page.");
+                    renderer.eject();
+                    self.idle_us = 0.0;
+                }
+            }
+        }
+        ack
+    }
+}
+
+/// Only the handshake is saved. The page in progress and output not yet collected by the frontend
+/// are kept as they are.
+impl SaveState for Printer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.data);
+        w.put(&self.control);
+        w.put(&self.handshake);
+        w.put(&self.handshake_us);
+        w.put(&self.idle_us);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.data = r.get()?;
+        self.control = r.get()?;
+        self.handshake = r.get()?;
+        self.handshake_us = r.get()?;
+        self.idle_us = r.get()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake() {
+        let mut printer = Printer::new(None);
+        assert_eq!(printer.status(0) & (STATUS_BUSY | STATUS_ACK), STATUS_BUSY | STATUS_ACK);
+
+        printer.data_write(b'A');
+        printer.control_write(CONTROL_INIT | CONTROL_STROBE);
+        printer.control_write(CONTROL_INIT);
+        assert_eq!(printer.status(0) & STATUS_BUSY, 0);
+
+        // A strobe while busy is ignored.
+        printer.data_write(b'B');
+        printer.control_write(CONTROL_INIT | CONTROL_STROBE);
+        printer.control_write(CONTROL_INIT);
+
+        assert!(!printer.run(PRINTER_BUSY_US));
+        assert_eq!(printer.status(0) & (STATUS_BUSY | STATUS_ACK), STATUS_BUSY);
+        assert!(printer.run(PRINTER_ACK_US));
+        assert_eq!(printer.status(0) & (STATUS_BUSY | STATUS_ACK), STATUS_BUSY | STATUS_ACK);
+        assert!(!printer.run(PRINTER_ACK_US));
+
+        assert_eq!(printer.take_output(), b"A");
+        assert!(printer.take_output().is_empty());
+    }
+}
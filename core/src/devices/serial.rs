@@ -41,6 +41,7 @@ use std::{collections::VecDeque, io::Read};
 use crate::{
     bus::{BusInterface, DeviceRunTimeUnit, IoDevice},
     devices::pic,
+    savestate::{impl_state_enum, SaveState, StateReader, StateWriter},
 };
 
 /*  1.8Mhz Oscillator.
@@ -803,3 +804,85 @@ impl SerialPortController {
         }
     }
 }
+
+impl_state_enum!(StopBits {
+    One,
+    OneAndAHalf,
+    Two
+});
+impl_state_enum!(IntrAction { None, Raise, Lower });
+
+/// Host serial port bridges belong to the host and are left as they are when a state is loaded.
+impl SaveState for SerialPort {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.irq);
+        w.put(&self.line_control_reg);
+        w.put(&self.word_length);
+        w.put(&self.stop_bits);
+        w.put(&self.parity_enable);
+        w.put(&self.divisor_latch_access);
+        w.put(&self.divisor);
+        w.put(&self.line_status_reg);
+        w.put(&self.interrupts_active);
+        w.put(&self.interrupt_enable_reg);
+        w.put(&self.intr_action);
+        w.put(&self.modem_control_reg);
+        w.put(&self.loopback);
+        w.put(&self.modem_status_reg);
+        w.put(&self.rx_byte);
+        w.put(&self.rx_count);
+        w.put(&self.rx_was_read);
+        w.put(&self.tx_holding_reg);
+        w.put(&self.tx_holding_empty);
+        w.put(&self.rx_queue);
+        w.put(&self.rx_timer);
+        w.put(&self.tx_count);
+        w.put(&self.tx_queue);
+        w.put(&self.tx_timer);
+        w.put(&self.us_per_byte);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.irq = r.get()?;
+        self.line_control_reg = r.get()?;
+        self.word_length = r.get()?;
+        self.stop_bits = r.get()?;
+        self.parity_enable = r.get()?;
+        self.divisor_latch_access = r.get()?;
+        self.divisor = r.get()?;
+        self.line_status_reg = r.get()?;
+        self.interrupts_active = r.get()?;
+        self.interrupt_enable_reg = r.get()?;
+        self.intr_action = r.get()?;
+        self.modem_control_reg = r.get()?;
+        self.loopback = r.get()?;
+        self.modem_status_reg = r.get()?;
+        self.rx_byte = r.get()?;
+        self.rx_count = r.get()?;
+        self.rx_was_read = r.get()?;
+        self.tx_holding_reg = r.get()?;
+        self.tx_holding_empty = r.get()?;
+        self.rx_queue = r.get()?;
+        self.rx_timer = r.get()?;
+        self.tx_count = r.get()?;
+        self.tx_queue = r.get()?;
+        self.tx_timer = r.get()?;
+        self.us_per_byte = r.get()?;
+        Ok(())
+    }
+}
+
+impl SaveState for SerialPortController {
+    fn save_state(&self, w: &mut StateWriter) {
+        for port in &self.port {
+            port.save_state(w);
+        }
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        for port in self.port.iter_mut() {
+            port.load_state(r)?;
+        }
+        Ok(())
+    }
+}
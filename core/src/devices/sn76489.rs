@@ -0,0 +1,386 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::sn76489.rs
+
+    Implement the TI SN76489 family of programmable sound generators, as
+    found in the IBM PCjr (SN76496N) and the Tandy 1000 (NCR 8496).
+
+    The chip has three square wave tone channels and one noise channel, each
+    with a 4-bit attenuator in 2dB steps. It is write-only, and is
+    programmed one byte at a time: a byte with bit 7 set latches a register
+    and sets its low four bits, a byte with bit 7 clear writes the upper six
+    bits of a tone period (or the whole of a volume or noise register) to
+    the last latched register.
+
+    The chip is clocked at 3.579545MHz and divides its input by 16. Every
+    chip tick is rendered, and groups of ticks are averaged into output
+    samples that the machine queues to the sound mixer.
+
+    The SN76496 and NCR 8496 differ in the taps of the noise shift register.
+
+*/
+
+use crate::{
+    bus::{BusInterface, DeviceRunTimeUnit, IoDevice, NO_IO_BYTE},
+    savestate::{SaveState, StateReader, StateWriter},
+};
+
+pub const SN76489_PORT: u16 = 0xC0;
+pub const SN76489_CLOCK: f64 = 157.5 / 44.0;
+pub const SN76489_CLOCK_DIVISOR: f64 = 16.0;
+/// Number of chip ticks averaged into each output sample.
+pub const SN76489_TICKS_PER_SAMPLE: u32 = 4;
+
+const TONE_CHANNELS: usize = 3;
+const NOISE_REGISTER: usize = 6;
+/// A tone period of 0 is treated as the maximum period.
+const MAX_PERIOD: u16 = 0x400;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Sn76489Type {
+    Sn76496,
+    Ncr8496,
+}
+
+#[derive(Copy, Clone, Default)]
+struct ToneChannel {
+    period:  u16,
+    counter: u16,
+    output:  bool,
+}
+
+pub struct Sn76489 {
+    chip_type: Sn76489Type,
+    latch: usize,
+    tone: [ToneChannel; TONE_CHANNELS],
+    noise_control: u8,
+    noise_counter: u16,
+    noise_flipflop: bool,
+    lfsr: u32,
+    attenuation: [u8; 4],
+    volume_table: [f32; 16],
+    enabled: bool,
+    tick_accum: f64,
+    sample_accum: f32,
+    sample_ticks: u32,
+    samples: Vec<f32>,
+}
+
+impl IoDevice for Sn76489 {
+    fn read_u8(&mut self, _port: u16, _delta: DeviceRunTimeUnit) -> u8 {
+        // The sound chip is write-only.
+        NO_IO_BYTE
+    }
+
+    fn write_u8(&mut self, _port: u16, data: u8, _bus: Option<&mut BusInterface>, _delta: DeviceRunTimeUnit) {
+        self.write_register(data);
+    }
+
+    fn port_list(&self) -> Vec<u16> {
+        vec![SN76489_PORT]
+    }
+}
+
+impl Sn76489 {
+    pub fn new(chip_type: Sn76489Type) -> Self {
+        // Each attenuation step is 2dB. The maximum attenuation is silence.
+        let mut volume_table = [0.0; 16];
+        for (i, volume) in volume_table.iter_mut().enumerate().take(15) {
+            *volume = 10.0f32.powf(-2.0 * i as f32 / 20.0);
+        }
+
+        let mut chip = Self {
+            chip_type,
+            latch: 0,
+            tone: [ToneChannel::default(); TONE_CHANNELS],
+            noise_control: 0,
+            noise_counter: 0,
+            noise_flipflop: false,
+            lfsr: 0,
+            attenuation: [0x0F; 4],
+            volume_table,
+            enabled: true,
+            tick_accum: 0.0,
+            sample_accum: 0.0,
+            sample_ticks: 0,
+            samples: Vec::new(),
+        };
+        chip.lfsr = chip.feedback_mask();
+        chip
+    }
+
+    pub fn chip_type(&self) -> Sn76489Type {
+        self.chip_type
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self.chip_type {
+            Sn76489Type::Sn76496 => "SN76496",
+            Sn76489Type::Ncr8496 => "NCR 8496",
+        }
+    }
+
+    /// The rate of the samples produced by the chip, in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        (SN76489_CLOCK * 1_000_000.0 / SN76489_CLOCK_DIVISOR / SN76489_TICKS_PER_SAMPLE as f64).round() as u32
+    }
+
+    /// Return the tone period of the specified channel.
+    pub fn period(&self, channel: usize) -> u16 {
+        self.tone[channel].period
+    }
+
+    /// Return the attenuation of the specified channel. Channel 3 is the noise channel.
+    pub fn attenuation(&self, channel: usize) -> u8 {
+        self.attenuation[channel]
+    }
+
+    /// Connect or disconnect the chip output. A disconnected chip keeps running but produces silence.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Return the samples produced since the last call to clear_samples().
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Return the feedback mask, first tap and second tap of the noise shift register.
+    fn lfsr_params(&self) -> (u32, u32, u32) {
+        match self.chip_type {
+            Sn76489Type::Sn76496 => (0x10000, 0x04, 0x08),
+            Sn76489Type::Ncr8496 => (0x8000, 0x02, 0x20),
+        }
+    }
+
+    fn feedback_mask(&self) -> u32 {
+        self.lfsr_params().0
+    }
+
+    pub fn write_register(&mut self, byte: u8) {
+        if byte & 0x80 != 0 {
+            self.latch = ((byte >> 4) & 0x07) as usize;
+            let data = byte & 0x0F;
+            match self.latch {
+                NOISE_REGISTER => self.set_noise_control(data),
+                r if r & 1 != 0 => self.attenuation[r >> 1] = data,
+                r => {
+                    let tone = &mut self.tone[r >> 1];
+                    tone.period = (tone.period & 0x3F0) | data as u16;
+                }
+            }
+        }
+        else {
+            let data = byte & 0x3F;
+            match self.latch {
+                NOISE_REGISTER => self.set_noise_control(data),
+                r if r & 1 != 0 => self.attenuation[r >> 1] = data & 0x0F,
+                r => {
+                    let tone = &mut self.tone[r >> 1];
+                    tone.period = (tone.period & 0x00F) | ((data as u16) << 4);
+                }
+            }
+        }
+    }
+
+    /// Writing the noise control register resets the noise shift register.
+    fn set_noise_control(&mut self, data: u8) {
+        self.noise_control = data & 0x07;
+        self.lfsr = self.feedback_mask();
+    }
+
+    fn noise_period(&self) -> u16 {
+        match self.noise_control & 0x03 {
+            0 => 0x10,
+            1 => 0x20,
+            2 => 0x40,
+            _ => match self.tone[2].period {
+                0 => MAX_PERIOD,
+                period => period,
+            },
+        }
+    }
+
+    /// Advance the chip by one tick of its divided clock.
+    fn tick(&mut self) {
+        for tone in self.tone.iter_mut() {
+            // Periods of 0 and 1 hold the output high. Software relies on this to play samples
+            // by modulating the volume of a channel.
+            if tone.period <= 1 {
+                tone.output = true;
+                continue;
+            }
+            if tone.counter > 0 {
+                tone.counter -= 1;
+            }
+            if tone.counter == 0 {
+                tone.counter = tone.period;
+                tone.output = !tone.output;
+            }
+        }
+
+        if self.noise_counter > 0 {
+            self.noise_counter -= 1;
+        }
+        if self.noise_counter == 0 {
+            self.noise_counter = self.noise_period();
+            self.noise_flipflop = !self.noise_flipflop;
+            // The shift register is clocked on the rising edge of the noise counter output.
+            if self.noise_flipflop {
+                let (feedback_mask, tap1, tap2) = self.lfsr_params();
+                let white_noise = self.noise_control & 0x04 != 0;
+                let feedback = (self.lfsr & tap1 != 0) ^ (white_noise && (self.lfsr & tap2 != 0));
+                self.lfsr >>= 1;
+                if feedback {
+                    self.lfsr |= feedback_mask;
+                }
+            }
+        }
+    }
+
+    /// Return the current output level of the chip, between -1.0 and 1.0.
+    fn output(&self) -> f32 {
+        let mut level = 0.0;
+        for (i, tone) in self.tone.iter().enumerate() {
+            let volume = self.volume_table[self.attenuation[i] as usize];
+            level += if tone.output { volume } else { -volume };
+        }
+        let volume = self.volume_table[self.attenuation[3] as usize];
+        level += if self.lfsr & 1 != 0 { volume } else { -volume };
+        level / 4.0
+    }
+
+    /// Run the chip for the specified number of microseconds, producing samples.
+    pub fn run(&mut self, us: f64) {
+        self.tick_accum += us * SN76489_CLOCK / SN76489_CLOCK_DIVISOR;
+
+        while self.tick_accum >= 1.0 {
+            self.tick_accum -= 1.0;
+            self.tick();
+
+            if self.enabled {
+                self.sample_accum += self.output();
+            }
+            self.sample_ticks += 1;
+            if self.sample_ticks == SN76489_TICKS_PER_SAMPLE {
+                self.samples.push(self.sample_accum / SN76489_TICKS_PER_SAMPLE as f32);
+                self.sample_accum = 0.0;
+                self.sample_ticks = 0;
+            }
+        }
+    }
+}
+
+/// The chip type is part of the machine configuration and is not saved. Samples not yet collected
+/// by the machine are discarded.
+impl SaveState for Sn76489 {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.latch);
+        for tone in self.tone.iter() {
+            w.put(&tone.period);
+            w.put(&tone.counter);
+            w.put(&tone.output);
+        }
+        w.put(&self.noise_control);
+        w.put(&self.noise_counter);
+        w.put(&self.noise_flipflop);
+        w.put(&self.lfsr);
+        w.put(&self.attenuation);
+        w.put(&self.enabled);
+        w.put(&self.tick_accum);
+        w.put(&self.sample_accum);
+        w.put(&self.sample_ticks);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.latch = r.get()?;
+        for tone in self.tone.iter_mut() {
+            tone.period = r.get()?;
+            tone.counter = r.get()?;
+            tone.output = r.get()?;
+        }
+        self.noise_control = r.get()?;
+        self.noise_counter = r.get()?;
+        self.noise_flipflop = r.get()?;
+        self.lfsr = r.get()?;
+        self.attenuation = r.get()?;
+        self.enabled = r.get()?;
+        self.tick_accum = r.get()?;
+        self.sample_accum = r.get()?;
+        self.sample_ticks = r.get()?;
+        self.samples.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_register_latch() {
+        let mut chip = Sn76489::new(Sn76489Type::Sn76496);
+
+        // Latch tone 1 low bits, then write the high bits with a data byte.
+        chip.write_register(0xA5);
+        chip.write_register(0x1F);
+        assert_eq!(chip.period(1), 0x1F5);
+
+        // A data byte after a volume latch writes the attenuation.
+        chip.write_register(0xD8);
+        assert_eq!(chip.attenuation(2), 0x08);
+        chip.write_register(0x03);
+        assert_eq!(chip.attenuation(2), 0x03);
+        assert_eq!(chip.period(1), 0x1F5);
+    }
+
+    #[test]
+    fn test_tone_frequency() {
+        let mut chip = Sn76489::new(Sn76489Type::Ncr8496);
+
+        // Tone 0 at full volume with a period of 0x100: 3.579545MHz / (32 * 256) = ~437Hz.
+        chip.write_register(0x80);
+        chip.write_register(0x10);
+        chip.write_register(0x90);
+
+        chip.run(1_000_000.0);
+        let samples = chip.samples();
+        assert_eq!(samples.len(), chip.sample_rate() as usize);
+
+        let crossings = samples
+            .windows(2)
+            .filter(|w| (w[0] < 0.0) != (w[1] < 0.0))
+            .count();
+        let freq = crossings as f64 / 2.0;
+        let expected = SN76489_CLOCK * 1_000_000.0 / (32.0 * 256.0);
+        assert!((freq - expected).abs() < 2.0, "frequency {} expected {}", freq, expected);
+    }
+}
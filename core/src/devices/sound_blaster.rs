@@ -0,0 +1,618 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::sound_blaster.rs
+
+    Implement the Creative Labs Sound Blaster 1.x and 2.0 cards.
+
+    The card carries an OPL2 for FM music and a DSP that drives an 8-bit
+    mono DAC. The DSP is programmed through a command/data port, and can
+    either write samples to the DAC directly or fetch them from memory by
+    8-bit DMA on channel 1, raising an interrupt at the end of each block.
+
+    Ports, relative to the base address:
+
+    +0x6      DSP reset
+    +0x8/+0x9 OPL2 status/address and data (also at 0x388/0x389)
+    +0xA      DSP read data
+    +0xC      DSP write command/data, read write buffer status
+    +0xE      DSP read buffer status, reading acknowledges the interrupt
+
+    Single-cycle DMA playback (0x14) is supported on all models. Auto-init
+    DMA (0x1C), the block size command and high-speed DMA are DSP 2.0
+    features. Recording returns silence, and ADPCM playback and the C/MS
+    chips are not implemented.
+
+    The DAC output is sampled at a fixed rate for the mixer, so the output
+    rate does not change with the playback rate programmed by software.
+
+*/
+
+use std::collections::VecDeque;
+
+use crate::{
+    bus::{BusInterface, DeviceRunTimeUnit, IoDevice, NO_IO_BYTE},
+    devices::{
+        adlib::{ADLIB_ADDRESS_PORT, ADLIB_DATA_PORT},
+        dma::DMAController,
+        opl2::Opl2,
+    },
+    savestate::{impl_state_enum, SaveState, StateReader, StateWriter},
+};
+
+pub const SB_DEFAULT_IO_BASE: u16 = 0x220;
+pub const SB_DEFAULT_IRQ: u8 = 0x07;
+/// IRQ lines selectable by the card's jumpers.
+pub const SB_VALID_IRQS: [u8; 4] = [2, 3, 5, 7];
+pub const SB_DMA: usize = 0x01;
+pub const SB_OUTPUT_RATE: u32 = 44100;
+
+const DSP_RESET: u16 = 0x06;
+const FM_ADDRESS: u16 = 0x08;
+const FM_DATA: u16 = 0x09;
+const DSP_READ_DATA: u16 = 0x0A;
+const DSP_WRITE: u16 = 0x0C;
+const DSP_READ_STATUS: u16 = 0x0E;
+
+const DSP_RESET_ACK: u8 = 0xAA;
+const DAC_SILENCE: u8 = 0x80;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SoundBlasterType {
+    SoundBlaster1,
+    SoundBlaster2,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum TransferKind {
+    Dac,
+    Adc,
+    Silence,
+}
+
+impl_state_enum!(TransferKind { Dac, Adc, Silence });
+
+pub struct SoundBlaster {
+    sb_type: SoundBlasterType,
+    io_base: u16,
+    irq: u8,
+    fm_alias: bool,
+    opl: Opl2,
+
+    reset_latch: bool,
+    command: Option<u8>,
+    params: Vec<u8>,
+    read_queue: VecDeque<u8>,
+    test_register: u8,
+    time_constant: u8,
+    block_size: u32,
+    speaker: bool,
+    dac: u8,
+
+    transfer: Option<TransferKind>,
+    auto_init: bool,
+    paused: bool,
+    bytes_left: u32,
+    transfer_us: f64,
+
+    irq_pending: bool,
+    send_interrupt: bool,
+    clear_interrupt: bool,
+
+    output_us: f64,
+    samples: Vec<f32>,
+}
+
+impl IoDevice for SoundBlaster {
+    fn read_u8(&mut self, port: u16, _delta: DeviceRunTimeUnit) -> u8 {
+        if port == ADLIB_ADDRESS_PORT {
+            return self.opl.read_status();
+        }
+        match port.wrapping_sub(self.io_base) {
+            FM_ADDRESS => self.opl.read_status(),
+            DSP_READ_DATA => self.read_queue.pop_front().unwrap_or(0xFF),
+            // Bit 7 clear: ready to accept a command or data byte.
+            DSP_WRITE => 0x7F,
+            DSP_READ_STATUS => {
+                // Reading the status port acknowledges the DSP interrupt.
+                if self.irq_pending {
+                    self.irq_pending = false;
+                    self.clear_interrupt = true;
+                }
+                if self.read_queue.is_empty() {
+                    0x7F
+                }
+                else {
+                    0xFF
+                }
+            }
+            _ => NO_IO_BYTE,
+        }
+    }
+
+    fn write_u8(&mut self, port: u16, data: u8, _bus: Option<&mut BusInterface>, _delta: DeviceRunTimeUnit) {
+        match port {
+            ADLIB_ADDRESS_PORT => return self.opl.write_address(data),
+            ADLIB_DATA_PORT => return self.opl.write_data(data),
+            _ => {}
+        }
+        match port.wrapping_sub(self.io_base) {
+            DSP_RESET => {
+                // The DSP resets when the reset bit is written with 1 and then 0.
+                if data & 0x01 != 0 {
+                    self.reset_latch = true;
+                }
+                else if self.reset_latch {
+                    self.reset_latch = false;
+                    self.reset_dsp();
+                }
+            }
+            FM_ADDRESS => self.opl.write_address(data),
+            FM_DATA => self.opl.write_data(data),
+            DSP_WRITE => self.write_dsp(data),
+            _ => {}
+        }
+    }
+
+    fn port_list(&self) -> Vec<u16> {
+        let mut ports: Vec<u16> = [DSP_RESET, FM_ADDRESS, FM_DATA, DSP_READ_DATA, DSP_WRITE, DSP_READ_STATUS]
+            .iter()
+            .map(|offset| self.io_base + offset)
+            .collect();
+        if self.fm_alias {
+            ports.extend([ADLIB_ADDRESS_PORT, ADLIB_DATA_PORT]);
+        }
+        ports
+    }
+}
+
+impl SoundBlaster {
+    /// Create a Sound Blaster. If fm_alias is set, the OPL2 is also decoded at the AdLib ports.
+    pub fn new(sb_type: SoundBlasterType, io_base: u16, irq: u8, fm_alias: bool) -> Self {
+        Self {
+            sb_type,
+            io_base,
+            irq,
+            fm_alias,
+            opl: Opl2::new(),
+            reset_latch: false,
+            command: None,
+            params: Vec::new(),
+            read_queue: VecDeque::new(),
+            test_register: 0,
+            time_constant: 0,
+            block_size: 0x800,
+            speaker: false,
+            dac: DAC_SILENCE,
+            transfer: None,
+            auto_init: false,
+            paused: false,
+            bytes_left: 0,
+            transfer_us: 0.0,
+            irq_pending: false,
+            send_interrupt: false,
+            clear_interrupt: false,
+            output_us: 0.0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn sb_type(&self) -> SoundBlasterType {
+        self.sb_type
+    }
+
+    pub fn opl(&self) -> &Opl2 {
+        &self.opl
+    }
+
+    pub fn opl_mut(&mut self) -> &mut Opl2 {
+        &mut self.opl
+    }
+
+    /// Return the DAC samples produced since the last call to clear_samples(), at SB_OUTPUT_RATE.
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+
+    pub fn clear_samples(&mut self) {
+        self.samples.clear();
+    }
+
+    fn dsp_version(&self) -> (u8, u8) {
+        match self.sb_type {
+            SoundBlasterType::SoundBlaster1 => (1, 5),
+            SoundBlasterType::SoundBlaster2 => (2, 1),
+        }
+    }
+
+    fn reset_dsp(&mut self) {
+        log::debug!("Sound Blaster: DSP reset");
+        self.command = None;
+        self.params.clear();
+        self.read_queue.clear();
+        self.transfer = None;
+        self.auto_init = false;
+        self.paused = false;
+        self.speaker = false;
+        self.dac = DAC_SILENCE;
+        if self.irq_pending {
+            self.irq_pending = false;
+            self.clear_interrupt = true;
+        }
+        self.read_queue.push_back(DSP_RESET_ACK);
+    }
+
+    /// Return the number of parameter bytes that follow a DSP command.
+    fn param_count(command: u8) -> usize {
+        match command {
+            0x10 | 0x40 | 0xE0 | 0xE4 => 1,
+            0x14 | 0x16 | 0x17 | 0x24 | 0x48 | 0x74..=0x77 | 0x80 => 2,
+            _ => 0,
+        }
+    }
+
+    fn write_dsp(&mut self, byte: u8) {
+        let command = match self.command {
+            Some(command) => {
+                self.params.push(byte);
+                command
+            }
+            None => byte,
+        };
+
+        if self.params.len() < Self::param_count(command) {
+            self.command = Some(command);
+            return;
+        }
+        self.command = None;
+        self.execute(command);
+        self.params.clear();
+    }
+
+    fn param_length(&self) -> u32 {
+        (self.params[0] as u32 | (self.params[1] as u32) << 8) + 1
+    }
+
+    fn execute(&mut self, command: u8) {
+        let sb2 = self.sb_type == SoundBlasterType::SoundBlaster2;
+        match command {
+            0x10 => self.dac = self.params[0],
+            0x14 => self.start_transfer(TransferKind::Dac, self.param_length(), false),
+            0x1C | 0x90 if sb2 => self.start_transfer(TransferKind::Dac, self.block_size, true),
+            0x91 if sb2 => self.start_transfer(TransferKind::Dac, self.block_size, false),
+            0x20 => self.read_queue.push_back(DAC_SILENCE),
+            0x24 => self.start_transfer(TransferKind::Adc, self.param_length(), false),
+            0x2C | 0x98 if sb2 => self.start_transfer(TransferKind::Adc, self.block_size, true),
+            0x99 if sb2 => self.start_transfer(TransferKind::Adc, self.block_size, false),
+            0x40 => self.time_constant = self.params[0],
+            0x48 if sb2 => self.block_size = self.param_length(),
+            0x80 => self.start_transfer(TransferKind::Silence, self.param_length(), false),
+            0xD0 => self.paused = true,
+            0xD1 => self.speaker = true,
+            0xD3 => self.speaker = false,
+            0xD4 => self.paused = false,
+            0xD8 => self.read_queue.push_back(if self.speaker { 0xFF } else { 0x00 }),
+            0xDA if sb2 => self.auto_init = false,
+            0xE0 if sb2 => self.read_queue.push_back(!self.params[0]),
+            0xE1 => {
+                let (major, minor) = self.dsp_version();
+                self.read_queue.push_back(major);
+                self.read_queue.push_back(minor);
+            }
+            0xE4 => self.test_register = self.params[0],
+            0xE8 => self.read_queue.push_back(self.test_register),
+            0xF2 => self.send_interrupt = true,
+            0x16 | 0x17 | 0x1F | 0x74..=0x77 | 0x7D | 0x7F => {
+                log::warn!("Sound Blaster: ADPCM command {:02X} not supported", command);
+            }
+            _ => {
+                log::warn!("Sound Blaster: Unsupported DSP command {:02X}", command);
+            }
+        }
+    }
+
+    fn start_transfer(&mut self, kind: TransferKind, length: u32, auto_init: bool) {
+        log::trace!(
+            "Sound Blaster: {:?} transfer of {} bytes at {}Hz, auto-init: {}",
+            kind,
+            length,
+            self.transfer_rate() as u32,
+            auto_init
+        );
+        self.transfer = Some(kind);
+        self.auto_init = auto_init;
+        self.paused = false;
+        self.bytes_left = length;
+        self.transfer_us = 0.0;
+    }
+
+    /// The transfer rate in Hz, set by the time constant.
+    fn transfer_rate(&self) -> f64 {
+        1_000_000.0 / self.transfer_period()
+    }
+
+    fn transfer_period(&self) -> f64 {
+        256.0 - self.time_constant as f64
+    }
+
+    /// Transfer one byte of the active transfer, ending the block and raising the interrupt at the
+    /// end of the count.
+    fn transfer_byte(&mut self, kind: TransferKind, dma: &mut DMAController, bus: &mut BusInterface) {
+        match kind {
+            TransferKind::Dac | TransferKind::Adc => {
+                // Wait while the DMA channel is masked.
+                if !dma.check_dma_ready(SB_DMA) {
+                    return;
+                }
+                dma.request_service(SB_DMA);
+                if kind == TransferKind::Dac {
+                    self.dac = dma.do_dma_read_u8(bus, SB_DMA);
+                }
+                else {
+                    dma.do_dma_write_u8(bus, SB_DMA, DAC_SILENCE);
+                }
+                dma.clear_service(SB_DMA);
+            }
+            TransferKind::Silence => self.dac = DAC_SILENCE,
+        }
+
+        self.bytes_left -= 1;
+        if self.bytes_left == 0 {
+            self.send_interrupt = true;
+            if self.auto_init {
+                self.bytes_left = self.block_size;
+            }
+            else {
+                self.transfer = None;
+            }
+        }
+    }
+
+    fn output_sample(&self) -> f32 {
+        if self.speaker {
+            (self.dac as f32 - 128.0) / 128.0
+        }
+        else {
+            0.0
+        }
+    }
+
+    /// Run the Sound Blaster, passing it the DMA controller while it is detached from the bus.
+    pub fn run(&mut self, dma: &mut DMAController, bus: &mut BusInterface, us: f64) {
+        self.opl.run(us);
+
+        // Step through the DAC transfer and output sample events in order.
+        let output_period = 1_000_000.0 / SB_OUTPUT_RATE as f64;
+        let mut remaining = us;
+        while remaining > 0.0 {
+            let active = self.transfer.filter(|_| !self.paused);
+            let to_output = output_period - self.output_us;
+            let to_transfer = if active.is_some() {
+                self.transfer_period() - self.transfer_us
+            }
+            else {
+                f64::MAX
+            };
+            let step = remaining.min(to_output).min(to_transfer).max(0.0);
+
+            remaining -= step;
+            self.output_us += step;
+            if let Some(kind) = active {
+                self.transfer_us += step;
+                if self.transfer_us >= self.transfer_period() {
+                    self.transfer_us -= self.transfer_period();
+                    self.transfer_byte(kind, dma, bus);
+                }
+            }
+            if self.output_us >= output_period {
+                self.output_us -= output_period;
+                self.samples.push(self.output_sample());
+            }
+        }
+
+        // An acknowledge from before this run must not withdraw an interrupt raised during it.
+        if self.clear_interrupt {
+            self.clear_interrupt = false;
+            bus.pic_mut().as_mut().unwrap().clear_interrupt(self.irq);
+        }
+        if self.send_interrupt {
+            self.send_interrupt = false;
+            self.irq_pending = true;
+            bus.pic_mut().as_mut().unwrap().request_interrupt(self.irq);
+        }
+    }
+}
+
+/// The card type and resources are part of the machine configuration and are not saved. Samples not
+/// yet collected by the machine are discarded.
+impl SaveState for SoundBlaster {
+    fn save_state(&self, w: &mut StateWriter) {
+        self.opl.save_state(w);
+        w.put(&self.reset_latch);
+        w.put(&self.command);
+        w.put(&self.params);
+        w.put(&self.read_queue);
+        w.put(&self.test_register);
+        w.put(&self.time_constant);
+        w.put(&self.block_size);
+        w.put(&self.speaker);
+        w.put(&self.dac);
+        w.put(&self.transfer);
+        w.put(&self.auto_init);
+        w.put(&self.paused);
+        w.put(&self.bytes_left);
+        w.put(&self.transfer_us);
+        w.put(&self.irq_pending);
+        w.put(&self.send_interrupt);
+        w.put(&self.clear_interrupt);
+        w.put(&self.output_us);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.opl.load_state(r)?;
+        self.reset_latch = r.get()?;
+        self.command = r.get()?;
+        self.params = r.get()?;
+        self.read_queue = r.get()?;
+        self.test_register = r.get()?;
+        self.time_constant = r.get()?;
+        self.block_size = r.get()?;
+        self.speaker = r.get()?;
+        self.dac = r.get()?;
+        self.transfer = r.get()?;
+        self.auto_init = r.get()?;
+        self.paused = r.get()?;
+        self.bytes_left = r.get()?;
+        self.transfer_us = r.get()?;
+        self.irq_pending = r.get()?;
+        self.send_interrupt = r.get()?;
+        self.clear_interrupt = r.get()?;
+        self.output_us = r.get()?;
+        self.samples.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::pic::Pic;
+
+    fn write(sb: &mut SoundBlaster, offset: u16, data: u8) {
+        sb.write_u8(SB_DEFAULT_IO_BASE + offset, data, None, DeviceRunTimeUnit::Microseconds(0.0));
+    }
+
+    fn read(sb: &mut SoundBlaster, offset: u16) -> u8 {
+        sb.read_u8(SB_DEFAULT_IO_BASE + offset, DeviceRunTimeUnit::Microseconds(0.0))
+    }
+
+    #[test]
+    fn test_dsp_commands() {
+        let mut sb = SoundBlaster::new(SoundBlasterType::SoundBlaster2, SB_DEFAULT_IO_BASE, SB_DEFAULT_IRQ, true);
+
+        // Reset the DSP and read the acknowledge byte.
+        assert_eq!(read(&mut sb, DSP_READ_STATUS) & 0x80, 0);
+        write(&mut sb, DSP_RESET, 1);
+        write(&mut sb, DSP_RESET, 0);
+        assert_eq!(read(&mut sb, DSP_READ_STATUS) & 0x80, 0x80);
+        assert_eq!(read(&mut sb, DSP_READ_DATA), DSP_RESET_ACK);
+
+        write(&mut sb, DSP_WRITE, 0xE1);
+        assert_eq!(read(&mut sb, DSP_READ_DATA), 2);
+        assert_eq!(read(&mut sb, DSP_READ_DATA), 1);
+
+        // Identification returns the inverted parameter.
+        write(&mut sb, DSP_WRITE, 0xE0);
+        write(&mut sb, DSP_WRITE, 0x5A);
+        assert_eq!(read(&mut sb, DSP_READ_DATA), 0xA5);
+
+        // Direct DAC output is only heard with the speaker on.
+        write(&mut sb, DSP_WRITE, 0x10);
+        write(&mut sb, DSP_WRITE, 0xC0);
+        assert_eq!(sb.output_sample(), 0.0);
+        write(&mut sb, DSP_WRITE, 0xD1);
+        assert_eq!(sb.output_sample(), 0.5);
+
+        // The time constant sets the transfer rate.
+        write(&mut sb, DSP_WRITE, 0x40);
+        write(&mut sb, DSP_WRITE, 0xA6);
+        assert_eq!(sb.transfer_rate() as u32, 11111);
+    }
+
+    /// Program DMA channel 1 to read 'len' bytes from 'address' in the specified mode.
+    fn program_dma(dma: &mut DMAController, mode: u8, address: u16, len: u16) {
+        let count = len - 1;
+        dma.handle_channel_mask_register_write(0x04 | SB_DMA as u8);
+        dma.handle_channel_mode_register_write(mode | SB_DMA as u8);
+        dma.handle_clear_flopflop();
+        dma.handle_addr_port_write(SB_DMA, address as u8);
+        dma.handle_addr_port_write(SB_DMA, (address >> 8) as u8);
+        dma.handle_wc_port_write(SB_DMA, count as u8);
+        dma.handle_wc_port_write(SB_DMA, (count >> 8) as u8);
+        dma.handle_page_register_write(SB_DMA, 0);
+        dma.handle_channel_mask_register_write(SB_DMA as u8);
+    }
+
+    /// Return the vector of the pending interrupt, acknowledging it at the PIC.
+    fn take_interrupt(bus: &mut BusInterface) -> Option<u8> {
+        let pic = bus.pic_mut().as_mut().unwrap();
+        if !pic.query_interrupt_line() {
+            return None;
+        }
+        let vector = pic.get_interrupt_vector();
+        pic.eoi(vector);
+        vector
+    }
+
+    #[test]
+    fn test_dma_playback() {
+        let mut sb = SoundBlaster::new(SoundBlasterType::SoundBlaster2, SB_DEFAULT_IO_BASE, SB_DEFAULT_IRQ, true);
+        let mut dma = DMAController::new();
+        let mut bus = BusInterface::default();
+        let mut pic = Pic::new();
+        pic.handle_data_register_write(0x00);
+        *bus.pic_mut() = Some(pic);
+        for (i, byte) in [0x10, 0x20, 0x30, 0x40].iter().enumerate() {
+            bus.write_u8(0x1000 + i, *byte, 0).unwrap();
+        }
+
+        // A 100us transfer period moves one byte per 100us.
+        write(&mut sb, DSP_WRITE, 0xD1);
+        write(&mut sb, DSP_WRITE, 0x40);
+        write(&mut sb, DSP_WRITE, 0x9C);
+
+        // 8-bit single-cycle playback raises the interrupt once at the end of the block.
+        program_dma(&mut dma, 0x48, 0x1000, 4);
+        for byte in [0x14, 0x03, 0x00] {
+            write(&mut sb, DSP_WRITE, byte);
+        }
+        sb.run(&mut dma, &mut bus, 350.0);
+        assert_eq!(sb.dac, 0x30);
+        assert_eq!(take_interrupt(&mut bus), None);
+        sb.run(&mut dma, &mut bus, 100.0);
+        assert_eq!(sb.dac, 0x40);
+        assert!(sb.transfer.is_none());
+        assert_eq!(take_interrupt(&mut bus), Some(SB_DEFAULT_IRQ));
+
+        // Acknowledge the interrupt at the DSP.
+        read(&mut sb, DSP_READ_STATUS);
+        sb.run(&mut dma, &mut bus, 1.0);
+
+        // Auto-init playback raises the interrupt at the end of every block and keeps running.
+        program_dma(&mut dma, 0x58, 0x1000, 4);
+        for byte in [0x48, 0x03, 0x00, 0x1C] {
+            write(&mut sb, DSP_WRITE, byte);
+        }
+        sb.run(&mut dma, &mut bus, 50.0);
+        for _ in 0..2 {
+            sb.run(&mut dma, &mut bus, 400.0);
+            assert_eq!(sb.dac, 0x40);
+            assert_eq!(take_interrupt(&mut bus), Some(SB_DEFAULT_IRQ));
+            read(&mut sb, DSP_READ_STATUS);
+        }
+        assert_eq!(sb.transfer, Some(TransferKind::Dac));
+    }
+}
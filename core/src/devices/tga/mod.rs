@@ -47,6 +47,7 @@ use std::{collections::HashMap, convert::TryInto, path::Path};
 mod io;
 mod draw;
 mod mmio;
+mod state;
 mod tablegen;
 mod videocard;
 
@@ -642,6 +643,7 @@ impl CgaDefault for DisplayExtents {
             field_w: CGA_XRES_MAX,
             field_h: CGA_YRES_MAX,
             row_stride: CGA_XRES_MAX as usize,
+            bytes_per_pixel: 1,
             double_scan: true,
             mode_byte: 0,
         }
@@ -929,7 +931,7 @@ impl TGACard {
         if self.ticks_advanced % CGA_LCHAR_CLOCK as u32 > 0 {
             // We have advanced the CGA card out of phase with the character clock. Count
             // how many pixel clocks we need to tick by to be back in phase.
-            ((!self.cycles + 1) & 0x0F) as u32
+            (self.cycles.wrapping_neg() & 0x0F) as u32
         }
         else {
             0
@@ -938,7 +940,7 @@ impl TGACard {
 
     #[inline]
     fn calc_phase_offset(&mut self) -> u32 {
-        ((!self.cycles + 1) & 0x0F) as u32
+        (self.cycles.wrapping_neg() & 0x0F) as u32
     }
 
     fn set_lp_latch(&mut self) {
@@ -0,0 +1,301 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::tga::state.rs
+
+    Save state support for the Tandy Graphics Adapter.
+
+    The frame buffers are not saved - they are redrawn as the restored card
+    continues to run. Debug and display options are left as they are.
+*/
+
+use super::*;
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateWriter};
+
+impl_state_enum!(CRTCRegister {
+    HorizontalTotal,
+    HorizontalDisplayed,
+    HorizontalSyncPosition,
+    SyncWidth,
+    VerticalTotal,
+    VerticalTotalAdjust,
+    VerticalDisplayed,
+    VerticalSync,
+    InterlaceMode,
+    MaximumScanLineAddress,
+    CursorStartLine,
+    CursorEndLine,
+    StartAddressH,
+    StartAddressL,
+    CursorAddressH,
+    CursorAddressL,
+    LightPenPositionH,
+    LightPenPositionL,
+});
+
+impl SaveState for TGACard {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.cycles);
+        w.put(&self.last_vsync_cycles);
+        w.put(&self.cur_screen_cycles);
+        w.put(&self.cycles_per_vsync);
+        w.put(&self.sink_cycles);
+        w.put(&self.catching_up);
+        w.put(&self.last_rw_tick);
+        w.put(&self.dirty_snow);
+        w.put(&self.snow_char);
+        w.put(&self.last_bus_value);
+        w.put(&self.last_bus_addr);
+        w.put(&self.snow_count);
+        w.put(&self.mode_pending);
+        w.put(&self.clock_pending);
+        w.put(&self.mode_byte);
+        w.put(&self.display_mode);
+        w.put(&self.mode_enable);
+        w.put(&self.mode_graphics);
+        w.put(&self.mode_bw);
+        w.put(&self.mode_hires_gfx);
+        w.put(&self.mode_hires_txt);
+        w.put(&self.mode_blinking);
+        w.put(&self.cc_palette);
+        w.put(&self.cc_altcolor);
+        w.put(&self.cc_overscan_color);
+        w.put(&self.scanline_us);
+        w.put(&self.frame_us);
+        w.put(&self.cursor_frames);
+        w.put(&self.frame_count);
+        w.put(&self.status_reads);
+        w.put(&self.cursor_status);
+        w.put(&self.cursor_slowblink);
+        w.put(&self.cursor_blink_rate);
+        w.put(&self.cursor_data);
+        w.put(&self.cursor_attr);
+        w.put(&self.crtc_register_select_byte);
+        w.put(&self.crtc_register_selected);
+        w.put(&self.crtc_horizontal_total);
+        w.put(&self.crtc_horizontal_displayed);
+        w.put(&self.crtc_horizontal_sync_pos);
+        w.put(&self.crtc_sync_width);
+        w.put(&self.crtc_vertical_total);
+        w.put(&self.crtc_vertical_total_adjust);
+        w.put(&self.crtc_vertical_displayed);
+        w.put(&self.crtc_vertical_sync_pos);
+        w.put(&self.crtc_interlace_mode);
+        w.put(&self.crtc_maximum_scanline_address);
+        w.put(&self.crtc_cursor_start_line);
+        w.put(&self.crtc_cursor_end_line);
+        w.put(&self.crtc_start_address);
+        w.put(&self.crtc_start_address_ho);
+        w.put(&self.crtc_start_address_lo);
+        w.put(&self.crtc_cursor_address_lo);
+        w.put(&self.crtc_cursor_address_ho);
+        w.put(&self.crtc_cursor_address);
+        w.put(&self.crtc_frame_address);
+        w.put(&self.in_crtc_hblank);
+        w.put(&self.in_crtc_vblank);
+        w.put(&self.in_crtc_vsync);
+        w.put(&self.in_last_vblank_line);
+        w.put(&self.hborder);
+        w.put(&self.vborder);
+        w.put(&self.cc_register);
+        w.put(&self.clock_divisor);
+        w.put(&self.clock_mode);
+        w.put(&self.char_clock);
+        w.put(&self.char_clock_mask);
+        w.put(&self.char_clock_odd_mask);
+        w.put(&self.beam_x);
+        w.put(&self.beam_y);
+        w.put(&self.in_monitor_hsync);
+        w.put(&self.in_monitor_vblank);
+        w.put(&self.monitor_hsc);
+        w.put(&self.scanline);
+        w.put(&self.missed_hsyncs);
+        w.put(&self.overscan_left);
+        w.put(&self.overscan_right_start);
+        w.put(&self.overscan_right);
+        w.put(&self.vsync_len);
+        w.put(&self.in_display_area);
+        w.put(&self.cur_char);
+        w.put(&self.cur_attr);
+        w.put(&self.cur_fg);
+        w.put(&self.cur_bg);
+        w.put(&self.cur_blink);
+        w.put(&self.char_col);
+        w.put(&self.hcc_c0);
+        w.put(&self.vlc_c9);
+        w.put(&self.vcc_c4);
+        w.put(&self.last_row);
+        w.put(&self.vsc_c3h);
+        w.put(&self.hsc_c3l);
+        w.put(&self.vtac_c5);
+        w.put(&self.in_vta);
+        w.put(&self.effective_vta);
+        w.put(&self.vma);
+        w.put(&self.vma_t);
+        w.put(&self.vmws);
+        w.put(&self.rba);
+        w.put(&self.blink_state);
+        w.put(&self.blink_accum_us);
+        w.put(&self.blink_accum_clocks);
+        w.put(&self.accumulated_us);
+        w.put(&self.ticks_advanced);
+        w.put(&self.pixel_clocks_owed);
+        w.put(&self.ticks_accum);
+        w.put(&self.clocks_accum);
+        w.write_bytes(&self.mem[..]);
+        w.put(&self.lightpen_latch);
+        w.put(&self.lightpen_addr);
+        w.put(&self.video_array_address);
+        w.put(&self.palette_mask);
+        w.put(&self.border_color);
+        w.put(&self.mode_control.into_bytes()[0]);
+        w.put(&self.palette_registers);
+        w.put(&self.page_register.into_bytes()[0]);
+        self.extents.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.cycles = r.get()?;
+        self.last_vsync_cycles = r.get()?;
+        self.cur_screen_cycles = r.get()?;
+        self.cycles_per_vsync = r.get()?;
+        self.sink_cycles = r.get()?;
+        self.catching_up = r.get()?;
+        self.last_rw_tick = r.get()?;
+        self.dirty_snow = r.get()?;
+        self.snow_char = r.get()?;
+        self.last_bus_value = r.get()?;
+        self.last_bus_addr = r.get()?;
+        self.snow_count = r.get()?;
+        self.mode_pending = r.get()?;
+        self.clock_pending = r.get()?;
+        self.mode_byte = r.get()?;
+        self.display_mode = r.get()?;
+        self.mode_enable = r.get()?;
+        self.mode_graphics = r.get()?;
+        self.mode_bw = r.get()?;
+        self.mode_hires_gfx = r.get()?;
+        self.mode_hires_txt = r.get()?;
+        self.mode_blinking = r.get()?;
+        self.cc_palette = r.get()?;
+        self.cc_altcolor = r.get()?;
+        self.cc_overscan_color = r.get()?;
+        self.scanline_us = r.get()?;
+        self.frame_us = r.get()?;
+        self.cursor_frames = r.get()?;
+        self.frame_count = r.get()?;
+        self.status_reads = r.get()?;
+        self.cursor_status = r.get()?;
+        self.cursor_slowblink = r.get()?;
+        self.cursor_blink_rate = r.get()?;
+        self.cursor_data = r.get()?;
+        self.cursor_attr = r.get()?;
+        self.crtc_register_select_byte = r.get()?;
+        self.crtc_register_selected = r.get()?;
+        self.crtc_horizontal_total = r.get()?;
+        self.crtc_horizontal_displayed = r.get()?;
+        self.crtc_horizontal_sync_pos = r.get()?;
+        self.crtc_sync_width = r.get()?;
+        self.crtc_vertical_total = r.get()?;
+        self.crtc_vertical_total_adjust = r.get()?;
+        self.crtc_vertical_displayed = r.get()?;
+        self.crtc_vertical_sync_pos = r.get()?;
+        self.crtc_interlace_mode = r.get()?;
+        self.crtc_maximum_scanline_address = r.get()?;
+        self.crtc_cursor_start_line = r.get()?;
+        self.crtc_cursor_end_line = r.get()?;
+        self.crtc_start_address = r.get()?;
+        self.crtc_start_address_ho = r.get()?;
+        self.crtc_start_address_lo = r.get()?;
+        self.crtc_cursor_address_lo = r.get()?;
+        self.crtc_cursor_address_ho = r.get()?;
+        self.crtc_cursor_address = r.get()?;
+        self.crtc_frame_address = r.get()?;
+        self.in_crtc_hblank = r.get()?;
+        self.in_crtc_vblank = r.get()?;
+        self.in_crtc_vsync = r.get()?;
+        self.in_last_vblank_line = r.get()?;
+        self.hborder = r.get()?;
+        self.vborder = r.get()?;
+        self.cc_register = r.get()?;
+        self.clock_divisor = r.get()?;
+        self.clock_mode = r.get()?;
+        self.char_clock = r.get()?;
+        self.char_clock_mask = r.get()?;
+        self.char_clock_odd_mask = r.get()?;
+        self.beam_x = r.get()?;
+        self.beam_y = r.get()?;
+        self.in_monitor_hsync = r.get()?;
+        self.in_monitor_vblank = r.get()?;
+        self.monitor_hsc = r.get()?;
+        self.scanline = r.get()?;
+        self.missed_hsyncs = r.get()?;
+        self.overscan_left = r.get()?;
+        self.overscan_right_start = r.get()?;
+        self.overscan_right = r.get()?;
+        self.vsync_len = r.get()?;
+        self.in_display_area = r.get()?;
+        self.cur_char = r.get()?;
+        self.cur_attr = r.get()?;
+        self.cur_fg = r.get()?;
+        self.cur_bg = r.get()?;
+        self.cur_blink = r.get()?;
+        self.char_col = r.get()?;
+        self.hcc_c0 = r.get()?;
+        self.vlc_c9 = r.get()?;
+        self.vcc_c4 = r.get()?;
+        self.last_row = r.get()?;
+        self.vsc_c3h = r.get()?;
+        self.hsc_c3l = r.get()?;
+        self.vtac_c5 = r.get()?;
+        self.in_vta = r.get()?;
+        self.effective_vta = r.get()?;
+        self.vma = r.get()?;
+        self.vma_t = r.get()?;
+        self.vmws = r.get()?;
+        self.rba = r.get()?;
+        self.blink_state = r.get()?;
+        self.blink_accum_us = r.get()?;
+        self.blink_accum_clocks = r.get()?;
+        self.accumulated_us = r.get()?;
+        self.ticks_advanced = r.get()?;
+        self.pixel_clocks_owed = r.get()?;
+        self.ticks_accum = r.get()?;
+        self.clocks_accum = r.get()?;
+        r.read_bytes_into(&mut self.mem[..])?;
+        self.lightpen_latch = r.get()?;
+        self.lightpen_addr = r.get()?;
+        self.video_array_address = r.get()?;
+        self.palette_mask = r.get()?;
+        self.border_color = r.get()?;
+        self.mode_control = TModeControlRegister::from_bytes([r.get()?]);
+        self.palette_registers = r.get()?;
+        self.page_register = TPageRegister::from_bytes([r.get()?]);
+        self.extents.load_state(r)?;
+        self.slot_idx = 0;
+        Ok(())
+    }
+}
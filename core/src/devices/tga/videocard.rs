@@ -31,13 +31,7 @@
 */
 
 use super::*;
-use crate::{
-    device_traits::videocard::*,
-    devices::{
-        ega::{ClockSelect, EGACard},
-        pic::Pic,
-    },
-};
+use crate::{device_traits::videocard::*, devices::pic::Pic};
 
 // Helper macro for pushing video card state entries.
 // For CGA, we put the decorator first as there is only one register file an we use it to show the register index.
@@ -142,6 +136,8 @@ impl VideoCard for TGACard {
         Some((self.beam_x, self.beam_y))
     }
 
+    fn set_light_pen(&mut self, _pos: Option<(u32, u32)>, _switch: bool) {}
+
     /// Tick the CGA the specified number of video clock cycles.
     fn debug_tick(&mut self, ticks: u32) {
         match self.clock_mode {
@@ -192,6 +188,10 @@ impl VideoCard for TGACard {
         }
     }
 
+    fn get_palette(&self) -> Option<&[[u8; 4]]> {
+        None
+    }
+
     /// Get the current scanline being rendered.
     fn get_scanline(&self) -> u32 {
         self.scanline
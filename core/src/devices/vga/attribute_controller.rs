@@ -0,0 +1,590 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    ---------------------------------------------------------------------------
+
+    vga::attribute_controller.rs
+
+    Implements the VGA Attribute Controller
+
+*/
+
+use super::*;
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateWriter};
+
+#[derive(Copy, Clone, Debug)]
+pub enum AttributeRegister {
+    Palette0,
+    Palette1,
+    Palette2,
+    Palette3,
+    Palette4,
+    Palette5,
+    Palette6,
+    Palette7,
+    Palette8,
+    Palette9,
+    PaletteA,
+    PaletteB,
+    PaletteC,
+    PaletteD,
+    PaletteE,
+    PaletteF,
+    ModeControl,
+    OverscanColor,
+    ColorPlaneEnable,
+    HorizontalPelPanning,
+    ColorSelect,
+}
+
+#[derive(Debug)]
+pub enum AttributeRegisterFlipFlop {
+    Address,
+    Data,
+}
+
+#[derive(Debug, BitfieldSpecifier)]
+pub enum AttributeMode {
+    Text,
+    Graphics,
+}
+
+#[derive(Debug, BitfieldSpecifier)]
+pub enum AttributeDisplayType {
+    Color,
+    Monochrome,
+}
+
+#[derive(Debug, BitfieldSpecifier)]
+pub enum AttributeBlinkOrIntensity {
+    BackgroundIntensity,
+    Blink,
+}
+
+#[derive(Debug, BitfieldSpecifier)]
+pub enum PaletteAddressSource {
+    HostAccess,
+    DisplayAccess,
+}
+
+#[derive(Debug, BitfieldSpecifier)]
+pub enum PixelClock {
+    EveryCycle,
+    EveryOtherCycle,
+}
+
+#[derive(Debug, BitfieldSpecifier)]
+pub enum PaletteSize {
+    PaletteRegister45,
+    ColorRegister45,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct AttributeAddress {
+    pub address: B5,
+    #[bits = 1]
+    pub palette_address_source: PaletteAddressSource,
+    #[skip]
+    unused: B2,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct AModeControl {
+    #[bits = 1]
+    pub mode: AttributeMode,
+    #[bits = 1]
+    pub display_type: AttributeDisplayType,
+    pub enable_line_character_codes: bool,
+    #[bits = 1]
+    pub enable_blink_or_intensity: AttributeBlinkOrIntensity,
+    #[skip]
+    unused: B1,
+    pub pel_panning_compatibility: bool,
+    #[bits = 1]
+    pub pixel_clock_select: PixelClock,
+    #[bits = 1]
+    pub internal_palette_size: PaletteSize,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct AColorPlaneEnable {
+    pub enable_plane: B4,
+    pub video_status_mux: B2,
+    #[skip]
+    unused: B2,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct AColorSelect {
+    pub c45: B2,
+    pub c67: B2,
+    #[skip]
+    unused: B4,
+}
+
+pub enum AttributeInput<'a> {
+    Black,
+    Border,
+    Serial(&'a [u8]),
+    /// A glyph row span, attribute byte, cursor status, and whether the glyph is a line-drawing character.
+    Parallel64(u64, u8, bool, bool),
+}
+
+pub struct AttributeController {
+    register_flipflop: AttributeRegisterFlipFlop,
+    address: AttributeAddress,
+    register_selected: AttributeRegister,
+    palette_registers: [u8; 16],
+    palette_dac: [u8; 16],
+    palette_index: usize,
+    mode_control: AModeControl,
+    overscan_color: u8,
+    color_plane_enable: AColorPlaneEnable,
+    pel_panning: u8,
+    color_select: AColorSelect,
+    blink_state: bool,
+    last_den: bool,
+    shift_reg: u128,
+    ninth_reg: u16,
+}
+
+impl Default for AttributeController {
+    fn default() -> Self {
+        Self {
+            register_flipflop: AttributeRegisterFlipFlop::Address,
+            address: AttributeAddress::new(),
+            register_selected: AttributeRegister::Palette0,
+            palette_registers: [0; 16],
+            palette_dac: [0; 16],
+            palette_index: 0,
+            mode_control: AModeControl::new(),
+            overscan_color: 0,
+            color_plane_enable: AColorPlaneEnable::new(),
+            pel_panning: 0,
+            color_select: AColorSelect::new(),
+            blink_state: false,
+            last_den: false,
+            shift_reg: 0,
+            ninth_reg: 0,
+        }
+    }
+}
+
+impl AttributeController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn reset_flipflop(&mut self) {
+        self.register_flipflop = AttributeRegisterFlipFlop::Address;
+    }
+
+    /// Handle a write to the Attribute Register 0x3C0.
+    ///
+    /// Unlike the other register files on the VGA, the Attribute Register doesn't have an
+    /// address port. Instead, it maintains a flipflop that determines whether the port 0x3C0
+    /// is in address or data mode. The flipflop is reset to a known state by reading 0x3DA.
+    pub fn write_attribute_register(&mut self, byte: u8) {
+        match self.register_flipflop {
+            AttributeRegisterFlipFlop::Address => {
+                self.address = AttributeAddress::from_bytes([byte & 0x3F]);
+                self.palette_index = (self.address.address() & 0x0F) as usize;
+                self.register_selected = match self.address.address() {
+                    0x00 => AttributeRegister::Palette0,
+                    0x01 => AttributeRegister::Palette1,
+                    0x02 => AttributeRegister::Palette2,
+                    0x03 => AttributeRegister::Palette3,
+                    0x04 => AttributeRegister::Palette4,
+                    0x05 => AttributeRegister::Palette5,
+                    0x06 => AttributeRegister::Palette6,
+                    0x07 => AttributeRegister::Palette7,
+                    0x08 => AttributeRegister::Palette8,
+                    0x09 => AttributeRegister::Palette9,
+                    0x0A => AttributeRegister::PaletteA,
+                    0x0B => AttributeRegister::PaletteB,
+                    0x0C => AttributeRegister::PaletteC,
+                    0x0D => AttributeRegister::PaletteD,
+                    0x0E => AttributeRegister::PaletteE,
+                    0x0F => AttributeRegister::PaletteF,
+                    0x10 => AttributeRegister::ModeControl,
+                    0x11 => AttributeRegister::OverscanColor,
+                    0x12 => AttributeRegister::ColorPlaneEnable,
+                    0x13 => AttributeRegister::HorizontalPelPanning,
+                    0x14 => AttributeRegister::ColorSelect,
+                    _ => {
+                        log::warn!("Invalid attribute register selected: {:02X}", byte);
+                        self.register_selected
+                    }
+                };
+
+                self.register_flipflop = AttributeRegisterFlipFlop::Data;
+            }
+            AttributeRegisterFlipFlop::Data => {
+                match self.register_selected {
+                    AttributeRegister::Palette0
+                    | AttributeRegister::Palette1
+                    | AttributeRegister::Palette2
+                    | AttributeRegister::Palette3
+                    | AttributeRegister::Palette4
+                    | AttributeRegister::Palette5
+                    | AttributeRegister::Palette6
+                    | AttributeRegister::Palette7
+                    | AttributeRegister::Palette8
+                    | AttributeRegister::Palette9
+                    | AttributeRegister::PaletteA
+                    | AttributeRegister::PaletteB
+                    | AttributeRegister::PaletteC
+                    | AttributeRegister::PaletteD
+                    | AttributeRegister::PaletteE
+                    | AttributeRegister::PaletteF => {
+                        // The palette registers can only be written while the palette address source is set to
+                        // host access.
+                        if let PaletteAddressSource::HostAccess = self.address.palette_address_source() {
+                            self.palette_registers[self.palette_index] = byte & 0x3F;
+                        }
+                    }
+                    AttributeRegister::ModeControl => {
+                        self.mode_control = AModeControl::from_bytes([byte]);
+                    }
+                    AttributeRegister::OverscanColor => {
+                        self.overscan_color = byte;
+                    }
+                    AttributeRegister::ColorPlaneEnable => {
+                        self.color_plane_enable = AColorPlaneEnable::from_bytes([byte & 0x3F]);
+                    }
+                    AttributeRegister::HorizontalPelPanning => {
+                        self.pel_panning = byte & 0x0F;
+                    }
+                    AttributeRegister::ColorSelect => {
+                        self.color_select = AColorSelect::from_bytes([byte & 0x0F]);
+                    }
+                }
+                self.recalculate_palette();
+
+                // IBM: "The flip-flop toggles each time an OUT is issued to the Attribute Controller"
+                self.register_flipflop = AttributeRegisterFlipFlop::Address;
+            }
+        }
+    }
+
+    /// Handle a read of the Attribute Address register at 0x3C0.
+    pub fn read_address(&self) -> u8 {
+        self.address.into_bytes()[0]
+    }
+
+    /// Handle a read of the Attribute Data register at 0x3C1. Reads do not toggle the flip-flop.
+    pub fn read_data(&self) -> u8 {
+        match self.register_selected {
+            AttributeRegister::ModeControl => self.mode_control.into_bytes()[0],
+            AttributeRegister::OverscanColor => self.overscan_color,
+            AttributeRegister::ColorPlaneEnable => self.color_plane_enable.into_bytes()[0],
+            AttributeRegister::HorizontalPelPanning => self.pel_panning,
+            AttributeRegister::ColorSelect => self.color_select.into_bytes()[0],
+            _ => self.palette_registers[self.palette_index],
+        }
+    }
+
+    /// Resolve the 16 palette registers into 8-bit DAC indices. Bits 4 and 5 of each entry are optionally
+    /// replaced by the Color Select register, and bits 6 and 7 are always supplied by the Color Select register.
+    fn recalculate_palette(&mut self) {
+        for (i, entry) in self.palette_registers.iter().enumerate() {
+            let mut dac_index = *entry & 0x3F;
+            if let PaletteSize::ColorRegister45 = self.mode_control.internal_palette_size() {
+                dac_index = (dac_index & 0x0F) | (self.color_select.c45() << 4);
+            }
+            self.palette_dac[i] = dac_index | (self.color_select.c67() << 6);
+        }
+    }
+
+    pub fn mode(&self) -> AttributeMode {
+        self.mode_control.mode()
+    }
+
+    /// Return whether the attribute controller assembles 8-bit pixels for 256-color modes.
+    #[inline]
+    pub fn eight_bit_mode(&self) -> bool {
+        matches!(self.mode_control.pixel_clock_select(), PixelClock::EveryOtherCycle)
+    }
+
+    pub fn display_type(&self) -> AttributeDisplayType {
+        self.mode_control.display_type()
+    }
+
+    #[inline]
+    pub fn overscan_color(&self) -> u8 {
+        self.overscan_color
+    }
+
+    /// Return whether the display is currently driven by the palette. When the palette address source bit is
+    /// cleared, the host has access to the palette and the screen shows the overscan color.
+    #[inline]
+    pub fn display_enabled(&self) -> bool {
+        matches!(self.address.palette_address_source(), PaletteAddressSource::DisplayAccess)
+    }
+
+    pub fn set_blink_state(&mut self, state: bool) {
+        self.blink_state = state;
+    }
+
+    /// Load the attribute controller with a new AttributeInput.
+    /// Should be called after shift_outX to make room for the new character clock worth of data.
+    pub fn load(&mut self, input: AttributeInput, den: bool) {
+        let mut ai = input;
+        // The attribute controller will emit the border color when display enable is low.
+        if !den && (den == self.last_den) {
+            // Delay border by one character clock, as on the EGA.
+            ai = AttributeInput::Border;
+        }
+        self.last_den = den;
+
+        if !self.display_enabled() && !matches!(ai, AttributeInput::Black) {
+            ai = AttributeInput::Border;
+        }
+
+        match ai {
+            AttributeInput::Black => {
+                // If we do nothing - black will be produced
+            }
+            AttributeInput::Border => {
+                // In border area, shift in overscan color
+                self.shift_reg |= VGA_COLORS_U64[self.overscan_color as usize] as u128;
+                self.ninth_reg |= self.overscan_color as u16;
+            }
+            AttributeInput::Serial(data) if self.eight_bit_mode() => {
+                // In 8-bit mode, pairs of 4-bit pixels are assembled into one 8-bit pixel, which is held for two
+                // dot clocks. The palette registers still map each 4-bit half, but the color select register does not
+                // apply.
+                let mut last_color = 0;
+                for (i, pair) in data.chunks_exact(2).enumerate() {
+                    let hi = self.palette_registers[(pair[0] & self.color_plane_enable.enable_plane()) as usize];
+                    let lo = self.palette_registers[(pair[1] & self.color_plane_enable.enable_plane()) as usize];
+                    last_color = ((hi & 0x0F) << 4) | (lo & 0x0F);
+                    let pel = (last_color as u128) << 8 | last_color as u128;
+                    self.shift_reg |= pel << ((3 - i) * 16);
+                }
+                self.ninth_reg |= last_color as u16;
+            }
+            AttributeInput::Serial(data) => {
+                let mut last_color = 0;
+                for (i, byte) in data.iter().enumerate() {
+                    let color = *byte & self.color_plane_enable.enable_plane();
+                    last_color = self.palette_dac[color as usize];
+                    self.shift_reg |= (last_color as u128) << ((7 - i) * 8);
+                }
+                self.ninth_reg |= last_color as u16;
+            }
+            AttributeInput::Parallel64(data, attr, cursor, line_char) => {
+                let resolved_glyph = if cursor { ALL_SET64 } else { data };
+                let (fg_color, bg_color) = self.resolve_attribute(attr);
+                self.shift_reg |=
+                    (resolved_glyph & VGA_COLORS_U64[fg_color] | !resolved_glyph & VGA_COLORS_U64[bg_color]) as u128;
+
+                // The ninth column of a 9-dot character duplicates the eighth for line-drawing characters when
+                // line graphics are enabled, and otherwise shows the background color.
+                let ninth_set =
+                    cursor || (line_char && self.mode_control.enable_line_character_codes() && (data & 0x01 != 0));
+                self.ninth_reg |= if ninth_set { fg_color as u16 } else { bg_color as u16 };
+            }
+        }
+    }
+
+    /// Return the horizontal pel panning shift for 8-dot characters.
+    #[inline]
+    fn pel_shift8(&self, split: bool) -> u32 {
+        if split && self.mode_control.pel_panning_compatibility() {
+            // Pel panning is disabled below the split screen when the compatibility bit is set.
+            return 0;
+        }
+        (self.pel_panning & 0x07) as u32
+    }
+
+    /// Return the horizontal pel panning shift for 9-dot characters. A value of 8 selects no shift, while values
+    /// 0-7 shift by one through eight pixels.
+    #[inline]
+    fn pel_shift9(&self, split: bool) -> usize {
+        if split && self.mode_control.pel_panning_compatibility() {
+            return 0;
+        }
+        match self.pel_panning {
+            0..=7 => self.pel_panning as usize + 1,
+            _ => 0,
+        }
+    }
+
+    /// Shift out a character clock of eight pixels.
+    pub fn shift_out64(&mut self, split: bool) -> [u8; 8] {
+        let out_data = ((self.shift_reg << (self.pel_shift8(split) * 8)) >> 64) as u64;
+
+        // Shift the attribute data 64 bits to make room for next character clock
+        self.shift_reg <<= 64;
+        self.ninth_reg <<= 8;
+
+        out_data.to_be_bytes()
+    }
+
+    /// Shift out a character clock of nine pixels. The ninth pixel of each character is held in a separate
+    /// register, so we assemble the two characters in the shift register into a pixel sequence before panning.
+    pub fn shift_out72(&mut self, split: bool) -> [u8; 9] {
+        let mut seq = [0u8; 18];
+        seq[0..8].copy_from_slice(&((self.shift_reg >> 64) as u64).to_be_bytes());
+        seq[8] = (self.ninth_reg >> 8) as u8;
+        seq[9..17].copy_from_slice(&(self.shift_reg as u64).to_be_bytes());
+        seq[17] = self.ninth_reg as u8;
+
+        let shift = self.pel_shift9(split);
+        let mut out_data = [0u8; 9];
+        out_data.copy_from_slice(&seq[shift..shift + 9]);
+
+        self.shift_reg <<= 64;
+        self.ninth_reg <<= 8;
+
+        out_data
+    }
+
+    /// Resolve an attribute byte into foreground and background DAC indices.
+    #[inline]
+    fn resolve_attribute(&self, attribute: u8) -> (usize, usize) {
+        let mut fg_index = (attribute & 0x0F) as usize;
+        let mut bg_index = (attribute >> 4) as usize;
+
+        // If blinking is enabled, the bg attribute is only 3 bits and only low-intensity colors
+        // are available. Blinking characters are drawn in the background color during the off phase.
+        // If blinking is disabled, all 16 colors are available as background attributes.
+        if let AttributeBlinkOrIntensity::Blink = self.mode_control.enable_blink_or_intensity() {
+            bg_index &= 0x07;
+            if (attribute & 0x80 != 0) && !self.blink_state {
+                fg_index = bg_index;
+            }
+        }
+
+        let plane_mask = self.color_plane_enable.enable_plane() as usize;
+        (
+            self.palette_dac[fg_index & plane_mask] as usize,
+            self.palette_dac[bg_index & plane_mask] as usize,
+        )
+    }
+
+    pub fn palette(&self, pel: u8) -> u8 {
+        self.palette_dac[(pel & 0x0F) as usize]
+    }
+
+    #[rustfmt::skip]
+    pub fn get_state(&self) -> Vec<(String, VideoCardStateEntry)> {
+        let mut attribute_vec = Vec::new();
+        attribute_vec.push((format!("{:?} [mode]", AttributeRegister::ModeControl), VideoCardStateEntry::String(format!("{:?}", self.mode_control.mode()))));
+        attribute_vec.push((format!("{:?} [disp]", AttributeRegister::ModeControl), VideoCardStateEntry::String(format!("{:?}", self.mode_control.display_type()))));
+        attribute_vec.push((format!("{:?} [elgc]", AttributeRegister::ModeControl), VideoCardStateEntry::String(format!("{:?}", self.mode_control.enable_line_character_codes()))));
+        attribute_vec.push((format!("{:?} [attr]", AttributeRegister::ModeControl), VideoCardStateEntry::String(format!("{:?}", self.mode_control.enable_blink_or_intensity()))));
+        attribute_vec.push((format!("{:?} [ppc]", AttributeRegister::ModeControl), VideoCardStateEntry::String(format!("{:?}", self.mode_control.pel_panning_compatibility()))));
+        attribute_vec.push((format!("{:?} [pcs]", AttributeRegister::ModeControl), VideoCardStateEntry::String(format!("{:?}", self.mode_control.pixel_clock_select()))));
+        attribute_vec.push((format!("{:?} [p54s]", AttributeRegister::ModeControl), VideoCardStateEntry::String(format!("{:?}", self.mode_control.internal_palette_size()))));
+        attribute_vec.push((format!("{:?}", AttributeRegister::OverscanColor), VideoCardStateEntry::String(format!("{:02X}", self.overscan_color))));
+        attribute_vec.push((format!("{:?} [en]", AttributeRegister::ColorPlaneEnable), VideoCardStateEntry::String(format!("{:04b}", self.color_plane_enable.enable_plane()))));
+        attribute_vec.push((format!("{:?} [mux]", AttributeRegister::ColorPlaneEnable), VideoCardStateEntry::String(format!("{:02b}", self.color_plane_enable.video_status_mux()))));
+        attribute_vec.push((format!("{:?}", AttributeRegister::HorizontalPelPanning), VideoCardStateEntry::String(format!("{}", self.pel_panning))));
+        attribute_vec.push((format!("{:?} [c45]", AttributeRegister::ColorSelect), VideoCardStateEntry::String(format!("{:02b}", self.color_select.c45()))));
+        attribute_vec.push((format!("{:?} [c67]", AttributeRegister::ColorSelect), VideoCardStateEntry::String(format!("{:02b}", self.color_select.c67()))));
+
+        attribute_vec
+    }
+
+    /// Return a list of the 16 palette registers and their resolved DAC indices.
+    pub fn get_palette_state(&self) -> Vec<(String, u8, u8)> {
+        self.palette_registers
+            .iter()
+            .zip(self.palette_dac.iter())
+            .enumerate()
+            .map(|(i, (reg, dac))| (format!("{:X}", i), *reg, *dac))
+            .collect()
+    }
+}
+
+impl_state_enum!(AttributeRegister {
+    Palette0,
+    Palette1,
+    Palette2,
+    Palette3,
+    Palette4,
+    Palette5,
+    Palette6,
+    Palette7,
+    Palette8,
+    Palette9,
+    PaletteA,
+    PaletteB,
+    PaletteC,
+    PaletteD,
+    PaletteE,
+    PaletteF,
+    ModeControl,
+    OverscanColor,
+    ColorPlaneEnable,
+    HorizontalPelPanning,
+    ColorSelect,
+});
+
+impl_state_enum!(AttributeRegisterFlipFlop { Address, Data });
+
+impl SaveState for AttributeController {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.register_flipflop);
+        w.put(&self.address.into_bytes()[0]);
+        w.put(&self.register_selected);
+        w.put(&self.palette_registers);
+        w.put(&self.palette_dac);
+        w.put(&self.palette_index);
+        w.put(&self.mode_control.into_bytes()[0]);
+        w.put(&self.overscan_color);
+        w.put(&self.color_plane_enable.into_bytes()[0]);
+        w.put(&self.pel_panning);
+        w.put(&self.color_select.into_bytes()[0]);
+        w.put(&self.blink_state);
+        w.put(&self.last_den);
+        w.put(&self.shift_reg);
+        w.put(&self.ninth_reg);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.register_flipflop = r.get()?;
+        self.address = AttributeAddress::from_bytes([r.get()?]);
+        self.register_selected = r.get()?;
+        self.palette_registers = r.get()?;
+        self.palette_dac = r.get()?;
+        self.palette_index = r.get()?;
+        self.mode_control = AModeControl::from_bytes([r.get()?]);
+        self.overscan_color = r.get()?;
+        self.color_plane_enable = AColorPlaneEnable::from_bytes([r.get()?]);
+        self.pel_panning = r.get()?;
+        self.color_select = AColorSelect::from_bytes([r.get()?]);
+        self.blink_state = r.get()?;
+        self.last_den = r.get()?;
+        self.shift_reg = r.get()?;
+        self.ninth_reg = r.get()?;
+        Ok(())
+    }
+}
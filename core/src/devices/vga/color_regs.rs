@@ -26,22 +26,41 @@
 
     vga::color_regs.rs
 
-    Implement the VGA Color registers.
+    Implement the VGA Color registers (the DAC).
 
 */
 
 use crate::devices::vga::*;
 
-pub const DAC_STATE_READ: u8 = 0;
-pub const DAC_STATE_WRITE: u8 = 0x03;
+// DAC State register values, as read from port 0x3C7.
+pub const DAC_STATE_WRITE: u8 = 0;
+pub const DAC_STATE_READ: u8 = 0x03;
+
+/// The DAC color registers are 6 bits per component.
+const DAC_COMPONENT_MASK: u8 = 0x3F;
 
 impl VGACard {
+    /// Handle a write to the PEL Address Write Mode register. This selects the color register to be written
+    /// by subsequent writes to the PEL Data register.
+    pub fn write_pel_write_address(&mut self, byte: u8) {
+        self.color_pel_write_address = byte;
+        self.color_pel_write_address_color = 0;
+        self.color_dac_state = DAC_STATE_WRITE;
+    }
+
+    /// Handle a write to the PEL Address Read Mode register. This selects the color register to be read
+    /// by subsequent reads of the PEL Data register.
+    pub fn write_pel_read_address(&mut self, byte: u8) {
+        self.color_pel_read_address = byte;
+        self.color_pel_read_address_color = 0;
+        self.color_dac_state = DAC_STATE_READ;
+    }
+
     pub fn read_pel_data(&mut self) -> u8 {
-        let byte;
         let color = self.color_pel_read_address as usize;
         let rgb_idx = self.color_pel_read_address_color as usize;
 
-        byte = self.color_registers[color][rgb_idx];
+        let byte = self.color_registers[color][rgb_idx];
 
         // Automatically increment to next color register, cycling through
         // Red, Green and Blue registers per Read Index
@@ -59,25 +78,29 @@ impl VGACard {
             self.color_pel_read_address = self.color_pel_read_address.wrapping_add(1);
         }
 
-        self.color_dac_state = DAC_STATE_READ;
         byte
     }
 
+    /// Handle a write to the PEL Data register. The DAC latches the red and green components and updates
+    /// the color register all at once when the blue component is written.
     pub fn write_pel_data(&mut self, byte: u8) {
         let color = self.color_pel_write_address as usize;
         let rgb_idx = self.color_pel_write_address_color as usize;
 
-        self.color_registers[color][rgb_idx] = byte;
+        self.color_pel_write_latch[rgb_idx] = byte & DAC_COMPONENT_MASK;
 
         // Automatically increment to next color register, cycling through
         // Red, Green and Blue registers per Read Index
         self.color_pel_write_address_color += 1;
         if self.color_pel_write_address_color == 3 {
+            self.color_registers[color] = self.color_pel_write_latch;
+
             // Save converted RGBA palette entries along with native ones
             self.color_registers_rgba[color][0] = ((self.color_registers[color][0] as u32 * 255) / 63) as u8;
             self.color_registers_rgba[color][1] = ((self.color_registers[color][1] as u32 * 255) / 63) as u8;
             self.color_registers_rgba[color][2] = ((self.color_registers[color][2] as u32 * 255) / 63) as u8;
             self.color_registers_rgba[color][3] = 0xFF;
+            self.update_dac_output(Some(color));
 
             trace!(
                 self,
@@ -88,18 +111,27 @@ impl VGACard {
                 self.color_registers[color][2]
             );
 
-            /*
-            log::trace!("Wrote color register [{}] ({:02X},{:02X},{:02X})",
-                color,
-                self.color_registers[color][0],
-                self.color_registers[color][1],
-                self.color_registers[color][2]);
-            */
             self.color_pel_write_address_color = 0;
             // Done with all colors, so go to next palette entry
             self.color_pel_write_address = self.color_pel_write_address.wrapping_add(1);
         }
+    }
 
-        self.color_dac_state = DAC_STATE_WRITE;
+    /// Handle a write to the PEL Mask register. Each 8-bit pixel value is ANDed with the mask before it
+    /// selects a color register.
+    pub fn write_pel_mask(&mut self, byte: u8) {
+        self.color_pel_mask = byte;
+        self.update_dac_output(None);
+    }
+
+    /// Update the DAC output table, which maps each 8-bit pixel value to the RGBA color produced after the
+    /// PEL mask is applied. If a color register is specified, only the pixel values that select it are updated.
+    fn update_dac_output(&mut self, color: Option<usize>) {
+        for pel in 0..self.color_registers_out.len() {
+            let index = pel & self.color_pel_mask as usize;
+            if color.map_or(true, |c| c == index) {
+                self.color_registers_out[pel] = self.color_registers_rgba[index];
+            }
+        }
     }
 }
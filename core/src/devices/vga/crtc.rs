@@ -0,0 +1,1269 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    ---------------------------------------------------------------------------
+
+    vga::crtc.rs
+
+    Implement the VGA CRTC logic
+
+*/
+
+use super::*;
+use crate::savestate::{impl_state_enum, SaveState, StateReader, StateValue, StateWriter};
+
+pub const VGA_VBLANK_MASK: u16 = 0x007F;
+pub const VGA_VSYNC_MASK: u16 = 0x000F;
+pub const VGA_HBLANK_MASK: u16 = 0x003F;
+pub const VGA_HSYNC_MASK: u16 = 0x001F;
+pub const VGA_HSLC_MASK: u16 = 0x03FF;
+
+const DEFAULT_CURSOR_START_LINE: u8 = 13;
+const DEFAULT_CURSOR_END_LINE: u8 = 14;
+const DEFAULT_HORIZONTAL_TOTAL: u8 = 95;
+const DEFAULT_HORIZONTAL_DISPLAYED: u8 = 79;
+const DEFAULT_HORIZONTAL_BLANK_START: u8 = 80;
+const DEFAULT_HORIZONTAL_RETRACE_START: u8 = 85;
+
+const DEFAULT_VERTICAL_TOTAL: u16 = 447;
+const DEFAULT_VERTICAL_RETRACE_START: u16 = 412;
+const DEFAULT_VERTICAL_DISPLAY_END: u16 = 399;
+const DEFAULT_VERTICAL_BLANK_START: u16 = 406;
+const DEFAULT_LINE_COMPARE: u16 = 0x3FF;
+const DEFAULT_MAX_SCANLINE: u8 = 15;
+const DEFAULT_OFFSET: u8 = 40;
+
+const CURSOR_LINE_MASK: u8 = 0b0001_1111;
+const AC_LATENCY: u8 = 1;
+
+// The VGA's horizontal total is programmed as the number of character clocks minus five.
+const HORIZONTAL_TOTAL_ADJUST: u16 = 5;
+
+// Helper macro for pushing video card state entries.
+macro_rules! push_reg_str {
+    ($vec: expr, $reg: expr, $decorator: expr, $val: expr ) => {
+        $vec.push((
+            format!("{} {:?}", $decorator, $reg),
+            VideoCardStateEntry::String(format!("{}", $val)),
+        ))
+    };
+}
+
+#[derive(Debug)]
+pub enum CRTCRegister {
+    HorizontalTotal,
+    HorizontalDisplayEnd,
+    StartHorizontalBlank,
+    EndHorizontalBlank,
+    StartHorizontalRetrace,
+    EndHorizontalRetrace,
+    VerticalTotal,
+    Overflow,
+    PresetRowScan,
+    MaximumScanLine,
+    CursorStartLine,
+    CursorEndLine,
+    StartAddressH,
+    StartAddressL,
+    CursorAddressH,
+    CursorAddressL,
+    VerticalRetraceStart,
+    VerticalRetraceEnd,
+    VerticalDisplayEnd,
+    Offset,
+    UnderlineLocation,
+    StartVerticalBlank,
+    EndVerticalBlank,
+    ModeControl,
+    LineCompare,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CCursorStart {
+    pub cursor_start: B5,
+    pub cursor_disable: bool,
+    #[skip]
+    unused: B2,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CCursorEnd {
+    pub cursor_end: B5,
+    pub cursor_skew: B2,
+    #[skip]
+    unused: B1,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CEndHorizontalBlank {
+    pub end_horizontal_blank: B5,
+    pub display_enable_skew: B2,
+    pub compatible_read: bool,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CEndHorizontalRetrace {
+    pub end_horizontal_retrace: B5,
+    pub horizontal_retrace_delay: B2,
+    pub ehb_bit_5: B1,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CPresetRowScan {
+    pub preset_row_scan: B5,
+    pub byte_panning: B2,
+    #[skip]
+    unused: B1,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CMaximumScanline {
+    pub maximum_scanline: B5,
+    pub svb_bit_9: B1,
+    pub lc_bit_9: B1,
+    pub two_to_four: bool,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CVerticalRetraceEnd {
+    pub vertical_retrace_end: B4,
+    pub cvi: B1,
+    pub dvi: B1,
+    pub bandwidth: B1,
+    pub protect_registers: bool,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CUnderlineLocation {
+    pub underline_location: B5,
+    pub count_by_four: bool,
+    pub double_word_mode: bool,
+    #[skip]
+    unused: B1,
+}
+
+#[derive(Debug, BitfieldSpecifier)]
+pub enum WordOrByteMode {
+    Word,
+    Byte,
+}
+
+#[derive(Debug, BitfieldSpecifier)]
+pub enum CompatibilityMode {
+    Cga,
+    Ega,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct CModeControl {
+    pub compatibility_mode: CompatibilityMode,
+    pub select_row_scan_counter: B1,
+    pub horizontal_retrace_select: B1,
+    pub count_by_two: bool,
+    #[skip]
+    unused: B1,
+    pub address_wrap: B1,
+    pub word_or_byte_mode: WordOrByteMode,
+    pub hardware_reset: B1,
+}
+
+#[derive(Copy, Clone, Default, Debug)]
+pub struct CrtcStatus {
+    pub begin_hsync: bool,
+    pub begin_vsync: bool,
+    pub hsync: bool,
+    pub vsync: bool,
+    pub hblank: bool,
+    pub vblank: bool,
+    pub hborder: bool,
+    pub vborder: bool,
+    pub den: bool,
+    pub den_skew: bool,
+    pub cursor: bool,
+    pub cref: bool,
+    pub split: bool,
+}
+
+pub struct VgaCrtc {
+    // CRTC registers
+    register_select_byte: u8,
+    register_selected:    CRTCRegister,
+
+    crtc_horizontal_total: u8,                          // R(0) Horizontal Total
+    crtc_horizontal_display_end: u8,                    // R(1) Horizontal Display End
+    crtc_start_horizontal_blank: u8,                    // R(2) Start Horizontal Blank
+    crtc_end_horizontal_blank: CEndHorizontalBlank,     // R(3) Bits 0-4 - End Horizontal Blank
+    crtc_end_horizontal_blank_6: u16,                   // End Horizontal Blank, 6-bit value including R(5) bit 7
+    crtc_start_horizontal_retrace: u8,                  // R(4) Start Horizontal Retrace
+    crtc_end_horizontal_retrace: CEndHorizontalRetrace, // R(5) End Horizontal Retrace
+    crtc_vertical_total: u16,                           // R(6) Vertical Total (10-bit value)
+    crtc_overflow: u8,                                  // R(7) Overflow
+    crtc_preset_row_scan: CPresetRowScan,               // R(8) Preset Row Scan
+    crtc_maximum_scanline: CMaximumScanline,            // R(9) Max Scanline
+    crtc_cursor_start: CCursorStart,                    // R(A) Cursor Start Line
+    crtc_cursor_end: CCursorEnd,                        // R(B) Cursor End Line
+    crtc_start_address_ho: u8,                          // R(C)
+    crtc_start_address_lo: u8,                          // R(D)
+    crtc_start_address: u16,                            // Calculated from C&D
+    start_address_latch: u16,
+    crtc_cursor_address_lo: u8, // R(E)
+    crtc_cursor_address_ho: u8, // R(F)
+    crtc_cursor_address: u16,
+    crtc_vertical_retrace_start: u16, // R(10) Vertical Retrace Start (10-bit value)
+    crtc_vertical_retrace_end: CVerticalRetraceEnd, // R(11) Vertical Retrace End
+    crtc_vertical_display_end: u16,   // R(12) Vertical Display Enable End (10-bit value)
+    crtc_offset: u8,                  // R(13)
+    crtc_underline_location: CUnderlineLocation, // R(14)
+    crtc_start_vertical_blank: u16,   // R(15) Start Vertical Blank (10-bit value)
+    crtc_end_vertical_blank: u16,     // R(16) End Vertical Blank (7-bit value)
+    crtc_mode_control: CModeControl,  // R(17)
+    crtc_line_compare: u16,           // R(18) Line Compare (10-bit value)
+
+    // CRTC internal counters
+    hcc: u16, // Horizontal character counter (x pos of character)
+    vlc: u8,  // Vertical line counter - row of character being drawn
+    vcc: u8,  // Vertical character counter (y pos of character)
+    slc: u16, // Scanline counter - increments after reaching vertical total
+    hsc: u8,  // Horizontal sync counter - counts during hsync period
+    in_hrd: bool,
+    hrdc: u8,
+    vma: u16,             // VMA register - Video memory address
+    vma_sl: u16,          // VMA of start of scanline
+    vma_cc: u8,           // Character clock counter for count-by-two and count-by-four modes
+    double_scan: bool,    // Whether the current scanline is the repeated half of a double-scanned line
+    den_skew_front: bool, // Display enable skew control for front porch
+    den_skew_back: bool,  // Display enable skew control for back porch
+    dsc: u8,              // Display enable skew counter
+
+    pub status: CrtcStatus,
+    blink_state: bool,
+    monitor_hsync: bool,
+    in_last_vblank_line: bool,
+    cursor_data: [bool; VGA_CURSOR_MAX],
+    frame: u64,
+}
+
+impl Default for VgaCrtc {
+    fn default() -> Self {
+        let mut crtc = Self {
+            // CRTC registers
+            register_selected:    CRTCRegister::HorizontalTotal,
+            register_select_byte: 0,
+
+            crtc_horizontal_total: DEFAULT_HORIZONTAL_TOTAL,
+            crtc_horizontal_display_end: DEFAULT_HORIZONTAL_DISPLAYED,
+            crtc_start_horizontal_blank: DEFAULT_HORIZONTAL_BLANK_START,
+            crtc_end_horizontal_blank: CEndHorizontalBlank::new(),
+            crtc_end_horizontal_blank_6: 0,
+            crtc_start_horizontal_retrace: DEFAULT_HORIZONTAL_RETRACE_START,
+            crtc_end_horizontal_retrace: CEndHorizontalRetrace::new(),
+            crtc_vertical_total: DEFAULT_VERTICAL_TOTAL,
+            crtc_overflow: 0,
+            crtc_preset_row_scan: CPresetRowScan::new(),
+            crtc_maximum_scanline: CMaximumScanline::new().with_maximum_scanline(DEFAULT_MAX_SCANLINE),
+            crtc_cursor_start: CCursorStart::new().with_cursor_start(DEFAULT_CURSOR_START_LINE),
+            crtc_cursor_end: CCursorEnd::new().with_cursor_end(DEFAULT_CURSOR_END_LINE),
+            crtc_start_address: 0,
+            crtc_start_address_ho: 0,
+            crtc_start_address_lo: 0,
+            start_address_latch: 0,
+            crtc_cursor_address_lo: 0,
+            crtc_cursor_address_ho: 0,
+            crtc_cursor_address: 0,
+            crtc_vertical_retrace_start: DEFAULT_VERTICAL_RETRACE_START,
+            crtc_vertical_retrace_end: CVerticalRetraceEnd::new(),
+            crtc_vertical_display_end: DEFAULT_VERTICAL_DISPLAY_END,
+            crtc_offset: DEFAULT_OFFSET,
+            crtc_underline_location: CUnderlineLocation::new(),
+            crtc_start_vertical_blank: DEFAULT_VERTICAL_BLANK_START,
+            crtc_end_vertical_blank: 0,
+            crtc_mode_control: CModeControl::new(),
+            crtc_line_compare: DEFAULT_LINE_COMPARE,
+
+            hcc: 0,
+            vlc: 0,
+            vcc: 0,
+            slc: 0,
+            hsc: 0,
+            in_hrd: false,
+            hrdc: 0,
+            vma: 0,
+            vma_sl: 0,
+            vma_cc: 0,
+            double_scan: false,
+            den_skew_front: false,
+            den_skew_back: false,
+            dsc: 0,
+
+            status: CrtcStatus::default(),
+            blink_state: false,
+            monitor_hsync: false,
+            in_last_vblank_line: false,
+            cursor_data: [false; VGA_CURSOR_MAX],
+            frame: 0,
+        };
+        crtc.update_cursor_data();
+        crtc
+    }
+}
+
+impl VgaCrtc {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn write_crtc_register_address(&mut self, byte: u8) {
+        self.register_select_byte = byte & 0x1F;
+
+        self.register_selected = match self.register_select_byte {
+            0x00 => CRTCRegister::HorizontalTotal,
+            0x01 => CRTCRegister::HorizontalDisplayEnd,
+            0x02 => CRTCRegister::StartHorizontalBlank,
+            0x03 => CRTCRegister::EndHorizontalBlank,
+            0x04 => CRTCRegister::StartHorizontalRetrace,
+            0x05 => CRTCRegister::EndHorizontalRetrace,
+            0x06 => CRTCRegister::VerticalTotal,
+            0x07 => CRTCRegister::Overflow,
+            0x08 => CRTCRegister::PresetRowScan,
+            0x09 => CRTCRegister::MaximumScanLine,
+            0x0A => CRTCRegister::CursorStartLine,
+            0x0B => CRTCRegister::CursorEndLine,
+            0x0C => CRTCRegister::StartAddressH,
+            0x0D => CRTCRegister::StartAddressL,
+            0x0E => CRTCRegister::CursorAddressH,
+            0x0F => CRTCRegister::CursorAddressL,
+            0x10 => CRTCRegister::VerticalRetraceStart,
+            0x11 => CRTCRegister::VerticalRetraceEnd,
+            0x12 => CRTCRegister::VerticalDisplayEnd,
+            0x13 => CRTCRegister::Offset,
+            0x14 => CRTCRegister::UnderlineLocation,
+            0x15 => CRTCRegister::StartVerticalBlank,
+            0x16 => CRTCRegister::EndVerticalBlank,
+            0x17 => CRTCRegister::ModeControl,
+            0x18 => CRTCRegister::LineCompare,
+            _ => {
+                log::debug!("Select to invalid CRTC register: {:02X}", byte);
+                self.register_select_byte = 0;
+                CRTCRegister::HorizontalTotal
+            }
+        }
+    }
+
+    /// Write to one of the CRT Controller registers.
+    /// Returns a a tuple, a boolean representing whether the card should recalculate mode parameters after this write,
+    /// and a boolean representing whether the current interrupt status should be cleared.
+    pub fn write_crtc_register_data(&mut self, byte: u8) -> (bool, bool) {
+        let mut clear_intr = false;
+
+        // When the Protect bit of the Vertical Retrace End register is set, registers 0-7 are read-only,
+        // with the exception of the Line Compare bit in the Overflow register.
+        if self.crtc_vertical_retrace_end.protect_registers() && self.register_select_byte < 0x08 {
+            if let CRTCRegister::Overflow = self.register_selected {
+                self.crtc_overflow = (self.crtc_overflow & !0x10) | (byte & 0x10);
+                self.crtc_line_compare = (self.crtc_line_compare & !0x0100) | ((byte as u16 & 0x10) << 4);
+                return (true, false);
+            }
+            log::trace!("Write to protected CRTC register: {:?}", self.register_selected);
+            return (false, false);
+        }
+
+        match self.register_selected {
+            CRTCRegister::HorizontalTotal => {
+                // (R0) 8 bit
+                self.crtc_horizontal_total = byte;
+            }
+            CRTCRegister::HorizontalDisplayEnd => {
+                // (R1) 8 bit
+                self.crtc_horizontal_display_end = byte;
+            }
+            CRTCRegister::StartHorizontalBlank => {
+                // (R2) 8 bit
+                self.crtc_start_horizontal_blank = byte;
+            }
+            CRTCRegister::EndHorizontalBlank => {
+                // (R3)
+                // Bits 0-4: End Horizontal Blank bits 0-4
+                // Bits 5-6: Display Enable Skew
+                // Bit 7: Compatible Read (enables access to vertical retrace registers on the EGA)
+                self.crtc_end_horizontal_blank = CEndHorizontalBlank::from_bytes([byte]);
+                self.update_end_horizontal_blank();
+            }
+            CRTCRegister::StartHorizontalRetrace => {
+                // (R4)
+                self.crtc_start_horizontal_retrace = byte;
+            }
+            CRTCRegister::EndHorizontalRetrace => {
+                // (R5)
+                // Bits 0-4: End Horizontal Retrace
+                // Bits 5-6: Horizontal Retrace Skew
+                // Bit 7: End Horizontal Blank bit 5
+                self.crtc_end_horizontal_retrace = CEndHorizontalRetrace::from_bytes([byte]);
+                self.update_end_horizontal_blank();
+            }
+            CRTCRegister::VerticalTotal => {
+                // (R6) 10-bit - Vertical Total
+                // Bits 8 and 9 in overflow register. Set only lower 8 bits here.
+                self.crtc_vertical_total &= 0xFF00;
+                self.crtc_vertical_total |= byte as u16;
+            }
+            CRTCRegister::Overflow => {
+                // (R7)
+                self.crtc_overflow = byte;
+                self.set_crtc_overflow_bits(byte);
+            }
+            CRTCRegister::PresetRowScan => {
+                // (R8)
+                // Bits 0-4: Preset Row Scan
+                // Bits 5-6: Byte Panning
+                self.crtc_preset_row_scan = CPresetRowScan::from_bytes([byte]);
+            }
+            CRTCRegister::MaximumScanLine => {
+                // (R9)
+                // Bits 0-4: Maximum Scanline
+                // Bit 5: Start Vertical Blank bit 9
+                // Bit 6: Line Compare bit 9
+                // Bit 7: Scan Doubling
+                self.crtc_maximum_scanline = CMaximumScanline::from_bytes([byte]);
+                self.crtc_start_vertical_blank &= !0x0200;
+                self.crtc_start_vertical_blank |= (self.crtc_maximum_scanline.svb_bit_9() as u16) << 9;
+                self.crtc_line_compare &= !0x0200;
+                self.crtc_line_compare |= (self.crtc_maximum_scanline.lc_bit_9() as u16) << 9;
+                self.update_cursor_data();
+            }
+            CRTCRegister::CursorStartLine => {
+                // R(A)
+                // Bits 0-4: Cursor Start Line
+                // Bit 5: Cursor Disable
+                self.crtc_cursor_start = CCursorStart::from_bytes([byte & 0x3F]);
+                self.update_cursor_data();
+            }
+            CRTCRegister::CursorEndLine => {
+                // R(B)
+                // Bits 0-4: Cursor End Line
+                // Bits 5-6: Cursor Skew
+                self.crtc_cursor_end = CCursorEnd::from_bytes([byte & 0x7F]);
+                self.update_cursor_data();
+            }
+            CRTCRegister::StartAddressH => {
+                // (RC) - 8 bits. High byte of Start Address register.
+                self.crtc_start_address_ho = byte;
+                self.crtc_start_address &= 0x00FF;
+                self.crtc_start_address |= (byte as u16) << 8;
+            }
+            CRTCRegister::StartAddressL => {
+                // (RD) - 8 bits. Low byte of Start Address register.
+                self.crtc_start_address_lo = byte;
+                self.crtc_start_address &= 0xFF00;
+                self.crtc_start_address |= byte as u16;
+            }
+            CRTCRegister::CursorAddressH => {
+                // (RE) - 8 bits.  High byte of Cursor Address register
+                self.crtc_cursor_address_ho = byte;
+                self.crtc_cursor_address &= 0x00FF;
+                self.crtc_cursor_address |= (byte as u16) << 8;
+            }
+            CRTCRegister::CursorAddressL => {
+                // (RF) - 8 bits. Low byte of Cursor Address register.
+                self.crtc_cursor_address_lo = byte;
+                self.crtc_cursor_address &= 0xFF00;
+                self.crtc_cursor_address |= byte as u16;
+            }
+            CRTCRegister::VerticalRetraceStart => {
+                // (R10) 10 bits - Vertical Retrace Start
+                // Bits 8 and 9 in overflow register. Set only lower 8 bits here.
+                self.crtc_vertical_retrace_start &= 0xFF00;
+                self.crtc_vertical_retrace_start |= byte as u16;
+            }
+            CRTCRegister::VerticalRetraceEnd => {
+                // (R11) Vertical Retrace End
+                // Bits 0-3: Vertical Retrace End
+                // Bit 4: Clear Vertical Interrupt
+                // Bit 5: Disable Vertical Interrupt
+                // Bit 6: Memory Refresh Bandwidth (ignored)
+                // Bit 7: Protect registers 0-7
+                self.crtc_vertical_retrace_end = CVerticalRetraceEnd::from_bytes([byte]);
+
+                if self.crtc_vertical_retrace_end.cvi() == 0 {
+                    clear_intr = true;
+                }
+            }
+            CRTCRegister::VerticalDisplayEnd => {
+                // (R12) 10 bits - Vertical Display End
+                // Bits 8 and 9 in overflow register. Set only lower 8 bits here.
+                self.crtc_vertical_display_end &= 0xFF00;
+                self.crtc_vertical_display_end |= byte as u16;
+            }
+            CRTCRegister::Offset => {
+                // (R13) 8 bits
+                self.crtc_offset = byte;
+            }
+            CRTCRegister::UnderlineLocation => {
+                // (R14)
+                // Bits 0-4: Underline Location
+                // Bit 5: Count by Four
+                // Bit 6: Double Word Mode
+                self.crtc_underline_location = CUnderlineLocation::from_bytes([byte & 0x7F]);
+            }
+            CRTCRegister::StartVerticalBlank => {
+                // R(15) - Start Vertical Blank
+                // Bit 8 in overflow register, bit 9 in maximum scanline register. Set only lower 8 bits here.
+                self.crtc_start_vertical_blank &= 0xFF00;
+                self.crtc_start_vertical_blank |= byte as u16;
+            }
+            CRTCRegister::EndVerticalBlank => {
+                // R(16) - Bits 0-6: End Vertical Blank
+                self.crtc_end_vertical_blank = (byte & 0x7F) as u16;
+            }
+            CRTCRegister::ModeControl => {
+                // (R17) Mode Control Register
+                self.crtc_mode_control = CModeControl::from_bytes([byte]);
+            }
+            CRTCRegister::LineCompare => {
+                // (R18) Line Compare Register
+                // Bit 8 in overflow register, bit 9 in maximum scanline register. Set only lower 8 bits here.
+                self.crtc_line_compare &= 0xFF00;
+                self.crtc_line_compare |= byte as u16;
+            }
+        }
+        (true, clear_intr)
+    }
+
+    /// Update the miscellaneous registers that share bits with the Overflow register.
+    ///
+    /// Bit 0: Vertical Total Bit 8
+    /// Bit 1: Vertical Display Enable End Bit 8
+    /// Bit 2: Vertical Retrace Start Bit 8
+    /// Bit 3: Start Vertical Blank Bit 8
+    /// Bit 4: Line Compare Bit 8
+    /// Bit 5: Vertical Total Bit 9
+    /// Bit 6: Vertical Display Enable End Bit 9
+    /// Bit 7: Vertical Retrace Start Bit 9
+    fn set_crtc_overflow_bits(&mut self, byte: u8) {
+        let byte = byte as u16;
+        self.crtc_vertical_total &= 0x00FF;
+        self.crtc_vertical_total |= (byte & 0x01) << 8 | (byte >> 5 & 0x01) << 9;
+        self.crtc_vertical_display_end &= 0x00FF;
+        self.crtc_vertical_display_end |= (byte >> 1 & 0x01) << 8 | (byte >> 6 & 0x01) << 9;
+        self.crtc_vertical_retrace_start &= 0x00FF;
+        self.crtc_vertical_retrace_start |= (byte >> 2 & 0x01) << 8 | (byte >> 7 & 0x01) << 9;
+        // Bit 9 of Start Vertical Blank and Line Compare live in the Maximum Scanline register.
+        self.crtc_start_vertical_blank &= 0x02FF;
+        self.crtc_start_vertical_blank |= (byte >> 3 & 0x01) << 8;
+        self.crtc_line_compare &= 0x02FF;
+        self.crtc_line_compare |= (byte >> 4 & 0x01) << 8;
+    }
+
+    /// Combine the End Horizontal Blank bits from R(3) and R(5) into a 6-bit value.
+    fn update_end_horizontal_blank(&mut self) {
+        self.crtc_end_horizontal_blank_6 = self.crtc_end_horizontal_blank.end_horizontal_blank() as u16
+            | (self.crtc_end_horizontal_retrace.ehb_bit_5() as u16) << 5;
+    }
+
+    /// Read from one of the CRT Controller registers. Unlike the EGA, all CRTC registers are readable
+    /// on the VGA.
+    pub fn read_crtc_register(&mut self) -> u8 {
+        match self.register_selected {
+            CRTCRegister::HorizontalTotal => self.crtc_horizontal_total,
+            CRTCRegister::HorizontalDisplayEnd => self.crtc_horizontal_display_end,
+            CRTCRegister::StartHorizontalBlank => self.crtc_start_horizontal_blank,
+            CRTCRegister::EndHorizontalBlank => self.crtc_end_horizontal_blank.into_bytes()[0],
+            CRTCRegister::StartHorizontalRetrace => self.crtc_start_horizontal_retrace,
+            CRTCRegister::EndHorizontalRetrace => self.crtc_end_horizontal_retrace.into_bytes()[0],
+            CRTCRegister::VerticalTotal => self.crtc_vertical_total as u8,
+            CRTCRegister::Overflow => self.crtc_overflow,
+            CRTCRegister::PresetRowScan => self.crtc_preset_row_scan.into_bytes()[0],
+            CRTCRegister::MaximumScanLine => self.crtc_maximum_scanline.into_bytes()[0],
+            CRTCRegister::CursorStartLine => self.crtc_cursor_start.into_bytes()[0],
+            CRTCRegister::CursorEndLine => self.crtc_cursor_end.into_bytes()[0],
+            CRTCRegister::StartAddressH => self.crtc_start_address_ho,
+            CRTCRegister::StartAddressL => self.crtc_start_address_lo,
+            CRTCRegister::CursorAddressH => self.crtc_cursor_address_ho,
+            CRTCRegister::CursorAddressL => self.crtc_cursor_address_lo,
+            CRTCRegister::VerticalRetraceStart => self.crtc_vertical_retrace_start as u8,
+            CRTCRegister::VerticalRetraceEnd => self.crtc_vertical_retrace_end.into_bytes()[0],
+            CRTCRegister::VerticalDisplayEnd => self.crtc_vertical_display_end as u8,
+            CRTCRegister::Offset => self.crtc_offset,
+            CRTCRegister::UnderlineLocation => self.crtc_underline_location.into_bytes()[0],
+            CRTCRegister::StartVerticalBlank => self.crtc_start_vertical_blank as u8,
+            CRTCRegister::EndVerticalBlank => self.crtc_end_vertical_blank as u8,
+            CRTCRegister::ModeControl => self.crtc_mode_control.into_bytes()[0],
+            CRTCRegister::LineCompare => self.crtc_line_compare as u8,
+        }
+    }
+
+    /// Update the cursor data array based when either cursor_start or cursor_end have changed.
+    fn update_cursor_data(&mut self) {
+        // Reset cursor data to 0.
+        self.cursor_data.fill(false);
+
+        let start = self.crtc_cursor_start.cursor_start();
+        let end = self.crtc_cursor_end.cursor_end();
+
+        // Unlike the EGA, the VGA has a dedicated cursor disable bit. Also unlike the EGA, the VGA does not
+        // draw a split cursor - if start > end, no cursor is drawn.
+        if self.crtc_cursor_start.cursor_disable() || start > end {
+            return;
+        }
+
+        // The VGA cursor runs from start_line to end_line, inclusive.
+        for i in start..=end {
+            self.cursor_data[i as usize] = true;
+        }
+    }
+
+    /// Update the CRTC logic for next character.
+    pub fn tick(&mut self, clock_divisor: u32) -> u16 {
+        // Reset hsync and vsync edge-triggered flags
+        self.status.begin_hsync = false;
+        self.status.begin_vsync = false;
+
+        // Advance video memory address offset. In count-by-two and count-by-four modes, the address
+        // advances only every second or fourth character clock.
+        self.vma_cc = self.vma_cc.wrapping_add(1);
+        if self.vma_cc >= self.address_clock_divisor() {
+            self.vma_cc = 0;
+            self.vma = self.vma.wrapping_add(1);
+        }
+
+        // Update horizontal character counter
+        self.hcc += 1;
+
+        // Process horizontal blank period
+        if self.status.hblank {
+            // End horizontal blank when the low six bits of the character counter match EHB
+            if (self.hcc & VGA_HBLANK_MASK) == self.crtc_end_horizontal_blank_6 {
+                self.status.hblank = false;
+                self.status.hborder = true;
+            }
+        }
+
+        // Process horizontal sync period
+        if self.monitor_hsync {
+            // Increment horizontal sync counter (wrapping)
+            self.hsc = self.hsc.wrapping_add(1);
+
+            // Implement a fixed hsync width from the monitor's perspective -
+            // A wider programmed hsync width than these values shifts the displayed image to the right.
+            let hsync_target = if clock_divisor == 1 { 12 } else { 6 };
+
+            // Do a horizontal sync
+            if self.hsc == hsync_target {
+                self.do_hsync();
+                // CRTC may still be in hsync at this point (if the programmed CRTC hsync width is larger
+                // than our fixed hsync value)
+                self.monitor_hsync = false;
+            }
+        }
+
+        if self.status.hsync {
+            // End horizontal sync when the low five bits of the character counter match EHR
+            if ((self.hcc - 1) & VGA_HSYNC_MASK)
+                == self.crtc_end_horizontal_retrace.end_horizontal_retrace() as u16
+            {
+                // Enter horizontal retrace delay time
+                self.in_hrd = true;
+                self.hrdc = 0;
+            }
+        }
+
+        if self.in_hrd {
+            if self.hrdc == self.crtc_end_horizontal_retrace.horizontal_retrace_delay() {
+                // If the monitor is still in hsync, we can end it now - the monitor hsync
+                // only enforces a maximum hsync width, not a minimum.
+                if self.monitor_hsync {
+                    self.monitor_hsync = false;
+                    self.do_hsync();
+                }
+                self.status.hsync = false;
+                self.in_hrd = false;
+                self.hrdc = 0;
+                self.hsc = 0;
+            }
+            else {
+                self.hrdc = self.hrdc.wrapping_add(1);
+            }
+        }
+
+        if self.hcc == self.crtc_horizontal_display_end as u16 + 1 {
+            // Leaving active display area, entering right overscan
+            self.den_skew_back = true;
+            self.status.den = false;
+        }
+
+        if self.hcc == self.crtc_start_horizontal_blank as u16 + 1 {
+            // Leaving right overscan and entering horizontal blank
+            self.status.hborder = false;
+            self.status.hblank = true;
+            self.status.den = false;
+        }
+
+        if self.hcc == self.crtc_start_horizontal_blank as u16 + 2 {
+            self.status.cref = false; // CRTC stops generating addresses
+        }
+
+        if self.hcc
+            == self.crtc_start_horizontal_retrace as u16
+                + self.crtc_end_horizontal_retrace.horizontal_retrace_delay() as u16
+        {
+            // Entering horizontal retrace. Retrace can start before hblank!
+            self.status.hsync = true;
+            self.monitor_hsync = true;
+            self.status.den = false;
+            self.hsc = 0;
+        }
+
+        // Actual HorizontalTotal is register value + 5 on VGA.
+        if self.hcc == self.crtc_horizontal_total as u16 + HORIZONTAL_TOTAL_ADJUST {
+            // Leaving left overscan, finished scanning row. Entering active display area with
+            // new logical scanline.
+            self.status.cref = true;
+
+            if self.in_last_vblank_line {
+                self.in_last_vblank_line = false;
+                self.status.vblank = false;
+            }
+
+            // Reset Horizontal Character Counter
+            self.hcc = 0;
+            self.vma_cc = 0;
+
+            if self.crtc_maximum_scanline.two_to_four() && !self.double_scan {
+                // Scan doubling is enabled: repeat the current row scan line before advancing.
+                self.double_scan = true;
+            }
+            else {
+                self.double_scan = false;
+                self.vlc += 1;
+            }
+
+            // Return video memory address to starting position for next character row
+            self.vma = self.vma_sl;
+
+            if !self.status.vblank && self.slc < self.crtc_vertical_display_end + 1 {
+                // Start the new row
+                self.den_skew_front = true;
+            }
+
+            if self.vlc > self.crtc_maximum_scanline.maximum_scanline() {
+                // We finished drawing this row of characters
+                self.vlc = 0;
+                // Advance Vertical Character Counter
+                self.vcc = self.vcc.wrapping_add(1);
+
+                // Set vma to starting position for next character row
+                self.vma_sl = self.vma_sl.wrapping_add(self.crtc_offset as u16 * 2);
+                self.vma = self.vma_sl;
+            }
+
+            if self.slc == self.crtc_line_compare + 1 {
+                // The line compare register is used to reset the effective start address to 0.
+                // This is used to implement split screen effects - the top of the screen is drawn from some start
+                // address offset, and then the split-screen window is drawn from address 0 after line compare.
+                // The reset takes effect on the line following the line compare value.
+                self.vma_sl = 0;
+                self.vma = 0;
+                self.vlc = 0;
+                self.double_scan = false;
+                self.status.split = true;
+            }
+
+            if self.slc == self.crtc_start_vertical_blank {
+                // Entering vertical blank.
+                self.status.vblank = true;
+                self.status.den = false;
+            }
+
+            if self.slc == self.crtc_vertical_retrace_start {
+                // We've reached vertical retrace start. Vertical retrace continues until the low four bits of the
+                // scanline counter match the Vertical Retrace End register.
+                self.status.vsync = true;
+                self.status.den = false;
+
+                // Latch CRTC start address at VSYNC (https://www.vogons.org/viewtopic.php?t=57320)
+                self.start_address_latch = self.crtc_start_address;
+            }
+
+            if self.slc == self.crtc_vertical_display_end + 1 {
+                // We are leaving the bottom of the active display area, entering the lower overscan area.
+                self.status.vborder = true;
+                self.status.den = false;
+                self.den_skew_back = true;
+                self.status.den_skew = true;
+            }
+
+            if self.slc == self.crtc_vertical_total + 1 {
+                // We have reached vertical total, we are at the end of the top overscan and entering the active
+                // display area.
+                self.slc = 0;
+
+                self.hcc = 0;
+                self.vcc = 0;
+                self.vlc = self.crtc_preset_row_scan.preset_row_scan();
+                self.double_scan = false;
+                self.status.split = false;
+
+                self.frame += 1;
+                // Toggle blink state. This is toggled every 8 frames by default.
+                if (self.frame % VGA_CURSOR_BLINK_RATE as u64) == 0 {
+                    self.blink_state = !self.blink_state;
+                }
+
+                self.vma = self.start_address_latch;
+                self.vma_sl = self.vma;
+
+                // Delay toggle of display enable by Display Enable Skew value.
+                self.den_skew_front = true;
+                self.status.vborder = false;
+                self.status.vblank = false;
+            }
+        }
+
+        // Handle DEN skew.
+        if self.den_skew_front {
+            if self.dsc == self.crtc_end_horizontal_blank.display_enable_skew() + AC_LATENCY {
+                self.den_skew_front = false;
+                self.status.vborder = false;
+                self.status.hborder = false;
+                self.status.den = true;
+                self.dsc = 0;
+            }
+            else {
+                self.dsc = self.dsc.wrapping_add(1);
+            }
+        }
+
+        if self.den_skew_back {
+            if self.dsc == self.crtc_end_horizontal_blank.display_enable_skew() + AC_LATENCY {
+                self.den_skew_back = false;
+                self.status.den_skew = false;
+                self.status.hborder = true;
+                self.dsc = 0;
+            }
+            else {
+                self.status.den_skew = true;
+                self.dsc = self.dsc.wrapping_add(1);
+            }
+        }
+
+        // Byte panning offsets the address generated for every character clock.
+        let addr = self.vma.wrapping_add(self.crtc_preset_row_scan.byte_panning() as u16);
+
+        // Update cursor status
+        self.status.cursor = (addr
+            == (self
+                .crtc_cursor_address
+                .wrapping_add(AC_LATENCY as u16 + self.crtc_cursor_end.cursor_skew() as u16)))
+            && self.blink_state
+            && self.cursor_data[(self.vlc & 0x1F) as usize];
+
+        self.output_address(addr)
+    }
+
+    /// Translate a CRTC memory address into a display memory address according to the current addressing mode.
+    fn output_address(&self, addr: u16) -> u16 {
+        let mut output_addr = addr;
+
+        if self.crtc_underline_location.double_word_mode() {
+            // Double word mode selected. Address bits 12 and 13 rotate into bits 0 and 1.
+            output_addr = (addr << 2) | ((addr >> 12) & 0x03);
+        }
+        else if let WordOrByteMode::Word = self.crtc_mode_control.word_or_byte_mode() {
+            // Word mode selected
+            let bit = match self.crtc_mode_control.address_wrap() {
+                0 => (addr & (1 << 13)) >> 13,
+                _ => (addr & (1 << 15)) >> 15,
+            };
+            output_addr = (addr << 1) | bit;
+        }
+
+        if let CompatibilityMode::Cga = self.crtc_mode_control.compatibility_mode() {
+            // Compatibility mode selected. Set A13 to VLC bit 0.
+            output_addr = (output_addr & !(1 << 13)) | ((self.vlc as u16 & 0x01) << 13)
+        };
+
+        if self.crtc_mode_control.select_row_scan_counter() == 0 {
+            // Hercules compatibility. Set A14 to VLC bit 1.
+            output_addr = (output_addr & !(1 << 14)) | ((self.vlc as u16 & 0x02) << 13)
+        }
+
+        output_addr
+    }
+
+    /// Return the number of character clocks per video memory address increment.
+    #[inline]
+    fn address_clock_divisor(&self) -> u8 {
+        if self.crtc_underline_location.count_by_four() {
+            4
+        }
+        else if self.crtc_mode_control.count_by_two() {
+            2
+        }
+        else {
+            1
+        }
+    }
+
+    fn do_hsync(&mut self) {
+        // Reset hsync delay
+        self.in_hrd = false;
+        self.hrdc = 0;
+
+        if self.status.vblank && (self.slc & VGA_VBLANK_MASK) == self.crtc_end_vertical_blank {
+            self.in_last_vblank_line = true;
+        }
+
+        if self.status.vsync
+            && (self.slc & VGA_VSYNC_MASK) == self.crtc_vertical_retrace_end.vertical_retrace_end() as u16
+        {
+            // We are leaving vsync period, generate a frame
+            self.status.begin_vsync = true;
+            self.status.vsync = false;
+        }
+        else {
+            self.status.begin_hsync = true;
+        }
+
+        // Restrict HSLC to 10-bit range.
+        self.slc = (self.slc + 1) & VGA_HSLC_MASK;
+    }
+
+    #[inline]
+    pub fn vlc(&self) -> u8 {
+        self.vlc
+    }
+
+    #[inline]
+    pub fn scanline(&self) -> u16 {
+        self.slc
+    }
+
+    #[inline]
+    pub fn maximum_scanline(&self) -> u8 {
+        self.crtc_maximum_scanline.maximum_scanline()
+    }
+
+    #[inline]
+    pub fn underline_location(&self) -> u8 {
+        self.crtc_underline_location.underline_location()
+    }
+
+    #[inline]
+    pub fn in_skew(&self) -> bool {
+        self.den_skew_front | (self.den_skew_back && self.dsc < self.crtc_end_horizontal_blank.display_enable_skew())
+    }
+
+    #[inline]
+    pub fn in_blanking(&self) -> bool {
+        self.status.hblank | self.status.vblank
+    }
+
+    #[inline]
+    pub fn start_address(&self) -> u16 {
+        self.crtc_start_address
+    }
+
+    #[inline]
+    pub(crate) fn int_enabled(&self) -> bool {
+        self.crtc_vertical_retrace_end.dvi() == 0
+    }
+
+    #[inline]
+    pub fn cursor_address(&self) -> u16 {
+        self.crtc_cursor_address
+    }
+
+    pub fn get_cursor_span(&self) -> (u8, u8) {
+        (self.crtc_cursor_start.cursor_start(), self.crtc_cursor_end.cursor_end())
+    }
+
+    pub fn horizontal_display_end(&self) -> u8 {
+        self.crtc_horizontal_display_end
+    }
+
+    /// Return the total number of character clocks per scanline.
+    pub fn horizontal_total(&self) -> u32 {
+        self.crtc_horizontal_total as u32 + HORIZONTAL_TOTAL_ADJUST as u32
+    }
+
+    pub fn vertical_display_end(&self) -> u16 {
+        self.crtc_vertical_display_end
+    }
+
+    /// Return the total number of scanlines per frame.
+    pub fn vertical_total(&self) -> u32 {
+        self.crtc_vertical_total as u32 + 2
+    }
+
+    #[inline]
+    pub fn address_mode(&self) -> WordOrByteMode {
+        self.crtc_mode_control.word_or_byte_mode()
+    }
+
+    #[rustfmt::skip]
+    pub fn get_state(&self) -> Vec<(String, VideoCardStateEntry)> {
+        let mut crtc_vec = Vec::new();
+        push_reg_str!(crtc_vec, CRTCRegister::HorizontalTotal, "[R00]", self.crtc_horizontal_total);
+        push_reg_str!(crtc_vec, CRTCRegister::HorizontalDisplayEnd, "[R01]", self.crtc_horizontal_display_end);
+        push_reg_str!(crtc_vec, CRTCRegister::StartHorizontalBlank, "[R02]", self.crtc_start_horizontal_blank);
+        push_reg_str!(crtc_vec, CRTCRegister::EndHorizontalBlank, "[R03]", self.crtc_end_horizontal_blank.end_horizontal_blank());
+        push_reg_str!(crtc_vec, CRTCRegister::EndHorizontalBlank, "[R03:des]", self.crtc_end_horizontal_blank.display_enable_skew());
+        push_reg_str!(crtc_vec, CRTCRegister::EndHorizontalBlank, "[R03:ehb]", self.crtc_end_horizontal_blank_6);
+        push_reg_str!(crtc_vec, CRTCRegister::StartHorizontalRetrace, "[R04]", self.crtc_start_horizontal_retrace);
+        push_reg_str!(crtc_vec, CRTCRegister::EndHorizontalRetrace, "[R05]", self.crtc_end_horizontal_retrace.end_horizontal_retrace());
+        push_reg_str!(crtc_vec, CRTCRegister::EndHorizontalRetrace, "[R05:hrd]", self.crtc_end_horizontal_retrace.horizontal_retrace_delay());
+        push_reg_str!(crtc_vec, CRTCRegister::VerticalTotal, "[R06]", self.crtc_vertical_total);
+        push_reg_str!(crtc_vec, CRTCRegister::Overflow, "[R07]", self.crtc_overflow);
+        push_reg_str!(crtc_vec, CRTCRegister::PresetRowScan, "[R08]", self.crtc_preset_row_scan.preset_row_scan());
+        push_reg_str!(crtc_vec, CRTCRegister::PresetRowScan, "[R08:bp]", self.crtc_preset_row_scan.byte_panning());
+        push_reg_str!(crtc_vec, CRTCRegister::MaximumScanLine, "[R09]", self.crtc_maximum_scanline.maximum_scanline());
+        push_reg_str!(crtc_vec, CRTCRegister::MaximumScanLine, "[R09:sd]", self.crtc_maximum_scanline.two_to_four());
+        push_reg_str!(crtc_vec, CRTCRegister::CursorStartLine, "[R0A]", self.crtc_cursor_start.cursor_start());
+        push_reg_str!(crtc_vec, CRTCRegister::CursorStartLine, "[R0A:cd]", self.crtc_cursor_start.cursor_disable());
+        push_reg_str!(crtc_vec, CRTCRegister::CursorEndLine, "[R0B]", self.crtc_cursor_end.cursor_end());
+        push_reg_str!(crtc_vec, CRTCRegister::StartAddressH, "[R0C]", self.crtc_start_address_ho);
+        push_reg_str!(crtc_vec, CRTCRegister::StartAddressL, "[R0D]", self.crtc_start_address_lo);
+        push_reg_str!(crtc_vec, CRTCRegister::CursorAddressH, "[R0E]", self.crtc_cursor_address_ho);
+        push_reg_str!(crtc_vec, CRTCRegister::CursorAddressL, "[R0F]", self.crtc_cursor_address_lo);
+        push_reg_str!(crtc_vec, CRTCRegister::VerticalRetraceStart, "[R10]", self.crtc_vertical_retrace_start);
+        push_reg_str!(crtc_vec, CRTCRegister::VerticalRetraceEnd, "[R11]", self.crtc_vertical_retrace_end.vertical_retrace_end());
+        push_reg_str!(crtc_vec, CRTCRegister::VerticalRetraceEnd, "[R11:dvi]", self.crtc_vertical_retrace_end.dvi());
+        push_reg_str!(crtc_vec, CRTCRegister::VerticalRetraceEnd, "[R11:pr]", self.crtc_vertical_retrace_end.protect_registers());
+        push_reg_str!(crtc_vec, CRTCRegister::VerticalDisplayEnd, "[R12]", self.crtc_vertical_display_end);
+        push_reg_str!(crtc_vec, CRTCRegister::Offset, "[R13]", self.crtc_offset);
+        push_reg_str!(crtc_vec, CRTCRegister::UnderlineLocation, "[R14]", self.crtc_underline_location.underline_location());
+        push_reg_str!(crtc_vec, CRTCRegister::UnderlineLocation, "[R14:cb4]", self.crtc_underline_location.count_by_four());
+        push_reg_str!(crtc_vec, CRTCRegister::UnderlineLocation, "[R14:dw]", self.crtc_underline_location.double_word_mode());
+        push_reg_str!(crtc_vec, CRTCRegister::StartVerticalBlank, "[R15]", self.crtc_start_vertical_blank);
+        push_reg_str!(crtc_vec, CRTCRegister::EndVerticalBlank, "[R16]", self.crtc_end_vertical_blank);
+        crtc_vec.push(("[R17] ModeControl".to_string(), VideoCardStateEntry::String(format!("{:08b}",self.crtc_mode_control.into_bytes()[0]))));
+        push_reg_str!(crtc_vec, CRTCRegister::LineCompare, "[R18]", self.crtc_line_compare);
+
+        crtc_vec
+    }
+
+    #[rustfmt::skip]
+    pub fn get_counter_state(&self) ->  Vec<(String, VideoCardStateEntry)> {
+        let mut internal_vec = Vec::new();
+        internal_vec.push(("hcc:".to_string(), VideoCardStateEntry::String(format!("{}", self.hcc))));
+        internal_vec.push(("vlc:".to_string(), VideoCardStateEntry::String(format!("{}", self.vlc))));
+        internal_vec.push(("vcc:".to_string(), VideoCardStateEntry::String(format!("{}", self.vcc))));
+        internal_vec.push(("hslc:".to_string(), VideoCardStateEntry::String(format!("{}", self.slc))));
+        internal_vec.push(("hsc:".to_string(), VideoCardStateEntry::String(format!("{}", self.hsc))));
+        internal_vec.push(("vma:".to_string(), VideoCardStateEntry::String(format!("{:04X}", self.vma))));
+        internal_vec.push(("den:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.status.den))));
+        internal_vec.push(("den_skew:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.status.den_skew))));
+        internal_vec.push(("ds_front:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.den_skew_front))));
+        internal_vec.push(("ds_back:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.den_skew_back))));
+        internal_vec.push(("dsc:".to_string(), VideoCardStateEntry::String(format!("{}", self.dsc))));
+        internal_vec.push(("hblank:".to_string(), VideoCardStateEntry::String(format!("{}", self.status.hblank))));
+        internal_vec.push(("vblank:".to_string(), VideoCardStateEntry::String(format!("{}", self.status.vblank))));
+        internal_vec.push(("hborder:".to_string(), VideoCardStateEntry::String(format!("{}", self.status.hborder))));
+        internal_vec.push(("vborder:".to_string(), VideoCardStateEntry::String(format!("{}", self.status.vborder))));
+        internal_vec.push(("split:".to_string(), VideoCardStateEntry::String(format!("{}", self.status.split))));
+        internal_vec
+    }
+}
+
+impl_state_enum!(CRTCRegister {
+    HorizontalTotal,
+    HorizontalDisplayEnd,
+    StartHorizontalBlank,
+    EndHorizontalBlank,
+    StartHorizontalRetrace,
+    EndHorizontalRetrace,
+    VerticalTotal,
+    Overflow,
+    PresetRowScan,
+    MaximumScanLine,
+    CursorStartLine,
+    CursorEndLine,
+    StartAddressH,
+    StartAddressL,
+    CursorAddressH,
+    CursorAddressL,
+    VerticalRetraceStart,
+    VerticalRetraceEnd,
+    VerticalDisplayEnd,
+    Offset,
+    UnderlineLocation,
+    StartVerticalBlank,
+    EndVerticalBlank,
+    ModeControl,
+    LineCompare,
+});
+
+impl StateValue for CrtcStatus {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.put(&self.begin_hsync);
+        w.put(&self.begin_vsync);
+        w.put(&self.hsync);
+        w.put(&self.vsync);
+        w.put(&self.hblank);
+        w.put(&self.vblank);
+        w.put(&self.hborder);
+        w.put(&self.vborder);
+        w.put(&self.den);
+        w.put(&self.den_skew);
+        w.put(&self.cursor);
+        w.put(&self.cref);
+        w.put(&self.split);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            begin_hsync: r.get()?,
+            begin_vsync: r.get()?,
+            hsync: r.get()?,
+            vsync: r.get()?,
+            hblank: r.get()?,
+            vblank: r.get()?,
+            hborder: r.get()?,
+            vborder: r.get()?,
+            den: r.get()?,
+            den_skew: r.get()?,
+            cursor: r.get()?,
+            cref: r.get()?,
+            split: r.get()?,
+        })
+    }
+}
+
+impl SaveState for VgaCrtc {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.register_select_byte);
+        w.put(&self.register_selected);
+        w.put(&self.crtc_horizontal_total);
+        w.put(&self.crtc_horizontal_display_end);
+        w.put(&self.crtc_start_horizontal_blank);
+        w.put(&self.crtc_end_horizontal_blank.into_bytes()[0]);
+        w.put(&self.crtc_end_horizontal_blank_6);
+        w.put(&self.crtc_start_horizontal_retrace);
+        w.put(&self.crtc_end_horizontal_retrace.into_bytes()[0]);
+        w.put(&self.crtc_vertical_total);
+        w.put(&self.crtc_overflow);
+        w.put(&self.crtc_preset_row_scan.into_bytes()[0]);
+        w.put(&self.crtc_maximum_scanline.into_bytes()[0]);
+        w.put(&self.crtc_cursor_start.into_bytes()[0]);
+        w.put(&self.crtc_cursor_end.into_bytes()[0]);
+        w.put(&self.crtc_start_address_ho);
+        w.put(&self.crtc_start_address_lo);
+        w.put(&self.crtc_start_address);
+        w.put(&self.start_address_latch);
+        w.put(&self.crtc_cursor_address_lo);
+        w.put(&self.crtc_cursor_address_ho);
+        w.put(&self.crtc_cursor_address);
+        w.put(&self.crtc_vertical_retrace_start);
+        w.put(&self.crtc_vertical_retrace_end.into_bytes()[0]);
+        w.put(&self.crtc_vertical_display_end);
+        w.put(&self.crtc_offset);
+        w.put(&self.crtc_underline_location.into_bytes()[0]);
+        w.put(&self.crtc_start_vertical_blank);
+        w.put(&self.crtc_end_vertical_blank);
+        w.put(&self.crtc_mode_control.into_bytes()[0]);
+        w.put(&self.crtc_line_compare);
+        w.put(&self.hcc);
+        w.put(&self.vlc);
+        w.put(&self.vcc);
+        w.put(&self.slc);
+        w.put(&self.hsc);
+        w.put(&self.in_hrd);
+        w.put(&self.hrdc);
+        w.put(&self.vma);
+        w.put(&self.vma_sl);
+        w.put(&self.vma_cc);
+        w.put(&self.double_scan);
+        w.put(&self.den_skew_front);
+        w.put(&self.den_skew_back);
+        w.put(&self.dsc);
+        w.put(&self.status);
+        w.put(&self.blink_state);
+        w.put(&self.monitor_hsync);
+        w.put(&self.in_last_vblank_line);
+        w.put(&self.cursor_data);
+        w.put(&self.frame);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.register_select_byte = r.get()?;
+        self.register_selected = r.get()?;
+        self.crtc_horizontal_total = r.get()?;
+        self.crtc_horizontal_display_end = r.get()?;
+        self.crtc_start_horizontal_blank = r.get()?;
+        self.crtc_end_horizontal_blank = CEndHorizontalBlank::from_bytes([r.get()?]);
+        self.crtc_end_horizontal_blank_6 = r.get()?;
+        self.crtc_start_horizontal_retrace = r.get()?;
+        self.crtc_end_horizontal_retrace = CEndHorizontalRetrace::from_bytes([r.get()?]);
+        self.crtc_vertical_total = r.get()?;
+        self.crtc_overflow = r.get()?;
+        self.crtc_preset_row_scan = CPresetRowScan::from_bytes([r.get()?]);
+        self.crtc_maximum_scanline = CMaximumScanline::from_bytes([r.get()?]);
+        self.crtc_cursor_start = CCursorStart::from_bytes([r.get()?]);
+        self.crtc_cursor_end = CCursorEnd::from_bytes([r.get()?]);
+        self.crtc_start_address_ho = r.get()?;
+        self.crtc_start_address_lo = r.get()?;
+        self.crtc_start_address = r.get()?;
+        self.start_address_latch = r.get()?;
+        self.crtc_cursor_address_lo = r.get()?;
+        self.crtc_cursor_address_ho = r.get()?;
+        self.crtc_cursor_address = r.get()?;
+        self.crtc_vertical_retrace_start = r.get()?;
+        self.crtc_vertical_retrace_end = CVerticalRetraceEnd::from_bytes([r.get()?]);
+        self.crtc_vertical_display_end = r.get()?;
+        self.crtc_offset = r.get()?;
+        self.crtc_underline_location = CUnderlineLocation::from_bytes([r.get()?]);
+        self.crtc_start_vertical_blank = r.get()?;
+        self.crtc_end_vertical_blank = r.get()?;
+        self.crtc_mode_control = CModeControl::from_bytes([r.get()?]);
+        self.crtc_line_compare = r.get()?;
+        self.hcc = r.get()?;
+        self.vlc = r.get()?;
+        self.vcc = r.get()?;
+        self.slc = r.get()?;
+        self.hsc = r.get()?;
+        self.in_hrd = r.get()?;
+        self.hrdc = r.get()?;
+        self.vma = r.get()?;
+        self.vma_sl = r.get()?;
+        self.vma_cc = r.get()?;
+        self.double_scan = r.get()?;
+        self.den_skew_front = r.get()?;
+        self.den_skew_back = r.get()?;
+        self.dsc = r.get()?;
+        self.status = r.get()?;
+        self.blink_state = r.get()?;
+        self.monitor_hsync = r.get()?;
+        self.in_last_vblank_line = r.get()?;
+        self.cursor_data = r.get()?;
+        self.frame = r.get()?;
+        Ok(())
+    }
+}
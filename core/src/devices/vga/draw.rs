@@ -0,0 +1,67 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    ---------------------------------------------------------------------------
+
+    vga::draw.rs
+
+    Drawing routines for VGA. Pixels are resolved to RGBA through the DAC as they are drawn,
+    so palette changes made mid-frame appear from the point on the screen where they were made.
+
+*/
+
+use super::*;
+
+impl VGACard {
+    /// Draw a span of pixels from the attribute controller, resolving each DAC index through the
+    /// current palette. When the Sequencer's dot clock is halved, each pixel is drawn twice.
+    #[inline]
+    pub fn draw_span(&mut self, span: &[u8]) {
+        let rba = self.rba;
+        let palette = &self.color_registers_out;
+        let frame = &mut self.buf[self.back_buf];
+
+        match self.sequencer.clock_divisor {
+            1 => {
+                for (pixel, pel) in frame[rba..rba + span.len()].iter_mut().zip(span.iter()) {
+                    *pixel = u32::from_ne_bytes(palette[*pel as usize]);
+                }
+            }
+            _ => {
+                for (pixels, pel) in frame[rba..rba + span.len() * 2].chunks_exact_mut(2).zip(span.iter()) {
+                    pixels.fill(u32::from_ne_bytes(palette[*pel as usize]));
+                }
+            }
+        }
+    }
+
+    /// Draw a character clock's worth of pixels using a single solid DAC color.
+    #[inline]
+    pub fn draw_solid_span(&mut self, color: u8) {
+        let rba = self.rba;
+        let span_w = self.sequencer.char_clock as usize;
+        let pixel = u32::from_ne_bytes(self.color_registers_out[color as usize]);
+        self.buf[self.back_buf][rba..rba + span_w].fill(pixel);
+    }
+}
@@ -0,0 +1,155 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    ---------------------------------------------------------------------------
+
+    vga::io.rs
+
+    Implementation of the IoDevice interface trait for the IBM VGA card.
+
+*/
+
+use super::*;
+use crate::bus::{BusInterface, DeviceRunTimeUnit, IoDevice};
+
+/// Implement Device IO for the VGA Card.
+///
+/// Unlike the EGA, most of the registers on the VGA are readable.
+impl IoDevice for VGACard {
+    fn read_u8(&mut self, port: u16, _delta: DeviceRunTimeUnit) -> u8 {
+        match port {
+            ATTRIBUTE_REGISTER => self.ac.read_address(),
+            ATTRIBUTE_REGISTER_ALT => self.ac.read_data(),
+            MISC_OUTPUT_REGISTER_READ => self.misc_output_register.into_bytes()[0],
+            INPUT_STATUS_REGISTER_0 => self.read_input_status_register_0(),
+            INPUT_STATUS_REGISTER_1 => {
+                // Don't answer this port if we are in MDA compatibility mode
+                match self.misc_output_register.io_address_select() {
+                    IoAddressSelect::CompatMonochrome => 0xFF,
+                    IoAddressSelect::CompatCGA => self.read_input_status_register_1(),
+                }
+            }
+            INPUT_STATUS_REGISTER_1_MDA => {
+                // Don't respond on this port if we are in CGA compatibility mode
+                match self.misc_output_register.io_address_select() {
+                    IoAddressSelect::CompatMonochrome => self.read_input_status_register_1(),
+                    IoAddressSelect::CompatCGA => 0xFF,
+                }
+            }
+            GRAPHICS_ADDRESS => self.gc.read_graphics_address(),
+            GRAPHICS_DATA => self.gc.read_graphics_data(),
+            SEQUENCER_ADDRESS_REGISTER => self.sequencer.read_address(),
+            SEQUENCER_DATA_REGISTER => self.sequencer.read_data(),
+            CRTC_REGISTER => {
+                // Don't answer this port if we are in MDA compatibility mode
+                match self.misc_output_register.io_address_select() {
+                    IoAddressSelect::CompatMonochrome => 0xFF,
+                    IoAddressSelect::CompatCGA => self.crtc.read_crtc_register(),
+                }
+            }
+            CRTC_REGISTER_MDA => {
+                // Don't respond on this port if we are in CGA compatibility mode
+                match self.misc_output_register.io_address_select() {
+                    IoAddressSelect::CompatMonochrome => self.crtc.read_crtc_register(),
+                    IoAddressSelect::CompatCGA => 0xFF,
+                }
+            }
+            PEL_ADDRESS_WRITE_MODE => self.color_pel_write_address,
+            PEL_DATA => self.read_pel_data(),
+            DAC_STATE_REGISTER => {
+                // Read only register
+                self.color_dac_state
+            }
+            PEL_MASK => self.color_pel_mask,
+            _ => {
+                0xFF // Open bus
+            }
+        }
+    }
+
+    fn write_u8(&mut self, port: u16, data: u8, _bus: Option<&mut BusInterface>, _delta: DeviceRunTimeUnit) {
+        match port {
+            MISC_OUTPUT_REGISTER_WRITE => {
+                self.write_external_misc_output_register(data);
+            }
+            CRTC_REGISTER_ADDRESS | CRTC_REGISTER_ADDRESS_MDA => {
+                self.crtc.write_crtc_register_address(data);
+            }
+            CRTC_REGISTER | CRTC_REGISTER_MDA => {
+                let (recalc, clear_intr) = self.crtc.write_crtc_register_data(data);
+                if recalc {
+                    self.recalculate_mode();
+                    self.update_extents();
+                }
+                if clear_intr {
+                    self.intr = false;
+                }
+            }
+            GRAPHICS_ADDRESS => self.gc.write_graphics_address(data),
+            GRAPHICS_DATA => {
+                self.gc.write_graphics_data(data);
+                self.recalculate_mode();
+            }
+            SEQUENCER_ADDRESS_REGISTER => self.sequencer.write_address(data),
+            SEQUENCER_DATA_REGISTER => {
+                self.sequencer.write_data(data);
+                self.update_clock();
+                self.recalculate_mode();
+            }
+            ATTRIBUTE_REGISTER | ATTRIBUTE_REGISTER_ALT => {
+                self.ac.write_attribute_register(data);
+                self.recalculate_mode();
+            }
+            PEL_ADDRESS_WRITE_MODE => self.write_pel_write_address(data),
+            PEL_ADDRESS_READ_MODE => self.write_pel_read_address(data),
+            PEL_DATA => self.write_pel_data(data),
+            PEL_MASK => self.write_pel_mask(data),
+            _ => {}
+        }
+    }
+
+    fn port_list(&self) -> Vec<u16> {
+        vec![
+            ATTRIBUTE_REGISTER,
+            ATTRIBUTE_REGISTER_ALT,
+            MISC_OUTPUT_REGISTER_READ,
+            MISC_OUTPUT_REGISTER_WRITE,
+            INPUT_STATUS_REGISTER_0,
+            INPUT_STATUS_REGISTER_1,
+            INPUT_STATUS_REGISTER_1_MDA,
+            SEQUENCER_ADDRESS_REGISTER,
+            SEQUENCER_DATA_REGISTER,
+            CRTC_REGISTER_ADDRESS,
+            CRTC_REGISTER,
+            CRTC_REGISTER_ADDRESS_MDA,
+            CRTC_REGISTER_MDA,
+            GRAPHICS_ADDRESS,
+            GRAPHICS_DATA,
+            PEL_ADDRESS_READ_MODE,
+            PEL_ADDRESS_WRITE_MODE,
+            PEL_DATA,
+            PEL_MASK,
+        ]
+    }
+}
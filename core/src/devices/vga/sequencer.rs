@@ -0,0 +1,442 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    ---------------------------------------------------------------------------
+
+    vga::sequencer.rs
+
+    Implement the VGA Sequencer
+
+*/
+
+use crate::{
+    device_traits::videocard::VideoCardStateEntry,
+    devices::vga::{tablegen::BIT_EXTEND_TABLE64, vram::Vram, VGA_CHARACTER_HEIGHT},
+    savestate::{impl_state_enum, SaveState, StateReader, StateWriter},
+};
+use modular_bitfield::{bitfield, prelude::*, BitfieldSpecifier};
+
+#[derive(Copy, Clone, Debug)]
+pub enum SequencerRegister {
+    Reset,
+    ClockingMode,
+    MapMask,
+    CharacterMapSelect,
+    MemoryMode,
+    Invalid,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct SClockingModeRegister {
+    #[bits = 1]
+    pub character_clock: CharacterClock,
+    #[skip]
+    unused: B1,
+    pub shift_load: B1,
+    pub dot_clock: DotClock,
+    pub shift_four: B1,
+    pub screen_off: bool,
+    #[skip]
+    unused2: B2,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct SMemoryModeRegister {
+    #[skip]
+    unused: B1,
+    pub extended_memory: B1,
+    pub odd_even: OddEvenMode,
+    pub chain4: bool,
+    #[skip]
+    unused2: B4,
+}
+
+#[bitfield]
+#[derive(Copy, Clone)]
+pub struct SCharacterMapSelect {
+    pub generator_b: B2,
+    pub generator_a: B2,
+    pub generator_b_hi: B1,
+    pub generator_a_hi: B1,
+    #[skip]
+    unused: B2,
+}
+
+#[derive(Copy, Clone, Debug, BitfieldSpecifier)]
+pub enum OddEvenMode {
+    OddEven,
+    Sequential,
+}
+
+// 0 == 9 Dots, as on the EGA.
+#[derive(Copy, Clone, Debug, PartialEq, BitfieldSpecifier)]
+pub enum CharacterClock {
+    NineDots,
+    EightDots,
+}
+
+#[derive(Copy, Clone, Debug, BitfieldSpecifier)]
+pub enum DotClock {
+    Native,
+    HalfClock,
+}
+
+const ODD_EVEN_MASK: u8 = 0b0101;
+
+pub struct Sequencer {
+    pub address_byte: u8,
+    pub register_selected: SequencerRegister,
+    pub reset: u8,                                 // S(0) Reset
+    pub clocking_mode: SClockingModeRegister,      // S(1) Clocking Mode
+    pub map_mask: u8,                              // S(2) Map Mask
+    pub character_map_select: SCharacterMapSelect, // S(3) Character Map Select
+    pub memory_mode: SMemoryModeRegister,          // S(4) Memory Mode
+
+    pub clock_change_pending: bool,
+    pub clock_divisor: u32,
+    pub char_width: u32,
+    pub char_clock: u32,
+
+    pub font_select_enabled: bool,
+    pub font_offset_a: usize,
+    pub font_offset_b: usize,
+    pub vram: Vram,
+}
+
+impl Default for Sequencer {
+    fn default() -> Self {
+        Self {
+            address_byte: 0,
+            register_selected: SequencerRegister::Reset,
+            reset: 0,
+            clocking_mode: SClockingModeRegister::new(),
+            map_mask: 0,
+            character_map_select: SCharacterMapSelect::new(),
+            memory_mode: SMemoryModeRegister::new(),
+            clock_change_pending: false,
+            clock_divisor: 1,
+            char_width: 9,
+            char_clock: 9,
+            font_select_enabled: false,
+            font_offset_a: 0,
+            font_offset_b: 0,
+            vram: Vram::new(),
+        }
+    }
+}
+
+impl Sequencer {
+    pub fn new() -> Self {
+        Sequencer::default()
+    }
+
+    pub fn reset(&mut self) {
+        *self = Sequencer::default();
+    }
+
+    /// Handle a write to the Sequencer Address register.
+    ///
+    /// The value written to this register controls which register will be written to
+    /// when a byte is sent to the Sequencer Data register.
+    pub fn write_address(&mut self, byte: u8) {
+        self.address_byte = byte & 0x07;
+
+        self.register_selected = match self.address_byte {
+            0x00 => SequencerRegister::Reset,
+            0x01 => SequencerRegister::ClockingMode,
+            0x02 => SequencerRegister::MapMask,
+            0x03 => SequencerRegister::CharacterMapSelect,
+            0x04 => SequencerRegister::MemoryMode,
+            _ => {
+                log::debug!("Select to invalid sequencer register: {:02X}", byte);
+                SequencerRegister::Invalid
+            }
+        };
+        log::trace!("Sequencer register: {:?} selected", self.register_selected);
+    }
+
+    #[inline]
+    pub fn read_address(&self) -> u8 {
+        self.address_byte
+    }
+
+    /// Handle a write to the Sequencer Data register.
+    ///
+    /// Will write to the internal register selected by the Sequencer Address Register.
+    pub fn write_data(&mut self, data: u8) {
+        match self.register_selected {
+            SequencerRegister::Reset => {
+                self.reset = data & 0x03;
+                log::trace!("Write to Sequencer::Reset register: {:02X}", data);
+            }
+            SequencerRegister::ClockingMode => {
+                self.clocking_mode = SClockingModeRegister::from_bytes([data & 0x3D]);
+                log::trace!("Write to Sequencer::ClockingMode register: {:02X}", data);
+                self.update_clock();
+            }
+            SequencerRegister::MapMask => {
+                self.map_mask = data & 0x0F;
+                // Warning: noisy
+                //log::trace!("Write to Sequencer::MapMask register: {:02X}", data);
+            }
+            SequencerRegister::CharacterMapSelect => {
+                self.character_map_select = SCharacterMapSelect::from_bytes([data & 0x3F]);
+                log::trace!("Write to Sequencer::CharacterMapSelect register: {:02X}", data);
+
+                self.update_character_maps();
+            }
+            SequencerRegister::MemoryMode => {
+                self.memory_mode = SMemoryModeRegister::from_bytes([data & 0x0E]);
+                log::trace!("Write to Sequencer::MemoryMode register: {:02X}", data);
+            }
+            SequencerRegister::Invalid => {
+                // ...Do nothing
+            }
+        }
+    }
+
+    /// Handle a read from the Sequencer Data register. Unlike the EGA, all Sequencer registers are readable
+    /// on the VGA.
+    pub fn read_data(&self) -> u8 {
+        match self.register_selected {
+            SequencerRegister::Reset => self.reset,
+            SequencerRegister::ClockingMode => self.clocking_mode.into_bytes()[0],
+            SequencerRegister::MapMask => self.map_mask,
+            SequencerRegister::CharacterMapSelect => self.character_map_select.into_bytes()[0],
+            SequencerRegister::MemoryMode => self.memory_mode.into_bytes()[0],
+            SequencerRegister::Invalid => 0xFF,
+        }
+    }
+
+    /// Recalculate the clock divisor and character clock width from the Clocking Mode register.
+    fn update_clock(&mut self) {
+        self.clock_change_pending = true;
+        self.char_width = match self.clocking_mode.character_clock() {
+            CharacterClock::NineDots => 9,
+            CharacterClock::EightDots => 8,
+        };
+        self.clock_divisor = match self.clocking_mode.dot_clock() {
+            DotClock::HalfClock => 2,
+            DotClock::Native => 1,
+        };
+        self.char_clock = self.char_width * self.clock_divisor;
+    }
+
+    pub fn update_character_maps(&mut self) {
+        let map_a = self.character_map_select.generator_a() | (self.character_map_select.generator_a_hi() << 2);
+        let map_b = self.character_map_select.generator_b() | (self.character_map_select.generator_b_hi() << 2);
+
+        // Character font selection is only enabled if the two generator selections differ.
+        self.font_select_enabled = map_a != map_b;
+        self.font_offset_a = Sequencer::font_offset(map_a);
+        self.font_offset_b = Sequencer::font_offset(map_b);
+    }
+
+    /// Return the offset into plane 2 of the specified character map. The VGA's additional four character maps
+    /// are interleaved between the four EGA-compatible maps.
+    fn font_offset(map: u8) -> usize {
+        match map & 0x07 {
+            0b000 => 0x0000,
+            0b001 => 0x4000,
+            0b010 => 0x8000,
+            0b011 => 0xC000,
+            0b100 => 0x2000,
+            0b101 => 0x6000,
+            0b110 => 0xA000,
+            _ => 0xE000,
+        }
+    }
+
+    #[inline]
+    pub fn screen_off(&self) -> bool {
+        self.clocking_mode.screen_off()
+    }
+
+    #[inline]
+    pub fn nine_dot(&self) -> bool {
+        self.clocking_mode.character_clock() == CharacterClock::NineDots
+    }
+
+    /// Return whether chain-4 addressing is enabled. In chain-4 mode, the low two bits of the CPU address select
+    /// the plane to access.
+    #[inline]
+    pub fn chain4(&self) -> bool {
+        self.memory_mode.chain4()
+    }
+
+    #[inline]
+    pub fn read_u8(&self, plane: usize, addr: usize, _a0: usize) -> u8 {
+        self.vram.read_u8(plane, addr)
+    }
+
+    #[inline]
+    pub fn write_u8(&mut self, plane: usize, addr: usize, a0: usize, data: u8) {
+        if self.plane_mask(a0) & (1 << plane) != 0 {
+            self.vram.write_u8(plane, addr, data);
+        }
+    }
+
+    #[inline]
+    pub fn peek_u8(&self, plane: usize, addr: usize, a0: usize) -> u8 {
+        self.read_u8(plane, addr, a0)
+    }
+
+    #[inline]
+    pub fn read_linear(&self, addr: usize) -> u8 {
+        self.vram.read_linear(addr)
+    }
+
+    #[inline]
+    pub fn serialize_linear(&self, addr: usize) -> &[u8] {
+        self.vram.serialize_linear(addr)
+    }
+
+    /// Return the effective plane write mask, handling odd/even addressing. Chain-4 addressing takes priority
+    /// over odd/even addressing; the plane itself is selected by the Graphics Controller.
+    #[inline]
+    fn plane_mask(&self, a0: usize) -> u8 {
+        if self.memory_mode.chain4() {
+            return self.map_mask;
+        }
+        match self.memory_mode.odd_even() {
+            OddEvenMode::Sequential => self.map_mask,
+            OddEvenMode::OddEven => self.map_mask & (ODD_EVEN_MASK << a0),
+        }
+    }
+
+    #[inline]
+    pub fn plane_set(&mut self, plane: usize, addr: usize, a0: usize, data: u8) {
+        if self.plane_mask(a0) & (1 << plane) != 0 {
+            self.vram.plane_set(plane, addr, data);
+        }
+    }
+
+    #[inline]
+    pub fn plane_and(&mut self, plane: usize, addr: usize, a0: usize, data: u8) {
+        if self.plane_mask(a0) & (1 << plane) != 0 {
+            self.vram.plane_and(plane, addr, data);
+        }
+    }
+
+    #[inline]
+    pub fn plane_or(&mut self, plane: usize, addr: usize, a0: usize, data: u8) {
+        if self.plane_mask(a0) & (1 << plane) != 0 {
+            self.vram.plane_or(plane, addr, data);
+        }
+    }
+
+    pub fn font_select_enabled(&self) -> bool {
+        self.font_select_enabled
+    }
+
+    /// Return the raw glyph row byte of the selected font glyph.
+    #[inline]
+    pub fn get_glyph_byte(&self, glyph: u8, font: u8, row: u8) -> u8 {
+        self.vram.read_glyph(self.get_glyph_address(glyph, font, row))
+    }
+
+    /// Return a packed u64 value representing the 8-pixel span of the selected font glyph.
+    #[inline]
+    pub fn get_glyph_span(&self, glyph: u8, font: u8, row: u8) -> u64 {
+        BIT_EXTEND_TABLE64[self.get_glyph_byte(glyph, font, row) as usize]
+    }
+
+    pub fn get_glyph_address(&self, glyph: u8, font: u8, row: u8) -> usize {
+        let offset = match font {
+            0 => self.font_offset_b,
+            _ => self.font_offset_a,
+        };
+        offset + ((glyph as usize) * VGA_CHARACTER_HEIGHT) + row as usize
+    }
+
+    #[rustfmt::skip]
+    pub fn get_state(&self) -> Vec<(String, VideoCardStateEntry)> {
+        let mut sequencer_vec = Vec::new();
+        sequencer_vec.push((format!("{:?}", SequencerRegister::Reset), VideoCardStateEntry::String(format!("{:02b}", self.reset))));
+        sequencer_vec.push((format!("{:?}", SequencerRegister::ClockingMode), VideoCardStateEntry::String(format!("{:06b}", self.clocking_mode.into_bytes()[0]))));
+        sequencer_vec.push((format!("{:?} [cc]", SequencerRegister::ClockingMode), VideoCardStateEntry::String(format!("{:?}", self.clocking_mode.character_clock()))));
+        sequencer_vec.push((format!("{:?} [sl]", SequencerRegister::ClockingMode), VideoCardStateEntry::String(format!("{}", self.clocking_mode.shift_load()))));
+        sequencer_vec.push((format!("{:?} [dc]", SequencerRegister::ClockingMode), VideoCardStateEntry::String(format!("{:?}", self.clocking_mode.dot_clock()))));
+        sequencer_vec.push((format!("{:?} [sf]", SequencerRegister::ClockingMode), VideoCardStateEntry::String(format!("{}", self.clocking_mode.shift_four()))));
+        sequencer_vec.push((format!("{:?} [so]", SequencerRegister::ClockingMode), VideoCardStateEntry::String(format!("{}", self.clocking_mode.screen_off()))));
+
+        sequencer_vec.push((format!("{:?}", SequencerRegister::MapMask), VideoCardStateEntry::String(format!("{:04b}", self.map_mask))));
+        sequencer_vec.push((format!("{:?}", SequencerRegister::CharacterMapSelect), VideoCardStateEntry::String(format!("{:06b}", self.character_map_select.into_bytes()[0]))));
+        sequencer_vec.push((format!("{:?}", SequencerRegister::MemoryMode), VideoCardStateEntry::String(format!("{:04b}", self.memory_mode.into_bytes()[0]))));
+        sequencer_vec.push((format!("{:?} [em]", SequencerRegister::MemoryMode), VideoCardStateEntry::String(format!("{}", self.memory_mode.extended_memory()))));
+        sequencer_vec.push((format!("{:?} [oe]", SequencerRegister::MemoryMode), VideoCardStateEntry::String(format!("{:?}", self.memory_mode.odd_even()))));
+        sequencer_vec.push((format!("{:?} [c4]", SequencerRegister::MemoryMode), VideoCardStateEntry::String(format!("{}", self.memory_mode.chain4()))));
+        sequencer_vec
+    }
+}
+
+impl_state_enum!(SequencerRegister {
+    Reset,
+    ClockingMode,
+    MapMask,
+    CharacterMapSelect,
+    MemoryMode,
+    Invalid,
+});
+
+impl SaveState for Sequencer {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.address_byte);
+        w.put(&self.register_selected);
+        w.put(&self.reset);
+        w.put(&self.clocking_mode.into_bytes()[0]);
+        w.put(&self.map_mask);
+        w.put(&self.character_map_select.into_bytes()[0]);
+        w.put(&self.memory_mode.into_bytes()[0]);
+        w.put(&self.clock_change_pending);
+        w.put(&self.clock_divisor);
+        w.put(&self.char_width);
+        w.put(&self.char_clock);
+        w.put(&self.font_select_enabled);
+        w.put(&self.font_offset_a);
+        w.put(&self.font_offset_b);
+        self.vram.save_state(w);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.address_byte = r.get()?;
+        self.register_selected = r.get()?;
+        self.reset = r.get()?;
+        self.clocking_mode = SClockingModeRegister::from_bytes([r.get()?]);
+        self.map_mask = r.get()?;
+        self.character_map_select = SCharacterMapSelect::from_bytes([r.get()?]);
+        self.memory_mode = SMemoryModeRegister::from_bytes([r.get()?]);
+        self.clock_change_pending = r.get()?;
+        self.clock_divisor = r.get()?;
+        self.char_width = r.get()?;
+        self.char_clock = r.get()?;
+        self.font_select_enabled = r.get()?;
+        self.font_offset_a = r.get()?;
+        self.font_offset_b = r.get()?;
+        self.vram.load_state(r)?;
+        Ok(())
+    }
+}
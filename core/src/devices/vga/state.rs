@@ -0,0 +1,113 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::vga::state.rs
+
+    Save state support for the IBM VGA card.
+
+    The frame buffers are not saved - they are redrawn as the restored card
+    continues to run.
+*/
+
+use super::*;
+use crate::savestate::{SaveState, StateReader, StateWriter};
+
+impl SaveState for VGACard {
+    fn save_state(&self, w: &mut StateWriter) {
+        w.put(&self.ticks_accum);
+        w.put(&self.clock_mode);
+        w.put(&self.cycles);
+        w.put(&self.display_mode);
+        w.put(&self.mode_graphics);
+        w.put(&self.scanline);
+        w.put(&self.frame);
+        w.put(&self.raster_x);
+        w.put(&self.raster_y);
+        w.put(&self.cur_char);
+        w.put(&self.cur_attr);
+        w.put(&self.blink_state);
+        self.crtc.save_state(w);
+        w.put(&self.vma);
+        self.sequencer.save_state(w);
+        self.gc.save_state(w);
+        self.ac.save_state(w);
+        w.put(&self.misc_output_register.into_bytes()[0]);
+        w.put(&self.color_pel_write_address);
+        w.put(&self.color_pel_write_address_color);
+        w.put(&self.color_pel_write_latch);
+        w.put(&self.color_pel_read_address);
+        w.put(&self.color_pel_read_address_color);
+        w.put(&self.color_dac_state);
+        w.put(&self.color_pel_mask);
+        w.put(&self.color_registers);
+        w.put(&self.color_registers_rgba);
+        w.put(&self.color_registers_out);
+        self.extents.save_state(w);
+        w.put(&self.rba);
+        w.put(&self.hsync_ct);
+        w.put(&self.vsync_ct);
+        w.put(&self.intr);
+        w.put(&self.last_intr);
+    }
+
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), anyhow::Error> {
+        self.ticks_accum = r.get()?;
+        self.clock_mode = r.get()?;
+        self.cycles = r.get()?;
+        self.display_mode = r.get()?;
+        self.mode_graphics = r.get()?;
+        self.scanline = r.get()?;
+        self.frame = r.get()?;
+        self.raster_x = r.get()?;
+        self.raster_y = r.get()?;
+        self.cur_char = r.get()?;
+        self.cur_attr = r.get()?;
+        self.blink_state = r.get()?;
+        self.crtc.load_state(r)?;
+        self.vma = r.get()?;
+        self.sequencer.load_state(r)?;
+        self.gc.load_state(r)?;
+        self.ac.load_state(r)?;
+        self.misc_output_register = EMiscellaneousOutputRegister::from_bytes([r.get()?]);
+        self.color_pel_write_address = r.get()?;
+        self.color_pel_write_address_color = r.get()?;
+        self.color_pel_write_latch = r.get()?;
+        self.color_pel_read_address = r.get()?;
+        self.color_pel_read_address_color = r.get()?;
+        self.color_dac_state = r.get()?;
+        self.color_pel_mask = r.get()?;
+        self.color_registers = r.get()?;
+        self.color_registers_rgba = r.get()?;
+        self.color_registers_out = r.get()?;
+        self.extents.load_state(r)?;
+        self.rba = r.get()?;
+        self.hsync_ct = r.get()?;
+        self.vsync_ct = r.get()?;
+        self.intr = r.get()?;
+        self.last_intr = r.get()?;
+        Ok(())
+    }
+}
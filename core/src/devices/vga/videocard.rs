@@ -0,0 +1,348 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    ---------------------------------------------------------------------------
+
+    vga::videocard.rs
+
+    Implement the VideoCard trait for VGA
+
+*/
+
+use super::*;
+use crate::{bus::DeviceRunTimeUnit, devices::pic::Pic};
+use std::{collections::HashMap, path::Path};
+
+impl VideoCard for VGACard {
+    fn get_sync(&self) -> (bool, bool, bool, bool) {
+        (false, false, false, false)
+    }
+
+    fn set_video_option(&mut self, opt: VideoOption) {
+        match opt {
+            VideoOption::EnableSnow(_state) => {
+                log::warn!("VideoOption::EnableSnow not supported for VGA");
+            }
+            VideoOption::DebugDraw(state) => {
+                log::debug!("VideoOption::DebugDraw set to: {}", state);
+                self.debug_draw = state;
+            }
+        }
+    }
+
+    fn get_video_type(&self) -> VideoType {
+        VideoType::VGA
+    }
+
+    fn get_render_mode(&self) -> RenderMode {
+        RenderMode::Direct
+    }
+
+    /// The VGA frame buffer holds 8-bit DAC indices, but in 16 color modes only the 6-bit
+    /// palette range is in use.
+    fn get_render_depth(&self) -> RenderBpp {
+        // The 256-color modes, chained or not, produce 8-bit pixels that bypass the Color Select register.
+        if self.ac.eight_bit_mode() {
+            RenderBpp::Eight
+        }
+        else {
+            RenderBpp::Six
+        }
+    }
+
+    fn get_display_mode(&self) -> DisplayMode {
+        self.display_mode
+    }
+
+    fn set_clocking_mode(&mut self, mode: ClockingMode) {
+        log::debug!("Clocking mode set to: {:?}", mode);
+        self.clock_mode = mode;
+    }
+
+    fn get_display_size(&self) -> (u32, u32) {
+        let width = (self.crtc.horizontal_display_end() as u32 + 1) * self.sequencer.char_clock;
+        let height = self.crtc.vertical_display_end() as u32 + 1;
+        (width, height)
+    }
+
+    /// Return the 16-bit value computed from the CRTC's pair of Page Address registers.
+    fn get_start_address(&self) -> u16 {
+        self.crtc.start_address()
+    }
+
+    fn get_display_extents(&self) -> &DisplayExtents {
+        &self.extents
+    }
+
+    fn get_beam_pos(&self) -> Option<(u32, u32)> {
+        Some((self.raster_x, self.raster_y))
+    }
+
+    fn set_light_pen(&mut self, _pos: Option<(u32, u32)>, _switch: bool) {}
+
+    /// Unimplemented
+    fn debug_tick(&mut self, _ticks: u32) {}
+
+    /// Get the current scanline being rendered.
+    fn get_scanline(&self) -> u32 {
+        self.scanline
+    }
+
+    /// Return whether to double scanlines produced by this adapter.
+    /// The VGA performs its own double-scanning, so this is always false.
+    fn get_scanline_double(&self) -> bool {
+        self.extents.double_scan
+    }
+
+    /// Return the u8 slice representing the requested buffer type. VGA buffers hold RGBA pixels.
+    fn get_buf(&self, buf_select: BufferSelect) -> &[u8] {
+        match buf_select {
+            BufferSelect::Back => bytemuck::cast_slice(&self.buf[self.back_buf][..]),
+            BufferSelect::Front => bytemuck::cast_slice(&self.buf[self.front_buf][..]),
+        }
+    }
+
+    fn get_display_buf(&self) -> &[u8] {
+        bytemuck::cast_slice(&self.buf[self.front_buf][..])
+    }
+
+    fn list_display_apertures(&self) -> Vec<DisplayApertureDesc> {
+        VGA_APERTURE_DESCS.to_vec()
+    }
+
+    fn get_display_apertures(&self) -> Vec<DisplayAperture> {
+        self.extents.apertures.clone()
+    }
+
+    fn get_overscan_color(&self) -> u8 {
+        self.ac.overscan_color()
+    }
+
+    /// Return the RGBA output of the DAC, with the PEL mask already applied. Pixels are resolved through this
+    /// palette as they are drawn to the frame buffer.
+    fn get_palette(&self) -> Option<&[[u8; 4]]> {
+        Some(&self.color_registers_out)
+    }
+
+    /// Return the current refresh rate. This is calculated from the master clock and the CRTC's
+    /// horizontal and vertical totals, giving 70Hz for 400-line modes and 60Hz for 480-line modes.
+    fn get_refresh_rate(&self) -> u32 {
+        let clock_hz = match self.misc_output_register.clock_select() {
+            ClockSelect::Clock28 => VGA_CLOCK_2,
+            _ => VGA_CLOCK_1,
+        } * 1_000_000.0;
+
+        let frame_dots = self.crtc.horizontal_total() * self.sequencer.char_clock * self.crtc.vertical_total();
+        (clock_hz / frame_dots as f64).round() as u32
+    }
+
+    fn get_clock_divisor(&self) -> u32 {
+        self.sequencer.clock_divisor
+    }
+
+    fn is_40_columns(&self) -> bool {
+        matches!(
+            self.display_mode,
+            DisplayMode::Mode0TextBw40
+                | DisplayMode::Mode1TextCo40
+                | DisplayMode::Mode4LowResGraphics
+                | DisplayMode::Mode5LowResAltPalette
+        )
+    }
+
+    fn is_graphics_mode(&self) -> bool {
+        self.mode_graphics
+    }
+
+    fn get_cursor_info(&self) -> CursorInfo {
+        let addr = self.get_cursor_address();
+
+        let span = self.crtc.get_cursor_span();
+        match self.display_mode {
+            DisplayMode::Mode0TextBw40 | DisplayMode::Mode1TextCo40 => CursorInfo {
+                addr: addr as usize,
+                pos_x: addr % 40,
+                pos_y: addr / 40,
+                line_start: span.0,
+                line_end: span.1,
+                visible: self.get_cursor_status(),
+            },
+            DisplayMode::Mode2TextBw80 | DisplayMode::Mode3TextCo80 => CursorInfo {
+                addr: addr as usize,
+                pos_x: addr % 80,
+                pos_y: addr / 80,
+                line_start: span.0,
+                line_end: span.1,
+                visible: self.get_cursor_status(),
+            },
+            _ => {
+                // Not a valid text mode
+                CursorInfo {
+                    addr: 0,
+                    pos_x: 0,
+                    pos_y: 0,
+                    line_start: 0,
+                    line_end: 0,
+                    visible: false,
+                }
+            }
+        }
+    }
+
+    fn get_current_font(&self) -> Option<FontInfo> {
+        None
+    }
+
+    fn get_character_height(&self) -> u8 {
+        self.crtc.maximum_scanline() + 1
+    }
+
+    /// Return the current palette number, intensity attribute bit, and alt color
+    fn get_cga_palette(&self) -> (CGAPalette, bool) {
+        (CGAPalette::MagentaCyanWhite(CGAColor::Black), false)
+    }
+
+    #[rustfmt::skip]
+    /// Returns a string representation of all the CRTC Registers.
+    fn get_videocard_string_state(&self) -> HashMap<String, Vec<(String, VideoCardStateEntry)>> {
+        let mut map = HashMap::new();
+
+        let mut general_vec = Vec::new();
+        general_vec.push(("Adapter Type:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.get_video_type()))));
+        general_vec.push(("Display Mode:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.get_display_mode()))));
+        general_vec.push(("Pixel Clock:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.misc_output_register.clock_select()))));
+        general_vec.push(("Clock Divisor:".to_string(), VideoCardStateEntry::String(format!("{:?}", self.sequencer.clock_divisor))));
+        general_vec.push(("Refresh Rate:".to_string(), VideoCardStateEntry::String(format!("{}Hz", self.get_refresh_rate()))));
+        general_vec.push((
+            "Field:".to_string(),
+            VideoCardStateEntry::String(format!("{}x{}", self.extents.field_w, self.extents.field_h)),
+        ));
+
+        map.insert("General".to_string(), general_vec);
+        map.insert("CRTC".to_string(), self.crtc.get_state());
+
+        let mut external_vec = Vec::new();
+        external_vec.push(("Misc Output".to_string(), VideoCardStateEntry::String(format!("{:?}", self.misc_output_register.into_bytes()[0]))));
+        external_vec.push(("Misc Output [ios]".to_string(), VideoCardStateEntry::String(format!("{:?}", self.misc_output_register.io_address_select()))));
+        external_vec.push(("Misc Output [er]".to_string(), VideoCardStateEntry::String(format!("{:?}", self.misc_output_register.enable_ram()))));
+        external_vec.push(("Misc Output [cs]".to_string(), VideoCardStateEntry::String(format!("{:?}", self.misc_output_register.clock_select()))));
+        external_vec.push(("Misc Output [pb]".to_string(), VideoCardStateEntry::String(format!("{:?}", self.misc_output_register.oddeven_page_select()))));
+        external_vec.push(("Misc Output [hrp]".to_string(), VideoCardStateEntry::String(format!("{:?}", self.misc_output_register.horizontal_retrace_polarity()))));
+        external_vec.push(("Misc Output [vrp]".to_string(), VideoCardStateEntry::String(format!("{:?}", self.misc_output_register.vertical_retrace_polarity()))));
+        map.insert("External".to_string(), external_vec);
+
+        map.insert("Sequencer".to_string(), self.sequencer.get_state());
+        map.insert("Graphics".to_string(), self.gc.get_state());
+        map.insert("Attribute".to_string(), self.ac.get_state());
+
+        let mut attribute_pal_vec = Vec::new();
+        for (name, reg, dac) in self.ac.get_palette_state() {
+            let [r, g, b, _] = self.color_registers_rgba[dac as usize];
+            attribute_pal_vec.push((name, VideoCardStateEntry::Color(format!("{:06b}", reg), r, g, b)));
+        }
+        map.insert("AttributePalette".to_string(), attribute_pal_vec);
+
+        let mut dac_pal_vec = Vec::new();
+        for (i, [r, g, b, _]) in self.color_registers_rgba.iter().enumerate() {
+            dac_pal_vec.push((format!("{}", i), VideoCardStateEntry::Color(format!("#{:02x}{:02x}{:02x}", r, g, b), *r, *g, *b)));
+        }
+        map.insert("DACPalette".to_string(), dac_pal_vec);
+
+        map.insert("CRTC Counters".to_string(), self.crtc.get_counter_state());
+
+        let mut internal_vec = Vec::new();
+        internal_vec.push(("scanline:".to_string(), VideoCardStateEntry::String(format!("{}", self.scanline))));
+        internal_vec.push(("vma:".to_string(), VideoCardStateEntry::String(format!("{:04X}", self.vma))));
+        internal_vec.push(("rba:".to_string(), VideoCardStateEntry::String(format!("{:04X}", self.rba))));
+        internal_vec.push(("raster_x:".to_string(), VideoCardStateEntry::String(format!("{}", self.raster_x))));
+        internal_vec.push(("raster_y:".to_string(), VideoCardStateEntry::String(format!("{}", self.raster_y))));
+        internal_vec.push(("blink state:".to_string(), VideoCardStateEntry::String(format!("{}", self.blink_state))));
+        internal_vec.push(("hsync_ct:".to_string(), VideoCardStateEntry::String(format!("{}", self.hsync_ct))));
+        internal_vec.push(("vsync_ct:".to_string(), VideoCardStateEntry::String(format!("{}", self.vsync_ct))));
+        map.insert("Internal".to_string(), internal_vec);
+
+        map
+    }
+
+    fn run(&mut self, time: DeviceRunTimeUnit, pic: &mut Option<Pic>) {
+        if let DeviceRunTimeUnit::Microseconds(us) = time {
+            // Select the appropriate timings based on the current clocking mode
+            let ticks = match self.misc_output_register.clock_select() {
+                ClockSelect::Clock25 => us * VGA_CLOCK_1,
+                ClockSelect::Clock28 => us * VGA_CLOCK_2,
+                _ => 0.0,
+            };
+
+            self.tick(ticks, pic)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.reset_private();
+    }
+
+    fn get_pixel(&self, _x: u32, _y: u32) -> &[u8] {
+        &DUMMY_PIXEL
+    }
+
+    fn get_pixel_raw(&self, _x: u32, _y: u32) -> u8 {
+        0
+    }
+
+    fn get_plane_slice(&self, plane: usize) -> &[u8] {
+        self.sequencer.vram.plane_slice(plane)
+    }
+
+    fn dump_mem(&self, path: &Path) {
+        for i in 0..4 {
+            let mut filename = path.to_path_buf();
+            filename.push(format!("vga_plane{}.bin", i));
+
+            match std::fs::write(filename.clone(), self.get_plane_slice(i)) {
+                Ok(_) => {
+                    log::debug!("Wrote memory dump: {}", &filename.display())
+                }
+                Err(e) => {
+                    log::error!("Failed to write memory dump '{}': {}", &filename.display(), e)
+                }
+            }
+        }
+    }
+
+    fn get_frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    fn write_trace_log(&mut self, msg: String) {
+        self.trace_logger.print(msg);
+    }
+
+    fn trace_flush(&mut self) {
+        self.trace_logger.flush();
+    }
+
+    fn get_text_mode_strings(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
@@ -0,0 +1,519 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    devices::video_golden.rs
+
+    Golden frame regression tests for the CGA, MDA, EGA and VGA video cards.
+
+    Each test programs a card directly through its IO ports and video memory,
+    runs it for a number of frames and compares the MD5 digest of the display
+    field of selected frames to the golden digests in testdata/golden.
+
+    To accept an intentional rendering change, run the tests with the
+    MARTY_BLESS_GOLDEN environment variable set. The golden files will be
+    rewritten with the new digests.
+
+*/
+
+use std::path::PathBuf;
+
+use crate::{
+    bus::{DeviceRunTimeUnit, IoDevice, MemoryMappedDevice},
+    device_traits::videocard::{ClockingMode, VideoCard},
+    devices::{cga::CGACard, mda::MDACard},
+    tracelogger::TraceLogger,
+};
+
+/// Frames captured by every golden test. Frame 0 is the first frame completed after setup.
+const GOLDEN_FRAMES: [u64; 3] = [1, 8, 20];
+
+/// Safety limit on device run steps, in case a card stops producing frames.
+const MAX_RUN_STEPS: usize = 100_000;
+
+/// Run a card for enough frames to capture all of GOLDEN_FRAMES, returning one line per
+/// captured frame in the format `NNNNNN digest`.
+fn capture_hashes(card: &mut dyn VideoCard, step: DeviceRunTimeUnit) -> Vec<String> {
+    let mut hashes = Vec::new();
+    let last = *GOLDEN_FRAMES.iter().max().unwrap();
+    let mut last_count = card.get_frame_count();
+    let mut frame = 0;
+
+    for _ in 0..MAX_RUN_STEPS {
+        card.run(step, &mut None);
+        let count = card.get_frame_count();
+        if count != last_count {
+            last_count = count;
+            if GOLDEN_FRAMES.contains(&frame) {
+                let hash = card.get_display_extents().hash_field(card.get_display_buf());
+                hashes.push(format!("{:06} {}", frame, hash));
+            }
+            if frame == last {
+                return hashes;
+            }
+            frame += 1;
+        }
+    }
+    panic!("Video card stopped producing frames at frame {}", frame);
+}
+
+/// Compare captured frame digests to the named golden file, or rewrite it if blessing.
+fn check_golden(name: &str, hashes: Vec<String>) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "testdata", "golden", &format!("{}.txt", name)]
+        .iter()
+        .collect();
+
+    if std::env::var_os("MARTY_BLESS_GOLDEN").is_some() {
+        let mut text = hashes.join("\n");
+        text.push('\n');
+        std::fs::write(&path, text).expect("Failed to write golden file");
+        return;
+    }
+
+    let golden = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("Failed to read golden file {}: {}", path.display(), e));
+    let golden: Vec<&str> = golden.lines().filter(|l| !l.trim().is_empty()).collect();
+
+    assert_eq!(
+        golden,
+        hashes,
+        "{}: display output differs from golden file {}. If the change is intended, rerun with \
+        MARTY_BLESS_GOLDEN=1.",
+        name,
+        path.display()
+    );
+}
+
+fn io_write(card: &mut dyn IoDevice, port: u16, data: u8, delta: DeviceRunTimeUnit) {
+    card.write_u8(port, data, None, delta);
+}
+
+fn crtc_setup(card: &mut dyn IoDevice, base: u16, regs: &[u8], delta: DeviceRunTimeUnit) {
+    for (i, reg) in regs.iter().enumerate() {
+        io_write(card, base, i as u8, delta);
+        io_write(card, base + 1, *reg, delta);
+    }
+}
+
+/// Fill an 80x25 text page with every character and a range of attributes.
+fn text_page(card: &mut dyn MemoryMappedDevice, address: usize, attr: impl Fn(usize) -> u8) {
+    for i in 0..80 * 25 {
+        card.mmio_write_u8(address + i * 2, i as u8, 0);
+        card.mmio_write_u8(address + i * 2 + 1, attr(i), 0);
+    }
+}
+
+#[test]
+fn test_golden_cga_text() {
+    let mut cga = CGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+    let delta = DeviceRunTimeUnit::SystemTicks(0);
+
+    crtc_setup(
+        &mut cga,
+        0x3D4,
+        &[113, 80, 90, 10, 31, 6, 25, 28, 2, 7, 6, 7, 0, 0, 0, 0],
+        delta,
+    );
+    text_page(&mut cga, 0xB8000, |i| ((i / 80) as u8).wrapping_mul(0x17) | 0x01);
+    io_write(&mut cga, 0x3D9, 0x00, delta);
+    io_write(&mut cga, 0x3D8, 0x29, delta);
+
+    let hashes = capture_hashes(&mut cga, DeviceRunTimeUnit::SystemTicks(912));
+    check_golden("cga_text80", hashes);
+}
+
+#[test]
+fn test_golden_cga_graphics() {
+    let mut cga = CGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+    let delta = DeviceRunTimeUnit::SystemTicks(0);
+
+    crtc_setup(
+        &mut cga,
+        0x3D4,
+        &[56, 40, 45, 10, 127, 6, 100, 112, 2, 1, 6, 7, 0, 0, 0, 0],
+        delta,
+    );
+    for i in 0..0x4000 {
+        cga.mmio_write_u8(0xB8000 + i, (i as u8).wrapping_mul(0x1B) ^ (i >> 6) as u8, 0);
+    }
+    io_write(&mut cga, 0x3D9, 0x31, delta);
+    io_write(&mut cga, 0x3D8, 0x0A, delta);
+
+    let hashes = capture_hashes(&mut cga, DeviceRunTimeUnit::SystemTicks(912));
+    check_golden("cga_320x200", hashes);
+}
+
+#[test]
+fn test_golden_mda_text() {
+    let mut mda = MDACard::new(TraceLogger::None, ClockingMode::Dynamic, false, false);
+    let delta = DeviceRunTimeUnit::Microseconds(0.0);
+
+    crtc_setup(
+        &mut mda,
+        0x3B4,
+        &[97, 80, 82, 15, 25, 6, 25, 25, 2, 13, 11, 12, 0, 0, 0, 0],
+        delta,
+    );
+    text_page(&mut mda, 0xB0000, |i| [0x07, 0x0F, 0x70, 0x01, 0x87, 0x09][(i / 80) % 6]);
+    io_write(&mut mda, 0x3B8, 0x28, delta);
+
+    let hashes = capture_hashes(&mut mda, DeviceRunTimeUnit::Microseconds(50.0));
+    check_golden("mda_text80", hashes);
+}
+
+#[test]
+fn test_golden_hgc_graphics() {
+    let mut hgc = MDACard::new_hgc(TraceLogger::None, ClockingMode::Dynamic, false, false);
+    let delta = DeviceRunTimeUnit::Microseconds(0.0);
+
+    // Register values used by the Hercules BIOS extension for 720x348 graphics.
+    io_write(&mut hgc, 0x3BF, 0x01, delta);
+    io_write(&mut hgc, 0x3B8, 0x02, delta);
+    crtc_setup(
+        &mut hgc,
+        0x3B4,
+        &[0x35, 0x2D, 0x2E, 0x07, 0x5B, 0x02, 0x57, 0x57, 0x02, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        delta,
+    );
+    // Checkerboard with a diagonal line, written through the four interleaved scanline banks.
+    for y in 0..348 {
+        for col in 0..90 {
+            let mut byte = if ((col / 4) + (y / 32)) % 2 == 0 { 0xF0 } else { 0x00 };
+            if col == (y * 2 / 8) % 90 {
+                byte |= 0x80 >> ((y * 2) % 8);
+            }
+            hgc.mmio_write_u8(0xB0000 + (y % 4) * 0x2000 + (y / 4) * 90 + col, byte, 0);
+        }
+    }
+    io_write(&mut hgc, 0x3B8, 0x0A, delta);
+
+    let hashes = capture_hashes(&mut hgc, DeviceRunTimeUnit::Microseconds(50.0));
+    check_golden("hgc_720x348", hashes);
+}
+
+#[cfg(feature = "ega")]
+#[test]
+fn test_golden_ega_640x350() {
+    use crate::devices::ega::EGACard;
+
+    let mut ega = EGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+    let delta = DeviceRunTimeUnit::Microseconds(0.0);
+
+    // Register values from the IBM EGA BIOS parameter table for mode 10h.
+    io_write(&mut ega, 0x3C2, 0xA7, delta);
+    for (i, reg) in [0x03, 0x01, 0x0F, 0x00, 0x06].iter().enumerate() {
+        io_write(&mut ega, 0x3C4, i as u8, delta);
+        io_write(&mut ega, 0x3C5, *reg, delta);
+    }
+    crtc_setup(
+        &mut ega,
+        0x3D4,
+        &[
+            0x5B, 0x4F, 0x53, 0x37, 0x52, 0x00, 0x6C, 0x1F, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x5E,
+            0x2B, 0x5D, 0x28, 0x0F, 0x5F, 0x0A, 0xE3, 0xFF,
+        ],
+        delta,
+    );
+    for (i, reg) in [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x0F, 0xFF].iter().enumerate() {
+        io_write(&mut ega, 0x3CE, i as u8, delta);
+        io_write(&mut ega, 0x3CF, *reg, delta);
+    }
+    _ = ega.read_u8(0x3DA, delta);
+    for (i, reg) in [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F, 0x01, 0x00,
+        0x0F, 0x00,
+    ]
+    .iter()
+    .enumerate()
+    {
+        io_write(&mut ega, 0x3C0, i as u8, delta);
+        io_write(&mut ega, 0x3C0, *reg, delta);
+    }
+    // Enable video output.
+    io_write(&mut ega, 0x3C0, 0x20, delta);
+
+    // Draw sixteen vertical color bars with a diagonal stripe, one plane at a time.
+    for plane in 0..4 {
+        io_write(&mut ega, 0x3C4, 0x02, delta);
+        io_write(&mut ega, 0x3C5, 1 << plane, delta);
+        for row in 0..350 {
+            for col in 0..80 {
+                let color = col / 5;
+                let mut byte = if color & (1 << plane) != 0 { 0xFF } else { 0x00 };
+                if (row + col * 8) % 64 < 8 {
+                    byte ^= 0x81;
+                }
+                ega.mmio_write_u8(0xA0000 + row * 80 + col, byte, 0);
+            }
+        }
+    }
+    io_write(&mut ega, 0x3C4, 0x02, delta);
+    io_write(&mut ega, 0x3C5, 0x0F, delta);
+
+    let hashes = capture_hashes(&mut ega, DeviceRunTimeUnit::Microseconds(50.0));
+    check_golden("ega_640x350", hashes);
+}
+
+/// Program the VGA DAC with the 64-color EGA-compatible palette used by the VGA BIOS.
+#[cfg(feature = "vga")]
+fn vga_ega_dac(card: &mut dyn IoDevice, delta: DeviceRunTimeUnit) {
+    io_write(card, 0x3C8, 0x00, delta);
+    for i in 0..64u8 {
+        let r = ((i >> 2) & 1) * 0x2A + ((i >> 5) & 1) * 0x15;
+        let g = ((i >> 1) & 1) * 0x2A + ((i >> 4) & 1) * 0x15;
+        let b = (i & 1) * 0x2A + ((i >> 3) & 1) * 0x15;
+        io_write(card, 0x3C9, r, delta);
+        io_write(card, 0x3C9, g, delta);
+        io_write(card, 0x3C9, b, delta);
+    }
+}
+
+#[cfg(feature = "vga")]
+fn vga_setup(card: &mut dyn IoDevice, misc: u8, seq: &[u8], crtc: &[u8], gc: &[u8], ac: &[u8]) {
+    let delta = DeviceRunTimeUnit::Microseconds(0.0);
+    io_write(card, 0x3C2, misc, delta);
+    for (i, reg) in seq.iter().enumerate() {
+        io_write(card, 0x3C4, i as u8, delta);
+        io_write(card, 0x3C5, *reg, delta);
+    }
+    // Unlock CRTC registers 0-7 before programming them.
+    io_write(card, 0x3D4, 0x11, delta);
+    io_write(card, 0x3D5, 0x00, delta);
+    crtc_setup(card, 0x3D4, crtc, delta);
+    for (i, reg) in gc.iter().enumerate() {
+        io_write(card, 0x3CE, i as u8, delta);
+        io_write(card, 0x3CF, *reg, delta);
+    }
+    _ = card.read_u8(0x3DA, delta);
+    for (i, reg) in ac.iter().enumerate() {
+        io_write(card, 0x3C0, i as u8, delta);
+        io_write(card, 0x3C0, *reg, delta);
+    }
+    // Enable video output.
+    io_write(card, 0x3C0, 0x20, delta);
+    vga_ega_dac(card, delta);
+}
+
+/// Program a VGA card for 80x25 text with a generated font and fill the text page.
+#[cfg(feature = "vga")]
+fn vga_720x400() -> crate::devices::vga::VGACard {
+    let mut vga = crate::devices::vga::VGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+    let delta = DeviceRunTimeUnit::Microseconds(0.0);
+
+    // Register values from the IBM VGA BIOS parameter table for mode 3.
+    vga_setup(
+        &mut vga,
+        0x67,
+        &[0x03, 0x00, 0x03, 0x00, 0x02],
+        &[
+            0x5F, 0x4F, 0x50, 0x82, 0x55, 0x81, 0xBF, 0x1F, 0x00, 0x4F, 0x0D, 0x0E, 0x00, 0x00, 0x00, 0x00, 0x9C,
+            0x8E, 0x8F, 0x28, 0x1F, 0x96, 0xB9, 0xA3, 0xFF,
+        ],
+        &[0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x0E, 0x00, 0xFF],
+        &[
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F, 0x0C, 0x00,
+            0x0F, 0x08, 0x00,
+        ],
+    );
+
+    // Load a generated 8x16 font into plane 2 the way the BIOS does, with sequential addressing.
+    io_write(&mut vga, 0x3C4, 0x02, delta);
+    io_write(&mut vga, 0x3C5, 0x04, delta);
+    io_write(&mut vga, 0x3C4, 0x04, delta);
+    io_write(&mut vga, 0x3C5, 0x07, delta);
+    io_write(&mut vga, 0x3CE, 0x05, delta);
+    io_write(&mut vga, 0x3CF, 0x00, delta);
+    io_write(&mut vga, 0x3CE, 0x06, delta);
+    io_write(&mut vga, 0x3CF, 0x04, delta);
+    for glyph in 0..256usize {
+        for row in 0..16usize {
+            let byte = if row == 0 || row == 15 { 0 } else { (glyph as u8).rotate_left(row as u32) | 0x80 };
+            vga.mmio_write_u8(0xA0000 + glyph * 32 + row, byte, 0);
+        }
+    }
+    io_write(&mut vga, 0x3C4, 0x02, delta);
+    io_write(&mut vga, 0x3C5, 0x03, delta);
+    io_write(&mut vga, 0x3C4, 0x04, delta);
+    io_write(&mut vga, 0x3C5, 0x02, delta);
+    io_write(&mut vga, 0x3CE, 0x05, delta);
+    io_write(&mut vga, 0x3CF, 0x10, delta);
+    io_write(&mut vga, 0x3CE, 0x06, delta);
+    io_write(&mut vga, 0x3CF, 0x0E, delta);
+
+    text_page(&mut vga, 0xB8000, |i| (i / 80) as u8 | 0x08);
+    vga
+}
+
+#[cfg(feature = "vga")]
+#[test]
+fn test_golden_vga_720x400() {
+    let mut vga = vga_720x400();
+    let hashes = capture_hashes(&mut vga, DeviceRunTimeUnit::Microseconds(50.0));
+    check_golden("vga_720x400", hashes);
+}
+
+#[cfg(feature = "vga")]
+#[test]
+fn test_golden_vga_720x400_restored() {
+    use crate::savestate::{SaveState, StateReader, StateWriter};
+
+    // Save the card partway through its first frame and restore it into a new card. The restored
+    // card must produce the same frames as the original would have.
+    let mut vga = vga_720x400();
+    vga.run(DeviceRunTimeUnit::Microseconds(5_000.0), &mut None);
+    let mut w = StateWriter::new();
+    vga.save_state(&mut w);
+    let data = w.into_inner();
+
+    let mut restored = crate::devices::vga::VGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+    let mut r = StateReader::new(&data);
+    restored.load_state(&mut r).expect("Failed to load VGA state");
+    assert_eq!(r.remaining(), 0);
+
+    let hashes = capture_hashes(&mut restored, DeviceRunTimeUnit::Microseconds(50.0));
+    check_golden("vga_720x400", hashes);
+}
+
+#[cfg(feature = "vga")]
+#[test]
+fn test_golden_vga_640x480_split() {
+    use crate::devices::vga::VGACard;
+
+    let mut vga = VGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+    let delta = DeviceRunTimeUnit::Microseconds(0.0);
+
+    // Register values from the IBM VGA BIOS parameter table for mode 12h, with the line compare
+    // set to scanline 240 and the start address moved down by 5 lines.
+    vga_setup(
+        &mut vga,
+        0xE3,
+        &[0x03, 0x01, 0x0F, 0x00, 0x06],
+        &[
+            0x5F, 0x4F, 0x50, 0x82, 0x54, 0x80, 0x0B, 0x2E, 0x00, 0x00, 0x00, 0x00, 0x01, 0x90, 0x00, 0x00, 0xEA,
+            0x8C, 0xDF, 0x28, 0x00, 0xE7, 0x04, 0xE3, 0xF0,
+        ],
+        &[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x0F, 0xFF],
+        &[
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x14, 0x07, 0x38, 0x39, 0x3A, 0x3B, 0x3C, 0x3D, 0x3E, 0x3F, 0x01, 0x00,
+            0x0F, 0x03, 0x00,
+        ],
+    );
+
+    // Draw sixteen vertical color bars with a diagonal stripe, one plane at a time.
+    for plane in 0..4 {
+        io_write(&mut vga, 0x3C4, 0x02, delta);
+        io_write(&mut vga, 0x3C5, 1 << plane, delta);
+        for row in 0..496 {
+            for col in 0..80 {
+                let color = col / 5;
+                let mut byte = if color & (1 << plane) != 0 { 0xFF } else { 0x00 };
+                if (row + col * 8) % 64 < 8 {
+                    byte ^= 0x81;
+                }
+                vga.mmio_write_u8(0xA0000 + row * 80 + col, byte, 0);
+            }
+        }
+    }
+    io_write(&mut vga, 0x3C4, 0x02, delta);
+    io_write(&mut vga, 0x3C5, 0x0F, delta);
+
+    let hashes = capture_hashes(&mut vga, DeviceRunTimeUnit::Microseconds(50.0));
+    check_golden("vga_640x480_split", hashes);
+}
+
+#[cfg(feature = "vga")]
+#[test]
+fn test_golden_vga_320x200_256() {
+    use crate::devices::vga::VGACard;
+
+    let mut vga = VGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+
+    // Register values from the IBM VGA BIOS parameter table for mode 13h.
+    vga_setup(
+        &mut vga,
+        0x63,
+        &[0x03, 0x01, 0x0F, 0x00, 0x0E],
+        &[
+            0x5F, 0x4F, 0x50, 0x82, 0x54, 0x80, 0xBF, 0x1F, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x9C,
+            0x8E, 0x8F, 0x28, 0x40, 0x96, 0xB9, 0xA3, 0xFF,
+        ],
+        &[0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x05, 0x0F, 0xFF],
+        &[
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x41, 0x00,
+            0x0F, 0x00, 0x00,
+        ],
+    );
+
+    // Draw a diagonal gradient through all 256 colors with a box outline, using chain-4 addressing.
+    for y in 0..200 {
+        for x in 0..320 {
+            let color = if x == 0 || x == 319 || y == 0 || y == 199 { 0x0F } else { (x + y) as u8 };
+            vga.mmio_write_u8(0xA0000 + y * 320 + x, color, 0);
+        }
+    }
+
+    let hashes = capture_hashes(&mut vga, DeviceRunTimeUnit::Microseconds(50.0));
+    check_golden("vga_320x200_256", hashes);
+}
+
+#[cfg(feature = "vga")]
+#[test]
+fn test_golden_vga_320x240_unchained() {
+    use crate::devices::vga::VGACard;
+
+    let mut vga = VGACard::new(TraceLogger::None, ClockingMode::Dynamic, false);
+    let delta = DeviceRunTimeUnit::Microseconds(0.0);
+
+    // Mode 13h with chain-4 disabled, the CRTC in byte mode and 480-line timings: the 320x240 'Mode X'.
+    vga_setup(
+        &mut vga,
+        0xE3,
+        &[0x03, 0x01, 0x0F, 0x00, 0x06],
+        &[
+            0x5F, 0x4F, 0x50, 0x82, 0x54, 0x80, 0x0D, 0x3E, 0x00, 0x41, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xEA,
+            0xAC, 0xDF, 0x28, 0x00, 0xE7, 0x06, 0xE3, 0xFF,
+        ],
+        &[0x00, 0x00, 0x00, 0x00, 0x00, 0x40, 0x05, 0x0F, 0xFF],
+        &[
+            0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D, 0x0E, 0x0F, 0x41, 0x00,
+            0x0F, 0x00, 0x00,
+        ],
+    );
+
+    // Draw the same gradient one plane at a time. Each plane holds every fourth pixel.
+    for plane in 0..4 {
+        io_write(&mut vga, 0x3C4, 0x02, delta);
+        io_write(&mut vga, 0x3C5, 1 << plane, delta);
+        for y in 0..240 {
+            for x in (plane..320).step_by(4) {
+                let color = if x == 0 || x == 319 || y == 0 || y == 239 { 0x0F } else { (x + y) as u8 };
+                vga.mmio_write_u8(0xA0000 + y * 80 + x / 4, color, 0);
+            }
+        }
+    }
+
+    let hashes = capture_hashes(&mut vga, DeviceRunTimeUnit::Microseconds(50.0));
+    check_golden("vga_320x240_unchained", hashes);
+}
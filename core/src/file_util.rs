@@ -42,3 +42,15 @@ pub fn find_unique_filename(path: &Path, base: &str, ext: &str) -> PathBuf {
 
     test_path
 }
+
+pub fn find_unique_dirname(path: &Path, base: &str) -> PathBuf {
+    let mut i = 1;
+    let mut test_path = path.join(format!("{}{:03}", base, i));
+
+    while test_path.exists() {
+        i += 1;
+        test_path = path.join(format!("{}{:03}", base, i));
+    }
+
+    test_path
+}
@@ -0,0 +1,287 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    floppy_image::imd.rs
+
+    Implements reading and writing ImageDisk (.IMD) floppy images.
+
+    An IMD file begins with an ASCII header and comment terminated by 0x1A,
+    followed by a list of tracks. Each track records its sector numbering
+    map, optional cylinder and head maps for sectors whose IDs don't match
+    their physical location, and a record per sector describing whether it
+    has data, is compressed to a single fill byte, has a deleted data
+    address mark or was read with a data CRC error.
+
+    IMD has no way to record a sector ID with a CRC error, so such sectors
+    are written as normal sectors.
+*/
+
+use anyhow::{bail, Error};
+
+use crate::{
+    bytebuf::ByteBuf,
+    floppy_image::{read_vec, timestamp, FloppyImage, FloppyImageFormat, FloppySector, FloppyTrack, SectorId},
+};
+
+pub const IMD_SIGNATURE: &[u8; 4] = b"IMD ";
+pub const IMD_COMMENT_END: u8 = 0x1A;
+
+pub const IMD_HEAD_MASK: u8 = 0x01;
+pub const IMD_CYLINDER_MAP: u8 = 0x80;
+pub const IMD_HEAD_MAP: u8 = 0x40;
+pub const IMD_SIZE_TABLE: u8 = 0xFF;
+
+/// Track modes for 250kbps (double density) and 500kbps (high density) MFM.
+pub const IMD_MODE_MFM_250K: u8 = 5;
+pub const IMD_MODE_MFM_500K: u8 = 3;
+/// Tracks holding more data than this must have been recorded at high density.
+pub const IMD_DD_TRACK_CAPACITY: usize = 6250;
+
+pub fn is_imd(data: &[u8]) -> bool {
+    data.starts_with(IMD_SIGNATURE)
+}
+
+pub fn load(data: &[u8]) -> Result<FloppyImage, Error> {
+    let comment_end = match data.iter().position(|&b| b == IMD_COMMENT_END) {
+        Some(pos) => pos,
+        None => bail!("IMD header is not terminated"),
+    };
+
+    // The first line of the header holds the ImageDisk version and creation date; the rest is the comment.
+    let header = String::from_utf8_lossy(&data[..comment_end]);
+    let (version, comment) = header.split_once("\r\n").unwrap_or((&header, ""));
+    log::debug!("Loading ImageDisk image: {}", version);
+
+    let mut buf = ByteBuf::from_slice(&data[comment_end + 1..]);
+    let mut tracks = Vec::new();
+    let mut heads = 1;
+
+    while buf.tell() < buf.len() {
+        let mode = buf.read_u8()?;
+        let cylinder = buf.read_u8()?;
+        let head_flags = buf.read_u8()?;
+        let sector_ct = buf.read_u8()? as usize;
+        let size_code = buf.read_u8()?;
+
+        if mode > 5 {
+            bail!("Invalid IMD track mode: {}", mode);
+        }
+
+        let head = head_flags & IMD_HEAD_MASK;
+        heads = heads.max(head + 1);
+
+        let sector_map = read_vec(&mut buf, sector_ct)?;
+        let cylinder_map = match head_flags & IMD_CYLINDER_MAP {
+            0 => vec![cylinder; sector_ct],
+            _ => read_vec(&mut buf, sector_ct)?,
+        };
+        let head_map = match head_flags & IMD_HEAD_MAP {
+            0 => vec![head; sector_ct],
+            _ => read_vec(&mut buf, sector_ct)?,
+        };
+
+        // A size code of 0xFF is followed by a table of sector sizes in bytes.
+        let mut sizes = Vec::with_capacity(sector_ct);
+        for _ in 0..sector_ct {
+            sizes.push(match size_code {
+                IMD_SIZE_TABLE => buf.read_u16_le()? as usize,
+                n if n <= 6 => 128 << n,
+                n => bail!("Invalid IMD sector size code: {}", n),
+            });
+        }
+
+        let mut track = FloppyTrack::new(cylinder, head);
+        for i in 0..sector_ct {
+            let size = sizes[i];
+            let n = (0..=6).find(|&n| 128usize << n >= size).unwrap_or(6);
+            let id = SectorId::new(cylinder_map[i], head_map[i], sector_map[i], n);
+
+            let record = buf.read_u8()?;
+            let data = match record {
+                0 => None,
+                1 | 3 | 5 | 7 => Some(read_vec(&mut buf, size)?),
+                2 | 4 | 6 | 8 => Some(vec![buf.read_u8()?; size]),
+                _ => bail!("Invalid IMD sector record type: {}", record),
+            };
+
+            track.sectors.push(FloppySector {
+                id,
+                data,
+                deleted: matches!(record, 3 | 4 | 7 | 8),
+                id_crc_error: false,
+                data_crc_error: matches!(record, 5..=8),
+            });
+        }
+        tracks.push(track);
+    }
+
+    let mut image = FloppyImage::from_tracks(FloppyImageFormat::ImageDisk, heads, tracks)?;
+    image.comment = comment.trim_end().to_string();
+    Ok(image)
+}
+
+pub fn save(image: &FloppyImage) -> Result<Vec<u8>, Error> {
+    let (year, month, day, hour, minute, second) = timestamp();
+    let mut imd = format!(
+        "IMD 1.18: {:02}/{:02}/{:04} {:02}:{:02}:{:02}\r\n",
+        day, month, year, hour, minute, second
+    )
+    .into_bytes();
+    if !image.comment.is_empty() {
+        imd.extend_from_slice(image.comment.replace('\n', "\r\n").as_bytes());
+        imd.extend_from_slice(b"\r\n");
+    }
+    imd.push(IMD_COMMENT_END);
+
+    for track in image.tracks() {
+        // IMD records unformatted tracks by omitting them
+        if track.sectors.is_empty() {
+            continue;
+        }
+        if track.sectors.len() > u8::MAX as usize {
+            bail!("Too many sectors on track {}:{}", track.cylinder, track.head);
+        }
+
+        let track_bytes: usize = track.sectors.iter().map(|s| s.id.size()).sum();
+        let mode = match track_bytes > IMD_DD_TRACK_CAPACITY {
+            true => IMD_MODE_MFM_500K,
+            false => IMD_MODE_MFM_250K,
+        };
+
+        // Sectors of mixed sizes need a size table
+        let first_n = track.sectors[0].id.n;
+        let size_code = match track.sectors.iter().all(|s| s.id.n == first_n) && first_n <= 6 {
+            true => first_n,
+            false => IMD_SIZE_TABLE,
+        };
+
+        let cylinder_map = track.sectors.iter().any(|s| s.id.c != track.cylinder);
+        let head_map = track.sectors.iter().any(|s| s.id.h != track.head);
+        let mut head_flags = track.head;
+        if cylinder_map {
+            head_flags |= IMD_CYLINDER_MAP;
+        }
+        if head_map {
+            head_flags |= IMD_HEAD_MAP;
+        }
+
+        imd.extend_from_slice(&[mode, track.cylinder, head_flags, track.sectors.len() as u8, size_code]);
+        imd.extend(track.sectors.iter().map(|s| s.id.r));
+        if cylinder_map {
+            imd.extend(track.sectors.iter().map(|s| s.id.c));
+        }
+        if head_map {
+            imd.extend(track.sectors.iter().map(|s| s.id.h));
+        }
+        if size_code == IMD_SIZE_TABLE {
+            for sector in &track.sectors {
+                imd.extend_from_slice(&(sector.id.size() as u16).to_le_bytes());
+            }
+        }
+
+        for sector in &track.sectors {
+            let Some(data) = &sector.data
+            else {
+                imd.push(0);
+                continue;
+            };
+
+            let mut data = data.clone();
+            data.resize(sector.id.size(), 0);
+
+            // Record types are 1 for normal data or 2 for compressed data, plus 2 if the data is
+            // deleted and 4 if it had a CRC error
+            let compressed = data.iter().all(|&b| b == data[0]);
+            let mut record = if compressed { 2 } else { 1 };
+            if sector.deleted {
+                record += 2;
+            }
+            if sector.data_crc_error {
+                record += 4;
+            }
+
+            imd.push(record);
+            if compressed {
+                imd.push(data[0]);
+            }
+            else {
+                imd.extend_from_slice(&data);
+            }
+        }
+    }
+    Ok(imd)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::floppy_image::SectorMatch;
+
+    #[test]
+    fn test_imd_load() {
+        let mut imd = b"IMD 1.18: 01/01/2024 0:00:00\r\ntest disk\x1A".to_vec();
+
+        // Track 0, head 0: sectors 1 and 5 normal and compressed, sector 3 deleted with a data error,
+        // sector 7 with no data. Sector 5 carries a cylinder ID of 9.
+        imd.extend_from_slice(&[3, 0, IMD_CYLINDER_MAP, 4, 2]);
+        imd.extend_from_slice(&[1, 3, 5, 7]);
+        imd.extend_from_slice(&[0, 0, 9, 0]);
+        imd.push(1);
+        imd.extend_from_slice(&[0xAA; 512]);
+        imd.push(7);
+        imd.extend_from_slice(&[0xBB; 512]);
+        imd.extend_from_slice(&[2, 0xF6]);
+        imd.push(0);
+
+        // Track 0, head 1: a single 1K sector
+        imd.extend_from_slice(&[3, 0, 1, 1, 3, 1, 2, 0xE5]);
+
+        let image = load(&imd).unwrap();
+        assert_eq!(image.comment, "test disk");
+        assert_eq!(image.heads, 2);
+        assert_eq!(image.cylinders, 1);
+
+        let track = image.track(0, 0).unwrap();
+        assert_eq!(track.sectors.len(), 4);
+        assert!(track.sectors[1].deleted && track.sectors[1].data_crc_error);
+        assert_eq!(track.sectors[2].id, SectorId::new(9, 0, 5, 2));
+        assert_eq!(track.sectors[2].data.as_deref(), Some(&[0xF6; 512][..]));
+        assert!(track.sectors[3].data.is_none());
+        assert!(matches!(
+            track.find_sector(&SectorId::new(0, 0, 5, 2), 0),
+            SectorMatch::WrongCylinder(9)
+        ));
+
+        let track = image.track(0, 1).unwrap();
+        assert_eq!(track.sectors[0].id, SectorId::new(0, 1, 1, 3));
+        assert_eq!(track.sectors[0].data.as_ref().unwrap().len(), 1024);
+
+        // Saving and reloading the image should preserve every track
+        let saved = load(&save(&image).unwrap()).unwrap();
+        assert_eq!(saved.comment, image.comment);
+        assert!(saved.tracks() == image.tracks());
+    }
+}
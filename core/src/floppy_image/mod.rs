@@ -0,0 +1,529 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    floppy_image::mod.rs
+
+    Implements a track-oriented floppy disk image model.
+
+    Raw sector dumps can only describe disks with a standard layout. Copy-
+    protected and other non-standard disks may have sectors with arbitrary
+    IDs and sizes, missing or duplicate IDs, CRC errors or deleted data
+    address marks. A FloppyImage stores each track as the list of sectors in
+    the order they pass under the head, so the FDC can search a track for
+    an ID just like a real controller does.
+
+    Raw (.img), ImageDisk (.imd) and Teledisk (.td0) images can be loaded
+    and written back in the format they were loaded from.
+*/
+
+pub mod imd;
+pub mod td0;
+
+use std::{fmt::Display, time::SystemTime};
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::{
+    bytebuf::ByteBuf,
+    device_types::{chs::DiskChs, fdc::DISK_FORMATS},
+    savestate::{impl_state_enum, StateReader, StateValue, StateWriter},
+    util::civil_time,
+};
+
+/// The largest sector size code we will store data for (16K).
+pub const MAX_SECTOR_SIZE_CODE: u8 = 7;
+pub const RAW_SECTOR_SIZE: usize = 512;
+
+/// The format a FloppyImage was loaded from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FloppyImageFormat {
+    Raw,
+    ImageDisk,
+    TeleDisk,
+}
+
+impl FloppyImageFormat {
+    /// The conventional file extension for images of this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            FloppyImageFormat::Raw => "img",
+            FloppyImageFormat::ImageDisk => "imd",
+            FloppyImageFormat::TeleDisk => "td0",
+        }
+    }
+}
+
+impl Display for FloppyImageFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FloppyImageFormat::Raw => write!(f, "Raw"),
+            FloppyImageFormat::ImageDisk => write!(f, "ImageDisk"),
+            FloppyImageFormat::TeleDisk => write!(f, "TeleDisk"),
+        }
+    }
+}
+
+/// The four bytes of a sector ID field: cylinder, head, record (sector number) and size code.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SectorId {
+    pub c: u8,
+    pub h: u8,
+    pub r: u8,
+    pub n: u8,
+}
+
+impl SectorId {
+    pub fn new(c: u8, h: u8, r: u8, n: u8) -> Self {
+        Self { c, h, r, n }
+    }
+
+    /// Return the size in bytes of a sector with this ID's size code.
+    pub fn size(&self) -> usize {
+        128 << self.n.min(MAX_SECTOR_SIZE_CODE)
+    }
+}
+
+impl Display for SectorId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[c:{} h:{} r:{} n:{}]", self.c, self.h, self.r, self.n)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FloppySector {
+    pub id: SectorId,
+    /// Sector data, or None if the sector has an ID field but no data address mark.
+    pub data: Option<Vec<u8>>,
+    pub deleted: bool,
+    pub id_crc_error: bool,
+    pub data_crc_error: bool,
+}
+
+impl FloppySector {
+    /// Create a normal sector with the specified ID and data.
+    pub fn new(id: SectorId, data: Vec<u8>) -> Self {
+        Self {
+            id,
+            data: Some(data),
+            deleted: false,
+            id_crc_error: false,
+            data_crc_error: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FloppyTrack {
+    pub cylinder: u8,
+    pub head: u8,
+    pub sectors: Vec<FloppySector>,
+}
+
+/// The result of searching a track for a sector ID.
+pub enum SectorMatch {
+    Found(usize),
+    /// A sector with the requested record number exists, but with a different cylinder.
+    WrongCylinder(u8),
+    NotFound,
+}
+
+impl FloppyTrack {
+    pub fn new(cylinder: u8, head: u8) -> Self {
+        Self {
+            cylinder,
+            head,
+            sectors: Vec::new(),
+        }
+    }
+
+    /// Search the track for a sector matching all four bytes of the ID, starting at the sector
+    /// index 'start' and wrapping around, as a controller would see them while the disk rotates.
+    /// If an ID is duplicated on the track, the first one found is returned.
+    pub fn find_sector(&self, id: &SectorId, start: usize) -> SectorMatch {
+        let len = self.sectors.len();
+        let mut wrong_cylinder = None;
+        for i in 0..len {
+            let idx = (start + i) % len;
+            let sector = &self.sectors[idx];
+            if sector.id_crc_error {
+                // A controller can't match an ID it can't read
+                continue;
+            }
+            if sector.id == *id {
+                return SectorMatch::Found(idx);
+            }
+            if sector.id.r == id.r && sector.id.c != id.c {
+                wrong_cylinder = Some(sector.id.c);
+            }
+        }
+        match wrong_cylinder {
+            Some(c) => SectorMatch::WrongCylinder(c),
+            None => SectorMatch::NotFound,
+        }
+    }
+
+    /// Return the highest record number on the track.
+    pub fn max_record(&self) -> u8 {
+        self.sectors.iter().map(|s| s.id.r).max().unwrap_or(0)
+    }
+}
+
+pub struct FloppyImage {
+    pub format:    FloppyImageFormat,
+    pub cylinders: u8,
+    pub heads:     u8,
+    pub comment:   String,
+    tracks:        Vec<FloppyTrack>,
+}
+
+impl FloppyImage {
+    /// Create an image from a list of tracks. Tracks are stored by physical cylinder and head,
+    /// and any tracks missing from the list are created as unformatted.
+    pub fn from_tracks(format: FloppyImageFormat, heads: u8, tracks: Vec<FloppyTrack>) -> Result<Self, Error> {
+        if heads == 0 || heads > 2 {
+            bail!("Invalid head count: {}", heads);
+        }
+        let cylinders = tracks.iter().map(|t| t.cylinder as usize + 1).max().unwrap_or(0);
+        if cylinders == 0 || cylinders > u8::MAX as usize {
+            bail!("Invalid cylinder count: {}", cylinders);
+        }
+
+        let mut image = Self {
+            format,
+            cylinders: cylinders as u8,
+            heads,
+            comment: String::new(),
+            tracks: (0..cylinders * heads as usize)
+                .map(|i| FloppyTrack::new((i / heads as usize) as u8, (i % heads as usize) as u8))
+                .collect(),
+        };
+
+        for track in tracks {
+            if track.head >= heads {
+                bail!("Track c:{} has invalid head: {}", track.cylinder, track.head);
+            }
+            let idx = image.track_index(track.cylinder, track.head);
+            image.tracks[idx] = track;
+        }
+        Ok(image)
+    }
+
+    /// Load an image, detecting its format from its contents.
+    pub fn load(src_vec: Vec<u8>) -> Result<Self, Error> {
+        if imd::is_imd(&src_vec) {
+            imd::load(&src_vec)
+        }
+        else if td0::is_td0(&src_vec) {
+            td0::load(&src_vec)
+        }
+        else {
+            Self::from_raw(&src_vec)
+        }
+    }
+
+    /// Create an image from a raw sector dump. The geometry is inferred from the image size.
+    pub fn from_raw(src: &[u8]) -> Result<Self, Error> {
+        let image_len: usize = src.len();
+
+        // Disk images must contain whole sectors
+        if image_len % RAW_SECTOR_SIZE > 0 {
+            bail!("Invalid image length");
+        }
+
+        // Look up disk parameters based on image size
+        let chs = if let Some(fmt) = DISK_FORMATS.get(&image_len) {
+            fmt.chs
+        }
+        else if image_len < 163_840 {
+            // If image is smaller than single sided disk, assume single sided disk, 8 sectors per track
+            // This is useful for loading things like boot sector images without having to copy them to
+            // a full disk image
+            DiskChs::new(40, 1, 8)
+        }
+        else {
+            bail!("Invalid image length");
+        };
+
+        let mut tracks = Vec::with_capacity(chs.c() as usize * chs.h() as usize);
+        let mut sectors = src.chunks(RAW_SECTOR_SIZE);
+        for c in 0..chs.c() {
+            for h in 0..chs.h() {
+                let mut track = FloppyTrack::new(c, h);
+                for r in 1..=chs.s() {
+                    let mut data = sectors.next().map(|d| d.to_vec()).unwrap_or_default();
+                    data.resize(RAW_SECTOR_SIZE, 0);
+                    track.sectors.push(FloppySector::new(SectorId::new(c, h, r, 2), data));
+                }
+                tracks.push(track);
+            }
+        }
+
+        Self::from_tracks(FloppyImageFormat::Raw, chs.h(), tracks)
+    }
+
+    /// Produce a raw sector dump of the image. Sectors are written in record number order for
+    /// each track, so this is only a faithful copy of disks with a standard layout. Duplicate
+    /// sectors are dropped and sectors without data are written as zeros.
+    pub fn to_raw(&self) -> Vec<u8> {
+        let mut raw = Vec::new();
+        for track in &self.tracks {
+            let mut sectors: Vec<&FloppySector> = Vec::with_capacity(track.sectors.len());
+            for sector in &track.sectors {
+                if !sectors.iter().any(|s| s.id.r == sector.id.r) {
+                    sectors.push(sector);
+                }
+            }
+            sectors.sort_by_key(|s| s.id.r);
+            for sector in sectors {
+                let start = raw.len();
+                if let Some(data) = &sector.data {
+                    raw.extend_from_slice(data);
+                }
+                raw.resize(start + sector.id.size(), 0);
+            }
+        }
+        raw
+    }
+
+    /// Return the geometry of the image. The sector count is the highest record number found on
+    /// any track, so it is only meaningful for disks with a standard layout.
+    pub fn geometry(&self) -> DiskChs {
+        let sectors = self.tracks.iter().map(|t| t.max_record()).max().unwrap_or(0);
+        DiskChs::new(self.cylinders, self.heads, sectors)
+    }
+
+    fn track_index(&self, cylinder: u8, head: u8) -> usize {
+        cylinder as usize * self.heads as usize + head as usize
+    }
+
+    /// Return the track at the specified physical cylinder and head, if it exists.
+    pub fn track(&self, cylinder: u8, head: u8) -> Option<&FloppyTrack> {
+        if cylinder >= self.cylinders || head >= self.heads {
+            return None;
+        }
+        self.tracks.get(self.track_index(cylinder, head))
+    }
+
+    pub fn track_mut(&mut self, cylinder: u8, head: u8) -> Option<&mut FloppyTrack> {
+        if cylinder >= self.cylinders || head >= self.heads {
+            return None;
+        }
+        let idx = self.track_index(cylinder, head);
+        self.tracks.get_mut(idx)
+    }
+
+    pub fn tracks(&self) -> &[FloppyTrack] {
+        &self.tracks
+    }
+
+    /// Erase the track at the specified physical cylinder and head, returning it so that new
+    /// sectors can be laid out. The image grows if the track lies beyond its current geometry.
+    pub fn format_track(&mut self, cylinder: u8, head: u8) -> &mut FloppyTrack {
+        if head >= self.heads {
+            // Re-index the existing tracks for the new head count
+            let tracks = std::mem::take(&mut self.tracks);
+            self.heads = head + 1;
+            self.tracks = (0..self.cylinders as usize * self.heads as usize)
+                .map(|i| FloppyTrack::new((i / self.heads as usize) as u8, (i % self.heads as usize) as u8))
+                .collect();
+            for track in tracks {
+                let idx = self.track_index(track.cylinder, track.head);
+                self.tracks[idx] = track;
+            }
+        }
+        while cylinder >= self.cylinders {
+            for h in 0..self.heads {
+                self.tracks.push(FloppyTrack::new(self.cylinders, h));
+            }
+            self.cylinders += 1;
+        }
+
+        let idx = self.track_index(cylinder, head);
+        let track = &mut self.tracks[idx];
+        track.sectors.clear();
+        track
+    }
+
+    /// Serialize the image in the specified format.
+    pub fn save(&self, format: FloppyImageFormat) -> Result<Vec<u8>, Error> {
+        match format {
+            FloppyImageFormat::Raw => {
+                if !self.is_standard_layout() {
+                    log::warn!("Floppy image has a non-standard layout; raw image will be incomplete.");
+                }
+                Ok(self.to_raw())
+            }
+            FloppyImageFormat::ImageDisk => imd::save(self),
+            FloppyImageFormat::TeleDisk => td0::save(self),
+        }
+    }
+
+    /// Returns whether the image can be represented as a raw sector dump without loss - every
+    /// track has the same number of 512 byte sectors, numbered from 1, with IDs matching their
+    /// physical location and no errors or deleted data.
+    pub fn is_standard_layout(&self) -> bool {
+        let spt = self.geometry().s();
+        self.tracks.iter().all(|track| {
+            track.sectors.len() == spt as usize
+                && track.sectors.iter().enumerate().all(|(i, sector)| {
+                    sector.id == SectorId::new(track.cylinder, track.head, i as u8 + 1, 2)
+                        && sector.data.as_ref().is_some_and(|d| d.len() == RAW_SECTOR_SIZE)
+                        && !sector.deleted
+                        && !sector.id_crc_error
+                        && !sector.data_crc_error
+                })
+        })
+    }
+}
+
+/// Return the current date and time as (year, month, day, hour, minute, second) in UTC, for
+/// image formats that record a creation date.
+pub(crate) fn timestamp() -> (u32, u32, u32, u32, u32, u32) {
+    civil_time(SystemTime::now())
+}
+
+/// Read 'len' bytes from a ByteBuf into a new vector, failing cleanly on truncated images.
+pub(crate) fn read_vec(buf: &mut ByteBuf, len: usize) -> Result<Vec<u8>, Error> {
+    if buf.len() - buf.tell() < len {
+        return Err(anyhow!("Unexpected end of image"));
+    }
+    let mut vec = vec![0; len];
+    buf.read_bytes(&mut vec, len)?;
+    Ok(vec)
+}
+
+impl_state_enum!(FloppyImageFormat {
+    Raw,
+    ImageDisk,
+    TeleDisk
+});
+
+impl StateValue for FloppySector {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.write_raw(&[self.id.c, self.id.h, self.id.r, self.id.n]);
+        w.put(&self.data);
+        w.put(&self.deleted);
+        w.put(&self.id_crc_error);
+        w.put(&self.data_crc_error);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        let id = r.read_raw(4)?;
+        Ok(Self {
+            id: SectorId::new(id[0], id[1], id[2], id[3]),
+            data: r.get()?,
+            deleted: r.get()?,
+            id_crc_error: r.get()?,
+            data_crc_error: r.get()?,
+        })
+    }
+}
+
+impl StateValue for FloppyTrack {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.put(&self.cylinder);
+        w.put(&self.head);
+        w.put(&self.sectors);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        Ok(Self {
+            cylinder: r.get()?,
+            head: r.get()?,
+            sectors: r.get()?,
+        })
+    }
+}
+
+impl StateValue for FloppyImage {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.put(&self.format);
+        w.put(&self.comment);
+        w.put(&self.heads);
+        w.put(&self.tracks);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        let format = r.get()?;
+        let comment = r.get()?;
+        let heads = r.get()?;
+        let mut image = Self::from_tracks(format, heads, r.get()?)?;
+        image.comment = comment;
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_roundtrip() {
+        let raw: Vec<u8> = (0..368_640).map(|i| (i / RAW_SECTOR_SIZE) as u8).collect();
+        let image = FloppyImage::from_raw(&raw).unwrap();
+
+        let geom = image.geometry();
+        assert_eq!(geom.get(), (40, 2, 9));
+
+        let track = image.track(1, 1).unwrap();
+        assert_eq!(track.sectors.len(), 9);
+        assert_eq!(track.sectors[0].id, SectorId::new(1, 1, 1, 2));
+        // Cylinder 1, head 1 begins at LBA 27
+        assert_eq!(track.sectors[0].data.as_ref().unwrap()[0], 27);
+
+        assert!(image.to_raw() == raw);
+        assert!(image.is_standard_layout());
+    }
+
+    #[test]
+    fn test_format_track_grows_image() {
+        let mut image = FloppyImage::from_raw(&vec![0; 163_840]).unwrap();
+
+        let track = image.format_track(40, 1);
+        track.sectors.push(FloppySector::new(SectorId::new(40, 1, 1, 3), vec![0xF6; 1024]));
+
+        assert_eq!((image.cylinders, image.heads), (41, 2));
+        assert_eq!(image.track(39, 0).unwrap().sectors.len(), 8);
+        assert!(image.track(39, 1).unwrap().sectors.is_empty());
+        assert_eq!(image.track(40, 1).unwrap().sectors[0].id.n, 3);
+        assert!(!image.is_standard_layout());
+    }
+
+    #[test]
+    fn test_find_sector() {
+        let mut track = FloppyTrack::new(0, 0);
+        for r in [1, 3, 3, 2] {
+            track.sectors.push(FloppySector::new(SectorId::new(0, 0, r, 2), vec![r; 512]));
+        }
+        track.sectors[1].id_crc_error = true;
+        track.sectors.push(FloppySector::new(SectorId::new(5, 0, 9, 2), vec![0; 512]));
+
+        assert!(matches!(track.find_sector(&SectorId::new(0, 0, 3, 2), 0), SectorMatch::Found(2)));
+        assert!(matches!(track.find_sector(&SectorId::new(0, 0, 1, 2), 3), SectorMatch::Found(0)));
+        assert!(matches!(track.find_sector(&SectorId::new(0, 0, 1, 3), 0), SectorMatch::NotFound));
+        assert!(matches!(
+            track.find_sector(&SectorId::new(0, 0, 9, 2), 0),
+            SectorMatch::WrongCylinder(5)
+        ));
+    }
+}
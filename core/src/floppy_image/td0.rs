@@ -0,0 +1,638 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    floppy_image::td0.rs
+
+    Implements reading and writing Teledisk (.TD0) floppy images.
+
+    A Teledisk image begins with a 12 byte header. Images with the "td"
+    signature use "advanced compression" - everything following the header
+    is compressed with the adaptive LZSS-Huffman scheme from Haruyasu
+    Yoshizaki's LZHUF, which we decompress in full before parsing.
+
+    The header is optionally followed by a comment block, then a list of
+    tracks, each containing a list of sector headers. Sector data may be
+    stored raw, as a repeated 2-byte pattern, or as a series of literal and
+    repeated runs.
+
+    Images are written without compression, with sector data stored raw.
+*/
+
+use anyhow::{bail, Error};
+
+use crate::{
+    bytebuf::ByteBuf,
+    floppy_image::{
+        read_vec,
+        timestamp,
+        FloppyImage,
+        FloppyImageFormat,
+        FloppySector,
+        FloppyTrack,
+        SectorId,
+        MAX_SECTOR_SIZE_CODE,
+    },
+};
+
+pub const TD0_SIGNATURE: &[u8; 2] = b"TD";
+pub const TD0_SIGNATURE_COMPRESSED: &[u8; 2] = b"td";
+pub const TD0_HEADER_LEN: usize = 12;
+pub const TD0_COMMENT_HEADER_LEN: usize = 10;
+pub const TD0_STEPPING_COMMENT: u8 = 0x80;
+pub const TD0_END_OF_IMAGE: u8 = 0xFF;
+
+pub const TD0_SECTOR_DUPLICATE: u8 = 0x01;
+pub const TD0_SECTOR_CRC_ERROR: u8 = 0x02;
+pub const TD0_SECTOR_DELETED: u8 = 0x04;
+pub const TD0_SECTOR_SKIPPED: u8 = 0x10;
+pub const TD0_SECTOR_NO_DATA: u8 = 0x20;
+pub const TD0_SECTOR_NO_ID: u8 = 0x40;
+
+pub const TD0_VERSION: u8 = 21;
+pub const TD0_RATE_250K: u8 = 0;
+pub const TD0_RATE_500K: u8 = 2;
+pub const TD0_DRIVE_360K: u8 = 1;
+pub const TD0_DRIVE_12M: u8 = 2;
+pub const TD0_DRIVE_720K: u8 = 3;
+pub const TD0_DRIVE_144M: u8 = 4;
+pub const TD0_CRC_POLY: u16 = 0xA097;
+
+pub fn is_td0(data: &[u8]) -> bool {
+    data.len() >= TD0_HEADER_LEN && (data.starts_with(TD0_SIGNATURE) || data.starts_with(TD0_SIGNATURE_COMPRESSED))
+}
+
+pub fn load(data: &[u8]) -> Result<FloppyImage, Error> {
+    if !is_td0(data) {
+        bail!("Not a Teledisk image");
+    }
+
+    let header = &data[..TD0_HEADER_LEN];
+    let version = header[4];
+    let heads = header[9].clamp(1, 2);
+    let stepping = header[7];
+
+    let body = if data.starts_with(TD0_SIGNATURE_COMPRESSED) {
+        if version < 20 {
+            // Teledisk 1.x used an LZW scheme for advanced compression
+            bail!("Unsupported Teledisk compression version: {}", version);
+        }
+        lzhuf_decompress(&data[TD0_HEADER_LEN..])
+    }
+    else {
+        data[TD0_HEADER_LEN..].to_vec()
+    };
+    log::debug!("Loading Teledisk image: version {} body size: {}", version, body.len());
+
+    let mut buf = ByteBuf::from_vec(body);
+    let mut comment = String::new();
+
+    if stepping & TD0_STEPPING_COMMENT != 0 {
+        let comment_header = read_vec(&mut buf, TD0_COMMENT_HEADER_LEN)?;
+        let comment_len = u16::from_le_bytes([comment_header[2], comment_header[3]]) as usize;
+        let text = read_vec(&mut buf, comment_len)?;
+        // Comment lines are separated by nulls
+        comment = text
+            .split(|&b| b == 0)
+            .filter(|line| !line.is_empty())
+            .map(|line| String::from_utf8_lossy(line).into_owned())
+            .collect::<Vec<_>>()
+            .join("\n");
+    }
+
+    let mut tracks: Vec<FloppyTrack> = Vec::new();
+    loop {
+        let sector_ct = buf.read_u8()?;
+        if sector_ct == TD0_END_OF_IMAGE {
+            break;
+        }
+        let cylinder = buf.read_u8()?;
+        // Bit 7 of the head byte flags a single density track
+        let head = buf.read_u8()? & 0x01;
+        let _crc = buf.read_u8()?;
+
+        let mut track = FloppyTrack::new(cylinder, head);
+        for _ in 0..sector_ct {
+            let id = read_vec(&mut buf, 4)?;
+            let flags = buf.read_u8()?;
+            let _crc = buf.read_u8()?;
+            let id = SectorId::new(id[0], id[1], id[2], id[3]);
+
+            let mut data = None;
+            if flags & (TD0_SECTOR_SKIPPED | TD0_SECTOR_NO_DATA) == 0 && id.n <= MAX_SECTOR_SIZE_CODE {
+                let block_len = buf.read_u16_le()? as usize;
+                let block = read_vec(&mut buf, block_len)?;
+                data = Some(decode_sector(&block, id.size())?);
+            }
+            else if flags & TD0_SECTOR_SKIPPED != 0 {
+                // Teledisk didn't store sectors DOS reported as unallocated
+                data = Some(vec![0; id.size()]);
+            }
+
+            if flags & TD0_SECTOR_NO_ID != 0 {
+                // Data with no ID field can never be found by a controller
+                continue;
+            }
+            track.sectors.push(FloppySector {
+                id,
+                data,
+                deleted: flags & TD0_SECTOR_DELETED != 0,
+                id_crc_error: false,
+                data_crc_error: flags & TD0_SECTOR_CRC_ERROR != 0,
+            });
+        }
+
+        // Teledisk may record a track more than once; keep the last copy
+        tracks.retain(|t| t.cylinder != cylinder || t.head != head);
+        tracks.push(track);
+    }
+
+    let heads = heads.max(tracks.iter().map(|t| t.head + 1).max().unwrap_or(1));
+    let mut image = FloppyImage::from_tracks(FloppyImageFormat::TeleDisk, heads, tracks)?;
+    image.comment = comment;
+    Ok(image)
+}
+
+pub fn save(image: &FloppyImage) -> Result<Vec<u8>, Error> {
+    // Guess the original drive type from the geometry
+    let geom = image.geometry();
+    let high_density = geom.s() > 10;
+    let drive_type = match (geom.c() > 42, high_density) {
+        (false, _) => TD0_DRIVE_360K,
+        (true, false) => TD0_DRIVE_720K,
+        (true, true) if geom.s() > 15 => TD0_DRIVE_144M,
+        (true, true) => TD0_DRIVE_12M,
+    };
+    let data_rate = if high_density { TD0_RATE_500K } else { TD0_RATE_250K };
+    let stepping = if image.comment.is_empty() { 0 } else { TD0_STEPPING_COMMENT };
+
+    let mut td0 = TD0_SIGNATURE.to_vec();
+    td0.extend_from_slice(&[0, 0, TD0_VERSION, data_rate, drive_type, stepping, 0, image.heads]);
+    let crc = crc16(&td0);
+    td0.extend_from_slice(&crc.to_le_bytes());
+
+    if !image.comment.is_empty() {
+        let (year, month, day, hour, minute, second) = timestamp();
+        let mut text: Vec<u8> = image.comment.replace('\n', "\0").into_bytes();
+        text.push(0);
+
+        let mut block = (text.len() as u16).to_le_bytes().to_vec();
+        block.extend_from_slice(&[
+            year.saturating_sub(1900) as u8,
+            month as u8 - 1,
+            day as u8,
+            hour as u8,
+            minute as u8,
+            second as u8,
+        ]);
+        block.extend_from_slice(&text);
+        td0.extend_from_slice(&crc16(&block).to_le_bytes());
+        td0.extend_from_slice(&block);
+    }
+
+    for track in image.tracks() {
+        if track.sectors.len() >= TD0_END_OF_IMAGE as usize {
+            bail!("Too many sectors on track {}:{}", track.cylinder, track.head);
+        }
+        let header = [track.sectors.len() as u8, track.cylinder, track.head];
+        td0.extend_from_slice(&header);
+        td0.push(crc16(&header) as u8);
+
+        for sector in &track.sectors {
+            let id = sector.id;
+            let mut flags = 0;
+            if sector.deleted {
+                flags |= TD0_SECTOR_DELETED;
+            }
+            if sector.data_crc_error {
+                flags |= TD0_SECTOR_CRC_ERROR;
+            }
+
+            let data = match &sector.data {
+                Some(data) if id.n <= MAX_SECTOR_SIZE_CODE => {
+                    let mut data = data.clone();
+                    data.resize(id.size(), 0);
+                    Some(data)
+                }
+                _ => {
+                    flags |= TD0_SECTOR_NO_DATA;
+                    None
+                }
+            };
+
+            td0.extend_from_slice(&[id.c, id.h, id.r, id.n, flags]);
+            td0.push(data.as_ref().map(|d| crc16(d) as u8).unwrap_or(0));
+            if let Some(data) = data {
+                td0.extend_from_slice(&(data.len() as u16 + 1).to_le_bytes());
+                td0.push(0);
+                td0.extend_from_slice(&data);
+            }
+        }
+    }
+    td0.push(TD0_END_OF_IMAGE);
+    Ok(td0)
+}
+
+/// Calculate the CRC Teledisk uses for headers and sector data.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ TD0_CRC_POLY } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Decode a sector data block. The first byte of the block specifies the encoding method.
+fn decode_sector(block: &[u8], size: usize) -> Result<Vec<u8>, Error> {
+    let (method, block) = match block.split_first() {
+        Some((method, block)) => (*method, block),
+        None => bail!("Empty Teledisk sector data block"),
+    };
+
+    let mut data = Vec::with_capacity(size);
+    match method {
+        0 => {
+            data.extend_from_slice(block);
+        }
+        1 => {
+            // A repeat count followed by a 2-byte pattern
+            if block.len() < 4 {
+                bail!("Truncated Teledisk sector pattern");
+            }
+            let count = u16::from_le_bytes([block[0], block[1]]) as usize;
+            for _ in 0..count {
+                data.extend_from_slice(&block[2..4]);
+            }
+        }
+        2 => {
+            // A series of runs. A type of 0 is a literal run of 'len' bytes, otherwise the run
+            // is a pattern of 2^type bytes repeated 'len' times.
+            let mut i = 0;
+            while data.len() < size && i + 1 < block.len() {
+                let run_type = block[i] as usize;
+                let len = block[i + 1] as usize;
+                i += 2;
+                let pattern_len = match run_type {
+                    0 => len,
+                    1..=4 => 1 << run_type,
+                    _ => bail!("Invalid Teledisk sector run type: {}", run_type),
+                };
+                if i + pattern_len > block.len() {
+                    bail!("Truncated Teledisk sector run");
+                }
+                let pattern = &block[i..i + pattern_len];
+                i += pattern_len;
+                if run_type == 0 {
+                    data.extend_from_slice(pattern);
+                }
+                else {
+                    for _ in 0..len {
+                        data.extend_from_slice(pattern);
+                    }
+                }
+            }
+        }
+        _ => bail!("Invalid Teledisk sector encoding: {}", method),
+    }
+
+    data.resize(size, 0);
+    Ok(data)
+}
+
+const LZ_BUF_SIZE: usize = 4096;
+const LZ_LOOKAHEAD: usize = 60;
+const LZ_THRESHOLD: usize = 2;
+const LZ_CHAR_CT: usize = 256 - LZ_THRESHOLD + LZ_LOOKAHEAD;
+const LZ_TABLE_SIZE: usize = LZ_CHAR_CT * 2 - 1;
+const LZ_ROOT: usize = LZ_TABLE_SIZE - 1;
+const LZ_MAX_FREQ: u32 = 0x8000;
+/// Limit decompressed output in case of a corrupt stream; no floppy image should come close.
+const LZ_MAX_OUTPUT: usize = 8 * 1024 * 1024;
+
+/// Bit lengths of the 64 prefix codes that encode the upper 6 bits of a match position.
+fn lz_position_code_len(code: usize) -> u32 {
+    match code {
+        0 => 3,
+        1..=3 => 4,
+        4..=11 => 5,
+        12..=23 => 6,
+        24..=47 => 7,
+        _ => 8,
+    }
+}
+
+/// An adaptive Huffman decoder for LZHUF compressed data.
+struct LzHuf<'a> {
+    src:     &'a [u8],
+    pos:     usize,
+    bit_buf: u32,
+    bit_ct:  u32,
+    freq:    [u32; LZ_TABLE_SIZE + 1],
+    parent:  [usize; LZ_TABLE_SIZE + LZ_CHAR_CT],
+    son:     [usize; LZ_TABLE_SIZE],
+}
+
+impl<'a> LzHuf<'a> {
+    fn new(src: &'a [u8]) -> Self {
+        let mut lz = Self {
+            src,
+            pos: 0,
+            bit_buf: 0,
+            bit_ct: 0,
+            freq: [0; LZ_TABLE_SIZE + 1],
+            parent: [0; LZ_TABLE_SIZE + LZ_CHAR_CT],
+            son: [0; LZ_TABLE_SIZE],
+        };
+
+        for i in 0..LZ_CHAR_CT {
+            lz.freq[i] = 1;
+            lz.son[i] = i + LZ_TABLE_SIZE;
+            lz.parent[i + LZ_TABLE_SIZE] = i;
+        }
+        let mut i = 0;
+        for j in LZ_CHAR_CT..=LZ_ROOT {
+            lz.freq[j] = lz.freq[i] + lz.freq[i + 1];
+            lz.son[j] = i;
+            lz.parent[i] = j;
+            lz.parent[i + 1] = j;
+            i += 2;
+        }
+        lz.freq[LZ_TABLE_SIZE] = 0xFFFF;
+        lz.parent[LZ_ROOT] = 0;
+        lz
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.src.len() && self.bit_ct == 0
+    }
+
+    fn get_bit(&mut self) -> usize {
+        if self.bit_ct == 0 {
+            self.bit_buf = self.src.get(self.pos).copied().unwrap_or(0) as u32;
+            self.pos += 1;
+            self.bit_ct = 8;
+        }
+        self.bit_ct -= 1;
+        ((self.bit_buf >> self.bit_ct) & 1) as usize
+    }
+
+    fn get_bits(&mut self, ct: u32) -> usize {
+        let mut bits = 0;
+        for _ in 0..ct {
+            bits = (bits << 1) | self.get_bit();
+        }
+        bits
+    }
+
+    /// Rebuild the tree when the root frequency saturates.
+    fn reconstruct(&mut self) {
+        // Collect the leaves, halving their frequencies
+        let mut j = 0;
+        for i in 0..LZ_TABLE_SIZE {
+            if self.son[i] >= LZ_TABLE_SIZE {
+                self.freq[j] = self.freq[i].div_ceil(2);
+                self.son[j] = self.son[i];
+                j += 1;
+            }
+        }
+
+        // Rebuild the internal nodes, keeping frequencies sorted
+        let mut i = 0;
+        for j in LZ_CHAR_CT..LZ_TABLE_SIZE {
+            let f = self.freq[i] + self.freq[i + 1];
+            let mut k = j;
+            while k > 0 && f < self.freq[k - 1] {
+                k -= 1;
+            }
+            self.freq.copy_within(k..j, k + 1);
+            self.freq[k] = f;
+            self.son.copy_within(k..j, k + 1);
+            self.son[k] = i;
+            i += 2;
+        }
+
+        for i in 0..LZ_TABLE_SIZE {
+            let k = self.son[i];
+            self.parent[k] = i;
+            if k < LZ_TABLE_SIZE {
+                self.parent[k + 1] = i;
+            }
+        }
+    }
+
+    /// Increment the frequency of a character and restore the ordering of the tree.
+    fn update(&mut self, c: usize) {
+        if self.freq[LZ_ROOT] == LZ_MAX_FREQ {
+            self.reconstruct();
+        }
+
+        let mut c = self.parent[c + LZ_TABLE_SIZE];
+        loop {
+            self.freq[c] += 1;
+            let k = self.freq[c];
+
+            // If the order is disturbed, swap nodes
+            let mut l = c + 1;
+            if k > self.freq[l] {
+                while k > self.freq[l + 1] {
+                    l += 1;
+                }
+                self.freq[c] = self.freq[l];
+                self.freq[l] = k;
+
+                let i = self.son[c];
+                self.parent[i] = l;
+                if i < LZ_TABLE_SIZE {
+                    self.parent[i + 1] = l;
+                }
+
+                let j = self.son[l];
+                self.son[l] = i;
+
+                self.parent[j] = c;
+                if j < LZ_TABLE_SIZE {
+                    self.parent[j + 1] = c;
+                }
+                self.son[c] = j;
+
+                c = l;
+            }
+
+            c = self.parent[c];
+            if c == 0 {
+                break;
+            }
+        }
+    }
+
+    fn decode_char(&mut self) -> usize {
+        let mut c = self.son[LZ_ROOT];
+        while c < LZ_TABLE_SIZE {
+            c = self.son[c + self.get_bit()];
+        }
+        c -= LZ_TABLE_SIZE;
+        self.update(c);
+        c
+    }
+
+    fn decode_position(&mut self) -> usize {
+        // The first 8 bits hold a variable length code for the upper 6 bits of the position,
+        // followed by as many of the lower 6 bits as fit.
+        let first = self.get_bits(8);
+        let (mut code, mut start) = (0, 0);
+        while code < 63 && first >= start + (256 >> lz_position_code_len(code)) {
+            start += 256 >> lz_position_code_len(code);
+            code += 1;
+        }
+        let len = lz_position_code_len(code);
+        let low = self.get_bits(len - 2);
+        (code << 6) | (((first << (len - 2)) | low) & 0x3F)
+    }
+}
+
+/// Decompress a Teledisk "advanced compression" stream.
+pub fn lzhuf_decompress(src: &[u8]) -> Vec<u8> {
+    let mut lz = LzHuf::new(src);
+    let mut text_buf = [0x20u8; LZ_BUF_SIZE];
+    let mut r = LZ_BUF_SIZE - LZ_LOOKAHEAD;
+    let mut out = Vec::new();
+
+    while !lz.at_end() && out.len() < LZ_MAX_OUTPUT {
+        let c = lz.decode_char();
+        if c < 256 {
+            out.push(c as u8);
+            text_buf[r] = c as u8;
+            r = (r + 1) & (LZ_BUF_SIZE - 1);
+        }
+        else {
+            let start = (r.wrapping_sub(lz.decode_position()).wrapping_sub(1)) & (LZ_BUF_SIZE - 1);
+            let len = c - 255 + LZ_THRESHOLD;
+            for k in 0..len {
+                let b = text_buf[(start + k) & (LZ_BUF_SIZE - 1)];
+                out.push(b);
+                text_buf[r] = b;
+                r = (r + 1) & (LZ_BUF_SIZE - 1);
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn td0_header(signature: &[u8; 2], stepping: u8, heads: u8) -> Vec<u8> {
+        let mut header = signature.to_vec();
+        header.extend_from_slice(&[0, 0, 21, 2, 1, stepping, 0, heads, 0, 0]);
+        header
+    }
+
+    #[test]
+    fn test_td0_load() {
+        let mut td0 = td0_header(TD0_SIGNATURE, TD0_STEPPING_COMMENT, 1);
+
+        let comment = b"line one\0line two\0";
+        td0.extend_from_slice(&[0, 0, comment.len() as u8, 0, 0, 0, 0, 0, 0, 0]);
+        td0.extend_from_slice(comment);
+
+        // One track with a raw sector, a pattern sector, a run-encoded deleted sector, and an ID with no data
+        td0.extend_from_slice(&[4, 0, 0, 0]);
+        td0.extend_from_slice(&[0, 0, 1, 1, 0, 0]);
+        td0.extend_from_slice(&[1, 1, 0]);
+        td0.extend_from_slice(&[0x11; 256]);
+        td0.extend_from_slice(&[0, 0, 2, 2, TD0_SECTOR_CRC_ERROR, 0]);
+        td0.extend_from_slice(&[5, 0, 1, 0x00, 0x01, 0xF6, 0xE5]);
+        td0.extend_from_slice(&[0, 0, 3, 1, TD0_SECTOR_DELETED, 0]);
+        td0.extend_from_slice(&[11, 0, 2, 0, 4, 1, 2, 3, 4, 1, 126, 0xAB, 0xCD]);
+        td0.extend_from_slice(&[0, 0, 4, 2, TD0_SECTOR_NO_DATA, 0]);
+        td0.push(TD0_END_OF_IMAGE);
+
+        let image = load(&td0).unwrap();
+        assert_eq!(image.comment, "line one\nline two");
+
+        let track = image.track(0, 0).unwrap();
+        assert_eq!(track.sectors.len(), 4);
+        assert_eq!(track.sectors[0].data.as_deref(), Some(&[0x11; 256][..]));
+
+        let data = track.sectors[1].data.as_ref().unwrap();
+        assert!(track.sectors[1].data_crc_error);
+        assert_eq!((data.len(), data[0], data[1], data[511]), (512, 0xF6, 0xE5, 0xE5));
+
+        let data = track.sectors[2].data.as_ref().unwrap();
+        assert!(track.sectors[2].deleted);
+        assert_eq!(&data[0..6], &[1, 2, 3, 4, 0xAB, 0xCD]);
+        assert_eq!(&data[254..256], &[0xAB, 0xCD]);
+
+        assert!(track.sectors[3].data.is_none());
+
+        // Saving and reloading the image should preserve every track
+        let saved = load(&save(&image).unwrap()).unwrap();
+        assert_eq!(saved.comment, image.comment);
+        assert!(saved.tracks() == image.tracks());
+    }
+
+    #[test]
+    fn test_decode_sector_runs() {
+        let data = decode_sector(&[2, 2, 2, 0x12, 0x34, 0x56, 0x78], 8).unwrap();
+        assert_eq!(data, [0x12, 0x34, 0x56, 0x78, 0x12, 0x34, 0x56, 0x78]);
+
+        // Run types above 4 are invalid and must not overflow the pattern length
+        assert!(decode_sector(&[2, 5, 1, 0], 512).is_err());
+        assert!(decode_sector(&[2, 0xFF, 1, 0], 512).is_err());
+    }
+
+    #[test]
+    fn test_lzhuf_literals() {
+        // Encode "AB" by walking the adaptive tree from each leaf to the root, then decode it back.
+        let mut bits = Vec::new();
+        let mut lz = LzHuf::new(&[]);
+        for c in [b'A' as usize, b'B' as usize] {
+            let mut path = Vec::new();
+            let mut child = lz.parent[c + LZ_TABLE_SIZE];
+            loop {
+                let node = lz.parent[child];
+                path.push((child - lz.son[node]) as u8);
+                if node == LZ_ROOT {
+                    break;
+                }
+                child = node;
+            }
+            path.reverse();
+            bits.extend(path);
+            lz.update(c);
+        }
+
+        let mut src = vec![0u8; bits.len().div_ceil(8)];
+        for (i, bit) in bits.iter().enumerate() {
+            src[i / 8] |= bit << (7 - (i % 8));
+        }
+        let out = lzhuf_decompress(&src);
+        assert_eq!(&out[0..2], b"AB");
+    }
+}
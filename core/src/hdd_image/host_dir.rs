@@ -0,0 +1,197 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    hdd_image::host_dir.rs
+
+    A hard disk backend presenting a host directory as a partitioned disk
+    with a single FAT12 or FAT16 primary partition. The disk is held in
+    memory; writes are applied back to the host directory on flush().
+
+*/
+
+use std::{collections::HashSet, path::Path};
+
+use anyhow::{bail, Error};
+
+use crate::{
+    device_types::hdc::HDC_SECTOR_SIZE,
+    hdd_image::{chs_offset, DiskBackend},
+    host_dir::{
+        fat::{FatParams, FatType},
+        HostDirVolume,
+    },
+};
+
+const PARTITION_TABLE_OFFSET: usize = 0x1BE;
+const PARTITION_TYPE_FAT12: u8 = 0x01;
+const PARTITION_TYPE_FAT16: u8 = 0x04;
+const PARTITION_TYPE_FAT16_LARGE: u8 = 0x06;
+
+pub struct HostDirDisk {
+    geometry: (u16, u8, u8),
+    data: Vec<u8>,
+    volume: HostDirVolume,
+    /// The first sector of the partition, in LBA.
+    partition_start: u32,
+    /// Sectors of the partition written since the last flush, relative to the partition start.
+    dirty_sectors: HashSet<u32>,
+}
+
+impl HostDirDisk {
+    /// Build a disk of the specified geometry from a host directory. The partition starts on the
+    /// second track and leaves the last cylinder unused, as DOS FDISK does.
+    pub fn new(path: &Path, c: u16, h: u8, s: u8, read_only: bool) -> Result<HostDirDisk, Error> {
+        if c < 2 || h == 0 || s == 0 {
+            bail!("Invalid geometry for host directory disk: c:{} h:{} s:{}", c, h, s);
+        }
+
+        let partition_start = s as u32;
+        let partition_sectors = (c as u32 - 1) * h as u32 * s as u32 - partition_start;
+        let params = FatParams::hard_disk(partition_sectors, s as u16, h as u16, partition_start);
+        let (volume, volume_data) = HostDirVolume::build(path, &params, read_only)?;
+
+        let mut data = vec![0u8; c as usize * h as usize * s as usize * HDC_SECTOR_SIZE];
+        let volume_offset = partition_start as usize * HDC_SECTOR_SIZE;
+        data[volume_offset..volume_offset + volume_data.len()].copy_from_slice(&volume_data);
+
+        // Master boot record. The code invokes ROM BASIC (int 18h); the partition isn't bootable.
+        data[0] = 0xCD;
+        data[1] = 0x18;
+        let partition_type = match params.fat_type {
+            FatType::Fat12 => PARTITION_TYPE_FAT12,
+            FatType::Fat16 if partition_sectors < 0x10000 => PARTITION_TYPE_FAT16,
+            FatType::Fat16 => PARTITION_TYPE_FAT16_LARGE,
+        };
+        let entry = &mut data[PARTITION_TABLE_OFFSET..PARTITION_TABLE_OFFSET + 16];
+        entry[0] = 0x00;
+        entry[1..4].copy_from_slice(&encode_chs(0, 1, 1));
+        entry[4] = partition_type;
+        entry[5..8].copy_from_slice(&encode_chs(c - 2, h - 1, s));
+        entry[8..12].copy_from_slice(&partition_start.to_le_bytes());
+        entry[12..16].copy_from_slice(&partition_sectors.to_le_bytes());
+        data[0x1FE] = 0x55;
+        data[0x1FF] = 0xAA;
+
+        log::info!(
+            "Host directory disk geometry: c:{} h:{} s:{}, {:?} partition of {} sectors",
+            c,
+            h,
+            s,
+            params.fat_type,
+            partition_sectors
+        );
+
+        Ok(HostDirDisk {
+            geometry: (c, h, s),
+            data,
+            volume,
+            partition_start,
+            dirty_sectors: HashSet::new(),
+        })
+    }
+
+    fn sector_offset(&self, cylinder: u16, head: u8, sector: u8) -> Result<usize, Error> {
+        let offset = chs_offset(self.geometry, cylinder, head, sector) as usize;
+        if offset + HDC_SECTOR_SIZE > self.data.len() {
+            bail!("Sector c:{} h:{} s:{} is out of bounds", cylinder, head, sector);
+        }
+        Ok(offset)
+    }
+}
+
+/// Encode a cylinder, head and sector in the packed form used by partition table entries.
+fn encode_chs(cylinder: u16, head: u8, sector: u8) -> [u8; 3] {
+    let cylinder = cylinder.min(1023);
+    [head, (sector & 0x3F) | ((cylinder >> 2) as u8 & 0xC0), cylinder as u8]
+}
+
+impl DiskBackend for HostDirDisk {
+    fn geometry(&self) -> (u16, u8, u8) {
+        self.geometry
+    }
+
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    fn read_sector(&mut self, buf: &mut [u8], cylinder: u16, head: u8, sector: u8) -> Result<(), Error> {
+        let offset = self.sector_offset(cylinder, head, sector)?;
+        buf.copy_from_slice(&self.data[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn write_sector(&mut self, buf: &[u8], cylinder: u16, head: u8, sector: u8) -> Result<(), Error> {
+        let offset = self.sector_offset(cylinder, head, sector)?;
+        self.data[offset..offset + buf.len()].copy_from_slice(buf);
+
+        let lba = (offset / HDC_SECTOR_SIZE) as u32;
+        if lba >= self.partition_start {
+            self.dirty_sectors.insert(lba - self.partition_start);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if self.dirty_sectors.is_empty() {
+            return Ok(());
+        }
+        let start = self.partition_start as usize * HDC_SECTOR_SIZE;
+        let result = self.volume.sync(&self.data[start..], &self.dirty_sectors);
+        self.dirty_sectors.clear();
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_dir_disk() {
+        let root = std::env::temp_dir().join(format!("martypc_host_dir_disk_{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("test.txt"), b"test").unwrap();
+
+        let mut disk = HostDirDisk::new(&root, 306, 4, 17, false).unwrap();
+        let mut sector = vec![0u8; HDC_SECTOR_SIZE];
+        disk.read_sector(&mut sector, 0, 0, 0).unwrap();
+        assert_eq!(&sector[0x1FE..], &[0x55, 0xAA]);
+        assert_eq!(sector[PARTITION_TABLE_OFFSET + 4], PARTITION_TYPE_FAT12);
+
+        // The volume boot sector is at the start of the second track.
+        disk.read_sector(&mut sector, 0, 1, 0).unwrap();
+        let params = FatParams::parse(&sector).unwrap();
+        assert_eq!(params.hidden_sectors, 17);
+        assert_eq!(params.total_sectors, 305 * 4 * 17 - 17);
+
+        disk.write_sector(&sector, 0, 1, 0).unwrap();
+        disk.flush().unwrap();
+        assert_eq!(std::fs::read(root.join("test.txt")).unwrap(), b"test");
+
+        _ = std::fs::remove_dir_all(&root);
+    }
+}
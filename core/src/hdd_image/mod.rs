@@ -0,0 +1,139 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    hdd_image::mod.rs
+
+    Defines the DiskBackend trait used by hard disk controllers to access
+    a mounted hard disk image, and implementations for raw sector images
+    and in-memory overlays. VHD images implement DiskBackend in vhd.rs.
+
+    A raw image (.img, .hdm) is a flat dump of sectors in CHS order with no
+    header, so its geometry must be supplied by configuration or inferred
+    from its size.
+
+    A host directory can also be mounted as a hard disk; see host_dir.rs.
+*/
+
+pub mod host_dir;
+pub mod overlay;
+pub mod raw;
+
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+use anyhow::{bail, Error};
+
+use crate::{
+    device_types::hdc::HardDiskFormat,
+    hdd_image::raw::RawDiskImage,
+    vhd::{VirtualHardDisk, VHD_FOOTER_LEN},
+};
+
+/// A hard disk image that can be mounted into a hard disk controller.
+pub trait DiskBackend {
+    /// Return the geometry of the disk as (cylinders, heads, sectors per track).
+    fn geometry(&self) -> (u16, u8, u8);
+    /// Return the size of the disk in bytes.
+    fn size(&self) -> u64;
+    fn read_sector(&mut self, buf: &mut [u8], cylinder: u16, head: u8, sector: u8) -> Result<(), Error>;
+    fn write_sector(&mut self, buf: &[u8], cylinder: u16, head: u8, sector: u8) -> Result<(), Error>;
+    /// Commit any buffered writes. Called by the controller at the end of each write command.
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HddImageFormat {
+    Vhd,
+    Raw,
+}
+
+impl Display for HddImageFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HddImageFormat::Vhd => write!(f, "VHD"),
+            HddImageFormat::Raw => write!(f, "Raw"),
+        }
+    }
+}
+
+impl HddImageFormat {
+    /// Detect the format of an image. VHDs are identified by the cookie of the footer at the end
+    /// of the file, or of the footer copy at the start of a dynamic VHD. Anything else is raw.
+    pub fn detect(file: &mut File) -> Result<HddImageFormat, Error> {
+        let len = file.metadata()?.len();
+        if len < VHD_FOOTER_LEN as u64 {
+            return Ok(HddImageFormat::Raw);
+        }
+
+        let mut cookie = [0u8; 8];
+        for offset in [SeekFrom::End(-(VHD_FOOTER_LEN as i64)), SeekFrom::Start(0)] {
+            file.seek(offset)?;
+            file.read_exact(&mut cookie)?;
+            if &cookie == b"conectix" {
+                return Ok(HddImageFormat::Vhd);
+            }
+        }
+        Ok(HddImageFormat::Raw)
+    }
+}
+
+/// Open a hard disk image of any supported format. `path` is the path the image was opened from,
+/// used to locate the parent of a differencing VHD. The geometry of a raw image is taken from
+/// `geometry` if specified, otherwise it is inferred from the image size and `formats`.
+pub fn open_hdd_image(
+    mut file: File,
+    path: &Path,
+    geometry: Option<(u16, u8, u8)>,
+    formats: &[HardDiskFormat],
+) -> Result<Box<dyn DiskBackend>, Error> {
+    match HddImageFormat::detect(&mut file)? {
+        HddImageFormat::Vhd => Ok(Box::new(VirtualHardDisk::from_file_with_path(file, path)?)),
+        HddImageFormat::Raw => {
+            let (c, h, s) = match geometry {
+                Some(geometry) => geometry,
+                None => match RawDiskImage::guess_geometry(file.metadata()?.len(), formats) {
+                    Some(geometry) => geometry,
+                    None => bail!("Couldn't determine geometry of raw hard disk image. Specify it in configuration."),
+                },
+            };
+            Ok(Box::new(RawDiskImage::from_file(file, c, h, s)?))
+        }
+    }
+}
+
+/// Return the byte offset of a sector for a disk with the specified geometry.
+/// Hard drive sectors are allowed to start at 0.
+pub(crate) fn chs_offset(geometry: (u16, u8, u8), cylinder: u16, head: u8, sector: u8) -> u64 {
+    let (_, heads, sectors) = geometry;
+    let lba = (cylinder as u64 * heads as u64 + head as u64) * sectors as u64 + sector as u64;
+    lba * crate::device_types::hdc::HDC_SECTOR_SIZE as u64
+}
@@ -0,0 +1,139 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    hdd_image::overlay.rs
+
+    Implements a copy-on-write overlay for a hard disk image.
+
+    Sectors written to an OverlayDisk are kept in memory and the underlying
+    image is never modified, so the image can be opened read-only. All
+    changes are discarded when the overlay is unmounted.
+
+*/
+
+use std::collections::HashMap;
+
+use anyhow::Error;
+
+use crate::{
+    device_types::hdc::HDC_SECTOR_SIZE,
+    hdd_image::{chs_offset, DiskBackend},
+};
+
+pub struct OverlayDisk {
+    base: Box<dyn DiskBackend>,
+    sectors: HashMap<u64, Vec<u8>>,
+}
+
+impl OverlayDisk {
+    pub fn new(base: Box<dyn DiskBackend>) -> Self {
+        Self {
+            base,
+            sectors: HashMap::new(),
+        }
+    }
+
+    /// Return the number of sectors that have been written to the overlay.
+    pub fn dirty_sectors(&self) -> usize {
+        self.sectors.len()
+    }
+}
+
+impl DiskBackend for OverlayDisk {
+    fn geometry(&self) -> (u16, u8, u8) {
+        self.base.geometry()
+    }
+
+    fn size(&self) -> u64 {
+        self.base.size()
+    }
+
+    fn read_sector(&mut self, buf: &mut [u8], cylinder: u16, head: u8, sector: u8) -> Result<(), Error> {
+        match self
+            .sectors
+            .get(&chs_offset(self.base.geometry(), cylinder, head, sector))
+        {
+            Some(data) => {
+                buf.copy_from_slice(data);
+                Ok(())
+            }
+            None => self.base.read_sector(buf, cylinder, head, sector),
+        }
+    }
+
+    fn write_sector(&mut self, buf: &[u8], cylinder: u16, head: u8, sector: u8) -> Result<(), Error> {
+        // Read the sector from the base image first, so that writes out of bounds fail the same way.
+        let mut sector_buf = vec![0; HDC_SECTOR_SIZE];
+        self.base.read_sector(&mut sector_buf, cylinder, head, sector)?;
+
+        sector_buf.copy_from_slice(buf);
+        self.sectors
+            .insert(chs_offset(self.base.geometry(), cylinder, head, sector), sector_buf);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct ZeroDisk;
+
+    impl DiskBackend for ZeroDisk {
+        fn geometry(&self) -> (u16, u8, u8) {
+            (306, 4, 17)
+        }
+        fn size(&self) -> u64 {
+            306 * 4 * 17 * HDC_SECTOR_SIZE as u64
+        }
+        fn read_sector(&mut self, buf: &mut [u8], cylinder: u16, _head: u8, _sector: u8) -> Result<(), Error> {
+            if cylinder >= 306 {
+                anyhow::bail!("Out of bounds");
+            }
+            buf.fill(0);
+            Ok(())
+        }
+        fn write_sector(&mut self, _buf: &[u8], _cylinder: u16, _head: u8, _sector: u8) -> Result<(), Error> {
+            panic!("Overlay wrote to base image");
+        }
+    }
+
+    #[test]
+    fn test_overlay_write() {
+        let mut disk = OverlayDisk::new(Box::new(ZeroDisk));
+        let mut buf = vec![0x5A; HDC_SECTOR_SIZE];
+
+        disk.write_sector(&buf, 10, 1, 2).unwrap();
+        assert!(disk.write_sector(&buf, 306, 0, 0).is_err());
+        assert_eq!(disk.dirty_sectors(), 1);
+
+        buf.fill(0xFF);
+        disk.read_sector(&mut buf, 10, 1, 3).unwrap();
+        assert!(buf.iter().all(|b| *b == 0));
+        disk.read_sector(&mut buf, 10, 1, 2).unwrap();
+        assert!(buf.iter().all(|b| *b == 0x5A));
+    }
+}
@@ -0,0 +1,123 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    hdd_image::raw.rs
+
+    Implements raw sector hard disk images.
+
+*/
+
+use std::{
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use anyhow::{bail, Context, Error};
+
+use crate::{
+    device_types::hdc::{HardDiskFormat, HDC_SECTOR_SIZE},
+    hdd_image::{chs_offset, DiskBackend},
+};
+
+pub struct RawDiskImage {
+    file: File,
+    geometry: (u16, u8, u8),
+    size: u64,
+}
+
+impl RawDiskImage {
+    pub fn from_file(file: File, c: u16, h: u8, s: u8) -> Result<RawDiskImage, Error> {
+        let len = file.metadata().context("Failed to read disk image metadata")?.len();
+        let size = c as u64 * h as u64 * s as u64 * HDC_SECTOR_SIZE as u64;
+
+        if size == 0 || len < size {
+            bail!(
+                "Disk image of {} bytes is too small for geometry c:{} h:{} s:{} ({} bytes)",
+                len,
+                c,
+                h,
+                s,
+                size
+            );
+        }
+        if len > size {
+            log::warn!("Disk image is larger than its geometry; {} bytes will be ignored.", len - size);
+        }
+
+        log::info!("Raw disk image geometry: c:{} h:{} s:{}", c, h, s);
+        Ok(RawDiskImage {
+            file,
+            geometry: (c, h, s),
+            size,
+        })
+    }
+
+    /// Guess the geometry of a raw image from its size, by finding the single format in `formats`
+    /// with the same size.
+    pub fn guess_geometry(len: u64, formats: &[HardDiskFormat]) -> Option<(u16, u8, u8)> {
+        let mut matches = formats.iter().filter(|f| f.get_size() as u64 == len);
+        match (matches.next(), matches.next()) {
+            (Some(format), None) => Some((format.max_cylinders, format.max_heads, format.max_sectors)),
+            _ => None,
+        }
+    }
+
+    fn sector_offset(&self, cylinder: u16, head: u8, sector: u8) -> Result<u64, Error> {
+        let offset = chs_offset(self.geometry, cylinder, head, sector);
+        if offset + HDC_SECTOR_SIZE as u64 > self.size {
+            bail!("Sector c:{} h:{} s:{} is out of bounds", cylinder, head, sector);
+        }
+        Ok(offset)
+    }
+}
+
+impl DiskBackend for RawDiskImage {
+    fn geometry(&self) -> (u16, u8, u8) {
+        self.geometry
+    }
+
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn read_sector(&mut self, buf: &mut [u8], cylinder: u16, head: u8, sector: u8) -> Result<(), Error> {
+        let offset = self.sector_offset(cylinder, head, sector)?;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file
+            .read_exact(buf)
+            .context("Error reading sector from disk image")?;
+        Ok(())
+    }
+
+    fn write_sector(&mut self, buf: &[u8], cylinder: u16, head: u8, sector: u8) -> Result<(), Error> {
+        let offset = self.sector_offset(cylinder, head, sector)?;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file
+            .write_all(buf)
+            .context("Error writing sector to disk image")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,622 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    host_dir::fat.rs
+
+    FAT12/FAT16 on-disk structures: the BIOS Parameter Block, the File
+    Allocation Table and 8.3 directory entries.
+
+*/
+
+use std::collections::HashSet;
+
+use anyhow::{bail, Error};
+
+pub const FAT_SECTOR_SIZE: usize = 512;
+pub const DIR_ENTRY_LEN: usize = 32;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+pub const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+
+/// The first byte of the name of a deleted directory entry.
+pub const DIR_ENTRY_DELETED: u8 = 0xE5;
+/// Stands in for a first name byte of 0xE5, which would otherwise mark the entry deleted.
+pub const DIR_ENTRY_KANJI_E5: u8 = 0x05;
+
+pub const FAT12_MAX_CLUSTERS: u32 = 4084;
+pub const FAT16_MAX_CLUSTERS: u32 = 65524;
+/// DOS 3.x uses FAT12 for volumes smaller than this many sectors.
+pub const FAT12_MAX_HDD_SECTORS: u32 = 32680;
+
+/// Characters other than letters and digits allowed in a short file name.
+const SHORT_NAME_CHARS: &[u8] = b"!#$%&'()-@^_`{}~";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+}
+
+/// The fields of the BIOS Parameter Block needed to lay out a volume. Sectors are always 512 bytes.
+#[derive(Clone, Debug)]
+pub struct FatParams {
+    pub fat_type: FatType,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub fat_count: u8,
+    pub root_entries: u16,
+    pub total_sectors: u32,
+    pub media: u8,
+    pub sectors_per_fat: u16,
+    pub sectors_per_track: u16,
+    pub heads: u16,
+    pub hidden_sectors: u32,
+}
+
+impl FatParams {
+    /// Return the standard parameters for a floppy of the specified number of sectors.
+    pub fn floppy(total_sectors: u32) -> Option<FatParams> {
+        // (sectors, sectors per cluster, root entries, media, sectors per fat, sectors per track)
+        let (spc, root_entries, media, spf, spt) = match total_sectors {
+            720 => (2, 112, 0xFD, 2, 9),
+            1440 => (2, 112, 0xF9, 3, 9),
+            2400 => (1, 224, 0xF9, 7, 15),
+            2880 => (1, 224, 0xF0, 9, 18),
+            _ => return None,
+        };
+        Some(FatParams {
+            fat_type: FatType::Fat12,
+            sectors_per_cluster: spc,
+            reserved_sectors: 1,
+            fat_count: 2,
+            root_entries,
+            total_sectors,
+            media,
+            sectors_per_fat: spf,
+            sectors_per_track: spt,
+            heads: 2,
+            hidden_sectors: 0,
+        })
+    }
+
+    /// Return parameters for a hard disk partition of the specified number of sectors, choosing the
+    /// FAT type and cluster size the way DOS 3.x FORMAT does.
+    pub fn hard_disk(total_sectors: u32, sectors_per_track: u16, heads: u16, hidden_sectors: u32) -> FatParams {
+        let (fat_type, max_clusters, min_spc) = if total_sectors < FAT12_MAX_HDD_SECTORS {
+            (FatType::Fat12, FAT12_MAX_CLUSTERS, 1)
+        }
+        else {
+            (FatType::Fat16, FAT16_MAX_CLUSTERS, 4)
+        };
+
+        let mut params = FatParams {
+            fat_type,
+            sectors_per_cluster: min_spc,
+            reserved_sectors: 1,
+            fat_count: 2,
+            root_entries: 512,
+            total_sectors,
+            media: 0xF8,
+            sectors_per_fat: 0,
+            sectors_per_track,
+            heads,
+            hidden_sectors,
+        };
+
+        loop {
+            params.sectors_per_fat = params.fat_sectors_needed();
+            if params.cluster_count() <= max_clusters || params.sectors_per_cluster >= 64 {
+                break;
+            }
+            params.sectors_per_cluster *= 2;
+        }
+        params
+    }
+
+    /// Calculate the size of a FAT large enough to map every cluster, assuming no space is used by
+    /// the FATs themselves.
+    fn fat_sectors_needed(&self) -> u16 {
+        let data_sectors = self.total_sectors - self.reserved_sectors as u32 - self.root_dir_sectors();
+        let clusters = data_sectors / self.sectors_per_cluster as u32 + 2;
+        let fat_bytes = match self.fat_type {
+            FatType::Fat12 => (clusters * 3 + 1) / 2,
+            FatType::Fat16 => clusters * 2,
+        };
+        ((fat_bytes + FAT_SECTOR_SIZE as u32 - 1) / FAT_SECTOR_SIZE as u32) as u16
+    }
+
+    /// Parse the BIOS Parameter Block from a volume boot sector. The FAT type is determined by
+    /// cluster count, as DOS does.
+    pub fn parse(boot: &[u8]) -> Result<FatParams, Error> {
+        if boot.len() < FAT_SECTOR_SIZE {
+            bail!("Boot sector too short");
+        }
+        let u16_at = |o: usize| u16::from_le_bytes([boot[o], boot[o + 1]]);
+        let u32_at = |o: usize| u32::from_le_bytes([boot[o], boot[o + 1], boot[o + 2], boot[o + 3]]);
+
+        if u16_at(0x0B) as usize != FAT_SECTOR_SIZE {
+            bail!("Unsupported sector size: {}", u16_at(0x0B));
+        }
+
+        let total_sectors = match u16_at(0x13) {
+            0 => u32_at(0x20),
+            total => total as u32,
+        };
+
+        let mut params = FatParams {
+            fat_type: FatType::Fat12,
+            sectors_per_cluster: boot[0x0D],
+            reserved_sectors: u16_at(0x0E),
+            fat_count: boot[0x10],
+            root_entries: u16_at(0x11),
+            total_sectors,
+            media: boot[0x15],
+            sectors_per_fat: u16_at(0x16),
+            sectors_per_track: u16_at(0x18),
+            heads: u16_at(0x1A),
+            hidden_sectors: u32_at(0x1C),
+        };
+
+        if params.sectors_per_cluster == 0
+            || params.fat_count == 0
+            || params.sectors_per_fat == 0
+            || params.data_start() >= params.total_sectors
+        {
+            bail!("Invalid BIOS Parameter Block");
+        }
+
+        if params.cluster_count() > FAT12_MAX_CLUSTERS {
+            params.fat_type = FatType::Fat16;
+        }
+        Ok(params)
+    }
+
+    /// Parse the BIOS Parameter Block of a complete volume, checking that the layout it describes
+    /// fits the volume. The boot sector may have been written by the guest, so nothing it contains
+    /// can be trusted.
+    pub fn parse_volume(volume: &[u8]) -> Result<FatParams, Error> {
+        let params = FatParams::parse(volume)?;
+
+        if params.total_sectors as usize * FAT_SECTOR_SIZE > volume.len() {
+            bail!(
+                "BPB describes {} sectors, but the volume has {}",
+                params.total_sectors,
+                volume.len() / FAT_SECTOR_SIZE
+            );
+        }
+
+        // The FAT must have an entry for every cluster, plus the two reserved entries.
+        let entries = params.cluster_count() as usize + 2;
+        let fat_len = match params.fat_type {
+            FatType::Fat12 => (entries * 3).div_ceil(2) + 1,
+            FatType::Fat16 => entries * 2,
+        };
+        if (params.sectors_per_fat as usize * FAT_SECTOR_SIZE) < fat_len {
+            bail!("FAT is too small for {} clusters", params.cluster_count());
+        }
+        Ok(params)
+    }
+
+    /// Write a volume boot sector containing this BPB. The boot code prints a message and reboots,
+    /// as the volume has no operating system.
+    pub fn write_boot_sector(&self, buf: &mut [u8], serial: u32) {
+        const BOOT_CODE: [u8; 28] = [
+            0x31, 0xC0, // xor ax, ax
+            0x8E, 0xD8, // mov ds, ax
+            0xBE, 0x5A, 0x7C, // mov si, 7C5Ah (message)
+            0xFC, // cld
+            0xAC, // lodsb
+            0x08, 0xC0, // or al, al
+            0x74, 0x09, // jz +9
+            0xB4, 0x0E, // mov ah, 0Eh
+            0xBB, 0x07, 0x00, // mov bx, 0007h
+            0xCD, 0x10, // int 10h
+            0xEB, 0xF2, // jmp lodsb
+            0x31, 0xC0, // xor ax, ax
+            0xCD, 0x16, // int 16h
+            0xCD, 0x19, // int 19h
+        ];
+        const BOOT_CODE_OFFSET: usize = 0x3E;
+        const BOOT_MESSAGE: &[u8] = b"Non-system disk. Press any key to reboot.\r\n\0";
+
+        buf[..FAT_SECTOR_SIZE].fill(0);
+        buf[0..3].copy_from_slice(&[0xEB, (BOOT_CODE_OFFSET - 2) as u8, 0x90]);
+        buf[0x03..0x0B].copy_from_slice(b"MARTYPC ");
+        buf[0x0B..0x0D].copy_from_slice(&(FAT_SECTOR_SIZE as u16).to_le_bytes());
+        buf[0x0D] = self.sectors_per_cluster;
+        buf[0x0E..0x10].copy_from_slice(&self.reserved_sectors.to_le_bytes());
+        buf[0x10] = self.fat_count;
+        buf[0x11..0x13].copy_from_slice(&self.root_entries.to_le_bytes());
+        if self.total_sectors < 0x10000 {
+            buf[0x13..0x15].copy_from_slice(&(self.total_sectors as u16).to_le_bytes());
+        }
+        else {
+            buf[0x20..0x24].copy_from_slice(&self.total_sectors.to_le_bytes());
+        }
+        buf[0x15] = self.media;
+        buf[0x16..0x18].copy_from_slice(&self.sectors_per_fat.to_le_bytes());
+        buf[0x18..0x1A].copy_from_slice(&self.sectors_per_track.to_le_bytes());
+        buf[0x1A..0x1C].copy_from_slice(&self.heads.to_le_bytes());
+        buf[0x1C..0x20].copy_from_slice(&self.hidden_sectors.to_le_bytes());
+
+        // Extended BPB, ignored by DOS versions before 4.0
+        buf[0x24] = if self.hidden_sectors > 0 { 0x80 } else { 0x00 };
+        buf[0x26] = 0x29;
+        buf[0x27..0x2B].copy_from_slice(&serial.to_le_bytes());
+        buf[0x2B..0x36].copy_from_slice(b"NO NAME    ");
+        buf[0x36..0x3E].copy_from_slice(match self.fat_type {
+            FatType::Fat12 => b"FAT12   ",
+            FatType::Fat16 => b"FAT16   ",
+        });
+
+        buf[BOOT_CODE_OFFSET..BOOT_CODE_OFFSET + BOOT_CODE.len()].copy_from_slice(&BOOT_CODE);
+        let msg_offset = BOOT_CODE_OFFSET + BOOT_CODE.len();
+        buf[msg_offset..msg_offset + BOOT_MESSAGE.len()].copy_from_slice(BOOT_MESSAGE);
+
+        buf[0x1FE] = 0x55;
+        buf[0x1FF] = 0xAA;
+    }
+
+    pub fn root_dir_sectors(&self) -> u32 {
+        (self.root_entries as u32 * DIR_ENTRY_LEN as u32 + FAT_SECTOR_SIZE as u32 - 1) / FAT_SECTOR_SIZE as u32
+    }
+
+    pub fn fat_start(&self) -> u32 {
+        self.reserved_sectors as u32
+    }
+
+    pub fn root_start(&self) -> u32 {
+        self.fat_start() + self.fat_count as u32 * self.sectors_per_fat as u32
+    }
+
+    pub fn data_start(&self) -> u32 {
+        self.root_start() + self.root_dir_sectors()
+    }
+
+    /// Return the number of data clusters on the volume.
+    pub fn cluster_count(&self) -> u32 {
+        self.total_sectors.saturating_sub(self.data_start()) / self.sectors_per_cluster as u32
+    }
+
+    pub fn cluster_len(&self) -> usize {
+        self.sectors_per_cluster as usize * FAT_SECTOR_SIZE
+    }
+
+    /// Return the first sector of the specified cluster. Clusters are numbered from 2.
+    pub fn cluster_sector(&self, cluster: u32) -> u32 {
+        self.data_start() + (cluster - 2) * self.sectors_per_cluster as u32
+    }
+
+    /// Return the cluster containing the specified sector, if it is in the data area.
+    pub fn sector_cluster(&self, sector: u32) -> Option<u32> {
+        if sector < self.data_start() {
+            return None;
+        }
+        Some((sector - self.data_start()) / self.sectors_per_cluster as u32 + 2)
+    }
+
+    /// Return whether a FAT entry value marks the end of a cluster chain.
+    pub fn is_end_of_chain(&self, value: u32) -> bool {
+        match self.fat_type {
+            FatType::Fat12 => value >= 0xFF8,
+            FatType::Fat16 => value >= 0xFFF8,
+        }
+    }
+
+    pub fn end_of_chain(&self) -> u32 {
+        match self.fat_type {
+            FatType::Fat12 => 0xFFF,
+            FatType::Fat16 => 0xFFFF,
+        }
+    }
+
+    /// Return the FAT entry for a cluster. Clusters beyond the end of the FAT read as end of chain.
+    pub fn get_fat_entry(&self, fat: &[u8], cluster: u32) -> u32 {
+        let offset = match self.fat_type {
+            FatType::Fat12 => (cluster + cluster / 2) as usize,
+            FatType::Fat16 => cluster as usize * 2,
+        };
+        let value = match fat.get(offset..offset + 2) {
+            Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]) as u32,
+            None => return self.end_of_chain(),
+        };
+
+        match self.fat_type {
+            FatType::Fat12 => {
+                if cluster & 1 != 0 {
+                    value >> 4
+                }
+                else {
+                    value & 0xFFF
+                }
+            }
+            FatType::Fat16 => value,
+        }
+    }
+
+    pub fn set_fat_entry(&self, fat: &mut [u8], cluster: u32, value: u32) {
+        match self.fat_type {
+            FatType::Fat12 => {
+                let offset = (cluster + cluster / 2) as usize;
+                let old = u16::from_le_bytes([fat[offset], fat[offset + 1]]);
+                let new = if cluster & 1 != 0 {
+                    (old & 0x000F) | ((value as u16) << 4)
+                }
+                else {
+                    (old & 0xF000) | (value as u16 & 0x0FFF)
+                };
+                fat[offset..offset + 2].copy_from_slice(&new.to_le_bytes());
+            }
+            FatType::Fat16 => {
+                let offset = cluster as usize * 2;
+                fat[offset..offset + 2].copy_from_slice(&(value as u16).to_le_bytes());
+            }
+        }
+    }
+
+    /// Follow a cluster chain from the specified starting cluster. Chains that run off the end of
+    /// the volume or loop are cut short.
+    pub fn cluster_chain(&self, fat: &[u8], start: u32) -> Vec<u32> {
+        let mut chain = Vec::new();
+        let mut cluster = start;
+        let max_cluster = self.cluster_count() + 1;
+
+        while cluster >= 2 && cluster <= max_cluster && chain.len() <= max_cluster as usize {
+            chain.push(cluster);
+            let next = self.get_fat_entry(fat, cluster);
+            if self.is_end_of_chain(next) {
+                break;
+            }
+            cluster = next;
+        }
+        chain
+    }
+}
+
+/// A short (8.3) directory entry.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DirEntry {
+    pub name: [u8; 11],
+    pub attr: u8,
+    pub time: u16,
+    pub date: u16,
+    pub cluster: u16,
+    pub size: u32,
+}
+
+impl DirEntry {
+    pub fn from_bytes(buf: &[u8]) -> DirEntry {
+        let mut name = [0u8; 11];
+        name.copy_from_slice(&buf[0..11]);
+        DirEntry {
+            name,
+            attr: buf[11],
+            time: u16::from_le_bytes([buf[22], buf[23]]),
+            date: u16::from_le_bytes([buf[24], buf[25]]),
+            cluster: u16::from_le_bytes([buf[26], buf[27]]),
+            size: u32::from_le_bytes([buf[28], buf[29], buf[30], buf[31]]),
+        }
+    }
+
+    pub fn write_bytes(&self, buf: &mut [u8]) {
+        buf[..DIR_ENTRY_LEN].fill(0);
+        buf[0..11].copy_from_slice(&self.name);
+        buf[11] = self.attr;
+        buf[22..24].copy_from_slice(&self.time.to_le_bytes());
+        buf[24..26].copy_from_slice(&self.date.to_le_bytes());
+        buf[26..28].copy_from_slice(&self.cluster.to_le_bytes());
+        buf[28..32].copy_from_slice(&self.size.to_le_bytes());
+    }
+
+    /// Return the entry's name in NAME.EXT form.
+    pub fn display_name(&self) -> String {
+        let mut name = self.name;
+        if name[0] == DIR_ENTRY_KANJI_E5 {
+            name[0] = DIR_ENTRY_DELETED;
+        }
+        let base = String::from_utf8_lossy(&name[0..8]).trim_end().to_string();
+        let ext = String::from_utf8_lossy(&name[8..11]).trim_end().to_string();
+        if ext.is_empty() {
+            base
+        }
+        else {
+            format!("{}.{}", base, ext)
+        }
+    }
+
+    /// Return the entry's name in NAME.EXT form for use on the host, or None if it is not a valid
+    /// 8.3 name. Entries are written by the guest, so this is what keeps a crafted name such as
+    /// "X/../../" from addressing anything outside its directory.
+    pub fn host_name(&self) -> Option<String> {
+        let mut name = self.name;
+        if name[0] == DIR_ENTRY_KANJI_E5 {
+            name[0] = DIR_ENTRY_DELETED;
+        }
+        let base = name[0..8].trim_ascii_end();
+        let ext = name[8..11].trim_ascii_end();
+        if base.is_empty() || !base.iter().chain(ext).all(|&c| is_short_name_char(c)) {
+            return None;
+        }
+        Some(self.display_name())
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+
+    /// Return whether this is the '.' or '..' entry of a subdirectory.
+    pub fn is_dot(&self) -> bool {
+        self.name[0] == b'.'
+    }
+}
+
+/// Pack a date and time into the DOS directory entry format, returning (time, date).
+pub fn dos_date_time(year: u32, month: u32, day: u32, hour: u32, minute: u32, second: u32) -> (u16, u16) {
+    let year = year.clamp(1980, 2107);
+    let time = (hour << 11 | minute << 5 | second / 2) as u16;
+    let date = ((year - 1980) << 9 | month << 5 | day) as u16;
+    (time, date)
+}
+
+fn is_short_name_char(c: u8) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || c >= 0x80 || SHORT_NAME_CHARS.contains(&c)
+}
+
+/// Convert a file name to a padded 8.3 name, if it is already a valid short name (ignoring case).
+pub fn short_name(name: &str) -> Option<[u8; 11]> {
+    let upper = name.to_ascii_uppercase();
+    let (base, ext) = match upper.rsplit_once('.') {
+        Some((base, ext)) => (base, ext),
+        None => (upper.as_str(), ""),
+    };
+
+    if base.is_empty()
+        || base.len() > 8
+        || ext.len() > 3
+        || !base.bytes().chain(ext.bytes()).all(|c| c.is_ascii() && is_short_name_char(c))
+    {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short)
+}
+
+/// Generate a unique 8.3 name for a file name. Valid short names are used as-is; other names are
+/// truncated, stripped of invalid characters and given a numeric tail ("LONGFI~1.TXT").
+pub fn make_short_name(name: &str, taken: &HashSet<[u8; 11]>) -> Option<[u8; 11]> {
+    if let Some(short) = short_name(name) {
+        if !taken.contains(&short) {
+            return Some(short);
+        }
+    }
+
+    let upper = name.to_ascii_uppercase();
+    let (base, ext) = match upper.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (base, ext),
+        _ => (upper.as_str(), ""),
+    };
+    let clean = |s: &str, len: usize| -> Vec<u8> {
+        s.bytes()
+            .filter(|c| *c != b' ' && *c != b'.')
+            .map(|c| if c.is_ascii() && is_short_name_char(c) { c } else { b'_' })
+            .take(len)
+            .collect()
+    };
+    let base = clean(base, 8);
+    let ext = clean(ext, 3);
+
+    for n in 1..1000000u32 {
+        let tail = format!("~{}", n);
+        let base_len = base.len().min(8 - tail.len());
+
+        let mut short = [b' '; 11];
+        short[..base_len].copy_from_slice(&base[..base_len]);
+        short[base_len..base_len + tail.len()].copy_from_slice(tail.as_bytes());
+        short[8..8 + ext.len()].copy_from_slice(&ext);
+        if !taken.contains(&short) {
+            return Some(short);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_short_names() {
+        let mut taken = HashSet::new();
+        assert_eq!(&short_name("command.com").unwrap(), b"COMMAND COM");
+        assert_eq!(&short_name("README").unwrap(), b"README     ");
+        assert!(short_name("longfilename.txt").is_none());
+        assert!(short_name("a.b.c").is_none());
+
+        let long = make_short_name("longfilename.text", &taken).unwrap();
+        assert_eq!(&long, b"LONGFI~1TEX");
+        taken.insert(long);
+        assert_eq!(&make_short_name("longfilename.text", &taken).unwrap(), b"LONGFI~2TEX");
+        assert_eq!(&make_short_name("my file+1.c", &taken).unwrap(), b"MYFILE~1C  ");
+    }
+
+    #[test]
+    fn test_fat12_entries() {
+        let params = FatParams::floppy(720).unwrap();
+        let mut fat = vec![0u8; params.sectors_per_fat as usize * FAT_SECTOR_SIZE];
+        params.set_fat_entry(&mut fat, 2, 3);
+        params.set_fat_entry(&mut fat, 3, 0xFFF);
+        params.set_fat_entry(&mut fat, 4, 0xABC);
+        assert_eq!(params.get_fat_entry(&fat, 2), 3);
+        assert_eq!(params.get_fat_entry(&fat, 3), 0xFFF);
+        assert_eq!(params.get_fat_entry(&fat, 4), 0xABC);
+        assert_eq!(params.cluster_chain(&fat, 2), vec![2, 3]);
+
+        // Entries past the end of the FAT read as end of chain.
+        assert_eq!(params.get_fat_entry(&fat, 0xFFFF), 0xFFF);
+    }
+
+    #[test]
+    fn test_host_name() {
+        let mut entry = DirEntry {
+            name: *b"README  TXT",
+            ..DirEntry::default()
+        };
+        assert_eq!(entry.host_name().as_deref(), Some("README.TXT"));
+
+        let bad_names = [
+            b"X/../../   ",
+            b"..        X",
+            b"A\\B     TXT",
+            b"C:      TXT",
+            b"AB\x01CD   TXT",
+            b"        TXT",
+        ];
+        for name in bad_names {
+            entry.name = *name;
+            assert_eq!(entry.host_name(), None, "{:?}", String::from_utf8_lossy(name));
+        }
+    }
+
+    #[test]
+    fn test_hard_disk_params() {
+        // A 10MB XT disk uses FAT12 with 4K clusters.
+        let params = FatParams::hard_disk(305 * 4 * 17 - 17, 17, 4, 17);
+        assert_eq!(params.fat_type, FatType::Fat12);
+        assert_eq!(params.sectors_per_cluster, 8);
+        assert!(params.cluster_count() <= FAT12_MAX_CLUSTERS);
+
+        let mut boot = vec![0u8; FAT_SECTOR_SIZE];
+        params.write_boot_sector(&mut boot, 0);
+        let parsed = FatParams::parse(&boot).unwrap();
+        assert_eq!(parsed.data_start(), params.data_start());
+        assert_eq!(parsed.fat_type, FatType::Fat12);
+    }
+}
@@ -0,0 +1,670 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    host_dir::mod.rs
+
+    Present a directory on the host as a FAT12 or FAT16 volume. The volume is
+    built in memory from the directory tree when mounted. After the guest
+    writes to the volume, sync() parses the FAT file system back out of the
+    volume image and applies any created, modified or deleted files and
+    directories to the host.
+
+*/
+
+pub mod fat;
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use anyhow::{anyhow, bail, Error};
+
+use crate::util::civil_time;
+use fat::*;
+
+/// How deep to descend into host subdirectories. Guards against symlink loops.
+const MAX_DIR_DEPTH: usize = 32;
+
+/// A file or directory scanned from the host.
+struct HostNode {
+    host_path: PathBuf,
+    short_name: [u8; 11],
+    is_dir: bool,
+    read_only: bool,
+    size: u64,
+    time: u16,
+    date: u16,
+    children: Vec<HostNode>,
+}
+
+/// A file or directory as last seen on the volume, and where it lives on the host.
+#[derive(Clone, Debug)]
+struct SnapshotEntry {
+    host_path: PathBuf,
+    is_dir: bool,
+    chain: Vec<u32>,
+    size: u32,
+}
+
+/// A file or directory parsed from the volume.
+struct VolumeEntry {
+    entry: DirEntry,
+    chain: Vec<u32>,
+}
+
+pub struct HostDirVolume {
+    root: PathBuf,
+    read_only: bool,
+    /// Entries keyed by DOS path, ie "SUBDIR\FILE.TXT".
+    snapshot: HashMap<String, SnapshotEntry>,
+}
+
+/// Assembles a volume image from a tree of host nodes, allocating clusters sequentially.
+struct VolumeBuilder<'a> {
+    params: &'a FatParams,
+    volume: Vec<u8>,
+    fat: Vec<u8>,
+    next_cluster: u32,
+    snapshot: HashMap<String, SnapshotEntry>,
+}
+
+impl<'a> VolumeBuilder<'a> {
+    /// Allocate a contiguous chain of clusters large enough to hold 'len' bytes and fill it with
+    /// 'data'. Returns the cluster chain, which is empty for a zero length.
+    fn allocate(&mut self, len: usize, data: &[u8]) -> Option<Vec<u32>> {
+        let cluster_len = self.params.cluster_len();
+        let count = ((len + cluster_len - 1) / cluster_len) as u32;
+        if self.next_cluster + count > self.params.cluster_count() + 2 {
+            return None;
+        }
+
+        let chain: Vec<u32> = (self.next_cluster..self.next_cluster + count).collect();
+        for (i, cluster) in chain.iter().enumerate() {
+            let next = chain.get(i + 1).copied().unwrap_or(self.params.end_of_chain());
+            self.params.set_fat_entry(&mut self.fat, *cluster, next);
+        }
+        self.next_cluster += count;
+
+        if let Some(first) = chain.first() {
+            let start = self.params.cluster_sector(*first) as usize * FAT_SECTOR_SIZE;
+            self.volume[start..start + data.len()].copy_from_slice(data);
+        }
+        Some(chain)
+    }
+
+    /// Lay out the nodes of a directory, returning the directory's entries.
+    fn place_dir(&mut self, nodes: &[HostNode], dos_prefix: &str, dir_cluster: u32, parent_cluster: u32, capacity: usize) -> Vec<DirEntry> {
+        let mut entries = Vec::new();
+
+        if dir_cluster != 0 {
+            let mut dot = DirEntry {
+                name: *b".          ",
+                attr: ATTR_DIRECTORY,
+                cluster: dir_cluster as u16,
+                ..Default::default()
+            };
+            entries.push(dot.clone());
+            dot.name = *b"..         ";
+            dot.cluster = parent_cluster as u16;
+            entries.push(dot);
+        }
+
+        for node in nodes {
+            let mut entry = DirEntry {
+                name: node.short_name,
+                attr: if node.is_dir { ATTR_DIRECTORY } else { ATTR_ARCHIVE },
+                time: node.time,
+                date: node.date,
+                ..Default::default()
+            };
+            if node.read_only && !node.is_dir {
+                entry.attr |= ATTR_READ_ONLY;
+            }
+            let dos_path = format!("{}{}", dos_prefix, entry.display_name());
+
+            if entries.len() >= capacity {
+                log::warn!("Host directory: no room in directory for {}", dos_path);
+                continue;
+            }
+
+            let chain = if node.is_dir {
+                // Reserve room for the '.' and '..' entries and every child.
+                let dir_len = (node.children.len() + 2) * DIR_ENTRY_LEN;
+                let chain = match self.allocate(dir_len, &[]) {
+                    Some(chain) => chain,
+                    None => {
+                        log::warn!("Host directory: volume full, skipping directory {}", dos_path);
+                        continue;
+                    }
+                };
+
+                let capacity = chain.len() * self.params.cluster_len() / DIR_ENTRY_LEN;
+                let child_prefix = format!("{}\\", dos_path);
+                let children = self.place_dir(&node.children, &child_prefix, chain[0], dir_cluster, capacity);
+                let mut dir_data = vec![0u8; children.len() * DIR_ENTRY_LEN];
+                for (i, child) in children.iter().enumerate() {
+                    child.write_bytes(&mut dir_data[i * DIR_ENTRY_LEN..]);
+                }
+                let start = self.params.cluster_sector(chain[0]) as usize * FAT_SECTOR_SIZE;
+                self.volume[start..start + dir_data.len()].copy_from_slice(&dir_data);
+                chain
+            }
+            else {
+                let data = match fs::read(&node.host_path) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        log::warn!("Host directory: couldn't read {}: {}", node.host_path.display(), e);
+                        continue;
+                    }
+                };
+                match self.allocate(data.len(), &data) {
+                    Some(chain) => {
+                        entry.size = data.len() as u32;
+                        chain
+                    }
+                    None => {
+                        log::warn!("Host directory: volume full, skipping file {}", dos_path);
+                        continue;
+                    }
+                }
+            };
+
+            entry.cluster = chain.first().copied().unwrap_or(0) as u16;
+            self.snapshot.insert(
+                dos_path,
+                SnapshotEntry {
+                    host_path: node.host_path.clone(),
+                    is_dir: node.is_dir,
+                    chain,
+                    size: entry.size,
+                },
+            );
+            entries.push(entry);
+        }
+        entries
+    }
+}
+
+/// Scan a host directory, assigning each entry a unique 8.3 name. Hidden (dot) files are skipped.
+fn scan_dir(path: &Path, depth: usize, max_file_size: u64) -> Vec<HostNode> {
+    let mut nodes = Vec::new();
+    let mut dir_entries: Vec<_> = match fs::read_dir(path) {
+        Ok(iter) => iter.filter_map(|e| e.ok()).collect(),
+        Err(e) => {
+            log::warn!("Host directory: couldn't read directory {}: {}", path.display(), e);
+            return nodes;
+        }
+    };
+    // Sort so that the volume layout and generated short names are stable between runs.
+    dir_entries.sort_by_key(|e| e.file_name());
+
+    let mut taken = HashSet::new();
+    for dir_entry in dir_entries {
+        let name = dir_entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let host_path = dir_entry.path();
+        let metadata = match fs::metadata(&host_path) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        if !metadata.is_dir() && !metadata.is_file() {
+            continue;
+        }
+        if metadata.is_file() && metadata.len() > max_file_size {
+            log::warn!("Host directory: {} is too large for the volume", host_path.display());
+            continue;
+        }
+        if metadata.is_dir() && depth >= MAX_DIR_DEPTH {
+            continue;
+        }
+
+        let short_name = match make_short_name(&name, &taken) {
+            Some(short_name) => short_name,
+            None => continue,
+        };
+        taken.insert(short_name);
+
+        let (year, month, day, hour, minute, second) = civil_time(metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+        let (time, date) = dos_date_time(year, month, day, hour, minute, second);
+
+        let children = if metadata.is_dir() {
+            scan_dir(&host_path, depth + 1, max_file_size)
+        }
+        else {
+            Vec::new()
+        };
+
+        nodes.push(HostNode {
+            host_path,
+            short_name,
+            is_dir: metadata.is_dir(),
+            read_only: metadata.permissions().readonly(),
+            size: metadata.len(),
+            time,
+            date,
+            children,
+        });
+    }
+    nodes
+}
+
+/// Return the sectors that differ between two images of the same volume.
+pub fn changed_sectors(old: &[u8], new: &[u8]) -> HashSet<u32> {
+    old.chunks(FAT_SECTOR_SIZE)
+        .zip(new.chunks(FAT_SECTOR_SIZE))
+        .enumerate()
+        .filter(|(_, (a, b))| a != b)
+        .map(|(i, _)| i as u32)
+        .collect()
+}
+
+impl HostDirVolume {
+    /// Build a volume image from the contents of the 'root' directory. Files that don't fit on the
+    /// volume are skipped with a warning.
+    pub fn build(root: &Path, params: &FatParams, read_only: bool) -> Result<(HostDirVolume, Vec<u8>), Error> {
+        if !root.is_dir() {
+            bail!("Host directory not found: {}", root.display());
+        }
+
+        let max_file_size = params.cluster_count() as u64 * params.cluster_len() as u64;
+        let nodes = scan_dir(root, 0, max_file_size);
+
+        let mut builder = VolumeBuilder {
+            params,
+            volume: vec![0u8; params.total_sectors as usize * FAT_SECTOR_SIZE],
+            fat: vec![0u8; params.sectors_per_fat as usize * FAT_SECTOR_SIZE],
+            next_cluster: 2,
+            snapshot: HashMap::new(),
+        };
+
+        let media_entry = match params.fat_type {
+            FatType::Fat12 => 0xF00 | params.media as u32,
+            FatType::Fat16 => 0xFF00 | params.media as u32,
+        };
+        params.set_fat_entry(&mut builder.fat, 0, media_entry);
+        params.set_fat_entry(&mut builder.fat, 1, params.end_of_chain());
+
+        let root_entries = builder.place_dir(&nodes, "", 0, 0, params.root_entries as usize);
+
+        let serial = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs() as u32)
+            .unwrap_or(0);
+        params.write_boot_sector(&mut builder.volume, serial);
+
+        let fat_len = builder.fat.len();
+        for i in 0..params.fat_count as usize {
+            let start = (params.fat_start() as usize) * FAT_SECTOR_SIZE + i * fat_len;
+            builder.volume[start..start + fat_len].copy_from_slice(&builder.fat);
+        }
+
+        let root_start = params.root_start() as usize * FAT_SECTOR_SIZE;
+        for (i, entry) in root_entries.iter().enumerate() {
+            entry.write_bytes(&mut builder.volume[root_start + i * DIR_ENTRY_LEN..]);
+        }
+
+        log::debug!(
+            "Host directory: built {:?} volume from {} with {} entries, {} of {} clusters used",
+            params.fat_type,
+            root.display(),
+            builder.snapshot.len(),
+            builder.next_cluster - 2,
+            params.cluster_count()
+        );
+
+        Ok((
+            HostDirVolume {
+                root: root.to_path_buf(),
+                read_only,
+                snapshot: builder.snapshot,
+            },
+            builder.volume,
+        ))
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Apply changes the guest has made to the volume to the host directory. 'dirty_sectors' are
+    /// the volume sectors written since the last sync, used to detect files rewritten in place.
+    pub fn sync(&mut self, volume: &[u8], dirty_sectors: &HashSet<u32>) -> Result<(), Error> {
+        if self.read_only || dirty_sectors.is_empty() {
+            return Ok(());
+        }
+
+        let params = FatParams::parse_volume(volume)?;
+        let fat_start = params.fat_start() as usize * FAT_SECTOR_SIZE;
+        let fat = volume
+            .get(fat_start..fat_start + params.sectors_per_fat as usize * FAT_SECTOR_SIZE)
+            .ok_or_else(|| anyhow!("FAT extends past the end of the volume"))?;
+
+        let dirty_clusters: HashSet<u32> = dirty_sectors.iter().filter_map(|s| params.sector_cluster(*s)).collect();
+
+        let mut current = HashMap::new();
+        Self::read_tree(volume, &params, fat, None, "", 0, &mut HashSet::new(), &mut current)?;
+
+        // Remove deleted entries, children before their parents.
+        let mut removed: Vec<String> = self
+            .snapshot
+            .iter()
+            .filter(|(path, old)| match current.get(*path) {
+                Some(new) => new.entry.is_dir() != old.is_dir,
+                None => true,
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        removed.sort_by_key(|path| std::cmp::Reverse(path.matches('\\').count()));
+
+        for path in removed {
+            if let Some(old) = self.snapshot.remove(&path) {
+                log::debug!("Host directory: removing {}", old.host_path.display());
+                if old.is_dir {
+                    // Fails harmlessly if the host put anything else in the directory.
+                    _ = fs::remove_dir(&old.host_path);
+                }
+                else if let Err(e) = fs::remove_file(&old.host_path) {
+                    log::warn!("Host directory: couldn't remove {}: {}", old.host_path.display(), e);
+                }
+            }
+        }
+
+        // Create and update entries, parents before their children.
+        let mut paths: Vec<&String> = current.keys().collect();
+        paths.sort();
+
+        for path in paths {
+            let new = &current[path];
+            let size = if new.entry.is_dir() { 0 } else { new.entry.size };
+
+            let host_path = match self.snapshot.get(path) {
+                Some(old) => {
+                    let changed = old.chain != new.chain
+                        || old.size != size
+                        || new.chain.iter().any(|c| dirty_clusters.contains(c));
+                    if new.entry.is_dir() || !changed {
+                        continue;
+                    }
+                    old.host_path.clone()
+                }
+                None => {
+                    let parent = match path.rsplit_once('\\') {
+                        Some((parent, _)) => match self.snapshot.get(parent) {
+                            Some(parent) => parent.host_path.clone(),
+                            None => continue,
+                        },
+                        None => self.root.clone(),
+                    };
+                    let host_path = match new.entry.host_name() {
+                        Some(name) => parent.join(name),
+                        None => continue,
+                    };
+                    if !host_path.starts_with(&self.root) {
+                        log::warn!(
+                            "Host directory: refusing to write outside of root: {}",
+                            host_path.display()
+                        );
+                        continue;
+                    }
+                    host_path
+                }
+            };
+
+            if new.entry.is_dir() {
+                log::debug!("Host directory: creating directory {}", host_path.display());
+                fs::create_dir_all(&host_path)
+                    .map_err(|e| anyhow!("Couldn't create directory {}: {}", host_path.display(), e))?;
+            }
+            else {
+                log::debug!("Host directory: writing {} ({} bytes)", host_path.display(), size);
+                let data = Self::read_chain(volume, &params, &new.chain, size as usize);
+                fs::write(&host_path, data).map_err(|e| anyhow!("Couldn't write {}: {}", host_path.display(), e))?;
+            }
+
+            self.snapshot.insert(
+                path.clone(),
+                SnapshotEntry {
+                    host_path,
+                    is_dir: new.entry.is_dir(),
+                    chain: new.chain.clone(),
+                    size,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// Read up to 'len' bytes of data from a cluster chain.
+    fn read_chain(volume: &[u8], params: &FatParams, chain: &[u32], len: usize) -> Vec<u8> {
+        let cluster_len = params.cluster_len();
+        let mut data = Vec::with_capacity(len);
+        for cluster in chain {
+            if data.len() >= len {
+                break;
+            }
+            let start = params.cluster_sector(*cluster) as usize * FAT_SECTOR_SIZE;
+            let take = cluster_len.min(len - data.len());
+            match volume.get(start..start + take) {
+                Some(bytes) => data.extend_from_slice(bytes),
+                None => break,
+            }
+        }
+        data
+    }
+
+    /// Recursively parse directory entries from the volume into 'tree', keyed by DOS path. The root
+    /// directory is read when 'dir_chain' is None. Entries without a valid 8.3 name are skipped, as
+    /// are subdirectories whose start cluster has already been visited, so a corrupted volume with
+    /// a directory loop can't produce the same files under many paths.
+    #[allow(clippy::too_many_arguments)]
+    fn read_tree(
+        volume: &[u8],
+        params: &FatParams,
+        fat: &[u8],
+        dir_chain: Option<&[u32]>,
+        dos_prefix: &str,
+        depth: usize,
+        visited: &mut HashSet<u32>,
+        tree: &mut HashMap<String, VolumeEntry>,
+    ) -> Result<(), Error> {
+        let dir_data = match dir_chain {
+            Some(chain) => Self::read_chain(volume, params, chain, chain.len() * params.cluster_len()),
+            None => {
+                let start = params.root_start() as usize * FAT_SECTOR_SIZE;
+                let end = params.data_start() as usize * FAT_SECTOR_SIZE;
+                volume
+                    .get(start..end)
+                    .ok_or_else(|| anyhow!("Root directory extends past the end of the volume"))?
+                    .to_vec()
+            }
+        };
+
+        for raw in dir_data.chunks_exact(DIR_ENTRY_LEN) {
+            let entry = DirEntry::from_bytes(raw);
+            if entry.name[0] == 0 {
+                // End of directory
+                break;
+            }
+            if entry.name[0] == DIR_ENTRY_DELETED || entry.is_dot() || entry.attr & ATTR_VOLUME_ID != 0 {
+                continue;
+            }
+
+            let name = match entry.host_name() {
+                Some(name) => name,
+                None => {
+                    log::warn!("Host directory: ignoring entry with invalid name: {:X?}", entry.name);
+                    continue;
+                }
+            };
+            let dos_path = format!("{}{}", dos_prefix, name);
+            let chain = params.cluster_chain(fat, entry.cluster as u32);
+
+            if entry.is_dir() && !chain.is_empty() {
+                if !visited.insert(chain[0]) {
+                    log::warn!("Host directory: ignoring repeated directory {}", dos_path);
+                    continue;
+                }
+                if depth < MAX_DIR_DEPTH {
+                    let child_prefix = format!("{}\\", dos_path);
+                    Self::read_tree(volume, params, fat, Some(&chain), &child_prefix, depth + 1, visited, tree)?;
+                }
+            }
+            tree.insert(dos_path, VolumeEntry { entry, chain });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Find the directory entry with the specified 8.3 name in a directory's data.
+    fn find_entry(dir: &[u8], name: &[u8; 11]) -> Option<usize> {
+        dir.chunks_exact(DIR_ENTRY_LEN)
+            .position(|raw| &raw[0..11] == name)
+            .map(|i| i * DIR_ENTRY_LEN)
+    }
+
+    #[test]
+    fn test_host_dir_sync() {
+        let root = std::env::temp_dir().join(format!("martypc_host_dir_{}", std::process::id()));
+        _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("subdir")).unwrap();
+        fs::write(root.join("hello.txt"), b"Hello, world!").unwrap();
+        fs::write(root.join("a long file name.dat"), vec![0xAA; 3000]).unwrap();
+        fs::write(root.join("subdir").join("inner.bin"), b"inner").unwrap();
+
+        let params = FatParams::floppy(720).unwrap();
+        let (mut host_dir, mut volume) = HostDirVolume::build(&root, &params, false).unwrap();
+        let original = volume.clone();
+
+        let root_start = params.root_start() as usize * FAT_SECTOR_SIZE;
+        let root_end = params.data_start() as usize * FAT_SECTOR_SIZE;
+        assert!(find_entry(&volume[root_start..root_end], b"ALONGF~1DAT").is_some());
+
+        // Overwrite the contents of HELLO.TXT in place.
+        let hello = root_start + find_entry(&volume[root_start..root_end], b"HELLO   TXT").unwrap();
+        let entry = DirEntry::from_bytes(&volume[hello..]);
+        let data_start = params.cluster_sector(entry.cluster as u32) as usize * FAT_SECTOR_SIZE;
+        volume[data_start..data_start + 13].copy_from_slice(b"Hello, MARTY!");
+
+        // Delete the long-named file.
+        let long = root_start + find_entry(&volume[root_start..root_end], b"ALONGF~1DAT").unwrap();
+        volume[long] = DIR_ENTRY_DELETED;
+
+        // Create a new empty file by reusing the deleted entry's slot after it.
+        let mut new_entry = DirEntry::from_bytes(&volume[hello..]);
+        new_entry.name = *b"NEWFILE TXT";
+        new_entry.cluster = 0;
+        new_entry.size = 0;
+        let free = root_start + volume[root_start..root_end].chunks_exact(DIR_ENTRY_LEN).position(|e| e[0] == 0).unwrap() * DIR_ENTRY_LEN;
+        new_entry.write_bytes(&mut volume[free..]);
+
+        let dirty = changed_sectors(&original, &volume);
+        host_dir.sync(&volume, &dirty).unwrap();
+
+        assert_eq!(fs::read(root.join("hello.txt")).unwrap(), b"Hello, MARTY!");
+        assert!(!root.join("a long file name.dat").exists());
+        assert!(root.join("NEWFILE.TXT").exists());
+        assert_eq!(fs::read(root.join("subdir").join("inner.bin")).unwrap(), b"inner");
+
+        _ = fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn test_host_dir_sync_untrusted() {
+        let base = std::env::temp_dir().join(format!("martypc_host_dir_untrusted_{}", std::process::id()));
+        let root = base.join("shared");
+        _ = fs::remove_dir_all(&base);
+        fs::create_dir_all(&root).unwrap();
+
+        let params = FatParams::floppy(720).unwrap();
+        let (mut host_dir, mut volume) = HostDirVolume::build(&root, &params, false).unwrap();
+        let original = volume.clone();
+
+        // A file whose name tries to climb out of the shared directory is ignored.
+        let root_start = params.root_start() as usize * FAT_SECTOR_SIZE;
+        let entry = DirEntry {
+            name: *b"X/../../EVL",
+            ..DirEntry::default()
+        };
+        entry.write_bytes(&mut volume[root_start..]);
+        let dirty = changed_sectors(&original, &volume);
+        host_dir.sync(&volume, &dirty).unwrap();
+        assert_eq!(fs::read_dir(&base).unwrap().count(), 1);
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+
+        // A BPB describing more sectors than the volume has is rejected rather than panicking.
+        volume[0x13..0x15].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert!(host_dir.sync(&volume, &dirty).is_err());
+        volume[0x13..0x15].copy_from_slice(&720u16.to_le_bytes());
+        volume[0x16..0x18].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        assert!(host_dir.sync(&volume, &dirty).is_err());
+
+        _ = fs::remove_dir_all(&base);
+    }
+
+    #[test]
+    fn test_host_dir_sync_directory_loop() {
+        let root = std::env::temp_dir().join(format!("martypc_host_dir_loop_{}", std::process::id()));
+        _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("subdir")).unwrap();
+        fs::write(root.join("subdir").join("inner.bin"), b"inner").unwrap();
+
+        let params = FatParams::floppy(720).unwrap();
+        let (mut host_dir, mut volume) = HostDirVolume::build(&root, &params, false).unwrap();
+        let original = volume.clone();
+
+        // Add an entry to SUBDIR that refers back to SUBDIR itself.
+        let root_start = params.root_start() as usize * FAT_SECTOR_SIZE;
+        let root_end = params.data_start() as usize * FAT_SECTOR_SIZE;
+        let subdir = root_start + find_entry(&volume[root_start..root_end], b"SUBDIR     ").unwrap();
+        let mut entry = DirEntry::from_bytes(&volume[subdir..]);
+        let dir_start = params.cluster_sector(entry.cluster as u32) as usize * FAT_SECTOR_SIZE;
+        let dir_end = dir_start + params.cluster_len();
+        let free = volume[dir_start..dir_end].chunks_exact(DIR_ENTRY_LEN).position(|e| e[0] == 0).unwrap();
+        entry.name = *b"LOOP       ";
+        entry.write_bytes(&mut volume[dir_start + free * DIR_ENTRY_LEN..]);
+
+        // The loop is skipped rather than followed, and the rest of the tree is left intact.
+        let dirty = changed_sectors(&original, &volume);
+        host_dir.sync(&volume, &dirty).unwrap();
+        assert!(!root.join("subdir").join("LOOP").exists());
+        assert_eq!(fs::read(root.join("subdir").join("inner.bin")).unwrap(), b"inner");
+
+        _ = fs::remove_dir_all(&root);
+    }
+}
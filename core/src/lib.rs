@@ -45,17 +45,22 @@ pub mod device_traits;
 pub mod device_types;
 pub mod devices;
 pub mod file_util;
+pub mod floppy_image;
+pub mod hdd_image;
+pub mod host_dir;
 pub mod interrupt;
 pub mod keys;
 pub mod machine;
 pub mod machine_config;
 pub mod memerror;
+pub mod savestate;
 pub mod sound;
 pub mod syntax_token;
 pub mod tracelogger;
 pub mod updatable;
 pub mod util;
 pub mod vhd;
+pub mod wav;
 
 pub mod cpu_validator; // CpuValidator trait
 
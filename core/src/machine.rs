@@ -35,37 +35,51 @@
 */
 use log;
 
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Error};
 use std::{
     cell::Cell,
     collections::{HashMap, VecDeque},
     fs::File,
     io::{BufWriter, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 use crate::{
     breakpoints::BreakPointType,
     bus::{BusInterface, ClockFactor, DeviceEvent, MEM_CP_BIT},
     coreconfig::CoreConfig,
-    cpu_808x::{Cpu, CpuAddress, CpuError, ServiceEvent, StepResult},
+    cpu_808x::{Cpu, CpuAddress, CpuError, ServiceEvent, StepResult, WatchpointHit},
     cpu_common::{CpuOption, CpuType, TraceMode},
-    device_traits::videocard::{VideoCard, VideoCardId, VideoCardInterface, VideoCardState, VideoOption},
+    device_traits::videocard::{
+        BufferSelect,
+        DisplayExtents,
+        VideoCard,
+        VideoCardId,
+        VideoCardInterface,
+        VideoCardState,
+        VideoOption,
+    },
     devices::{
         dma::DMAControllerStringState,
         fdc::FloppyController,
         hdc::HardDiskController,
         keyboard::KeyboardModifiers,
+        lpt_port::ParallelDevice,
         mouse::Mouse,
+        pc_speaker::{PcSpeaker, SpeakerFilter},
         pic::PicStringState,
         pit::{self, PitDisplayState},
         ppi::PpiStringState,
+        printer::Printer,
+        sound_blaster::SB_OUTPUT_RATE,
     },
     keys::MartyKey,
     machine_config::{get_machine_descriptor, MachineConfiguration, MachineDescriptor},
     machine_types::MachineType,
-    sound::{SoundPlayer, BUFFER_MS, VOLUME_ADJUST},
+    savestate::{SaveState, StateReader, StateWriter},
+    sound::{SoundMixer, SoundPlayer, SoundSourceId, BUFFER_MS, DEFAULT_SAMPLE_RATE, MIXER_CHANNELS, VOLUME_ADJUST},
     tracelogger::TraceLogger,
+    wav::WavWriter,
 };
 
 use ringbuf::{Consumer, Producer, RingBuffer};
@@ -208,14 +222,31 @@ impl ExecutionControl {
     }
 }
 
+/// A completed frame copied from the primary video card during video capture.
+pub struct CapturedFrame {
+    pub number: u64,
+    pub buf: Vec<u8>,
+    pub extents: DisplayExtents,
+}
+
+/// The result of a finished video capture. The frame rate is measured in emulated time.
+pub struct VideoCaptureInfo {
+    pub frames: u64,
+    pub frame_rate: f64,
+}
+
+struct VideoCapture {
+    last_frame: u64,
+    frame_ct: u64,
+    elapsed_us: f64,
+    frames: VecDeque<CapturedFrame>,
+}
+
 pub struct PitData {
     buffer_consumer: Consumer<u8>,
     samples_produced: u64,
-    ticks_per_sample: f64,
     log_file: Option<Box<BufWriter<File>>>,
     logging_triggered: bool,
-    fractional_part: f64,
-    next_sample_size: usize,
 }
 
 #[derive(Clone, Default, Debug)]
@@ -402,11 +433,21 @@ pub struct Machine {
     machine_config: MachineConfiguration,
     state: MachineState,
     sound_player: Option<SoundPlayer>,
+    mixer: SoundMixer,
+    speaker_source: SoundSourceId,
+    sound_chip_source: Option<SoundSourceId>,
+    adlib_source: Option<SoundSourceId>,
+    sound_blaster_sources: Option<(SoundSourceId, SoundSourceId)>,
+    parallel_source: Option<SoundSourceId>,
+    audio_capture: Option<WavWriter>,
+    video_capture: Option<VideoCapture>,
     rom_manifest: MachineRomManifest,
     load_bios: bool,
     cpu: Cpu,
     speaker_buf_producer: Producer<u8>,
     pit_data: PitData,
+    speaker: PcSpeaker,
+    speaker_samples: Vec<f32>,
     debug_snd_file: Option<File>,
     kb_buf: VecDeque<KeybufferEntry>,
     error: bool,
@@ -491,20 +532,23 @@ impl Machine {
         let speaker_buf: RingBuffer<u8> = RingBuffer::new(speaker_buf_size);
         let (speaker_buf_producer, speaker_buf_consumer) = speaker_buf.split();
 
-        let mut sample_rate = 44000;
+        let mut sample_rate = DEFAULT_SAMPLE_RATE;
         if let Some(sound_player) = &sound_player {
             sample_rate = sound_player.sample_rate();
         }
         let pit_ticks_per_sample = (pit::PIT_MHZ * 1_000_000.0) / sample_rate as f64;
+        let speaker = PcSpeaker::new(pit::PIT_MHZ * 1_000_000.0, sample_rate, core_config.get_speaker_filter());
+
+        // The PC speaker is sampled at the output rate and is the first mixer source.
+        let mut mixer = SoundMixer::new(sample_rate);
+        let speaker_source = mixer.add_source("PC Speaker", sample_rate);
+        mixer.set_volume(speaker_source, VOLUME_ADJUST);
 
         let pit_data = PitData {
             buffer_consumer: speaker_buf_consumer,
-            ticks_per_sample: pit_ticks_per_sample,
             samples_produced: 0,
             log_file: pit_output_file_option,
             logging_triggered: false,
-            fractional_part: pit_ticks_per_sample.fract(),
-            next_sample_size: pit_ticks_per_sample.trunc() as usize,
         };
 
         // open a file to write the sound to
@@ -534,6 +578,34 @@ impl Machine {
             log::error!("Failed to install devices: {}", err);
         }
 
+        // Give the onboard sound chip, if any, its own mixer source at the chip's native rate.
+        let sound_chip_source = cpu.bus_mut().sound_chip_mut().as_ref().map(|sound_chip| {
+            let source = mixer.add_source(sound_chip.name(), sound_chip.sample_rate());
+            mixer.set_volume(source, VOLUME_ADJUST);
+            source
+        });
+        let adlib_source = cpu
+            .bus_mut()
+            .adlib_mut()
+            .as_ref()
+            .map(|adlib| mixer.add_source("AdLib", adlib.opl().sample_rate()));
+        let sound_blaster_sources = cpu.bus_mut().sound_blaster_mut().as_ref().map(|sound_blaster| {
+            (
+                mixer.add_source("Sound Blaster DAC", SB_OUTPUT_RATE),
+                mixer.add_source("Sound Blaster FM", sound_blaster.opl().sample_rate()),
+            )
+        });
+        let parallel_source = cpu
+            .bus_mut()
+            .parallel_mut()
+            .as_ref()
+            .and_then(|parallel| parallel.port().device())
+            .and_then(|device| {
+                device
+                    .sample_rate()
+                    .map(|sample_rate| mixer.add_source(device.name(), sample_rate))
+            });
+
         // Load keyboard translation file if specified.
         if let Some(kb_translation_path) = keyboard_layout_file {
             if let Some(keyboard) = cpu.bus_mut().keyboard_mut() {
@@ -595,11 +667,21 @@ impl Machine {
             machine_config,
             state: MachineState::On,
             sound_player,
+            mixer,
+            speaker_source,
+            sound_chip_source,
+            adlib_source,
+            sound_blaster_sources,
+            parallel_source,
+            audio_capture: None,
+            video_capture: None,
             rom_manifest,
             load_bios: !core_config.get_machine_noroms(),
             cpu,
             speaker_buf_producer,
             pit_data,
+            speaker,
+            speaker_samples: Vec::new(),
             debug_snd_file: None,
             kb_buf: VecDeque::new(),
             error: false,
@@ -946,6 +1028,20 @@ impl Machine {
         self.cpu.bus_mut().mouse_mut()
     }
 
+    /// Return the printer, if one is plugged into the parallel port.
+    pub fn printer_mut(&mut self) -> Option<&mut Printer> {
+        match self
+            .cpu
+            .bus_mut()
+            .parallel_mut()
+            .as_mut()
+            .and_then(|parallel| parallel.port_mut().device_mut())
+        {
+            Some(ParallelDevice::Printer(printer)) => Some(printer),
+            _ => None,
+        }
+    }
+
     pub fn bridge_serial_port(&mut self, port_num: usize, host_port_name: String, host_port_id: usize) -> Result<(), Error> {
         if let Some(spc) = self.cpu.bus_mut().serial_mut() {
             if let Err(e) = spc.bridge_port(port_num, host_port_name, host_port_id) {
@@ -964,6 +1060,16 @@ impl Machine {
         self.cpu.set_breakpoints(bp_list)
     }
 
+    /// Replace the breakpoints matching the specified predicate with the provided list.
+    pub fn update_breakpoints(&mut self, remove: impl Fn(&BreakPointType) -> bool, add: Vec<BreakPointType>) {
+        self.cpu.update_breakpoints(remove, add)
+    }
+
+    /// Return and clear the details of the last watchpoint hit, if any.
+    pub fn take_watchpoint_hit(&mut self) -> Option<WatchpointHit> {
+        self.cpu.take_watchpoint_hit()
+    }
+
     pub fn reset(&mut self) {
         // TODO: Reload any program specified here?
 
@@ -994,6 +1100,77 @@ impl Machine {
         self.reload_pending = state;
     }
 
+    /// Save the complete state of the machine to a byte vector. The state can be restored with
+    /// [Machine::load_state] on a machine built from the same configuration.
+    ///
+    /// Mounted floppy images are included in the state; hard disk contents are not, so a state
+    /// should be restored with the same VHDs mounted.
+    pub fn save_state(&mut self) -> Result<Vec<u8>, Error> {
+        let mut w = StateWriter::with_header();
+        w.put(&format!("{:?}", self.machine_type));
+        w.section(b"MACH", |w| {
+            w.put(&self.cpu_cycles);
+            w.put(&self.cpu_instructions);
+            w.put(&self.system_ticks);
+            w.put(&self.turbo_bit);
+            w.put(&self.turbo_button);
+        });
+        w.section(b"BUS ", |w| self.cpu.bus().save_state(w));
+        w.section(b"CPU ", |w| self.cpu.save_cpu_state(w));
+        Ok(w.into_inner())
+    }
+
+    /// Restore machine state previously produced by [Machine::save_state]. If the state cannot
+    /// be loaded, the machine is returned to the state it was in before the call. Should that fail
+    /// too, the machine is left in the error state.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), Error> {
+        let snapshot = self.save_state()?;
+
+        if let Err(e) = self.load_state_inner(data) {
+            log::error!("Failed to load machine state: {}", e);
+            if let Err(restore_err) = self.load_state_inner(&snapshot) {
+                log::error!("Failed to restore machine state after failed load: {}", restore_err);
+                self.error = true;
+                self.error_str = Some(format!("State is inconsistent after a failed load: {}", restore_err));
+                return Err(anyhow!("{} (restoring the previous state failed: {})", e, restore_err));
+            }
+            return Err(e);
+        }
+
+        self.kb_buf.clear();
+        self.error = false;
+        self.error_str = None;
+        self.set_turbo_mode(self.turbo_button);
+        Ok(())
+    }
+
+    fn load_state_inner(&mut self, data: &[u8]) -> Result<(), Error> {
+        let mut r = StateReader::with_header(data)?;
+        let machine_type: String = r.get()?;
+        if machine_type != format!("{:?}", self.machine_type) {
+            return Err(anyhow!(
+                "Machine type mismatch: state has {}, machine is {:?}",
+                machine_type,
+                self.machine_type
+            ));
+        }
+        r.section(b"MACH", |r| {
+            self.cpu_cycles = r.get()?;
+            self.cpu_instructions = r.get()?;
+            self.system_ticks = r.get()?;
+            self.turbo_bit = r.get()?;
+            self.turbo_button = r.get()?;
+            Ok(())
+        })?;
+        // The bus must be restored first, as the CPU may need to re-decode from memory.
+        r.section(b"BUS ", |r| self.cpu.bus_mut().load_state(r))?;
+        r.section(b"CPU ", |r| self.cpu.load_cpu_state(r))?;
+        if r.remaining() > 0 {
+            return Err(anyhow!("Unexpected {} bytes at end of machine state", r.remaining()));
+        }
+        Ok(())
+    }
+
     #[inline]
     /// Convert a count of CPU cycles to microseconds based on the current CPU clock
     /// divisor and system crystal speed.
@@ -1275,6 +1452,7 @@ impl Machine {
                 self.error_str = Some(format!("{}", err));
                 log::error!("CPU Error: {}\n{}", err, self.cpu.dump_instruction_history_string());
             }
+            self.check_kbc_reset();
 
             // If we returned a step over target address, execution is paused, and step over was requested,
             // then consume as many instructions as needed to get to the 'next' instruction. This will
@@ -1330,6 +1508,7 @@ impl Machine {
                         }
 
                         self.run_devices(cpu_cycles, &mut kb_event_processed);
+                        self.check_kbc_reset();
 
                         cs_ip = self.cpu.get_csip();
 
@@ -1360,6 +1539,17 @@ impl Machine {
         instr_count
     }
 
+    /// Reset the CPU if the keyboard controller pulsed the reset line. Memory and devices are untouched,
+    /// which AT software relies on to return from protected mode.
+    fn check_kbc_reset(&mut self) {
+        if let Some(kbc) = self.cpu.bus_mut().kbc_mut() {
+            if kbc.take_reset_request() {
+                log::debug!("Keyboard controller reset the CPU");
+                self.cpu.reset();
+            }
+        }
+    }
+
     /// Run the other devices in the machine for the specified number of cpu cycles.
     /// CPU cycles drive the timing of the rest of the system; they will be converted into the
     /// appropriate timing units for other devices as needed.
@@ -1425,8 +1615,78 @@ impl Machine {
         }
 
         // Sample the PIT channel #2 for sound
-        while self.speaker_buf_producer.len() >= self.pit_data.next_sample_size {
-            self.pit_buf_to_sound_buf();
+        self.pit_buf_to_sound_buf();
+
+        // Collect the output of the onboard sound chip
+        if let Some(source) = self.sound_chip_source {
+            if let Some(sound_chip) = self.cpu.bus_mut().sound_chip_mut() {
+                self.mixer.queue_samples(source, sound_chip.samples());
+                sound_chip.clear_samples();
+            }
+        }
+
+        // Collect the output of the AdLib card
+        if let Some(source) = self.adlib_source {
+            if let Some(adlib) = self.cpu.bus_mut().adlib_mut() {
+                self.mixer.queue_samples(source, adlib.opl().samples());
+                adlib.opl_mut().clear_samples();
+            }
+        }
+
+        // Collect the output of the Sound Blaster DAC and FM chip
+        if let Some((dac_source, fm_source)) = self.sound_blaster_sources {
+            if let Some(sound_blaster) = self.cpu.bus_mut().sound_blaster_mut() {
+                self.mixer.queue_samples(dac_source, sound_blaster.samples());
+                sound_blaster.clear_samples();
+                self.mixer.queue_samples(fm_source, sound_blaster.opl().samples());
+                sound_blaster.opl_mut().clear_samples();
+            }
+        }
+
+        // Collect the output of the device plugged into the parallel port
+        if let Some(source) = self.parallel_source {
+            if let Some(device) = self
+                .cpu
+                .bus_mut()
+                .parallel_mut()
+                .as_mut()
+                .and_then(|parallel| parallel.port_mut().device_mut())
+            {
+                self.mixer.queue_samples(source, device.samples());
+                device.clear_samples();
+            }
+        }
+
+        // Mix all sound sources for the elapsed time and send the result to the sound player
+        self.mixer.run(us);
+        if !self.mixer.output().is_empty() {
+            if let Some(sound_player) = &mut self.sound_player {
+                sound_player.queue_frames(self.mixer.output());
+            }
+            if let Some(capture) = &mut self.audio_capture {
+                if let Err(err) = capture.write_samples(self.mixer.output()) {
+                    log::error!("Audio capture failed: {}", err);
+                    self.audio_capture = None;
+                }
+            }
+            self.mixer.clear_output();
+        }
+
+        // Copy each frame completed by the primary video card for video capture.
+        if let Some(capture) = &mut self.video_capture {
+            capture.elapsed_us += us;
+            if let Some(card) = self.cpu.bus_mut().primary_video_mut() {
+                let frame = card.get_frame_count();
+                if frame != capture.last_frame {
+                    capture.last_frame = frame;
+                    capture.frames.push_back(CapturedFrame {
+                        number: capture.frame_ct,
+                        buf: card.get_buf(BufferSelect::Front).to_vec(),
+                        extents: card.get_display_extents().clone(),
+                    });
+                    capture.frame_ct += 1;
+                }
+            }
         }
 
         // Query interrupt line after device processing.
@@ -1504,67 +1764,139 @@ impl Machine {
         device_events
     }
 
+    pub fn mixer(&self) -> &SoundMixer {
+        &self.mixer
+    }
+
+    /// Return the sound mixer, to adjust the volume, pan or mute of its sources.
+    pub fn mixer_mut(&mut self) -> &mut SoundMixer {
+        &mut self.mixer
+    }
+
+    /// Start recording the mixer output to a WAV file. The recording is clocked by emulated time,
+    /// so the same program produces the same recording regardless of host speed. It is made at the
+    /// mixer's sample rate, which follows the host output device, or DEFAULT_SAMPLE_RATE without one.
+    pub fn start_audio_capture(&mut self, path: &Path) -> Result<(), Error> {
+        if let Some(capture) = &self.audio_capture {
+            bail!("Already capturing audio to {}", capture.path().display());
+        }
+        self.audio_capture = Some(WavWriter::create(
+            path,
+            self.mixer.sample_rate(),
+            MIXER_CHANNELS as u16,
+        )?);
+        log::info!("Started audio capture to {}", path.display());
+        Ok(())
+    }
+
+    /// Stop recording audio, returning the path of the finished recording if one was in progress.
+    pub fn stop_audio_capture(&mut self) -> Result<Option<PathBuf>, Error> {
+        match self.audio_capture.take() {
+            Some(mut capture) => {
+                capture.finish()?;
+                log::info!(
+                    "Stopped audio capture to {}: {} frames",
+                    capture.path().display(),
+                    capture.frames()
+                );
+                Ok(Some(capture.path().to_path_buf()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn is_capturing_audio(&self) -> bool {
+        self.audio_capture.is_some()
+    }
+
+    /// Start copying every frame completed by the primary video card, to be collected with
+    /// take_captured_frames(). Frames are captured as the card completes them, so each emulated
+    /// frame is captured exactly once regardless of how often the host renders.
+    pub fn start_video_capture(&mut self) -> Result<(), Error> {
+        if self.video_capture.is_some() {
+            bail!("Already capturing video");
+        }
+        let last_frame = match self.cpu.bus_mut().primary_video_mut() {
+            Some(card) => card.get_frame_count(),
+            None => bail!("No video card to capture"),
+        };
+        self.video_capture = Some(VideoCapture {
+            last_frame,
+            frame_ct: 0,
+            elapsed_us: 0.0,
+            frames: VecDeque::new(),
+        });
+        log::info!("Started video capture");
+        Ok(())
+    }
+
+    /// Return the frames captured since the last call.
+    pub fn take_captured_frames(&mut self) -> Vec<CapturedFrame> {
+        match &mut self.video_capture {
+            Some(capture) => capture.frames.drain(..).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Stop capturing video, returning the number of frames captured and their rate if a capture
+    /// was in progress. Frames not yet collected are discarded.
+    pub fn stop_video_capture(&mut self) -> Option<VideoCaptureInfo> {
+        self.video_capture.take().map(|capture| {
+            log::info!("Stopped video capture: {} frames", capture.frame_ct);
+            let frame_rate = if capture.elapsed_us > 0.0 {
+                capture.frame_ct as f64 * 1_000_000.0 / capture.elapsed_us
+            }
+            else {
+                0.0
+            };
+            VideoCaptureInfo {
+                frames: capture.frame_ct,
+                frame_rate,
+            }
+        })
+    }
+
+    pub fn is_capturing_video(&self) -> bool {
+        self.video_capture.is_some()
+    }
+
     pub fn play_sound_buffer(&self) {
         if let Some(sound_player) = &self.sound_player {
             sound_player.play();
         }
     }
 
+    /// Convert the PIT channel #2 output collected since the last call into speaker samples, and
+    /// queue them to the mixer.
     pub fn pit_buf_to_sound_buf(&mut self) {
-        let nsamples = self.pit_data.next_sample_size;
-        if self.pit_data.buffer_consumer.len() < self.pit_data.next_sample_size {
-            return;
-        }
-
-        let mut sum = 0;
-        let mut sample;
-        let mut samples_read = false;
-
-        // If logging enabled, read samples and log to file.
-        if let Some(file) = self.pit_data.log_file.as_mut() {
-            if self.pit_data.logging_triggered {
-                for _ in 0..nsamples {
-                    sample = self.pit_data.buffer_consumer.pop().unwrap_or_else(|| {
-                        log::trace!("No byte in pit buffer");
-                        0
-                    });
-                    sum += sample;
+        let logging = self.pit_data.logging_triggered && self.pit_data.log_file.is_some();
 
+        while let Some(sample) = self.pit_data.buffer_consumer.pop() {
+            // If logging enabled, log each PIT tick to file.
+            if logging {
+                if let Some(file) = self.pit_data.log_file.as_mut() {
                     let sample_f32: f32 = if sample == 0 { 0.0 } else { 1.0 };
                     file.write_all(&sample_f32.to_le_bytes())
                         .expect("Error writing to debug sound file");
                 }
-                samples_read = true;
             }
+            self.speaker.push_tick(sample, &mut self.speaker_samples);
         }
 
-        // Otherwise, just read samples
-        if !samples_read {
-            for _ in 0..nsamples {
-                sample = self.pit_data.buffer_consumer.pop().unwrap_or_else(|| {
-                    log::trace!("No byte in pit buffer");
-                    0
-                });
-                sum += sample;
-            }
-        }
-
-        // Averaging samples is effectively a poor lowpass filter.
-        // TODO: replace with actual lowpass filter from biquad?
-        let average: f32 = sum as f32 / nsamples as f32;
-
-        //log::trace!("Sample: sum: {}, ticks: {}, avg: {}", sum, pit_ticks, average);
-        self.pit_data.samples_produced += 1;
-        //log::trace!("producer: {}", self.pit_samples_produced);
-        if let Some(sound_player) = &mut self.sound_player {
-            sound_player.queue_sample(average * VOLUME_ADJUST);
+        if !self.speaker_samples.is_empty() {
+            self.pit_data.samples_produced += self.speaker_samples.len() as u64;
+            self.mixer.queue_samples(self.speaker_source, &self.speaker_samples);
+            self.speaker_samples.clear();
         }
+    }
 
-        // Calculate size of next audio sample in pit samples by carrying over fractional part
-        let next_sample_f: f64 = self.pit_data.ticks_per_sample + self.pit_data.fractional_part;
+    pub fn speaker_filter(&self) -> SpeakerFilter {
+        self.speaker.filter()
+    }
 
-        self.pit_data.next_sample_size = next_sample_f as usize;
-        self.pit_data.fractional_part = next_sample_f.fract();
+    /// Select the filter used to produce PC speaker samples from the PIT output.
+    pub fn set_speaker_filter(&mut self, filter: SpeakerFilter) {
+        self.speaker.set_filter(filter);
     }
 
     pub fn for_each_videocard<F>(&mut self, f: F)
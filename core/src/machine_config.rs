@@ -36,8 +36,11 @@ use crate::machine_types::{
     HardDiskControllerType,
     HardDriveFormat,
     MachineType,
+    ParallelDeviceType,
+    PrinterEmulation,
     SerialControllerType,
     SerialMouseType,
+    SoundType,
 };
 use anyhow::{anyhow, Error};
 use lazy_static::lazy_static;
@@ -47,7 +50,7 @@ use crate::{
     bus::ClockFactor,
     cpu_common::CpuType,
     device_traits::videocard::VideoType,
-    devices::{keyboard::KeyboardType, pit::PitType},
+    devices::{keyboard::KeyboardType, pit::PitType, sn76489::Sn76489Type},
     tracelogger::TraceLogger,
 };
 
@@ -159,12 +162,14 @@ pub struct MachineDescriptor {
     pub bus_factor: ClockFactor, // Specifies the ISA bus speed in either a divisor or multiplier of bus crystal.
     pub timer_divisor: u32,      // Specifies the PIT timer speed in a divisor of timer clock speed.
     pub have_ppi: bool,
+    pub have_pcjr_port_b: bool, // Whether port 0x61 is the PCjr's port B register, with its audio multiplexer.
     pub kb_controller: KbControllerType,
     pub pit_type: PitType,
     pub pic_type: PicType,
     pub dma_type: Option<DmaType>,     // Not all machines have DMA (PCJr)
     pub onboard_serial: Option<u16>,   // Whether the machine has an onboard serial port - and if so, the port base.
     pub onboard_parallel: Option<u16>, // Whether the machine has an onboard parallel port - and if so, the port base.
+    pub onboard_sound: Option<Sn76489Type>, // Whether the machine has an onboard 3-voice sound chip (PCJr, Tandy)
 }
 
 impl Default for MachineDescriptor {
@@ -182,12 +187,14 @@ impl Default for MachineDescriptor {
             bus_factor: ClockFactor::Divisor(1),
             timer_divisor: PIT_DIVISOR,
             have_ppi: true,
+            have_pcjr_port_b: false,
             kb_controller: KbControllerType::Ppi,
             pit_type: PitType::Model8253,
             pic_type: PicType::Single,
             dma_type: Some(DmaType::Single),
             onboard_serial: None,
             onboard_parallel: None,
+            onboard_sound: None,
         }
     }
 }
@@ -229,10 +236,12 @@ lazy_static! {
                     bus_factor: ClockFactor::Divisor(1),
                     timer_divisor: PIT_DIVISOR,
                     have_ppi: false,
+                    have_pcjr_port_b: true,
                     kb_controller: KbControllerType::Ppi,
                     pit_type: PitType::Model8253,
                     pic_type: PicType::Single,
                     dma_type: None,
+                    onboard_sound: Some(Sn76489Type::Sn76496),
                     ..Default::default()
                 },
             ),
@@ -250,12 +259,14 @@ lazy_static! {
                     bus_factor: ClockFactor::Divisor(1),
                     timer_divisor: PIT_DIVISOR,
                     have_ppi: true,
+                    have_pcjr_port_b: false,
                     kb_controller: KbControllerType::Ppi,
                     pit_type: PitType::Model8253,
                     pic_type: PicType::Single,
                     dma_type: Some(DmaType::Single),
                     onboard_serial: None,
                     onboard_parallel: Some(0x378),
+                    onboard_sound: Some(Sn76489Type::Ncr8496),
                 },
             )
         ]);
@@ -315,6 +326,22 @@ pub struct SerialControllerConfig {
     pub port:    Vec<SerialPortConfig>,
 }
 
+#[derive(Clone, Debug, Deserialize)]
+pub struct ParallelPortConfig {
+    pub io_base: Option<u16>,
+    pub irq: Option<u16>,
+    pub device: Option<ParallelDeviceType>,
+    pub printer_emulation: Option<PrinterEmulation>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SoundConfig {
+    #[serde(rename = "type")]
+    pub sound_type: SoundType,
+    pub io_base: Option<u16>,
+    pub irq: Option<u8>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 pub struct FloppyControllerConfig {
     #[serde(rename = "type")]
@@ -370,6 +397,8 @@ pub struct MachineConfiguration {
     pub serial_mouse: Option<SerialMouseConfig>,
     pub video: Vec<VideoCardConfig>,
     pub serial: Vec<SerialControllerConfig>,
+    pub parallel: Vec<ParallelPortConfig>,
+    pub sound: Vec<SoundConfig>,
     pub fdc: Option<FloppyControllerConfig>,
     pub hdc: Option<HardDriveControllerConfig>,
     pub media: Option<MediaConfig>,
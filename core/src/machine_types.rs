@@ -177,6 +177,26 @@ pub enum SerialControllerType {
     IbmAsync,
 }
 
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum ParallelDeviceType {
+    Covox,
+    DisneySoundSource,
+    Printer,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum PrinterEmulation {
+    EpsonEscP,
+    IbmGraphics,
+}
+
+#[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
+pub enum SoundType {
+    AdLib,
+    SoundBlaster,
+    SoundBlaster2,
+}
+
 #[derive(Copy, Clone, Debug, Deserialize, Eq, PartialEq)]
 pub enum SerialMouseType {
     Microsoft,
@@ -0,0 +1,504 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    ---------------------------------------------------------------------------
+
+    savestate.rs
+
+    Implements the binary save state format used to freeze and restore a
+    running Machine.
+
+    A save state begins with a magic string and a format version, followed by
+    a series of tagged sections. Each section records its length so that a
+    mismatch between what a device wrote and what it reads back is detected
+    immediately instead of silently corrupting the state of every device that
+    follows it. All values are stored little-endian.
+
+    Devices implement the SaveState trait. Simple values implement StateValue,
+    which lets devices write fields with StateWriter::put() and read them back
+    with StateReader::get(). Field-less enums can implement StateValue via the
+    impl_state_enum! macro.
+
+    Save states are only valid for the machine configuration that produced
+    them - no attempt is made to migrate state between different machines.
+*/
+
+use std::collections::VecDeque;
+
+use anyhow::{anyhow, bail, Error};
+
+pub const SAVESTATE_MAGIC: &[u8; 8] = b"MARTYSAV";
+pub const SAVESTATE_VERSION: u32 = 6;
+
+/// Devices that can be frozen and restored implement SaveState.
+pub trait SaveState {
+    fn save_state(&self, w: &mut StateWriter);
+    fn load_state(&mut self, r: &mut StateReader) -> Result<(), Error>;
+}
+
+/// A value that can be written to or read from a save state.
+pub trait StateValue: Sized {
+    fn write_state(&self, w: &mut StateWriter);
+    fn read_state(r: &mut StateReader) -> Result<Self, Error>;
+}
+
+#[derive(Default)]
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a writer with a save state header already written.
+    pub fn with_header() -> Self {
+        let mut w = Self::new();
+        w.write_raw(SAVESTATE_MAGIC);
+        w.write_u32(SAVESTATE_VERSION);
+        w
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.buf
+    }
+
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    #[inline]
+    pub fn write_u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    #[inline]
+    pub fn write_u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    #[inline]
+    pub fn write_u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    #[inline]
+    pub fn write_u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// Write a slice of bytes without a length prefix.
+    pub fn write_raw(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Write a length-prefixed slice of bytes. This is much faster than put() for large buffers
+    /// such as system or video memory.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    #[inline]
+    pub fn put<T: StateValue>(&mut self, v: &T) {
+        v.write_state(self);
+    }
+
+    /// Write a tagged section. The section length is patched in once the closure returns.
+    pub fn section<F: FnOnce(&mut Self)>(&mut self, tag: &[u8; 4], f: F) {
+        self.write_raw(tag);
+        let len_pos = self.buf.len();
+        self.write_u32(0);
+        f(self);
+        let len = (self.buf.len() - len_pos - 4) as u32;
+        self.buf[len_pos..len_pos + 4].copy_from_slice(&len.to_le_bytes());
+    }
+
+    /// Write an optional device as a presence flag followed by a tagged section.
+    pub fn device<T: SaveState>(&mut self, tag: &[u8; 4], device: &Option<T>) {
+        self.put(&device.is_some());
+        if let Some(device) = device {
+            self.section(tag, |w| device.save_state(w));
+        }
+    }
+}
+
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    /// Create a reader after validating the save state header.
+    pub fn with_header(buf: &'a [u8]) -> Result<Self, Error> {
+        let mut r = Self::new(buf);
+        if r.read_raw(SAVESTATE_MAGIC.len())? != SAVESTATE_MAGIC {
+            bail!("Not a MartyPC save state.");
+        }
+        let version = r.read_u32()?;
+        if version != SAVESTATE_VERSION {
+            bail!(
+                "Unsupported save state version: {} (expected {})",
+                version,
+                SAVESTATE_VERSION
+            );
+        }
+        Ok(r)
+    }
+
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn read_raw(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.remaining() {
+            bail!("Unexpected end of save state at offset {}", self.pos);
+        }
+        let slice = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(slice)
+    }
+
+    #[inline]
+    pub fn read_u8(&mut self) -> Result<u8, Error> {
+        Ok(self.read_raw(1)?[0])
+    }
+
+    #[inline]
+    pub fn read_u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.read_raw(2)?.try_into()?))
+    }
+
+    #[inline]
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.read_raw(4)?.try_into()?))
+    }
+
+    #[inline]
+    pub fn read_u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.read_raw(8)?.try_into()?))
+    }
+
+    /// Read a length-prefixed slice of bytes.
+    pub fn read_bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.read_u32()? as usize;
+        self.read_raw(len)
+    }
+
+    /// Read a length-prefixed slice of bytes into a fixed size destination. The stored length must
+    /// match the length of the destination.
+    pub fn read_bytes_into(&mut self, dst: &mut [u8]) -> Result<(), Error> {
+        let src = self.read_bytes()?;
+        if src.len() != dst.len() {
+            bail!("Buffer size mismatch: expected {} bytes, got {}", dst.len(), src.len());
+        }
+        dst.copy_from_slice(src);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn get<T: StateValue>(&mut self) -> Result<T, Error> {
+        T::read_state(self)
+    }
+
+    /// Read a tagged section. The closure must consume exactly the number of bytes in the section.
+    pub fn section<F: FnOnce(&mut Self) -> Result<(), Error>>(&mut self, tag: &[u8; 4], f: F) -> Result<(), Error> {
+        let tag_str = String::from_utf8_lossy(tag).to_string();
+        let read_tag = self.read_raw(4)?;
+        if read_tag != tag {
+            bail!(
+                "Expected section '{}' but found '{}'",
+                tag_str,
+                String::from_utf8_lossy(read_tag)
+            );
+        }
+        let len = self.read_u32()? as usize;
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            bail!("Section '{}' is truncated", tag_str);
+        }
+        f(self).map_err(|e| anyhow!("In section '{}': {}", tag_str, e))?;
+        if self.pos != end {
+            bail!(
+                "Section '{}' size mismatch: expected {} bytes, read {}",
+                tag_str,
+                len,
+                len as isize + (self.pos as isize - end as isize)
+            );
+        }
+        Ok(())
+    }
+
+    /// Read an optional device written by StateWriter::device(). The presence of the device must
+    /// match the current machine.
+    pub fn device<T: SaveState>(&mut self, tag: &[u8; 4], device: &mut Option<T>) -> Result<(), Error> {
+        let present: bool = self.get()?;
+        match (present, device) {
+            (true, Some(device)) => self.section(tag, |r| device.load_state(r)),
+            (false, None) => Ok(()),
+            (present, _) => bail!(
+                "Device '{}' is {} in save state but not in machine",
+                String::from_utf8_lossy(tag),
+                if present { "present" } else { "absent" }
+            ),
+        }
+    }
+}
+
+macro_rules! impl_state_value_int {
+    ($($t:ty),*) => {
+        $(
+            impl StateValue for $t {
+                #[inline]
+                fn write_state(&self, w: &mut StateWriter) {
+                    w.write_raw(&self.to_le_bytes());
+                }
+                #[inline]
+                fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+                    Ok(<$t>::from_le_bytes(r.read_raw(std::mem::size_of::<$t>())?.try_into()?))
+                }
+            }
+        )*
+    };
+}
+
+impl_state_value_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, f32, f64);
+
+impl StateValue for usize {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.write_u64(*self as u64);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        Ok(r.read_u64()? as usize)
+    }
+}
+
+impl StateValue for bool {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.write_u8(*self as u8);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        match r.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => bail!("Invalid bool value: {}", b),
+        }
+    }
+}
+
+impl StateValue for String {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.write_bytes(self.as_bytes());
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        Ok(String::from_utf8(r.read_bytes()?.to_vec())?)
+    }
+}
+
+impl<T: StateValue> StateValue for Option<T> {
+    fn write_state(&self, w: &mut StateWriter) {
+        match self {
+            Some(v) => {
+                w.write_u8(1);
+                v.write_state(w);
+            }
+            None => w.write_u8(0),
+        }
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        match r.read_u8()? {
+            0 => Ok(None),
+            1 => Ok(Some(T::read_state(r)?)),
+            b => bail!("Invalid Option tag: {}", b),
+        }
+    }
+}
+
+impl<T: StateValue> StateValue for Vec<T> {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.write_u32(self.len() as u32);
+        for v in self {
+            v.write_state(w);
+        }
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        let len = r.read_u32()? as usize;
+        let mut vec = Vec::with_capacity(len.min(r.remaining()));
+        for _ in 0..len {
+            vec.push(T::read_state(r)?);
+        }
+        Ok(vec)
+    }
+}
+
+impl<T: StateValue> StateValue for VecDeque<T> {
+    fn write_state(&self, w: &mut StateWriter) {
+        w.write_u32(self.len() as u32);
+        for v in self {
+            v.write_state(w);
+        }
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        Ok(VecDeque::from(Vec::<T>::read_state(r)?))
+    }
+}
+
+impl<T: StateValue, const N: usize> StateValue for [T; N] {
+    fn write_state(&self, w: &mut StateWriter) {
+        for v in self {
+            v.write_state(w);
+        }
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        let mut vec = Vec::with_capacity(N);
+        for _ in 0..N {
+            vec.push(T::read_state(r)?);
+        }
+        vec.try_into().map_err(|_| anyhow!("Array length mismatch"))
+    }
+}
+
+impl<A: StateValue, B: StateValue> StateValue for (A, B) {
+    fn write_state(&self, w: &mut StateWriter) {
+        self.0.write_state(w);
+        self.1.write_state(w);
+    }
+    fn read_state(r: &mut StateReader) -> Result<Self, Error> {
+        Ok((A::read_state(r)?, B::read_state(r)?))
+    }
+}
+
+/// Implement StateValue for a field-less enum. Variants are stored as a u8 index in the order
+/// they are listed, so new variants must only ever be appended.
+macro_rules! impl_state_enum {
+    ($t:ident { $($v:ident),+ $(,)? }) => {
+        impl $crate::savestate::StateValue for $t {
+            #[allow(unused_assignments)]
+            fn write_state(&self, w: &mut $crate::savestate::StateWriter) {
+                let mut i: u8 = 0;
+                $(
+                    if let $t::$v = self {
+                        w.write_u8(i);
+                        return;
+                    }
+                    i += 1;
+                )+
+            }
+            #[allow(unused_assignments)]
+            fn read_state(r: &mut $crate::savestate::StateReader) -> Result<Self, anyhow::Error> {
+                let tag = r.read_u8()?;
+                let mut i: u8 = 0;
+                $(
+                    if tag == i {
+                        return Ok($t::$v);
+                    }
+                    i += 1;
+                )+
+                Err(anyhow::anyhow!("Invalid {} value: {}", stringify!($t), tag))
+            }
+        }
+    };
+}
+
+pub(crate) use impl_state_enum;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    enum TestEnum {
+        First,
+        Second,
+        Third,
+    }
+    impl_state_enum!(TestEnum { First, Second, Third });
+
+    #[test]
+    fn test_roundtrip() {
+        let mut w = StateWriter::with_header();
+        w.section(b"TEST", |w| {
+            w.put(&0x1234u16);
+            w.put(&true);
+            w.put(&Some(-5i32));
+            w.put(&vec![1u8, 2, 3]);
+            w.put(&TestEnum::Third);
+            w.put(&1.5f64);
+            w.write_bytes(&[0xAA; 16]);
+        });
+        let data = w.into_inner();
+
+        let mut r = StateReader::with_header(&data).unwrap();
+        r.section(b"TEST", |r| {
+            assert_eq!(r.get::<u16>()?, 0x1234);
+            assert_eq!(r.get::<bool>()?, true);
+            assert_eq!(r.get::<Option<i32>>()?, Some(-5));
+            assert_eq!(r.get::<Vec<u8>>()?, vec![1, 2, 3]);
+            assert_eq!(r.get::<TestEnum>()?, TestEnum::Third);
+            assert_eq!(r.get::<f64>()?, 1.5);
+            let mut buf = [0u8; 16];
+            r.read_bytes_into(&mut buf)?;
+            assert_eq!(buf, [0xAA; 16]);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(r.remaining(), 0);
+    }
+
+    #[test]
+    fn test_section_mismatch() {
+        let mut w = StateWriter::with_header();
+        w.section(b"TEST", |w| {
+            w.put(&0u32);
+        });
+        let data = w.into_inner();
+
+        // Reading too little of a section is an error.
+        let mut r = StateReader::with_header(&data).unwrap();
+        assert!(r
+            .section(b"TEST", |r| {
+                r.get::<u16>()?;
+                Ok(())
+            })
+            .is_err());
+
+        // So is the wrong tag.
+        let mut r = StateReader::with_header(&data).unwrap();
+        assert!(r.section(b"NOPE", |_| Ok(())).is_err());
+
+        // And a bad header.
+        let mut header = SAVESTATE_MAGIC.to_vec();
+        header.extend_from_slice(&(SAVESTATE_VERSION + 1).to_le_bytes());
+        assert!(StateReader::with_header(&header).is_err());
+    }
+}
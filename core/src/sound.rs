@@ -26,12 +26,16 @@
 
     sound.rs
 
-    Implement the sound player interface.
+    Implement the sound player interface, and the mixer that combines the
+    output of the machine's sound sources into the stereo stream the sound
+    player consumes.
 
 */
 
 #![allow(dead_code)]
 
+use std::collections::VecDeque;
+
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use ringbuf::{
     Producer,
@@ -49,6 +53,212 @@ pub const BUFFER_MS: f32 = 100.0;
 #[cfg(not(target_arch = "wasm32"))]
 pub const BUFFER_MS: f32 = 30.0;
 
+/// Sample rate used when there is no sound player to set one.
+pub const DEFAULT_SAMPLE_RATE: u32 = 44000;
+
+/// The mixer output is interleaved stereo.
+pub const MIXER_CHANNELS: usize = 2;
+/// The longest a source may queue samples ahead of the mixer before the oldest are dropped.
+pub const MAX_SOURCE_BUFFER_MS: f32 = 500.0;
+
+/// Identifies a source registered with a SoundMixer.
+pub type SoundSourceId = usize;
+
+/// The settings of a mixer source, for display.
+#[derive(Clone, Debug)]
+pub struct SoundSourceInfo {
+    pub name: String,
+    pub sample_rate: u32,
+    pub volume: f32,
+    pub pan: f32,
+    pub muted: bool,
+}
+
+/// A stream of mono samples at its own sample rate, resampled to the mixer's rate by linear
+/// interpolation. Sources should produce samples at a rate close to the output rate; a source
+/// at a much higher rate should filter itself first, as interpolation doesn't.
+struct SoundSource {
+    name: String,
+    sample_rate: u32,
+    volume: f32,
+    /// -1.0 (left) to 1.0 (right)
+    pan: f32,
+    muted: bool,
+    buffer: VecDeque<f32>,
+    max_buffer: usize,
+    /// Input samples consumed per output frame.
+    step: f64,
+    /// Position of the output frame between the 'prev' and 'next' input samples.
+    phase: f64,
+    prev: f32,
+    next: f32,
+}
+
+impl SoundSource {
+    /// Produce the source's next sample at the output rate. If the source has fallen behind, its
+    /// last sample is held; the source then runs one sample further ahead of the mixer.
+    fn next_sample(&mut self) -> f32 {
+        while self.phase >= 1.0 {
+            self.prev = self.next;
+            if let Some(sample) = self.buffer.pop_front() {
+                self.next = sample;
+            }
+            self.phase -= 1.0;
+        }
+        let sample = self.prev + (self.next - self.prev) * self.phase as f32;
+        self.phase += self.step;
+        sample
+    }
+
+    /// Return the left and right gain of the source. Center pan plays at full volume on both
+    /// channels, so a mono source sounds the same as it did through a mono output.
+    fn gains(&self) -> (f32, f32) {
+        if self.muted {
+            return (0.0, 0.0);
+        }
+        let left = (1.0 - self.pan).min(1.0);
+        let right = (1.0 + self.pan).min(1.0);
+        (left * self.volume, right * self.volume)
+    }
+}
+
+/// Mixes any number of sound sources into a single stereo stream. The mixer is clocked by
+/// emulated time via run(), so the output stays in step with the machine regardless of host
+/// speed.
+pub struct SoundMixer {
+    sample_rate: u32,
+    sources: Vec<SoundSource>,
+    master_volume: f32,
+    /// Output frames owed for the time elapsed, including a fractional part carried between runs.
+    frames_due: f64,
+    output: Vec<f32>,
+}
+
+impl SoundMixer {
+    pub fn new(sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            sources: Vec::new(),
+            master_volume: 1.0,
+            frames_due: 0.0,
+            output: Vec::new(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Register a new source producing mono samples at the specified rate.
+    pub fn add_source(&mut self, name: &str, sample_rate: u32) -> SoundSourceId {
+        self.sources.push(SoundSource {
+            name: name.to_string(),
+            sample_rate,
+            volume: 1.0,
+            pan: 0.0,
+            muted: false,
+            buffer: VecDeque::new(),
+            max_buffer: (sample_rate as f32 * MAX_SOURCE_BUFFER_MS / 1000.0) as usize,
+            step: sample_rate as f64 / self.sample_rate as f64,
+            phase: 0.0,
+            prev: 0.0,
+            next: 0.0,
+        });
+        self.sources.len() - 1
+    }
+
+    pub fn queue_sample(&mut self, id: SoundSourceId, sample: f32) {
+        if let Some(source) = self.sources.get_mut(id) {
+            if source.buffer.len() >= source.max_buffer {
+                source.buffer.pop_front();
+            }
+            source.buffer.push_back(sample);
+        }
+    }
+
+    pub fn queue_samples(&mut self, id: SoundSourceId, samples: &[f32]) {
+        for sample in samples {
+            self.queue_sample(id, *sample);
+        }
+    }
+
+    pub fn set_volume(&mut self, id: SoundSourceId, volume: f32) {
+        if let Some(source) = self.sources.get_mut(id) {
+            source.volume = volume.max(0.0);
+        }
+    }
+
+    pub fn set_pan(&mut self, id: SoundSourceId, pan: f32) {
+        if let Some(source) = self.sources.get_mut(id) {
+            source.pan = pan.clamp(-1.0, 1.0);
+        }
+    }
+
+    pub fn set_muted(&mut self, id: SoundSourceId, muted: bool) {
+        if let Some(source) = self.sources.get_mut(id) {
+            source.muted = muted;
+        }
+    }
+
+    pub fn set_master_volume(&mut self, volume: f32) {
+        self.master_volume = volume.max(0.0);
+    }
+
+    pub fn master_volume(&self) -> f32 {
+        self.master_volume
+    }
+
+    pub fn sources(&self) -> Vec<SoundSourceInfo> {
+        self.sources
+            .iter()
+            .map(|source| SoundSourceInfo {
+                name: source.name.clone(),
+                sample_rate: source.sample_rate,
+                volume: source.volume,
+                pan: source.pan,
+                muted: source.muted,
+            })
+            .collect()
+    }
+
+    /// Advance the mixer by the specified number of microseconds of emulated time, mixing the
+    /// output frames that fall within it.
+    pub fn run(&mut self, us: f64) {
+        self.frames_due += us * self.sample_rate as f64 / 1_000_000.0;
+        let frames = self.frames_due as usize;
+        if frames > 0 {
+            self.frames_due -= frames as f64;
+            self.mix(frames);
+        }
+    }
+
+    /// Mix the specified number of output frames from all sources. Muted sources are still
+    /// consumed, so they stay in step with the others.
+    pub fn mix(&mut self, frames: usize) {
+        self.output.reserve(frames * MIXER_CHANNELS);
+        for _ in 0..frames {
+            let (mut left, mut right) = (0.0, 0.0);
+            for source in self.sources.iter_mut() {
+                let sample = source.next_sample();
+                let (left_gain, right_gain) = source.gains();
+                left += sample * left_gain;
+                right += sample * right_gain;
+            }
+            self.output.push(left * self.master_volume);
+            self.output.push(right * self.master_volume);
+        }
+    }
+
+    /// Return the interleaved stereo frames mixed since the output was last cleared.
+    pub fn output(&self) -> &[f32] {
+        &self.output
+    }
+
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+}
+
 pub struct SoundPlayer {
     audio_device: cpal::Device,
     //audio_config_s: cpal::SupportedStreamConfig,
@@ -94,9 +304,10 @@ impl SoundPlayer {
         let sample_rate = config.sample_rate().0;
         let channels = config.channels() as usize;
 
-        let min_buffer = ((BUFFER_MS / 1000.0) / (1.0 / sample_rate as f32)) as usize;
+        // The buffer holds interleaved stereo frames from the mixer.
+        let min_buffer = ((BUFFER_MS / 1000.0) / (1.0 / sample_rate as f32)) as usize * MIXER_CHANNELS;
         //log::trace!("Minimum sample buffer size: {}", min_buffer);
-        let buffer_size = (sample_rate as f32 * (BUFFER_MS as f32 / 1000.0)) as usize;
+        let buffer_size = (sample_rate as f32 * (BUFFER_MS as f32 / 1000.0)) as usize * MIXER_CHANNELS;
         let buffer = RingBuffer::new(buffer_size as usize);
         let (buffer_producer, mut buffer_consumer) = buffer.split();
 
@@ -117,23 +328,23 @@ impl SoundPlayer {
 
             if refill_buffer {
                 if buffer_consumer.len() < min_buffer {
-                    return 0.0;
+                    return (0.0, 0.0);
                 }
                 else {
                     refill_buffer = false;
                 }
             }
 
-            let sample: f32 = match buffer_consumer.pop() {
-                Some(s) => s,
-                None => {
+            let frame = match (buffer_consumer.pop(), buffer_consumer.pop()) {
+                (Some(left), Some(right)) => (left, right),
+                _ => {
                     //log::trace!("Buffer underrun");
                     refill_buffer = true;
-                    0.0
+                    (0.0, 0.0)
                 }
             };
             //debug_snd_file.write(&s.to_be_bytes());
-            sample
+            frame
         };
 
         let output_stream = audio_device
@@ -162,15 +373,12 @@ impl SoundPlayer {
         self.output_stream.play().unwrap();
     }
 
-    pub fn queue_sample(&mut self, data: f32) {
-        match self.buffer_producer.push(data) {
-            Ok(_) => {}
-            Err(_) => {}
-        }
-    }
-
-    pub fn queue_sample_slice(&mut self, data: &[f32]) {
-        self.buffer_producer.push_slice(data);
+    /// Queue interleaved stereo frames for playback. Frames that don't fit in the buffer are
+    /// dropped whole, so the channels stay aligned.
+    pub fn queue_frames(&mut self, data: &[f32]) {
+        let room = self.buffer_producer.remaining() / MIXER_CHANNELS * MIXER_CHANNELS;
+        let len = data.len().min(room);
+        self.buffer_producer.push_slice(&data[..len]);
     }
 
     pub fn sample_rate(&self) -> u32 {
@@ -178,15 +386,61 @@ impl SoundPlayer {
     }
 }
 
-fn write_data<T>(output: &mut [T], channels: usize, next_sample: &mut dyn FnMut() -> f32)
+/// Write stereo frames to the output device. A mono device receives the average of the two
+/// channels; on devices with more than two channels, the extra channels are silent.
+fn write_data<T>(output: &mut [T], channels: usize, next_frame: &mut dyn FnMut() -> (f32, f32))
 where
     T: cpal::Sample,
 {
     for frame in output.chunks_mut(channels) {
-        let value: T = cpal::Sample::from::<f32>(&next_sample());
+        let (left, right) = next_frame();
 
-        for sample in frame.iter_mut() {
-            *sample = value;
+        if channels == 1 {
+            frame[0] = cpal::Sample::from::<f32>(&((left + right) / 2.0));
+            continue;
+        }
+        for (i, sample) in frame.iter_mut().enumerate() {
+            let value = match i {
+                0 => left,
+                1 => right,
+                _ => 0.0,
+            };
+            *sample = cpal::Sample::from::<f32>(&value);
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixer_resample_and_pan() {
+        let mut mixer = SoundMixer::new(44100);
+        let half = mixer.add_source("half", 22050);
+        let full = mixer.add_source("full", 44100);
+        mixer.set_pan(half, -1.0);
+        mixer.set_pan(full, 1.0);
+
+        mixer.queue_samples(half, &[1.0, 1.0, 0.0, 0.0]);
+        mixer.queue_samples(full, &[0.5; 8]);
+
+        // One millisecond at 44.1kHz is 44.1 frames.
+        mixer.run(1000.0);
+        assert_eq!(mixer.output().len(), 44 * MIXER_CHANNELS);
+
+        // Left has only the half rate source, interpolated between its samples after the
+        // interpolator's initial latency.
+        let left: Vec<f32> = mixer.output().iter().step_by(2).copied().collect();
+        assert_eq!(&left[0..8], &[0.0, 0.0, 0.0, 0.5, 1.0, 1.0, 1.0, 0.5]);
+        // Right has only the full rate source.
+        let right: Vec<f32> = mixer.output().iter().skip(1).step_by(2).copied().collect();
+        assert_eq!(&right[0..3], &[0.0, 0.0, 0.5]);
+
+        mixer.clear_output();
+        mixer.set_muted(full, true);
+        mixer.queue_samples(full, &[0.5; 4]);
+        mixer.mix(2);
+        assert_eq!(mixer.output()[1], 0.0);
+    }
+}
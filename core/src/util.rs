@@ -30,6 +30,8 @@
 */
 
 #![allow(dead_code)]
+use std::time::{SystemTime, UNIX_EPOCH};
+
 pub fn relative_offset_u32(base: u32, offset: i32) -> u32 {
     base.wrapping_add(offset as u32)
 }
@@ -55,6 +57,25 @@ pub fn fmt_byte_array(bytes: &[u8]) -> String {
     fmt_str
 }
 
+/// Convert a SystemTime to a civil date and time as (year, month, day, hour, minute, second) in UTC.
+pub fn civil_time(time: SystemTime) -> (u32, u32, u32, u32, u32, u32) {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, time) = ((secs / 86_400) as i64, (secs % 86_400) as u32);
+
+    // Convert days since the epoch to a civil date
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = (if mp < 10 { mp + 3 } else { mp - 9 }) as u32;
+    let year = (yoe + era * 400 + if month <= 2 { 1 } else { 0 }) as u32;
+
+    (year, month, day, time / 3600, (time / 60) % 60, time % 60)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -28,6 +28,12 @@
 
     Implements VHD support including reading and writing to VHD images.
 
+    Fixed, dynamic and differencing VHDs are supported. A dynamic VHD
+    stores its data in sparse blocks located through a Block Allocation
+    Table (BAT). A differencing VHD is a dynamic VHD that records only the
+    sectors written to it; unwritten sectors are read from a parent image
+    found via the parent locators in the dynamic disk header.
+
 */
 
 use core::fmt::Display;
@@ -37,7 +43,9 @@ use std::{
     fs,
     fs::File,
     io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
     str,
+    time::{SystemTime, UNIX_EPOCH},
 };
 
 use anyhow::{bail, Context, Result};
@@ -46,6 +54,7 @@ use uuid::Uuid;
 use crate::{
     bytebuf::{ByteBuf, ByteBufWriter},
     devices::hdc::SECTOR_SIZE,
+    hdd_image::DiskBackend,
 };
 
 pub const VHD_FOOTER_LEN: usize = 512;
@@ -55,6 +64,26 @@ pub const VHD_DATA_OFFSET: u64 = 0xFFFFFFFFFFFFFFFF;
 pub const VHD_FEATURE_RESERVED: u32 = 0x02;
 pub const VHD_CHECKSUM_OFFSET: usize = 64;
 pub const VHD_DISK_TYPE: u32 = 0x02;
+pub const VHD_DISK_TYPE_DYNAMIC: u32 = 0x03;
+pub const VHD_DISK_TYPE_DIFFERENCING: u32 = 0x04;
+
+pub const VHD_DYNAMIC_HEADER_LEN: usize = 1024;
+pub const VHD_DYNAMIC_HEADER_VERSION: u32 = 0x00010000;
+pub const VHD_DYNAMIC_CHECKSUM_OFFSET: usize = 36;
+pub const VHD_DEFAULT_BLOCK_SIZE: u32 = 0x200000;
+pub const VHD_BAT_ENTRY_UNUSED: u32 = 0xFFFFFFFF;
+pub const VHD_PARENT_NAME_LEN: usize = 512;
+pub const VHD_PARENT_LOCATOR_CT: usize = 8;
+/// Parent locator platform codes.
+pub const VHD_PLATFORM_W2RU: u32 = 0x57327275; // 'W2ru': Relative path, UTF-16LE
+pub const VHD_PLATFORM_W2KU: u32 = 0x57326B75; // 'W2ku': Absolute path, UTF-16LE
+pub const VHD_PLATFORM_MACX: u32 = 0x4D616358; // 'MacX': File URL, UTF-8
+/// Locator data longer than this is assumed to be corrupt.
+pub const VHD_MAX_LOCATOR_LEN: u32 = 0x10000;
+/// Limit on the length of a chain of differencing disks, to guard against cycles.
+pub const VHD_MAX_PARENT_DEPTH: usize = 16;
+/// Seconds between the Unix epoch and the VHD epoch of January 1, 2000 UTC.
+pub const VHD_EPOCH_OFFSET: u64 = 946684800;
 
 #[derive(Debug)]
 pub enum VirtualHardDiskError {
@@ -64,6 +93,9 @@ pub enum VirtualHardDiskError {
     InvalidVersion,
     InvalidType,
     InvalidSeek,
+    InvalidHeader,
+    ParentNotFound,
+    ParentMismatch,
 }
 impl Error for VirtualHardDiskError {}
 impl Display for VirtualHardDiskError {
@@ -84,6 +116,15 @@ impl Display for VirtualHardDiskError {
             VirtualHardDiskError::InvalidSeek => {
                 write!(f, "An IO operation was requested out of bounds.")
             }
+            VirtualHardDiskError::InvalidHeader => {
+                write!(f, "The VHD dynamic disk header was invalid or contained an invalid value.")
+            }
+            VirtualHardDiskError::ParentNotFound => {
+                write!(f, "The parent image of the differencing VHD could not be found.")
+            }
+            VirtualHardDiskError::ParentMismatch => {
+                write!(f, "The parent image of the differencing VHD does not match its recorded ID.")
+            }
         }
     }
 }
@@ -103,6 +144,9 @@ pub struct VirtualHardDisk {
     cur_cylinder: u32,
     cur_head: u32,
     cur_sector: u32,
+
+    dynamic: Option<VHDDynamicDisk>,
+    parent:  Option<Box<VirtualHardDisk>>,
 }
 
 #[derive(Default)]
@@ -166,13 +210,13 @@ impl VHDFileFooter {
     }
 
     /// Write the fields of a VHD footer into the specified buffer which should be 512 bytes long.
-    fn make_vhd_footer_bytes(buf: &mut [u8], footer: VHDFileFooter) {
+    fn make_vhd_footer_bytes(buf: &mut [u8], footer: &VHDFileFooter) {
         {
             let mut bytebuf = ByteBufWriter::from_slice(buf);
             bytebuf.write_bytes("conectix".as_bytes(), 8).unwrap();
             bytebuf.write_u32_be(footer.features).unwrap();
             bytebuf.write_u32_be(footer.version).unwrap();
-            bytebuf.write_u64_be(VHD_DATA_OFFSET).unwrap();
+            bytebuf.write_u64_be(footer.offset).unwrap();
             bytebuf.write_u32_be(footer.timestamp).unwrap();
             bytebuf.write_bytes(&footer.creator_app, 4).unwrap();
             bytebuf.write_u32_be(footer.creator_version).unwrap();
@@ -219,9 +263,6 @@ impl VHDFileFooter {
         }
 
         footer.offset = bytebuf.read_u64_be()?;
-        if footer.offset != 0xFFFFFFFFFFFFFFFFu64 {
-            bail!(VirtualHardDiskError::InvalidFooter);
-        }
 
         footer.timestamp = bytebuf.read_u32_be()?;
 
@@ -252,9 +293,21 @@ impl VHDFileFooter {
         );
 
         footer.disk_type = bytebuf.read_u32_be()?;
-        if footer.disk_type != 0x02 {
-            bail!(VirtualHardDiskError::InvalidType);
+        match footer.disk_type {
+            VHD_DISK_TYPE => {
+                // Fixed disks have no dynamic disk header.
+                if footer.offset != VHD_DATA_OFFSET {
+                    bail!(VirtualHardDiskError::InvalidFooter);
+                }
+            }
+            VHD_DISK_TYPE_DYNAMIC | VHD_DISK_TYPE_DIFFERENCING => {
+                if footer.offset == VHD_DATA_OFFSET {
+                    bail!(VirtualHardDiskError::InvalidFooter);
+                }
+            }
+            _ => bail!(VirtualHardDiskError::InvalidType),
         }
+        log::info!("VHD Type: {}", footer.disk_type);
 
         footer.checksum = bytebuf.read_u32_be()?;
 
@@ -274,23 +327,331 @@ impl VHDFileFooter {
     }
 
     fn calculate_footer_checksum(buf: &[u8]) -> u32 {
-        let mut sum: u32 = 0;
+        // Skip checksum field
+        let sum = buf[..VHD_CHECKSUM_OFFSET]
+            .iter()
+            .chain(&buf[(VHD_CHECKSUM_OFFSET + 4)..VHD_FOOTER_LEN])
+            .fold(0u32, |sum, byte| sum.wrapping_add(*byte as u32));
+
+        // Return one's compliment of sum
+        !sum
+    }
+}
 
-        for i in 0..VHD_CHECKSUM_OFFSET {
-            sum += buf[i] as u32;
+/// A parent locator entry from a dynamic disk header. Locators describe where to find the parent
+/// of a differencing disk; the locator data itself is stored elsewhere in the file.
+#[derive(Copy, Clone, Default)]
+pub struct VHDParentLocator {
+    platform: u32,
+    data_space: u32,
+    data_len: u32,
+    data_offset: u64,
+}
+
+impl VHDParentLocator {
+    /// Read this locator's data from the VHD and decode it into a path. Relative paths are resolved
+    /// against `dir`, the directory containing the VHD.
+    fn read_path(&self, vhd_file: &mut File, dir: &Path) -> Result<Option<PathBuf>, anyhow::Error> {
+        if self.data_len == 0 || self.data_len > VHD_MAX_LOCATOR_LEN {
+            return Ok(None);
         }
-        // Skip checksum field
-        for i in (VHD_CHECKSUM_OFFSET + 4)..VHD_FOOTER_LEN {
-            sum += buf[i] as u32;
+
+        let mut buf = vec![0u8; self.data_len as usize];
+        vhd_file.seek(SeekFrom::Start(self.data_offset))?;
+        vhd_file.read_exact(&mut buf).context("Error reading VHD parent locator")?;
+
+        let path_str = match self.platform {
+            VHD_PLATFORM_W2RU | VHD_PLATFORM_W2KU => {
+                let utf16: Vec<u16> = buf.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+                String::from_utf16_lossy(&utf16)
+            }
+            VHD_PLATFORM_MACX => {
+                let url = String::from_utf8_lossy(&buf);
+                url.trim_start_matches("file://").to_string()
+            }
+            _ => return Ok(None),
+        };
+
+        // Locators are typically written on Windows hosts, so normalize the path separators.
+        let path = PathBuf::from(path_str.trim_end_matches('\0').replace('\\', "/"));
+        if self.platform == VHD_PLATFORM_W2RU {
+            Ok(Some(dir.join(path)))
+        }
+        else {
+            Ok(Some(path))
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct VHDDynamicHeader {
+    table_offset: u64,
+    max_table_entries: u32,
+    block_size: u32,
+    checksum: u32,
+    parent_uuid: Uuid,
+    parent_timestamp: u32,
+    parent_name: String,
+    parent_locators: [VHDParentLocator; VHD_PARENT_LOCATOR_CT],
+}
+
+impl VHDDynamicHeader {
+    /// Write the fields of a dynamic disk header into the specified buffer which should be 1024
+    /// bytes long.
+    fn make_dynamic_header_bytes(buf: &mut [u8], header: &VHDDynamicHeader) {
+        {
+            let mut bytebuf = ByteBufWriter::from_slice(buf);
+            bytebuf.write_bytes("cxsparse".as_bytes(), 8).unwrap();
+            bytebuf.write_u64_be(VHD_DATA_OFFSET).unwrap();
+            bytebuf.write_u64_be(header.table_offset).unwrap();
+            bytebuf.write_u32_be(VHD_DYNAMIC_HEADER_VERSION).unwrap();
+            bytebuf.write_u32_be(header.max_table_entries).unwrap();
+            bytebuf.write_u32_be(header.block_size).unwrap();
+            bytebuf.write_u32_be(0).unwrap(); // Checksum calculated later
+            bytebuf.write_bytes(&header.parent_uuid.into_bytes(), 16).unwrap();
+            bytebuf.write_u32_be(header.parent_timestamp).unwrap();
+            bytebuf.write_u32_be(0).unwrap();
+
+            // The parent name is stored as big-endian UTF-16.
+            let mut name_buf = [0u8; VHD_PARENT_NAME_LEN];
+            for (i, c) in header
+                .parent_name
+                .encode_utf16()
+                .take(VHD_PARENT_NAME_LEN / 2 - 1)
+                .enumerate()
+            {
+                name_buf[i * 2..i * 2 + 2].copy_from_slice(&c.to_be_bytes());
+            }
+            bytebuf.write_bytes(&name_buf, VHD_PARENT_NAME_LEN).unwrap();
+
+            for locator in header.parent_locators.iter() {
+                bytebuf.write_u32_be(locator.platform).unwrap();
+                bytebuf.write_u32_be(locator.data_space).unwrap();
+                bytebuf.write_u32_be(locator.data_len).unwrap();
+                bytebuf.write_u32_be(0).unwrap();
+                bytebuf.write_u64_be(locator.data_offset).unwrap();
+            }
         }
+        let checksum = VHDDynamicHeader::calculate_header_checksum(buf);
+
+        let mut bytebuf = ByteBufWriter::from_slice(buf);
+        bytebuf.seek(VHD_DYNAMIC_CHECKSUM_OFFSET).unwrap();
+        bytebuf.write_u32_be(checksum).unwrap();
+    }
+
+    /// Parse the dynamic disk header of a dynamic or differencing VHD.
+    fn parse_dynamic_header(buf: &[u8]) -> Result<VHDDynamicHeader, anyhow::Error> {
+        let mut header = VHDDynamicHeader::default();
+        let mut bytebuf = ByteBuf::from_slice(buf);
+
+        let mut cookie = [0u8; 8];
+        bytebuf.read_bytes(&mut cookie, 8)?;
+        if cookie != "cxsparse".as_bytes() {
+            bail!(VirtualHardDiskError::InvalidHeader);
+        }
+
+        // The data offset field is unused and should be all 1's.
+        _ = bytebuf.read_u64_be()?;
+        header.table_offset = bytebuf.read_u64_be()?;
+
+        if bytebuf.read_u32_be()? != VHD_DYNAMIC_HEADER_VERSION {
+            bail!(VirtualHardDiskError::InvalidVersion);
+        }
+
+        header.max_table_entries = bytebuf.read_u32_be()?;
+        header.block_size = bytebuf.read_u32_be()?;
+        log::info!(
+            "VHD Dynamic disk: {} blocks of {} bytes",
+            header.max_table_entries,
+            header.block_size
+        );
+
+        header.checksum = bytebuf.read_u32_be()?;
+        if header.checksum != VHDDynamicHeader::calculate_header_checksum(buf) {
+            log::warn!("VHD Dynamic header checksum incorrect");
+        }
+
+        let mut uuid_buf: [u8; 16] = [0; 16];
+        bytebuf.read_bytes(&mut uuid_buf, 16)?;
+        header.parent_uuid = uuid::Builder::from_bytes(uuid_buf).into_uuid();
+        header.parent_timestamp = bytebuf.read_u32_be()?;
+        _ = bytebuf.read_u32_be()?;
+
+        let mut name_buf = [0u8; VHD_PARENT_NAME_LEN];
+        bytebuf.read_bytes(&mut name_buf, VHD_PARENT_NAME_LEN)?;
+        let name_utf16: Vec<u16> = name_buf
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .take_while(|c| *c != 0)
+            .collect();
+        header.parent_name = String::from_utf16_lossy(&name_utf16);
+
+        for locator in header.parent_locators.iter_mut() {
+            locator.platform = bytebuf.read_u32_be()?;
+            locator.data_space = bytebuf.read_u32_be()?;
+            locator.data_len = bytebuf.read_u32_be()?;
+            _ = bytebuf.read_u32_be()?;
+            locator.data_offset = bytebuf.read_u64_be()?;
+        }
+
+        Ok(header)
+    }
+
+    fn calculate_header_checksum(buf: &[u8]) -> u32 {
+        // Skip checksum field
+        let sum = buf[..VHD_DYNAMIC_CHECKSUM_OFFSET]
+            .iter()
+            .chain(&buf[(VHD_DYNAMIC_CHECKSUM_OFFSET + 4)..VHD_DYNAMIC_HEADER_LEN])
+            .fold(0u32, |sum, byte| sum.wrapping_add(*byte as u32));
 
-        // Return one's compliment of sum
         !sum
     }
 }
 
+/// State for a dynamic or differencing VHD: the dynamic disk header and the Block Allocation Table.
+///
+/// Each allocated block begins with a sector bitmap, padded to a sector boundary, with one bit per
+/// sector in the block (MSB first). A set bit means the sector's data is present in this image.
+struct VHDDynamicDisk {
+    header: VHDDynamicHeader,
+    bat: Vec<u32>,
+    bitmap_len: u64,
+    /// File offset at which the next block will be allocated. The footer is moved past each new block.
+    next_block: u64,
+}
+
+impl VHDDynamicDisk {
+    fn load(vhd_file: &mut File, footer: &VHDFileFooter, file_len: u64) -> Result<VHDDynamicDisk, anyhow::Error> {
+        let mut header_buf = vec![0u8; VHD_DYNAMIC_HEADER_LEN];
+        vhd_file.seek(SeekFrom::Start(footer.offset))?;
+        vhd_file
+            .read_exact(&mut header_buf)
+            .context("Error reading VHD dynamic disk header")?;
+
+        let header = VHDDynamicHeader::parse_dynamic_header(&header_buf)?;
+
+        if header.block_size == 0 || header.block_size % VHD_SECTOR_SIZE as u32 != 0 {
+            bail!(VirtualHardDiskError::InvalidHeader);
+        }
+        // The BAT must be able to address the entire disk.
+        if header.max_table_entries as u64 * (header.block_size as u64) < footer.current_size {
+            bail!(VirtualHardDiskError::InvalidHeader);
+        }
+
+        let bat_len = header.max_table_entries as u64 * 4;
+        let bat_end = match header.table_offset.checked_add(bat_len) {
+            Some(bat_end) if bat_end <= file_len => bat_end,
+            _ => bail!(VirtualHardDiskError::InvalidLength),
+        };
+
+        let mut bat_buf = vec![0u8; bat_len as usize];
+        vhd_file.seek(SeekFrom::Start(header.table_offset))?;
+        vhd_file.read_exact(&mut bat_buf).context("Error reading VHD BAT")?;
+
+        let bat: Vec<u32> = bat_buf
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+            .collect();
+
+        let sectors_per_block = header.block_size as u64 / VHD_SECTOR_SIZE as u64;
+        let bitmap_len = round_to_sector(sectors_per_block.div_ceil(8));
+
+        // New blocks go where the footer currently is, or past any structure that extends beyond it.
+        let mut next_block = round_to_sector(file_len.saturating_sub(VHD_FOOTER_LEN as u64));
+        next_block = next_block.max(round_to_sector(bat_end));
+        for entry in bat.iter().filter(|e| **e != VHD_BAT_ENTRY_UNUSED) {
+            next_block = next_block
+                .max(*entry as u64 * VHD_SECTOR_SIZE as u64 + bitmap_len + header.block_size as u64);
+        }
+
+        Ok(VHDDynamicDisk {
+            header,
+            bat,
+            bitmap_len,
+            next_block,
+        })
+    }
+
+    /// Return the block index and the sector index within the block for a byte offset.
+    fn locate(&self, offset: u64) -> Result<(usize, u64), anyhow::Error> {
+        let block = (offset / self.header.block_size as u64) as usize;
+        if block >= self.bat.len() {
+            bail!(VirtualHardDiskError::InvalidSeek);
+        }
+        let block_sector = (offset % self.header.block_size as u64) / VHD_SECTOR_SIZE as u64;
+        Ok((block, block_sector))
+    }
+
+    /// Return the file offset of the specified block, if it has been allocated.
+    fn block_offset(&self, block: usize) -> Option<u64> {
+        match self.bat[block] {
+            VHD_BAT_ENTRY_UNUSED => None,
+            entry => Some(entry as u64 * VHD_SECTOR_SIZE as u64),
+        }
+    }
+
+    fn sector_offset(&self, block_offset: u64, block_sector: u64) -> u64 {
+        block_offset + self.bitmap_len + block_sector * VHD_SECTOR_SIZE as u64
+    }
+
+    fn is_sector_present(&self, vhd_file: &mut File, block_offset: u64, block_sector: u64) -> Result<bool, anyhow::Error> {
+        let mut bitmap_byte = [0u8; 1];
+        vhd_file.seek(SeekFrom::Start(block_offset + block_sector / 8))?;
+        vhd_file.read_exact(&mut bitmap_byte)?;
+        Ok(bitmap_byte[0] & (0x80 >> (block_sector % 8)) != 0)
+    }
+
+    fn mark_sector_present(&self, vhd_file: &mut File, block_offset: u64, block_sector: u64) -> Result<(), anyhow::Error> {
+        let mut bitmap_byte = [0u8; 1];
+        vhd_file.seek(SeekFrom::Start(block_offset + block_sector / 8))?;
+        vhd_file.read_exact(&mut bitmap_byte)?;
+        bitmap_byte[0] |= 0x80 >> (block_sector % 8);
+        vhd_file.seek(SeekFrom::Start(block_offset + block_sector / 8))?;
+        vhd_file.write_all(&bitmap_byte)?;
+        Ok(())
+    }
+
+    /// Allocate a new, empty block at the end of the file and record it in the BAT. The footer is
+    /// rewritten after the new block. Returns the file offset of the block.
+    fn allocate_block(&mut self, vhd_file: &mut File, footer: &VHDFileFooter, block: usize) -> Result<u64, anyhow::Error> {
+        let block_offset = self.next_block;
+        log::debug!("Allocating VHD block {} at offset {:X}", block, block_offset);
+
+        let block_len = (self.bitmap_len + self.header.block_size as u64) as usize;
+        let mut block_buf = vec![0u8; block_len + VHD_FOOTER_LEN];
+        VHDFileFooter::make_vhd_footer_bytes(&mut block_buf[block_len..], footer);
+
+        vhd_file.seek(SeekFrom::Start(block_offset))?;
+        vhd_file
+            .write_all(&block_buf)
+            .context("Error writing new block to VHD")?;
+
+        let entry = (block_offset / VHD_SECTOR_SIZE as u64) as u32;
+        vhd_file.seek(SeekFrom::Start(self.header.table_offset + block as u64 * 4))?;
+        vhd_file
+            .write_all(&entry.to_be_bytes())
+            .context("Error updating VHD BAT")?;
+
+        self.bat[block] = entry;
+        self.next_block = block_offset + self.bitmap_len + self.header.block_size as u64;
+        Ok(block_offset)
+    }
+}
+
 impl VirtualHardDisk {
-    pub fn from_file(mut vhd_file: File) -> Result<VirtualHardDisk, anyhow::Error> {
+    /// Open a VHD from a file. Differencing VHDs cannot be opened this way, as their parent image
+    /// is located relative to the VHD's path. Use [VirtualHardDisk::from_file_with_path] instead.
+    pub fn from_file(vhd_file: File) -> Result<VirtualHardDisk, anyhow::Error> {
+        VirtualHardDisk::open(vhd_file, None, 0)
+    }
+
+    /// Open a VHD from a file, given the path it was opened from. If the VHD is a differencing VHD,
+    /// its parent is located and opened read-only.
+    pub fn from_file_with_path(vhd_file: File, path: &Path) -> Result<VirtualHardDisk, anyhow::Error> {
+        VirtualHardDisk::open(vhd_file, Some(path), 0)
+    }
+
+    fn open(mut vhd_file: File, path: Option<&Path>, depth: usize) -> Result<VirtualHardDisk, anyhow::Error> {
         let metadata = vhd_file.metadata().context("Failed to read VHD file metadata")?;
         // Check that the file is long enough to even read the footer in. Such a small file will fail
         // for other reasons later such as not containing the proper chs
@@ -304,7 +665,37 @@ impl VirtualHardDisk {
         // Read in the entire footer
         vhd_file.read_exact(&mut trailer_buf)?;
 
-        let footer = VHDFileFooter::parse_vhd_footer(&mut trailer_buf)?;
+        let footer = match VHDFileFooter::parse_vhd_footer(&trailer_buf) {
+            Ok(footer) => footer,
+            Err(e) => {
+                // Dynamic disks keep a copy of the footer at the start of the file.
+                vhd_file.seek(SeekFrom::Start(0))?;
+                vhd_file.read_exact(&mut trailer_buf)?;
+                match VHDFileFooter::parse_vhd_footer(&trailer_buf) {
+                    Ok(footer) if footer.disk_type != VHD_DISK_TYPE => {
+                        log::warn!("VHD footer invalid, using footer copy from start of file.");
+                        footer
+                    }
+                    _ => return Err(e),
+                }
+            }
+        };
+
+        let mut dynamic = None;
+        let mut parent = None;
+        if footer.disk_type != VHD_DISK_TYPE {
+            let dynamic_disk = VHDDynamicDisk::load(&mut vhd_file, &footer, metadata.len())?;
+            if footer.disk_type == VHD_DISK_TYPE_DIFFERENCING {
+                parent = Some(Box::new(VirtualHardDisk::open_parent(
+                    &mut vhd_file,
+                    &footer,
+                    &dynamic_disk.header,
+                    path,
+                    depth,
+                )?));
+            }
+            dynamic = Some(dynamic_disk);
+        }
 
         Ok(VirtualHardDisk {
             vhd_file,
@@ -321,9 +712,64 @@ impl VirtualHardDisk {
             cur_sector: 0,
 
             footer,
+            dynamic,
+            parent,
         })
     }
 
+    /// Locate and open the parent of a differencing VHD. Each parent locator is tried in turn,
+    /// followed by the parent's file name in the child's directory.
+    fn open_parent(
+        vhd_file: &mut File,
+        footer: &VHDFileFooter,
+        header: &VHDDynamicHeader,
+        path: Option<&Path>,
+        depth: usize,
+    ) -> Result<VirtualHardDisk, anyhow::Error> {
+        let dir = match path {
+            Some(path) => path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            None => bail!(VirtualHardDiskError::ParentNotFound),
+        };
+        if depth >= VHD_MAX_PARENT_DEPTH {
+            bail!(VirtualHardDiskError::ParentNotFound);
+        }
+
+        let mut candidates = Vec::new();
+        for locator in header.parent_locators.iter() {
+            if let Some(parent_path) = locator.read_path(vhd_file, &dir)? {
+                candidates.push(parent_path);
+            }
+        }
+        if !header.parent_name.is_empty() {
+            candidates.push(dir.join(&header.parent_name));
+        }
+
+        for candidate in candidates {
+            if !candidate.is_file() {
+                continue;
+            }
+            log::info!("Opening parent VHD: {:?}", candidate);
+            let parent_file = File::open(&candidate).context("Failed to open parent VHD")?;
+            let parent = VirtualHardDisk::open(parent_file, Some(&candidate), depth + 1)?;
+
+            if parent.footer.uuid != header.parent_uuid {
+                log::error!(
+                    "Parent VHD UUID {} does not match expected UUID {}",
+                    parent.footer.uuid,
+                    header.parent_uuid
+                );
+                bail!(VirtualHardDiskError::ParentMismatch);
+            }
+            if parent.footer.current_size != footer.current_size {
+                bail!(VirtualHardDiskError::ParentMismatch);
+            }
+            return Ok(parent);
+        }
+
+        log::error!("Couldn't locate parent VHD: {:?}", header.parent_name);
+        bail!(VirtualHardDiskError::ParentNotFound)
+    }
+
     /// Return a byte offset given a CHS (Cylinder, Head, Sector) address
     ///
     /// Hard drive sectors are allowed to start at 0
@@ -335,32 +781,98 @@ impl VirtualHardDisk {
         lba * SECTOR_SIZE
     }
 
+    /// Return whether this VHD is a differencing VHD with a parent image.
+    pub fn has_parent(&self) -> bool {
+        self.parent.is_some()
+    }
+
     pub fn read_sector(&mut self, buf: &mut [u8], cylinder: u16, head: u8, sector: u8) -> Result<(), anyhow::Error> {
         let read_offset = self.get_chs_offset(cylinder, head, sector);
+        self.read_at(buf, read_offset as u64)
+    }
+
+    pub fn write_sector(&mut self, buf: &[u8], cylinder: u16, head: u8, sector: u8) -> Result<(), anyhow::Error> {
+        let write_offset = self.get_chs_offset(cylinder, head, sector);
+        self.write_at(buf, write_offset as u64)
+    }
+
+    fn read_at(&mut self, buf: &mut [u8], read_offset: u64) -> Result<(), anyhow::Error> {
+        let dynamic = match &self.dynamic {
+            Some(dynamic) => dynamic,
+            None => return self.read_fixed(buf, read_offset),
+        };
+
+        if read_offset + VHD_SECTOR_SIZE as u64 > self.footer.current_size {
+            bail!(VirtualHardDiskError::InvalidSeek);
+        }
+
+        let (block, block_sector) = dynamic.locate(read_offset)?;
+        let mut data_offset = None;
+        if let Some(block_offset) = dynamic.block_offset(block) {
+            // Sectors of a differencing disk that have not been written are read from the parent.
+            if self.parent.is_none() || dynamic.is_sector_present(&mut self.vhd_file, block_offset, block_sector)? {
+                data_offset = Some(dynamic.sector_offset(block_offset, block_sector));
+            }
+        }
+
+        match (data_offset, &mut self.parent) {
+            (Some(data_offset), _) => {
+                self.vhd_file.seek(SeekFrom::Start(data_offset))?;
+                self.vhd_file.read_exact(buf).context("Error reading sector from VHD")?;
+            }
+            (None, Some(parent)) => parent.read_at(buf, read_offset)?,
+            (None, None) => buf.fill(0),
+        }
+        Ok(())
+    }
+
+    fn write_at(&mut self, buf: &[u8], write_offset: u64) -> Result<(), anyhow::Error> {
+        let dynamic = match &mut self.dynamic {
+            Some(dynamic) => dynamic,
+            None => return self.write_fixed(buf, write_offset),
+        };
+
+        if write_offset + VHD_SECTOR_SIZE as u64 > self.footer.current_size {
+            bail!(VirtualHardDiskError::InvalidSeek);
+        }
 
+        let (block, block_sector) = dynamic.locate(write_offset)?;
+        let block_offset = match dynamic.block_offset(block) {
+            Some(block_offset) => block_offset,
+            None => dynamic.allocate_block(&mut self.vhd_file, &self.footer, block)?,
+        };
+
+        self.vhd_file
+            .seek(SeekFrom::Start(dynamic.sector_offset(block_offset, block_sector)))?;
+        self.vhd_file
+            .write_all(buf)
+            .context("Error writing sector to VHD")?;
+        dynamic.mark_sector_present(&mut self.vhd_file, block_offset, block_sector)?;
+        Ok(())
+    }
+
+    fn read_fixed(&mut self, buf: &mut [u8], read_offset: u64) -> Result<(), anyhow::Error> {
         let metadata = self.vhd_file.metadata().context("Couldn't get VHD file metadata")?;
-        if read_offset as u64 > metadata.len() - VHD_FOOTER_LEN as u64 - VHD_SECTOR_SIZE as u64 {
+        if read_offset > metadata.len() - VHD_FOOTER_LEN as u64 - VHD_SECTOR_SIZE as u64 {
             // Read requested past last sector in file
             bail!(VirtualHardDiskError::InvalidSeek);
         }
 
-        self.vhd_file.seek(SeekFrom::Start(read_offset as u64))?;
+        self.vhd_file.seek(SeekFrom::Start(read_offset))?;
 
         self.vhd_file.read_exact(buf).context("Error reading sector from VHD")?;
 
         Ok(())
     }
 
-    pub fn write_sector(&mut self, buf: &[u8], cylinder: u16, head: u8, sector: u8) -> Result<(), anyhow::Error> {
-        let write_offset = self.get_chs_offset(cylinder, head, sector);
-
+    fn write_fixed(&mut self, buf: &[u8], write_offset: u64) -> Result<(), anyhow::Error> {
         let metadata = self.vhd_file.metadata().context("Couldn't get VHD file metadata")?;
-        if write_offset as u64 > metadata.len() - VHD_FOOTER_LEN as u64 - VHD_SECTOR_SIZE as u64 {
+        if write_offset > metadata.len() - VHD_FOOTER_LEN as u64 - VHD_SECTOR_SIZE as u64 {
             // Write requested past last sector in file
             bail!(VirtualHardDiskError::InvalidSeek);
         }
 
-        self.vhd_file.seek(SeekFrom::Start(write_offset as u64))?;
+        self.vhd_file.seek(SeekFrom::Start(write_offset))?;
 
         let write_len = self.vhd_file.write(buf)?;
         if write_len != VHD_SECTOR_SIZE {
@@ -371,6 +883,35 @@ impl VirtualHardDisk {
     }
 }
 
+impl DiskBackend for VirtualHardDisk {
+    fn geometry(&self) -> (u16, u8, u8) {
+        (self.max_cylinders as u16, self.max_heads as u8, self.max_sectors as u8)
+    }
+
+    fn size(&self) -> u64 {
+        self.footer.current_size
+    }
+
+    fn read_sector(&mut self, buf: &mut [u8], cylinder: u16, head: u8, sector: u8) -> Result<(), anyhow::Error> {
+        VirtualHardDisk::read_sector(self, buf, cylinder, head, sector)
+    }
+
+    fn write_sector(&mut self, buf: &[u8], cylinder: u16, head: u8, sector: u8) -> Result<(), anyhow::Error> {
+        VirtualHardDisk::write_sector(self, buf, cylinder, head, sector)
+    }
+}
+
+/// Round a length up to a multiple of the VHD sector size.
+fn round_to_sector(len: u64) -> u64 {
+    len.div_ceil(VHD_SECTOR_SIZE as u64) * VHD_SECTOR_SIZE as u64
+}
+
+/// Convert a SystemTime to a VHD timestamp, the number of seconds since January 1, 2000 UTC.
+fn vhd_timestamp(time: SystemTime) -> u32 {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    secs.saturating_sub(VHD_EPOCH_OFFSET) as u32
+}
+
 pub fn create_vhd(filename: OsString, c: u16, h: u8, s: u8) -> Result<File, anyhow::Error> {
     assert_eq!(VHD_FOOTER_LEN, VHD_SECTOR_SIZE);
 
@@ -398,7 +939,7 @@ pub fn create_vhd(filename: OsString, c: u16, h: u8, s: u8) -> Result<File, anyh
     let footer = VHDFileFooter::new(c, h, s, uuid);
 
     // Since the length of a VHD footer == a sector size, re-use sector buf
-    VHDFileFooter::make_vhd_footer_bytes(&mut write_buf, footer);
+    VHDFileFooter::make_vhd_footer_bytes(&mut write_buf, &footer);
 
     vhd_file
         .write(&write_buf)
@@ -406,3 +947,225 @@ pub fn create_vhd(filename: OsString, c: u16, h: u8, s: u8) -> Result<File, anyh
 
     Ok(vhd_file)
 }
+
+/// Create a dynamic VHD with the specified geometry. No blocks are allocated until written.
+pub fn create_dynamic_vhd(filename: &Path, c: u16, h: u8, s: u8) -> Result<File, anyhow::Error> {
+    let mut footer = VHDFileFooter::new(c, h, s, Uuid::new_v4());
+    footer.disk_type = VHD_DISK_TYPE_DYNAMIC;
+
+    write_dynamic_vhd(filename, footer, VHDDynamicHeader::default(), Vec::new())
+}
+
+/// Create a differencing VHD whose parent is the VHD at `parent_path`. The new VHD has the
+/// geometry of its parent and initially reads back identically to it. Writes go to the new VHD
+/// only, so the parent can be shared between any number of children.
+pub fn create_differencing_vhd(filename: &Path, parent_path: &Path) -> Result<File, anyhow::Error> {
+    let parent_file = File::open(parent_path).context("Failed to open parent VHD")?;
+    let parent_modified = parent_file.metadata().and_then(|m| m.modified()).ok();
+    let parent = VirtualHardDisk::from_file_with_path(parent_file, parent_path)?;
+
+    let mut footer = VHDFileFooter::new(
+        parent.footer.geometry.c,
+        parent.footer.geometry.h,
+        parent.footer.geometry.s,
+        Uuid::new_v4(),
+    );
+    footer.disk_type = VHD_DISK_TYPE_DIFFERENCING;
+    footer.original_size = parent.footer.current_size;
+    footer.current_size = parent.footer.current_size;
+
+    let parent_name = parent_path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let header = VHDDynamicHeader {
+        parent_uuid: parent.footer.uuid,
+        parent_timestamp: parent_modified.map(vhd_timestamp).unwrap_or(0),
+        parent_name: parent_name.clone(),
+        ..Default::default()
+    };
+
+    // Write a relative locator if the parent is in the same directory as the child, and an
+    // absolute locator in any case.
+    let mut locators = Vec::new();
+    let parent_abs = fs::canonicalize(parent_path).context("Failed to resolve parent VHD path")?;
+    let child_dir = filename
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    if fs::canonicalize(child_dir).ok().as_deref() == parent_abs.parent() {
+        locators.push((VHD_PLATFORM_W2RU, format!(".\\{}", parent_name)));
+    }
+    locators.push((VHD_PLATFORM_W2KU, parent_abs.to_string_lossy().to_string()));
+
+    let locators = locators
+        .into_iter()
+        .map(|(platform, path)| {
+            let data: Vec<u8> = path.encode_utf16().flat_map(|c| c.to_le_bytes()).collect();
+            (platform, data)
+        })
+        .collect();
+
+    write_dynamic_vhd(filename, footer, header, locators)
+}
+
+/// Write a new dynamic or differencing VHD with an empty BAT. The file is laid out as a footer copy,
+/// the dynamic disk header, the BAT, any parent locator data, and finally the footer.
+fn write_dynamic_vhd(
+    filename: &Path,
+    mut footer: VHDFileFooter,
+    mut header: VHDDynamicHeader,
+    locators: Vec<(u32, Vec<u8>)>,
+) -> Result<File, anyhow::Error> {
+    if locators.len() > VHD_PARENT_LOCATOR_CT {
+        bail!(VirtualHardDiskError::InvalidHeader);
+    }
+
+    // Don't overwrite an existing file
+    let mut vhd_file = match File::options().read(true).write(true).create_new(true).open(filename) {
+        Ok(file) => file,
+        Err(_) => {
+            log::warn!("Couldn't create VHD file: {:?}", filename);
+            bail!(VirtualHardDiskError::FileExists);
+        }
+    };
+
+    footer.offset = VHD_FOOTER_LEN as u64;
+    footer.timestamp = vhd_timestamp(SystemTime::now());
+
+    header.block_size = VHD_DEFAULT_BLOCK_SIZE;
+    header.max_table_entries = footer.current_size.div_ceil(VHD_DEFAULT_BLOCK_SIZE as u64) as u32;
+    header.table_offset = (VHD_FOOTER_LEN + VHD_DYNAMIC_HEADER_LEN) as u64;
+
+    let bat_len = round_to_sector(header.max_table_entries as u64 * 4);
+    let mut locator_offset = header.table_offset + bat_len;
+    for (i, (platform, data)) in locators.iter().enumerate() {
+        let data_space = round_to_sector(data.len() as u64);
+        header.parent_locators[i] = VHDParentLocator {
+            platform: *platform,
+            data_space: (data_space / VHD_SECTOR_SIZE as u64) as u32,
+            data_len: data.len() as u32,
+            data_offset: locator_offset,
+        };
+        locator_offset += data_space;
+    }
+
+    let mut vhd_buf = vec![0u8; (locator_offset + VHD_FOOTER_LEN as u64) as usize];
+    VHDFileFooter::make_vhd_footer_bytes(&mut vhd_buf[0..VHD_FOOTER_LEN], &footer);
+    VHDDynamicHeader::make_dynamic_header_bytes(
+        &mut vhd_buf[VHD_FOOTER_LEN..VHD_FOOTER_LEN + VHD_DYNAMIC_HEADER_LEN],
+        &header,
+    );
+
+    let table_offset = header.table_offset as usize;
+    vhd_buf[table_offset..table_offset + bat_len as usize].fill(0xFF);
+
+    for (locator, (_, data)) in header.parent_locators.iter().zip(locators.iter()) {
+        let offset = locator.data_offset as usize;
+        vhd_buf[offset..offset + data.len()].copy_from_slice(data);
+    }
+
+    let footer_offset = locator_offset as usize;
+    VHDFileFooter::make_vhd_footer_bytes(&mut vhd_buf[footer_offset..], &footer);
+
+    vhd_file
+        .write_all(&vhd_buf)
+        .context("Error writing VHD file to disk.")?;
+
+    Ok(vhd_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("marty_{}_{}.vhd", name, Uuid::new_v4()))
+    }
+
+    fn open_vhd(path: &Path) -> VirtualHardDisk {
+        let file = File::options().read(true).write(true).open(path).unwrap();
+        VirtualHardDisk::from_file_with_path(file, path).unwrap()
+    }
+
+    #[test]
+    fn test_dynamic_vhd() {
+        let path = temp_path("dynamic");
+        create_dynamic_vhd(&path, 306, 4, 17).unwrap();
+
+        let mut sector = [0u8; VHD_SECTOR_SIZE];
+        {
+            let mut vhd = open_vhd(&path);
+            assert_eq!(vhd.max_cylinders, 306);
+
+            // Unallocated blocks read as zeros.
+            sector.fill(0xAA);
+            vhd.read_sector(&mut sector, 100, 2, 5).unwrap();
+            assert!(sector.iter().all(|b| *b == 0));
+
+            sector.fill(0x55);
+            vhd.write_sector(&sector, 100, 2, 5).unwrap();
+            sector.fill(0x66);
+            vhd.write_sector(&sector, 305, 3, 16).unwrap();
+            assert!(vhd.write_sector(&sector, 306, 0, 0).is_err());
+        }
+
+        let mut vhd = open_vhd(&path);
+        vhd.read_sector(&mut sector, 100, 2, 5).unwrap();
+        assert!(sector.iter().all(|b| *b == 0x55));
+        vhd.read_sector(&mut sector, 305, 3, 16).unwrap();
+        assert!(sector.iter().all(|b| *b == 0x66));
+        vhd.read_sector(&mut sector, 100, 2, 6).unwrap();
+        assert!(sector.iter().all(|b| *b == 0));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_differencing_vhd() {
+        let parent_path = temp_path("parent");
+        let child_path = temp_path("child");
+        create_dynamic_vhd(&parent_path, 306, 4, 17).unwrap();
+
+        let mut sector = [0u8; VHD_SECTOR_SIZE];
+        {
+            let mut parent = open_vhd(&parent_path);
+            sector.fill(0x11);
+            parent.write_sector(&sector, 0, 0, 0).unwrap();
+            parent.write_sector(&sector, 0, 0, 1).unwrap();
+        }
+
+        create_differencing_vhd(&child_path, &parent_path).unwrap();
+        {
+            let mut child = open_vhd(&child_path);
+            assert!(child.has_parent());
+            assert_eq!(child.max_heads, 4);
+
+            child.read_sector(&mut sector, 0, 0, 1).unwrap();
+            assert!(sector.iter().all(|b| *b == 0x11));
+
+            sector.fill(0x22);
+            child.write_sector(&sector, 0, 0, 1).unwrap();
+        }
+
+        // Written sectors come from the child; everything else from the parent.
+        let mut child = open_vhd(&child_path);
+        child.read_sector(&mut sector, 0, 0, 0).unwrap();
+        assert!(sector.iter().all(|b| *b == 0x11));
+        child.read_sector(&mut sector, 0, 0, 1).unwrap();
+        assert!(sector.iter().all(|b| *b == 0x22));
+
+        // The parent is unmodified.
+        let mut parent = open_vhd(&parent_path);
+        parent.read_sector(&mut sector, 0, 0, 1).unwrap();
+        assert!(sector.iter().all(|b| *b == 0x11));
+
+        // A differencing disk can't be opened without knowing where to look for its parent.
+        let file = File::open(&child_path).unwrap();
+        assert!(VirtualHardDisk::from_file(file).is_err());
+
+        fs::remove_file(&child_path).unwrap();
+        fs::remove_file(&parent_path).unwrap();
+    }
+}
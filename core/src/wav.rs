@@ -0,0 +1,157 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    wav.rs
+
+    A minimal writer for 16-bit PCM WAV files, used to capture the output
+    of the sound mixer.
+
+*/
+
+use std::{
+    fs::File,
+    io::{BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Error};
+
+const WAV_HEADER_LEN: u32 = 44;
+const WAV_BITS_PER_SAMPLE: u16 = 16;
+const WAV_FORMAT_PCM: u16 = 1;
+
+pub struct WavWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    channels: u16,
+    sample_rate: u32,
+    /// Bytes of sample data written so far.
+    data_len: u32,
+    finished: bool,
+}
+
+impl WavWriter {
+    /// Create a WAV file for interleaved samples with the specified format. The header's length
+    /// fields are filled in by finish().
+    pub fn create(path: &Path, sample_rate: u32, channels: u16) -> Result<WavWriter, Error> {
+        let file = File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+        let mut writer = WavWriter {
+            file: BufWriter::new(file),
+            path: path.to_path_buf(),
+            channels,
+            sample_rate,
+            data_len: 0,
+            finished: false,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> Result<(), Error> {
+        let block_align = self.channels * WAV_BITS_PER_SAMPLE / 8;
+        let byte_rate = self.sample_rate * block_align as u32;
+
+        let mut header = Vec::with_capacity(WAV_HEADER_LEN as usize);
+        header.extend_from_slice(b"RIFF");
+        header.extend_from_slice(&(WAV_HEADER_LEN - 8).saturating_add(self.data_len).to_le_bytes());
+        header.extend_from_slice(b"WAVE");
+        header.extend_from_slice(b"fmt ");
+        header.extend_from_slice(&16u32.to_le_bytes());
+        header.extend_from_slice(&WAV_FORMAT_PCM.to_le_bytes());
+        header.extend_from_slice(&self.channels.to_le_bytes());
+        header.extend_from_slice(&self.sample_rate.to_le_bytes());
+        header.extend_from_slice(&byte_rate.to_le_bytes());
+        header.extend_from_slice(&block_align.to_le_bytes());
+        header.extend_from_slice(&WAV_BITS_PER_SAMPLE.to_le_bytes());
+        header.extend_from_slice(b"data");
+        header.extend_from_slice(&self.data_len.to_le_bytes());
+
+        self.file.write_all(&header)?;
+        Ok(())
+    }
+
+    /// Write interleaved samples in the range -1.0 to 1.0. Samples outside the range are clipped.
+    pub fn write_samples(&mut self, samples: &[f32]) -> Result<(), Error> {
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32).round() as i16;
+            self.file.write_all(&value.to_le_bytes())?;
+        }
+        self.data_len = self.data_len.saturating_add((samples.len() as u32).saturating_mul(2));
+        Ok(())
+    }
+
+    /// Fill in the header's length fields and flush the file. Called automatically on drop.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.flush()?;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the number of sample frames written.
+    pub fn frames(&self) -> u64 {
+        self.data_len as u64 / (self.channels as u64 * 2)
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(err) = self.finish() {
+            log::error!("Failed to finish WAV file {}: {}", self.path.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav_header() {
+        let path = std::env::temp_dir().join(format!("martypc_wav_{}.wav", std::process::id()));
+        {
+            let mut wav = WavWriter::create(&path, 44100, 2).unwrap();
+            wav.write_samples(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+            assert_eq!(wav.frames(), 2);
+        }
+
+        let data = std::fs::read(&path).unwrap();
+        _ = std::fs::remove_file(&path);
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[0..4], b"RIFF");
+        assert_eq!(u32::from_le_bytes(data[4..8].try_into().unwrap()), 44);
+        assert_eq!(u32::from_le_bytes(data[40..44].try_into().unwrap()), 8);
+        assert_eq!(&data[44..], &[0x00, 0x00, 0xFF, 0x7F, 0x01, 0x80, 0xFF, 0x7F]);
+    }
+}
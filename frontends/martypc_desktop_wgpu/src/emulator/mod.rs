@@ -30,15 +30,17 @@
 */
 
 use display_manager_wgpu::DisplayManager;
-use std::{cell::RefCell, ffi::OsString, rc::Rc};
+use std::{cell::RefCell, ffi::OsString, fs::OpenOptions, io::Write, path::PathBuf, rc::Rc};
 
-use crate::{input::HotkeyManager, Counter, KeyboardData, MouseData};
-use anyhow::Error;
-use config_toml_bpaf::ConfigFileParams;
+use crate::{input::HotkeyManager, Counter, KeyboardData, LightPenData, MouseData};
+use anyhow::{anyhow, Error};
+use config_toml_bpaf::{ConfigFileParams, HostDirDevice};
 use display_manager_wgpu::WgpuDisplayManager;
 use frontend_common::{
+    constants::{LONG_NOTIFICATION_TIME, NORMAL_NOTIFICATION_TIME},
     display_scaler::SCALER_MODES,
     floppy_manager::FloppyManager,
+    gdb_stub::GdbStub,
     resource_manager::ResourceManager,
     rom_manager::RomManager,
     timestep_manager::PerfSnapshot,
@@ -46,8 +48,9 @@ use frontend_common::{
 };
 use marty_core::{
     cpu_common::CpuOption,
+    file_util::{find_unique_dirname, find_unique_filename},
+    hdd_image::host_dir::HostDirDisk,
     machine::{ExecutionControl, Machine, MachineEvent, MachineState},
-    vhd::VirtualHardDisk,
 };
 use marty_egui::{state::GuiState, GuiBoolean, GuiWindow};
 use videocard_renderer::AspectCorrectionMode;
@@ -72,6 +75,7 @@ pub struct Emulator {
     pub machine_events: Vec<MachineEvent>,
     pub exec_control: Rc<RefCell<ExecutionControl>>,
     pub mouse_data: MouseData,
+    pub lightpen_data: LightPenData,
     pub kb_data: KeyboardData,
     pub stat_counter: Counter,
     pub gui: GuiState,
@@ -80,6 +84,9 @@ pub struct Emulator {
     pub flags: EmuFlags,
     pub perf: PerfSnapshot,
     pub hkm: HotkeyManager,
+    pub gdb: Option<GdbStub>,
+    pub print_file: Option<PathBuf>,
+    pub video_capture_dir: Option<PathBuf>,
 }
 
 impl Emulator {
@@ -180,6 +187,7 @@ impl Emulator {
             .set_cpu_option(CpuOption::TraceLoggingEnabled(self.config.machine.cpu.trace_on));
 
         self.gui.set_option(GuiBoolean::TurboButton, self.config.machine.turbo);
+        self.gui.set_option(GuiBoolean::LightPen, self.lightpen_data.enabled);
 
         self.gui.set_scaler_presets(&self.config.emulator.scaler_preset);
 
@@ -268,7 +276,12 @@ impl Emulator {
     /// hard disk, and continuing until all images are mounted, or there are no more hard disks.
     pub fn mount_vhds(&mut self) -> Result<(), Error> {
         // First, retrieve the list of VHD images specified in the machine configuration.
-        let mut vhd_names: Vec<Option<String>> = self.get_vhds_from_machine();
+        // Each entry is an image name, optional raw image geometry, and overlay flag.
+        let mut vhd_names: Vec<Option<(String, Option<(u16, u8, u8)>, bool)>> = self
+            .get_vhds_from_machine()
+            .into_iter()
+            .map(|name| name.map(|name| (name, None, false)))
+            .collect();
         let machine_max = vhd_names.len();
 
         for (drive_i, vhd) in self
@@ -281,49 +294,53 @@ impl Emulator {
             .iter()
             .enumerate()
         {
+            let entry = Some((vhd.filename.clone(), vhd.geometry(), vhd.overlay));
             if drive_i >= machine_max {
                 // Add new drive
-                vhd_names.push(Some(vhd.filename.clone()));
+                vhd_names.push(entry);
             }
             else {
                 // Replace existing drive
-                vhd_names[drive_i] = Some(vhd.filename.clone());
+                vhd_names[drive_i] = entry;
             }
         }
 
+        let formats = match self.machine.hdc() {
+            Some(hdc) => hdc.get_supported_formats(),
+            None => Vec::new(),
+        };
+
         let mut config_drive_idx: usize = 0;
-        for vhd_name in vhd_names.into_iter().filter_map(|x| x) {
+        for (vhd_name, geometry, overlay) in vhd_names.into_iter().filter_map(|x| x) {
             let vhd_os_name: OsString = vhd_name.into();
-            match self.vhd_manager.load_vhd_file_by_name(config_drive_idx, &vhd_os_name) {
-                Ok((vhd_file, vhd_idx)) => match VirtualHardDisk::from_file(vhd_file) {
-                    Ok(vhd) => {
-                        if let Some(hdc) = self.machine.hdc() {
-                            match hdc.set_vhd(config_drive_idx, vhd) {
-                                Ok(_) => {
-                                    log::info!(
-                                        "VHD image {:?} successfully loaded into virtual drive: {}",
-                                        vhd_os_name,
-                                        config_drive_idx
-                                    );
-
-                                    if let Some(selection) = self.vhd_manager.get_vhd_path(vhd_idx) {
-                                        self.gui
-                                            .set_hdd_selection(config_drive_idx, Some(vhd_idx), Some(selection));
-                                    }
-                                }
-                                Err(err) => {
-                                    log::error!("Error mounting VHD: {}", err);
+            match self
+                .vhd_manager
+                .load_disk_by_name(config_drive_idx, &vhd_os_name, geometry, overlay, &formats)
+            {
+                Ok((disk, vhd_idx)) => {
+                    if let Some(hdc) = self.machine.hdc() {
+                        match hdc.set_disk(config_drive_idx, disk, geometry) {
+                            Ok(_) => {
+                                log::info!(
+                                    "VHD image {:?} successfully loaded into virtual drive: {}",
+                                    vhd_os_name,
+                                    config_drive_idx
+                                );
+
+                                if let Some(selection) = self.vhd_manager.get_vhd_path(vhd_idx) {
+                                    self.gui
+                                        .set_hdd_selection(config_drive_idx, Some(vhd_idx), Some(selection));
                                 }
                             }
-                        }
-                        else {
-                            log::error!("Couldn't load VHD: No Hard Disk Controller present!");
+                            Err(err) => {
+                                log::error!("Error mounting VHD: {}", err);
+                            }
                         }
                     }
-                    Err(err) => {
-                        log::error!("Error loading VHD: {}", err);
+                    else {
+                        log::error!("Couldn't load VHD: No Hard Disk Controller present!");
                     }
-                },
+                }
                 Err(err) => {
                     log::error!("Failed to load VHD image {:?}: {}", vhd_os_name, err);
                 }
@@ -333,6 +350,304 @@ impl Emulator {
         Ok(())
     }
 
+    /// Start recording the machine's audio output to a new WAV file in the 'audio' resource
+    /// directory, or stop the recording in progress.
+    pub fn toggle_audio_capture(&mut self) {
+        if self.machine.is_capturing_video() {
+            self.gui
+                .toasts()
+                .error("Audio is being recorded as part of a video capture".to_string())
+                .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+            return;
+        }
+        if self.machine.is_capturing_audio() {
+            match self.machine.stop_audio_capture() {
+                Ok(Some(path)) => {
+                    self.gui
+                        .toasts()
+                        .info(format!("Audio saved: {}", path.display()))
+                        .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+                }
+                Ok(None) => {}
+                Err(err) => {
+                    log::error!("Failed to finish audio capture: {}", err);
+                    self.gui
+                        .toasts()
+                        .error(format!("{}", err))
+                        .set_duration(Some(LONG_NOTIFICATION_TIME));
+                }
+            }
+            return;
+        }
+
+        let result = match self.rm.get_resource_path("audio") {
+            Some(audio_path) => {
+                let filename = find_unique_filename(&audio_path, "audio", "wav");
+                self.machine.start_audio_capture(&filename).map(|_| filename)
+            }
+            None => Err(anyhow!("No 'audio' resource path is configured")),
+        };
+
+        match result {
+            Ok(filename) => {
+                self.gui
+                    .toasts()
+                    .info(format!("Recording audio to {}", filename.display()))
+                    .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+            }
+            Err(err) => {
+                log::error!("Failed to start audio capture: {}", err);
+                self.gui
+                    .toasts()
+                    .error(format!("{}", err))
+                    .set_duration(Some(LONG_NOTIFICATION_TIME));
+            }
+        }
+    }
+
+    /// Start capturing video to a new directory in the 'video' resource directory, or stop the
+    /// capture in progress. Each emulated frame is saved as a numbered PNG, and the audio output
+    /// is recorded alongside it as a WAV file.
+    pub fn toggle_video_capture(&mut self) {
+        if self.machine.is_capturing_video() {
+            self.stop_video_capture();
+            return;
+        }
+        if self.machine.is_capturing_audio() {
+            self.gui
+                .toasts()
+                .error("Stop the audio capture before starting a video capture".to_string())
+                .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+            return;
+        }
+
+        let result = match self.rm.get_resource_path("video") {
+            Some(video_path) => {
+                let capture_dir = find_unique_dirname(&video_path, "capture");
+                std::fs::create_dir_all(&capture_dir)
+                    .map_err(Error::from)
+                    .and_then(|_| self.machine.start_video_capture())
+                    .and_then(|_| self.machine.start_audio_capture(&capture_dir.join("audio.wav")))
+                    .map(|_| capture_dir)
+            }
+            None => Err(anyhow!("No 'video' resource path is configured")),
+        };
+
+        match result {
+            Ok(capture_dir) => {
+                self.gui
+                    .toasts()
+                    .info(format!("Recording video to {}", capture_dir.display()))
+                    .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+                self.video_capture_dir = Some(capture_dir);
+            }
+            Err(err) => {
+                log::error!("Failed to start video capture: {}", err);
+                _ = self.machine.stop_video_capture();
+                self.gui
+                    .toasts()
+                    .error(format!("{}", err))
+                    .set_duration(Some(LONG_NOTIFICATION_TIME));
+            }
+        }
+    }
+
+    fn stop_video_capture(&mut self) {
+        self.save_captured_frames();
+        let info = self.machine.stop_video_capture();
+        if let Err(err) = self.machine.stop_audio_capture() {
+            log::error!("Failed to finish video capture audio: {}", err);
+        }
+
+        if let (Some(info), Some(capture_dir)) = (info, self.video_capture_dir.take()) {
+            // The frame rate is needed to assemble the frames into a movie, so report it.
+            log::info!(
+                "Video saved to {}: {} frames at {:.3} fps",
+                capture_dir.display(),
+                info.frames,
+                info.frame_rate
+            );
+            self.gui
+                .toasts()
+                .info(format!(
+                    "Video saved: {} ({} frames at {:.3} fps)",
+                    capture_dir.display(),
+                    info.frames,
+                    info.frame_rate
+                ))
+                .set_duration(Some(LONG_NOTIFICATION_TIME));
+        }
+    }
+
+    /// Update the light pen from the host cursor. The pen is held against the screen while the left
+    /// mouse button is pressed over a display window, and only while the mouse is not captured.
+    pub fn update_light_pen(&mut self) {
+        if !self.lightpen_data.enabled {
+            return;
+        }
+
+        let pressed = self.lightpen_data.is_pressed || self.lightpen_data.was_pressed;
+        let pen_pos = match (self.lightpen_data.window, self.lightpen_data.cursor) {
+            (Some(wid), Some((x, y))) if pressed && !self.mouse_data.is_captured => {
+                self.dm.window_to_field(wid, x, y, &self.machine)
+            }
+            _ => None,
+        };
+
+        // Clicks are sticky for one frame, like mouse buttons, so a quick click still triggers the pen.
+        if !self.lightpen_data.is_pressed {
+            self.lightpen_data.was_pressed = false;
+        }
+
+        // Lift the pen from the card it was last held against if it has left that card's display.
+        let pen_vid = pen_pos.map(|(vid, _)| vid);
+        if self.lightpen_data.card != pen_vid {
+            self.lift_light_pen();
+        }
+
+        if let Some((vid, pos)) = pen_pos {
+            if let Some(card) = self.machine.bus_mut().video_mut(&vid) {
+                card.set_light_pen(Some(pos), true);
+            }
+            self.lightpen_data.card = Some(vid);
+        }
+    }
+
+    /// Lift the light pen from the card it was last held against, if any.
+    pub fn lift_light_pen(&mut self) {
+        if let Some(vid) = self.lightpen_data.card.take() {
+            if let Some(card) = self.machine.bus_mut().video_mut(&vid) {
+                card.set_light_pen(None, false);
+            }
+        }
+    }
+
+    /// Render the frames captured by the machine since the last call and save them to the capture
+    /// directory. Frames are rendered by the primary display's renderer, so they are cropped to its
+    /// display aperture, but not scaled.
+    pub fn save_captured_frames(&mut self) {
+        let frames = self.machine.take_captured_frames();
+        if frames.is_empty() {
+            return;
+        }
+        let capture_dir = match &self.video_capture_dir {
+            Some(capture_dir) => capture_dir.clone(),
+            None => return,
+        };
+        let renderer = match self.dm.get_primary_renderer() {
+            Some(renderer) => renderer,
+            None => {
+                log::warn!("No renderer for video capture. {} frames dropped.", frames.len());
+                return;
+            }
+        };
+
+        for frame in frames {
+            if renderer.get_mode_byte() != frame.extents.mode_byte {
+                renderer.cga_direct_mode_update(frame.extents.mode_byte);
+                renderer.set_mode_byte(frame.extents.mode_byte);
+            }
+            let (dims, pixels) = renderer.render_capture(&frame.buf, &frame.extents);
+            let filename = capture_dir.join(format!("frame{:06}.png", frame.number));
+            if let Err(err) = image::save_buffer(&filename, pixels, dims.w, dims.h, image::ColorType::Rgba8) {
+                log::error!("Failed to save video frame {}: {}", filename.display(), err);
+            }
+        }
+    }
+
+    /// Save anything the printer has produced to the 'print' resource directory. Received bytes
+    /// are appended to one capture file per session, and each finished page is saved as a PNG.
+    pub fn save_printer_output(&mut self) {
+        let (output, pages) = match self.machine.printer_mut() {
+            Some(printer) => (printer.take_output(), printer.take_pages()),
+            None => return,
+        };
+        if output.is_empty() && pages.is_empty() {
+            return;
+        }
+
+        let print_path = match self.rm.get_resource_path("print") {
+            Some(path) => path,
+            None => {
+                log::warn!("No 'print' resource path is configured. Printer output discarded.");
+                return;
+            }
+        };
+
+        if !output.is_empty() {
+            let print_file = self
+                .print_file
+                .get_or_insert_with(|| find_unique_filename(&print_path, "printer", "prn"));
+            let result = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(print_file.as_path())
+                .and_then(|mut file| file.write_all(&output));
+            if let Err(err) = result {
+                log::error!("Failed to write printer output to {}: {}", print_file.display(), err);
+            }
+        }
+
+        for page in pages {
+            let filename = find_unique_filename(&print_path, "page", "png");
+            match image::save_buffer(&filename, &page.pixels, page.width, page.height, image::ColorType::L8) {
+                Ok(_) => {
+                    self.gui
+                        .toasts()
+                        .info(format!("Printed page saved: {}", filename.display()))
+                        .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+                }
+                Err(err) => {
+                    log::error!("Failed to save printed page to {}: {}", filename.display(), err);
+                }
+            }
+        }
+    }
+
+    /// Mount the host directories listed in the configuration as floppy or hard disks. These are
+    /// mounted after VHDs, so a host directory replaces any image configured for the same drive.
+    pub fn mount_host_dirs(&mut self) -> Result<(), Error> {
+        let entries = match &self.config.emulator.media.host_dir {
+            Some(entries) => entries,
+            None => return Ok(()),
+        };
+
+        for entry in entries {
+            let result = match entry.device {
+                HostDirDevice::Floppy => match self.machine.fdc() {
+                    Some(fdc) => fdc.load_host_dir(entry.drive, &entry.path, entry.read_only),
+                    None => Err(anyhow!("No floppy controller present!")),
+                },
+                HostDirDevice::Hdd => match self.machine.hdc() {
+                    Some(hdc) => {
+                        let geometry = entry.geometry().or_else(|| {
+                            hdc.get_supported_formats()
+                                .first()
+                                .map(|f| (f.max_cylinders, f.max_heads, f.max_sectors))
+                        });
+                        match geometry {
+                            Some((c, h, s)) => HostDirDisk::new(&entry.path, c, h, s, entry.read_only)
+                                .and_then(|disk| Ok(hdc.set_disk(entry.drive, Box::new(disk), entry.geometry())?)),
+                            None => Err(anyhow!("No hard disk geometry available")),
+                        }
+                    }
+                    None => Err(anyhow!("No hard disk controller present!")),
+                },
+            };
+
+            match result {
+                Ok(_) => log::info!(
+                    "Host directory {} mounted as {:?} drive: {}",
+                    entry.path.display(),
+                    entry.device,
+                    entry.drive
+                ),
+                Err(err) => log::error!("Failed to mount host directory {}: {}", entry.path.display(), err),
+            }
+        }
+        Ok(())
+    }
+
     pub fn post_dm_build_init(&mut self) {
         // Set all DisplayTargets to hardware aspect correction
         self.dm.for_each_target(|dtc, _idx| {
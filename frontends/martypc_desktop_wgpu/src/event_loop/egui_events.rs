@@ -32,7 +32,7 @@
 use crate::Emulator;
 use display_manager_wgpu::DisplayManager;
 use marty_core::{
-    breakpoints::BreakPointType,
+    breakpoints::{BreakPointCondition, BreakPointExpr, BreakPointType},
     cpu_common::CpuOption,
     device_traits::videocard::ClockingMode,
     machine::MachineState,
@@ -50,7 +50,6 @@ use marty_egui::{
 use std::{mem::discriminant, time::Duration};
 
 use frontend_common::constants::{LONG_NOTIFICATION_TIME, NORMAL_NOTIFICATION_TIME, SHORT_NOTIFICATION_TIME};
-use marty_core::vhd::VirtualHardDisk;
 use videocard_renderer::AspectCorrectionMode;
 use winit::event_loop::EventLoopWindowTarget;
 
@@ -84,6 +83,12 @@ pub fn handle_egui_event(emu: &mut Emulator, elwt: &EventLoopWindowTarget<()>, g
                 (GuiBoolean::TurboButton, state) => {
                     emu.machine.set_turbo_mode(state);
                 }
+                (GuiBoolean::LightPen, state) => {
+                    emu.lightpen_data.enabled = state;
+                    if !state {
+                        emu.lift_light_pen();
+                    }
+                }
                 _ => {}
             },
             GuiVariable::Enum(op) => match ctx {
@@ -158,37 +163,36 @@ pub fn handle_egui_event(emu: &mut Emulator, elwt: &EventLoopWindowTarget<()>, g
 
             let mut error_str = None;
 
-            match emu.vhd_manager.load_vhd_file(*drive_idx, *image_idx) {
-                Ok(vhd_file) => match VirtualHardDisk::from_file(vhd_file) {
-                    Ok(vhd) => {
-                        if let Some(hdc) = emu.machine.hdc() {
-                            match hdc.set_vhd(*drive_idx, vhd) {
-                                Ok(_) => {
-                                    let vhd_name = emu.vhd_manager.get_vhd_name(*image_idx).unwrap();
-                                    log::info!(
-                                        "VHD image {:?} successfully loaded into virtual drive: {}",
-                                        vhd_name,
-                                        *drive_idx
-                                    );
-
-                                    emu.gui
-                                        .toasts()
-                                        .info(format!("VHD loaded: {:?}", vhd_name))
-                                        .set_duration(Some(NORMAL_NOTIFICATION_TIME));
-                                }
-                                Err(err) => {
-                                    error_str = Some(format!("Error mounting VHD: {}", err));
-                                }
+            let formats = match emu.machine.hdc() {
+                Some(hdc) => hdc.get_supported_formats(),
+                None => Vec::new(),
+            };
+            match emu.vhd_manager.load_disk(*drive_idx, *image_idx, None, false, &formats) {
+                Ok(disk) => {
+                    if let Some(hdc) = emu.machine.hdc() {
+                        match hdc.set_disk(*drive_idx, disk, None) {
+                            Ok(_) => {
+                                let vhd_name = emu.vhd_manager.get_vhd_name(*image_idx).unwrap();
+                                log::info!(
+                                    "VHD image {:?} successfully loaded into virtual drive: {}",
+                                    vhd_name,
+                                    *drive_idx
+                                );
+
+                                emu.gui
+                                    .toasts()
+                                    .info(format!("VHD loaded: {:?}", vhd_name))
+                                    .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+                            }
+                            Err(err) => {
+                                error_str = Some(format!("Error mounting VHD: {}", err));
                             }
-                        }
-                        else {
-                            error_str = Some("No Hard Disk Controller present!".to_string());
                         }
                     }
-                    Err(err) => {
-                        error_str = Some(format!("Error loading VHD: {}", err));
+                    else {
+                        error_str = Some("No Hard Disk Controller present!".to_string());
                     }
-                },
+                }
                 Err(err) => {
                     error_str = Some(format!("Failed to load VHD image index {}: {}", *image_idx, err));
                 }
@@ -335,9 +339,10 @@ pub fn handle_egui_event(emu: &mut Emulator, elwt: &EventLoopWindowTarget<()>, g
             if let Some(fdc) = emu.machine.fdc() {
                 let floppy = fdc.get_image_data(*drive_select);
                 if let Some(floppy_image) = floppy {
-                    match emu.floppy_manager.save_floppy_data(floppy_image, *image_idx, &emu.rm) {
+                    match emu.floppy_manager.save_floppy_data(&floppy_image, *image_idx, &emu.rm) {
                         Ok(path) => {
                             log::info!("Floppy image successfully saved: {:?}", path);
+                            fdc.clear_image_dirty(*drive_select);
 
                             emu.gui
                                 .toasts()
@@ -351,6 +356,59 @@ pub fn handle_egui_event(emu: &mut Emulator, elwt: &EventLoopWindowTarget<()>, g
                 }
             }
         }
+        GuiEvent::SaveFloppyAs(drive_select, image_idx, format) => {
+            log::debug!(
+                "Received SaveFloppyAs event image index: {}, drive: {}, format: {}",
+                image_idx,
+                drive_select,
+                format
+            );
+
+            let mut saved_path = None;
+            if let Some(fdc) = emu.machine.fdc() {
+                match fdc.get_image_data_as(*drive_select, *format) {
+                    Ok(floppy_image) => {
+                        match emu
+                            .floppy_manager
+                            .save_floppy_data_as(&floppy_image, *image_idx, format.extension(), &emu.rm)
+                        {
+                            Ok(path) => {
+                                log::info!("Floppy image successfully saved: {:?}", path);
+                                emu.gui
+                                    .toasts()
+                                    .info(format!("Floppy saved: {:?}", path.file_name()))
+                                    .set_duration(Some(SHORT_NOTIFICATION_TIME));
+                                saved_path = Some(path);
+                            }
+                            Err(err) => {
+                                log::warn!("Floppy image failed to save: {}", err);
+                                emu.gui
+                                    .toasts()
+                                    .error(format!("Floppy save failed: {}", err))
+                                    .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        log::warn!("Floppy image could not be converted to {}: {}", format, err);
+                        emu.gui
+                            .toasts()
+                            .error(format!("Floppy save failed: {}", err))
+                            .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+                    }
+                }
+            }
+
+            // Rescan so the new image appears in the media tree.
+            if saved_path.is_some() {
+                if let Err(e) = emu.floppy_manager.scan_resource(&emu.rm) {
+                    log::error!("Error scanning floppy directory: {}", e);
+                }
+                if let Ok(floppy_tree) = emu.floppy_manager.make_tree(&emu.rm) {
+                    emu.gui.set_floppy_tree(floppy_tree);
+                }
+            }
+        }
         GuiEvent::EjectFloppy(drive_select) => {
             log::info!("Ejecting floppy in drive: {}", drive_select);
             if let Some(fdc) = emu.machine.fdc() {
@@ -467,7 +525,56 @@ pub fn handle_egui_event(emu: &mut Emulator, elwt: &EventLoopWindowTarget<()>, g
                 }
             }
 
-            emu.machine.set_breakpoints(breakpoints);
+            // Push IO breakpoints to list
+            let (bp_io_read_str, bp_io_write_str) = emu.gui.get_io_breakpoints();
+            if let Ok(port) = u16::from_str_radix(bp_io_read_str.trim(), 16) {
+                breakpoints.push(BreakPointType::IoRead(port));
+            }
+            if let Some((port, value, mask)) = parse_io_write_breakpoint(bp_io_write_str) {
+                breakpoints.push(BreakPointType::IoWrite(port, value, mask));
+            }
+
+            // Push watchpoint to list
+            let (bp_watch_str, watch_type) = emu.gui.get_watchpoint();
+            let (watch_addr_str, watch_len_str) = bp_watch_str.split_once(',').unwrap_or((bp_watch_str, "1"));
+            if let (Some(addr), Ok(len)) = (
+                emu.machine.cpu().eval_address(watch_addr_str.trim()),
+                u32::from_str_radix(watch_len_str.trim(), 16),
+            ) {
+                let flat_addr = u32::from(addr);
+                if flat_addr < 0x100000 && len > 0 {
+                    breakpoints.push(BreakPointType::Watch(flat_addr, len.min(0x100000 - flat_addr), watch_type));
+                }
+            }
+
+            // Wrap breakpoints in a condition if one was specified. Skip setting breakpoints
+            // entirely while the condition doesn't parse, so we don't break unconditionally.
+            let (bp_cond_str, bp_hit_str) = emu.gui.get_breakpoint_condition();
+            let hit_count = bp_hit_str.trim().parse::<u32>().unwrap_or(0);
+            let condition = match bp_cond_str.trim() {
+                "" => Ok(None),
+                expr => BreakPointExpr::parse(expr).map(Some),
+            };
+
+            match condition {
+                Ok(expr) => {
+                    if expr.is_some() || hit_count > 1 {
+                        breakpoints = breakpoints
+                            .into_iter()
+                            .map(|bp| {
+                                BreakPointType::Conditional(
+                                    Box::new(bp),
+                                    BreakPointCondition::new(expr.clone(), hit_count),
+                                )
+                            })
+                            .collect();
+                    }
+                    emu.machine.set_breakpoints(breakpoints);
+                }
+                Err(e) => {
+                    log::debug!("Invalid breakpoint condition: {}", e);
+                }
+            }
         }
         GuiEvent::MemoryUpdate => {
             // The address bar for the memory viewer was updated. We need to
@@ -540,6 +647,12 @@ pub fn handle_egui_event(emu: &mut Emulator, elwt: &EventLoopWindowTarget<()>, g
             }
             emu.machine.change_state(*state);
         }
+        GuiEvent::ToggleAudioCapture => {
+            emu.toggle_audio_capture();
+        }
+        GuiEvent::ToggleVideoCapture => {
+            emu.toggle_video_capture();
+        }
         GuiEvent::TakeScreenshot(dt_idx) => {
             let screenshot_path = emu.rm.get_resource_path("screenshot").unwrap();
 
@@ -590,3 +703,23 @@ pub fn handle_egui_event(emu: &mut Emulator, elwt: &EventLoopWindowTarget<()>, g
         }
     }
 }
+
+/// Parse an IO write breakpoint of the form 'port[=value[/mask]]', all in hex. Without a value,
+/// the breakpoint matches any value written to the port.
+fn parse_io_write_breakpoint(bp_str: &str) -> Option<(u16, u8, u8)> {
+    let (port_str, value_str) = match bp_str.trim().split_once('=') {
+        Some((port_str, value_str)) => (port_str, Some(value_str)),
+        None => (bp_str.trim(), None),
+    };
+    let port = u16::from_str_radix(port_str.trim(), 16).ok()?;
+
+    match value_str {
+        Some(value_str) => {
+            let (value_str, mask_str) = value_str.split_once('/').unwrap_or((value_str, "FF"));
+            let value = u8::from_str_radix(value_str.trim(), 16).ok()?;
+            let mask = u8::from_str_radix(mask_str.trim(), 16).ok()?;
+            Some((port, value, mask))
+        }
+        None => Some((port, 0, 0)),
+    }
+}
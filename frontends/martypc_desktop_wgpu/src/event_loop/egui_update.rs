@@ -72,6 +72,16 @@ pub fn update_egui(emu: &mut Emulator, tm: &TimestepManager, elwt: &EventLoopWin
     // -- Update serial ports
     emu.gui.set_serial_ports(emu.machine.bus().enumerate_serial_ports());
 
+    emu.gui.set_audio_capturing(emu.machine.is_capturing_audio());
+    emu.gui.set_video_capturing(emu.machine.is_capturing_video());
+
+    // -- Update floppy modified state
+    if let Some(fdc) = emu.machine.fdc() {
+        for drive in 0..fdc.drive_ct() {
+            emu.gui.set_floppy_dirty(drive, fdc.is_image_dirty(drive));
+        }
+    }
+
     // -- Update VHD Creator window
     if emu.gui.is_window_open(GuiWindow::VHDCreator) {
         if let Some(hdc) = emu.machine.hdc() {
@@ -244,6 +244,14 @@ pub fn process_hotkeys(emu: &mut Emulator, keycode: KeyCode, pressed: bool, wind
                         .set_duration(Some(LONG_NOTIFICATION_TIME));
                 }
             }
+            HotkeyEvent::ToggleAudioCapture => {
+                log::debug!("ToggleAudioCapture hotkey triggered.");
+                emu.toggle_audio_capture();
+            }
+            HotkeyEvent::ToggleVideoCapture => {
+                log::debug!("ToggleVideoCapture hotkey triggered.");
+                emu.toggle_video_capture();
+            }
             HotkeyEvent::DebugStep => {
                 emu.exec_control.borrow_mut().set_op(ExecutionOperation::Step);
             }
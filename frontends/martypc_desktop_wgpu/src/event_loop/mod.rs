@@ -150,6 +150,38 @@ pub fn handle_event(emu: &mut Emulator, tm: &mut TimestepManager, event: Event<(
                 } => {
                     pass_to_egui = !handle_key_event(emu, window_id, key_event);
                 }
+                WindowEvent::CursorMoved { position, .. } => {
+                    emu.lightpen_data.window = Some(window_id);
+                    emu.lightpen_data.cursor = Some((position.x as f32, position.y as f32));
+                    pass_to_egui = true;
+                }
+                WindowEvent::CursorLeft { .. } => {
+                    emu.lightpen_data.cursor = None;
+                    pass_to_egui = true;
+                }
+                WindowEvent::MouseInput {
+                    state,
+                    button: winit::event::MouseButton::Left,
+                    ..
+                } => {
+                    // Press the light pen against the screen, unless the click is meant for the gui.
+                    let gui_wants_pointer = emu
+                        .dm
+                        .get_gui_by_window_id(window_id)
+                        .map_or(false, |gui| gui.wants_pointer());
+
+                    match state {
+                        ElementState::Pressed if !gui_wants_pointer => {
+                            emu.lightpen_data.is_pressed = true;
+                            emu.lightpen_data.was_pressed = true;
+                        }
+                        ElementState::Released => {
+                            emu.lightpen_data.is_pressed = false;
+                        }
+                        _ => {}
+                    }
+                    pass_to_egui = true;
+                }
                 WindowEvent::RedrawRequested => {
                     process_update(emu, tm, elwt);
                 }
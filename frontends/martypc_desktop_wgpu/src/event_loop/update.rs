@@ -101,6 +101,30 @@ pub fn process_update(emu: &mut Emulator, tm: &mut TimestepManager, elwt: &Event
                 }
             }
 
+            // Update the light pen from the host cursor
+            emuc.update_light_pen();
+
+            // Service the GDB remote debugger connection, if any
+            if let Some(gdb) = emuc.gdb.as_mut() {
+                gdb.poll(&mut emuc.machine, &mut emuc.exec_control.borrow_mut());
+            }
+
+            // Report watchpoint hits
+            if let Some(hit) = emuc.machine.take_watchpoint_hit() {
+                log::info!("WATCHPOINT: {}", hit);
+                if emuc.config.emulator.debugger.breakpoint_notify {
+                    emuc.gui
+                        .toasts()
+                        .info(format!("WATCHPOINT: {}", hit))
+                        .set_duration(Some(NORMAL_NOTIFICATION_TIME));
+                }
+                emuc.gui.set_watchpoint_hit(hit.to_string());
+            }
+
+            // Save any printer output and captured video frames
+            emuc.save_printer_output();
+            emuc.save_captured_frames();
+
             // Drain machine events
             while let Some(event) = emuc.machine.get_event() {
                 match event {
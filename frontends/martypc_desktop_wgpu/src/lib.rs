@@ -52,7 +52,7 @@ use std::{
     time::{Duration, Instant},
 };
 
-use crate::run_benchmark::run_benchmark;
+use crate::{run_benchmark::run_benchmark, run_headless::run_headless};
 
 #[cfg(feature = "arduino_validator")]
 use crate::{cpu_test::gen_tests::run_gentests, cpu_test::run_tests, run_fuzzer::run_fuzzer};
@@ -60,6 +60,7 @@ use crate::{cpu_test::gen_tests::run_gentests, cpu_test::run_tests, run_fuzzer::
 use config_toml_bpaf::TestMode;
 
 use marty_core::{
+    device_traits::videocard::VideoCardId,
     devices::keyboard::KeyboardModifiers,
     machine::{ExecutionControl, ExecutionState, MachineBuilder},
     sound::SoundPlayer,
@@ -68,11 +69,13 @@ use marty_core::{
 use display_manager_wgpu::{DisplayBackend, DisplayManager, DisplayManagerGuiOptions, WgpuDisplayManagerBuilder};
 use frontend_common::{
     floppy_manager::FloppyManager,
+    gdb_stub::GdbStub,
     resource_manager::ResourceManager,
     timestep_manager::TimestepManager,
     vhd_manager::VhdManager,
 };
 use marty_egui::state::GuiState;
+use winit::window::WindowId;
 
 #[cfg(feature = "cpu_validator")]
 use run_tests::run_runtests;
@@ -211,6 +214,30 @@ impl MouseData {
     }
 }
 
+/// Host cursor state used to drive the light pen.
+pub struct LightPenData {
+    pub enabled: bool,
+    pub window: Option<WindowId>,
+    pub cursor: Option<(f32, f32)>,
+    pub is_pressed: bool,
+    pub was_pressed: bool,
+    /// The card the pen was last held against, so it can be lifted from the same card.
+    pub card: Option<VideoCardId>,
+}
+
+impl LightPenData {
+    fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            window: None,
+            cursor: None,
+            is_pressed: false,
+            was_pressed: false,
+            card: None,
+        }
+    }
+}
+
 pub struct KeyboardData {
     pub modifiers:    KeyboardModifiers,
     pub ctrl_pressed: bool,
@@ -512,9 +539,47 @@ pub fn run() {
         );
     }
 
+    let machine_config = machine_config_file.to_machine_config();
+
+    let trace_file_base = resource_manager.get_resource_path("trace").unwrap_or_else(|| {
+        eprintln!("Failed to retrieve 'trace' resource path.");
+        std::process::exit(1);
+    });
+
+    let mut trace_file_path = None;
+    if let Some(trace_file) = &config.machine.cpu.trace_file {
+        log::info!("Using CPU trace log file: {:?}", trace_file);
+        trace_file_path = Some(trace_file_base.join(trace_file));
+    }
+
+    // Calculate the path to the keyboard layout file
+    let mut kb_layout_file_path = None;
+    let mut kb_string = "US".to_string();
+
+    if let Some(global_kb_string) = &config.machine.input.keyboard_layout {
+        kb_string = global_kb_string.clone()
+    }
+    else {
+        if let Some(keyboard) = machine_config.keyboard.as_ref() {
+            kb_string = keyboard.layout.clone();
+        }
+    }
+
+    if let Some(mut kb_layout_resource_path) = resource_manager.get_resource_path("keyboard_layout") {
+        kb_layout_resource_path.push(format!("keyboard_{}.toml", kb_string));
+        kb_layout_file_path = Some(kb_layout_resource_path);
+    }
+
     // If headless mode was specified, run the emulator in headless mode now
     if config.emulator.headless {
-        //return run_headless::run_headless(&config, rom_manager, floppy_manager);
+        return run_headless(
+            &config,
+            machine_config_file,
+            rom_manifest,
+            vhd_manager,
+            trace_file_path,
+            kb_layout_file_path,
+        );
     }
 
     // ----------------------------------------------------------------------------
@@ -544,6 +609,9 @@ pub fn run() {
         config.emulator.input.reverse_mouse_buttons
     );
 
+    // Light pen state, driven by the host cursor
+    let lightpen_data = LightPenData::new(config.emulator.input.light_pen);
+
     // Init sound
     let sound_player_opt = {
         if config.emulator.audio.enabled {
@@ -562,37 +630,6 @@ pub fn run() {
         }
     };
 
-    let machine_config = machine_config_file.to_machine_config();
-
-    let trace_file_base = resource_manager.get_resource_path("trace").unwrap_or_else(|| {
-        eprintln!("Failed to retrieve 'trace' resource path.");
-        std::process::exit(1);
-    });
-
-    let mut trace_file_path = None;
-    if let Some(trace_file) = &config.machine.cpu.trace_file {
-        log::info!("Using CPU trace log file: {:?}", trace_file);
-        trace_file_path = Some(trace_file_base.join(trace_file));
-    }
-
-    // Calculate the path to the keyboard layout file
-    let mut kb_layout_file_path = None;
-    let mut kb_string = "US".to_string();
-
-    if let Some(global_kb_string) = &config.machine.input.keyboard_layout {
-        kb_string = global_kb_string.clone()
-    }
-    else {
-        if let Some(keyboard) = machine_config.keyboard.as_ref() {
-            kb_string = keyboard.layout.clone();
-        }
-    }
-
-    if let Some(mut kb_layout_resource_path) = resource_manager.get_resource_path("keyboard_layout") {
-        kb_layout_resource_path.push(format!("keyboard_{}.toml", kb_string));
-        kb_layout_file_path = Some(kb_layout_resource_path);
-    }
-
     let machine_builder = MachineBuilder::new()
         .with_core_config(Box::new(&config))
         .with_machine_config(&machine_config)
@@ -658,6 +695,20 @@ pub fn run() {
 
     let machine_events = Vec::new();
 
+    // Start the GDB stub if requested. Failing to bind is not fatal.
+    let gdb = config
+        .emulator
+        .debugger
+        .gdb_stub
+        .as_ref()
+        .and_then(|address| match GdbStub::new(address) {
+            Ok(stub) => Some(stub),
+            Err(e) => {
+                log::error!("Failed to start GDB stub on {}: {}", address, e);
+                None
+            }
+        });
+
     // Put everything we want to handle in event loop into an Emulator struct
     let mut emu = Emulator {
         rm: resource_manager,
@@ -669,6 +720,7 @@ pub fn run() {
         machine_events,
         exec_control,
         mouse_data,
+        lightpen_data,
         kb_data,
         stat_counter,
         gui,
@@ -680,6 +732,9 @@ pub fn run() {
             debug_keyboard: false,
         },
         hkm: hotkey_manager,
+        gdb,
+        print_file: None,
+        video_capture_dir: None,
     };
 
     // Resize video cards
@@ -726,6 +781,18 @@ pub fn run() {
         std::process::exit(1);
     }
 
+    if let Err(_e) = emu.mount_host_dirs() {
+        log::error!("Failed to mount host directories!");
+        std::process::exit(1);
+    }
+
+    if let Some(capture_file) = emu.config.emulator.audio.capture_file.clone() {
+        if let Err(e) = emu.machine.start_audio_capture(&capture_file) {
+            log::error!("Failed to start audio capture: {}", e);
+            std::process::exit(1);
+        }
+    }
+
     // Start emulator
     emu.start();
 
@@ -26,112 +26,590 @@
 
     run_headless.rs - Implement the main procedure for headless mode.
 
+    Headless mode builds the same machine the GUI would, but runs it with no
+    window or audio device, as fast as possible, until one of the conditions
+    in [emulator.headless_exit] is met.
+
+    Exit codes:
+        0 - An exit condition was met.
+        1 - An error occurred, or the CPU halted and 'halt' was not an exit condition.
+        2 - The wall-clock timeout expired before any other condition was met.
+        3 - One or more frames did not match their golden image, or a requested frame or
+            golden image was not reached before the run ended.
+
+    Frames can be hashed, saved as PNG images or compared to golden images as
+    specified in [emulator.headless_video]. Images hold the raw palette index of
+    each pixel of the display field as 8-bit grayscale, so that they compare
+    exactly regardless of renderer settings. The VGA resolves its palette as it
+    draws, so VGA frames are saved as RGBA.
 */
 
-use config_toml_bpaf::ConfigFileParams;
-use frontend_common::floppy_manager::FloppyManager;
+use std::{
+    collections::BTreeSet,
+    ffi::OsString,
+    fmt,
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-#[allow(dead_code)]
-pub fn run_headless(_config: &ConfigFileParams, _floppy_manager: FloppyManager) {
+use anyhow::anyhow;
+use config_toml_bpaf::{ConfigFileParams, HeadlessVideo, HostDirDevice};
+use frontend_common::{machine_manager::MachineConfigFileEntry, vhd_manager::VhdManager};
+use marty_core::{
+    breakpoints::BreakPointType,
+    cpu_common::CpuOption,
+    hdd_image::host_dir::HostDirDisk,
+    machine::{
+        CapturedFrame,
+        ExecutionControl,
+        ExecutionState,
+        Machine,
+        MachineBuilder,
+        MachineEvent,
+        MachineRomManifest,
+        MachineState,
+    },
+};
 
-    /*
+/// Emulated frames per second used to size each batch of CPU cycles. Exit conditions that
+/// are polled (memory value, wall time) are checked once per batch.
+const HEADLESS_BATCH_RATE: f64 = 60.0;
 
-    // Init sound
-    // The cpal sound library uses generics to initialize depending on the SampleFormat type.
-    // On Windows at least a sample type of f32 is typical, but just in case...
-    let sample_fmt = SoundPlayer::get_sample_format();
-    let sp = match sample_fmt {
-        cpal::SampleFormat::F32 => SoundPlayer::new::<f32>(),
-        cpal::SampleFormat::I16 => SoundPlayer::new::<i16>(),
-        cpal::SampleFormat::U16 => SoundPlayer::new::<u16>(),
-    };
+#[derive(Debug)]
+enum HeadlessExitReason {
+    Cycles(u64),
+    Frames(u64),
+    Timeout(u32),
+    Halted,
+    Checkpoint(u32),
+    MemoryValue(u32, u8),
+}
 
-    // Look up the machine description given the machine type in the configuration file
-    let machine_desc_opt = MACHINE_DESCS.get(&config.machine.model);
-    if let Some(machine_desc) = machine_desc_opt {
-        log::debug!(
-            "Given machine type {:?} got machine description: {:?}",
-            config.machine.model,
-            machine_desc
-        );
+impl fmt::Display for HeadlessExitReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HeadlessExitReason::Cycles(c) => write!(f, "reached {} cycles", c),
+            HeadlessExitReason::Frames(n) => write!(f, "reached {} frames", n),
+            HeadlessExitReason::Timeout(t) => write!(f, "timed out after {} seconds", t),
+            HeadlessExitReason::Halted => write!(f, "CPU halted"),
+            HeadlessExitReason::Checkpoint(addr) => write!(f, "reached checkpoint [{:05X}]", addr),
+            HeadlessExitReason::MemoryValue(addr, val) => {
+                write!(f, "memory at [{:05X}] has value {:02X}", addr, val)
+            }
+        }
     }
-    else {
-        log::error!("Couldn't get machine description for {:?}", config.machine.model);
+}
 
-        eprintln!(
-            "Couldn't get machine description for machine type {:?}. \
-             Check that you have a valid machine type specified in configuration file.",
-            config.machine.model
-        );
+impl HeadlessExitReason {
+    fn exit_code(&self) -> i32 {
+        match self {
+            HeadlessExitReason::Timeout(_) => 2,
+            _ => 0,
+        }
+    }
+}
+
+/// Hashes, saves and compares the frames selected in [emulator.headless_video].
+struct HeadlessVideoOutput {
+    frames: Vec<u64>,
+    hash_log: Option<BufWriter<File>>,
+    png_dir: Option<PathBuf>,
+    golden_dir: Option<PathBuf>,
+    mismatches: u32,
+    /// Frame numbers that were captured and output.
+    processed: BTreeSet<u64>,
+}
+
+impl HeadlessVideoOutput {
+    /// Returns None if no video output was requested.
+    fn new(video: &HeadlessVideo) -> Result<Option<Self>, anyhow::Error> {
+        if video.hash_log.is_none() && video.png_dir.is_none() && video.golden_dir.is_none() {
+            return Ok(None);
+        }
+        let hash_log = match &video.hash_log {
+            Some(path) => Some(BufWriter::new(
+                File::create(path).map_err(|e| anyhow!("Couldn't create hash log {}: {}", path.display(), e))?,
+            )),
+            None => None,
+        };
+        if let Some(dir) = &video.png_dir {
+            std::fs::create_dir_all(dir).map_err(|e| anyhow!("Couldn't create {}: {}", dir.display(), e))?;
+        }
+        Ok(Some(Self {
+            frames: video.frames.clone(),
+            hash_log,
+            png_dir: video.png_dir.clone(),
+            golden_dir: video.golden_dir.clone(),
+            mismatches: 0,
+            processed: BTreeSet::new(),
+        }))
+    }
+
+    fn process(&mut self, frame: &CapturedFrame) -> Result<(), anyhow::Error> {
+        if !self.frames.is_empty() && !self.frames.contains(&frame.number) {
+            return Ok(());
+        }
+        self.processed.insert(frame.number);
+        let (w, h) = (frame.extents.field_w, frame.extents.field_h);
+        let pixels = frame.extents.pack_field(&frame.buf);
+        let rgba = frame.extents.bytes_per_pixel == 4;
+        let color_type = match rgba {
+            true => image::ColorType::Rgba8,
+            false => image::ColorType::L8,
+        };
+        let filename = format!("frame{:06}.png", frame.number);
+
+        if let Some(log) = &mut self.hash_log {
+            writeln!(log, "{:06} {}", frame.number, frame.extents.hash_field(&frame.buf))?;
+        }
+        if let Some(dir) = &self.png_dir {
+            image::save_buffer(dir.join(&filename), &pixels, w, h, color_type)?;
+        }
+        if let Some(dir) = &self.golden_dir {
+            let golden_path = dir.join(&filename);
+            match image::open(&golden_path) {
+                Ok(golden) => {
+                    let (golden_dims, golden_pixels) = match rgba {
+                        true => {
+                            let golden = golden.into_rgba8();
+                            (golden.dimensions(), golden.into_raw())
+                        }
+                        false => {
+                            let golden = golden.into_luma8();
+                            (golden.dimensions(), golden.into_raw())
+                        }
+                    };
+                    if golden_dims != (w, h) || golden_pixels != pixels {
+                        eprintln!("Frame {} differs from golden image {}", frame.number, golden_path.display());
+                        self.mismatches += 1;
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Couldn't read golden image {}: {}", golden_path.display(), e);
+                    self.mismatches += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the frame numbers of the golden images in the golden directory. Golden images for
+    /// frames that were not requested are ignored.
+    fn golden_frames(&self, dir: &Path) -> Result<BTreeSet<u64>, anyhow::Error> {
+        let mut frames = BTreeSet::new();
+        for entry in std::fs::read_dir(dir)? {
+            let name = entry?.file_name();
+            let number = name
+                .to_str()
+                .and_then(|name| name.strip_prefix("frame"))
+                .and_then(|name| name.strip_suffix(".png"))
+                .and_then(|number| number.parse::<u64>().ok());
+            if let Some(number) = number {
+                if self.frames.is_empty() || self.frames.contains(&number) {
+                    frames.insert(number);
+                }
+            }
+        }
+        Ok(frames)
+    }
+
+    /// Flush the hash log and return the number of frames that did not match their golden image.
+    /// Requested frames and golden images that were never reached, because the run ended first,
+    /// count as mismatches.
+    fn finish(&mut self) -> u32 {
+        if let Some(log) = &mut self.hash_log {
+            if let Err(e) = log.flush() {
+                eprintln!("Failed to write hash log: {}", e);
+            }
+        }
+
+        let mut expected: BTreeSet<u64> = self.frames.iter().copied().collect();
+        if let Some(dir) = &self.golden_dir {
+            match self.golden_frames(dir) {
+                Ok(frames) => expected.extend(frames),
+                Err(e) => {
+                    eprintln!("Couldn't read golden directory {}: {}", dir.display(), e);
+                    self.mismatches += 1;
+                }
+            }
+        }
+        for number in expected.difference(&self.processed) {
+            eprintln!("Frame {} was not reached before the run ended", number);
+            self.mismatches += 1;
+        }
+        self.mismatches
+    }
+}
+
+pub fn run_headless(
+    config: &ConfigFileParams,
+    machine_config_file: &MachineConfigFileEntry,
+    rom_manifest: MachineRomManifest,
+    vhd_manager: VhdManager,
+    trace_file_path: Option<PathBuf>,
+    kb_layout_file_path: Option<PathBuf>,
+) {
+    let machine_config = machine_config_file.to_machine_config();
+
+    let machine_builder = MachineBuilder::new()
+        .with_core_config(Box::new(config))
+        .with_machine_config(&machine_config)
+        .with_roms(rom_manifest)
+        .with_trace_mode(config.machine.cpu.trace_mode.unwrap_or_default())
+        .with_trace_log(trace_file_path)
+        .with_sound_player(None)
+        .with_keyboard_layout(kb_layout_file_path);
+
+    let mut machine = machine_builder.build().unwrap_or_else(|e| {
+        log::error!("Failed to build machine: {:?}", e);
         std::process::exit(1);
+    });
+
+    apply_config(config, &mut machine);
+    mount_vhds(config, &mut machine, vhd_manager);
+    mount_host_dirs(config, &mut machine);
+
+    if let Some(capture_file) = &config.emulator.audio.capture_file {
+        if let Err(e) = machine.start_audio_capture(capture_file) {
+            eprintln!("Failed to start audio capture: {}", e);
+            std::process::exit(1);
+        }
     }
 
-    let (video_type, _clock_mode, _video_debug) = {
-        let mut video_type: Option<VideoType> = None;
-        let mut clock_mode: Option<ClockingMode> = None;
-        let video_cards = config.get_video_cards();
-        if video_cards.len() > 0 {
-            clock_mode = video_cards[0].clocking_mode;
-            video_type = Some(video_cards[0].video_type); // Videotype is not optional
+    let exit = &config.emulator.headless_exit;
+    if let Some(addr) = exit.checkpoint {
+        machine.set_breakpoints(vec![BreakPointType::ExecuteFlat(addr & 0xFFFFF)]);
+    }
+    let memory_watch = match (exit.memory_address, exit.memory_value) {
+        (Some(addr), Some(val)) => Some((addr as usize & 0xFFFFF, val)),
+        (None, None) => None,
+        _ => {
+            eprintln!("Headless exit condition requires both memory_address and memory_value.");
+            std::process::exit(1);
         }
-        (
-            video_type.unwrap_or(VideoType::CGA),
-            clock_mode.unwrap_or_default(),
-            video_cards[0].debug.unwrap_or(false),
-        )
     };
 
-    // Instantiate the main Machine data struct
-    // Machine coordinates all the parts of the emulated computer
-    let mut machine = Machine::new(
-        config,
-        config.machine.model,
-        *machine_desc_opt.unwrap(),
-        config.machine.cpu.trace_mode.unwrap_or_default(),
-        video_type,
-        sp,
-        rom_manager,
-    );
+    let mut video_out = HeadlessVideoOutput::new(&config.emulator.headless_video).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    if exit.frames.is_some() || video_out.is_some() {
+        if let Err(e) = machine.start_video_capture() {
+            eprintln!("Failed to start video capture: {}", e);
+            std::process::exit(1);
+        }
+    }
 
-    // Load program binary if one was specified in config options
-    if let Some(prog_bin) = &config.emulator.run_bin {
-        if let Some(prog_seg) = config.emulator.run_bin_seg {
-            if let Some(prog_ofs) = config.emulator.run_bin_ofs {
-                let prog_vec = match std::fs::read(prog_bin.clone()) {
-                    Ok(vec) => vec,
-                    Err(e) => {
-                        eprintln!("Error opening filename {:?}: {}", prog_bin, e);
-                        std::process::exit(1);
-                    }
-                };
+    if exit.cycles.is_none()
+        && exit.frames.is_none()
+        && exit.timeout.is_none()
+        && exit.checkpoint.is_none()
+        && memory_watch.is_none()
+    {
+        log::warn!("No headless exit conditions specified. Running until interrupted.");
+    }
+
+    let mut exec_control = ExecutionControl::new();
+    exec_control.set_state(ExecutionState::Running);
+
+    let batch_cycles = ((machine.get_cpu_mhz() * 1_000_000.0) / HEADLESS_BATCH_RATE) as u64;
+    let timeout = exit.timeout.map(|t| Duration::from_secs(t as u64));
+    let mut cycles_run: u64 = 0;
+    let mut frames_run: u64 = 0;
+
+    println!("Running headless.");
+    let run_start = Instant::now();
 
-                if let Err(_) = machine.load_program(&prog_vec, prog_seg, prog_ofs) {
-                    eprintln!(
-                        "Error loading program into memory at {:04X}:{:04X}.",
-                        prog_seg, prog_ofs
+    let reason = loop {
+        let mut batch = batch_cycles;
+        if let Some(cycle_target) = exit.cycles {
+            batch = std::cmp::min(batch, cycle_target.saturating_sub(cycles_run));
+        }
+        if batch > 0 {
+            let start_cycles = machine.cpu_cycles();
+            machine.run(batch as u32, &mut exec_control);
+            cycles_run += machine.cpu_cycles() - start_cycles;
+        }
+
+        match exec_control.get_state() {
+            ExecutionState::BreakpointHit => {
+                // The only breakpoint we install is the checkpoint.
+                break HeadlessExitReason::Checkpoint(exit.checkpoint.unwrap_or_default() & 0xFFFFF);
+            }
+            ExecutionState::Halted => {
+                if exit.halt {
+                    break HeadlessExitReason::Halted;
+                }
+                eprintln!("Machine halted during headless run!");
+                _ = machine.stop_audio_capture();
+                std::process::exit(1);
+            }
+            _ => {}
+        }
+
+        let mut halted = false;
+        while let Some(event) = machine.get_event() {
+            match event {
+                MachineEvent::CheckpointHit(checkpoint, _) => {
+                    log::info!(
+                        "CHECKPOINT: {}",
+                        machine
+                            .get_checkpoint_string(checkpoint)
+                            .unwrap_or("ERROR".to_string())
                     );
+                }
+                MachineEvent::Halted => halted = true,
+                MachineEvent::Reset => {}
+            }
+        }
+        if halted && exit.halt {
+            break HeadlessExitReason::Halted;
+        }
+
+        _ = machine.frame_update();
+
+        for frame in machine.take_captured_frames() {
+            if exit.frames.is_some_and(|target| frame.number >= target) {
+                continue;
+            }
+            frames_run = frame.number + 1;
+            if let Some(video_out) = &mut video_out {
+                if let Err(e) = video_out.process(&frame) {
+                    eprintln!("Failed to process frame {}: {}", frame.number, e);
                     std::process::exit(1);
-                };
+                }
+            }
+        }
+
+        if let Some((addr, val)) = memory_watch {
+            if let Ok(byte) = machine.bus().peek_u8(addr) {
+                if byte == val {
+                    break HeadlessExitReason::MemoryValue(addr as u32, val);
+                }
+            }
+        }
+
+        if let Some(cycle_target) = exit.cycles {
+            if cycles_run >= cycle_target {
+                break HeadlessExitReason::Cycles(cycles_run);
+            }
+        }
+
+        if let Some(frame_target) = exit.frames {
+            if frames_run >= frame_target {
+                break HeadlessExitReason::Frames(frames_run);
             }
-            else {
-                eprintln!("Must specifiy program load offset.");
+        }
+
+        if let Some(timeout) = timeout {
+            if run_start.elapsed() >= timeout {
+                break HeadlessExitReason::Timeout(timeout.as_secs() as u32);
+            }
+        }
+    };
+
+    let run_duration = run_start.elapsed();
+    println!(
+        "Headless run ended: {}.\nRan {} cycles and {} instructions in {:.4} seconds.",
+        reason,
+        machine.cpu_cycles(),
+        machine.cpu_instructions(),
+        run_duration.as_secs_f64()
+    );
+
+    machine.flush_trace_logs();
+    if let Err(e) = machine.stop_audio_capture() {
+        eprintln!("Failed to finish audio capture: {}", e);
+    }
+    _ = machine.stop_video_capture();
+    if let Some(mut video_out) = video_out {
+        let mismatches = video_out.finish();
+        if mismatches > 0 {
+            eprintln!("{} frame(s) were missing or did not match their golden image.", mismatches);
+            std::process::exit(3);
+        }
+    }
+    std::process::exit(reason.exit_code());
+}
+
+/// Apply the subset of the configuration that the GUI applies to the machine at startup.
+fn apply_config(config: &ConfigFileParams, machine: &mut Machine) {
+    machine.change_state(MachineState::On);
+
+    machine.pit_adjust(config.machine.pit_phase.unwrap_or(0) & 0x03);
+
+    machine.set_cpu_option(CpuOption::OffRailsDetection(
+        config.machine.cpu.off_rails_detection.unwrap_or(false),
+    ));
+    machine.set_cpu_option(CpuOption::EnableServiceInterrupt(
+        config.machine.cpu.service_interrupt.unwrap_or(false),
+    ));
+    machine.set_cpu_option(CpuOption::EnableWaitStates(
+        config.machine.cpu.wait_states.unwrap_or(true),
+    ));
+    machine.set_cpu_option(CpuOption::InstructionHistory(
+        config.machine.cpu.instruction_history.unwrap_or(false),
+    ));
+    machine.set_cpu_option(CpuOption::TraceLoggingEnabled(config.machine.cpu.trace_on));
+
+    // Load program binary if one was specified in config options
+    if let Some(prog_bin) = &config.emulator.run_bin {
+        let (prog_seg, prog_ofs) = match (config.emulator.run_bin_seg, config.emulator.run_bin_ofs) {
+            (Some(seg), Some(ofs)) => (seg, ofs),
+            _ => {
+                eprintln!("Must specify program load segment and offset.");
                 std::process::exit(1);
             }
+        };
+
+        let prog_vec = std::fs::read(prog_bin).unwrap_or_else(|e| {
+            eprintln!("Error opening filename {:?}: {}", prog_bin, e);
+            std::process::exit(1);
+        });
+
+        if let Err(_) = machine.load_program(&prog_vec, prog_seg, prog_ofs) {
+            eprintln!(
+                "Error loading program into memory at {:04X}:{:04X}.",
+                prog_seg, prog_ofs
+            );
+            std::process::exit(1);
+        };
+    }
+}
+
+/// Mount VHD images the same way as the GUI: images from the main configuration override images
+/// from the machine configuration, drive by drive. Any failure is fatal, as a headless run with
+/// a missing disk would not produce a meaningful result.
+fn mount_vhds(config: &ConfigFileParams, machine: &mut Machine, mut vhd_manager: VhdManager) {
+    // Each entry is an image name, optional raw image geometry, and overlay flag.
+    let mut vhd_names: Vec<Option<(String, Option<(u16, u8, u8)>, bool)>> = Vec::new();
+    if let Some(controller) = machine.config().hdc.as_ref() {
+        for drive in controller.drive.as_ref().unwrap_or(&Vec::new()) {
+            vhd_names.push(drive.vhd.clone().map(|name| (name, None, false)));
+        }
+    }
+
+    for (drive_i, vhd) in config.emulator.media.vhd.as_ref().unwrap_or(&Vec::new()).iter().enumerate() {
+        let entry = Some((vhd.filename.clone(), vhd.geometry(), vhd.overlay));
+        if drive_i >= vhd_names.len() {
+            vhd_names.push(entry);
         }
         else {
-            eprintln!("Must specifiy program load segment.");
+            vhd_names[drive_i] = entry;
+        }
+    }
+
+    for (drive_idx, (vhd_name, geometry, overlay)) in vhd_names.into_iter().flatten().enumerate() {
+        let vhd_os_name: OsString = vhd_name.into();
+
+        let hdc = match machine.hdc() {
+            Some(hdc) => hdc,
+            None => {
+                eprintln!("Couldn't load VHD: No Hard Disk Controller present!");
+                std::process::exit(1);
+            }
+        };
+
+        let formats = hdc.get_supported_formats();
+        let (disk, _) = vhd_manager
+            .load_disk_by_name(drive_idx, &vhd_os_name, geometry, overlay, &formats)
+            .unwrap_or_else(|e| {
+                eprintln!("Failed to load VHD image {:?}: {}", vhd_os_name, e);
+                std::process::exit(1);
+            });
+
+        if let Err(e) = hdc.set_disk(drive_idx, disk, geometry) {
+            eprintln!("Error mounting VHD {:?}: {}", vhd_os_name, e);
             std::process::exit(1);
         }
+        log::info!(
+            "VHD image {:?} successfully loaded into virtual drive: {}",
+            vhd_os_name,
+            drive_idx
+        );
     }
+}
 
-    let mut exec_control = ExecutionControl::new();
-    exec_control.set_state(ExecutionState::Running);
+/// Mount host directories as floppy or hard disks, after VHDs. Any failure is fatal.
+fn mount_host_dirs(config: &ConfigFileParams, machine: &mut Machine) {
+    for entry in config.emulator.media.host_dir.as_ref().unwrap_or(&Vec::new()) {
+        let result = match entry.device {
+            HostDirDevice::Floppy => match machine.fdc() {
+                Some(fdc) => fdc.load_host_dir(entry.drive, &entry.path, entry.read_only),
+                None => Err(anyhow!("No floppy controller present!")),
+            },
+            HostDirDevice::Hdd => match machine.hdc() {
+                Some(hdc) => {
+                    let geometry = entry.geometry().or_else(|| {
+                        hdc.get_supported_formats()
+                            .first()
+                            .map(|f| (f.max_cylinders, f.max_heads, f.max_sectors))
+                    });
+                    match geometry {
+                        Some((c, h, s)) => HostDirDisk::new(&entry.path, c, h, s, entry.read_only)
+                            .and_then(|disk| Ok(hdc.set_disk(entry.drive, Box::new(disk), entry.geometry())?)),
+                        None => Err(anyhow!("No hard disk geometry available")),
+                    }
+                }
+                None => Err(anyhow!("No hard disk controller present!")),
+            },
+        };
 
-    loop {
-        // This should really return a Result
-        machine.run(1000, &mut exec_control);
+        if let Err(e) = result {
+            eprintln!("Failed to mount host directory {}: {}", entry.path.display(), e);
+            std::process::exit(1);
+        }
+        log::info!(
+            "Host directory {} mounted as {:?} drive: {}",
+            entry.path.display(),
+            entry.device,
+            entry.drive
+        );
     }
-    */
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    //std::process::exit(0);
+    fn video_output(frames: Vec<u64>, golden_dir: Option<PathBuf>) -> HeadlessVideoOutput {
+        HeadlessVideoOutput {
+            frames,
+            hash_log: None,
+            png_dir: None,
+            golden_dir,
+            mismatches: 0,
+            processed: BTreeSet::new(),
+        }
+    }
+
+    #[test]
+    fn test_missing_frames() {
+        // Requested frames that are never captured are mismatches.
+        let mut out = video_output(vec![1, 2], None);
+        out.processed.insert(1);
+        assert_eq!(out.finish(), 1);
+
+        // So are golden images that are never compared, unless their frame was not requested.
+        let dir = std::env::temp_dir().join(format!("martypc_headless_golden_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["frame000001.png", "frame000005.png", "notes.txt"] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+
+        let mut out = video_output(Vec::new(), Some(dir.clone()));
+        out.processed.insert(1);
+        assert_eq!(out.finish(), 1);
+
+        let mut out = video_output(vec![1], Some(dir.clone()));
+        out.processed.insert(1);
+        assert_eq!(out.finish(), 0);
+
+        let mut out = video_output(vec![3], Some(dir.clone()));
+        assert_eq!(out.finish(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
 }
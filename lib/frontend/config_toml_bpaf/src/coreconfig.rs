@@ -40,6 +40,7 @@ use marty_core::{
     coreconfig::CoreConfig,
     cpu_common::TraceMode,
     cpu_validator::ValidatorType,
+    devices::pc_speaker::SpeakerFilter,
     machine_types::{MachineType, OnHaltBehavior},
 };
 
@@ -65,6 +66,9 @@ impl CoreConfig for ConfigFileParams {
     fn get_audio_enabled(&self) -> bool {
         self.emulator.audio.enabled
     }
+    fn get_speaker_filter(&self) -> SpeakerFilter {
+        self.emulator.audio.speaker_filter
+    }
     fn get_machine_noroms(&self) -> bool {
         self.machine.no_roms
     }
@@ -37,7 +37,12 @@ use std::{
     str::FromStr,
 };
 
-use marty_core::{cpu_common::TraceMode, cpu_validator::ValidatorType, machine_types::OnHaltBehavior};
+use marty_core::{
+    cpu_common::TraceMode,
+    cpu_validator::ValidatorType,
+    devices::pc_speaker::SpeakerFilter,
+    machine_types::OnHaltBehavior,
+};
 
 use frontend_common::{
     display_scaler::ScalerPreset,
@@ -95,6 +100,54 @@ impl FromStr for TestMode {
 pub struct VhdConfigEntry {
     pub drive:    usize,
     pub filename: String,
+    /// Geometry of a raw sector image. Not needed for VHDs, or for raw images whose size
+    /// matches a supported drive type.
+    pub cylinders: Option<u16>,
+    pub heads: Option<u8>,
+    pub sectors: Option<u8>,
+    /// Keep writes in memory and leave the image unmodified.
+    #[serde(default)]
+    pub overlay: bool,
+}
+
+impl VhdConfigEntry {
+    pub fn geometry(&self) -> Option<(u16, u8, u8)> {
+        match (self.cylinders, self.heads, self.sectors) {
+            (Some(c), Some(h), Some(s)) => Some((c, h, s)),
+            _ => None,
+        }
+    }
+}
+
+/// The type of drive a host directory is mounted as.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum HostDirDevice {
+    Floppy,
+    Hdd,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HostDirConfigEntry {
+    pub path:   PathBuf,
+    pub device: HostDirDevice,
+    pub drive:  usize,
+    /// Don't write changes made by the guest back to the host directory.
+    #[serde(default)]
+    pub read_only: bool,
+    /// Geometry of a hard disk. Defaults to the first drive type the controller supports.
+    pub cylinders: Option<u16>,
+    pub heads: Option<u8>,
+    pub sectors: Option<u8>,
+}
+
+impl HostDirConfigEntry {
+    pub fn geometry(&self) -> Option<(u16, u8, u8)> {
+        match (self.cylinders, self.heads, self.sectors) {
+            (Some(c), Some(h), Some(s)) => Some((c, h, s)),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -103,12 +156,17 @@ pub struct Media {
     #[serde(default)]
     pub write_protect_default: bool,
     pub vhd: Option<Vec<VhdConfigEntry>>,
+    pub host_dir: Option<Vec<HostDirConfigEntry>>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Audio {
     #[serde(default = "_default_true")]
     pub enabled: bool,
+    #[serde(default)]
+    pub speaker_filter: SpeakerFilter,
+    /// Record the machine's audio output to this WAV file from startup.
+    pub capture_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -116,6 +174,7 @@ pub struct Debugger {
     pub checkpoint_notify_level: Option<u32>,
     #[serde(default)]
     pub breakpoint_notify: bool,
+    pub gdb_stub: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -163,6 +222,10 @@ pub struct Emulator {
     pub scaler_preset: Vec<ScalerPreset>,
     pub input: EmulatorInput,
     pub benchmark: Benchmark,
+    #[serde(default)]
+    pub headless_exit: HeadlessExit,
+    #[serde(default)]
+    pub headless_video: HeadlessVideo,
 }
 
 #[derive(Debug, Deserialize)]
@@ -194,6 +257,31 @@ pub struct Benchmark {
     pub cycles: Option<u64>,
 }
 
+/// Conditions that end a headless run. Whichever condition is met first ends the run.
+#[derive(Debug, Default, Deserialize)]
+pub struct HeadlessExit {
+    pub cycles: Option<u64>,
+    pub frames: Option<u64>,
+    pub timeout: Option<u32>,
+    #[serde(default)]
+    pub halt: bool,
+    pub checkpoint: Option<u32>,
+    pub memory_address: Option<u32>,
+    pub memory_value: Option<u8>,
+}
+
+/// Video output of a headless run, for visual regression testing. Frames are numbered from
+/// the first frame completed after power-on.
+#[derive(Debug, Default, Deserialize)]
+pub struct HeadlessVideo {
+    /// Frame numbers to hash, save or compare. If empty, every frame is used.
+    #[serde(default)]
+    pub frames: Vec<u64>,
+    pub hash_log: Option<PathBuf>,
+    pub png_dir: Option<PathBuf>,
+    pub golden_dir: Option<PathBuf>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Tests {
     pub test_mode: Option<TestMode>,
@@ -251,6 +339,8 @@ pub struct Machine {
 pub struct EmulatorInput {
     #[serde(default)]
     pub reverse_mouse_buttons: bool,
+    #[serde(default)]
+    pub light_pen: bool,
     pub hotkeys: Vec<HotkeyConfigEntry>,
     #[serde(default)]
     pub debug_keyboard: bool,
@@ -295,10 +385,29 @@ pub struct CmdLineArgs {
     #[bpaf(long, switch)]
     pub noaudio: bool,
 
+    #[bpaf(long)]
+    pub record_audio: Option<PathBuf>,
+
     // Emulator options
     #[bpaf(long, switch)]
     pub headless: bool,
 
+    #[bpaf(long)]
+    pub headless_cycles: Option<u64>,
+    #[bpaf(long)]
+    pub headless_timeout: Option<u32>,
+    #[bpaf(long)]
+    pub headless_frames: Option<u64>,
+    #[bpaf(long)]
+    pub headless_hash_log: Option<PathBuf>,
+    #[bpaf(long)]
+    pub headless_png_dir: Option<PathBuf>,
+    #[bpaf(long)]
+    pub headless_golden_dir: Option<PathBuf>,
+
+    #[bpaf(long)]
+    pub gdb: Option<String>,
+
     #[bpaf(long, switch)]
     pub fuzzer: bool,
 
@@ -380,11 +489,35 @@ impl ConfigFileParams {
 
         self.emulator.benchmark_mode |= shell_args.benchmark_mode;
         self.emulator.headless |= shell_args.headless;
+        if let Some(cycles) = shell_args.headless_cycles {
+            self.emulator.headless_exit.cycles = Some(cycles);
+        }
+        if let Some(timeout) = shell_args.headless_timeout {
+            self.emulator.headless_exit.timeout = Some(timeout);
+        }
+        if let Some(frames) = shell_args.headless_frames {
+            self.emulator.headless_exit.frames = Some(frames);
+        }
+        if let Some(hash_log) = shell_args.headless_hash_log {
+            self.emulator.headless_video.hash_log = Some(hash_log);
+        }
+        if let Some(png_dir) = shell_args.headless_png_dir {
+            self.emulator.headless_video.png_dir = Some(png_dir);
+        }
+        if let Some(golden_dir) = shell_args.headless_golden_dir {
+            self.emulator.headless_video.golden_dir = Some(golden_dir);
+        }
+        if let Some(gdb) = shell_args.gdb {
+            self.emulator.debugger.gdb_stub = Some(gdb);
+        }
         self.emulator.fuzzer |= shell_args.fuzzer;
         self.emulator.auto_poweron |= shell_args.auto_poweron;
         self.emulator.warpspeed |= shell_args.warpspeed;
         self.emulator.title_hacks |= shell_args.title_hacks;
         self.emulator.audio.enabled &= !shell_args.noaudio;
+        if let Some(record_audio) = shell_args.record_audio {
+            self.emulator.audio.capture_file = Some(record_audio);
+        }
 
         //self.emulator.scaler_aspect_correction |= shell_args.scaler_aspect_correction;
         self.emulator.debug_mode |= shell_args.debug_mode;
@@ -893,6 +893,15 @@ impl DisplayManager<PixelsBackend, GuiRenderContext, WindowId, Window> for WgpuD
         info_vec
     }
 
+    fn window_to_field(&self, wid: WindowId, x: f32, y: f32, machine: &Machine) -> Option<(VideoCardId, (u32, u32))> {
+        let vt = &self.targets[*self.window_id_map.get(&wid)?];
+        let vid = vt.card_id?;
+        let (u, v) = vt.scaler.as_ref()?.surface_to_texture(x, y)?;
+        let card = machine.bus().video(&vid)?;
+        let pos = vt.renderer.as_ref()?.image_to_field(u, v, card.get_display_extents());
+        Some((vid, pos))
+    }
+
     fn get_window_by_id(&self, wid: WindowId) -> Option<&Window> {
         self.window_id_map.get(&wid).and_then(|idx| {
             //log::warn!("got id, running map():");
@@ -166,6 +166,12 @@ pub trait DisplayManager<B, G, Wi, W> {
     /// to a Machine must be provided to query video card parameters.
     fn get_display_info(&self, machine: &Machine) -> Vec<DisplayInfo>;
 
+    /// Map a position within the specified Window, in physical pixels, to a position on the display
+    /// field of the video card shown in that Window, in the same coordinate space as the card's beam
+    /// position. A reference to a Machine must be provided to query the card's display extents.
+    /// Returns the card's VideoCardId and field position, or None if the position is off the image.
+    fn window_to_field(&self, wid: Wi, x: f32, y: f32, machine: &Machine) -> Option<(VideoCardId, (u32, u32))>;
+
     /// Return the associated Window given a Window id.
     fn get_window_by_id(&self, wid: Wi) -> Option<&W>;
 
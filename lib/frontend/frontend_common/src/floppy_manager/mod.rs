@@ -42,6 +42,10 @@ use std::{
 
 use anyhow::Error;
 
+/// Extensions of track-based image formats. These are recognized in addition to the configured
+/// raw sector image extensions.
+pub const TRACK_IMAGE_EXTENSIONS: [&str; 2] = ["imd", "td0"];
+
 #[derive(Debug)]
 pub enum FloppyError {
     DirNotFound,
@@ -82,7 +86,11 @@ impl FloppyManager {
             files: Vec::new(),
             image_vec: Vec::new(),
             image_map: HashMap::new(),
-            extensions: vec![OsString::from("img"), OsString::from("ima")],
+            extensions: ["img", "ima"]
+                .iter()
+                .chain(TRACK_IMAGE_EXTENSIONS.iter())
+                .map(OsString::from)
+                .collect(),
         }
     }
 
@@ -91,6 +99,7 @@ impl FloppyManager {
             self.extensions = extensions
                 .iter()
                 .map(|ext| OsString::from(ext.to_lowercase()))
+                .chain(TRACK_IMAGE_EXTENSIONS.iter().map(OsString::from))
                 .collect();
         }
     }
@@ -281,4 +290,37 @@ impl FloppyManager {
             }
         }
     }
+
+    /// Save floppy image data to a new file alongside the image at the specified index. The new
+    /// file is named after the original image with a numeric suffix and the given extension, so
+    /// that the original image is never overwritten.
+    pub fn save_floppy_data_as(
+        &self,
+        data: &[u8],
+        idx: usize,
+        extension: &str,
+        _rm: &ResourceManager,
+    ) -> Result<PathBuf, FloppyError> {
+        if idx >= self.image_vec.len() {
+            return Err(FloppyError::ImageNotFound);
+        }
+
+        let floppy_path = &self.image_vec[idx].path;
+        let dir = floppy_path.parent().ok_or(FloppyError::DirNotFound)?;
+        let stem = floppy_path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| String::from("floppy"));
+
+        let new_path = (1..1000)
+            .map(|n| dir.join(format!("{}_{:02}.{}", stem, n, extension)))
+            .find(|path| !path.exists())
+            .ok_or(FloppyError::FileWriteError)?;
+
+        // TODO: Implement write through resource manager instead of direct file access.
+        match std::fs::write(&new_path, data) {
+            Ok(_) => Ok(new_path),
+            Err(_e) => Err(FloppyError::FileWriteError),
+        }
+    }
 }
@@ -0,0 +1,562 @@
+/*
+    MartyPC
+    https://github.com/dbalsom/martypc
+
+    Copyright 2022-2024 Daniel Balsom
+
+    Permission is hereby granted, free of charge, to any person obtaining a
+    copy of this software and associated documentation files (the “Software”),
+    to deal in the Software without restriction, including without limitation
+    the rights to use, copy, modify, merge, publish, distribute, sublicense,
+    and/or sell copies of the Software, and to permit persons to whom the
+    Software is furnished to do so, subject to the following conditions:
+
+    The above copyright notice and this permission notice shall be included in
+    all copies or substantial portions of the Software.
+
+    THE SOFTWARE IS PROVIDED “AS IS”, WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+    IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+    FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+    AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+    LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING
+    FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER
+    DEALINGS IN THE SOFTWARE.
+
+    --------------------------------------------------------------------------
+
+    frontend_common::gdb_stub.rs
+
+    Implements a GDB Remote Serial Protocol stub for the 8088 core, so that gdb
+    or any other RSP client can attach to a running machine over TCP or a Unix
+    domain socket.
+
+    The stub is polled once per frame by the front end. It never blocks - all
+    socket IO is non-blocking, and execution control requests are passed to
+    the machine through ExecutionControl like any other debugger operation.
+
+    gdb has no 8088 target of its own. Connect with:
+
+        (gdb) set architecture i8086
+        (gdb) target remote localhost:1234
+
+    Registers are reported in the i386 layout, zero-extended to 32 bits.
+    Memory addresses and breakpoint addresses are flat 20-bit addresses. EIP
+    is reported as the flat address of CS:IP so that $pc matches them.
+    Breakpoints set from the debugger GUI are left in place.
+*/
+
+use std::{
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use anyhow::Error;
+use marty_core::{
+    breakpoints::{BreakPointType, WatchType},
+    machine::{ExecutionControl, ExecutionOperation, ExecutionState, Machine},
+};
+
+const GDB_PACKET_SIZE: usize = 0x1000;
+const GDB_ADDRESS_MASK: u32 = 0xFFFFF;
+const GDB_REGISTER_CT: usize = 16;
+const GDB_MAX_WATCH_LEN: u32 = 0x10000;
+
+// Stop reply signals
+const GDB_SIGINT: u8 = 2;
+const GDB_SIGTRAP: u8 = 5;
+
+enum GdbListener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+enum GdbStream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Read for GdbStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            GdbStream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            GdbStream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for GdbStream {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            GdbStream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            GdbStream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            GdbStream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            GdbStream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum GdbWatch {
+    Write,
+    Read,
+    Access,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum GdbBreakpoint {
+    Execute(u32),
+    Watch(u32, u32, GdbWatch),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum PendingStop {
+    Continue,
+    Step,
+}
+
+pub struct GdbStub {
+    listener: GdbListener,
+    stream: Option<GdbStream>,
+    rx_buf: Vec<u8>,
+    last_packet: Vec<u8>,
+    no_ack: bool,
+    breakpoints: Vec<GdbBreakpoint>,
+    pending: Option<PendingStop>,
+}
+
+impl GdbStub {
+    /// Start listening for a client. `address` is either a TCP socket address such as
+    /// "127.0.0.1:1234", or, on Unix hosts, a socket path prefixed with "unix:".
+    pub fn new(address: &str) -> Result<Self, Error> {
+        let listener = if let Some(_path) = address.strip_prefix("unix:") {
+            #[cfg(unix)]
+            {
+                // Remove a stale socket left behind by a previous session.
+                _ = std::fs::remove_file(_path);
+                let listener = UnixListener::bind(_path)?;
+                listener.set_nonblocking(true)?;
+                GdbListener::Unix(listener)
+            }
+            #[cfg(not(unix))]
+            {
+                return Err(anyhow::anyhow!("Unix sockets are not supported on this platform"));
+            }
+        }
+        else {
+            let listener = TcpListener::bind(address)?;
+            listener.set_nonblocking(true)?;
+            GdbListener::Tcp(listener)
+        };
+
+        log::info!("GDB stub listening on {}", address);
+        Ok(Self {
+            listener,
+            stream: None,
+            rx_buf: Vec::new(),
+            last_packet: Vec::new(),
+            no_ack: false,
+            breakpoints: Vec::new(),
+            pending: None,
+        })
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Service the connection. Accepts a new client, processes any received packets and sends a
+    /// stop reply once a pending continue or step has completed.
+    pub fn poll(&mut self, machine: &mut Machine, exec_control: &mut ExecutionControl) {
+        if self.stream.is_none() {
+            self.accept(exec_control);
+            if self.stream.is_none() {
+                return;
+            }
+        }
+
+        self.check_pending(exec_control);
+
+        let mut buf = [0u8; 1024];
+        loop {
+            let stream = match self.stream.as_mut() {
+                Some(stream) => stream,
+                None => return,
+            };
+            match stream.read(&mut buf) {
+                Ok(0) => {
+                    log::info!("GDB client disconnected.");
+                    self.detach(machine, exec_control);
+                    return;
+                }
+                Ok(n) => self.rx_buf.extend_from_slice(&buf[..n]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log::error!("GDB connection error: {}", e);
+                    self.detach(machine, exec_control);
+                    return;
+                }
+            }
+        }
+
+        while let Some(packet) = self.next_packet(exec_control) {
+            if let Some(reply) = self.handle_packet(&packet, machine, exec_control) {
+                self.send_packet(&reply);
+            }
+            if self.stream.is_none() {
+                // Client detached or killed the session.
+                self.detach(machine, exec_control);
+                return;
+            }
+        }
+    }
+
+    fn accept(&mut self, exec_control: &mut ExecutionControl) {
+        let stream = match &self.listener {
+            GdbListener::Tcp(listener) => listener.accept().map(|(s, _)| {
+                _ = s.set_nodelay(true);
+                s.set_nonblocking(true).map(|_| GdbStream::Tcp(s))
+            }),
+            #[cfg(unix)]
+            GdbListener::Unix(listener) => listener
+                .accept()
+                .map(|(s, _)| s.set_nonblocking(true).map(|_| GdbStream::Unix(s))),
+        };
+
+        match stream {
+            Ok(Ok(stream)) => {
+                log::info!("GDB client connected.");
+                self.stream = Some(stream);
+                self.rx_buf.clear();
+                self.no_ack = false;
+                self.pending = None;
+                // gdb expects the target to be stopped when it attaches.
+                exec_control.set_op(ExecutionOperation::Pause);
+            }
+            Ok(Err(e)) => log::error!("Failed to configure GDB connection: {}", e),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {}
+            Err(e) => log::error!("Failed to accept GDB connection: {}", e),
+        }
+    }
+
+    /// Release the machine when the client goes away: drop our breakpoints and let it run.
+    fn detach(&mut self, machine: &mut Machine, exec_control: &mut ExecutionControl) {
+        self.stream = None;
+        self.pending = None;
+        let breakpoints = std::mem::take(&mut self.breakpoints);
+        machine.update_breakpoints(|bp| owns_breakpoint(&breakpoints, bp), Vec::new());
+        exec_control.set_op(ExecutionOperation::Run);
+    }
+
+    fn check_pending(&mut self, exec_control: &mut ExecutionControl) {
+        if self.pending.is_none() || !matches!(exec_control.peek_op(), ExecutionOperation::None) {
+            // Nothing to report, or the machine has not picked up the operation yet.
+            return;
+        }
+        let signal = match (self.pending, exec_control.get_state()) {
+            (_, ExecutionState::Running) => return,
+            (Some(PendingStop::Continue), ExecutionState::Paused) => GDB_SIGINT,
+            _ => GDB_SIGTRAP,
+        };
+        self.pending = None;
+        self.send_packet(&format!("S{:02x}", signal));
+    }
+
+    /// Extract the next complete packet from the receive buffer, handling acks and interrupts.
+    fn next_packet(&mut self, exec_control: &mut ExecutionControl) -> Option<String> {
+        loop {
+            let first = *self.rx_buf.first()?;
+            match first {
+                b'$' => {
+                    let end = self.rx_buf.iter().position(|&b| b == b'#')?;
+                    if self.rx_buf.len() < end + 3 {
+                        return None;
+                    }
+                    let payload: Vec<u8> = self.rx_buf[1..end].to_vec();
+                    let checksum = std::str::from_utf8(&self.rx_buf[end + 1..end + 3])
+                        .ok()
+                        .and_then(|s| u8::from_str_radix(s, 16).ok());
+                    self.rx_buf.drain(..end + 3);
+
+                    if checksum == Some(gdb_checksum(&payload)) {
+                        self.send_raw(b"+");
+                        return Some(String::from_utf8_lossy(&payload).to_string());
+                    }
+                    log::warn!("GDB packet checksum mismatch");
+                    self.send_raw(b"-");
+                }
+                b'-' => {
+                    self.rx_buf.remove(0);
+                    let last = self.last_packet.clone();
+                    self.send_raw(&last);
+                }
+                0x03 => {
+                    self.rx_buf.remove(0);
+                    exec_control.set_op(ExecutionOperation::Pause);
+                }
+                _ => {
+                    // Acks and line noise.
+                    self.rx_buf.remove(0);
+                }
+            }
+        }
+    }
+
+    fn handle_packet(
+        &mut self,
+        packet: &str,
+        machine: &mut Machine,
+        exec_control: &mut ExecutionControl,
+    ) -> Option<String> {
+        log::trace!("GDB packet: {}", packet);
+        let (cmd, args) = packet.split_at(1.min(packet.len()));
+
+        let reply = match cmd {
+            "?" => format!("S{:02x}", GDB_SIGTRAP),
+            "g" => read_registers(machine),
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg < GDB_REGISTER_CT => hex_u32(register_value(machine, reg)),
+                _ => "E00".to_string(),
+            },
+            // Register writes are not supported.
+            "G" | "P" => "E01".to_string(),
+            "m" => self.read_memory(machine, args).unwrap_or_else(|| "E01".to_string()),
+            "M" => self.write_memory(machine, args).unwrap_or_else(|| "E01".to_string()),
+            "c" => {
+                exec_control.set_op(ExecutionOperation::Run);
+                self.pending = Some(PendingStop::Continue);
+                return None;
+            }
+            "s" => {
+                exec_control.set_op(ExecutionOperation::Step);
+                self.pending = Some(PendingStop::Step);
+                return None;
+            }
+            "Z" | "z" => self
+                .update_breakpoint(machine, cmd == "Z", args)
+                .unwrap_or_else(|| "E01".to_string()),
+            "H" | "T" => "OK".to_string(),
+            "q" => query(args),
+            "Q" if args == "StartNoAckMode" => {
+                self.send_packet("OK");
+                self.no_ack = true;
+                return None;
+            }
+            "D" => {
+                self.send_packet("OK");
+                self.stream = None;
+                return None;
+            }
+            "k" => {
+                self.stream = None;
+                return None;
+            }
+            _ => String::new(),
+        };
+        Some(reply)
+    }
+
+    fn read_memory(&self, machine: &Machine, args: &str) -> Option<String> {
+        let (addr, len) = parse_addr_len(args)?;
+        let len = len.min(GDB_PACKET_SIZE as u32 / 2);
+        let mut reply = String::with_capacity(len as usize * 2);
+        for i in 0..len {
+            let byte = machine
+                .bus()
+                .peek_u8((addr.wrapping_add(i) & GDB_ADDRESS_MASK) as usize)
+                .ok()?;
+            reply.push_str(&format!("{:02x}", byte));
+        }
+        Some(reply)
+    }
+
+    fn write_memory(&self, machine: &mut Machine, args: &str) -> Option<String> {
+        let (range, data) = args.split_once(':')?;
+        let (addr, len) = parse_addr_len(range)?;
+        let bytes = decode_hex(data)?;
+        if bytes.len() != len as usize {
+            return None;
+        }
+        for (i, byte) in bytes.iter().enumerate() {
+            machine
+                .bus_mut()
+                .write_u8((addr.wrapping_add(i as u32) & GDB_ADDRESS_MASK) as usize, *byte, 0)
+                .ok()?;
+        }
+        Some("OK".to_string())
+    }
+
+    /// Handle Z/z packets. Software and hardware breakpoints (types 0 and 1) map onto execute
+    /// breakpoints. Write and read watchpoints (types 2 and 3) map onto write and read watchpoints,
+    /// and access watchpoints (type 4) onto both.
+    fn update_breakpoint(&mut self, machine: &mut Machine, insert: bool, args: &str) -> Option<String> {
+        let mut fields = args.split(',');
+        let bp_type = fields.next()?;
+        let addr = u32::from_str_radix(fields.next()?, 16).ok()? & GDB_ADDRESS_MASK;
+        let kind = u32::from_str_radix(fields.next()?, 16).ok()?;
+
+        let bp = match bp_type {
+            "0" | "1" => GdbBreakpoint::Execute(addr),
+            "2" | "3" | "4" if kind > GDB_MAX_WATCH_LEN => return Some("E01".to_string()),
+            "2" => GdbBreakpoint::Watch(addr, kind.max(1), GdbWatch::Write),
+            "3" => GdbBreakpoint::Watch(addr, kind.max(1), GdbWatch::Read),
+            "4" => GdbBreakpoint::Watch(addr, kind.max(1), GdbWatch::Access),
+            _ => return Some(String::new()),
+        };
+
+        let installed = self.breakpoints.clone();
+        if insert {
+            if !self.breakpoints.contains(&bp) {
+                self.breakpoints.push(bp);
+            }
+        }
+        else {
+            self.breakpoints.retain(|b| *b != bp);
+        }
+
+        let mut bp_list = Vec::new();
+        for bp in self.breakpoints.iter() {
+            match *bp {
+                GdbBreakpoint::Execute(addr) => bp_list.push(BreakPointType::ExecuteFlat(addr)),
+                GdbBreakpoint::Watch(addr, len, watch) => {
+                    for (start, len, kind) in watch_ranges(addr, len, watch) {
+                        bp_list.push(BreakPointType::Watch(start, len, kind));
+                    }
+                }
+            }
+        }
+        machine.update_breakpoints(|bp| owns_breakpoint(&installed, bp), bp_list);
+        Some("OK".to_string())
+    }
+
+    fn send_packet(&mut self, payload: &str) {
+        let packet = format!("${}#{:02x}", payload, gdb_checksum(payload.as_bytes()));
+        self.last_packet = packet.clone().into_bytes();
+        self.send_raw(packet.as_bytes());
+    }
+
+    fn send_raw(&mut self, data: &[u8]) {
+        if self.no_ack && (data == b"+" || data == b"-") {
+            return;
+        }
+        if let Some(stream) = self.stream.as_mut() {
+            // The stream is non-blocking, but replies are small enough that a short write only
+            // happens if the client has stopped reading.
+            if let Err(e) = stream.write_all(data).and_then(|_| stream.flush()) {
+                log::error!("GDB send error: {}", e);
+                self.stream = None;
+            }
+        }
+    }
+}
+
+fn query(args: &str) -> String {
+    let name = args.split([':', ',']).next().unwrap_or("");
+    match name {
+        "Supported" => format!("PacketSize={:x};QStartNoAckMode+", GDB_PACKET_SIZE),
+        "Attached" => "1".to_string(),
+        "C" => "QC1".to_string(),
+        "fThreadInfo" => "m1".to_string(),
+        "sThreadInfo" => "l".to_string(),
+        "Offsets" => "Text=0;Data=0;Bss=0".to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Return the value of a register by its index in the gdb i386 register layout.
+fn register_value(machine: &Machine, reg: usize) -> u32 {
+    let regs = machine.cpu().get_state();
+    let value = match reg {
+        0 => regs.ax,
+        1 => regs.cx,
+        2 => regs.dx,
+        3 => regs.bx,
+        4 => regs.sp,
+        5 => regs.bp,
+        6 => regs.si,
+        7 => regs.di,
+        8 => return ((regs.cs as u32) << 4).wrapping_add(regs.ip as u32) & GDB_ADDRESS_MASK,
+        9 => regs.flags,
+        10 => regs.cs,
+        11 => regs.ss,
+        12 => regs.ds,
+        13 => regs.es,
+        // FS and GS do not exist on the 8088.
+        _ => 0,
+    };
+    value as u32
+}
+
+/// Return whether a machine breakpoint was installed for one of the stub's breakpoints.
+fn owns_breakpoint(breakpoints: &[GdbBreakpoint], bp: &BreakPointType) -> bool {
+    breakpoints.iter().any(|gdb_bp| match (*gdb_bp, bp) {
+        (GdbBreakpoint::Execute(addr), BreakPointType::ExecuteFlat(bp_addr)) => addr == *bp_addr,
+        (GdbBreakpoint::Watch(addr, len, watch), BreakPointType::Watch(start, bp_len, kind)) => {
+            watch_ranges(addr, len, watch).contains(&(*start, *bp_len, *kind))
+        }
+        _ => false,
+    })
+}
+
+/// Return the core watchpoints for a gdb watchpoint, as (start, length, type). A range that runs
+/// past the end of the address space wraps around to the start, like gdb addresses do.
+fn watch_ranges(addr: u32, len: u32, watch: GdbWatch) -> Vec<(u32, u32, WatchType)> {
+    let kinds: &[WatchType] = match watch {
+        GdbWatch::Write => &[WatchType::Write],
+        GdbWatch::Read => &[WatchType::Read],
+        GdbWatch::Access => &[WatchType::Read, WatchType::Write],
+    };
+    let head = len.min(GDB_ADDRESS_MASK + 1 - addr);
+    let mut ranges = Vec::new();
+    for kind in kinds {
+        ranges.push((addr, head, *kind));
+        if head < len {
+            ranges.push((0, len - head, *kind));
+        }
+    }
+    ranges
+}
+
+fn read_registers(machine: &Machine) -> String {
+    (0..GDB_REGISTER_CT).map(|reg| hex_u32(register_value(machine, reg))).collect()
+}
+
+/// Encode a register value as little-endian hex, as gdb expects.
+fn hex_u32(value: u32) -> String {
+    value.to_le_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn gdb_checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |sum, b| sum.wrapping_add(*b))
+}
+
+fn parse_addr_len(args: &str) -> Option<(u32, u32)> {
+    let (addr, len) = args.split_once(',')?;
+    Some((
+        u32::from_str_radix(addr, 16).ok()?,
+        u32::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+fn decode_hex(data: &str) -> Option<Vec<u8>> {
+    if data.len() & 1 != 0 {
+        return None;
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+        .collect()
+}
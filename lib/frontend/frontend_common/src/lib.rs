@@ -39,6 +39,7 @@ pub mod display_manager;
 #[cfg(feature = "use_wgpu")]
 pub mod display_scaler;
 pub mod floppy_manager;
+pub mod gdb_stub;
 pub mod machine_manager;
 pub mod resource_manager;
 pub mod rom_manager;
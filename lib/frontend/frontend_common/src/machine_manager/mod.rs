@@ -40,8 +40,10 @@ use marty_core::{
         MachineConfiguration,
         MediaConfig,
         MemoryConfig,
+        ParallelPortConfig,
         SerialControllerConfig,
         SerialMouseConfig,
+        SoundConfig,
         VideoCardConfig,
     },
     machine_types::{HardDiskControllerType, MachineType},
@@ -79,6 +81,8 @@ pub struct MachineConfigFileEntry {
     fdc: Option<FloppyControllerConfig>,
     hdc: Option<HardDriveControllerConfig>,
     serial: Option<Vec<SerialControllerConfig>>,
+    parallel: Option<Vec<ParallelPortConfig>>,
+    sound: Option<Vec<SoundConfig>>,
     video: Option<Vec<VideoCardConfig>>,
     keyboard: Option<KeyboardConfig>,
     serial_mouse: Option<SerialMouseConfig>,
@@ -92,20 +96,14 @@ pub struct MachineConfigFileOverlayEntry {
     fdc: Option<FloppyControllerConfig>,
     hdc: Option<HardDriveControllerConfig>,
     serial: Option<Vec<SerialControllerConfig>>,
+    parallel: Option<Vec<ParallelPortConfig>>,
+    sound: Option<Vec<SoundConfig>>,
     video: Option<Vec<VideoCardConfig>>,
     keyboard: Option<KeyboardConfig>,
     serial_mouse: Option<SerialMouseConfig>,
     media: Option<MediaConfig>,
 }
 
-/*
-#[derive(Clone, Debug, Deserialize)]
-pub struct ParallelControllerConfig {
-    type: ParallelControllerType,
-    port: Vec<ParallelPortConfig>,
-}
- */
-
 pub struct MachineManager {
     active_config: Option<MachineConfigFileEntry>,
     config_names: HashSet<String>,
@@ -363,6 +361,14 @@ impl MachineConfigFileEntry {
             log::debug!("Applying serial overlay: {:?}", serial);
             self.serial = Some(serial);
         }
+        if let Some(parallel) = overlay.parallel {
+            log::debug!("Applying parallel overlay: {:?}", parallel);
+            self.parallel = Some(parallel);
+        }
+        if let Some(sound) = overlay.sound {
+            log::debug!("Applying sound overlay: {:?}", sound);
+            self.sound = Some(sound);
+        }
         if let Some(video) = overlay.video {
             log::debug!("Applying video overlay: {:?}", video);
             self.video = Some(video);
@@ -386,6 +392,8 @@ impl MachineConfigFileEntry {
             fdc: self.fdc.clone(),
             hdc: self.hdc.clone(),
             serial: self.serial.clone().unwrap_or_default(),
+            parallel: self.parallel.clone().unwrap_or_default(),
+            sound: self.sound.clone().unwrap_or_default(),
             video: self.video.clone().unwrap_or_default(),
             keyboard: self.keyboard.clone(),
             serial_mouse: self.serial_mouse.clone(),
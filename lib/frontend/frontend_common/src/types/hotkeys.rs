@@ -44,6 +44,8 @@ pub enum HotkeyEvent {
     ToggleFullscreen,
     DebugStep,
     DebugStepOver,
+    ToggleAudioCapture,
+    ToggleVideoCapture,
 }
 
 #[derive(Copy, Clone, Debug, Deserialize)]
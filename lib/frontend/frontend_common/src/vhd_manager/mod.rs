@@ -27,7 +27,8 @@
     frontend_common::vhd_manager.rs
 
     Discover Vhd images in the 'hdd' resource and provide an interface
-    for enumerating and loading them.
+    for enumerating and loading them. Raw sector images (.img, .hdm) are
+    discovered and loaded alongside VHDs.
 
     Unlike most other resources, the core writes to Vhd files directly.
     Therefore, the Vhd manager is primarily responsible for enumerating file
@@ -40,6 +41,9 @@
 
 const DRIVE_MAX: usize = 4;
 
+/// Extensions of hard disk images recognized by default.
+pub const HDD_IMAGE_EXTENSIONS: [&str; 3] = ["vhd", "img", "hdm"];
+
 use crate::resource_manager::{PathTreeNode, ResourceItem, ResourceManager};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
@@ -50,6 +54,10 @@ use std::{
 };
 
 use anyhow::Error;
+use marty_core::{
+    device_types::hdc::HardDiskFormat,
+    hdd_image::{open_hdd_image, overlay::OverlayDisk, DiskBackend},
+};
 
 #[derive(Debug)]
 pub enum VhdManagerError {
@@ -107,7 +115,7 @@ impl VhdManager {
             image_map: HashMap::new(),
             drives_loaded: BTreeMap::new(),
             images_loaded: BTreeSet::new(),
-            extensions: vec![OsString::from("vhd")],
+            extensions: HDD_IMAGE_EXTENSIONS.iter().map(OsString::from).collect(),
         }
     }
 
@@ -254,8 +262,60 @@ impl VhdManager {
     }
 
     pub fn load_vhd_file(&mut self, drive: usize, idx: usize) -> Result<File, VhdManagerError> {
+        self.open_image_file(drive, idx, false)
+    }
+
+    /// Load a hard disk image of any supported format for the specified drive.
+    /// The geometry of a raw image is taken from `geometry` if specified, otherwise it is inferred
+    /// from the image size and `formats`. If `overlay` is set, the image is opened read-only and
+    /// all writes are kept in memory, to be discarded when the image is released.
+    pub fn load_disk(
+        &mut self,
+        drive: usize,
+        idx: usize,
+        geometry: Option<(u16, u8, u8)>,
+        overlay: bool,
+        formats: &[HardDiskFormat],
+    ) -> Result<Box<dyn DiskBackend>, Error> {
+        let file = self.open_image_file(drive, idx, overlay)?;
+        let path = self.image_vec[idx].path.clone();
+
+        let disk = match open_hdd_image(file, &path, geometry, formats) {
+            Ok(disk) => disk,
+            Err(e) => {
+                // Don't leave the drive associated with an image we failed to open.
+                self.release_vhd(drive);
+                return Err(e);
+            }
+        };
+
+        if overlay {
+            log::debug!("Mounting {:?} with a read-only overlay", path);
+            Ok(Box::new(OverlayDisk::new(disk)))
+        }
+        else {
+            Ok(disk)
+        }
+    }
+
+    pub fn load_disk_by_name(
+        &mut self,
+        drive: usize,
+        name: &OsString,
+        geometry: Option<(u16, u8, u8)>,
+        overlay: bool,
+        formats: &[HardDiskFormat],
+    ) -> Result<(Box<dyn DiskBackend>, usize), Error> {
+        let path = self.find_first_name(name.clone()).ok_or(VhdManagerError::FileNotFound)?;
+        let idx = *self.image_map.get(&path).ok_or(VhdManagerError::IndexNotFound)?;
+
+        let disk = self.load_disk(drive, idx, geometry, overlay, formats)?;
+        Ok((disk, idx))
+    }
+
+    fn open_image_file(&mut self, drive: usize, idx: usize, read_only: bool) -> Result<File, VhdManagerError> {
         if let Some(vhd) = self.image_vec.get(idx) {
-            let vhd_file_result = File::options().read(true).write(true).open(&vhd.path);
+            let vhd_file_result = File::options().read(true).write(!read_only).open(&vhd.path);
 
             match vhd_file_result {
                 Ok(file) => {
@@ -172,6 +172,11 @@ impl GuiRenderContext {
         }
     }
 
+    /// Returns whether egui is interested in the pointer, ie, it is over a gui window or menu.
+    pub fn wants_pointer(&self) -> bool {
+        self.egui_ctx.wants_pointer_input()
+    }
+
     /// Handle input events from the window manager.
     pub fn handle_event(&mut self, window: &Window, event: &winit::event::WindowEvent) {
         #[cfg(not(target_arch = "wasm32"))]
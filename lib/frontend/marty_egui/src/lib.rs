@@ -67,6 +67,7 @@ use marty_core::{
     device_traits::videocard::DisplayApertureType,
     device_types::hdc::HardDiskFormat,
     devices::pic::PicStringState,
+    floppy_image::FloppyImageFormat,
     machine::MachineState,
 };
 
@@ -122,6 +123,7 @@ pub enum GuiBoolean {
     TurboButton,
     ShowBackBuffer,
     ShowRasterPosition,
+    LightPen,
 }
 
 // Enums are hashed with a tuple of GuiEnumContext and their base discriminant.
@@ -170,6 +172,7 @@ pub enum GuiEvent {
     CreateVHD(OsString, HardDiskFormat),
     LoadFloppy(usize, usize),
     SaveFloppy(usize, usize),
+    SaveFloppyAs(usize, usize, FloppyImageFormat),
     EjectFloppy(usize),
     SetFloppyWriteProtect(usize, bool),
     BridgeSerialPort(usize, String, usize),
@@ -187,6 +190,8 @@ pub enum GuiEvent {
     TickDevice(DeviceSelection, u32),
     MachineStateChange(MachineState),
     TakeScreenshot(usize),
+    ToggleAudioCapture,
+    ToggleVideoCapture,
     ToggleFullscreen(usize),
     Exit,
     SetNMI(bool),
@@ -32,7 +32,11 @@
 
 use crate::{state::GuiState, GuiBoolean, GuiEnum, GuiEvent, GuiVariable, GuiVariableContext, GuiWindow};
 
-use marty_core::{device_traits::videocard::VideoType, devices::serial::SerialPortDescriptor};
+use marty_core::{
+    device_traits::videocard::VideoType,
+    devices::serial::SerialPortDescriptor,
+    floppy_image::FloppyImageFormat,
+};
 
 use marty_core::machine::MachineState;
 
@@ -98,6 +102,19 @@ impl GuiState {
                             }
                         });
                     }
+
+                    if ui
+                        .checkbox(&mut self.get_option_mut(GuiBoolean::LightPen), "Light Pen")
+                        .clicked()
+                    {
+                        let new_opt = self.get_option(GuiBoolean::LightPen).unwrap();
+
+                        self.event_queue.send(GuiEvent::VariableChanged(
+                            GuiVariableContext::Global,
+                            GuiVariable::Bool(GuiBoolean::LightPen, new_opt),
+                        ));
+                        ui.close_menu();
+                    }
                 });
 
                 ui.separator();
@@ -166,6 +183,26 @@ impl GuiState {
                         ui.close_menu();
                     }
                 });
+
+                ui.separator();
+
+                let capture_label = match self.audio_capturing {
+                    true => "⏹ Stop Audio Capture",
+                    false => "🎙 Start Audio Capture",
+                };
+                if ui.button(capture_label).clicked() {
+                    self.event_queue.send(GuiEvent::ToggleAudioCapture);
+                    ui.close_menu();
+                }
+
+                let video_capture_label = match self.video_capturing {
+                    true => "⏹ Stop Video Capture",
+                    false => "🎥 Start Video Capture",
+                };
+                if ui.button(video_capture_label).clicked() {
+                    self.event_queue.send(GuiEvent::ToggleVideoCapture);
+                    ui.close_menu();
+                }
             });
 
             let _media_response = ui.menu_button("Media", |ui| {
@@ -400,8 +437,10 @@ impl GuiState {
 
             ui.horizontal(|ui| {
                 if let Some(floppy_name) = &self.floppy_drives[drive_idx].filename() {
+                    // Mark images modified since they were loaded or last saved.
+                    let modified = if self.floppy_drives[drive_idx].dirty { "*" } else { "" };
                     ui.add_enabled_ui(!self.floppy_drives[drive_idx].write_protected, |ui| {
-                        if ui.button(format!("Save image: {}", floppy_name)).clicked() {
+                        if ui.button(format!("Save image: {}{}", floppy_name, modified)).clicked() {
                             if let Some(floppy_idx) = self.floppy_drives[drive_idx].selected_idx {
                                 self.event_queue.send(GuiEvent::SaveFloppy(drive_idx, floppy_idx));
                             }
@@ -413,6 +452,25 @@ impl GuiState {
                 }
             });
 
+            if let Some(floppy_idx) = self.floppy_drives[drive_idx].selected_idx {
+                ui.menu_button("Save image as new file", |ui| {
+                    for format in [
+                        FloppyImageFormat::Raw,
+                        FloppyImageFormat::ImageDisk,
+                        FloppyImageFormat::TeleDisk,
+                    ] {
+                        if ui.button(format!("{}", format)).clicked() {
+                            self.event_queue
+                                .send(GuiEvent::SaveFloppyAs(drive_idx, floppy_idx, format));
+                            ui.close_menu();
+                        }
+                    }
+                });
+            }
+            else {
+                ui.add_enabled(false, egui::Button::new("Save image as new file"));
+            }
+
             if ui
                 .checkbox(&mut self.floppy_drives[drive_idx].write_protected, "Write Protect")
                 .changed()
@@ -48,6 +48,7 @@ use frontend_common::{
     resource_manager::PathTreeNode,
 };
 use marty_core::{
+    breakpoints::WatchType,
     device_traits::videocard::{DisplayApertureDesc, VideoCardState, VideoCardStateEntry},
     devices::{pit::PitDisplayState, serial::SerialPortDescriptor},
     machine::{ExecutionControl, MachineState},
@@ -96,6 +97,7 @@ pub struct GuiFloppyDriveInfo {
     pub(crate) selected_idx: Option<usize>,
     pub(crate) selected_path: Option<PathBuf>,
     pub(crate) write_protected: bool,
+    pub(crate) dirty: bool,
 }
 
 impl GuiFloppyDriveInfo {
@@ -178,6 +180,11 @@ pub struct GuiState {
     // VHD Images
     pub(crate) vhd_names: Vec<OsString>,
 
+    // Whether the machine's audio output is being recorded
+    pub(crate) audio_capturing: bool,
+    // Whether the machine's video output is being recorded
+    pub(crate) video_capturing: bool,
+
     // Serial ports
     pub(crate) serial_ports: Vec<SerialPortDescriptor>,
     pub(crate) host_serial_ports: Vec<SerialPortInfo>,
@@ -271,6 +278,7 @@ impl GuiState {
             (GuiBoolean::TurboButton, false),
             (GuiBoolean::ShowBackBuffer, false),
             (GuiBoolean::ShowRasterPosition, true),
+            (GuiBoolean::LightPen, false),
             //(GuiBoolean::EnableSnow, true),
         ]
         .into();
@@ -303,6 +311,9 @@ impl GuiState {
             hdds: Vec::new(),
             vhd_names: Vec::new(),
 
+            audio_capturing: false,
+            video_capturing: false,
+
             serial_ports: Vec::new(),
             host_serial_ports: Vec::new(),
             serial_port_name: String::new(),
@@ -429,6 +440,7 @@ impl GuiState {
                 selected_idx: None,
                 selected_path: None,
                 write_protected: true,
+                dirty: false,
             });
         }
     }
@@ -437,6 +449,20 @@ impl GuiState {
         self.floppy_drives[drive].write_protected = state;
     }
 
+    pub fn set_audio_capturing(&mut self, state: bool) {
+        self.audio_capturing = state;
+    }
+
+    pub fn set_video_capturing(&mut self, state: bool) {
+        self.video_capturing = state;
+    }
+
+    pub fn set_floppy_dirty(&mut self, drive: usize, state: bool) {
+        if let Some(drive) = self.floppy_drives.get_mut(drive) {
+            drive.dirty = state;
+        }
+    }
+
     pub fn set_floppy_tree(&mut self, tree: PathTreeNode) {
         self.floppy_tree_menu.set_root(tree);
     }
@@ -497,6 +523,22 @@ impl GuiState {
         self.cpu_control.get_breakpoints()
     }
 
+    pub fn get_io_breakpoints(&mut self) -> (&str, &str) {
+        self.cpu_control.get_io_breakpoints()
+    }
+
+    pub fn get_breakpoint_condition(&mut self) -> (&str, &str) {
+        self.cpu_control.get_breakpoint_condition()
+    }
+
+    pub fn get_watchpoint(&mut self) -> (&str, WatchType) {
+        self.cpu_control.get_watchpoint()
+    }
+
+    pub fn set_watchpoint_hit(&mut self, hit: String) {
+        self.cpu_control.set_watchpoint_hit(hit);
+    }
+
     pub fn update_pit_state(&mut self, state: &PitDisplayState) {
         self.pit_viewer.update_state(state);
     }
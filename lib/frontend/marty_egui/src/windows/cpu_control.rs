@@ -33,12 +33,22 @@
 use crate::*;
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use marty_core::machine::{ExecutionControl, ExecutionOperation, ExecutionState};
+use marty_core::{
+    breakpoints::WatchType,
+    machine::{ExecutionControl, ExecutionOperation, ExecutionState},
+};
 pub struct CpuControl {
     exec_control: Rc<RefCell<ExecutionControl>>,
     breakpoint: String,
     mem_breakpoint: String,
     int_breakpoint: String,
+    io_read_breakpoint: String,
+    io_write_breakpoint: String,
+    bp_condition: String,
+    bp_hit_count: String,
+    watchpoint: String,
+    watch_type: WatchType,
+    watchpoint_hit: Option<String>,
 }
 
 impl CpuControl {
@@ -48,6 +58,13 @@ impl CpuControl {
             breakpoint: String::new(),
             mem_breakpoint: String::new(),
             int_breakpoint: String::new(),
+            io_read_breakpoint: String::new(),
+            io_write_breakpoint: String::new(),
+            bp_condition: String::new(),
+            bp_hit_count: String::new(),
+            watchpoint: String::new(),
+            watch_type: WatchType::Write,
+            watchpoint_hit: None,
         }
     }
 
@@ -194,10 +211,86 @@ impl CpuControl {
                     events.send(GuiEvent::EditBreakpoint);
                 }
                 ui.end_row();
+
+                ui.label("IO Read Breakpoint: ");
+                if ui.text_edit_singleline(&mut self.io_read_breakpoint).changed() {
+                    events.send(GuiEvent::EditBreakpoint);
+                }
+                ui.end_row();
+
+                ui.label("IO Write Breakpoint: ");
+                if ui
+                    .text_edit_singleline(&mut self.io_write_breakpoint)
+                    .on_hover_text("Port, optionally followed by =value and /mask, e.g. 3D4=0E/FF")
+                    .changed()
+                {
+                    events.send(GuiEvent::EditBreakpoint);
+                }
+                ui.end_row();
+
+                ui.label("Watchpoint: ");
+                ui.horizontal(|ui| {
+                    if ui
+                        .text_edit_singleline(&mut self.watchpoint)
+                        .on_hover_text("Address, optionally followed by a hex length, e.g. DS:0100,10")
+                        .changed()
+                    {
+                        events.send(GuiEvent::EditBreakpoint);
+                    }
+                    let old_type = self.watch_type;
+                    egui::ComboBox::from_id_source("cpu-control-watch-type")
+                        .selected_text(format!("{}", self.watch_type))
+                        .show_ui(ui, |ui| {
+                            for wt in [WatchType::Read, WatchType::Write, WatchType::Change] {
+                                ui.selectable_value(&mut self.watch_type, wt, wt.to_string());
+                            }
+                        });
+                    if self.watch_type != old_type {
+                        events.send(GuiEvent::EditBreakpoint);
+                    }
+                });
+                ui.end_row();
+
+                ui.label("Condition: ");
+                if ui
+                    .text_edit_singleline(&mut self.bp_condition)
+                    .on_hover_text("Applies to all breakpoints above, e.g. AX==0x4C00 && [DS:SI]==0x24")
+                    .changed()
+                {
+                    events.send(GuiEvent::EditBreakpoint);
+                }
+                ui.end_row();
+
+                ui.label("Hit Count: ");
+                if ui.text_edit_singleline(&mut self.bp_hit_count).changed() {
+                    events.send(GuiEvent::EditBreakpoint);
+                }
+                ui.end_row();
             });
+
+        if let Some(hit) = &self.watchpoint_hit {
+            ui.separator();
+            ui.label(format!("Last watchpoint: {}", hit));
+        }
     }
 
     pub fn get_breakpoints(&mut self) -> (&str, &str, &str) {
         (&self.breakpoint, &self.mem_breakpoint, &self.int_breakpoint)
     }
+
+    pub fn get_io_breakpoints(&mut self) -> (&str, &str) {
+        (&self.io_read_breakpoint, &self.io_write_breakpoint)
+    }
+
+    pub fn get_breakpoint_condition(&mut self) -> (&str, &str) {
+        (&self.bp_condition, &self.bp_hit_count)
+    }
+
+    pub fn get_watchpoint(&mut self) -> (&str, WatchType) {
+        (&self.watchpoint, self.watch_type)
+    }
+
+    pub fn set_watchpoint_hit(&mut self, hit: String) {
+        self.watchpoint_hit = Some(hit);
+    }
 }
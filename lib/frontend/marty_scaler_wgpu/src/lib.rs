@@ -716,6 +716,39 @@ impl DisplayScaler<pixels::Pixels> for MartyScaler {
         }
     }
 
+    fn surface_to_texture(&self, x: f32, y: f32) -> Option<(f32, f32)> {
+        if self.screen_width == 0 || self.screen_height == 0 {
+            return None;
+        }
+        let matrix = ScalingMatrix::new(
+            self.mode,
+            (self.texture_width as f32, self.texture_height as f32),
+            (self.target_width as f32, self.target_height as f32),
+            (self.screen_width as f32, self.screen_height as f32),
+            self.screen_margin_y as f32,
+        );
+
+        // Convert to normalized device coordinates and invert the scaling transform.
+        let ndc_x = (x / self.screen_width as f32) * 2.0 - 1.0;
+        let ndc_y = 1.0 - (y / self.screen_height as f32) * 2.0;
+        let (u, v) = matrix.ndc_to_tex_coord(ndc_x, ndc_y);
+
+        // Apply the same barrel distortion as the shader so we land on the texel that was drawn.
+        let (cx, cy) = (self.h_curvature * 0.1, self.v_curvature * 0.1);
+        let (mut mx, mut my) = (u * 2.0 - 1.0, v * 2.0 - 1.0);
+        let distortion = 1.0 - (mx * mx + my * my) * (cx + cy);
+        mx /= distortion;
+        my /= distortion;
+        let (u, v) = (mx * 0.5 + 0.5, my * 0.5 + 0.5);
+
+        if (0.0..1.0).contains(&u) && (0.0..1.0).contains(&v) {
+            Some((u, v))
+        }
+        else {
+            None
+        }
+    }
+
     /// Draw the pixel buffer to the marty_render target.
     fn render(&self, encoder: &mut wgpu::CommandEncoder, render_target: &wgpu::TextureView) {
         //println!("render_target: {:?}", render_target);
@@ -1094,6 +1127,17 @@ impl ScalingMatrix {
         }
     }
 
+    /// Invert the transform for a point in normalized device coordinates, returning the texture
+    /// coordinate the vertex shader would have produced for it.
+    fn ndc_to_tex_coord(&self, x: f32, y: f32) -> (f32, f32) {
+        let (sw, sh) = (self.transform.cols[0].x, self.transform.cols[1].y);
+        let (tx, ty) = (self.transform.cols[3].x, self.transform.cols[3].y);
+
+        let pos_x = (x - tx) / sw;
+        let pos_y = (y - ty) / sh;
+        (pos_x * 0.5 + 0.5, pos_y * -0.5 + 0.5)
+    }
+
     fn as_bytes(&self) -> &[u8] {
         self.transform.as_byte_slice()
     }
@@ -69,7 +69,7 @@ impl VideoRenderer {
         };
 
         match self.video_type {
-            VideoType::MDA => {
+            VideoType::MDA | VideoType::HGC => {
                 VideoRenderer::draw_mda_direct_u32(
                     first_pass_buf,
                     self.params.render.w,
@@ -114,6 +114,15 @@ impl VideoRenderer {
                 extents,
                 RenderBpp::Six,
             ),
+            #[cfg(feature = "vga")]
+            VideoType::VGA => VideoRenderer::draw_vga_direct_u32(
+                first_pass_buf,
+                self.params.render.w,
+                self.params.render.h,
+                input_buf,
+                self.params.aperture,
+                extents,
+            ),
         }
 
         // Draw raster beam position if provided
@@ -700,4 +709,65 @@ impl VideoRenderer {
             }
         }
     }
+
+    /// Draw the VGA card in Direct Mode.
+    /// The VGA in Direct mode generates a framebuffer of RGBA pixels already resolved through its DAC,
+    /// so rows are copied directly.
+    pub fn draw_vga_direct_u32(
+        frame: &mut [u8],
+        w: u32,
+        mut h: u32,
+        dbuf: &[u8],
+        aperture: DisplayApertureType,
+        extents: &DisplayExtents,
+    ) {
+        let aperture = &extents.apertures[aperture as usize];
+
+        let mut horiz_adjust = aperture.x;
+        let mut vert_adjust = aperture.y;
+        // Ignore aperture adjustments if it pushes us outside of the field boundaries
+        if aperture.x + aperture.w >= extents.field_w {
+            horiz_adjust = 0;
+        }
+        if aperture.y + aperture.h >= extents.field_h {
+            vert_adjust = 0;
+        }
+
+        if extents.double_scan {
+            h = h / 2;
+        }
+
+        if (h + vert_adjust) as usize * extents.row_stride > dbuf.len() {
+            log::warn!(
+                "draw_vga_direct_u32(): extents {}x{} greater than buffer: {}",
+                w,
+                h,
+                dbuf.len()
+            );
+            return;
+        }
+
+        let max_y = std::cmp::min(h, aperture.h);
+        let max_x = std::cmp::min(w, aperture.w);
+
+        let bpp = extents.bytes_per_pixel;
+        let row_len = max_x as usize * bpp;
+        let frame_stride = w as usize * bpp;
+
+        for y in 0..max_y {
+            let dbuf_row_offset = (y + vert_adjust) as usize * extents.row_stride + horiz_adjust as usize * bpp;
+            let src = &dbuf[dbuf_row_offset..dbuf_row_offset + row_len];
+
+            if extents.double_scan {
+                let frame_row0_offset = (y * 2) as usize * frame_stride;
+                let frame_row1_offset = frame_row0_offset + frame_stride;
+                frame[frame_row0_offset..frame_row0_offset + row_len].copy_from_slice(src);
+                frame[frame_row1_offset..frame_row1_offset + row_len].copy_from_slice(src);
+            }
+            else {
+                let frame_row_offset = y as usize * frame_stride;
+                frame[frame_row_offset..frame_row_offset + row_len].copy_from_slice(src);
+            }
+        }
+    }
 }
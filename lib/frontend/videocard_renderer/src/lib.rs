@@ -223,6 +223,7 @@ pub struct VideoRenderer {
     screenshot_buf: Vec<u8>,
     screenshot_path: Option<std::path::PathBuf>,
     screenshot_requested: bool,
+    capture_buf: Vec<u8>,
 
     last_render_time: Duration,
     event_queue: VecDeque<RendererEvent>,
@@ -272,6 +273,7 @@ impl VideoRenderer {
             screenshot_buf: Vec::new(),
             screenshot_path: None,
             screenshot_requested: false,
+            capture_buf: Vec::new(),
 
             last_render_time: Duration::from_secs(0),
             event_queue: VecDeque::new(),
@@ -339,6 +341,30 @@ impl VideoRenderer {
         self.aperture_dirty = true;
     }
 
+    /// Convert a normalized (0.0-1.0) position on the rendered image to a position on the display field
+    /// of the video card, in the same coordinate space as the card's beam position. This is the inverse
+    /// of the aperture cropping and line doubling performed when drawing.
+    pub fn image_to_field(&self, x: f32, y: f32, extents: &DisplayExtents) -> (u32, u32) {
+        let aperture = &extents.apertures[self.params.aperture as usize];
+
+        let mut horiz_adjust = aperture.x;
+        let mut vert_adjust = aperture.y;
+        // Ignore aperture adjustments if it pushes us outside the field boundaries
+        if aperture.x + aperture.w >= extents.field_w {
+            horiz_adjust = 0;
+        }
+        if aperture.y + aperture.h >= extents.field_h {
+            vert_adjust = 0;
+        }
+
+        let image_x = (x * self.params.render.w as f32) as u32;
+        let mut image_y = (y * self.params.render.h as f32) as u32;
+        if self.params.line_double {
+            image_y /= 2;
+        }
+        (image_x + horiz_adjust, image_y + vert_adjust)
+    }
+
     pub fn set_debug(&mut self, state: bool) {
         self.params.debug_aperture = state;
     }
@@ -543,6 +569,18 @@ impl VideoRenderer {
         self.screenshot_requested = true;
     }
 
+    /// Render a frame as it would be drawn to the backend: after the display aperture and any
+    /// software aspect correction, but before the scaler. Returns the dimensions of the frame and
+    /// its RGBA pixels. Used for video capture.
+    pub fn render_capture(&mut self, input_buf: &[u8], extents: &DisplayExtents) -> (VideoDimensions, &[u8]) {
+        let dims = self.params.backend;
+        let mut capture_buf = std::mem::take(&mut self.capture_buf);
+        capture_buf.resize((dims.w * dims.h * 4) as usize, 0);
+        self.draw(input_buf, &mut capture_buf, extents, None);
+        self.capture_buf = capture_buf;
+        (dims, &self.capture_buf)
+    }
+
     pub fn render_screenshot(&self, frame: &[u8], path: &Path) {
         let frame_slice =
             &frame[0..(self.params.backend.w as usize * self.params.backend.h as usize * std::mem::size_of::<u32>())];